@@ -0,0 +1,1390 @@
+//! Typed replies for server administration commands.
+use bson::{self, Bson, UtcDateTime, doc};
+use db::options::TimeSeriesOptions;
+use Error::{ArgumentError, DecoderError, ResponseError};
+use {Client, Result, ThreadedClient};
+
+use std::collections::BTreeMap;
+
+/// Decodes a command reply into a typed struct.
+pub(crate) fn decode_reply<T>(reply: bson::Document) -> Result<T>
+where
+    T: for<'de> ::serde::Deserialize<'de>,
+{
+    bson::from_bson(Bson::Document(reply)).map_err(DecoderError)
+}
+
+/// The options a server was started with, as reported by `getCmdLineOpts`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CmdLineOpts {
+    /// The raw command line arguments.
+    #[serde(default)]
+    pub argv: Vec<String>,
+    /// The options parsed from the command line and configuration file.
+    #[serde(default)]
+    pub parsed: bson::Document,
+}
+
+/// Information about the host a server runs on, as reported by `hostInfo`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HostInfo {
+    pub system: HostSystemInfo,
+    pub os: HostOsInfo,
+    /// Platform-specific details, such as the kernel version or page size.
+    #[serde(default)]
+    pub extra: bson::Document,
+}
+
+/// Hardware details from the `system` section of `hostInfo`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HostSystemInfo {
+    #[serde(rename="currentTime", skip_serializing_if="Option::is_none")]
+    pub current_time: Option<UtcDateTime>,
+
+    pub hostname: String,
+
+    #[serde(rename="cpuAddrSize", skip_serializing_if="Option::is_none")]
+    pub cpu_addr_size: Option<i32>,
+
+    #[serde(rename="memSizeMB", skip_serializing_if="Option::is_none")]
+    pub mem_size_mb: Option<i64>,
+
+    #[serde(rename="memLimitMB", skip_serializing_if="Option::is_none")]
+    pub mem_limit_mb: Option<i64>,
+
+    #[serde(rename="numCores", skip_serializing_if="Option::is_none")]
+    pub num_cores: Option<i32>,
+
+    #[serde(rename="cpuArch", skip_serializing_if="Option::is_none")]
+    pub cpu_arch: Option<String>,
+
+    #[serde(rename="numaEnabled", skip_serializing_if="Option::is_none")]
+    pub numa_enabled: Option<bool>,
+}
+
+/// Operating system details from the `os` section of `hostInfo`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HostOsInfo {
+    #[serde(rename="type", skip_serializing_if="Option::is_none")]
+    pub os_type: Option<String>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Holds the fsync lock taken by `ThreadedClient::fsync_lock`, which blocks writes to the
+/// server so its data files can be copied or snapshotted.
+///
+/// The lock is released when the guard is dropped; call `unlock` to find out whether that
+/// succeeded. Locks nest: the server accepts writes again once every lock is released.
+#[derive(Debug)]
+pub struct FsyncLock {
+    client: Client,
+    lock_count: i32,
+    locked: bool,
+}
+
+impl FsyncLock {
+    pub(crate) fn new(client: Client, reply: &bson::Document) -> FsyncLock {
+        FsyncLock {
+            client: client,
+            lock_count: integer(reply.get("lockCount")) as i32,
+            locked: true,
+        }
+    }
+
+    /// Returns how many fsync locks the server held once this one was taken.
+    pub fn lock_count(&self) -> i32 {
+        self.lock_count
+    }
+
+    /// Releases the lock and returns how many fsync locks the server still holds.
+    pub fn unlock(mut self) -> Result<i32> {
+        self.locked = false;
+        self.client.fsync_unlock()
+    }
+}
+
+impl Drop for FsyncLock {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = self.client.fsync_unlock();
+        }
+    }
+}
+
+/// A user whose sessions should be listed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SessionUser {
+    pub user: String,
+    pub db: String,
+}
+
+impl SessionUser {
+    pub fn new(user: &str, db: &str) -> SessionUser {
+        SessionUser {
+            user: String::from(user),
+            db: String::from(db),
+        }
+    }
+}
+
+/// Selects the sessions reported by `$listSessions` and `$listLocalSessions`.
+///
+/// Without options, only the sessions of the authenticated user are listed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ListSessionsOptions {
+    /// Lists the sessions of every user; requires the `listSessions` privilege.
+    pub all_users: Option<bool>,
+    /// Lists the sessions of the given users only.
+    pub users: Option<Vec<SessionUser>>,
+}
+
+impl ListSessionsOptions {
+    pub fn new() -> ListSessionsOptions {
+        Default::default()
+    }
+}
+
+impl From<ListSessionsOptions> for bson::Document {
+    fn from(options: ListSessionsOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(all_users) = options.all_users {
+            document.insert("allUsers", all_users);
+        }
+
+        if let Some(users) = options.users {
+            let users: Vec<_> = users
+                .into_iter()
+                .map(|user| Bson::Document(doc! { "user": user.user, "db": user.db }))
+                .collect();
+            document.insert("users", users);
+        }
+
+        document
+    }
+}
+
+/// A shard of the cluster, from `config.shards`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShardInfo {
+    #[serde(rename="_id")]
+    pub id: String,
+    /// The connection string of the shard's replica set, e.g. `rs0/db0:27018,db1:27018`.
+    pub host: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub state: Option<i32>,
+    /// Whether the shard is being removed from the cluster.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub draining: Option<bool>,
+    /// The zones the shard belongs to.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A sharded collection, from `config.collections`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShardedCollectionInfo {
+    /// The namespace of the collection.
+    #[serde(rename="_id")]
+    pub namespace: String,
+    /// The shard key pattern.
+    pub key: bson::Document,
+    #[serde(default)]
+    pub unique: bool,
+    /// Set by servers before MongoDB 5.0 on entries of collections that were dropped.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub dropped: Option<bool>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub uuid: Option<Bson>,
+    #[serde(rename="lastmodEpoch", default, skip_serializing_if="Option::is_none")]
+    pub lastmod_epoch: Option<Bson>,
+}
+
+impl ShardedCollectionInfo {
+    /// Returns the filter selecting the collection's chunks in `config.chunks`, which are
+    /// keyed by collection UUID since MongoDB 5.0 and by namespace before.
+    pub fn chunk_filter(&self) -> bson::Document {
+        match self.uuid {
+            Some(ref uuid) => doc! {
+                "$or": [{ "ns": self.namespace.clone() }, { "uuid": uuid.clone() }],
+            },
+            None => doc! { "ns": self.namespace.clone() },
+        }
+    }
+}
+
+/// A range of shard key values owned by one shard, from `config.chunks`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChunkInfo {
+    #[serde(rename="_id")]
+    pub id: Bson,
+    /// The namespace of the collection; set by servers before MongoDB 5.0.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub ns: Option<String>,
+    /// The UUID of the collection; set by servers since MongoDB 5.0.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub uuid: Option<Bson>,
+    /// The inclusive lower bound of the range.
+    pub min: bson::Document,
+    /// The exclusive upper bound of the range.
+    pub max: bson::Document,
+    pub shard: String,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub lastmod: Option<Bson>,
+    /// Whether the chunk is too large to be split or moved.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub jumbo: Option<bool>,
+}
+
+/// Selects what `listDatabases` reports.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ListDatabasesOptions {
+    /// Reports only the names of the databases, which needs no database locks.
+    pub name_only: Option<bool>,
+    /// Reports only the databases the user has privileges on, so that users without the
+    /// `listDatabases` privilege can list them.
+    pub authorized_databases: Option<bool>,
+}
+
+impl ListDatabasesOptions {
+    pub fn new() -> ListDatabasesOptions {
+        Default::default()
+    }
+}
+
+impl From<ListDatabasesOptions> for bson::Document {
+    fn from(options: ListDatabasesOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(name_only) = options.name_only {
+            document.insert("nameOnly", name_only);
+        }
+
+        if let Some(authorized_databases) = options.authorized_databases {
+            document.insert("authorizedDatabases", authorized_databases);
+        }
+
+        document
+    }
+}
+
+/// A database, as reported by `listDatabases`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DatabaseInfo {
+    pub name: String,
+    /// The size of the database files, in bytes.
+    #[serde(rename="sizeOnDisk", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub size_on_disk: Option<i64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub empty: Option<bool>,
+    /// The size of the database on each shard, when listed through a mongos.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub shards: Option<bson::Document>,
+}
+
+// Sizes are reported as doubles or integers depending on the server version.
+fn deserialize_size<'de, D>(deserializer: D) -> ::std::result::Result<Option<i64>, D::Error>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    let size: Option<Bson> = ::serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(match size {
+        Some(Bson::I32(size)) => Some(i64::from(size)),
+        Some(Bson::I64(size)) => Some(size),
+        Some(Bson::FloatingPoint(size)) => Some(size as i64),
+        _ => None,
+    })
+}
+
+/// A collection or view, as reported by `listCollections`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionInfo {
+    pub name: String,
+    /// `"collection"`, `"view"` or `"timeseries"`.
+    #[serde(rename="type", default="default_collection_type")]
+    pub collection_type: String,
+    /// The options the collection was created with; left out with `name_only`.
+    #[serde(default)]
+    pub options: bson::Document,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub info: Option<CollectionDetails>,
+    /// The `_id` index specification; left out for views.
+    #[serde(rename="idIndex", default, skip_serializing_if="Option::is_none")]
+    pub id_index: Option<bson::Document>,
+}
+
+impl CollectionInfo {
+    /// Returns whether this is a read-only view rather than a collection.
+    pub fn is_view(&self) -> bool {
+        self.collection_type == "view"
+    }
+
+    /// Returns the collection or view the view is defined on, or `None` if this is not a view.
+    pub fn view_on(&self) -> Option<&str> {
+        match self.options.get("viewOn") {
+            Some(&Bson::String(ref view_on)) if self.is_view() => Some(view_on),
+            _ => None,
+        }
+    }
+
+    /// Returns whether this is a time-series collection.
+    pub fn is_time_series(&self) -> bool {
+        self.collection_type == "timeseries"
+    }
+
+    /// Returns the time-series specification of the collection, or `None` if this is not a
+    /// time-series collection.
+    pub fn time_series(&self) -> Option<TimeSeriesOptions> {
+        match self.options.get("timeseries") {
+            Some(&Bson::Document(ref timeseries)) if self.is_time_series() => {
+                TimeSeriesOptions::from_document(timeseries)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the aggregation pipeline of a view, or `None` if this is not a view.
+    pub fn pipeline(&self) -> Option<Vec<bson::Document>> {
+        if !self.is_view() {
+            return None;
+        }
+
+        match self.options.get("pipeline") {
+            Some(&Bson::Array(ref stages)) => Some(
+                stages
+                    .iter()
+                    .filter_map(|stage| match *stage {
+                        Bson::Document(ref stage) => Some(stage.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            _ => Some(Vec::new()),
+        }
+    }
+}
+
+fn default_collection_type() -> String {
+    String::from("collection")
+}
+
+/// The `info` section of a `listCollections` entry.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionDetails {
+    #[serde(rename="readOnly", default, skip_serializing_if="Option::is_none")]
+    pub read_only: Option<bool>,
+    /// The UUID of the collection, from MongoDB 3.6 on.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub uuid: Option<Bson>,
+}
+
+/// Selects the sections `serverStatus` reports besides those it includes by default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ServerStatusOptions {
+    /// Sections to add, such as `"latchAnalysis"`.
+    pub include: Vec<String>,
+    /// Default sections to leave out, such as `"repl"` or `"metrics"`.
+    pub exclude: Vec<String>,
+}
+
+impl ServerStatusOptions {
+    pub fn new() -> ServerStatusOptions {
+        Default::default()
+    }
+}
+
+impl From<ServerStatusOptions> for bson::Document {
+    fn from(options: ServerStatusOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        for section in options.include {
+            document.insert(section, 1);
+        }
+
+        for section in options.exclude {
+            document.insert(section, 0);
+        }
+
+        document
+    }
+}
+
+/// The state of a server, as reported by `serverStatus`.
+///
+/// The commonly used sections are decoded; every other section is kept in `other`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServerStatus {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub version: Option<String>,
+    /// `"mongod"` or `"mongos"`.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub process: Option<String>,
+    /// The time since the server started, in seconds.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub uptime: Option<f64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub connections: Option<ConnectionStatus>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub opcounters: Option<OpCounters>,
+    #[serde(rename="wiredTiger", default, skip_serializing_if="Option::is_none")]
+    pub wired_tiger: Option<WiredTigerStatus>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub mem: Option<MemoryStatus>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub network: Option<NetworkStatus>,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `connections` section of `serverStatus`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConnectionStatus {
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub current: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub available: i64,
+    #[serde(rename="totalCreated", default, deserialize_with="deserialize_count")]
+    pub total_created: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `opcounters` section of `serverStatus`: operations received since the server started.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OpCounters {
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub insert: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub query: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub update: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub delete: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub getmore: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub command: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `wiredTiger` section of `serverStatus`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WiredTigerStatus {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub cache: Option<WiredTigerCache>,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `wiredTiger.cache` section of `serverStatus`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WiredTigerCache {
+    #[serde(rename="bytes currently in the cache", default,
+            deserialize_with="deserialize_count")]
+    pub bytes_in_cache: i64,
+    #[serde(rename="maximum bytes configured", default, deserialize_with="deserialize_count")]
+    pub maximum_bytes: i64,
+    #[serde(rename="tracked dirty bytes in the cache", default,
+            deserialize_with="deserialize_count")]
+    pub dirty_bytes: i64,
+    #[serde(rename="pages read into cache", default, deserialize_with="deserialize_count")]
+    pub pages_read: i64,
+    #[serde(rename="pages written from cache", default, deserialize_with="deserialize_count")]
+    pub pages_written: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `mem` section of `serverStatus`, with sizes in mebibytes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MemoryStatus {
+    /// Whether the server is a 32 or 64 bit build.
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub bits: i64,
+    #[serde(rename="resident", default, deserialize_with="deserialize_count")]
+    pub resident_mb: i64,
+    #[serde(rename="virtual", default, deserialize_with="deserialize_count")]
+    pub virtual_mb: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// The `network` section of `serverStatus`, counted since the server started.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NetworkStatus {
+    #[serde(rename="bytesIn", default, deserialize_with="deserialize_count")]
+    pub bytes_in: i64,
+    #[serde(rename="bytesOut", default, deserialize_with="deserialize_count")]
+    pub bytes_out: i64,
+    #[serde(rename="numRequests", default, deserialize_with="deserialize_count")]
+    pub num_requests: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// Storage statistics of a database, as reported by `dbStats`.
+///
+/// Sizes are in bytes divided by `scale_factor`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DatabaseStats {
+    pub db: String,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub collections: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub views: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub objects: i64,
+    #[serde(rename="avgObjSize", default)]
+    pub avg_obj_size: f64,
+    #[serde(rename="dataSize", default, deserialize_with="deserialize_count")]
+    pub data_size: i64,
+    #[serde(rename="storageSize", default, deserialize_with="deserialize_count")]
+    pub storage_size: i64,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub indexes: i64,
+    #[serde(rename="indexSize", default, deserialize_with="deserialize_count")]
+    pub index_size: i64,
+    /// The storage and index sizes together, from MongoDB 4.4 on.
+    #[serde(rename="totalSize", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub total_size: Option<i64>,
+    #[serde(rename="scaleFactor", default="default_scale", deserialize_with="deserialize_count")]
+    pub scale_factor: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+/// Storage statistics of a collection, as reported by `collStats`.
+///
+/// Sizes are in bytes divided by `scale_factor`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionStats {
+    pub ns: String,
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub count: i64,
+    /// The uncompressed size of the documents.
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub size: i64,
+    /// Left out when the collection is empty.
+    #[serde(rename="avgObjSize", default, skip_serializing_if="Option::is_none")]
+    pub avg_obj_size: Option<f64>,
+    #[serde(rename="storageSize", default, deserialize_with="deserialize_count")]
+    pub storage_size: i64,
+    #[serde(rename="nindexes", default, deserialize_with="deserialize_count")]
+    pub index_count: i64,
+    #[serde(rename="totalIndexSize", default, deserialize_with="deserialize_count")]
+    pub total_index_size: i64,
+    /// The size of each index by name.
+    #[serde(rename="indexSizes", default, deserialize_with="deserialize_counts")]
+    pub index_sizes: BTreeMap<String, i64>,
+    /// The storage and index sizes together, from MongoDB 4.4 on.
+    #[serde(rename="totalSize", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub total_size: Option<i64>,
+    #[serde(default)]
+    pub capped: bool,
+    /// The most documents a capped collection keeps.
+    #[serde(default, deserialize_with="deserialize_size", skip_serializing_if="Option::is_none")]
+    pub max: Option<i64>,
+    #[serde(rename="scaleFactor", default="default_scale", deserialize_with="deserialize_count")]
+    pub scale_factor: i64,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+fn default_scale() -> i64 {
+    1
+}
+
+/// Fails unless `scale` is a valid divisor for the sizes `dbStats` and `collStats` report.
+pub(crate) fn check_scale(scale: i32) -> Result<i32> {
+    if scale < 1 {
+        return Err(ArgumentError(format!("Invalid scale {}; it must be positive.", scale)));
+    }
+
+    Ok(scale)
+}
+
+/// Which operations the database profiler records in `system.profile`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProfilingLevel {
+    Off,
+    /// Records operations slower than the slow operation threshold.
+    SlowOperations,
+    All,
+}
+
+impl ProfilingLevel {
+    pub(crate) fn to_i32(&self) -> i32 {
+        match *self {
+            ProfilingLevel::Off => 0,
+            ProfilingLevel::SlowOperations => 1,
+            ProfilingLevel::All => 2,
+        }
+    }
+}
+
+/// The profiler settings of a database, as reported by `profile`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfilingStatus {
+    pub level: ProfilingLevel,
+    /// The threshold in milliseconds above which operations count as slow. It applies to the
+    /// whole server, and decides which operations are logged as well as profiled.
+    pub slow_ms: i64,
+    /// The fraction of slow operations that are profiled, from MongoDB 3.6 on.
+    pub sample_rate: Option<f64>,
+}
+
+impl ProfilingStatus {
+    pub(crate) fn from_reply(reply: &bson::Document) -> Result<ProfilingStatus> {
+        let level = match integer(reply.get("was")) {
+            0 => ProfilingLevel::Off,
+            1 => ProfilingLevel::SlowOperations,
+            2 => ProfilingLevel::All,
+            level => {
+                return Err(ResponseError(format!("Unknown profiling level {}.", level)));
+            }
+        };
+
+        let sample_rate = match reply.get("sampleRate") {
+            Some(&Bson::FloatingPoint(rate)) => Some(rate),
+            Some(&Bson::I32(rate)) => Some(f64::from(rate)),
+            _ => None,
+        };
+
+        Ok(ProfilingStatus {
+            level: level,
+            slow_ms: integer(reply.get("slowms")),
+            sample_rate: sample_rate,
+        })
+    }
+}
+
+/// An operation recorded by the database profiler in `system.profile`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProfileEntry {
+    /// The kind of operation, such as `"query"`, `"update"` or `"command"`.
+    pub op: String,
+    pub ns: String,
+    /// The command or query document of the operation.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub command: Option<bson::Document>,
+    /// How long the operation took, in milliseconds.
+    #[serde(default, deserialize_with="deserialize_count")]
+    pub millis: i64,
+    /// When the operation ran.
+    pub ts: UtcDateTime,
+    #[serde(rename="keysExamined", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub keys_examined: Option<i64>,
+    #[serde(rename="docsExamined", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub docs_examined: Option<i64>,
+    #[serde(rename="nreturned", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub returned: Option<i64>,
+    /// A summary of the query plan, such as `"COLLSCAN"` or `"IXSCAN { a: 1 }"`.
+    #[serde(rename="planSummary", default, skip_serializing_if="Option::is_none")]
+    pub plan_summary: Option<String>,
+    #[serde(rename="responseLength", default, deserialize_with="deserialize_size",
+            skip_serializing_if="Option::is_none")]
+    pub response_length: Option<i64>,
+    /// The address of the client that ran the operation.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub client: Option<String>,
+    /// The authenticated user that ran the operation.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub user: Option<String>,
+    #[serde(flatten)]
+    pub other: bson::Document,
+}
+
+// Counters are reported as whichever BSON number type fits their value.
+pub(crate) fn deserialize_count<'de, D>(deserializer: D) -> ::std::result::Result<i64, D::Error>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    let count: Bson = ::serde::Deserialize::deserialize(deserializer)?;
+    Ok(integer(Some(&count)))
+}
+
+fn deserialize_counts<'de, D>(
+    deserializer: D,
+) -> ::std::result::Result<BTreeMap<String, i64>, D::Error>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    let counts: bson::Document = ::serde::Deserialize::deserialize(deserializer)?;
+    Ok(counts.iter().map(|(name, count)| (name.clone(), integer(Some(count)))).collect())
+}
+
+/// The data and chunks a sharded collection keeps on one shard.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShardDataDistribution {
+    pub shard: String,
+    /// The uncompressed size of the documents on the shard, in bytes.
+    pub data_size: i64,
+    pub document_count: i64,
+    pub chunk_count: i64,
+}
+
+/// How a sharded collection is spread across its shards, as reported by the shell's
+/// `getShardDistribution` helper.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShardDistribution {
+    /// One entry per shard, ordered by shard name.
+    pub shards: Vec<ShardDataDistribution>,
+}
+
+impl ShardDistribution {
+    /// Combines `$collStats` storage statistics with `{ _id: <shard>, chunks: <count> }`
+    /// chunk counts.
+    pub(crate) fn from_replies(
+        coll_stats: Vec<bson::Document>,
+        chunk_counts: Vec<bson::Document>,
+    ) -> Result<ShardDistribution> {
+        let mut shards: Vec<ShardDataDistribution> = Vec::new();
+
+        for stats in coll_stats {
+            let shard = stats.get_str("shard").map_err(|_| {
+                ResponseError(String::from("$collStats did not report a shard."))
+            })?;
+            let storage = stats.get_document("storageStats").map_err(|_| {
+                ResponseError(String::from("$collStats did not report storage statistics."))
+            })?;
+
+            shards.push(ShardDataDistribution {
+                shard: String::from(shard),
+                data_size: integer(storage.get("size")),
+                document_count: integer(storage.get("count")),
+                chunk_count: 0,
+            });
+        }
+
+        for counts in chunk_counts {
+            let shard = counts.get_str("_id").map_err(|_| {
+                ResponseError(String::from("Chunk counts must be grouped by shard name."))
+            })?;
+            let chunks = integer(counts.get("chunks"));
+
+            match shards.iter_mut().find(|entry| entry.shard == shard) {
+                Some(entry) => entry.chunk_count = chunks,
+                // A shard owning only empty chunks reports no storage statistics.
+                None => shards.push(ShardDataDistribution {
+                    shard: String::from(shard),
+                    data_size: 0,
+                    document_count: 0,
+                    chunk_count: chunks,
+                }),
+            }
+        }
+
+        shards.sort_by(|a, b| a.shard.cmp(&b.shard));
+        Ok(ShardDistribution { shards: shards })
+    }
+
+    pub fn total_data_size(&self) -> i64 {
+        self.shards.iter().map(|shard| shard.data_size).sum()
+    }
+
+    pub fn total_document_count(&self) -> i64 {
+        self.shards.iter().map(|shard| shard.document_count).sum()
+    }
+
+    pub fn total_chunk_count(&self) -> i64 {
+        self.shards.iter().map(|shard| shard.chunk_count).sum()
+    }
+}
+
+/// An index that is not defined the same way on every shard of a collection.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IndexInconsistency {
+    pub name: String,
+    /// The shards holding the collection without the index.
+    pub missing_from_shards: Vec<String>,
+    /// The index options, such as `unique` or `expireAfterSeconds`, whose values differ
+    /// between the shards holding the index.
+    pub inconsistent_properties: Vec<String>,
+}
+
+/// The indexes of a sharded collection on each of its shards.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexConsistency {
+    /// The index specifications on each shard, by shard name.
+    pub indexes: BTreeMap<String, Vec<bson::Document>>,
+    /// The indexes that differ between shards, ordered by index name.
+    pub inconsistencies: Vec<IndexInconsistency>,
+}
+
+impl IndexConsistency {
+    /// Compares the per-shard `$indexStats` entries of a collection.
+    ///
+    /// Every shard reporting an index is expected to hold all of them, as the shell's
+    /// inconsistent index check does.
+    pub(crate) fn from_index_stats(stats: Vec<bson::Document>) -> Result<IndexConsistency> {
+        let mut indexes: BTreeMap<String, Vec<bson::Document>> = BTreeMap::new();
+
+        for entry in stats {
+            let shard = entry.get_str("shard").map_err(|_| {
+                ResponseError(String::from("$indexStats did not report a shard."))
+            })?;
+
+            // Servers before MongoDB 4.2 report the key pattern but not the full specification.
+            let spec = match entry.get("spec") {
+                Some(&Bson::Document(ref spec)) => spec.clone(),
+                _ => {
+                    let name = entry.get_str("name").map_err(|_| {
+                        ResponseError(String::from("$indexStats did not report an index name."))
+                    })?;
+                    let key = entry.get_document("key").map_err(|_| {
+                        ResponseError(String::from("$indexStats did not report an index key."))
+                    })?;
+                    doc! { "key": key.clone(), "name": name }
+                }
+            };
+
+            indexes.entry(String::from(shard)).or_insert_with(Vec::new).push(spec);
+        }
+
+        let mut names: Vec<String> = indexes
+            .values()
+            .flat_map(|specs| specs.iter())
+            .filter_map(|spec| spec.get_str("name").ok().map(String::from))
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let mut inconsistencies = Vec::new();
+
+        for name in names {
+            let mut missing_from_shards = Vec::new();
+            let mut specs = Vec::new();
+
+            for (shard, shard_specs) in &indexes {
+                match shard_specs.iter().find(|spec| spec.get_str("name") == Ok(&name[..])) {
+                    Some(spec) => specs.push(spec),
+                    None => missing_from_shards.push(shard.clone()),
+                }
+            }
+
+            let mut inconsistent_properties: Vec<String> = specs
+                .iter()
+                .flat_map(|spec| spec.keys())
+                .filter(|key| specs.iter().any(|spec| spec.get(key) != specs[0].get(key)))
+                .cloned()
+                .collect();
+            inconsistent_properties.sort();
+            inconsistent_properties.dedup();
+
+            if !missing_from_shards.is_empty() || !inconsistent_properties.is_empty() {
+                inconsistencies.push(IndexInconsistency {
+                    name: name,
+                    missing_from_shards: missing_from_shards,
+                    inconsistent_properties: inconsistent_properties,
+                });
+            }
+        }
+
+        Ok(IndexConsistency {
+            indexes: indexes,
+            inconsistencies: inconsistencies,
+        })
+    }
+
+    /// Returns whether every shard defines the same indexes.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// How often a failpoint triggers once it is enabled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailPointMode {
+    /// Triggers until the failpoint is disabled.
+    AlwaysOn,
+    /// Triggers the given number of times, then disables itself.
+    Times(i32),
+    /// Lets the given number of matching operations through, then triggers until disabled.
+    Skip(i32),
+    /// Triggers with the given probability, between 0 and 1.
+    ActivationProbability(f64),
+    /// Disables the failpoint.
+    Off,
+}
+
+impl From<FailPointMode> for Bson {
+    fn from(mode: FailPointMode) -> Self {
+        match mode {
+            FailPointMode::AlwaysOn => Bson::from("alwaysOn"),
+            FailPointMode::Times(times) => Bson::from(doc! { "times": times }),
+            FailPointMode::Skip(skip) => Bson::from(doc! { "skip": skip }),
+            FailPointMode::ActivationProbability(probability) => {
+                Bson::from(doc! { "activationProbability": probability })
+            }
+            FailPointMode::Off => Bson::from("off"),
+        }
+    }
+}
+
+/// A server-side failpoint, enabled with `ThreadedClient::enable_fail_point` to inject
+/// errors into the operations of integration tests.
+///
+/// Failpoints are only available on servers started with `enableTestCommands=1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailPoint {
+    /// The name of the failpoint, such as `failCommand`.
+    pub name: String,
+    pub mode: FailPointMode,
+    /// The failpoint-specific options, sent as `data`.
+    pub data: bson::Document,
+}
+
+impl FailPoint {
+    pub fn new(name: &str, mode: FailPointMode) -> FailPoint {
+        FailPoint {
+            name: String::from(name),
+            mode: mode,
+            data: bson::Document::new(),
+        }
+    }
+
+    /// Returns a `failCommand` failpoint that fails the named commands on every connection
+    /// until it is disabled. Without an error code, the commands fail with code 1.
+    pub fn fail_command(command_names: &[&str]) -> FailPoint {
+        let names: Vec<_> = command_names.iter().map(|&name| Bson::from(name)).collect();
+
+        let mut fail_point = FailPoint::new("failCommand", FailPointMode::AlwaysOn);
+        fail_point.data.insert("failCommands", names);
+        fail_point.data.insert("errorCode", 1);
+        fail_point
+    }
+
+    /// Triggers the failpoint `times` times only.
+    pub fn times(mut self, times: i32) -> FailPoint {
+        self.mode = FailPointMode::Times(times);
+        self
+    }
+
+    /// Fails the commands with the given server error code, e.g. 91 (`ShutdownInProgress`)
+    /// to exercise retries.
+    pub fn error_code(mut self, code: i32) -> FailPoint {
+        self.data.insert("errorCode", code);
+        self
+    }
+
+    /// Attaches the given error labels, such as `RetryableWriteError`, to the failures.
+    pub fn error_labels(mut self, labels: &[&str]) -> FailPoint {
+        let labels: Vec<_> = labels.iter().map(|&label| Bson::from(label)).collect();
+        self.data.insert("errorLabels", labels);
+        self
+    }
+
+    /// Closes the connection instead of replying, as a network error or failover would.
+    pub fn close_connection(mut self) -> FailPoint {
+        self.data.remove("errorCode");
+        self.data.insert("closeConnection", true);
+        self
+    }
+
+    /// Replies to the commands successfully, but with the given `writeConcernError`.
+    pub fn write_concern_error(mut self, error: bson::Document) -> FailPoint {
+        self.data.remove("errorCode");
+        self.data.insert("writeConcernError", error);
+        self
+    }
+
+    /// Holds the commands for `ms` milliseconds before they run, to exercise timeouts.
+    pub fn block_connection(mut self, ms: i32) -> FailPoint {
+        self.data.insert("blockConnection", true);
+        self.data.insert("blockTimeMS", ms);
+        self
+    }
+}
+
+impl From<FailPoint> for bson::Document {
+    fn from(fail_point: FailPoint) -> Self {
+        let mut document = doc! {
+            "configureFailPoint": fail_point.name,
+            "mode": fail_point.mode,
+        };
+
+        if !fail_point.data.is_empty() {
+            document.insert("data", fail_point.data);
+        }
+
+        document
+    }
+}
+
+// Reads a numeric statistic, which servers report as whichever BSON number type fits.
+fn integer(value: Option<&Bson>) -> i64 {
+    match value {
+        Some(&Bson::I32(v)) => i64::from(v),
+        Some(&Bson::I64(v)) => v,
+        Some(&Bson::FloatingPoint(v)) => v as i64,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn decode_host_info_reply() {
+        let reply = doc! {
+            "system": {
+                "hostname": "db0.example.net:27017",
+                "cpuAddrSize": 64,
+                "memSizeMB": 16384_i64,
+                "numCores": 8,
+                "cpuArch": "x86_64",
+                "numaEnabled": false,
+            },
+            "os": {
+                "type": "Linux",
+                "name": "Ubuntu",
+                "version": "18.04",
+            },
+            "extra": { "pageSize": 4096_i64 },
+            "ok": 1.0,
+        };
+
+        let info: HostInfo = decode_reply(reply).unwrap();
+
+        assert_eq!("db0.example.net:27017", info.system.hostname);
+        assert_eq!(Some(16384), info.system.mem_size_mb);
+        assert_eq!(Some(8), info.system.num_cores);
+        assert_eq!(Some(String::from("Linux")), info.os.os_type);
+        assert_eq!(Some(&Bson::I64(4096)), info.extra.get("pageSize"));
+    }
+
+    #[test]
+    fn decode_config_metadata() {
+        let shard: ShardInfo = decode_reply(doc! {
+            "_id": "shard0",
+            "host": "rs0/db0:27018,db1:27018",
+            "state": 1,
+            "tags": ["east"],
+        }).unwrap();
+        assert_eq!("shard0", shard.id);
+        assert_eq!(vec![String::from("east")], shard.tags);
+        assert_eq!(None, shard.draining);
+
+        let collection: ShardedCollectionInfo = decode_reply(doc! {
+            "_id": "shop.orders",
+            "key": { "customer": "hashed" },
+        }).unwrap();
+        assert!(!collection.unique);
+        assert_eq!(doc! { "ns": "shop.orders" }, collection.chunk_filter());
+
+        let chunk: ChunkInfo = decode_reply(doc! {
+            "_id": "shop.orders-customer_-100",
+            "ns": "shop.orders",
+            "min": { "customer": -100_i64 },
+            "max": { "customer": 0_i64 },
+            "shard": "shard0",
+        }).unwrap();
+        assert_eq!("shard0", chunk.shard);
+        assert_eq!(Some(&Bson::I64(-100)), chunk.min.get("customer"));
+    }
+
+    #[test]
+    fn combine_shard_distribution_replies() {
+        let stats = vec![
+            doc! { "shard": "shard1", "storageStats": { "size": 300_i64, "count": 3 } },
+            doc! { "shard": "shard0", "storageStats": { "size": 100, "count": 1 } },
+        ];
+        let chunks = vec![
+            doc! { "_id": "shard0", "chunks": 2 },
+            doc! { "_id": "shard1", "chunks": 1 },
+            doc! { "_id": "shard2", "chunks": 1 },
+        ];
+
+        let distribution = ShardDistribution::from_replies(stats, chunks).unwrap();
+        let names: Vec<_> = distribution.shards.iter().map(|shard| &shard.shard[..]).collect();
+
+        assert_eq!(vec!["shard0", "shard1", "shard2"], names);
+        assert_eq!(2, distribution.shards[0].chunk_count);
+        assert_eq!(0, distribution.shards[2].data_size);
+        assert_eq!(400, distribution.total_data_size());
+        assert_eq!(4, distribution.total_document_count());
+        assert_eq!(4, distribution.total_chunk_count());
+    }
+
+    #[test]
+    fn compare_index_stats_across_shards() {
+        let stats = vec![
+            doc! {
+                "name": "_id_",
+                "shard": "shard0",
+                "spec": { "v": 2, "key": { "_id": 1 }, "name": "_id_" },
+            },
+            doc! {
+                "name": "_id_",
+                "shard": "shard1",
+                "spec": { "v": 2, "key": { "_id": 1 }, "name": "_id_" },
+            },
+            doc! {
+                "name": "email_1",
+                "shard": "shard0",
+                "spec": { "v": 2, "key": { "email": 1 }, "name": "email_1", "unique": true },
+            },
+            doc! {
+                "name": "email_1",
+                "shard": "shard1",
+                "spec": { "v": 2, "key": { "email": 1 }, "name": "email_1" },
+            },
+            doc! { "name": "created_1", "shard": "shard1", "key": { "created": 1 } },
+        ];
+
+        let consistency = IndexConsistency::from_index_stats(stats).unwrap();
+        assert!(!consistency.is_consistent());
+        assert_eq!(2, consistency.indexes["shard0"].len());
+        assert_eq!(3, consistency.indexes["shard1"].len());
+
+        let expected = vec![
+            IndexInconsistency {
+                name: String::from("created_1"),
+                missing_from_shards: vec![String::from("shard0")],
+                inconsistent_properties: Vec::new(),
+            },
+            IndexInconsistency {
+                name: String::from("email_1"),
+                missing_from_shards: Vec::new(),
+                inconsistent_properties: vec![String::from("unique")],
+            },
+        ];
+        assert_eq!(expected, consistency.inconsistencies);
+    }
+
+    #[test]
+    fn list_sessions_options_to_document() {
+        assert_eq!(bson::Document::new(), bson::Document::from(ListSessionsOptions::new()));
+
+        let mut options = ListSessionsOptions::new();
+        options.users = Some(vec![SessionUser::new("reporting", "admin")]);
+
+        let expected = doc! { "users": [{ "user": "reporting", "db": "admin" }] };
+        assert_eq!(expected, bson::Document::from(options));
+    }
+
+    #[test]
+    fn fail_point_to_document() {
+        let fail_point = FailPoint::fail_command(&["insert", "find"]).times(2).error_code(91);
+        let expected = doc! {
+            "configureFailPoint": "failCommand",
+            "mode": { "times": 2 },
+            "data": { "failCommands": ["insert", "find"], "errorCode": 91 },
+        };
+        assert_eq!(expected, bson::Document::from(fail_point));
+
+        let fail_point = FailPoint::fail_command(&["insert"]).close_connection();
+        let expected = doc! {
+            "configureFailPoint": "failCommand",
+            "mode": "alwaysOn",
+            "data": { "failCommands": ["insert"], "closeConnection": true },
+        };
+        assert_eq!(expected, bson::Document::from(fail_point));
+
+        let expected = doc! { "configureFailPoint": "failCommand", "mode": "off" };
+        let fail_point = FailPoint::new("failCommand", FailPointMode::Off);
+        assert_eq!(expected, bson::Document::from(fail_point));
+    }
+
+    #[test]
+    fn decode_database_and_collection_lists() {
+        let database: DatabaseInfo = decode_reply(doc! {
+            "name": "shop",
+            "sizeOnDisk": 73728.0,
+            "empty": false,
+        }).unwrap();
+        assert_eq!(Some(73728), database.size_on_disk);
+
+        let name_only: DatabaseInfo = decode_reply(doc! { "name": "shop" }).unwrap();
+        assert_eq!(None, name_only.size_on_disk);
+
+        let collection: CollectionInfo = decode_reply(doc! {
+            "name": "orders",
+            "type": "collection",
+            "options": { "capped": true },
+            "info": { "readOnly": false, "uuid": "not-a-real-uuid" },
+            "idIndex": { "v": 2, "key": { "_id": 1 }, "name": "_id_" },
+        }).unwrap();
+        assert_eq!("collection", collection.collection_type);
+        assert_eq!(Some(&Bson::Boolean(true)), collection.options.get("capped"));
+        assert_eq!(Some(false), collection.info.as_ref().unwrap().read_only);
+
+        assert!(!collection.is_view());
+        assert_eq!(None, collection.view_on());
+
+        let legacy: CollectionInfo = decode_reply(doc! { "name": "orders" }).unwrap();
+        assert_eq!("collection", legacy.collection_type);
+        assert!(legacy.options.is_empty());
+
+        let view: CollectionInfo = decode_reply(doc! {
+            "name": "open_orders",
+            "type": "view",
+            "options": { "viewOn": "orders", "pipeline": [{ "$match": { "open": true } }] },
+            "info": { "readOnly": true },
+        }).unwrap();
+        assert!(view.is_view());
+        assert!(!view.is_time_series());
+        assert_eq!(Some("orders"), view.view_on());
+        assert_eq!(Some(vec![doc! { "$match": { "open": true } }]), view.pipeline());
+
+        let metrics: CollectionInfo = decode_reply(doc! {
+            "name": "metrics",
+            "type": "timeseries",
+            "options": {
+                "timeseries": {
+                    "timeField": "ts",
+                    "granularity": "seconds",
+                    "bucketMaxSpanSeconds": 3600,
+                },
+            },
+        }).unwrap();
+        assert!(metrics.is_time_series());
+        assert_eq!("ts", metrics.time_series().unwrap().time_field);
+    }
+
+    #[test]
+    fn decode_server_status_reply() {
+        let reply = doc! {
+            "host": "db0",
+            "version": "4.4.6",
+            "process": "mongod",
+            "uptime": 120.0,
+            "localTime": Bson::UtcDatetime(::chrono::Utc::now()),
+            "connections": { "current": 3, "available": 51197, "totalCreated": 9_i64 },
+            "opcounters": {
+                "insert": 1_i64,
+                "query": 2_i64,
+                "update": 3_i64,
+                "delete": 4_i64,
+                "getmore": 5_i64,
+                "command": 6_i64,
+                "deprecated": { "total": 0 },
+            },
+            "wiredTiger": {
+                "cache": {
+                    "bytes currently in the cache": 4096.0,
+                    "maximum bytes configured": 1_073_741_824_i64,
+                    "eviction walks started": 0,
+                },
+                "uri": "statistics:",
+            },
+            "mem": { "bits": 64, "resident": 80, "virtual": 1500, "supported": true },
+            "network": { "bytesIn": 10_i64, "bytesOut": 20_i64, "numRequests": 3_i64 },
+            "asserts": { "regular": 0 },
+        };
+
+        let status: ServerStatus = decode_reply(reply).unwrap();
+
+        assert_eq!(Some(String::from("mongod")), status.process);
+        assert_eq!(Some(120.0), status.uptime);
+        assert_eq!(9, status.connections.unwrap().total_created);
+
+        let opcounters = status.opcounters.unwrap();
+        assert_eq!(6, opcounters.command);
+        assert!(opcounters.other.get("deprecated").is_some());
+
+        let wired_tiger = status.wired_tiger.unwrap();
+        let cache = wired_tiger.cache.unwrap();
+        assert_eq!(4096, cache.bytes_in_cache);
+        assert_eq!(Some(&Bson::I32(0)), cache.other.get("eviction walks started"));
+        assert_eq!(Some(&Bson::from("statistics:")), wired_tiger.other.get("uri"));
+
+        let mem = status.mem.unwrap();
+        assert_eq!((80, 1500), (mem.resident_mb, mem.virtual_mb));
+        assert_eq!(Some(&Bson::Boolean(true)), mem.other.get("supported"));
+
+        assert_eq!(3, status.network.unwrap().num_requests);
+        assert_eq!(Some(&Bson::Document(doc! { "regular": 0 })), status.other.get("asserts"));
+        match status.other.get("localTime") {
+            Some(&Bson::UtcDatetime(_)) => (),
+            other => panic!("Expected localTime to stay a date, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_storage_stats() {
+        let database: DatabaseStats = decode_reply(doc! {
+            "db": "shop",
+            "collections": 2,
+            "views": 0,
+            "objects": 10_i64,
+            "avgObjSize": 42.5,
+            "dataSize": 425.0,
+            "storageSize": 8192.0,
+            "indexes": 3,
+            "indexSize": 12288.0,
+            "totalSize": 20480.0,
+            "scaleFactor": 1.0,
+            "fsUsedSize": 1e9,
+        }).unwrap();
+        assert_eq!(10, database.objects);
+        assert_eq!(425, database.data_size);
+        assert_eq!(Some(20480), database.total_size);
+        assert_eq!(Some(&Bson::FloatingPoint(1e9)), database.other.get("fsUsedSize"));
+
+        let collection: CollectionStats = decode_reply(doc! {
+            "ns": "shop.orders",
+            "count": 0,
+            "size": 0,
+            "storageSize": 4,
+            "nindexes": 2,
+            "totalIndexSize": 8,
+            "indexSizes": { "_id_": 4, "created_1": 4.0 },
+            "capped": false,
+            "scaleFactor": 1024,
+        }).unwrap();
+        assert_eq!(None, collection.avg_obj_size);
+        assert_eq!(Some(&4), collection.index_sizes.get("created_1"));
+        assert_eq!(None, collection.total_size);
+        assert_eq!(1024, collection.scale_factor);
+    }
+
+    #[test]
+    fn decode_profiler_replies() {
+        let status = ProfilingStatus::from_reply(&doc! {
+            "was": 1,
+            "slowms": 100,
+            "sampleRate": 1.0,
+            "ok": 1.0,
+        }).unwrap();
+        assert_eq!(ProfilingLevel::SlowOperations, status.level);
+        assert_eq!(100, status.slow_ms);
+        assert_eq!(Some(1.0), status.sample_rate);
+        assert!(ProfilingStatus::from_reply(&doc! { "was": 3, "slowms": 100 }).is_err());
+
+        let entry: ProfileEntry = decode_reply(doc! {
+            "op": "query",
+            "ns": "shop.orders",
+            "command": { "find": "orders", "filter": { "x": 1 } },
+            "docsExamined": 120,
+            "nreturned": 1,
+            "millis": 7,
+            "planSummary": "COLLSCAN",
+            "ts": Bson::UtcDatetime(::chrono::Utc::now()),
+            "client": "127.0.0.1",
+            "locks": {},
+        }).unwrap();
+        assert_eq!(7, entry.millis);
+        assert_eq!(Some(120), entry.docs_examined);
+        assert_eq!(None, entry.keys_examined);
+        assert_eq!(Some(String::from("COLLSCAN")), entry.plan_summary);
+        assert!(entry.other.get("locks").is_some());
+    }
+}
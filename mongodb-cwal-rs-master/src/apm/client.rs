@@ -1,10 +1,15 @@
-use apm::{CommandStarted, CommandResult};
+use apm::{CommandStarted, CommandResult, CredentialFallback, ServerHeartbeatFailed,
+          ServerHeartbeatStarted, ServerHeartbeatSucceeded};
 use Client;
 use error::Result;
 
 pub trait EventRunner {
     fn run_start_hooks(&self, hook: &CommandStarted) -> Result<()>;
     fn run_completion_hooks(&self, hook: &CommandResult) -> Result<()>;
+    fn run_credential_fallback_hooks(&self, hook: &CredentialFallback) -> Result<()>;
+    fn run_heartbeat_started_hooks(&self, hook: &ServerHeartbeatStarted) -> Result<()>;
+    fn run_heartbeat_succeeded_hooks(&self, hook: &ServerHeartbeatSucceeded) -> Result<()>;
+    fn run_heartbeat_failed_hooks(&self, hook: &ServerHeartbeatFailed) -> Result<()>;
 }
 
 impl EventRunner for Client {
@@ -15,4 +20,20 @@ impl EventRunner for Client {
     fn run_completion_hooks(&self, hook: &CommandResult) -> Result<()> {
         self.listener.run_completion_hooks(self.clone(), hook)
     }
+
+    fn run_credential_fallback_hooks(&self, hook: &CredentialFallback) -> Result<()> {
+        self.listener.run_credential_fallback_hooks(self.clone(), hook)
+    }
+
+    fn run_heartbeat_started_hooks(&self, hook: &ServerHeartbeatStarted) -> Result<()> {
+        self.listener.run_heartbeat_started_hooks(self.clone(), hook)
+    }
+
+    fn run_heartbeat_succeeded_hooks(&self, hook: &ServerHeartbeatSucceeded) -> Result<()> {
+        self.listener.run_heartbeat_succeeded_hooks(self.clone(), hook)
+    }
+
+    fn run_heartbeat_failed_hooks(&self, hook: &ServerHeartbeatFailed) -> Result<()> {
+        self.listener.run_heartbeat_failed_hooks(self.clone(), hook)
+    }
 }
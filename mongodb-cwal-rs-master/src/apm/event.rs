@@ -26,6 +26,90 @@ impl Display for CommandStarted {
     }
 }
 
+/// Reports a connection that authenticated with the previous credential of a rotation
+/// because the server rejected the current one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialFallback {
+    /// The user of the credential that was rejected.
+    pub rejected_user: String,
+    /// The user the connection authenticated as instead.
+    pub user: String,
+    /// Why the current credential was rejected.
+    pub failure: String,
+    pub connection_string: String,
+}
+
+impl Display for CredentialFallback {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(
+            fmt,
+            "AUTH {} FALLBACK: {} rejected ({}), authenticated as {}",
+            self.connection_string,
+            self.rejected_user,
+            self.failure,
+            self.user
+        )
+    }
+}
+
+/// Reports a heartbeat about to be sent to a server by its monitor.
+///
+/// Monitors poll servers at the heartbeat frequency rather than awaiting topology changes,
+/// so `awaited` is always false.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerHeartbeatStarted {
+    pub connection_string: String,
+    pub awaited: bool,
+}
+
+impl Display for ServerHeartbeatStarted {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "HEARTBEAT {} STARTED", self.connection_string)
+    }
+}
+
+/// Reports a heartbeat answered by a server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerHeartbeatSucceeded {
+    /// How long the heartbeat took in nanoseconds, including connecting to the server.
+    pub duration: u64,
+    pub connection_string: String,
+    pub awaited: bool,
+}
+
+impl Display for ServerHeartbeatSucceeded {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(
+            fmt,
+            "HEARTBEAT {} SUCCEEDED ({} ns)",
+            self.connection_string,
+            self.duration.separated_string()
+        )
+    }
+}
+
+/// Reports a heartbeat that could not be sent to a server or was not answered.
+#[derive(Debug, Clone)]
+pub struct ServerHeartbeatFailed<'a> {
+    /// How long the heartbeat took in nanoseconds, including connecting to the server.
+    pub duration: u64,
+    pub failure: &'a MongoError,
+    pub connection_string: String,
+    pub awaited: bool,
+}
+
+impl<'a> Display for ServerHeartbeatFailed<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(
+            fmt,
+            "HEARTBEAT {} FAILED: {} ({} ns)",
+            self.connection_string,
+            self.failure,
+            self.duration.separated_string()
+        )
+    }
+}
+
 /// Contains the information about a given command that completed.
 #[derive(Debug, Clone)]
 pub enum CommandResult<'a> {
@@ -2,18 +2,27 @@ use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 
-use apm::event::{CommandStarted, CommandResult};
+use apm::event::{CommandStarted, CommandResult, CredentialFallback, ServerHeartbeatFailed,
+                 ServerHeartbeatStarted, ServerHeartbeatSucceeded};
 use Client;
 use error::Result;
 
 pub type StartHook = fn(Client, &CommandStarted);
 pub type CompletionHook = fn(Client, &CommandResult);
+pub type CredentialFallbackHook = fn(Client, &CredentialFallback);
+pub type HeartbeatStartedHook = fn(Client, &ServerHeartbeatStarted);
+pub type HeartbeatSucceededHook = fn(Client, &ServerHeartbeatSucceeded);
+pub type HeartbeatFailedHook = fn(Client, &ServerHeartbeatFailed);
 
 pub struct Listener {
     no_start_hooks: AtomicBool,
     no_completion_hooks: AtomicBool,
     start_hooks: RwLock<Vec<StartHook>>,
     completion_hooks: RwLock<Vec<CompletionHook>>,
+    credential_fallback_hooks: RwLock<Vec<CredentialFallbackHook>>,
+    heartbeat_started_hooks: RwLock<Vec<HeartbeatStartedHook>>,
+    heartbeat_succeeded_hooks: RwLock<Vec<HeartbeatSucceededHook>>,
+    heartbeat_failed_hooks: RwLock<Vec<HeartbeatFailedHook>>,
 }
 
 impl Listener {
@@ -23,6 +32,10 @@ impl Listener {
             no_completion_hooks: AtomicBool::new(true),
             start_hooks: RwLock::new(Vec::new()),
             completion_hooks: RwLock::new(Vec::new()),
+            credential_fallback_hooks: RwLock::new(Vec::new()),
+            heartbeat_started_hooks: RwLock::new(Vec::new()),
+            heartbeat_succeeded_hooks: RwLock::new(Vec::new()),
+            heartbeat_failed_hooks: RwLock::new(Vec::new()),
         }
     }
 
@@ -38,6 +51,26 @@ impl Listener {
         Ok(guard.deref_mut().push(hook))
     }
 
+    pub fn add_credential_fallback_hook(&self, hook: CredentialFallbackHook) -> Result<()> {
+        let mut guard = self.credential_fallback_hooks.write()?;
+        Ok(guard.deref_mut().push(hook))
+    }
+
+    pub fn add_heartbeat_started_hook(&self, hook: HeartbeatStartedHook) -> Result<()> {
+        let mut guard = self.heartbeat_started_hooks.write()?;
+        Ok(guard.deref_mut().push(hook))
+    }
+
+    pub fn add_heartbeat_succeeded_hook(&self, hook: HeartbeatSucceededHook) -> Result<()> {
+        let mut guard = self.heartbeat_succeeded_hooks.write()?;
+        Ok(guard.deref_mut().push(hook))
+    }
+
+    pub fn add_heartbeat_failed_hook(&self, hook: HeartbeatFailedHook) -> Result<()> {
+        let mut guard = self.heartbeat_failed_hooks.write()?;
+        Ok(guard.deref_mut().push(hook))
+    }
+
     pub fn run_start_hooks(&self, client: Client, started: &CommandStarted) -> Result<()> {
         if self.no_start_hooks.load(Ordering::SeqCst) {
             return Ok(());
@@ -65,4 +98,60 @@ impl Listener {
 
         Ok(())
     }
+
+    pub fn run_credential_fallback_hooks(
+        &self,
+        client: Client,
+        fallback: &CredentialFallback,
+    ) -> Result<()> {
+        let guard = self.credential_fallback_hooks.read()?;
+
+        for hook in guard.deref().iter() {
+            hook(client.clone(), fallback);
+        }
+
+        Ok(())
+    }
+
+    pub fn run_heartbeat_started_hooks(
+        &self,
+        client: Client,
+        started: &ServerHeartbeatStarted,
+    ) -> Result<()> {
+        let guard = self.heartbeat_started_hooks.read()?;
+
+        for hook in guard.deref().iter() {
+            hook(client.clone(), started);
+        }
+
+        Ok(())
+    }
+
+    pub fn run_heartbeat_succeeded_hooks(
+        &self,
+        client: Client,
+        succeeded: &ServerHeartbeatSucceeded,
+    ) -> Result<()> {
+        let guard = self.heartbeat_succeeded_hooks.read()?;
+
+        for hook in guard.deref().iter() {
+            hook(client.clone(), succeeded);
+        }
+
+        Ok(())
+    }
+
+    pub fn run_heartbeat_failed_hooks(
+        &self,
+        client: Client,
+        failed: &ServerHeartbeatFailed,
+    ) -> Result<()> {
+        let guard = self.heartbeat_failed_hooks.read()?;
+
+        for hook in guard.deref().iter() {
+            hook(client.clone(), failed);
+        }
+
+        Ok(())
+    }
 }
@@ -13,16 +13,16 @@ use error::{
     Error::{DefaultError, MaliciousServerError, OperationError, ResponseError},
     MaliciousServerErrorType, Result,
 };
+use crypto::{Backend, CryptoBackend, SHA1_OUTPUT};
 use hex;
-use hmac::{Hmac, Mac};
-use md5::Md5;
-use pbkdf2::pbkdf2;
 use pool::PooledStream;
-use sha1::{Digest, Sha1};
 use std::fmt;
 use textnonce::TextNonce;
 use CommandType::Suppressed;
 
+// The fewest PBKDF2 iterations RFC 5802 allows servers to ask for.
+const SCRAM_MIN_ITERATIONS: i64 = 4096;
+
 /// Handles SCRAM-SHA-1 authentication logic.
 pub struct Authenticator<'a> {
     stream: &'a mut PooledStream,
@@ -53,9 +53,6 @@ struct AuthData {
     response: Document,
 }
 
-type HmacSha1 = Hmac<Sha1>;
-const SHA1_OUTPUT: usize = 20;
-
 impl Authenticator<'_> {
     /// Creates a new authenticator.
     pub fn new(stream: &mut PooledStream, client: Client) -> Authenticator {
@@ -126,7 +123,7 @@ impl Authenticator<'_> {
             "r={},s={},i={}",
             String,
             String,
-            u32
+            i64
         );
 
         let rnonce_b64 =
@@ -149,32 +146,20 @@ impl Authenticator<'_> {
             )))
         })?;
 
-        let i =
-            i_opt.ok_or_else(|| ResponseError(String::from("Invalid iteration count returned")))?;
+        let i = iteration_count(i_opt)?;
 
         // Hash password
-        let hashed_password = hex::encode(Md5::digest(password.as_bytes()));
+        let hashed_password = hex::encode(Backend::md5(password.as_bytes())?);
 
         // Salt password
-        let mut salted_password = [0u8; SHA1_OUTPUT];
-        pbkdf2::<HmacSha1>(
-            hashed_password.as_bytes(),
-            &salt,
-            i as usize,
-            &mut salted_password,
-        );
+        let salted_password: [u8; SHA1_OUTPUT] =
+            Backend::pbkdf2_hmac_sha1(hashed_password.as_bytes(), &salt, i)?;
 
         // Compute client key
-        let mut client_key_hmac =
-            HmacSha1::new_varkey(&salted_password).expect("HMAC can take key of any size");
-        let client_key_bytes = b"Client Key";
-        client_key_hmac.input(client_key_bytes);
-        let client_key = client_key_hmac.result().code().to_owned();
+        let client_key = Backend::hmac_sha1(&salted_password, b"Client Key")?;
 
         // Hash into stored key
-        let mut stored_key_sha1 = Sha1::new();
-        stored_key_sha1.input(&client_key[..]);
-        let stored_key = stored_key_sha1.result();
+        let stored_key = Backend::sha1(&client_key)?;
 
         // Create auth message
         let without_proof = format!("c=biws,r={}", rnonce_b64);
@@ -184,10 +169,7 @@ impl Authenticator<'_> {
         );
 
         // Compute client signature
-        let mut client_signature_hmac =
-            HmacSha1::new_varkey(&stored_key).expect("HMAC can take key of any size");
-        client_signature_hmac.input(auth_message.as_bytes());
-        let client_signature = client_signature_hmac.result().code().to_owned();
+        let client_signature = Backend::hmac_sha1(&stored_key, auth_message.as_bytes())?;
 
         // Sanity check
         if client_key.len() != client_signature.len() {
@@ -198,8 +180,8 @@ impl Authenticator<'_> {
 
         // Compute proof by xor'ing key and signature
         let proof: Vec<_> = client_key
-            .into_iter()
-            .zip(client_signature)
+            .iter()
+            .zip(client_signature.iter())
             .map(|(key_byte, sig_byte)| key_byte ^ sig_byte)
             .collect();
 
@@ -231,17 +213,10 @@ impl Authenticator<'_> {
         };
 
         // Compute server key
-        let mut server_key_hmac = HmacSha1::new_varkey(&auth_data.salted_password)
-            .expect("HMAC can take key of any size");
-        let server_key_bytes = b"Server Key";
-        server_key_hmac.input(server_key_bytes);
-        let server_key = server_key_hmac.result().code();
+        let server_key = Backend::hmac_sha1(&auth_data.salted_password, b"Server Key")?;
 
         // Compute server signature
-        let mut server_signature_hmac =
-            HmacSha1::new_varkey(&server_key).expect("HMAC can take key of any size");
-        server_signature_hmac.input(auth_data.message.as_bytes());
-        let server_signature = server_signature_hmac.result().code();
+        let server_signature = Backend::hmac_sha1(&server_key, auth_data.message.as_bytes())?;
 
         let mut doc = auth_data.response;
 
@@ -327,3 +302,30 @@ impl Authenticator<'_> {
         }
     }
 }
+
+// Checks the iteration count the server asked for, before any work is done for it.
+fn iteration_count(i: Option<i64>) -> Result<u32> {
+    match i {
+        Some(i) if i >= SCRAM_MIN_ITERATIONS && i <= i64::from(u32::MAX) => Ok(i as u32),
+        Some(i) => Err(ResponseError(format!("Invalid iteration count {} returned", i))),
+        None => Err(ResponseError(String::from("Invalid iteration count returned"))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rejects_weak_or_invalid_iteration_counts() {
+        assert_eq!(4096, iteration_count(Some(4096)).unwrap());
+        assert_eq!(10000, iteration_count(Some(10000)).unwrap());
+
+        for &i in &[None, Some(-1), Some(0), Some(4095), Some(i64::from(u32::MAX) + 1)] {
+            match iteration_count(i) {
+                Err(ResponseError(_)) => (),
+                other => panic!("expected a response error for {:?}, got {:?}", i, other),
+            }
+        }
+    }
+}
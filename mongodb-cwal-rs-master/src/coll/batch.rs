@@ -1,13 +1,19 @@
 //! Models for collection-level batch operations.
-use super::options::WriteModel;
+use super::options::{Collation, WriteModel};
 
 use bson::{Bson, bson, Document, doc};
+use topology::WriteLimits;
+use Error::ArgumentError;
+use Result;
 use std::convert::From;
+use std::ops::Range;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeleteModel {
     pub filter: Document,
     pub multi: bool,
+    pub hint: Option<Bson>,
+    pub collation: Option<Collation>,
 }
 
 impl DeleteModel {
@@ -15,16 +21,41 @@ impl DeleteModel {
         DeleteModel {
             filter: filter,
             multi: multi,
+            hint: None,
+            collation: None,
         }
     }
 }
 
+impl From<DeleteModel> for Document {
+    fn from(model: DeleteModel) -> Self {
+        let mut document =
+            doc! {
+                "q": model.filter,
+                "limit": if model.multi { 0_i64 } else { 1_i64 },
+            };
+
+        if let Some(hint) = model.hint {
+            document.insert("hint", hint);
+        }
+
+        if let Some(collation) = model.collation {
+            document.insert("collation", Document::from(collation));
+        }
+
+        document
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct UpdateModel {
     pub filter: Document,
     pub update: Document,
     pub upsert: Option<bool>,
     pub multi: bool,
+    pub array_filters: Option<Vec<Document>>,
+    pub hint: Option<Bson>,
+    pub collation: Option<Collation>,
 }
 
 impl UpdateModel {
@@ -39,6 +70,9 @@ impl UpdateModel {
             update: update,
             upsert: upsert,
             multi: multi,
+            array_filters: None,
+            hint: None,
+            collation: None,
         }
     }
 }
@@ -59,6 +93,19 @@ impl From<UpdateModel> for Document {
             document.insert("multi", Bson::Boolean(true));
         }
 
+        if let Some(array_filters) = model.array_filters {
+            let filters: Vec<_> = array_filters.into_iter().map(Bson::Document).collect();
+            document.insert("arrayFilters", filters);
+        }
+
+        if let Some(hint) = model.hint {
+            document.insert("hint", hint);
+        }
+
+        if let Some(collation) = model.collation {
+            document.insert("collation", Document::from(collation));
+        }
+
         document
     }
 }
@@ -75,20 +122,10 @@ impl From<WriteModel> for Batch {
         match model {
             WriteModel::InsertOne { document } => Batch::Insert(vec![document]),
             WriteModel::DeleteOne { filter } => {
-                Batch::Delete(vec![
-                    DeleteModel {
-                        filter: filter,
-                        multi: false,
-                    },
-                ])
+                Batch::Delete(vec![DeleteModel::new(filter, false)])
             }
             WriteModel::DeleteMany { filter } => {
-                Batch::Delete(vec![
-                    DeleteModel {
-                        filter: filter,
-                        multi: true,
-                    },
-                ])
+                Batch::Delete(vec![DeleteModel::new(filter, true)])
             }
             WriteModel::ReplaceOne {
                 filter,
@@ -100,28 +137,14 @@ impl From<WriteModel> for Batch {
                 update,
                 upsert,
             } => {
-                Batch::Update(vec![
-                    UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: false,
-                    },
-                ])
+                Batch::Update(vec![UpdateModel::new(filter, update, upsert, false)])
             }
             WriteModel::UpdateMany {
                 filter,
                 update,
                 upsert,
             } => {
-                Batch::Update(vec![
-                    UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: true,
-                    },
-                ])
+                Batch::Update(vec![UpdateModel::new(filter, update, upsert, true)])
             }
         }
     }
@@ -159,16 +182,10 @@ impl Batch {
             Batch::Delete(ref mut models) => {
                 match model {
                     WriteModel::DeleteOne { filter } => {
-                        models.push(DeleteModel {
-                            filter: filter,
-                            multi: false,
-                        })
+                        models.push(DeleteModel::new(filter, false))
                     }
                     WriteModel::DeleteMany { filter } => {
-                        models.push(DeleteModel {
-                            filter: filter,
-                            multi: true,
-                        })
+                        models.push(DeleteModel::new(filter, true))
                     }
                     _ => return Some(model),
                 }
@@ -185,24 +202,14 @@ impl Batch {
                         update,
                         upsert,
                     } => {
-                        models.push(UpdateModel {
-                            filter: filter,
-                            update: update,
-                            upsert: upsert,
-                            multi: false,
-                        })
+                        models.push(UpdateModel::new(filter, update, upsert, false))
                     }
                     WriteModel::UpdateMany {
                         filter,
                         update,
                         upsert,
                     } => {
-                        models.push(UpdateModel {
-                            filter: filter,
-                            update: update,
-                            upsert: upsert,
-                            multi: true,
-                        })
+                        models.push(UpdateModel::new(filter, update, upsert, true))
                     }
                     _ => return Some(model),
                 }
@@ -212,3 +219,102 @@ impl Batch {
         None
     }
 }
+
+/// Fails before anything is sent if one of the documents with the given encoded sizes is
+/// larger than the servers accept, naming its position, e.g. `Document 3` or
+/// `Update document 0`.
+pub fn check_sizes(kind: &str, sizes: &[i64], limits: WriteLimits) -> Result<()> {
+    for (index, &size) in sizes.iter().enumerate() {
+        if size > limits.max_bson_object_size {
+            return Err(ArgumentError(format!(
+                "{} {} is {} bytes, more than the maximum of {} bytes.",
+                kind,
+                index,
+                size,
+                limits.max_bson_object_size
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Splits consecutive documents with the given encoded sizes into runs that each fit in a
+/// single write command, respecting the batch size and the size of the `documents` array.
+/// A document too large to share a command is placed in a run of its own.
+pub fn split_by_limits(sizes: &[i64], limits: WriteLimits) -> Vec<Range<usize>> {
+    let max_count = limits.max_write_batch_size.max(1) as usize;
+    let max_bytes = limits.max_bson_object_size.min(limits.max_message_size_bytes);
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    // The array length prefix and terminator.
+    let mut bytes = 5;
+
+    for (index, &size) in sizes.iter().enumerate() {
+        // The type tag, the decimal index key with its terminator, then the document.
+        let element = 1 + (index - start).to_string().len() as i64 + 1 + size;
+        let count = index - start;
+
+        if count > 0 && (count == max_count || bytes + element > max_bytes) {
+            runs.push(start..index);
+            start = index;
+            bytes = 5 + 1 + 1 + 1 + size;
+        } else {
+            bytes += element;
+        }
+    }
+
+    if start < sizes.len() {
+        runs.push(start..sizes.len());
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limits(max_bson_object_size: i64, max_write_batch_size: i64) -> WriteLimits {
+        WriteLimits {
+            max_bson_object_size: max_bson_object_size,
+            max_message_size_bytes: 48000000,
+            max_write_batch_size: max_write_batch_size,
+        }
+    }
+
+    #[test]
+    fn splits_on_batch_size() {
+        let sizes = vec![10; 7];
+        assert_eq!(vec![0..3, 3..6, 6..7], split_by_limits(&sizes, limits(1 << 20, 3)));
+        assert_eq!(vec![0..7], split_by_limits(&sizes, limits(1 << 20, 7)));
+        assert!(split_by_limits(&[], limits(1 << 20, 3)).is_empty());
+    }
+
+    #[test]
+    fn splits_on_bytes() {
+        // Each element takes 3 bytes of overhead for single-digit keys, plus 5 for the array.
+        let sizes = vec![40, 40, 40, 100, 10];
+        assert_eq!(
+            vec![0..2, 2..3, 3..4, 4..5],
+            split_by_limits(&sizes, limits(91, 1000))
+        );
+        assert_eq!(
+            vec![0..3, 3..5],
+            split_by_limits(&sizes, limits(134, 1000))
+        );
+    }
+
+    #[test]
+    fn checks_document_sizes() {
+        assert!(check_sizes("Document", &[10, 100], limits(100, 1000)).is_ok());
+        match check_sizes("Update document", &[10, 101, 200], limits(100, 1000)) {
+            Err(ArgumentError(message)) => assert_eq!(
+                "Update document 1 is 101 bytes, more than the maximum of 100 bytes.",
+                message
+            ),
+            other => panic!("Expected an argument error, got {:?}", other),
+        }
+    }
+}
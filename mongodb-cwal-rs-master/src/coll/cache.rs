@@ -0,0 +1,261 @@
+//! Opt-in read-through cache for `_id` lookups on hot collections.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Bson, Client, ThreadedClient};
+//! # use mongodb::coll::cache::DocumentCache;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let cache = DocumentCache::new(client.db("reference").collection("countries"), 256);
+//!
+//! // The first lookup reads from the server, later ones are served from memory.
+//! let country = cache.find_one_by_id(Bson::String(String::from("NZ"))).unwrap();
+//!
+//! // Writes made through other handles must be reported to the cache.
+//! cache.invalidate(&Bson::String(String::from("NZ"))).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+use super::Collection;
+use Result;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// A bounded, least-recently-used cache of documents keyed by `_id`.
+///
+/// The cache never observes writes on its own; callers are responsible for invalidating
+/// entries that they modify or delete.
+#[derive(Debug)]
+pub struct DocumentCache {
+    coll: Collection,
+    entries: Mutex<Lru>,
+}
+
+impl DocumentCache {
+    /// Wraps a collection with a cache holding at most `capacity` documents.
+    pub fn new(coll: Collection, capacity: usize) -> DocumentCache {
+        DocumentCache {
+            coll: coll,
+            entries: Mutex::new(Lru::new(capacity)),
+        }
+    }
+
+    /// Returns the underlying collection.
+    pub fn collection(&self) -> &Collection {
+        &self.coll
+    }
+
+    /// Returns the document with the given `_id`, reading it from the server on a cache miss.
+    ///
+    /// Documents that do not exist are not cached, so a later insert is picked up. Neither are
+    /// documents read while an invalidation happened, since they may predate the write it
+    /// reports.
+    pub fn find_one_by_id(&self, id: Bson) -> Result<Option<bson::Document>> {
+        let key = Lru::key(&id)?;
+
+        let generation = {
+            let mut entries = self.entries.lock()?;
+            if let Some(doc) = entries.get(&key) {
+                return Ok(Some(doc));
+            }
+            entries.generation
+        };
+
+        let doc = self.coll.find_one(Some(doc! { "_id": id }), None)?;
+
+        if let Some(ref found) = doc {
+            self.entries.lock()?.insert_read(generation, key, found.clone());
+        }
+
+        Ok(doc)
+    }
+
+    /// Evicts the document with the given `_id`, if it is cached.
+    pub fn invalidate(&self, id: &Bson) -> Result<()> {
+        let key = Lru::key(id)?;
+        self.entries.lock()?.invalidate(&key);
+        Ok(())
+    }
+
+    /// Evicts every cached document.
+    pub fn invalidate_all(&self) -> Result<()> {
+        self.entries.lock()?.clear();
+        Ok(())
+    }
+
+    /// Returns the number of documents currently cached.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.entries.lock()?.len())
+    }
+
+    /// Returns whether the cache currently holds no documents.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+// Entries are keyed by the encoded `{ _id: ... }` document, so ids of different BSON types
+// never collide even when they print the same way. The generation counts invalidations, so
+// that documents read before one are not cached after it.
+#[derive(Debug)]
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    generation: u64,
+    entries: HashMap<Vec<u8>, (bson::Document, u64)>,
+    recency: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Lru {
+        Lru {
+            capacity: capacity,
+            tick: 0,
+            generation: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    fn key(id: &Bson) -> Result<Vec<u8>> {
+        let mut key = Vec::new();
+        bson::encode_document(&mut key, &doc! { "_id": id.clone() })?;
+        Ok(key)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<bson::Document> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.1);
+        self.recency.insert(tick, key.to_vec());
+        entry.1 = tick;
+        Some(entry.0.clone())
+    }
+
+    fn insert(&mut self, key: Vec<u8>, doc: bson::Document) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.recency.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+
+            if let Some(evicted) = self.recency.remove(&oldest) {
+                self.entries.remove(&evicted);
+            }
+        }
+
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (doc, self.tick));
+    }
+
+    // Caches a document read from the server, unless an invalidation happened since the
+    // `generation` the read started at.
+    fn insert_read(&mut self, generation: u64, key: Vec<u8>, doc: bson::Document) {
+        if generation == self.generation {
+            self.insert(key, doc);
+        }
+    }
+
+    fn invalidate(&mut self, key: &[u8]) {
+        self.generation += 1;
+        self.remove(key);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some((_, tick)) = self.entries.remove(key) {
+            self.recency.remove(&tick);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(id: i32) -> Vec<u8> {
+        Lru::key(&Bson::I32(id)).unwrap()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.insert(key(1), doc! { "_id": 1 });
+        lru.insert(key(2), doc! { "_id": 2 });
+
+        // Reading 1 makes 2 the eviction candidate.
+        assert!(lru.get(&key(1)).is_some());
+        lru.insert(key(3), doc! { "_id": 3 });
+
+        assert_eq!(lru.len(), 2);
+        assert!(lru.get(&key(2)).is_none());
+        assert!(lru.get(&key(1)).is_some());
+        assert!(lru.get(&key(3)).is_some());
+    }
+
+    #[test]
+    fn keys_distinguish_bson_types() {
+        let mut lru = Lru::new(4);
+        lru.insert(key(1), doc! { "_id": 1 });
+
+        assert!(lru.get(&Lru::key(&Bson::I64(1)).unwrap()).is_none());
+        assert!(lru.get(&Lru::key(&Bson::String(String::from("1"))).unwrap()).is_none());
+    }
+
+    #[test]
+    fn skips_reads_that_raced_an_invalidation() {
+        let mut lru = Lru::new(4);
+
+        // The read misses, then a write is reported before its result is cached.
+        let generation = lru.generation;
+        lru.invalidate(&key(1));
+        lru.insert_read(generation, key(1), doc! { "_id": 1, "stale": true });
+        assert!(lru.get(&key(1)).is_none());
+
+        let generation = lru.generation;
+        lru.clear();
+        lru.insert_read(generation, key(1), doc! { "_id": 1, "stale": true });
+        assert!(lru.get(&key(1)).is_none());
+
+        let generation = lru.generation;
+        lru.insert_read(generation, key(1), doc! { "_id": 1 });
+        assert!(lru.get(&key(1)).is_some());
+    }
+
+    #[test]
+    fn remove_and_zero_capacity() {
+        let mut lru = Lru::new(2);
+        lru.insert(key(1), doc! { "_id": 1 });
+        lru.remove(&key(1));
+        assert_eq!(lru.len(), 0);
+        assert!(lru.recency.is_empty());
+
+        let mut empty = Lru::new(0);
+        empty.insert(key(1), doc! { "_id": 1 });
+        assert_eq!(empty.len(), 0);
+    }
+}
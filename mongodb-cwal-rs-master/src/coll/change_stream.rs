@@ -0,0 +1,236 @@
+//! Change stream stage options and typed change events.
+//!
+//! The driver does not yet resume change streams on its own; the `$changeStream` stage built
+//! from `ChangeStreamOptions` is run like any other pipeline, and the returned documents can
+//! be decoded with `ChangeStreamEvent::from_document`.
+//!
+//! ```no_run
+//! # extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::change_stream::{ChangeStreamEvent, ChangeStreamOptions, FullDocument};
+//! # use mongodb::coll::options::AggregateOptions;
+//! # use mongodb::coll::pipeline::Pipeline;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//!
+//! let mut options = ChangeStreamOptions::new();
+//! options.full_document = Some(FullDocument::UpdateLookup);
+//!
+//! let pipeline = Pipeline::new().change_stream(options).build();
+//! let mut aggregate_options = AggregateOptions::new();
+//! aggregate_options.max_await_time_ms = Some(1000);
+//! let mut cursor = coll.aggregate(pipeline, Some(aggregate_options)).unwrap();
+//!
+//! // Iterating stops at the first empty batch, when nothing changed within the await time,
+//! // but the stream stays open until its cursor id is zero.
+//! while cursor.id() != 0 {
+//!     for doc in cursor.by_ref() {
+//!         let event = ChangeStreamEvent::from_document(doc.unwrap()).unwrap();
+//!         println!("{} {:?}", event.operation_type, event.document_key);
+//!     }
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+
+use Error::DecoderError;
+use Result;
+
+/// Whether change events for updates carry the current version of the document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FullDocument {
+    /// Only deltas are reported for updates.
+    Default,
+    /// Looks up the current majority-committed version of updated documents.
+    UpdateLookup,
+    /// Reports the post-image recorded by the server, if there is one (MongoDB 6.0+).
+    WhenAvailable,
+    /// Reports the post-image recorded by the server, failing if there is none (MongoDB 6.0+).
+    Required,
+}
+
+impl FullDocument {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            FullDocument::Default => "default",
+            FullDocument::UpdateLookup => "updateLookup",
+            FullDocument::WhenAvailable => "whenAvailable",
+            FullDocument::Required => "required",
+        }
+    }
+}
+
+/// Whether change events carry the version of the document from before the change.
+///
+/// Pre-images must be enabled on the collection (MongoDB 6.0+).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FullDocumentBeforeChange {
+    Off,
+    WhenAvailable,
+    Required,
+}
+
+impl FullDocumentBeforeChange {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            FullDocumentBeforeChange::Off => "off",
+            FullDocumentBeforeChange::WhenAvailable => "whenAvailable",
+            FullDocumentBeforeChange::Required => "required",
+        }
+    }
+}
+
+/// Options for the `$changeStream` stage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChangeStreamOptions {
+    pub full_document: Option<FullDocument>,
+    pub full_document_before_change: Option<FullDocumentBeforeChange>,
+    /// Resumes after the event with this resume token.
+    pub resume_after: Option<bson::Document>,
+    /// Starts after the event with this resume token, even if it invalidated the stream.
+    pub start_after: Option<bson::Document>,
+    /// Starts at the given operation time, as a BSON timestamp:
+    /// the seconds in the high 32 bits and the increment in the low 32 bits.
+    pub start_at_operation_time: Option<i64>,
+}
+
+impl ChangeStreamOptions {
+    pub fn new() -> ChangeStreamOptions {
+        Default::default()
+    }
+}
+
+impl From<ChangeStreamOptions> for bson::Document {
+    fn from(options: ChangeStreamOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(full_document) = options.full_document {
+            document.insert("fullDocument", full_document.to_str());
+        }
+
+        if let Some(before_change) = options.full_document_before_change {
+            document.insert("fullDocumentBeforeChange", before_change.to_str());
+        }
+
+        if let Some(resume_after) = options.resume_after {
+            document.insert("resumeAfter", resume_after);
+        }
+
+        if let Some(start_after) = options.start_after {
+            document.insert("startAfter", start_after);
+        }
+
+        if let Some(time) = options.start_at_operation_time {
+            document.insert("startAtOperationTime", Bson::TimeStamp(time));
+        }
+
+        document
+    }
+}
+
+/// The namespace a change event applies to.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ChangeNamespace {
+    pub db: String,
+    #[serde(default)]
+    pub coll: Option<String>,
+}
+
+/// The fields modified by an update event.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct UpdateDescription {
+    #[serde(rename="updatedFields", default)]
+    pub updated_fields: bson::Document,
+    #[serde(rename="removedFields", default)]
+    pub removed_fields: Vec<String>,
+}
+
+/// A change event returned by a `$changeStream` stage.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ChangeStreamEvent {
+    /// The resume token of this event.
+    #[serde(rename="_id")]
+    pub id: bson::Document,
+
+    /// The kind of change, e.g. `insert`, `update`, `replace`, `delete` or `invalidate`.
+    #[serde(rename="operationType")]
+    pub operation_type: String,
+
+    #[serde(default)]
+    pub ns: Option<ChangeNamespace>,
+
+    /// The `_id` of the changed document, plus the shard key on sharded collections.
+    #[serde(rename="documentKey", default)]
+    pub document_key: Option<bson::Document>,
+
+    #[serde(rename="updateDescription", default)]
+    pub update_description: Option<UpdateDescription>,
+
+    #[serde(rename="fullDocument", default)]
+    pub full_document: Option<bson::Document>,
+
+    #[serde(rename="fullDocumentBeforeChange", default)]
+    pub full_document_before_change: Option<bson::Document>,
+
+    /// The operation time of the change, as a BSON timestamp.
+    #[serde(rename="clusterTime", default)]
+    pub cluster_time: Option<Bson>,
+}
+
+impl ChangeStreamEvent {
+    /// Decodes a document returned by a change stream.
+    pub fn from_document(document: bson::Document) -> Result<ChangeStreamEvent> {
+        bson::from_bson(Bson::Document(document)).map_err(DecoderError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn stage_options() {
+        let mut options = ChangeStreamOptions::new();
+        options.full_document = Some(FullDocument::UpdateLookup);
+        options.full_document_before_change = Some(FullDocumentBeforeChange::WhenAvailable);
+        options.start_at_operation_time = Some(1 << 32);
+
+        let expected = doc! {
+            "fullDocument": "updateLookup",
+            "fullDocumentBeforeChange": "whenAvailable",
+            "startAtOperationTime": Bson::TimeStamp(1 << 32),
+        };
+
+        assert_eq!(expected, bson::Document::from(options));
+    }
+
+    #[test]
+    fn decode_update_event() {
+        let event = ChangeStreamEvent::from_document(doc! {
+            "_id": { "_data": "8263" },
+            "operationType": "update",
+            "clusterTime": Bson::TimeStamp(7),
+            "ns": { "db": "shop", "coll": "orders" },
+            "documentKey": { "_id": 1 },
+            "updateDescription": {
+                "updatedFields": { "status": "shipped" },
+                "removedFields": ["eta"],
+            },
+        }).unwrap();
+
+        assert_eq!("update", event.operation_type);
+        assert_eq!(Some(doc! { "_id": 1 }), event.document_key);
+        assert_eq!(Some(String::from("orders")), event.ns.unwrap().coll);
+
+        let update = event.update_description.unwrap();
+        assert_eq!(doc! { "status": "shipped" }, update.updated_fields);
+        assert_eq!(vec![String::from("eta")], update.removed_fields);
+        assert!(event.full_document.is_none());
+    }
+}
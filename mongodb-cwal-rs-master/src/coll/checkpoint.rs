@@ -0,0 +1,364 @@
+//! Restartable scans over query results.
+//!
+//! A checkpoint records the sort key of the last document a scan returned instead of the
+//! server cursor id, so it stays valid after the cursor is closed, the client exits or the
+//! server restarts. Resuming runs the same query again, restricted to documents sorting after
+//! that watermark.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::checkpoint::Checkpoint;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let saved: Option<bson::Document> = None;
+//! let coll = client.db("shop").collection("orders");
+//! let filter = doc! { "status": "shipped" };
+//!
+//! let mut scan = match saved {
+//!     Some(doc) => {
+//!         let checkpoint = Checkpoint::from_document(doc).unwrap();
+//!         coll.resume_scan(Some(filter), &checkpoint, None).unwrap()
+//!     }
+//!     None => coll.find_resumable(Some(filter), doc! { "created": 1 }, None).unwrap(),
+//! };
+//!
+//! while let Some(order) = scan.next() {
+//!     let order = order.unwrap();
+//!     // Process the order, then persist `scan.checkpoint().into()` now and then.
+//! }
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+use super::Collection;
+use super::options::FindOptions;
+use cursor::Cursor;
+use poison::Recover;
+use topology::TopologyType;
+use Error::{self, ArgumentError, CursorInvalidatedError, CursorNotFoundError,
+            ScanInterruptedError};
+use Result;
+
+use std::{error, fmt};
+
+/// The position of a resumable scan: its sort specification and the sort key values of the
+/// last document returned.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// The sort specification of the scan; always ends with `_id` so the order is total.
+    pub sort: bson::Document,
+    /// The sort key values of the last returned document, or `None` if nothing was returned.
+    pub after: Option<bson::Document>,
+}
+
+impl Checkpoint {
+    /// Decodes a checkpoint saved with `Into<bson::Document>`.
+    pub fn from_document(doc: bson::Document) -> Result<Checkpoint> {
+        let sort = match doc.get("sort") {
+            Some(&Bson::Document(ref sort)) => sort.clone(),
+            _ => {
+                return Err(ArgumentError(
+                    String::from("Checkpoint is missing its sort specification."),
+                ))
+            }
+        };
+
+        let after = match doc.get("after") {
+            Some(&Bson::Document(ref after)) => Some(after.clone()),
+            None | Some(&Bson::Null) => None,
+            Some(_) => {
+                return Err(ArgumentError(
+                    String::from("Checkpoint watermark must be a document."),
+                ))
+            }
+        };
+
+        Ok(Checkpoint {
+            sort: sort,
+            after: after,
+        })
+    }
+
+    // Returns the filter selecting documents sorting strictly after the watermark:
+    // `{ $or: [{ k1: { $gt: v1 } }, { k1: v1, k2: { $gt: v2 } }, ...] }`.
+    fn resume_filter(&self) -> Result<Option<bson::Document>> {
+        let after = match self.after {
+            Some(ref after) => after,
+            None => return Ok(None),
+        };
+
+        let mut clauses = Vec::new();
+        let mut equal = bson::Document::new();
+
+        for (key, direction) in self.sort.iter() {
+            let operator = match *direction {
+                Bson::I32(1) | Bson::I64(1) => "$gt",
+                Bson::I32(-1) | Bson::I64(-1) => "$lt",
+                _ => {
+                    return Err(ArgumentError(format!(
+                        "Resumable scans only support ascending or descending sorts, got {} \
+                         for '{}'.",
+                        direction,
+                        key
+                    )))
+                }
+            };
+
+            let value = after.get(key).cloned().unwrap_or(Bson::Null);
+
+            let mut clause = equal.clone();
+            let mut condition = bson::Document::new();
+            condition.insert(operator, value.clone());
+            clause.insert(key.clone(), condition);
+            clauses.push(Bson::Document(clause));
+
+            equal.insert(key.clone(), value);
+        }
+
+        Ok(Some(doc! { "$or": clauses }))
+    }
+}
+
+impl From<Checkpoint> for bson::Document {
+    fn from(checkpoint: Checkpoint) -> Self {
+        let mut document = doc! { "sort": checkpoint.sort };
+
+        if let Some(after) = checkpoint.after {
+            document.insert("after", after);
+        }
+
+        document
+    }
+}
+
+/// A resumable scan whose cursor was lost after it started returning documents, such as when
+/// the `mongos` holding it went away. Returned inside `Error::ScanInterruptedError`.
+#[derive(Debug)]
+pub struct ScanInterruption {
+    pub namespace: String,
+    /// The filter the scan was started with, without the checkpoint's watermark.
+    pub filter: Option<bson::Document>,
+    /// The options the scan was started with, including its read preference.
+    pub options: FindOptions,
+    /// The position after the last document returned.
+    pub checkpoint: Checkpoint,
+    /// The failure that invalidated the cursor.
+    pub cause: Box<Error>,
+}
+
+impl error::Error for ScanInterruption {
+    fn description(&self) -> &str {
+        "Resumable scan lost its cursor."
+    }
+}
+
+impl fmt::Display for ScanInterruption {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Resumable scan of {} lost its cursor: {}", self.namespace, self.cause)
+    }
+}
+
+/// A cursor that keeps track of its position as a `Checkpoint`.
+///
+/// Projections must keep every sort key, or the watermark cannot be recorded.
+///
+/// On sharded clusters, losing the cursor partway through is reported as a
+/// `ScanInterruptedError` that `Collection::resume_interrupted_scan` continues from.
+#[derive(Debug)]
+pub struct ResumableCursor {
+    cursor: Cursor,
+    checkpoint: Checkpoint,
+    namespace: String,
+    filter: Option<bson::Document>,
+    options: FindOptions,
+    sharded: bool,
+}
+
+impl ResumableCursor {
+    /// Returns the position after the last document returned.
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.checkpoint.clone()
+    }
+}
+
+impl Iterator for ResumableCursor {
+    type Item = Result<bson::Document>;
+
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        let doc = match self.cursor.next() {
+            Some(Ok(doc)) => doc,
+            Some(Err(err)) => return Some(Err(self.interrupted(err))),
+            None => return None,
+        };
+
+        let mut after = bson::Document::new();
+        for key in self.checkpoint.sort.keys() {
+            after.insert(key.clone(), lookup(&doc, key).unwrap_or(Bson::Null));
+        }
+
+        self.checkpoint.after = Some(after);
+        Some(Ok(doc))
+    }
+}
+
+impl ResumableCursor {
+    // Wraps failures that lose the server-side cursor with what is needed to resume the scan.
+    fn interrupted(&self, err: Error) -> Error {
+        match *err.without_context() {
+            CursorInvalidatedError(..) | CursorNotFoundError if self.sharded => {
+                ScanInterruptedError(ScanInterruption {
+                    namespace: self.namespace.clone(),
+                    filter: self.filter.clone(),
+                    options: self.options.clone(),
+                    checkpoint: self.checkpoint.clone(),
+                    cause: Box::new(err),
+                })
+            }
+            _ => err,
+        }
+    }
+}
+
+// Resolves a dotted path within a document.
+fn lookup(doc: &bson::Document, path: &str) -> Option<Bson> {
+    let mut parts = path.splitn(2, '.');
+    let value = doc.get(parts.next()?)?;
+
+    match parts.next() {
+        None => Some(value.clone()),
+        Some(rest) => match *value {
+            Bson::Document(ref inner) => lookup(inner, rest),
+            _ => None,
+        },
+    }
+}
+
+impl Collection {
+    /// Starts a scan over the documents matching `filter` in the given `sort` order, which
+    /// can be stopped and later continued from its `checkpoint`.
+    ///
+    /// `_id` is appended to the sort specification if missing, so ties are broken
+    /// consistently.
+    pub fn find_resumable(
+        &self,
+        filter: Option<bson::Document>,
+        sort: bson::Document,
+        options: Option<FindOptions>,
+    ) -> Result<ResumableCursor> {
+        let mut sort = sort;
+        if !sort.contains_key("_id") {
+            sort.insert("_id", 1);
+        }
+
+        let checkpoint = Checkpoint {
+            sort: sort,
+            after: None,
+        };
+
+        self.resume_scan(filter, &checkpoint, options)
+    }
+
+    /// Continues a scan started with `find_resumable` after its checkpoint. `filter` and
+    /// `options` should match those of the original scan.
+    pub fn resume_scan(
+        &self,
+        filter: Option<bson::Document>,
+        checkpoint: &Checkpoint,
+        options: Option<FindOptions>,
+    ) -> Result<ResumableCursor> {
+        let mut options = options.unwrap_or_default();
+
+        if options.skip.is_some() {
+            return Err(ArgumentError(
+                String::from("Resumable scans cannot skip documents."),
+            ));
+        }
+
+        if options.sort.is_some() {
+            return Err(ArgumentError(String::from(
+                "Resumable scans take their sort specification from the checkpoint.",
+            )));
+        }
+
+        let original_filter = filter.clone();
+        let original_options = options.clone();
+
+        let filter = match (filter, checkpoint.resume_filter()?) {
+            (Some(filter), Some(resume)) => Some(doc! { "$and": [filter, resume] }),
+            (filter, resume) => filter.or(resume),
+        };
+
+        options.sort = Some(checkpoint.sort.clone());
+
+        let sharded = self.db.client.topology.description.read().recover().topology_type ==
+            TopologyType::Sharded;
+
+        Ok(ResumableCursor {
+            cursor: self.find(filter, Some(options))?,
+            checkpoint: checkpoint.clone(),
+            namespace: self.namespace.clone(),
+            filter: original_filter,
+            options: original_options,
+            sharded: sharded,
+        })
+    }
+
+    /// Continues a scan that failed with a `ScanInterruptedError`, selecting a server with the
+    /// scan's read preference again so that another `mongos` can take over.
+    pub fn resume_interrupted_scan(
+        &self,
+        interruption: &ScanInterruption,
+    ) -> Result<ResumableCursor> {
+        if interruption.namespace != self.namespace {
+            return Err(ArgumentError(format!(
+                "Cannot resume a scan of {} on {}.",
+                interruption.namespace,
+                self.namespace
+            )));
+        }
+
+        self.resume_scan(
+            interruption.filter.clone(),
+            &interruption.checkpoint,
+            Some(interruption.options.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resume_filter_follows_sort() {
+        let checkpoint = Checkpoint {
+            sort: doc! { "created": 1, "_id": -1 },
+            after: Some(doc! { "created": 10, "_id": 7 }),
+        };
+
+        let expected = doc! {
+            "$or": [
+                { "created": { "$gt": 10 } },
+                { "created": 10, "_id": { "$lt": 7 } },
+            ],
+        };
+
+        assert_eq!(Some(expected), checkpoint.resume_filter().unwrap());
+
+        let saved = bson::Document::from(checkpoint.clone());
+        assert_eq!(checkpoint, Checkpoint::from_document(saved).unwrap());
+    }
+
+    #[test]
+    fn looks_up_dotted_paths() {
+        let doc = doc! { "a": { "b": { "c": 3 } }, "d": 4 };
+        assert_eq!(Some(Bson::I32(3)), lookup(&doc, "a.b.c"));
+        assert_eq!(Some(Bson::I32(4)), lookup(&doc, "d"));
+        assert_eq!(None, lookup(&doc, "d.e"));
+    }
+}
@@ -0,0 +1,338 @@
+//! Unacknowledged inserts merged into fewer messages.
+//!
+//! An `InsertCoalescer` queues the documents handed to it and sends them from a background
+//! thread as a single unordered, unacknowledged insert, once the oldest of them has waited
+//! `max_delay` or they add up to `max_bytes`. Workloads inserting many small documents with
+//! `w: 0`, such as telemetry, then pay for a message header and a socket write per batch
+//! rather than per document.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("metrics").collection("samples");
+//! let coalescer = coll.insert_coalescer(None).unwrap();
+//!
+//! for i in 0..10_000 {
+//!     coalescer.insert(doc! { "sensor": i % 16, "value": i }).unwrap();
+//! }
+//!
+//! // Sends what is still queued and stops the background thread.
+//! coalescer.close().unwrap();
+//! # }
+//! ```
+use bson;
+
+use super::Collection;
+use super::options::InsertManyOptions;
+use common::WriteConcern;
+use poison::Recover;
+use wire_protocol::operations::ByteLength;
+use Error::{self, ArgumentError};
+use Result;
+
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a queued document waits to be sent, unless configured otherwise.
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_millis(100);
+
+/// How many encoded bytes are queued before they are sent, unless configured otherwise.
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+
+/// Options for an `InsertCoalescer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoalesceOptions {
+    /// The longest a document waits in the queue before it is sent; default 100 ms.
+    pub max_delay: Duration,
+    /// How many encoded bytes are queued before they are sent without waiting for
+    /// `max_delay`; default 1 MiB. Inserts block while this many are queued, until the
+    /// background thread takes them.
+    pub max_bytes: usize,
+}
+
+impl CoalesceOptions {
+    /// Creates a new CoalesceOptions struct with default parameters.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Default for CoalesceOptions {
+    fn default() -> Self {
+        CoalesceOptions {
+            max_delay: DEFAULT_MAX_DELAY,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Queue {
+    documents: Vec<bson::Document>,
+    bytes: usize,
+    // When the oldest queued document was queued.
+    oldest: Option<Instant>,
+    // Whether a batch taken from the queue is being sent.
+    sending: bool,
+    // How many callers of `flush` wait for the queue to be sent.
+    flushing: usize,
+    closed: bool,
+    // The first error a batch was sent with since errors were last reported.
+    error: Option<Error>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+}
+
+/// Queues documents to be inserted unacknowledged, in batches sent by a background thread.
+///
+/// Dropping the coalescer sends the documents still queued, without reporting errors.
+#[derive(Debug)]
+pub struct InsertCoalescer {
+    shared: Arc<Shared>,
+    max_bytes: usize,
+    flusher: Option<JoinHandle<()>>,
+}
+
+impl InsertCoalescer {
+    // Starts the flusher thread, which hands each batch to `send`.
+    fn start<F>(options: CoalesceOptions, send: F) -> Result<InsertCoalescer>
+    where
+        F: FnMut(Vec<bson::Document>) -> Result<()> + Send + 'static,
+    {
+        if options.max_bytes == 0 {
+            return Err(ArgumentError(
+                String::from("The coalescer's max_bytes must be greater than zero."),
+            ));
+        }
+
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                documents: Vec::new(),
+                bytes: 0,
+                oldest: None,
+                sending: false,
+                flushing: 0,
+                closed: false,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let max_bytes = options.max_bytes;
+        let flusher_shared = shared.clone();
+        let flusher = thread::spawn(move || flush_queue(&flusher_shared, &options, send));
+
+        Ok(InsertCoalescer {
+            shared: shared,
+            max_bytes: max_bytes,
+            flusher: Some(flusher),
+        })
+    }
+
+    /// Queues `doc` to be inserted.
+    ///
+    /// Fails with the error a previous batch failed to be sent with, if any, in which case
+    /// `doc` is not queued.
+    pub fn insert(&self, doc: bson::Document) -> Result<()> {
+        let bytes = doc.byte_length()? as usize;
+
+        let mut queue = self.shared.queue.lock().recover();
+        while queue.bytes >= self.max_bytes {
+            queue = self.shared.changed.wait(queue).recover();
+        }
+
+        if let Some(err) = queue.error.take() {
+            return Err(err);
+        }
+
+        if queue.oldest.is_none() {
+            queue.oldest = Some(Instant::now());
+        }
+        queue.documents.push(doc);
+        queue.bytes += bytes;
+
+        // The flusher waits for a first document, then for the delay or a full batch.
+        if queue.documents.len() == 1 || queue.bytes >= self.max_bytes {
+            self.shared.changed.notify_all();
+        }
+
+        Ok(())
+    }
+
+    /// Returns how many documents are queued and not yet sent.
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().recover().documents.len()
+    }
+
+    /// Sends the queued documents without waiting for `max_delay`, returning once they have
+    /// been written, along with the first error a batch was sent with since the last report.
+    pub fn flush(&self) -> Result<()> {
+        let mut queue = self.shared.queue.lock().recover();
+        queue.flushing += 1;
+        self.shared.changed.notify_all();
+
+        while !queue.documents.is_empty() || queue.sending {
+            queue = self.shared.changed.wait(queue).recover();
+        }
+
+        queue.flushing -= 1;
+        match queue.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends the queued documents and stops the background thread, returning the first error
+    /// a batch was sent with since the last report.
+    pub fn close(mut self) -> Result<()> {
+        self.stop();
+        match self.shared.queue.lock().recover().error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(flusher) = self.flusher.take() {
+            self.shared.queue.lock().recover().closed = true;
+            self.shared.changed.notify_all();
+            let _ = flusher.join();
+        }
+    }
+}
+
+impl Drop for InsertCoalescer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Sends batches taken from the queue until the coalescer is closed and the queue is empty.
+fn flush_queue<F>(shared: &Shared, options: &CoalesceOptions, mut send: F)
+where
+    F: FnMut(Vec<bson::Document>) -> Result<()>,
+{
+    let mut queue = shared.queue.lock().recover();
+
+    loop {
+        let oldest = match queue.oldest {
+            Some(oldest) => oldest,
+            None if queue.closed => return,
+            None => {
+                queue = shared.changed.wait(queue).recover();
+                continue;
+            }
+        };
+
+        let now = Instant::now();
+        let due = oldest + options.max_delay;
+        if now < due && !queue.closed && queue.flushing == 0 &&
+            queue.bytes < options.max_bytes
+        {
+            queue = shared.changed.wait_timeout(queue, due - now).recover().0;
+            continue;
+        }
+
+        let batch = mem::replace(&mut queue.documents, Vec::new());
+        queue.bytes = 0;
+        queue.oldest = None;
+        queue.sending = true;
+        drop(queue);
+        shared.changed.notify_all();
+
+        let result = send(batch);
+
+        queue = shared.queue.lock().recover();
+        queue.sending = false;
+        if let Err(err) = result {
+            queue.error = queue.error.take().or(Some(err));
+        }
+        shared.changed.notify_all();
+    }
+}
+
+impl Collection {
+    /// Returns a coalescer inserting the documents queued with it into this collection, in
+    /// unordered batches with a write concern of `w: 0`.
+    pub fn insert_coalescer(&self, options: Option<CoalesceOptions>) -> Result<InsertCoalescer> {
+        let coll = Collection::new(
+            self.db.clone(),
+            &self.name(),
+            false,
+            Some(self.read_preference.clone()),
+            None,
+        );
+
+        InsertCoalescer::start(options.unwrap_or_default(), move |docs| {
+            let options = InsertManyOptions {
+                ordered: Some(false),
+                write_concern: Some(WriteConcern {
+                    w: 0,
+                    ..WriteConcern::new()
+                }),
+                ..InsertManyOptions::new()
+            };
+
+            coll.insert_many(docs, Some(options)).map(|_| ())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use std::sync::mpsc;
+
+    #[test]
+    fn sends_full_batches_and_the_rest_on_close() {
+        let (sender, batches) = mpsc::channel();
+        let options = CoalesceOptions {
+            max_delay: Duration::from_secs(3600),
+            max_bytes: 3 * doc! { "i": 0 }.byte_length().unwrap() as usize,
+        };
+        let coalescer = InsertCoalescer::start(options, move |batch| {
+            sender.send(batch.len()).unwrap();
+            Ok(())
+        }).unwrap();
+
+        for i in 0..7 {
+            coalescer.insert(doc! { "i": i }).unwrap();
+        }
+        coalescer.close().unwrap();
+
+        assert_eq!(vec![3, 3, 1], batches.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sends_after_the_delay_and_reports_errors() {
+        let (sender, batches) = mpsc::channel();
+        let options = CoalesceOptions {
+            max_delay: Duration::from_millis(10),
+            ..CoalesceOptions::new()
+        };
+        let coalescer = InsertCoalescer::start(options, move |batch| {
+            sender.send(batch.len()).unwrap();
+            Err(Error::OperationError(String::from("unreachable")))
+        }).unwrap();
+
+        coalescer.insert(doc! { "i": 0 }).unwrap();
+        assert_eq!(Ok(1), batches.recv_timeout(Duration::from_secs(5)));
+
+        assert!(coalescer.flush().is_err());
+        assert!(coalescer.flush().is_ok());
+        assert_eq!(0, coalescer.queued());
+    }
+}
@@ -2,7 +2,7 @@
 use bson::{self, Bson};
 use super::options::WriteModel;
 use common::WriteConcern;
-use {Error, Result};
+use {Error, ErrorCode, Result};
 use std::{error, fmt};
 
 /// The error type for Write-related MongoDB operations.
@@ -17,15 +17,26 @@ pub struct WriteException {
 #[derive(Debug, Clone, PartialEq)]
 pub struct WriteConcernError {
     pub code: i32,
+    /// The name of the error code, e.g. `WriteConcernFailed`.
+    pub code_name: Option<String>,
+    /// The write concern the write was sent with.
     pub details: WriteConcern,
     pub message: String,
+    /// Additional information from the server, such as `{ wtimeout: true }`.
+    pub err_info: Option<bson::Document>,
 }
 
 /// The error struct for a write-related error.
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct WriteError {
+    /// The position of the failed write in the command, 0 for single writes.
+    pub index: i32,
     pub code: i32,
+    /// The name of the error code, e.g. `DuplicateKey`.
+    pub code_name: Option<String>,
     pub message: String,
+    /// Additional information from the server, such as why a document failed validation.
+    pub details: Option<bson::Document>,
 }
 
 /// The error struct for Bulk-Write related MongoDB operations.
@@ -44,10 +55,32 @@ pub struct BulkWriteException {
 pub struct BulkWriteError {
     pub index: i32,
     pub code: i32,
+    /// The name of the error code, e.g. `DuplicateKey`.
+    pub code_name: Option<String>,
     pub message: String,
+    /// Additional information from the server, such as why a document failed validation.
+    pub details: Option<bson::Document>,
     pub request: Option<WriteModel>,
 }
 
+/// Everything an unordered bulk write or insert reported failing, with the counts of the
+/// writes that succeeded regardless.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkWriteFailure {
+    /// The failed writes, in order of their position in the original requests.
+    pub write_errors: Vec<BulkWriteError>,
+    pub write_concern_error: Option<WriteConcernError>,
+    /// Requests whose command failed before the server could report on them.
+    pub unprocessed_requests: Vec<WriteModel>,
+    pub inserted_count: i32,
+    pub matched_count: i32,
+    pub modified_count: i32,
+    pub deleted_count: i32,
+    pub upserted_count: i32,
+    /// The errors of the writes and of any failed commands, as in `BulkWriteException`.
+    pub message: String,
+}
+
 impl error::Error for WriteException {
     fn description(&self) -> &str {
         &self.message
@@ -60,6 +93,8 @@ impl error::Error for BulkWriteException {
     }
 }
 
+impl error::Error for BulkWriteFailure {}
+
 impl fmt::Display for WriteException {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str("WriteException:\n")?;
@@ -119,6 +154,27 @@ impl fmt::Display for BulkWriteError {
     }
 }
 
+impl fmt::Display for BulkWriteFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "BulkWriteFailure: {} write errors, {} unprocessed requests",
+            self.write_errors.len(),
+            self.unprocessed_requests.len()
+        )?;
+
+        if let Some(ref error) = self.write_concern_error {
+            write!(fmt, "\nWrite concern error (code {}): {}", error.code, error.message)?;
+        }
+
+        for error in &self.write_errors {
+            write!(fmt, "\n{}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl WriteException {
     /// Returns a new WriteException containing the given errors.
     pub fn new(wc_err: Option<WriteConcernError>, w_err: Option<WriteError>) -> WriteException {
@@ -141,7 +197,13 @@ impl WriteException {
     /// last write error to emulate the behavior of continue_on_error.
     pub fn with_bulk_exception(bulk_exception: BulkWriteException) -> WriteException {
         let mut write_errors = bulk_exception.write_errors;
-        let write_error = write_errors.pop().map(|e| WriteError::new(e.code, e.message));
+        let write_error = write_errors.pop().map(|e| WriteError {
+            index: e.index,
+            code: e.code,
+            code_name: e.code_name,
+            message: e.message,
+            details: e.details,
+        });
 
         WriteException::new(bulk_exception.write_concern_error, write_error)
     }
@@ -172,8 +234,10 @@ impl WriteConcernError {
     pub fn new<T: ToString>(code: i32, details: WriteConcern, message: T) -> WriteConcernError {
         WriteConcernError {
             code: code,
+            code_name: None,
             details: details,
             message: message.to_string(),
+            err_info: None,
         }
     }
 
@@ -181,7 +245,10 @@ impl WriteConcernError {
     pub fn parse(error: bson::Document, write_concern: WriteConcern) -> Result<WriteConcernError> {
         match (error.get("code"), error.get("errmsg")) {
             (Some(&Bson::I32(code)), Some(&Bson::String(ref message))) => {
-                Ok(WriteConcernError::new(code, write_concern, message))
+                let mut wc_err = WriteConcernError::new(code, write_concern, message);
+                wc_err.code_name = error.get_str("codeName").ok().map(String::from);
+                wc_err.err_info = error.get_document("errInfo").ok().cloned();
+                Ok(wc_err)
             }
             _ => Err(Error::ResponseError(format!(
                 "WriteConcernError document is invalid: {:?}",
@@ -189,14 +256,30 @@ impl WriteConcernError {
             )))
         }
     }
+
+    /// Returns whether the write was applied but not replicated within `w_timeout`.
+    pub fn timed_out(&self) -> bool {
+        match self.err_info {
+            Some(ref info) => info.get_bool("wtimeout").unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Returns the error code, if it is a known one.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        ErrorCode::from_i32(self.code)
+    }
 }
 
 impl WriteError {
     /// Returns a new WriteError containing the provided error information.
     pub fn new<T: ToString>(code: i32, message: T) -> WriteError {
         WriteError {
+            index: 0,
             code: code,
+            code_name: None,
             message: message.to_string(),
+            details: None,
         }
     }
 
@@ -204,13 +287,32 @@ impl WriteError {
     pub fn parse(error: bson::Document) -> Result<WriteError> {
         if let Some(&Bson::I32(code)) = error.get("code") {
             if let Some(&Bson::String(ref message)) = error.get("errmsg") {
-                return Ok(WriteError::new(code, message));
+                let mut w_err = WriteError::new(code, message);
+                w_err.index = error.get_i32("index").unwrap_or(0);
+                w_err.code_name = error.get_str("codeName").ok().map(String::from);
+                w_err.details = error.get_document("errInfo").ok().cloned();
+                return Ok(w_err);
             }
         }
         Err(Error::ResponseError(
             format!("WriteError document is invalid: {:?}", error),
         ))
     }
+
+    /// Returns the error code, if it is a known one.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        ErrorCode::from_i32(self.code)
+    }
+
+    /// Returns whether the write violated a unique index.
+    pub fn is_duplicate_key(&self) -> bool {
+        self.code == ErrorCode::DuplicateKey as i32
+    }
+
+    /// Returns whether the document failed the collection's validation rules.
+    pub fn is_validation_failure(&self) -> bool {
+        self.code == ErrorCode::DocumentValidationFailure as i32
+    }
 }
 
 impl BulkWriteError {
@@ -224,7 +326,9 @@ impl BulkWriteError {
         BulkWriteError {
             index: index,
             code: code,
+            code_name: None,
             message: message.to_string(),
+            details: None,
             request: request,
         }
     }
@@ -235,13 +339,31 @@ impl BulkWriteError {
             (Some(&Bson::I32(index)),
              Some(&Bson::I32(code)),
              Some(&Bson::String(ref message))) => {
-                Ok(BulkWriteError::new(index, code, message, None))
+                let mut bulk_err = BulkWriteError::new(index, code, message, None);
+                bulk_err.code_name = error.get_str("codeName").ok().map(String::from);
+                bulk_err.details = error.get_document("errInfo").ok().cloned();
+                Ok(bulk_err)
             }
             _ => Err(Error::ResponseError(
                 format!("WriteError document is invalid: {:?}", error),
             ))
         }
     }
+
+    /// Returns the error code, if it is a known one.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        ErrorCode::from_i32(self.code)
+    }
+
+    /// Returns whether the write violated a unique index.
+    pub fn is_duplicate_key(&self) -> bool {
+        self.code == ErrorCode::DuplicateKey as i32
+    }
+
+    /// Returns whether the document failed the collection's validation rules.
+    pub fn is_validation_failure(&self) -> bool {
+        self.code == ErrorCode::DocumentValidationFailure as i32
+    }
 }
 
 impl BulkWriteException {
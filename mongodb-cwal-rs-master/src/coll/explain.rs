@@ -0,0 +1,347 @@
+//! Query plans returned by the `explain` command.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::explain::Verbosity;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//!
+//! let explain = coll.explain_find(Some(doc! { "customer": 42 }), None, Verbosity::ExecutionStats)
+//!     .unwrap();
+//!
+//! assert!(explain.winning_plan().unwrap().uses_stage("IXSCAN"));
+//! assert!(explain.execution_stats.unwrap().total_docs_examined < 100);
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+use super::Collection;
+use super::options::{AggregateOptions, FindOptions};
+use common::{merge_options, ReadPreference};
+use db::ThreadedDatabase;
+use CommandType;
+use Error::{DecoderError, ResponseError};
+use Result;
+
+/// How much the server reports about a query plan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Verbosity {
+    /// Only the plan selected by the query optimizer.
+    QueryPlanner,
+    /// The selected plan, after running it to completion.
+    ExecutionStats,
+    /// Also the partial execution of the rejected candidate plans.
+    AllPlansExecution,
+}
+
+impl Verbosity {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            Verbosity::QueryPlanner => "queryPlanner",
+            Verbosity::ExecutionStats => "executionStats",
+            Verbosity::AllPlansExecution => "allPlansExecution",
+        }
+    }
+}
+
+/// A stage of a query plan, such as `COLLSCAN`, `IXSCAN` or `FETCH`.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct PlanStage {
+    pub stage: String,
+    #[serde(rename="inputStage", default)]
+    pub input_stage: Option<Box<PlanStage>>,
+    #[serde(rename="inputStages", default)]
+    pub input_stages: Vec<PlanStage>,
+    #[serde(rename="indexName", default)]
+    pub index_name: Option<String>,
+    #[serde(rename="keyPattern", default)]
+    pub key_pattern: Option<bson::Document>,
+    #[serde(default)]
+    pub direction: Option<String>,
+    /// The documents returned by this stage; reported with execution statistics only.
+    #[serde(rename="nReturned", default)]
+    pub n_returned: Option<i64>,
+    #[serde(rename="docsExamined", default)]
+    pub docs_examined: Option<i64>,
+    #[serde(rename="keysExamined", default)]
+    pub keys_examined: Option<i64>,
+}
+
+impl PlanStage {
+    /// Returns whether this stage or any stage feeding it is named `stage`.
+    pub fn uses_stage(&self, stage: &str) -> bool {
+        self.stage == stage ||
+            self.input_stage.iter().any(|input| input.uses_stage(stage)) ||
+            self.input_stages.iter().any(|input| input.uses_stage(stage))
+    }
+
+    /// Returns the names of the indexes scanned by this stage and the stages feeding it.
+    pub fn index_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.index_name.iter().cloned().collect();
+
+        for input in self.input_stage.iter().map(|input| &**input).chain(self.input_stages.iter()) {
+            names.extend(input.index_names());
+        }
+
+        names
+    }
+}
+
+/// The plans considered by the query optimizer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryPlanner {
+    pub namespace: Option<String>,
+    pub parsed_query: Option<bson::Document>,
+    pub index_filter_set: bool,
+    pub winning_plan: PlanStage,
+    pub rejected_plans: Vec<PlanStage>,
+}
+
+/// Statistics from running the winning plan.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct ExecutionStats {
+    #[serde(rename="executionSuccess", default)]
+    pub execution_success: bool,
+    #[serde(rename="nReturned", default)]
+    pub n_returned: i64,
+    #[serde(rename="executionTimeMillis", default)]
+    pub execution_time_millis: i64,
+    #[serde(rename="totalKeysExamined", default)]
+    pub total_keys_examined: i64,
+    #[serde(rename="totalDocsExamined", default)]
+    pub total_docs_examined: i64,
+    #[serde(rename="executionStages", default)]
+    pub execution_stages: Option<PlanStage>,
+}
+
+/// The result of an `explain` command.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explain {
+    pub query_planner: Option<QueryPlanner>,
+    /// Reported for the `ExecutionStats` and `AllPlansExecution` verbosities.
+    pub execution_stats: Option<ExecutionStats>,
+    /// The full server response, for fields without a typed counterpart.
+    pub raw: bson::Document,
+}
+
+impl Explain {
+    /// Decodes an `explain` response.
+    ///
+    /// Aggregations that start by reading a collection report its plan within their first
+    /// `$cursor` stage; that plan is used when there is none at the top level.
+    pub fn from_document(doc: bson::Document) -> Result<Explain> {
+        let (query_planner, execution_stats) = {
+            let source = match (doc.get("queryPlanner"), first_cursor_stage(&doc)) {
+                (None, Some(cursor)) => cursor,
+                _ => &doc,
+            };
+
+            let query_planner = match source.get("queryPlanner") {
+                Some(&Bson::Document(ref planner)) => Some(decode_query_planner(planner)?),
+                _ => None,
+            };
+
+            let execution_stats = match source.get("executionStats") {
+                Some(stats) => Some(decode(stats.clone())?),
+                None => None,
+            };
+
+            (query_planner, execution_stats)
+        };
+
+        Ok(Explain {
+            query_planner: query_planner,
+            execution_stats: execution_stats,
+            raw: doc,
+        })
+    }
+
+    /// Returns the plan selected by the query optimizer.
+    pub fn winning_plan(&self) -> Option<&PlanStage> {
+        self.query_planner.as_ref().map(|planner| &planner.winning_plan)
+    }
+}
+
+fn first_cursor_stage(doc: &bson::Document) -> Option<&bson::Document> {
+    match doc.get("stages") {
+        Some(&Bson::Array(ref stages)) => match stages.first() {
+            Some(&Bson::Document(ref stage)) => match stage.get("$cursor") {
+                Some(&Bson::Document(ref cursor)) => Some(cursor),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn decode_query_planner(planner: &bson::Document) -> Result<QueryPlanner> {
+    let winning_plan = match planner.get("winningPlan") {
+        Some(plan) => decode_plan(plan)?,
+        None => {
+            return Err(ResponseError(
+                String::from("The query planner did not report a winning plan."),
+            ));
+        }
+    };
+
+    let rejected_plans = match planner.get("rejectedPlans") {
+        Some(&Bson::Array(ref plans)) => plans.iter().map(decode_plan).collect::<Result<_>>()?,
+        _ => Vec::new(),
+    };
+
+    Ok(QueryPlanner {
+        namespace: planner.get_str("namespace").ok().map(String::from),
+        parsed_query: planner.get_document("parsedQuery").ok().cloned(),
+        index_filter_set: planner.get_bool("indexFilterSet").unwrap_or(false),
+        winning_plan: winning_plan,
+        rejected_plans: rejected_plans,
+    })
+}
+
+// Plans from the slot-based execution engine nest the classic plan tree under `queryPlan`.
+fn decode_plan(plan: &Bson) -> Result<PlanStage> {
+    match *plan {
+        Bson::Document(ref doc) if !doc.contains_key("stage") => match doc.get("queryPlan") {
+            Some(query_plan) => decode(query_plan.clone()),
+            None => decode(plan.clone()),
+        },
+        _ => decode(plan.clone()),
+    }
+}
+
+fn decode<T: ::serde::de::DeserializeOwned>(value: Bson) -> Result<T> {
+    bson::from_bson(value).map_err(DecoderError)
+}
+
+impl Collection {
+    /// Explains how the server would run `find` with the given filter and options.
+    pub fn explain_find(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let options = options.unwrap_or_default();
+        let read_preference = options.read_preference.clone();
+        self.check_collation(options.collation.as_ref())?;
+
+        let spec = doc! {
+            "find": self.name(),
+            "filter": filter.unwrap_or_default(),
+        };
+
+        self.explain(merge_options(spec, options), verbosity, read_preference)
+    }
+
+    /// Explains how the server would run the aggregation pipeline.
+    pub fn explain_aggregate(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: Option<AggregateOptions>,
+        verbosity: Verbosity,
+    ) -> Result<Explain> {
+        let options = options.unwrap_or_default();
+        let read_preference = options.read_preference.clone();
+        self.check_collation(options.collation.as_ref())?;
+
+        let pipeline: Vec<_> = pipeline.into_iter().map(Bson::Document).collect();
+        let spec = doc! {
+            "aggregate": self.name(),
+            "pipeline": pipeline,
+        };
+
+        self.explain(merge_options(spec, options), verbosity, read_preference)
+    }
+
+    fn explain(
+        &self,
+        command: bson::Document,
+        verbosity: Verbosity,
+        read_preference: Option<ReadPreference>,
+    ) -> Result<Explain> {
+        let spec = doc! {
+            "explain": command,
+            "verbosity": verbosity.to_str(),
+        };
+
+        let read_preference = read_preference.or_else(|| Some(self.read_preference.clone()));
+        let result = self.db.command(spec, CommandType::Explain, read_preference)?;
+        Explain::from_document(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_find_explain() {
+        let explain = Explain::from_document(doc! {
+            "queryPlanner": {
+                "namespace": "shop.orders",
+                "indexFilterSet": false,
+                "winningPlan": {
+                    "stage": "FETCH",
+                    "inputStage": {
+                        "stage": "IXSCAN",
+                        "indexName": "customer_1",
+                        "keyPattern": { "customer": 1 },
+                        "direction": "forward",
+                    },
+                },
+                "rejectedPlans": [{ "stage": "COLLSCAN", "direction": "forward" }],
+            },
+            "executionStats": {
+                "executionSuccess": true,
+                "nReturned": 3,
+                "executionTimeMillis": 0,
+                "totalKeysExamined": 3,
+                "totalDocsExamined": 3,
+            },
+            "ok": 1.0,
+        }).unwrap();
+
+        let plan = explain.winning_plan().unwrap();
+        assert!(plan.uses_stage("IXSCAN"));
+        assert!(!plan.uses_stage("COLLSCAN"));
+        assert_eq!(vec![String::from("customer_1")], plan.index_names());
+
+        let planner = explain.query_planner.as_ref().unwrap();
+        assert_eq!(Some(String::from("shop.orders")), planner.namespace);
+        assert_eq!("COLLSCAN", planner.rejected_plans[0].stage);
+
+        let stats = explain.execution_stats.unwrap();
+        assert_eq!(3, stats.n_returned);
+        assert_eq!(3, stats.total_docs_examined);
+    }
+
+    #[test]
+    fn decodes_aggregate_and_slot_based_plans() {
+        let explain = Explain::from_document(doc! {
+            "stages": [
+                {
+                    "$cursor": {
+                        "queryPlanner": {
+                            "winningPlan": {
+                                "queryPlan": { "stage": "COLLSCAN" },
+                                "slotBasedPlan": { "slots": "" },
+                            },
+                        },
+                    },
+                },
+                { "$group": { "_id": "$customer" } },
+            ],
+            "ok": 1.0,
+        }).unwrap();
+
+        assert_eq!("COLLSCAN", explain.winning_plan().unwrap().stage);
+        assert!(explain.execution_stats.is_none());
+    }
+}
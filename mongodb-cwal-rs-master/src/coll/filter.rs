@@ -0,0 +1,503 @@
+//! Builder for query filters.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::filter::Filter;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("customers");
+//!
+//! let filter = Filter::field("age").gt(21)
+//!     .and(Filter::field("status").in_(vec!["a", "b"]));
+//!
+//! let cursor = coll.find(Some(filter.build()), None).unwrap();
+//! # }
+//! ```
+//!
+//! Full-text queries need a text index on the collection:
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::filter::TextSearch;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("products");
+//!
+//! let search = TextSearch::new("coffee -decaf").language("en");
+//! for product in coll.text_search(search, None, None).unwrap() {
+//!     let product = product.unwrap();
+//!     let score = product.get_f64("score").unwrap();
+//!     println!("{} scored {}", product.get_str("name").unwrap(), score);
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use super::geo::{Geometry, Near, Shape};
+
+/// A query predicate.
+///
+/// Filters are built from `Field` conditions and combined with `and`, `or` and `nor`;
+/// predicates without a dedicated method can be wrapped with `Filter::from`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    doc: bson::Document,
+}
+
+impl Filter {
+    /// Creates a filter matching every document.
+    pub fn new() -> Filter {
+        Default::default()
+    }
+
+    /// Starts a condition on the field at `name`, which may be a dotted path.
+    pub fn field(name: &str) -> Field {
+        Field {
+            name: String::from(name),
+            negated: false,
+        }
+    }
+
+    /// Matches documents for which the aggregation expression evaluates to true.
+    pub fn expr<T: Into<Bson>>(expression: T) -> Filter {
+        Filter::operator("$expr", expression.into())
+    }
+
+    /// Matches documents matching every filter.
+    pub fn all(filters: Vec<Filter>) -> Filter {
+        Filter::logical("$and", filters)
+    }
+
+    /// Matches documents matching at least one filter.
+    pub fn any(filters: Vec<Filter>) -> Filter {
+        Filter::logical("$or", filters)
+    }
+
+    /// Matches documents matching none of the filters.
+    pub fn none(filters: Vec<Filter>) -> Filter {
+        Filter::logical("$nor", filters)
+    }
+
+    /// Matches documents matching both this filter and `other`.
+    pub fn and(self, other: Filter) -> Filter {
+        self.combine("$and", other)
+    }
+
+    /// Matches documents matching either this filter or `other`.
+    pub fn or(self, other: Filter) -> Filter {
+        self.combine("$or", other)
+    }
+
+    /// Matches documents matching neither this filter nor `other`.
+    pub fn nor(self, other: Filter) -> Filter {
+        Filter::none(vec![self, other])
+    }
+
+    /// Matches documents whose text index matches `search`, with the default options.
+    pub fn text(search: &str) -> Filter {
+        Filter::from(TextSearch::new(search))
+    }
+
+    /// Returns the filter document.
+    pub fn build(self) -> bson::Document {
+        self.doc
+    }
+
+    fn operator(name: &str, value: Bson) -> Filter {
+        let mut doc = bson::Document::new();
+        doc.insert(name, value);
+        Filter { doc: doc }
+    }
+
+    fn logical(name: &str, filters: Vec<Filter>) -> Filter {
+        let clauses = filters.into_iter().map(|filter| Bson::Document(filter.doc)).collect();
+        Filter::operator(name, Bson::Array(clauses))
+    }
+
+    // Appends to an existing clause list of the same operator, so chained calls stay flat.
+    fn combine(self, name: &str, other: Filter) -> Filter {
+        if self.doc.len() == 1 {
+            if let Some(Bson::Array(clauses)) = self.doc.get(name) {
+                let mut clauses = clauses.clone();
+                clauses.push(Bson::Document(other.doc));
+                return Filter::operator(name, Bson::Array(clauses));
+            }
+        }
+
+        Filter::logical(name, vec![self, other])
+    }
+}
+
+impl From<bson::Document> for Filter {
+    fn from(doc: bson::Document) -> Self {
+        Filter { doc: doc }
+    }
+}
+
+impl From<Filter> for bson::Document {
+    fn from(filter: Filter) -> Self {
+        filter.doc
+    }
+}
+
+/// A field that a condition is being built for; each method completes the condition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    name: String,
+    negated: bool,
+}
+
+impl Field {
+    /// Inverts the condition that follows, e.g. `Filter::field("age").not().gt(21)`.
+    ///
+    /// Like `$not`, this also matches documents without the field.
+    pub fn not(mut self) -> Field {
+        self.negated = !self.negated;
+        self
+    }
+
+    /// Matches values equal to `value`.
+    pub fn eq<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$eq", value.into())
+    }
+
+    /// Matches values not equal to `value`.
+    pub fn ne<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$ne", value.into())
+    }
+
+    /// Matches values greater than `value`.
+    pub fn gt<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$gt", value.into())
+    }
+
+    /// Matches values greater than or equal to `value`.
+    pub fn gte<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$gte", value.into())
+    }
+
+    /// Matches values less than `value`.
+    pub fn lt<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$lt", value.into())
+    }
+
+    /// Matches values less than or equal to `value`.
+    pub fn lte<T: Into<Bson>>(self, value: T) -> Filter {
+        self.operator("$lte", value.into())
+    }
+
+    /// Matches values from `start` up to but excluding `end`, such as the datetimes of a
+    /// period given as two `DateTime<Utc>`s.
+    pub fn between<T: Into<Bson>>(self, start: T, end: T) -> Filter {
+        let mut condition = bson::Document::new();
+        condition.insert("$gte", start.into());
+        condition.insert("$lt", end.into());
+        self.condition(condition)
+    }
+
+    /// Matches datetimes on the UTC day `date`.
+    pub fn on_day(self, date: NaiveDate) -> Filter {
+        let start = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap());
+        let end = match date.succ_opt() {
+            Some(next) => Utc.from_utc_datetime(&next.and_hms_opt(0, 0, 0).unwrap()),
+            None => return self.gte(start),
+        };
+        self.between(start, end)
+    }
+
+    /// Matches values equal to any of `values`.
+    ///
+    /// Named with a trailing underscore since `in` is a keyword.
+    pub fn in_<I, T>(self, values: I) -> Filter
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$in", array(values))
+    }
+
+    /// Matches values equal to none of `values`, or a missing field.
+    pub fn nin<I, T>(self, values: I) -> Filter
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$nin", array(values))
+    }
+
+    /// Matches documents that have (or, with `false`, lack) the field.
+    pub fn exists(self, exists: bool) -> Filter {
+        self.operator("$exists", Bson::Boolean(exists))
+    }
+
+    /// Matches values of the given BSON type, by alias such as `"string"` or number.
+    ///
+    /// Named with a trailing underscore since `type` is a keyword.
+    pub fn type_<T: Into<Bson>>(self, bson_type: T) -> Filter {
+        self.operator("$type", bson_type.into())
+    }
+
+    /// Matches strings matching the regular expression `pattern` with the given options,
+    /// e.g. `"i"` for case-insensitive matching.
+    pub fn regex(self, pattern: &str, options: &str) -> Filter {
+        self.operator(
+            "$regex",
+            Bson::RegExp(String::from(pattern), String::from(options)),
+        )
+    }
+
+    /// Matches arrays containing every one of `values`.
+    pub fn all<I, T>(self, values: I) -> Filter
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$all", array(values))
+    }
+
+    /// Matches arrays with at least one element matching `filter`.
+    pub fn elem_match(self, filter: Filter) -> Filter {
+        self.operator("$elemMatch", Bson::Document(filter.doc))
+    }
+
+    /// Matches arrays with exactly `size` elements.
+    pub fn size(self, size: i64) -> Filter {
+        self.operator("$size", Bson::I64(size))
+    }
+
+    /// Matches locations near the origin of `near`, nearest first, measuring distances on a
+    /// plane for legacy coordinate pairs.
+    pub fn near(self, near: Near) -> Filter {
+        self.condition(near.to_condition("$near"))
+    }
+
+    /// Matches locations near the origin of `near`, nearest first, measuring distances on a
+    /// sphere.
+    pub fn near_sphere(self, near: Near) -> Filter {
+        self.condition(near.to_condition("$nearSphere"))
+    }
+
+    /// Matches locations within the legacy `shape`.
+    pub fn geo_within(self, shape: Shape) -> Filter {
+        self.operator("$geoWithin", shape.to_bson())
+    }
+
+    /// Matches GeoJSON geometries lying entirely within the polygon `geometry`.
+    pub fn geo_within_geometry(self, geometry: Geometry) -> Filter {
+        self.operator("$geoWithin", geometry_operand(geometry))
+    }
+
+    /// Matches GeoJSON geometries that intersect `geometry`.
+    pub fn geo_intersects(self, geometry: Geometry) -> Filter {
+        self.operator("$geoIntersects", geometry_operand(geometry))
+    }
+
+    fn operator(self, name: &str, value: Bson) -> Filter {
+        let mut condition = bson::Document::new();
+        condition.insert(name, value);
+        self.condition(condition)
+    }
+
+    fn condition(self, condition: bson::Document) -> Filter {
+        let condition = if self.negated {
+            let mut negated = bson::Document::new();
+            negated.insert("$not", condition);
+            negated
+        } else {
+            condition
+        };
+
+        let mut doc = bson::Document::new();
+        doc.insert(self.name, condition);
+        Filter { doc: doc }
+    }
+}
+
+/// A `$text` query over the text index of a collection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextSearch {
+    search: String,
+    language: Option<String>,
+    case_sensitive: Option<bool>,
+    diacritic_sensitive: Option<bool>,
+}
+
+impl TextSearch {
+    /// Searches for the words of `search`; quoted phrases must match as a whole and words
+    /// prefixed with `-` must not match.
+    pub fn new(search: &str) -> TextSearch {
+        TextSearch {
+            search: String::from(search),
+            language: None,
+            case_sensitive: None,
+            diacritic_sensitive: None,
+        }
+    }
+
+    /// Sets the language whose stop words and stemming rules apply, instead of the index
+    /// default.
+    pub fn language(mut self, language: &str) -> TextSearch {
+        self.language = Some(String::from(language));
+        self
+    }
+
+    /// Tells whether upper and lower case letters differ.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> TextSearch {
+        self.case_sensitive = Some(case_sensitive);
+        self
+    }
+
+    /// Tells whether letters with diacritical marks, such as `é`, differ from those without.
+    pub fn diacritic_sensitive(mut self, diacritic_sensitive: bool) -> TextSearch {
+        self.diacritic_sensitive = Some(diacritic_sensitive);
+        self
+    }
+}
+
+impl From<TextSearch> for Filter {
+    fn from(search: TextSearch) -> Self {
+        let mut text = bson::Document::new();
+        text.insert("$search", search.search);
+
+        if let Some(language) = search.language {
+            text.insert("$language", language);
+        }
+
+        if let Some(case_sensitive) = search.case_sensitive {
+            text.insert("$caseSensitive", case_sensitive);
+        }
+
+        if let Some(diacritic_sensitive) = search.diacritic_sensitive {
+            text.insert("$diacriticSensitive", diacritic_sensitive);
+        }
+
+        Filter::operator("$text", Bson::Document(text))
+    }
+}
+
+/// Returns `{ "$meta": "textScore" }`, which projects or sorts by the relevance of a document
+/// to the text search of the query, e.g. `doc! { "score": text_score() }`.
+pub fn text_score() -> bson::Document {
+    let mut meta = bson::Document::new();
+    meta.insert("$meta", "textScore");
+    meta
+}
+
+fn geometry_operand(geometry: Geometry) -> Bson {
+    let mut operand = bson::Document::new();
+    operand.insert("$geometry", bson::Document::from(geometry));
+    Bson::Document(operand)
+}
+
+fn array<I, T>(values: I) -> Bson
+where
+    I: IntoIterator<Item = T>,
+    T: Into<Bson>,
+{
+    Bson::Array(values.into_iter().map(Into::into).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn combines_conditions() {
+        let filter = Filter::field("age").gt(21)
+            .and(Filter::field("status").in_(["a", "b"]))
+            .and(Filter::field("tags").size(2));
+
+        let expected = doc! {
+            "$and": [
+                { "age": { "$gt": 21 } },
+                { "status": { "$in": ["a", "b"] } },
+                { "tags": { "$size": 2i64 } },
+            ],
+        };
+
+        assert_eq!(expected, filter.build());
+    }
+
+    #[test]
+    fn negates_and_nests() {
+        let filter = Filter::field("qty").not().lte(5)
+            .or(Filter::field("items").elem_match(Filter::field("sku").eq("x1")))
+            .and(Filter::field("deleted").exists(false));
+
+        let expected = doc! {
+            "$and": [
+                {
+                    "$or": [
+                        { "qty": { "$not": { "$lte": 5 } } },
+                        { "items": { "$elemMatch": { "sku": { "$eq": "x1" } } } },
+                    ],
+                },
+                { "deleted": { "$exists": false } },
+            ],
+        };
+
+        assert_eq!(expected, bson::Document::from(filter));
+    }
+
+    #[test]
+    fn builds_text_searches() {
+        let search = TextSearch::new("coffee -decaf")
+            .language("es")
+            .case_sensitive(true)
+            .diacritic_sensitive(false);
+
+        let expected = doc! {
+            "$text": {
+                "$search": "coffee -decaf",
+                "$language": "es",
+                "$caseSensitive": true,
+                "$diacriticSensitive": false,
+            },
+        };
+
+        assert_eq!(expected, Filter::from(search).build());
+        assert_eq!(doc! { "$text": { "$search": "tea" } }, Filter::text("tea").build());
+        assert_eq!(doc! { "$meta": "textScore" }, text_score());
+    }
+
+    #[test]
+    fn builds_geospatial_conditions() {
+        let point = Geometry::point(1.0, 2.0).unwrap();
+        let expected = doc! {
+            "area": {
+                "$geoIntersects": {
+                    "$geometry": { "type": "Point", "coordinates": [1.0, 2.0] },
+                },
+            },
+        };
+        assert_eq!(expected, Filter::field("area").geo_intersects(point).build());
+
+        let near = Filter::field("loc").near(Near::legacy(1.0, 2.0).max_distance(5.0));
+        assert_eq!(doc! { "loc": { "$near": [1.0, 2.0], "$maxDistance": 5.0 } }, near.build());
+
+        let within = Filter::field("loc").geo_within(Shape::Center((0.0, 0.0), 2.0));
+        assert_eq!(doc! { "loc": { "$geoWithin": { "$center": [[0.0, 0.0], 2.0] } } },
+                   within.build());
+    }
+
+    #[test]
+    fn builds_date_ranges() {
+        let start = Utc.with_ymd_and_hms(2018, 3, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2018, 3, 2, 0, 0, 0).unwrap();
+        let expected = doc! { "placed_at": { "$gte": start, "$lt": end } };
+
+        assert_eq!(expected, Filter::field("placed_at").between(start, end).build());
+        let day = NaiveDate::from_ymd_opt(2018, 3, 1).unwrap();
+        assert_eq!(expected, Filter::field("placed_at").on_day(day).build());
+    }
+}
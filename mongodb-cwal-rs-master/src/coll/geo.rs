@@ -0,0 +1,331 @@
+//! Geometries and shapes for geospatial queries.
+//!
+//! GeoJSON geometries use longitude and latitude in degrees and need a `2dsphere` index; legacy
+//! coordinate pairs are planar and need a `2d` index.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::filter::Filter;
+//! # use mongodb::coll::geo::{Geometry, Near};
+//! # use mongodb::coll::options::IndexModel;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("city").collection("cafes");
+//! coll.create_index_model(IndexModel::builder().sphere("location").build().unwrap()).unwrap();
+//!
+//! // Cafes within a kilometre, nearest first.
+//! let near = Near::point(2.3522, 48.8566).unwrap().max_distance(1000.0);
+//! let nearby = coll.find(Some(Filter::field("location").near(near).build()), None).unwrap();
+//!
+//! let area = Geometry::polygon(vec![
+//!     vec![(2.33, 48.85), (2.37, 48.85), (2.37, 48.87), (2.33, 48.87), (2.33, 48.85)],
+//! ]).unwrap();
+//! let inside = coll.find(Some(Filter::field("location").geo_within_geometry(area).build()), None)
+//!     .unwrap();
+//! # }
+//! ```
+use bson::{self, Bson};
+
+use Error::ArgumentError;
+use Result;
+
+/// A longitude and latitude in degrees, or x and y for legacy coordinate pairs.
+pub type Position = (f64, f64);
+
+/// A GeoJSON geometry, checked when it is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Geometry {
+    kind: &'static str,
+    coordinates: Bson,
+}
+
+impl Geometry {
+    /// Creates a point at `longitude` and `latitude`.
+    pub fn point(longitude: f64, latitude: f64) -> Result<Geometry> {
+        Ok(Geometry {
+            kind: "Point",
+            coordinates: position((longitude, latitude))?,
+        })
+    }
+
+    /// Creates a line through at least two positions.
+    pub fn line_string(positions: Vec<Position>) -> Result<Geometry> {
+        if positions.len() < 2 {
+            return Err(ArgumentError(String::from(
+                "GeoJSON line strings need at least two positions.",
+            )));
+        }
+
+        Ok(Geometry {
+            kind: "LineString",
+            coordinates: positions_to_bson(positions)?,
+        })
+    }
+
+    /// Creates a polygon from its exterior ring followed by the rings of any holes. Each ring
+    /// needs at least four positions and must end where it starts.
+    pub fn polygon(rings: Vec<Vec<Position>>) -> Result<Geometry> {
+        Ok(Geometry {
+            kind: "Polygon",
+            coordinates: rings_to_bson(rings)?,
+        })
+    }
+
+    /// Creates a set of polygons, each given as for `polygon`.
+    pub fn multi_polygon(polygons: Vec<Vec<Vec<Position>>>) -> Result<Geometry> {
+        if polygons.is_empty() {
+            return Err(ArgumentError(String::from(
+                "GeoJSON multi-polygons need at least one polygon.",
+            )));
+        }
+
+        let mut coordinates = Vec::with_capacity(polygons.len());
+        for rings in polygons {
+            coordinates.push(rings_to_bson(rings)?);
+        }
+
+        Ok(Geometry {
+            kind: "MultiPolygon",
+            coordinates: Bson::Array(coordinates),
+        })
+    }
+
+    /// Returns the GeoJSON type, such as `"Point"`.
+    pub fn kind(&self) -> &str {
+        self.kind
+    }
+}
+
+impl From<Geometry> for bson::Document {
+    fn from(geometry: Geometry) -> Self {
+        let mut document = bson::Document::new();
+        document.insert("type", geometry.kind);
+        document.insert("coordinates", geometry.coordinates);
+        document
+    }
+}
+
+// What a `$near` or `$nearSphere` query measures distances from.
+#[derive(Clone, Debug, PartialEq)]
+enum Origin {
+    Point(Geometry),
+    Legacy(Position),
+}
+
+/// The origin and distance bounds of a `$near` or `$nearSphere` query, which returns the
+/// nearest documents first.
+///
+/// Distances are in metres from a GeoJSON point. From a legacy coordinate pair they are in the
+/// units of the coordinates for `$near`, and in radians for `$nearSphere`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Near {
+    origin: Origin,
+    max_distance: Option<f64>,
+    min_distance: Option<f64>,
+}
+
+impl Near {
+    /// Measures distances from the GeoJSON point at `longitude` and `latitude`.
+    pub fn point(longitude: f64, latitude: f64) -> Result<Near> {
+        Ok(Near::from_origin(Origin::Point(Geometry::point(longitude, latitude)?)))
+    }
+
+    /// Measures distances from the legacy coordinate pair `x`, `y`.
+    pub fn legacy(x: f64, y: f64) -> Near {
+        Near::from_origin(Origin::Legacy((x, y)))
+    }
+
+    fn from_origin(origin: Origin) -> Near {
+        Near {
+            origin: origin,
+            max_distance: None,
+            min_distance: None,
+        }
+    }
+
+    /// Only matches documents at most `distance` away.
+    pub fn max_distance(mut self, distance: f64) -> Near {
+        self.max_distance = Some(distance);
+        self
+    }
+
+    /// Only matches documents at least `distance` away.
+    pub fn min_distance(mut self, distance: f64) -> Near {
+        self.min_distance = Some(distance);
+        self
+    }
+
+    // Returns the condition on the queried field: the distance bounds go inside `$near` for a
+    // GeoJSON point, and next to it for a legacy pair.
+    pub(crate) fn to_condition(&self, operator: &str) -> bson::Document {
+        let mut bounds = bson::Document::new();
+
+        if let Some(max_distance) = self.max_distance {
+            bounds.insert("$maxDistance", max_distance);
+        }
+
+        if let Some(min_distance) = self.min_distance {
+            bounds.insert("$minDistance", min_distance);
+        }
+
+        let mut condition = bson::Document::new();
+
+        match self.origin {
+            Origin::Point(ref point) => {
+                let mut near = bson::Document::new();
+                near.insert("$geometry", bson::Document::from(point.clone()));
+                for (key, value) in bounds {
+                    near.insert(key, value);
+                }
+                condition.insert(operator, near);
+            }
+            Origin::Legacy((x, y)) => {
+                condition.insert(operator, pair(&(x, y)));
+                for (key, value) in bounds {
+                    condition.insert(key, value);
+                }
+            }
+        }
+
+        condition
+    }
+}
+
+/// A legacy shape over coordinate pairs, for `$geoWithin` queries.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// The rectangle between its bottom left and top right corners.
+    Box(Position, Position),
+    /// The circle around a center with a radius in the units of the coordinates.
+    Center(Position, f64),
+    /// The spherical cap around a center with a radius in radians, which works with `2d` and
+    /// `2dsphere` indexes alike.
+    CenterSphere(Position, f64),
+    /// The polygon through at least three positions; it is closed implicitly.
+    Polygon(Vec<Position>),
+}
+
+impl Shape {
+    pub(crate) fn to_bson(&self) -> Bson {
+        let mut shape = bson::Document::new();
+
+        match *self {
+            Shape::Box(ref bottom_left, ref top_right) => {
+                shape.insert("$box", vec![pair(bottom_left), pair(top_right)]);
+            }
+            Shape::Center(ref center, radius) => {
+                shape.insert("$center", vec![pair(center), Bson::FloatingPoint(radius)]);
+            }
+            Shape::CenterSphere(ref center, radius) => {
+                shape.insert("$centerSphere", vec![pair(center), Bson::FloatingPoint(radius)]);
+            }
+            Shape::Polygon(ref positions) => {
+                shape.insert("$polygon", positions.iter().map(pair).collect::<Vec<_>>());
+            }
+        }
+
+        Bson::Document(shape)
+    }
+}
+
+fn pair(&(x, y): &Position) -> Bson {
+    Bson::Array(vec![Bson::FloatingPoint(x), Bson::FloatingPoint(y)])
+}
+
+fn position((longitude, latitude): Position) -> Result<Bson> {
+    if !(longitude >= -180.0 && longitude <= 180.0 && latitude >= -90.0 && latitude <= 90.0) {
+        return Err(ArgumentError(format!(
+            "Invalid GeoJSON position ({}, {}); longitudes range from -180 to 180 and \
+             latitudes from -90 to 90.",
+            longitude,
+            latitude
+        )));
+    }
+
+    Ok(pair(&(longitude, latitude)))
+}
+
+fn positions_to_bson(positions: Vec<Position>) -> Result<Bson> {
+    let mut coordinates = Vec::with_capacity(positions.len());
+    for pair in positions {
+        coordinates.push(position(pair)?);
+    }
+
+    Ok(Bson::Array(coordinates))
+}
+
+fn rings_to_bson(rings: Vec<Vec<Position>>) -> Result<Bson> {
+    if rings.is_empty() {
+        return Err(ArgumentError(String::from(
+            "GeoJSON polygons need an exterior ring.",
+        )));
+    }
+
+    let mut coordinates = Vec::with_capacity(rings.len());
+
+    for ring in rings {
+        if ring.len() < 4 || ring.first() != ring.last() {
+            return Err(ArgumentError(String::from(
+                "GeoJSON polygon rings need at least four positions and must end where they \
+                 start.",
+            )));
+        }
+
+        coordinates.push(positions_to_bson(ring)?);
+    }
+
+    Ok(Bson::Array(coordinates))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn checks_geometries() {
+        let point = Geometry::point(2.5, 48.0).unwrap();
+        assert_eq!(
+            doc! { "type": "Point", "coordinates": [2.5, 48.0] },
+            bson::Document::from(point)
+        );
+
+        assert!(Geometry::point(190.0, 0.0).is_err());
+        assert!(Geometry::point(0.0, -91.0).is_err());
+        assert!(Geometry::point(::std::f64::NAN, 0.0).is_err());
+        assert!(Geometry::line_string(vec![(0.0, 0.0)]).is_err());
+
+        let square = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+        assert_eq!("Polygon", Geometry::polygon(vec![square.clone()]).unwrap().kind());
+        assert!(Geometry::polygon(vec![square[..4].to_vec()]).is_err());
+        assert!(Geometry::polygon(vec![]).is_err());
+        assert!(Geometry::multi_polygon(vec![vec![square]]).is_ok());
+    }
+
+    #[test]
+    fn places_distance_bounds() {
+        let near = Near::point(1.0, 2.0).unwrap().max_distance(500.0);
+        let expected = doc! {
+            "$near": {
+                "$geometry": { "type": "Point", "coordinates": [1.0, 2.0] },
+                "$maxDistance": 500.0,
+            },
+        };
+        assert_eq!(expected, near.to_condition("$near"));
+
+        let legacy = Near::legacy(1.0, 2.0).max_distance(0.1).min_distance(0.01);
+        let expected = doc! {
+            "$nearSphere": [1.0, 2.0],
+            "$maxDistance": 0.1,
+            "$minDistance": 0.01,
+        };
+        assert_eq!(expected, legacy.to_condition("$nearSphere"));
+
+        let shape = Shape::Box((0.0, 0.0), (2.0, 3.0)).to_bson();
+        assert_eq!(Bson::Document(doc! { "$box": [[0.0, 0.0], [2.0, 3.0]] }), shape);
+    }
+}
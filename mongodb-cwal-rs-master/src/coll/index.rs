@@ -0,0 +1,330 @@
+//! Builder for index models.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::options::IndexModel;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//!
+//! let by_customer = IndexModel::builder()
+//!     .ascending("customer")
+//!     .descending("created")
+//!     .build()
+//!     .unwrap();
+//!
+//! let attributes = IndexModel::builder()
+//!     .wildcard_path("attributes")
+//!     .build()
+//!     .unwrap();
+//!
+//! coll.create_indexes(vec![by_customer, attributes]).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson};
+
+use super::options::{Collation, IndexModel, IndexOptions};
+use Error::ArgumentError;
+use Result;
+
+const WILDCARD: &str = "$**";
+
+/// An index model made of key specifications and options.
+///
+/// `build` checks the keys before the model is sent: there must be at least one, no path may
+/// appear twice, at most one may be hashed, and a wildcard key must be the only key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexBuilder {
+    // (path, specification), in index order.
+    keys: Vec<(String, Bson)>,
+    options: IndexOptions,
+}
+
+impl IndexModel {
+    /// Starts building an index model.
+    pub fn builder() -> IndexBuilder {
+        IndexBuilder::new()
+    }
+}
+
+impl IndexBuilder {
+    /// Creates a builder without keys or options.
+    pub fn new() -> IndexBuilder {
+        Default::default()
+    }
+
+    /// Adds an arbitrary key specification, such as `1` or `"2d"`.
+    pub fn key<T: Into<Bson>>(mut self, path: &str, specification: T) -> IndexBuilder {
+        self.keys.push((String::from(path), specification.into()));
+        self
+    }
+
+    /// Indexes the field at `path` in ascending order.
+    pub fn ascending(self, path: &str) -> IndexBuilder {
+        self.key(path, 1)
+    }
+
+    /// Indexes the field at `path` in descending order.
+    pub fn descending(self, path: &str) -> IndexBuilder {
+        self.key(path, -1)
+    }
+
+    /// Indexes the hash of the field at `path`, for hashed sharding.
+    pub fn hashed(self, path: &str) -> IndexBuilder {
+        self.key(path, "hashed")
+    }
+
+    /// Indexes the words of the string field at `path` for `$text` queries.
+    pub fn text(self, path: &str) -> IndexBuilder {
+        self.key(path, "text")
+    }
+
+    /// Indexes the GeoJSON or legacy coordinates at `path` on a sphere.
+    pub fn sphere(self, path: &str) -> IndexBuilder {
+        self.key(path, "2dsphere")
+    }
+
+    /// Indexes the legacy coordinate pairs at `path` on a plane.
+    pub fn planar(self, path: &str) -> IndexBuilder {
+        self.key(path, "2d")
+    }
+
+    /// Sets the bounds of the coordinates a `planar` index accepts, instead of -180 to 180.
+    pub fn planar_bounds(mut self, min: f64, max: f64) -> IndexBuilder {
+        self.options.min = Some(min);
+        self.options.max = Some(max);
+        self
+    }
+
+    /// Indexes every field of the documents, narrowed down by `wildcard_projection` if set.
+    pub fn wildcard(self) -> IndexBuilder {
+        self.key(WILDCARD, 1)
+    }
+
+    /// Indexes every field within the embedded document at `path`.
+    pub fn wildcard_path(self, path: &str) -> IndexBuilder {
+        self.key(&format!("{}.{}", path, WILDCARD), 1)
+    }
+
+    /// Sets the fields a `wildcard` index includes, such as `{ "a": 1, "b.c": 1 }`, or
+    /// excludes, such as `{ "d": 0 }`.
+    pub fn wildcard_projection(mut self, projection: bson::Document) -> IndexBuilder {
+        self.options.wildcard_projection = Some(projection);
+        self
+    }
+
+    /// Replaces the options set so far, to set those without a helper of their own.
+    pub fn options(mut self, options: IndexOptions) -> IndexBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Names the index instead of deriving its name from the keys.
+    pub fn name(mut self, name: &str) -> IndexBuilder {
+        self.options.name = Some(String::from(name));
+        self
+    }
+
+    /// Rejects documents that repeat the indexed values of another document.
+    pub fn unique(mut self) -> IndexBuilder {
+        self.options.unique = Some(true);
+        self
+    }
+
+    /// Leaves out documents missing the indexed fields.
+    pub fn sparse(mut self) -> IndexBuilder {
+        self.options.sparse = Some(true);
+        self
+    }
+
+    /// Only indexes the documents matching `filter`.
+    pub fn partial(mut self, filter: bson::Document) -> IndexBuilder {
+        self.options.partial_filter_expression = Some(filter);
+        self
+    }
+
+    /// Removes documents `seconds` after the date in the indexed field.
+    pub fn expire_after_seconds(mut self, seconds: i32) -> IndexBuilder {
+        self.options.expire_after_seconds = Some(seconds);
+        self
+    }
+
+    /// Hides the index from the query planner.
+    pub fn hidden(mut self) -> IndexBuilder {
+        self.options.hidden = Some(true);
+        self
+    }
+
+    /// Compares the indexed strings with `collation`.
+    pub fn collation(mut self, collation: Collation) -> IndexBuilder {
+        self.options.collation = Some(collation);
+        self
+    }
+
+    /// Checks the keys and returns the index model.
+    pub fn build(self) -> Result<IndexModel> {
+        if self.keys.is_empty() {
+            return Err(ArgumentError(String::from("Indexes need at least one key.")));
+        }
+
+        let mut keys = bson::Document::new();
+        let mut hashed = 0;
+
+        for (path, specification) in self.keys.iter() {
+            check_path(path)?;
+
+            match *specification {
+                Bson::I32(1) | Bson::I32(-1) => (),
+                Bson::String(ref kind) if kind == "hashed" => hashed += 1,
+                Bson::String(ref kind)
+                    if kind == "text" || kind == "2d" || kind == "2dsphere" ||
+                           kind == "geoHaystack" => (),
+                _ => {
+                    return Err(ArgumentError(format!(
+                        "Invalid index specification {} for '{}'; expected 1, -1, \"hashed\", \
+                         \"text\", \"2d\", \"2dsphere\" or \"geoHaystack\".",
+                        specification,
+                        path
+                    )))
+                }
+            }
+
+            if keys.insert(path.clone(), specification.clone()).is_some() {
+                return Err(ArgumentError(format!("Index key '{}' appears twice.", path)));
+            }
+        }
+
+        if hashed > 1 {
+            return Err(ArgumentError(String::from("Indexes may hash at most one key.")));
+        }
+
+        let wildcard = self.keys.iter().find(|&&(ref path, _)| is_wildcard(path));
+
+        if let Some(&(ref path, _)) = wildcard {
+            if self.keys.len() > 1 {
+                return Err(ArgumentError(String::from(
+                    "Wildcard indexes cannot be compound.",
+                )));
+            }
+
+            if self.options.unique.is_some() || self.options.expire_after_seconds.is_some() {
+                return Err(ArgumentError(String::from(
+                    "Wildcard indexes can neither be unique nor expire documents.",
+                )));
+            }
+
+            if self.options.wildcard_projection.is_some() && path != WILDCARD {
+                return Err(ArgumentError(String::from(
+                    "Only wildcard indexes on all fields take a wildcard projection.",
+                )));
+            }
+        } else if self.options.wildcard_projection.is_some() {
+            return Err(ArgumentError(String::from(
+                "Only wildcard indexes take a wildcard projection.",
+            )));
+        }
+
+        Ok(IndexModel::new(keys, Some(self.options)))
+    }
+}
+
+fn is_wildcard(path: &str) -> bool {
+    path == WILDCARD || path.ends_with(".$**")
+}
+
+// Fails for empty paths and path components, and for `$**` anywhere but at the end.
+fn check_path(path: &str) -> Result<()> {
+    let components: Vec<_> = path.split('.').collect();
+
+    if components.iter().any(|component| component.is_empty()) {
+        return Err(ArgumentError(format!("Invalid index key path '{}'.", path)));
+    }
+
+    let last = components.len() - 1;
+    if components[..last].iter().any(|component| component.contains(WILDCARD)) ||
+        (components[last].contains(WILDCARD) && components[last] != WILDCARD)
+    {
+        return Err(ArgumentError(format!(
+            "Wildcard index key '{}' must end with '.$**' or be '$**'.",
+            path
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn builds_compound_keys_in_order() {
+        let model = IndexModel::builder()
+            .ascending("customer")
+            .descending("created")
+            .hashed("region")
+            .name("orders")
+            .unique()
+            .build()
+            .unwrap();
+
+        assert_eq!(doc! { "customer": 1, "created": -1, "region": "hashed" }, model.keys);
+        assert_eq!(Some(String::from("orders")), model.options.name);
+        assert_eq!(Some(true), model.options.unique);
+
+        let text = IndexModel::builder().text("title").sphere("location").build().unwrap();
+        assert_eq!("title_text_location_2dsphere", text.name().unwrap());
+
+        let planar = IndexModel::builder().planar("xy").planar_bounds(0.0, 100.0).build().unwrap();
+        assert_eq!(doc! { "xy": "2d" }, planar.keys);
+        assert_eq!((Some(0.0), Some(100.0)), (planar.options.min, planar.options.max));
+    }
+
+    #[test]
+    fn builds_wildcard_indexes() {
+        let all = IndexModel::builder()
+            .wildcard()
+            .wildcard_projection(doc! { "secret": 0 })
+            .build()
+            .unwrap();
+        let document = all.to_bson().unwrap();
+        assert_eq!(Some(&Bson::Document(doc! { "$**": 1 })), document.get("key"));
+        assert_eq!(
+            Some(&Bson::Document(doc! { "secret": 0 })),
+            document.get("wildcardProjection")
+        );
+
+        let attributes = IndexModel::builder().wildcard_path("attributes").build().unwrap();
+        assert_eq!(doc! { "attributes.$**": 1 }, attributes.keys);
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        assert!(IndexModel::builder().build().is_err());
+        assert!(IndexModel::builder().ascending("a").descending("a").build().is_err());
+        assert!(IndexModel::builder().hashed("a").hashed("b").build().is_err());
+        assert!(IndexModel::builder().key("a", 2).build().is_err());
+        assert!(IndexModel::builder().key("a", "btree").build().is_err());
+        assert!(IndexModel::builder().ascending("a..b").build().is_err());
+        assert!(IndexModel::builder().ascending("a.$**.b").build().is_err());
+
+        assert!(IndexModel::builder().wildcard().ascending("a").build().is_err());
+        assert!(IndexModel::builder().wildcard().unique().build().is_err());
+        assert!(IndexModel::builder()
+            .wildcard_path("a")
+            .wildcard_projection(doc! { "b": 1 })
+            .build()
+            .is_err());
+        assert!(IndexModel::builder()
+            .ascending("a")
+            .wildcard_projection(doc! { "b": 1 })
+            .build()
+            .is_err());
+    }
+}
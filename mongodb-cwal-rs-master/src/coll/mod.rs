@@ -1,28 +1,118 @@
 //! Interface for collection-level operations.
 mod batch;
+pub mod cache;
+pub mod change_stream;
+pub mod checkpoint;
+pub mod coalesce;
 pub mod error;
+pub mod explain;
+pub mod filter;
+pub mod geo;
+pub mod index;
 pub mod options;
+pub mod partition;
+pub mod pipeline;
 pub mod results;
+pub mod search;
+mod time_series;
+pub mod typed;
+pub mod update;
 
-use bson::{self, Bson, bson, doc, oid};
+use bson::{self, Bson, doc};
 use command_type::CommandType;
 
 use self::batch::{Batch, DeleteModel, UpdateModel};
 use self::error::{BulkWriteException, WriteException};
+use self::filter::{text_score, Filter, TextSearch};
 use self::options::*;
 use self::results::*;
+use self::typed::TypedCursor;
 
 use ThreadedClient;
+use admin::{self, CollectionStats, IndexConsistency, ShardDistribution};
+use apm::{CommandResult, CommandStarted, EventRunner};
 use common::{merge_options, ReadPreference, WriteConcern};
-use cursor::Cursor;
+use cursor::{self, Cursor};
 use db::{Database, ThreadedDatabase};
+use interceptor::CommandContext;
+use pool::PooledStream;
+use poison::Recover;
 
 use Result;
-use Error::{ArgumentError, DecoderError, ResponseError, OperationError, BulkWriteError};
+use Error::{self, ArgumentError, DecoderError, ResponseError, OperationError, BulkWriteError};
 
 use wire_protocol::flags::OpQueryFlags;
+use wire_protocol::operations::{ByteLength, Message};
+use wire_protocol::streamed::{self, StreamedBinary};
 use std::collections::{BTreeMap, VecDeque};
+use std::io::Read;
 use std::iter::FromIterator;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+// Collations were introduced in MongoDB 3.4.
+const COLLATION_MIN_WIRE_VERSION: i64 = 5;
+
+// The aggregate command accepts a write concern from MongoDB 3.4.
+const AGGREGATE_WRITE_CONCERN_MIN_WIRE_VERSION: i64 = 5;
+
+// Views were introduced in MongoDB 3.4.
+const VIEW_MIN_WIRE_VERSION: i64 = 5;
+
+// $merge was introduced in MongoDB 4.2.
+const MERGE_MIN_WIRE_VERSION: i64 = 8;
+
+// $out accepts a target in another database from MongoDB 4.4.
+const OUT_TO_DB_MIN_WIRE_VERSION: i64 = 9;
+
+// createIndexes accepts a commit quorum from MongoDB 4.4.
+const COMMIT_QUORUM_MIN_WIRE_VERSION: i64 = 9;
+
+// The field text_search returns the relevance of each document in.
+const TEXT_SCORE_FIELD: &str = "score";
+
+// Returns whether the last stage of the pipeline writes its results to a collection.
+fn ends_with_write_stage(pipeline: &[bson::Document]) -> bool {
+    match pipeline.last() {
+        Some(stage) => stage.contains_key("$out") || stage.contains_key("$merge"),
+        None => false,
+    }
+}
+
+// Returns the minimum wire version required by the write stage ending the pipeline, along
+// with a description of the stage for error messages.
+fn write_stage_requirement(pipeline: &[bson::Document]) -> Option<(i64, &'static str)> {
+    let stage = pipeline.last()?;
+
+    if stage.contains_key("$merge") {
+        Some((MERGE_MIN_WIRE_VERSION, "$merge requires MongoDB 4.2 or newer"))
+    } else if let Some(&Bson::Document(_)) = stage.get("$out") {
+        Some((
+            OUT_TO_DB_MIN_WIRE_VERSION,
+            "$out to another database requires MongoDB 4.4 or newer",
+        ))
+    } else {
+        None
+    }
+}
+
+// Fails unless `size` is a valid size in bytes for a capped collection.
+fn check_capped_size(size: i64) -> Result<()> {
+    if size <= 0 {
+        return Err(ArgumentError(format!("Invalid capped collection size {}.", size)));
+    }
+
+    Ok(())
+}
+
+// Returns whether the server rejected a command, looking past any attached context.
+fn is_operation_error(err: &Error) -> bool {
+    match *err.without_context() {
+        OperationError(_) => true,
+        _ => false,
+    }
+}
 
 /// Interfaces with a MongoDB collection.
 #[derive(Debug)]
@@ -85,17 +175,286 @@ impl Collection {
         }
     }
 
+    // Servers older than 3.4 silently ignore collations in some commands, so
+    // reject them before sending anything.
+    fn check_collation(&self, collation: Option<&Collation>) -> Result<()> {
+        if collation.is_none() {
+            return Ok(());
+        }
+
+        let description = self.db.client.topology.description.read().recover();
+
+        match description.lowest_max_wire_version() {
+            Some(version) if version < COLLATION_MIN_WIRE_VERSION => {
+                Err(ArgumentError(String::from(
+                    "Collations are not supported by this server; MongoDB 3.4 or newer is required.",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if the collection is a view, looking up its type with listCollections the first
+    // time the namespace is written to.
+    fn check_not_view(&self) -> Result<()> {
+        let cached = self.db.client.views.lock()?.get(&self.namespace).cloned();
+
+        let view_on = match cached {
+            Some(view_on) => view_on,
+            None => {
+                let view_on = self.find_view_source()?;
+                self.db.client.views.lock()?.insert(self.namespace.clone(), view_on.clone());
+                view_on
+            }
+        };
+
+        match view_on {
+            Some(source) => Err(ArgumentError(format!(
+                "Cannot write to '{}', which is a read-only view on '{}'.",
+                self.namespace,
+                source
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // Returns the collection the view is defined on, or None if this is not a view.
+    fn find_view_source(&self) -> Result<Option<String>> {
+        {
+            let description = self.db.client.topology.description.read().recover();
+
+            match description.lowest_max_wire_version() {
+                Some(version) if version < VIEW_MIN_WIRE_VERSION => return Ok(None),
+                _ => (),
+            }
+        }
+
+        let spec = doc! {
+            "listCollections": 1,
+            "filter": { "name": self.name() },
+            "cursor": {},
+        };
+
+        // An internal lookup, so it is kept out of command monitoring.
+        let cursor = self.db.command_cursor(
+            spec,
+            CommandType::Suppressed,
+            self.db.read_preference.clone(),
+        );
+
+        let info = match cursor {
+            Ok(mut cursor) => cursor.next(),
+            // Users allowed to write may still lack the listCollections privilege.
+            Err(ref err) if is_operation_error(err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let info = match info {
+            Some(Ok(info)) => info,
+            Some(Err(ref err)) if is_operation_error(err) => return Ok(None),
+            None => return Ok(None),
+            Some(Err(err)) => return Err(err),
+        };
+
+        match info.get("type") {
+            Some(&Bson::String(ref kind)) if kind == "view" => (),
+            _ => return Ok(None),
+        }
+
+        let source = match info.get("options") {
+            Some(&Bson::Document(ref options)) => {
+                match options.get("viewOn") {
+                    Some(&Bson::String(ref view_on)) => view_on.clone(),
+                    _ => String::new(),
+                }
+            }
+            _ => String::new(),
+        };
+
+        Ok(Some(format!("{}.{}", self.db.name, source)))
+    }
+
     /// Permanently deletes the collection from the database.
     pub fn drop(&self) -> Result<()> {
-        self.db.drop_collection(&self.name())
+        self.drop_with_write_concern(None)
+    }
+
+    /// Permanently deletes the collection from the database, acknowledged with the given
+    /// write concern instead of the collection's.
+    pub fn drop_with_write_concern(&self, write_concern: Option<WriteConcern>) -> Result<()> {
+        let wc = write_concern.unwrap_or(self.write_concern);
+        self.db.drop_collection_with_write_concern(&self.name(), Some(wc))
+    }
+
+    /// Returns the storage statistics of the collection, with sizes divided by `scale`.
+    pub fn stats(&self, scale: Option<i32>) -> Result<CollectionStats> {
+        let mut spec = doc! { "collStats": self.name() };
+        if let Some(scale) = scale {
+            spec.insert("scale", admin::check_scale(scale)?);
+        }
+
+        let mut result = self.db.command(
+            spec,
+            CommandType::CollStats,
+            Some(self.read_preference.clone()),
+        )?;
+        result.remove("ok");
+        admin::decode_reply(result)
+    }
+
+    /// Renames the collection to `new_name` within the same database and returns the renamed
+    /// collection. An existing collection named `new_name` is replaced if `drop_target` is set;
+    /// otherwise the rename fails.
+    pub fn rename(&self, new_name: &str, drop_target: bool) -> Result<Collection> {
+        if new_name.is_empty() || new_name.contains('$') || new_name.starts_with("system.") {
+            return Err(ArgumentError(format!("Invalid collection name '{}'.", new_name)));
+        }
+
+        let target = format!("{}.{}", self.db.name, new_name);
+
+        let cmd = doc! {
+            "renameCollection": self.namespace.clone(),
+            "to": target.clone(),
+            "dropTarget": drop_target,
+            "writeConcern": self.write_concern.to_bson(),
+        };
+
+        let result = self.db.client.db("admin").command(
+            cmd,
+            CommandType::RenameCollection,
+            None,
+        )?;
+        WriteException::validate_write_result(result, self.write_concern)?;
+
+        {
+            let mut views = self.db.client.views.lock()?;
+            views.remove(&self.namespace);
+            views.remove(&target);
+        }
+
+        Ok(Collection::new(
+            self.db.clone(),
+            new_name,
+            false,
+            Some(self.read_preference.clone()),
+            Some(self.write_concern),
+        ))
+    }
+
+    /// Replaces the collection with a capped collection of at most `size` bytes holding the
+    /// same documents, as many of the newest as fit.
+    pub fn convert_to_capped(&self, size: i64) -> Result<()> {
+        check_capped_size(size)?;
+
+        let cmd = doc! {
+            "convertToCapped": self.name(),
+            "size": size,
+            "writeConcern": self.write_concern.to_bson(),
+        };
+
+        let result = self.db.command(cmd, CommandType::ConvertToCapped, None)?;
+        WriteException::validate_write_result(result, self.write_concern)
+    }
+
+    /// Copies the collection into a new capped collection `target` of at most `size` bytes,
+    /// keeping as many of the newest documents as fit, and returns the new collection.
+    pub fn clone_as_capped(&self, target: &str, size: i64) -> Result<Collection> {
+        check_capped_size(size)?;
+
+        let cmd = doc! {
+            "cloneCollectionAsCapped": self.name(),
+            "toCollection": target,
+            "size": size,
+            "writeConcern": self.write_concern.to_bson(),
+        };
+
+        let result = self.db.command(cmd, CommandType::CloneCollectionAsCapped, None)?;
+        WriteException::validate_write_result(result, self.write_concern)?;
+
+        Ok(Collection::new(
+            self.db.clone(),
+            target,
+            false,
+            Some(self.read_preference.clone()),
+            Some(self.write_concern),
+        ))
+    }
+
+    /// Reports the data size, document count and chunk count of the collection on each shard.
+    ///
+    /// Must be run through a `mongos`; fails with an `OperationError` if the collection is
+    /// not sharded.
+    pub fn shard_distribution(&self) -> Result<ShardDistribution> {
+        let chunk_filter = match self.db.client.sharded_collection(&self.namespace)? {
+            Some(collection) => collection.chunk_filter(),
+            None => {
+                return Err(OperationError(
+                    format!("Collection {} is not sharded.", self.namespace),
+                ));
+            }
+        };
+
+        let stats = self
+            .aggregate(vec![doc! { "$collStats": { "storageStats": {} } }], None)?
+            .collect::<Result<Vec<_>>>()?;
+
+        let chunks = self.db.client.db("config")
+            .collection("chunks")
+            .aggregate(
+                vec![
+                    doc! { "$match": chunk_filter },
+                    doc! { "$group": { "_id": "$shard", "chunks": { "$sum": 1 } } },
+                ],
+                None,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        ShardDistribution::from_replies(stats, chunks)
+    }
+
+    /// Lists the indexes of the collection on each of its shards and reports those that are
+    /// missing from some shards or defined with different options.
+    ///
+    /// Must be run through a `mongos`; fails with an `OperationError` if the collection is
+    /// not sharded.
+    pub fn check_index_consistency(&self) -> Result<IndexConsistency> {
+        if self.db.client.sharded_collection(&self.namespace)?.is_none() {
+            return Err(OperationError(
+                format!("Collection {} is not sharded.", self.namespace),
+            ));
+        }
+
+        let stats = self
+            .aggregate(vec![doc! { "$indexStats": {} }], None)?
+            .collect::<Result<Vec<_>>>()?;
+
+        IndexConsistency::from_index_stats(stats)
     }
 
     /// Runs an aggregation framework pipeline.
+    ///
+    /// Pipelines ending in `$out` or `$merge` are sent to a writable server with the write
+    /// concern from the options, or the collection's by default, and return an empty cursor.
+    /// Targets in other databases are written with the same write concern; a write stage the
+    /// connected servers do not support fails with an `ArgumentError` before anything is sent.
     pub fn aggregate(
         &self,
         pipeline: Vec<bson::Document>,
         options: Option<AggregateOptions>,
     ) -> Result<Cursor> {
+        let writes = ends_with_write_stage(&pipeline);
+        self.check_write_stage(&pipeline)?;
+
+        // Only change streams return awaitData cursors, whose getMores accept an await time.
+        let max_await_time_ms = options.as_ref().and_then(|options| options.max_await_time_ms);
+        if max_await_time_ms.is_some() &&
+            !pipeline.first().map_or(false, |stage| stage.contains_key("$changeStream"))
+        {
+            return Err(ArgumentError(String::from(
+                "max_await_time_ms only applies to pipelines starting with $changeStream.",
+            )));
+        }
+
         let pipeline_map: Vec<_> = pipeline.into_iter().map(Bson::Document).collect();
 
         let mut spec = doc! {
@@ -104,6 +463,7 @@ impl Collection {
         };
 
         let mut read_preference = self.read_preference.clone();
+        let mut write_concern = self.write_concern;
 
         match options {
             Some(aggregate_options) => {
@@ -111,6 +471,11 @@ impl Collection {
                     read_preference = read_preference_option.clone();
                 }
 
+                if let Some(write_concern_option) = aggregate_options.write_concern {
+                    write_concern = write_concern_option;
+                }
+
+                self.check_collation(aggregate_options.collation.as_ref())?;
                 spec = merge_options(spec, aggregate_options);
             }
             None => {
@@ -118,11 +483,128 @@ impl Collection {
             }
         };
 
-        self.db.command_cursor(
-            spec,
-            CommandType::Aggregate,
-            read_preference,
-        )
+        if !writes {
+            let mut cursor = self.db.command_cursor(spec, CommandType::Aggregate, read_preference)?;
+
+            if let Some(max_await_time_ms) = max_await_time_ms {
+                cursor.set_max_await_time_ms(max_await_time_ms);
+            }
+
+            return Ok(cursor);
+        }
+
+        if self.supports_aggregate_write_concern()? {
+            spec.insert("writeConcern", write_concern.to_bson());
+        }
+
+        self.db.command_cursor(spec, CommandType::AggregateWrite, read_preference)
+    }
+
+    fn check_write_stage(&self, pipeline: &[bson::Document]) -> Result<()> {
+        let (min_wire_version, message) = match write_stage_requirement(pipeline) {
+            Some(requirement) => requirement,
+            None => return Ok(()),
+        };
+
+        let description = self.db.client.topology.description.read().recover();
+
+        match description.lowest_max_wire_version() {
+            Some(version) if version < min_wire_version => {
+                Err(ArgumentError(format!("{}.", message)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Servers older than 4.4 reject commitQuorum on createIndexes.
+    fn check_commit_quorum(&self) -> Result<()> {
+        let description = self.db.client.topology.description.read().recover();
+
+        match description.lowest_max_wire_version() {
+            Some(version) if version < COMMIT_QUORUM_MIN_WIRE_VERSION => {
+                Err(ArgumentError(String::from(
+                    "Commit quorums are not supported by this server; MongoDB 4.4 or newer is \
+                     required.",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Servers older than 3.4 reject writeConcern on the aggregate command.
+    fn supports_aggregate_write_concern(&self) -> Result<bool> {
+        let description = self.db.client.topology.description.read().recover();
+
+        Ok(match description.lowest_max_wire_version() {
+            Some(version) => version >= AGGREGATE_WRITE_CONCERN_MIN_WIRE_VERSION,
+            None => true,
+        })
+    }
+
+    /// Runs a mapReduce with the JavaScript `map` and `reduce` functions.
+    ///
+    /// Inline output returns a cursor over the results. Output to a collection is sent to a
+    /// writable server with the write concern from the options, or the collection's by default.
+    pub fn map_reduce(
+        &self,
+        map: &str,
+        reduce: &str,
+        options: Option<MapReduceOptions>,
+    ) -> Result<MapReduceResult> {
+        let options = options.unwrap_or_default();
+        self.check_not_view()?;
+        self.check_collation(options.collation.as_ref())?;
+
+        let inline = options.out == MapReduceOutput::Inline;
+        let read_preference = options.read_preference.clone().unwrap_or_else(
+            || self.read_preference.clone(),
+        );
+        let write_concern = options.write_concern.clone().unwrap_or_else(
+            || self.write_concern.clone(),
+        );
+
+        let spec = doc! {
+            "mapReduce": self.name(),
+            "map": Bson::JavaScriptCode(String::from(map)),
+            "reduce": Bson::JavaScriptCode(String::from(reduce)),
+        };
+        let mut spec = merge_options(spec, options);
+
+        if inline {
+            let reply = self.db.command(
+                spec,
+                CommandType::MapReduce,
+                Some(read_preference.clone()),
+            )?;
+
+            let results = match reply.get("results") {
+                Some(&Bson::Array(ref results)) => results
+                    .iter()
+                    .filter_map(|result| match *result {
+                        Bson::Document(ref doc) => Some(doc.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => return Err(ResponseError(String::from("mapReduce returned no results."))),
+            };
+
+            return Ok(MapReduceResult::Inline(Cursor::with_documents(
+                self.db.client.clone(),
+                self.namespace.clone(),
+                results,
+                CommandType::MapReduce,
+                read_preference,
+            )));
+        }
+
+        // mapReduce accepts a writeConcern from the same server version as aggregate.
+        if self.supports_aggregate_write_concern()? {
+            spec.insert("writeConcern", write_concern.to_bson());
+        }
+
+        let reply = self.db.command(spec, CommandType::MapReduceWrite, None)?;
+        WriteException::validate_write_result(reply.clone(), write_concern)?;
+        MapReduceStats::new(&reply).map(MapReduceResult::Collection)
     }
 
     /// Gets the number of documents matching the filter.
@@ -146,6 +628,7 @@ impl Collection {
                 read_preference = read_preference_option.clone();
             }
 
+            self.check_collation(count_options.collation.as_ref())?;
             spec = merge_options(spec, count_options);
         }
 
@@ -179,9 +662,16 @@ impl Collection {
             spec.insert("query", filter_doc);
         }
 
-        let read_preference = options.and_then(|o| o.read_preference).unwrap_or_else(|| {
-            self.read_preference.clone()
-        });
+        let mut read_preference = self.read_preference.clone();
+
+        if let Some(distinct_options) = options {
+            if let Some(ref read_preference_option) = distinct_options.read_preference {
+                read_preference = read_preference_option.clone();
+            }
+
+            self.check_collation(distinct_options.collation.as_ref())?;
+            spec = merge_options(spec, distinct_options);
+        }
 
         let result = self.db.command(
             spec,
@@ -205,6 +695,46 @@ impl Collection {
         self.find_with_command_type(filter, options, CommandType::Find)
     }
 
+    /// Returns the documents matching both the text search and the filter, with their
+    /// relevance to the search in a `score` field.
+    ///
+    /// The best matches come first unless the options set another sort order, and a
+    /// projection of `score` in the options takes precedence over the relevance.
+    pub fn text_search(
+        &self,
+        search: TextSearch,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<Cursor> {
+        let mut filter = filter.unwrap_or_default();
+
+        if filter.contains_key("$text") {
+            return Err(ArgumentError(String::from(
+                "Queries can hold a single text search, which is already given.",
+            )));
+        }
+
+        for (key, value) in bson::Document::from(Filter::from(search)) {
+            filter.insert(key, value);
+        }
+
+        let mut options = options.unwrap_or_default();
+
+        let mut projection = options.projection.take().unwrap_or_default();
+        if !projection.contains_key(TEXT_SCORE_FIELD) {
+            projection.insert(TEXT_SCORE_FIELD, text_score());
+        }
+        options.projection = Some(projection);
+
+        if options.sort.is_none() {
+            let mut sort = bson::Document::new();
+            sort.insert(TEXT_SCORE_FIELD, text_score());
+            options.sort = Some(sort);
+        }
+
+        self.find(Some(filter), Some(options))
+    }
+
     fn find_with_command_type(
         &self,
         filter: Option<bson::Document>,
@@ -212,24 +742,83 @@ impl Collection {
         cmd_type: CommandType,
     ) -> Result<Cursor> {
         let find_options = options.unwrap_or_default();
-        let flags = OpQueryFlags::with_find_options(&find_options);
-
-        let doc = match find_options.sort {
-            Some(ref sort_opt) => {
-                doc! {
-                    "$query": filter.unwrap_or_default(),
-                    "$orderby": sort_opt.clone(),
-                }
-            }
-            None => filter.unwrap_or_default(),
-        };
 
         let read_preference = match find_options.read_preference {
             Some(ref read_preference_option) => read_preference_option.clone(),
             None => self.read_preference.clone(),
         };
 
-        Cursor::query(
+        if find_options.max_await_time_ms.is_some() &&
+            find_options.cursor_type != CursorType::TailableAwait
+        {
+            return Err(ArgumentError(String::from(
+                "max_await_time_ms only applies to TailableAwait cursors.",
+            )));
+        }
+
+        // Legacy OP_QUERY finds cannot carry a collation or variables, and their getMores
+        // cannot carry an await time, so those are sent as a `find` command instead.
+        if find_options.collation.is_some() || find_options.let_vars.is_some() ||
+            find_options.max_await_time_ms.is_some()
+        {
+            self.check_collation(find_options.collation.as_ref())?;
+
+            let max_await_time_ms = find_options.max_await_time_ms;
+            let max_decoded = find_options.max_decoded_documents;
+            let spec = doc! {
+                "find": self.name(),
+                "filter": filter.unwrap_or_default(),
+            };
+
+            let mut cursor = self.db.command_cursor(
+                merge_options(spec, find_options),
+                cmd_type,
+                read_preference,
+            )?;
+
+            if let Some(max_await_time_ms) = max_await_time_ms {
+                cursor.set_max_await_time_ms(max_await_time_ms);
+            }
+
+            if let Some(max) = max_decoded {
+                cursor.set_max_decoded_documents(max);
+            }
+
+            return Ok(cursor);
+        }
+
+        let flags = OpQueryFlags::with_find_options(&find_options);
+        let max_decoded = find_options.max_decoded_documents;
+
+        let hint = hint_to_bson(find_options.hint.clone(), find_options.hint_doc.clone());
+
+        let doc = if find_options.sort.is_some() || hint.is_some() ||
+            find_options.max_time_ms.is_some() || find_options.comment.is_some()
+        {
+            let mut query = doc! { "$query": filter.unwrap_or_default() };
+
+            if let Some(ref sort_opt) = find_options.sort {
+                query.insert("$orderby", sort_opt.clone());
+            }
+
+            if let Some(hint) = hint {
+                query.insert("$hint", hint);
+            }
+
+            if let Some(max_time_ms) = find_options.max_time_ms {
+                query.insert("$maxTimeMS", max_time_ms);
+            }
+
+            if let Some(ref comment) = find_options.comment {
+                query.insert("$comment", comment.clone());
+            }
+
+            query
+        } else {
+            filter.unwrap_or_default()
+        };
+
+        let mut cursor = Cursor::query(
             self.db.client.clone(),
             self.namespace.to_owned(),
             flags,
@@ -238,7 +827,13 @@ impl Collection {
             cmd_type,
             false,
             read_preference,
-        )
+        )?;
+
+        if let Some(max) = max_decoded {
+            cursor.set_max_decoded_documents(max);
+        }
+
+        Ok(cursor)
     }
 
     /// Returns the first document within the collection that matches the filter, or None.
@@ -277,10 +872,12 @@ impl Collection {
         &self,
         filter: bson::Document,
         options: bson::Document,
-        _max_time_ms: Option<i64>,
+        max_time_ms: Option<i64>,
         write_concern: Option<WriteConcern>,
         cmd_type: CommandType,
-    ) -> Result<Option<bson::Document>> {
+    ) -> Result<FindAndModifyResult> {
+        self.check_not_view()?;
+
         let mut cmd = doc! {
             "findAndModify": self.name(),
             "query": filter,
@@ -288,16 +885,19 @@ impl Collection {
 
         cmd = merge_options(cmd, options);
 
-        let res = self.db.command(cmd, cmd_type, None)?;
+        if let Some(max_time_ms) = max_time_ms {
+            cmd.insert("maxTimeMS", max_time_ms);
+        }
+
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
-        WriteException::validate_write_result(res.clone(), wc)?;
+        if !cmd.contains_key("writeConcern") {
+            cmd.insert("writeConcern", wc.to_bson());
+        }
 
-        let doc = match res.get("value") {
-            Some(&Bson::Document(ref nested_doc)) => Some(nested_doc.to_owned()),
-            _ => None,
-        };
+        let res = self.db.command(cmd, cmd_type, None)?;
+        WriteException::validate_write_result(res.clone(), wc)?;
 
-        Ok(doc)
+        Ok(FindAndModifyResult::new(&res))
     }
 
     /// Finds a single document and deletes it, returning the original.
@@ -306,8 +906,21 @@ impl Collection {
         filter: bson::Document,
         options: Option<FindOneAndDeleteOptions>,
     ) -> Result<Option<bson::Document>> {
+        self.find_one_and_delete_with_result(filter, options).map(|result| result.value)
+    }
+
+    /// Finds a single document and deletes it, returning the original along with how many
+    /// documents were removed.
+    pub fn find_one_and_delete_with_result(
+        &self,
+        filter: bson::Document,
+        options: Option<FindOneAndDeleteOptions>,
+    ) -> Result<FindAndModifyResult> {
         let (max_time_ms, write_concern) = match options {
-            Some(ref opts) => (opts.max_time_ms, opts.write_concern.clone()),
+            Some(ref opts) => {
+                self.check_collation(opts.collation.as_ref())?;
+                (opts.max_time_ms, opts.write_concern.clone())
+            }
             None => (None, None),
         };
 
@@ -334,10 +947,31 @@ impl Collection {
         replacement: bson::Document,
         options: Option<FindOneAndUpdateOptions>,
     ) -> Result<Option<bson::Document>> {
+        self.find_one_and_replace_with_result(filter, replacement, options)
+            .map(|result| result.value)
+    }
+
+    /// Finds a single document and replaces it, also reporting whether an existing document
+    /// was replaced or, with `upsert`, a new one inserted.
+    pub fn find_one_and_replace_with_result(
+        &self,
+        filter: bson::Document,
+        replacement: bson::Document,
+        options: Option<FindOneAndUpdateOptions>,
+    ) -> Result<FindAndModifyResult> {
         Collection::validate_replace(&replacement)?;
 
         let (max_time_ms, write_concern) = match options {
-            Some(ref opts) => (opts.max_time_ms, opts.write_concern.clone()),
+            Some(ref opts) => {
+                if opts.array_filters.is_some() {
+                    return Err(ArgumentError(
+                        String::from("Replacement cannot use array filters."),
+                    ));
+                }
+
+                self.check_collation(opts.collation.as_ref())?;
+                (opts.max_time_ms, opts.write_concern.clone())
+            }
             None => (None, None),
         };
 
@@ -364,10 +998,24 @@ impl Collection {
         update: bson::Document,
         options: Option<FindOneAndUpdateOptions>,
     ) -> Result<Option<bson::Document>> {
+        self.find_one_and_update_with_result(filter, update, options).map(|result| result.value)
+    }
+
+    /// Finds a single document and updates it, also reporting whether an existing document
+    /// was updated or, with `upsert`, a new one inserted.
+    pub fn find_one_and_update_with_result(
+        &self,
+        filter: bson::Document,
+        update: bson::Document,
+        options: Option<FindOneAndUpdateOptions>,
+    ) -> Result<FindAndModifyResult> {
         Collection::validate_update(&update)?;
 
         let (max_time_ms, write_concern) = match options {
-            Some(ref opts) => (opts.max_time_ms, opts.write_concern.clone()),
+            Some(ref opts) => {
+                self.check_collation(opts.collation.as_ref())?;
+                (opts.max_time_ms, opts.write_concern.clone())
+            }
             None => (None, None),
         };
 
@@ -386,70 +1034,61 @@ impl Collection {
         )
     }
 
-    fn get_unordered_batches(requests: Vec<WriteModel>) -> Vec<Batch> {
+    // Groups requests by kind, each with the positions of its requests in `requests`. Kinds
+    // without requests are left out.
+    fn get_unordered_batches(requests: Vec<WriteModel>) -> Vec<(Batch, Vec<i64>)> {
         let mut inserts = Vec::new();
         let mut deletes = Vec::new();
         let mut updates = Vec::new();
+        let mut positions = (Vec::new(), Vec::new(), Vec::new());
+
+        for (position, req) in requests.into_iter().enumerate() {
+            match req {
+                WriteModel::InsertOne { .. } => positions.0.push(position as i64),
+                WriteModel::DeleteOne { .. } |
+                WriteModel::DeleteMany { .. } => positions.1.push(position as i64),
+                _ => positions.2.push(position as i64),
+            }
 
-        for req in requests {
             match req {
                 WriteModel::InsertOne { document } => inserts.push(document),
                 WriteModel::DeleteOne { filter } => {
-                    deletes.push(DeleteModel {
-                        filter: filter,
-                        multi: false,
-                    })
+                    deletes.push(DeleteModel::new(filter, false))
                 }
                 WriteModel::DeleteMany { filter } => {
-                    deletes.push(DeleteModel {
-                        filter: filter,
-                        multi: true,
-                    })
+                    deletes.push(DeleteModel::new(filter, true))
                 }
                 WriteModel::ReplaceOne {
                     filter,
                     replacement,
                     upsert,
                 } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: replacement,
-                        upsert: upsert,
-                        multi: false,
-                    })
+                    updates.push(UpdateModel::new(filter, replacement, upsert, false))
                 }
                 WriteModel::UpdateOne {
                     filter,
                     update,
                     upsert,
                 } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: false,
-                    })
+                    updates.push(UpdateModel::new(filter, update, upsert, false))
                 }
                 WriteModel::UpdateMany {
                     filter,
                     update,
                     upsert,
                 } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: true,
-                    })
+                    updates.push(UpdateModel::new(filter, update, upsert, true))
                 }
             }
         }
 
         vec![
-            Batch::Insert(inserts),
-            Batch::Delete(deletes),
-            Batch::Update(updates),
-        ]
+            (Batch::Insert(inserts), positions.0),
+            (Batch::Delete(deletes), positions.1),
+            (Batch::Update(updates), positions.2),
+        ].into_iter()
+            .filter(|&(_, ref positions)| !positions.is_empty())
+            .collect()
     }
 
     fn get_ordered_batches(mut requests: VecDeque<WriteModel>) -> Vec<Batch> {
@@ -474,8 +1113,8 @@ impl Collection {
     fn execute_insert_batch(
         &self,
         documents: Vec<bson::Document>,
-        start_index: i64,
         ordered: bool,
+        options: &BulkWriteOptions,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
@@ -487,14 +1126,21 @@ impl Collection {
 
         let options = Some(InsertManyOptions {
             ordered: Some(ordered),
-            ..Default::default()
+            max_time_ms: options.max_time_ms,
+            bypass_document_validation: options.bypass_document_validation,
+            comment: options.comment.clone(),
+            write_concern: options.write_concern.clone(),
+            max_concurrent_batches: None,
         });
 
         match self.insert_many(documents, options) {
             Ok(insert_result) => {
-                result.process_insert_many_result(insert_result, models, start_index, exception)
+                result.process_insert_many_result(insert_result, models, 0, exception)
             }
-            Err(_) => {
+            Err(err) => {
+                if exception.message.is_empty() {
+                    exception.message = err.to_string();
+                }
                 exception.add_unproccessed_models(models);
                 false
             }
@@ -505,6 +1151,7 @@ impl Collection {
         &self,
         models: Vec<DeleteModel>,
         ordered: bool,
+        options: &BulkWriteOptions,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
@@ -517,11 +1164,26 @@ impl Collection {
             })
             .collect();
 
-        match self.bulk_delete(models, ordered, None, CommandType::DeleteMany) {
+        let command_options = WriteCommandOptions {
+            max_time_ms: options.max_time_ms,
+            comment: options.comment.clone(),
+            ..Default::default()
+        };
+
+        match self.bulk_delete(
+            models,
+            ordered,
+            options.write_concern.clone(),
+            command_options,
+            CommandType::DeleteMany,
+        ) {
             Ok(bulk_delete_result) => {
                 result.process_bulk_delete_result(bulk_delete_result, original_models, exception)
             }
-            Err(_) => {
+            Err(err) => {
+                if exception.message.is_empty() {
+                    exception.message = err.to_string();
+                }
                 exception.add_unproccessed_models(original_models);
                 false
             }
@@ -531,8 +1193,8 @@ impl Collection {
     fn execute_update_batch(
         &self,
         models: Vec<UpdateModel>,
-        start_index: i64,
         ordered: bool,
+        options: &BulkWriteOptions,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
@@ -553,45 +1215,106 @@ impl Collection {
             })
             .collect();
 
-        match self.bulk_update(models, ordered, None, CommandType::UpdateMany) {
+        let command_options = WriteCommandOptions {
+            max_time_ms: options.max_time_ms,
+            bypass_document_validation: options.bypass_document_validation,
+            comment: options.comment.clone(),
+            let_vars: None,
+        };
+
+        match self.bulk_update(
+            models,
+            ordered,
+            options.write_concern.clone(),
+            command_options,
+            CommandType::UpdateMany,
+        ) {
             Ok(bulk_update_result) => {
                 result.process_bulk_update_result(
                     bulk_update_result,
                     original_models,
-                    start_index,
+                    0,
                     exception,
                 )
             }
-            Err(_) => {
+            Err(err) => {
+                if exception.message.is_empty() {
+                    exception.message = err.to_string();
+                }
                 exception.add_unproccessed_models(original_models);
                 false
             }
         }
     }
 
+    // Executes a single batch, reporting counts, ids and write errors by position in it.
     fn execute_batch(
         &self,
         batch: Batch,
-        start_index: i64,
         ordered: bool,
+        options: &BulkWriteOptions,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
         match batch {
             Batch::Insert(docs) => {
-                self.execute_insert_batch(docs, start_index, ordered, result, exception)
+                self.execute_insert_batch(
+                    docs,
+                    ordered,
+                    options,
+                    result,
+                    exception,
+                )
+            }
+            Batch::Delete(models) => {
+                self.execute_delete_batch(models, ordered, options, result, exception)
             }
-            Batch::Delete(models) => self.execute_delete_batch(models, ordered, result, exception),
             Batch::Update(models) => {
-                self.execute_update_batch(models, start_index, ordered, result, exception)
+                self.execute_update_batch(
+                    models,
+                    ordered,
+                    options,
+                    result,
+                    exception,
+                )
             }
         }
     }
 
     /// Sends a batch of writes to the server at the same time.
-    pub fn bulk_write(&self, requests: Vec<WriteModel>, ordered: bool) -> BulkWriteResult {
+    ///
+    /// Ordered writes are grouped into runs of consecutive operations of the same kind and
+    /// stop at the first failing run; unordered writes are grouped into at most one insert,
+    /// one delete and one update command, and every group is attempted. Any failures are
+    /// reported in `bulk_write_exception` by position in `requests`, and collected with the
+    /// counts of the successful writes by `BulkWriteResult::failure`.
+    pub fn bulk_write(
+        &self,
+        requests: Vec<WriteModel>,
+        options: Option<BulkWriteOptions>,
+    ) -> BulkWriteResult {
+        let options = options.unwrap_or_default();
+        let ordered = options.ordered.unwrap_or(true);
+
+        if let Err(err) = self.check_not_view() {
+            let mut result = BulkWriteResult::new();
+            let mut exception = BulkWriteException::new(Vec::new(), requests, Vec::new(), None);
+            exception.message = err.to_string();
+            result.bulk_write_exception = Some(exception);
+            return result;
+        }
+
         let batches = if ordered {
+            let mut start_index = 0;
             Collection::get_ordered_batches(VecDeque::from_iter(requests.into_iter()))
+                .into_iter()
+                .map(|batch| {
+                    let end_index = start_index + batch.len() as i64;
+                    let positions = (start_index..end_index).collect();
+                    start_index = end_index;
+                    (batch, positions)
+                })
+                .collect()
         } else {
             Collection::get_unordered_batches(requests)
         };
@@ -599,21 +1322,34 @@ impl Collection {
         let mut result = BulkWriteResult::new();
         let mut exception = BulkWriteException::new(Vec::new(), Vec::new(), Vec::new(), None);
 
-        let mut start_index = 0;
+        for (batch, positions) in batches {
+            let mut batch_result = BulkWriteResult::new();
+            let reported_errors = exception.write_errors.len();
+            let success = self.execute_batch(
+                batch,
+                ordered,
+                &options,
+                &mut batch_result,
+                &mut exception,
+            );
+
+            for error in &mut exception.write_errors[reported_errors..] {
+                if let Some(&position) = positions.get(error.index as usize) {
+                    error.index = position as i32;
+                }
+            }
 
-        for batch in batches {
-            let length = batch.len();
-            let success =
-                self.execute_batch(batch, start_index, ordered, &mut result, &mut exception);
+            result.merge_batch(batch_result, &positions);
 
             if !success && ordered {
                 break;
             }
-
-            start_index += length;
         }
 
-        if !exception.unprocessed_requests.is_empty() {
+        if !exception.unprocessed_requests.is_empty() || !exception.write_errors.is_empty() ||
+            exception.write_concern_error.is_some()
+        {
+            exception.write_errors.sort_by_key(|error| error.index);
             result.bulk_write_exception = Some(exception);
         }
 
@@ -621,6 +1357,12 @@ impl Collection {
     }
 
     // Internal insertion helper function. Returns a vec of collected ids and a possible exception.
+    //
+    // Documents are split into as many insert commands as the server's write limits require.
+    // Ids are only returned for documents that were sent; ordered inserts stop sending after
+    // the first command reporting write errors or failing. Unordered inserts send every
+    // command, reporting a write error for each document of a failed one, and only fail
+    // outright if every command did.
     fn insert(
         &self,
         docs: Vec<bson::Document>,
@@ -628,48 +1370,185 @@ impl Collection {
         write_concern: Option<WriteConcern>,
         cmd_type: CommandType,
     ) -> Result<(Vec<Bson>, Option<BulkWriteException>)> {
+        self.check_not_view()?;
 
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
+        let limits = self.db.client.topology.description.read().recover().lowest_write_limits();
         let mut converted_docs = Vec::with_capacity(docs.len());
         let mut ids = Vec::with_capacity(docs.len());
+        let mut sizes = Vec::with_capacity(docs.len());
 
         for mut doc in docs {
             let id = match doc.get("_id").cloned() {
                 Some(id) => id,
                 None => {
-                    let id = oid::ObjectId::new()?;
+                    let id = self.db.client.object_ids.generate();
                     doc.insert("_id", id.clone());
                     Bson::ObjectId(id)
                 },
             };
+
             ids.push(id);
+            sizes.push(i64::from(doc.byte_length()?));
             converted_docs.push(Bson::Document(doc));
         }
 
-        let mut cmd = doc! {
-            "insert": self.name(),
-            "documents": converted_docs
-        };
+        batch::check_sizes("Document", &sizes, limits)?;
+
+        let ordered = options.as_ref().and_then(|opts| opts.ordered).unwrap_or(true);
+        let concurrency = options.as_ref().and_then(|opts| opts.max_concurrent_batches);
+        let mut remaining = converted_docs.into_iter();
+        let mut exception: Option<BulkWriteException> = None;
+        let mut failed_runs = Vec::new();
+        let mut sent = 0;
+
+        let runs = batch::split_by_limits(&sizes, limits);
+        let run_count = runs.len();
+
+        let mut commands = runs.iter().map(|run| {
+            let documents: Vec<_> = remaining.by_ref().take(run.len()).collect();
+
+            let cmd = doc! {
+                "insert": self.name(),
+                "documents": documents
+            };
+
+            match options {
+                Some(ref insert_options) => merge_options(cmd, insert_options.clone()),
+                None => cmd,
+            }
+        });
+
+        // Unordered commands may all be sent up front, their replies then being handled in
+        // order as if they had been sent one at a time.
+        let mut replies = match concurrency {
+            Some(concurrency) if !ordered && concurrency > 1 && run_count > 1 => {
+                self.send_concurrently(commands.by_ref().collect(), &cmd_type, concurrency)
+            }
+            _ => Vec::new(),
+        }.into_iter();
+
+        for run in runs.iter().cloned() {
+            let reply = match replies.next() {
+                Some(reply) => reply,
+                None => {
+                    let cmd = commands.next().expect("a command is built for every run");
+                    self.db.command(cmd, cmd_type.clone(), None)
+                }
+            };
+
+            let result = match reply {
+                Ok(result) => result,
+                Err(err) => {
+                    if ordered {
+                        return Err(err);
+                    }
+                    sent = run.end;
+                    failed_runs.push((run, err));
+                    continue;
+                }
+            };
+            sent = run.end;
+
+            // Intercept bulk write exceptions and insert into the result
+            let exception_res = BulkWriteException::validate_bulk_write_result(result, wc.clone());
+            let mut run_exception = match exception_res {
+                Ok(()) => continue,
+                Err(BulkWriteError(err)) => err,
+                Err(e) => return Err(e),
+            };
+
+            // Report errors against the document's position in the input.
+            for error in &mut run_exception.write_errors {
+                error.index += run.start as i32;
+            }
 
-        if let Some(insert_options) = options {
-            cmd = merge_options(cmd, insert_options);
+            let failed = !run_exception.write_errors.is_empty();
+
+            match exception {
+                Some(ref mut exc) => {
+                    exc.add_bulk_write_exception(Some(run_exception), Vec::new());
+                }
+                None => exception = Some(run_exception),
+            }
+
+            if failed && ordered {
+                break;
+            }
         }
 
-        let result = self.db.command(cmd, cmd_type, None)?;
+        if !failed_runs.is_empty() {
+            if failed_runs.len() == run_count {
+                return Err(failed_runs.swap_remove(0).1);
+            }
 
-        // Intercept bulk write exceptions and insert into the result
-        let exception_res = BulkWriteException::validate_bulk_write_result(result.clone(), wc);
-        let exception = match exception_res {
-            Ok(()) => None,
-            Err(BulkWriteError(err)) => Some(err),
-            Err(e) => return Err(e),
-        };
+            let write_errors = failed_runs
+                .into_iter()
+                .flat_map(|(run, err)| {
+                    let code = err.code().map_or(0, |code| code.code());
+                    let message = err.to_string();
+                    run.map(move |index| error::BulkWriteError {
+                        index: index as i32,
+                        code: code,
+                        code_name: None,
+                        message: message.clone(),
+                        details: None,
+                        request: None,
+                    })
+                })
+                .collect();
+
+            let run_exception = BulkWriteException::new(Vec::new(), Vec::new(), write_errors, None);
+            let exc = exception.get_or_insert_with(|| {
+                BulkWriteException::new(Vec::new(), Vec::new(), Vec::new(), None)
+            });
+            exc.add_bulk_write_exception(Some(run_exception), Vec::new());
+            exc.write_errors.sort_by_key(|error| error.index);
+        }
 
+        ids.truncate(sent);
         Ok((ids, exception))
     }
 
-    /// Inserts the provided document. If the document is missing an identifier,
-    /// the driver should generate one.
+    // Sends `commands` over up to `concurrency` pooled connections at once, returning their
+    // replies in the order of the commands.
+    fn send_concurrently(
+        &self,
+        commands: Vec<bson::Document>,
+        cmd_type: &CommandType,
+        concurrency: usize,
+    ) -> Vec<Result<bson::Document>> {
+        let count = commands.len();
+        let queue = Mutex::new(commands.into_iter().enumerate());
+        let replies = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+
+        thread::scope(|scope| {
+            for _ in 0..concurrency.min(count) {
+                scope.spawn(|| loop {
+                    let next = queue.lock().recover().next();
+                    let (index, cmd) = match next {
+                        Some(next) => next,
+                        None => return,
+                    };
+
+                    let reply = self.db.command(cmd, cmd_type.clone(), None);
+                    replies.lock().recover()[index] = Some(reply);
+                });
+            }
+        });
+
+        // The scope only ends once every command was taken and its reply stored.
+        replies
+            .into_inner()
+            .recover()
+            .into_iter()
+            .map(|reply| reply.expect("every command is sent before the scope ends"))
+            .collect()
+    }
+
+    /// Inserts the provided document. If the document is missing an identifier, the driver
+    /// generates one with the client's `ObjectIdGenerator`; either way, the document's id is
+    /// returned as `inserted_id` unless the write failed.
     pub fn insert_one(
         &self,
         doc: bson::Document,
@@ -693,27 +1572,267 @@ impl Collection {
             ));
         }
 
-        // Downgrade bulk exception, if it exists.
-        let exception = match bulk_exception {
-            Some(e) => Some(WriteException::with_bulk_exception(e)),
-            None => None,
-        };
+        Ok(Collection::insert_one_result(ids[0].to_owned(), bulk_exception))
+    }
+
+    // Downgrades the bulk exception of a single insert, dropping the id if the write failed.
+    fn insert_one_result(id: Bson, bulk_exception: Option<BulkWriteException>) -> InsertOneResult {
+        let exception = bulk_exception.map(WriteException::with_bulk_exception);
 
         let id = match exception {
-            Some(ref exc) => {
-                match exc.write_error {
-                    Some(_) => None,
-                    None => Some(ids[0].to_owned()),
+            Some(ref exc) if exc.write_error.is_some() => None,
+            _ => Some(id),
+        };
+
+        InsertOneResult::new(id, exception)
+    }
+
+    /// Inserts the provided document with `binary` appended as its last field.
+    ///
+    /// The binary contents are copied from their source directly onto the connection.
+    /// If the document is missing an identifier, the driver generates one. Interceptors and
+    /// command monitoring see the document without the streamed field. Collections the client
+    /// encrypts fields of are refused, as the streamed field would be stored unencrypted.
+    pub fn insert_one_streamed<R: Read>(
+        &self,
+        mut doc: bson::Document,
+        binary: StreamedBinary<R>,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<InsertOneResult> {
+        if doc.contains_key(&binary.key) {
+            return Err(ArgumentError(format!(
+                "The document already contains the streamed field '{}'.",
+                binary.key
+            )));
+        }
+
+        self.check_not_view()?;
+
+        if self.db.client.auto_encrypts(&self.namespace) {
+            return Err(ArgumentError(format!(
+                "Documents cannot be streamed into {}, which has encrypted fields.",
+                self.namespace
+            )));
+        }
+
+        let id = match doc.get("_id").cloned() {
+            Some(id) => id,
+            None => {
+                let id = self.db.client.object_ids.generate();
+                doc.insert("_id", id.clone());
+                Bson::ObjectId(id)
+            }
+        };
+
+        // type tag, key cstring, length, subtype, payload
+        let binary_length = 1 + binary.key.len() + 1 + 4 + 1 + binary.length;
+        let size = i64::from(doc.byte_length()?) + binary_length as i64;
+        let limits = self.db.client.topology.description.read().recover().lowest_write_limits();
+        batch::check_sizes("Document", &[size], limits)?;
+
+        let mut cmd = doc! {
+            "insert": self.name(),
+            "ordered": true,
+        };
+
+        if let Some(ref concern) = write_concern {
+            cmd.insert("writeConcern", concern.to_bson());
+        }
+
+        let mut stream = self.db.client.acquire_write_stream()?;
+        let result = self.send_streamed_insert(&mut stream, cmd, doc, binary)?;
+
+        let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
+        let bulk_exception = match BulkWriteException::validate_bulk_write_result(result, wc) {
+            Ok(()) => None,
+            Err(BulkWriteError(err)) => Some(err),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Collection::insert_one_result(id, bulk_exception))
+    }
+
+    // Sends a streamed insert through the interceptors and command monitoring like any other
+    // insert_one, and returns its reply. Both see the document without the streamed field.
+    fn send_streamed_insert<R: Read>(
+        &self,
+        stream: &mut PooledStream,
+        cmd: bson::Document,
+        doc: bson::Document,
+        binary: StreamedBinary<R>,
+    ) -> Result<bson::Document> {
+        let client = &self.db.client;
+        let cmd_name = CommandType::InsertOne.to_str();
+        let namespace = format!("{}.$cmd", self.db.name);
+        let req_id = self.get_req_id();
+        let connstring = stream.get_socket().get_ref().peer_addr()?.to_string();
+
+        let context = CommandContext {
+            command_name: cmd_name,
+            database_name: &self.db.name,
+            namespace: &namespace,
+            connection_string: &connstring,
+            request_id: req_id,
+        };
+
+        let mut command = cursor::with_server_api(cmd, client.server_api.as_ref());
+        command.insert("documents", vec![Bson::Document(doc)]);
+        client.interceptors.intercept_command(&context, &mut command)?;
+
+        let hook_result = client.run_start_hooks(&CommandStarted {
+            command: command.clone(),
+            database_name: self.db.name.clone(),
+            command_name: String::from(cmd_name),
+            request_id: i64::from(req_id),
+            connection_string: connstring.clone(),
+        });
+
+        if hook_result.is_err() {
+            return Err(Error::EventListenerError(None));
+        }
+
+        let mut documents = match command.remove("documents") {
+            Some(Bson::Array(documents)) => documents,
+            _ => Vec::new(),
+        };
+
+        let doc = match documents.pop() {
+            Some(Bson::Document(doc)) if documents.is_empty() => doc,
+            _ => {
+                return Err(ArgumentError(String::from(
+                    "Interceptors must leave a streamed insert with a single document.",
+                )))
+            }
+        };
+
+        let started = Instant::now();
+        let written = streamed::write_insert_command(
+            stream.get_socket(),
+            req_id,
+            &namespace,
+            &command,
+            &doc,
+            binary,
+        );
+
+        let reply = match written {
+            Ok(()) => stream.reader().and_then(|reader| Message::read(reader)),
+            Err(err) => {
+                stream.discard();
+                Err(err)
+            }
+        };
+
+        let result = reply.and_then(|mut reply| {
+            client.interceptors.intercept_reply(&context, &mut reply)?;
+            let (result, _, _) = Cursor::get_bson_and_cid_from_message(reply)?;
+            Ok(result)
+        });
+
+        let elapsed = started.elapsed();
+        let duration = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+
+        match result {
+            Ok(result) => {
+                let _hook_result = client.run_completion_hooks(&CommandResult::Success {
+                    duration: duration,
+                    reply: result.clone(),
+                    command_name: String::from(cmd_name),
+                    request_id: i64::from(req_id),
+                    connection_string: connstring,
+                });
+                Ok(result)
+            }
+            Err(err) => {
+                let hook_result = client.run_completion_hooks(&CommandResult::Failure {
+                    duration: duration,
+                    command_name: String::from(cmd_name),
+                    failure: &err,
+                    request_id: i64::from(req_id),
+                    connection_string: connstring,
+                });
+
+                if hook_result.is_err() {
+                    return Err(Error::EventListenerError(Some(Box::new(err))));
                 }
+                Err(err)
             }
-            None => Some(ids[0].to_owned()),
+        }
+    }
+
+    /// Inserts documents that are already encoded as BSON, sending their bytes as they are.
+    ///
+    /// This skips the encoding and identifier generation of `insert_many`, so the caller
+    /// must make sure that every document is valid BSON with an `_id` field. Nothing is sent
+    /// if a document is larger than the servers accept or the documents do not fit in a
+    /// single command. The result carries no inserted ids. Collections the client encrypts
+    /// fields of are refused, as the documents would be stored unencrypted.
+    pub fn insert_many_unchecked<D: AsRef<[u8]>>(
+        &self,
+        docs: &[D],
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        if docs.is_empty() {
+            return Err(ArgumentError(String::from("No documents were given to insert.")));
+        }
+
+        self.check_not_view()?;
+
+        if self.db.client.auto_encrypts(&self.namespace) {
+            return Err(ArgumentError(format!(
+                "Documents cannot be inserted unchecked into {}, which has encrypted fields.",
+                self.namespace
+            )));
+        }
+
+        let limits = self.db.client.topology.description.read().recover().lowest_write_limits();
+        let sizes: Vec<_> = docs.iter().map(|doc| doc.as_ref().len() as i64).collect();
+        batch::check_sizes("Document", &sizes, limits)?;
+        if batch::split_by_limits(&sizes, limits).len() > 1 {
+            return Err(ArgumentError(format!(
+                "The {} documents do not fit in a single insert command.",
+                docs.len()
+            )));
+        }
+
+        let options = options.unwrap_or_default();
+        let wc = options.write_concern.clone().unwrap_or_else(|| self.write_concern.clone());
+
+        let cmd = merge_options(doc! { "insert": self.name() }, options);
+        let cmd = cursor::with_server_api(cmd, self.db.client.server_api.as_ref());
+
+        let namespace = format!("{}.$cmd", self.db.name);
+        let req_id = self.get_req_id();
+        let mut stream = self.db.client.acquire_write_stream()?;
+
+        let written = streamed::write_raw_insert_command(
+            &mut stream.writer(),
+            req_id,
+            &namespace,
+            &cmd,
+            docs,
+        );
+
+        if let Err(err) = written {
+            stream.discard();
+            return Err(err);
+        }
+
+        let reply = Message::read(stream.reader()?)?;
+        let (result, _, _) = Cursor::get_bson_and_cid_from_message(reply)?;
+
+        let exception = match BulkWriteException::validate_bulk_write_result(result, wc) {
+            Ok(()) => None,
+            Err(BulkWriteError(err)) => Some(err),
+            Err(e) => return Err(e),
         };
 
-        Ok(InsertOneResult::new(id, exception))
+        Ok(InsertManyResult::new(None, exception))
     }
 
-    /// Inserts the provided documents. If any documents are missing an identifier,
-    /// the driver should generate them.
+    /// Inserts the provided documents. If any documents are missing an identifier, the
+    /// driver generates them with the client's `ObjectIdGenerator`. The ids of the documents
+    /// written are returned as `inserted_ids`, by their position in `docs`.
     pub fn insert_many(
         &self,
         docs: Vec<bson::Document>,
@@ -743,30 +1862,96 @@ impl Collection {
         Ok(InsertManyResult::new(Some(map), exception))
     }
 
+    /// Inserts measurements into a time-series collection.
+    ///
+    /// Measurements are grouped by their meta field value and time window, and each group is
+    /// sent as a single insert so the server can fill one bucket per command. Unless
+    /// `ordered` is false, only consecutive measurements are grouped, so they are inserted in
+    /// their input order, and groups after one that reports write errors are not sent.
+    pub fn insert_time_series(
+        &self,
+        docs: Vec<bson::Document>,
+        options: TimeSeriesInsertOptions,
+    ) -> Result<InsertManyResult> {
+        let groups = time_series::group_measurements(docs, &options)?;
+        let ordered = options.ordered.unwrap_or(true);
+        let insert_options = InsertManyOptions {
+            ordered: options.ordered,
+            comment: options.comment,
+            write_concern: options.write_concern,
+            ..Default::default()
+        };
+
+        let mut ids = BTreeMap::new();
+        let mut exception: Option<BulkWriteException> = None;
+
+        for group in groups {
+            let result = self.insert_many(group.docs, Some(insert_options.clone()))?;
+
+            for (index, id) in result.inserted_ids.unwrap_or_default() {
+                ids.insert(group.indices[index as usize], id);
+            }
+
+            let mut group_exception = match result.bulk_write_exception {
+                Some(group_exception) => group_exception,
+                None => continue,
+            };
+
+            // Report errors against the measurement's position in the input.
+            for error in &mut group_exception.write_errors {
+                error.index = group.indices[error.index as usize] as i32;
+            }
+
+            let failed = !group_exception.write_errors.is_empty();
+
+            match exception {
+                Some(ref mut exc) => {
+                    exc.add_bulk_write_exception(Some(group_exception), Vec::new());
+                }
+                None => exception = Some(group_exception),
+            }
+
+            if failed && ordered {
+                break;
+            }
+        }
+
+        Ok(InsertManyResult::new(Some(ids), exception))
+    }
+
     // Sends a batch of delete ops to the server at once.
     fn bulk_delete(
         &self,
         models: Vec<DeleteModel>,
         ordered: bool,
         write_concern: Option<WriteConcern>,
+        command_options: WriteCommandOptions,
         cmd_type: CommandType,
     ) -> Result<BulkDeleteResult> {
+        self.check_not_view()?;
 
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
+        let limits = self.db.client.topology.description.read().recover().lowest_write_limits();
+        let sizes = models
+            .iter()
+            .map(|model| model.filter.byte_length().map(i64::from))
+            .collect::<Result<Vec<_>>>()?;
+        batch::check_sizes("Delete filter", &sizes, limits)?;
+
         let deletes: Vec<_> = models
             .into_iter()
-            .map(|model| bson!({
-                "q": model.filter,
-                "limit": if model.multi { 0_i64 } else { 1_i64 },
-            }))
+            .map(|model| Bson::Document(bson::Document::from(model)))
             .collect();
 
-        let cmd = doc! {
+        let mut cmd = doc! {
             "delete": self.name(),
             "deletes": deletes,
             "ordered": ordered,
             "writeConcern": wc.to_bson(),
         };
+
+        cmd = merge_options(cmd, command_options);
+
         let result = self.db.command(cmd, cmd_type, None)?;
 
         // Intercept write exceptions and insert into the result
@@ -785,18 +1970,34 @@ impl Collection {
         &self,
         filter: bson::Document,
         multi: bool,
-        write_concern: Option<WriteConcern>,
+        options: Option<DeleteOptions>,
     ) -> Result<DeleteResult> {
+        let options = options.unwrap_or_default();
+
+        self.check_collation(options.collation.as_ref())?;
+
         let cmd_type = if multi {
             CommandType::DeleteMany
         } else {
             CommandType::DeleteOne
         };
 
+        let mut model = DeleteModel::new(filter, multi);
+        model.hint = hint_to_bson(options.hint, options.hint_doc);
+        model.collation = options.collation;
+
+        let command_options = WriteCommandOptions {
+            max_time_ms: options.max_time_ms,
+            comment: options.comment,
+            let_vars: options.let_vars,
+            ..Default::default()
+        };
+
         self.bulk_delete(
-            vec![DeleteModel::new(filter, multi)],
+            vec![model],
             true,
-            write_concern,
+            options.write_concern,
+            command_options,
             cmd_type,
         ).map(
             DeleteResult::with_bulk_result
@@ -809,7 +2010,17 @@ impl Collection {
         filter: bson::Document,
         write_concern: Option<WriteConcern>,
     ) -> Result<DeleteResult> {
-        self.delete(filter, false, write_concern)
+        let options = DeleteOptions { write_concern: write_concern, ..Default::default() };
+        self.delete(filter, false, Some(options))
+    }
+
+    /// Deletes a single document, with a hint, collation and other options.
+    pub fn delete_one_with_options(
+        &self,
+        filter: bson::Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        self.delete(filter, false, options)
     }
 
     /// Deletes multiple documents.
@@ -818,7 +2029,17 @@ impl Collection {
         filter: bson::Document,
         write_concern: Option<WriteConcern>,
     ) -> Result<DeleteResult> {
-        self.delete(filter, true, write_concern)
+        let options = DeleteOptions { write_concern: write_concern, ..Default::default() };
+        self.delete(filter, true, Some(options))
+    }
+
+    /// Deletes multiple documents, with a hint, collation and other options.
+    pub fn delete_many_with_options(
+        &self,
+        filter: bson::Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        self.delete(filter, true, options)
     }
 
     // Sends a batch of replace and update ops to the server at once.
@@ -827,21 +2048,33 @@ impl Collection {
         models: Vec<UpdateModel>,
         ordered: bool,
         write_concern: Option<WriteConcern>,
+        command_options: WriteCommandOptions,
         cmd_type: CommandType,
     ) -> Result<BulkUpdateResult> {
+        self.check_not_view()?;
+
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
+        let limits = self.db.client.topology.description.read().recover().lowest_write_limits();
+        let sizes = models
+            .iter()
+            .map(|model| model.update.byte_length().map(i64::from))
+            .collect::<Result<Vec<_>>>()?;
+        batch::check_sizes("Update document", &sizes, limits)?;
+
         let updates: Vec<_> = models
             .into_iter()
             .map(|model| Bson::Document(bson::Document::from(model)))
             .collect();
 
-        let cmd = doc! {
+        let mut cmd = doc! {
             "update": self.name(),
             "updates": updates,
             "ordered": ordered,
             "writeConcern": wc.to_bson()
         };
 
+        cmd = merge_options(cmd, command_options);
+
         let result = self.db.command(cmd, cmd_type, None)?;
 
         // Intercept write exceptions and insert into the result
@@ -860,10 +2093,10 @@ impl Collection {
         &self,
         filter: bson::Document,
         update: bson::Document,
-        upsert: Option<bool>,
         multi: bool,
-        write_concern: Option<WriteConcern>,
+        options: UpdateOptions,
     ) -> Result<UpdateResult> {
+        self.check_collation(options.collation.as_ref())?;
 
         let cmd_type = if multi {
             CommandType::UpdateMany
@@ -871,10 +2104,23 @@ impl Collection {
             CommandType::UpdateOne
         };
 
+        let mut model = UpdateModel::new(filter, update, options.upsert, multi);
+        model.array_filters = options.array_filters;
+        model.hint = hint_to_bson(options.hint, options.hint_doc);
+        model.collation = options.collation;
+
+        let command_options = WriteCommandOptions {
+            max_time_ms: options.max_time_ms,
+            bypass_document_validation: options.bypass_document_validation,
+            comment: options.comment,
+            let_vars: options.let_vars,
+        };
+
         self.bulk_update(
-            vec![UpdateModel::new(filter, update, upsert, multi)],
+            vec![model],
             true,
-            write_concern,
+            options.write_concern,
+            command_options,
             cmd_type,
         ).map(
             UpdateResult::with_bulk_result
@@ -888,17 +2134,16 @@ impl Collection {
         replacement: bson::Document,
         options: Option<ReplaceOptions>,
     ) -> Result<UpdateResult> {
-        let options = options.unwrap_or_default();
-
         Collection::validate_replace(&replacement)?;
 
-        self.update(
-            filter,
-            replacement,
-            options.upsert,
-            false,
-            options.write_concern,
-        )
+        let options = options.unwrap_or_default();
+        if options.array_filters.is_some() {
+            return Err(ArgumentError(
+                String::from("Replacement cannot use array filters."),
+            ));
+        }
+
+        self.update(filter, replacement, false, options)
     }
 
     /// Updates a single document.
@@ -908,17 +2153,8 @@ impl Collection {
         update: bson::Document,
         options: Option<UpdateOptions>,
     ) -> Result<UpdateResult> {
-        let options = options.unwrap_or_default();
-
         Collection::validate_update(&update)?;
-
-        self.update(
-            filter,
-            update,
-            options.upsert,
-            false,
-            options.write_concern
-        )
+        self.update(filter, update, false, options.unwrap_or_default())
     }
 
     /// Updates multiple documents.
@@ -928,17 +2164,8 @@ impl Collection {
         update: bson::Document,
         options: Option<UpdateOptions>,
     ) -> Result<UpdateResult> {
-        let options = options.unwrap_or_default();
-
         Collection::validate_update(&update)?;
-
-        self.update(
-            filter,
-            update,
-            options.upsert,
-            true,
-            options.write_concern
-        )
+        self.update(filter, update, true, options.unwrap_or_default())
     }
 
     fn validate_replace(replacement: &bson::Document) -> Result<()> {
@@ -979,25 +2206,69 @@ impl Collection {
         Ok(result.swap_remove(0))
     }
 
-    /// Create multiple indexes.
+    /// Create multiple indexes, acknowledged with the collection's write concern.
     pub fn create_indexes(&self, models: Vec<IndexModel>) -> Result<Vec<String>> {
+        self.create_indexes_with_write_concern(models, None)
+    }
+
+    /// Create multiple indexes with a single `createIndexes` command, acknowledged with the
+    /// given write concern or the collection's.
+    pub fn create_indexes_with_write_concern(
+        &self,
+        models: Vec<IndexModel>,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<Vec<String>> {
+        let options = CreateIndexOptions {
+            write_concern: write_concern,
+            ..CreateIndexOptions::new()
+        };
+
+        self.create_indexes_with_options(models, Some(options))
+    }
+
+    /// Create multiple indexes with a single `createIndexes` command.
+    ///
+    /// A commit quorum is rejected before anything is sent if a server is older than 4.4.
+    pub fn create_indexes_with_options(
+        &self,
+        models: Vec<IndexModel>,
+        options: Option<CreateIndexOptions>,
+    ) -> Result<Vec<String>> {
+        let options = options.unwrap_or_default();
+        let write_concern = options.write_concern.unwrap_or(self.write_concern);
+
+        if options.commit_quorum.is_some() {
+            self.check_commit_quorum()?;
+        }
+
         let mut names = Vec::with_capacity(models.len());
         let mut indexes = Vec::with_capacity(models.len());
 
         for model in models {
+            self.check_collation(model.options.collation.as_ref())?;
             names.push(model.name()?);
             indexes.push(Bson::Document(model.to_bson()?));
         }
 
-        let cmd = doc! {
+        let spec = doc! {
             "createIndexes": self.name(),
             "indexes": indexes,
         };
+        let mut cmd = merge_options(spec, options);
+
+        // createIndexes accepts a writeConcern from the same server version as aggregate.
+        if self.supports_aggregate_write_concern()? {
+            cmd.insert("writeConcern", write_concern.to_bson());
+        }
+
         let mut result = self.db.command(cmd, CommandType::CreateIndexes, None)?;
 
         match result.remove("errmsg") {
             Some(Bson::String(msg)) => Err(OperationError(msg)),
-            _ => Ok(names),
+            _ => {
+                WriteException::validate_write_result(result, write_concern)?;
+                Ok(names)
+            }
         }
     }
 
@@ -1061,4 +2332,10 @@ impl Collection {
             })
         })
     }
+
+    /// List all indexes in the collection as `IndexDescription`s, which keep the name and
+    /// version the server reports and can be compared against the desired `IndexModel`s.
+    pub fn list_index_descriptions(&self) -> Result<TypedCursor<IndexDescription>> {
+        self.list_indexes().map(TypedCursor::new)
+    }
 }
@@ -1,14 +1,27 @@
 //! Options for collection-level operations.
 use bson::{self, bson, Bson, doc};
-use common::{ReadPreference, WriteConcern};
+use common::{ReadConcern, ReadPreference, WriteConcern};
 use Error::ArgumentError;
 use Result;
 
+// Index hints can name an index or repeat its key pattern; the key pattern wins if both are set.
+pub(crate) fn hint_to_bson(hint: Option<String>, hint_doc: Option<bson::Document>) -> Option<Bson> {
+    match (hint, hint_doc) {
+        (_, Some(hint_doc)) => Some(Bson::Document(hint_doc)),
+        (Some(hint), None) => Some(Bson::String(hint)),
+        (None, None) => None,
+    }
+}
+
 /// Describes the type of cursor to return on collection queries.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CursorType {
     NonTailable,
+    /// Stays open after returning the last document of a capped collection, so documents
+    /// inserted later are returned too.
     Tailable,
+    /// A tailable cursor whose getMores wait for new documents on the server for a while
+    /// before returning an empty batch.
     TailableAwait,
 }
 
@@ -34,6 +47,42 @@ impl ReturnDocument {
     }
 }
 
+/// Where the results of a mapReduce are written.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapReduceOutput {
+    /// Returns the results with the reply, which limits them to the maximum document size.
+    Inline,
+    /// Replaces the contents of the named collection with the results.
+    Replace(String),
+    /// Adds the results to the named collection, replacing documents with the same key.
+    Merge(String),
+    /// Adds the results to the named collection, reducing them with documents of the same key.
+    Reduce(String),
+}
+
+impl Default for MapReduceOutput {
+    fn default() -> Self {
+        MapReduceOutput::Inline
+    }
+}
+
+impl MapReduceOutput {
+    fn to_bson(&self, db: Option<String>) -> bson::Document {
+        let mut document = match *self {
+            MapReduceOutput::Inline => return doc! { "inline": 1 },
+            MapReduceOutput::Replace(ref name) => doc! { "replace": name.clone() },
+            MapReduceOutput::Merge(ref name) => doc! { "merge": name.clone() },
+            MapReduceOutput::Reduce(ref name) => doc! { "reduce": name.clone() },
+        };
+
+        if let Some(db) = db {
+            document.insert("db", db);
+        }
+
+        document
+    }
+}
+
 /// Marker interface for writes that can be batched together.
 #[derive(Debug, Clone, PartialEq)]
 pub enum WriteModel {
@@ -57,14 +106,109 @@ pub enum WriteModel {
     },
 }
 
+/// Language-specific rules for string comparison, such as letter case and accent marks.
+///
+/// Collations require MongoDB 3.4 or newer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Collation {
+    pub locale: String,
+
+    #[serde(rename="caseLevel", skip_serializing_if="Option::is_none")]
+    pub case_level: Option<bool>,
+
+    #[serde(rename="caseFirst", skip_serializing_if="Option::is_none")]
+    pub case_first: Option<String>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub strength: Option<i32>,
+
+    #[serde(rename="numericOrdering", skip_serializing_if="Option::is_none")]
+    pub numeric_ordering: Option<bool>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub alternate: Option<String>,
+
+    #[serde(rename="maxVariable", skip_serializing_if="Option::is_none")]
+    pub max_variable: Option<String>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub normalization: Option<bool>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub backwards: Option<bool>,
+}
+
+impl Collation {
+    /// Creates a collation for the given ICU locale, such as "en_US" or "fr_CA", with the
+    /// server defaults for every other setting.
+    pub fn new<S: Into<String>>(locale: S) -> Collation {
+        Collation {
+            locale: locale.into(),
+            case_level: None,
+            case_first: None,
+            strength: None,
+            numeric_ordering: None,
+            alternate: None,
+            max_variable: None,
+            normalization: None,
+            backwards: None,
+        }
+    }
+}
+
+impl From<Collation> for bson::Document {
+    fn from(collation: Collation) -> Self {
+        match bson::to_bson(&collation) {
+            Ok(Bson::Document(document)) => document,
+            _ => unreachable!("Collations always serialize to a document."),
+        }
+    }
+}
+
+/// Options for bulk write operations.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BulkWriteOptions {
+    /// Whether the writes must be applied in order, stopping at the first failure.
+    /// Defaults to `true`.
+    pub ordered: Option<bool>,
+    /// The time limit for each batch the writes are split into.
+    pub max_time_ms: Option<i64>,
+    /// Whether inserts and updates may write documents that fail the collection's validator.
+    pub bypass_document_validation: Option<bool>,
+    pub comment: Option<String>,
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl BulkWriteOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
 /// Options for aggregation queries.
-#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct AggregateOptions {
+    /// Whether stages such as `$sort` and `$group` may spill to temporary files once they
+    /// exceed the server's memory limit for a stage.
     pub allow_disk_use: Option<bool>,
+    /// Whether a final `$out` or `$merge` stage may write documents that fail the target
+    /// collection's validator.
+    pub bypass_document_validation: Option<bool>,
     pub use_cursor: Option<bool>,
     pub batch_size: i32,
     pub max_time_ms: Option<i64>,
+    /// How long getMores on a change stream wait for new events; only valid for pipelines
+    /// starting with `$changeStream`. Unlike `max_time_ms`, it does not bound the aggregate.
+    pub max_await_time_ms: Option<i64>,
+    pub hint: Option<String>,
+    pub hint_doc: Option<bson::Document>,
+    pub collation: Option<Collation>,
+    pub comment: Option<String>,
+    /// Variables the pipeline can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
+    pub read_concern: Option<ReadConcern>,
     pub read_preference: Option<ReadPreference>,
+    pub write_concern: Option<WriteConcern>,
 }
 
 impl AggregateOptions {
@@ -81,6 +225,10 @@ impl From<AggregateOptions> for bson::Document {
             document.insert("allowDiskUse", allow_disk_use);
         }
 
+        if let Some(bypass_document_validation) = options.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass_document_validation);
+        }
+
         let cursor = if let Some(false) = options.use_cursor {
            doc! {}
         } else {
@@ -89,9 +237,113 @@ impl From<AggregateOptions> for bson::Document {
 
         document.insert("cursor", cursor);
 
-        // maxTimeMS is not currently used by the driver.
+        if let Some(hint) = hint_to_bson(options.hint, options.hint_doc) {
+            document.insert("hint", hint);
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(read_concern) = options.read_concern {
+            document.insert("readConcern", read_concern.to_bson());
+        }
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(let_vars) = options.let_vars {
+            document.insert("let", let_vars);
+        }
+
+        // read_preference and write_concern are used directly by Collection::aggregate, and
+        // max_await_time_ms is sent with the cursor's getMores.
+
+        document
+    }
+}
+
+/// Options for mapReduce operations.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MapReduceOptions {
+    pub out: MapReduceOutput,
+    /// The database of the output collection, if not the collection's own.
+    pub out_db: Option<String>,
+    /// Selects the documents passed to the map function.
+    pub query: Option<bson::Document>,
+    pub sort: Option<bson::Document>,
+    pub limit: Option<i64>,
+    /// A JavaScript function applied to each reduced value.
+    pub finalize: Option<String>,
+    /// Global variables available to the map, reduce and finalize functions.
+    pub scope: Option<bson::Document>,
+    pub js_mode: Option<bool>,
+    pub verbose: Option<bool>,
+    pub bypass_document_validation: Option<bool>,
+    pub collation: Option<Collation>,
+    pub max_time_ms: Option<i64>,
+    pub read_preference: Option<ReadPreference>,
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl MapReduceOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl From<MapReduceOptions> for bson::Document {
+    fn from(options: MapReduceOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        document.insert("out", options.out.to_bson(options.out_db));
+
+        if let Some(query) = options.query {
+            document.insert("query", query);
+        }
+
+        if let Some(sort) = options.sort {
+            document.insert("sort", sort);
+        }
+
+        if let Some(limit) = options.limit {
+            document.insert("limit", limit);
+        }
 
-        // read_preference is used directly by Collection::aggregate.
+        if let Some(finalize) = options.finalize {
+            document.insert("finalize", Bson::JavaScriptCode(finalize));
+        }
+
+        if let Some(scope) = options.scope {
+            document.insert("scope", scope);
+        }
+
+        if let Some(js_mode) = options.js_mode {
+            document.insert("jsMode", js_mode);
+        }
+
+        if let Some(verbose) = options.verbose {
+            document.insert("verbose", verbose);
+        }
+
+        if let Some(bypass_document_validation) = options.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass_document_validation);
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        // read_preference and write_concern are used directly by Collection::map_reduce.
 
         document
     }
@@ -105,6 +357,8 @@ pub struct CountOptions {
     pub hint: Option<String>,
     pub hint_doc: Option<bson::Document>,
     pub max_time_ms: Option<i64>,
+    pub collation: Option<Collation>,
+    pub comment: Option<String>,
     pub read_preference: Option<ReadPreference>,
 }
 
@@ -126,15 +380,21 @@ impl From<CountOptions> for bson::Document {
             document.insert("limit", limit);
         }
 
-        if let Some(hint) = options.hint {
+        if let Some(hint) = hint_to_bson(options.hint, options.hint_doc) {
             document.insert("hint", hint);
         }
 
-        if let Some(hint_doc) = options.hint_doc {
-            document.insert("hint_doc", hint_doc);
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
         }
 
-        // maxTimeMS is not currently used by the driver.
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
 
         // read_preference is used directly by Collection::count.
 
@@ -146,6 +406,9 @@ impl From<CountOptions> for bson::Document {
 #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DistinctOptions {
     pub max_time_ms: Option<i64>,
+    pub collation: Option<Collation>,
+    pub comment: Option<String>,
+    pub read_concern: Option<ReadConcern>,
     pub read_preference: Option<ReadPreference>,
 }
 
@@ -155,10 +418,40 @@ impl DistinctOptions {
     }
 }
 
+impl From<DistinctOptions> for bson::Document {
+    fn from(options: DistinctOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(read_concern) = options.read_concern {
+            document.insert("readConcern", read_concern.to_bson());
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        // read_preference is used directly by Collection::distinct.
+
+        document
+    }
+}
+
 /// Options for collection queries.
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct FindOptions {
+    /// Lets queries against a sharded cluster return the results of the available shards
+    /// when some are down, rather than failing.
     pub allow_partial_results: bool,
+    /// Keeps the server from closing the cursor after it has been idle for a while, 10
+    /// minutes by default. Such cursors should be exhausted or dropped, so they are closed.
     pub no_cursor_timeout: bool,
     pub oplog_replay: bool,
     pub skip: Option<i64>,
@@ -167,10 +460,21 @@ pub struct FindOptions {
     pub batch_size: Option<i32>,
     pub comment: Option<String>,
     pub max_time_ms: Option<i64>,
+    /// How long getMores on a `TailableAwait` cursor wait for new documents. Finds setting it
+    /// are sent as a `find` command; otherwise the server decides, usually waiting a second.
+    pub max_await_time_ms: Option<i64>,
     pub modifiers: Option<bson::Document>,
     pub projection: Option<bson::Document>,
     pub sort: Option<bson::Document>,
+    pub hint: Option<String>,
+    pub hint_doc: Option<bson::Document>,
+    pub collation: Option<Collation>,
+    /// Variables the filter can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
     pub read_preference: Option<ReadPreference>,
+    /// How many of the documents buffered by the cursor are kept decoded at once; see
+    /// `Cursor::set_max_decoded_documents`. Unlimited by default.
+    pub max_decoded_documents: Option<usize>,
 }
 
 impl FindOptions {
@@ -184,12 +488,30 @@ impl From<FindOptions> for bson::Document {
     fn from(options: FindOptions) -> Self {
         let mut document = bson::Document::new();
 
-        // `allow_partial_results`, `no_cursor_timeout`, `oplog_relay`, and `cursor_type` are used by
-        // wire_protocol::OpQueryFlags.
+        // `oplog_relay` is used by wire_protocol::OpQueryFlags. `allow_partial_results`,
+        // `no_cursor_timeout` and `cursor_type` are too, but are also needed by finds sent as
+        // a `find` command. `max_await_time_ms` is sent with the cursor's getMores.
         //
-        // `max_time_ms` and `modifiers` are not currently used by the driver.
+        // `modifiers` is not currently used by the driver.
         //
-        // read_preference is used directly by Collection::find_with_command_type.
+        // read_preference and max_decoded_documents are used directly by
+        // Collection::find_with_command_type.
+
+        if options.allow_partial_results {
+            document.insert("allowPartialResults", true);
+        }
+
+        if options.no_cursor_timeout {
+            document.insert("noCursorTimeout", true);
+        }
+
+        if options.cursor_type != CursorType::NonTailable {
+            document.insert("tailable", true);
+        }
+
+        if options.cursor_type == CursorType::TailableAwait {
+            document.insert("awaitData", true);
+        }
 
         if let Some(projection) = options.projection {
             document.insert("projection", projection);
@@ -211,6 +533,26 @@ impl From<FindOptions> for bson::Document {
             document.insert("sort", sort);
         }
 
+        if let Some(hint) = hint_to_bson(options.hint, options.hint_doc) {
+            document.insert("hint", hint);
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(let_vars) = options.let_vars {
+            document.insert("let", let_vars);
+        }
+
         document
     }
 }
@@ -221,6 +563,10 @@ pub struct FindOneAndDeleteOptions {
     pub max_time_ms: Option<i64>,
     pub projection: Option<bson::Document>,
     pub sort: Option<bson::Document>,
+    pub collation: Option<Collation>,
+    pub comment: Option<String>,
+    /// Variables the filter can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
     pub write_concern: Option<WriteConcern>,
 }
 
@@ -234,7 +580,9 @@ impl From<FindOneAndDeleteOptions> for bson::Document {
     fn from(options: FindOneAndDeleteOptions) -> Self {
         let mut document = bson::Document::new();
 
-        // max_time_ms is not currently used by the driver
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
 
         if let Some(projection) = options.projection {
             document.insert("fields", projection);
@@ -244,6 +592,18 @@ impl From<FindOneAndDeleteOptions> for bson::Document {
             document.insert("sort", sort);
         }
 
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(let_vars) = options.let_vars {
+            document.insert("let", let_vars);
+        }
+
         if let Some(write_concern) = options.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
@@ -260,6 +620,12 @@ pub struct FindOneAndUpdateOptions {
     pub projection: Option<bson::Document>,
     pub sort: Option<bson::Document>,
     pub upsert: Option<bool>,
+    pub array_filters: Option<Vec<bson::Document>>,
+    pub bypass_document_validation: Option<bool>,
+    pub collation: Option<Collation>,
+    pub comment: Option<String>,
+    /// Variables the filter and update can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
     pub write_concern: Option<WriteConcern>,
 }
 
@@ -277,7 +643,9 @@ impl From<FindOneAndUpdateOptions> for bson::Document {
             document.insert("new", return_document.as_bool());
         }
 
-        // max_time_ms is not currently used by the driver
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
 
         if let Some(projection) = options.projection {
             document.insert("fields", projection);
@@ -291,6 +659,27 @@ impl From<FindOneAndUpdateOptions> for bson::Document {
             document.insert("upsert", upsert);
         }
 
+        if let Some(array_filters) = options.array_filters {
+            let filters: Vec<_> = array_filters.into_iter().map(Bson::Document).collect();
+            document.insert("arrayFilters", filters);
+        }
+
+        if let Some(bypass_document_validation) = options.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass_document_validation);
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", bson::Document::from(collation));
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(let_vars) = options.let_vars {
+            document.insert("let", let_vars);
+        }
+
         if let Some(write_concern) = options.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
@@ -323,9 +712,16 @@ pub struct IndexOptions {
     #[serde(skip_serializing_if="Option::is_none")]
     pub unique: Option<bool>,
 
+    /// Whether the query planner ignores the index while it is still maintained (MongoDB 4.4+).
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub hidden: Option<bool>,
+
     #[serde(rename="v", skip_serializing_if="Option::is_none")]
     pub version: Option<i32>,
 
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub collation: Option<Collation>,
+
     // Options for text indexes
     #[serde(skip_serializing_if="Option::is_none")]
     pub default_language: Option<String>,
@@ -356,6 +752,11 @@ pub struct IndexOptions {
     // Options for geoHaystack indexes
     #[serde(rename="bucketSize", skip_serializing_if="Option::is_none")]
     pub bucket_size: Option<i32>,
+
+    // Options for wildcard indexes
+    /// The fields a `$**` index includes or excludes (MongoDB 4.2+).
+    #[serde(rename="wildcardProjection", skip_serializing_if="Option::is_none")]
+    pub wildcard_projection: Option<bson::Document>,
 }
 
 impl IndexOptions {
@@ -445,9 +846,15 @@ impl IndexModel {
         if let Some(val) = self.options.unique {
             doc.insert("unique", val);
         }
+        if let Some(val) = self.options.hidden {
+            doc.insert("hidden", val);
+        }
         if let Some(val) = self.options.version {
             doc.insert("v", val);
         }
+        if let Some(ref val) = self.options.collation {
+            doc.insert("collation", bson::Document::from(val.clone()));
+        }
         if let Some(ref val) = self.options.default_language {
             doc.insert("default_language", val);
         }
@@ -475,16 +882,153 @@ impl IndexModel {
         if let Some(val) = self.options.bucket_size {
             doc.insert("bucketSize", val);
         }
+        if let Some(ref val) = self.options.wildcard_projection {
+            doc.insert("wildcardProjection", val.clone());
+        }
 
         Ok(doc)
     }
 }
 
+/// An index as reported by `listIndexes`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexDescription {
+    pub name: String,
+
+    #[serde(rename="key")]
+    pub keys: bson::Document,
+
+    /// The index version.
+    #[serde(rename="v", skip_serializing_if="Option::is_none")]
+    pub version: Option<i32>,
+
+    /// The namespace of the collection, which servers before 4.4 report.
+    #[serde(rename="ns", skip_serializing_if="Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// The remaining options; their name and version are kept in the fields above.
+    #[serde(flatten)]
+    pub options: IndexOptions,
+}
+
+impl IndexDescription {
+    /// Returns a model that creates this index again.
+    pub fn model(&self) -> IndexModel {
+        let mut options = self.options.clone();
+        options.name = Some(self.name.clone());
+        options.version = self.version;
+
+        IndexModel::new(self.keys.clone(), Some(options))
+    }
+
+    /// Returns whether this is the index `model` creates, so that an existing index can be
+    /// told apart from one that has to be rebuilt.
+    ///
+    /// The index version is only compared if the model sets it, `background` is ignored since
+    /// servers no longer report it, and of a collation only the fields the model sets are
+    /// compared, since servers report every field.
+    pub fn matches(&self, model: &IndexModel) -> bool {
+        let mut desired = match model.to_bson() {
+            Ok(desired) => desired,
+            Err(_) => return false,
+        };
+        let mut actual = match self.model().to_bson() {
+            Ok(actual) => actual,
+            Err(_) => return false,
+        };
+
+        if model.options.version.is_none() {
+            actual.remove("v");
+        }
+        desired.remove("background");
+        actual.remove("background");
+
+        let desired_collation = desired.remove("collation");
+        let actual_collation = actual.remove("collation");
+
+        let collation_matches = match (desired_collation, actual_collation) {
+            (Some(Bson::Document(desired)), Some(Bson::Document(actual))) => {
+                desired.iter().all(|(key, value)| actual.get(key) == Some(value))
+            }
+            (desired, actual) => desired == actual,
+        };
+
+        collation_matches && desired == actual
+    }
+}
+
+/// How many data-bearing replica set members must finish building an index before the
+/// primary commits it.
+///
+/// Commit quorums require MongoDB 4.4 or newer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CommitQuorum {
+    /// Every voting data-bearing member, which is the server default.
+    VotingMembers,
+    /// A majority of the voting data-bearing members.
+    Majority,
+    /// The given number of data-bearing members; zero commits without waiting for any.
+    Nodes(i32),
+    /// The members matching a custom write concern name from the replica set configuration.
+    Custom(String),
+}
+
+impl CommitQuorum {
+    fn to_bson(&self) -> Bson {
+        match *self {
+            CommitQuorum::VotingMembers => Bson::String(String::from("votingMembers")),
+            CommitQuorum::Majority => Bson::String(String::from("majority")),
+            CommitQuorum::Nodes(nodes) => Bson::I32(nodes),
+            CommitQuorum::Custom(ref name) => Bson::String(name.clone()),
+        }
+    }
+}
+
+/// Options for createIndexes operations.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CreateIndexOptions {
+    pub commit_quorum: Option<CommitQuorum>,
+    pub max_time_ms: Option<i64>,
+    /// The write concern acknowledging the build, instead of the collection's.
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl CreateIndexOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+// The write concern is left out, since older servers reject it.
+impl From<CreateIndexOptions> for bson::Document {
+    fn from(options: CreateIndexOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(commit_quorum) = options.commit_quorum {
+            document.insert("commitQuorum", commit_quorum.to_bson());
+        }
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        document
+    }
+}
+
 /// Options for insertMany operations.
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct InsertManyOptions {
     pub ordered: Option<bool>,
+    pub max_time_ms: Option<i64>,
+    /// Whether documents that fail the collection's validator may be inserted.
+    pub bypass_document_validation: Option<bool>,
+    pub comment: Option<String>,
     pub write_concern: Option<WriteConcern>,
+    /// The most insert commands of an unordered insert sent at once, each over its own pooled
+    /// connection. Commands are sent one at a time by default, and always for ordered inserts.
+    /// This is not sent to the server.
+    pub max_concurrent_batches: Option<usize>,
 }
 
 impl InsertManyOptions {
@@ -501,6 +1045,18 @@ impl From<InsertManyOptions> for bson::Document {
             document.insert("ordered", ordered);
         }
 
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(bypass_document_validation) = options.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass_document_validation);
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
         if let Some(write_concern) = options.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
@@ -509,10 +1065,50 @@ impl From<InsertManyOptions> for bson::Document {
     }
 }
 
+/// Options for inserting measurements into a time-series collection.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TimeSeriesInsertOptions {
+    /// The field holding each measurement's date.
+    pub time_field: String,
+    /// The field identifying the series a measurement belongs to, if any.
+    pub meta_field: Option<String>,
+    /// The width of the time windows measurements are grouped by. Defaults to one hour,
+    /// matching the bucket span of the collection's default "seconds" granularity.
+    pub window_secs: i64,
+    pub ordered: Option<bool>,
+    pub comment: Option<String>,
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl TimeSeriesInsertOptions {
+    pub fn new(time_field: &str) -> Self {
+        TimeSeriesInsertOptions {
+            time_field: String::from(time_field),
+            meta_field: None,
+            window_secs: 3600,
+            ordered: None,
+            comment: None,
+            write_concern: None,
+        }
+    }
+}
+
 /// Options for update operations.
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct UpdateOptions {
     pub upsert: Option<bool>,
+    /// Filters selecting which array elements an `$[<identifier>]` positional operator
+    /// in the update applies to.
+    pub array_filters: Option<Vec<bson::Document>>,
+    pub hint: Option<String>,
+    pub hint_doc: Option<bson::Document>,
+    pub collation: Option<Collation>,
+    pub max_time_ms: Option<i64>,
+    /// Whether updated documents may fail the collection's validator.
+    pub bypass_document_validation: Option<bool>,
+    pub comment: Option<String>,
+    /// Variables the filter and update can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
     pub write_concern: Option<WriteConcern>,
 }
 
@@ -524,9 +1120,63 @@ impl UpdateOptions {
 
 pub type ReplaceOptions = UpdateOptions;
 
+/// Options for delete operations.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeleteOptions {
+    pub hint: Option<String>,
+    pub hint_doc: Option<bson::Document>,
+    pub collation: Option<Collation>,
+    pub max_time_ms: Option<i64>,
+    pub comment: Option<String>,
+    /// Variables the filter can refer to as `$$<name>`.
+    pub let_vars: Option<bson::Document>,
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl DeleteOptions {
+    pub fn new() -> DeleteOptions {
+        Default::default()
+    }
+}
+
+// Command-level options shared by the `update` and `delete` commands, which apply to every
+// statement the command carries.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct WriteCommandOptions {
+    pub max_time_ms: Option<i64>,
+    pub bypass_document_validation: Option<bool>,
+    pub comment: Option<String>,
+    pub let_vars: Option<bson::Document>,
+}
+
+impl From<WriteCommandOptions> for bson::Document {
+    fn from(options: WriteCommandOptions) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(max_time_ms) = options.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(bypass_document_validation) = options.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass_document_validation);
+        }
+
+        if let Some(comment) = options.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(let_vars) = options.let_vars {
+            document.insert("let", let_vars);
+        }
+
+        document
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use wire_protocol::flags::OpQueryFlags;
 
     fn build_populated_index_opts() -> IndexOptions {
         // Note, this setup is not actually valid, but tests (de)serialization effectively.
@@ -537,7 +1187,9 @@ mod test {
         opts.sparse = Some(true);
         opts.storage_engine = Some(doc!{"mmapv1": true}); // Not sure about the actual shape `:)`.
         opts.unique = Some(true);
+        opts.hidden = Some(false);
         opts.version = Some(2);
+        opts.collation = Some(Collation { strength: Some(2), ..Collation::new("en") });
         opts.default_language = Some("en_us".to_string());
         opts.language_override = Some("en_us".to_string());
         opts.text_version = Some(3);
@@ -547,6 +1199,7 @@ mod test {
         opts.max = Some(-180.0);
         opts.min = Some(180.0);
         opts.bucket_size = Some(10);
+        opts.wildcard_projection = Some(doc!{"test_field": 1});
         opts
     }
 
@@ -596,4 +1249,208 @@ mod test {
         assert_eq!(doc!{"test_field": "text"}, de.keys);
         assert_eq!(opts, de.options);
     }
+
+    #[test]
+    fn index_descriptions_match_their_models() {
+        let listed = doc! {
+            "v": 2,
+            "unique": true,
+            "key": { "sku": 1, "store": -1 },
+            "name": "sku_1_store_-1",
+            "ns": "shop.items",
+            "collation": { "locale": "fr", "strength": 3, "caseLevel": false },
+        };
+        let description: IndexDescription = bson::from_bson(Bson::Document(listed))
+            .expect("Expected valid BSON deserialization.");
+
+        assert_eq!("sku_1_store_-1", description.name);
+        assert_eq!(Some(2), description.version);
+        assert_eq!(Some(String::from("shop.items")), description.namespace);
+        assert_eq!(None, description.options.name);
+
+        let mut opts = IndexOptions::new();
+        opts.unique = Some(true);
+        opts.background = Some(true);
+        opts.collation = Some(Collation::new("fr"));
+        let model = IndexModel::new(doc! { "sku": 1, "store": -1 }, Some(opts.clone()));
+        assert!(description.matches(&model));
+        assert!(description.matches(&description.model()));
+
+        opts.unique = None;
+        assert!(!description.matches(&IndexModel::new(model.keys.clone(), Some(opts.clone()))));
+
+        opts.unique = Some(true);
+        let reordered = IndexModel::new(doc! { "store": -1, "sku": 1 }, Some(opts));
+        assert!(!description.matches(&reordered));
+    }
+
+    #[test]
+    fn max_time_ms_is_sent_as_max_time_ms() {
+        let find = FindOptions { max_time_ms: Some(5), ..FindOptions::new() };
+        assert_eq!(Some(&Bson::I64(5)), bson::Document::from(find).get("maxTimeMS"));
+
+        let aggregate = AggregateOptions { max_time_ms: Some(5), ..AggregateOptions::new() };
+        assert_eq!(Some(&Bson::I64(5)), bson::Document::from(aggregate).get("maxTimeMS"));
+
+        let count = CountOptions { max_time_ms: Some(5), ..CountOptions::new() };
+        assert_eq!(Some(&Bson::I64(5)), bson::Document::from(count).get("maxTimeMS"));
+
+        let insert = InsertManyOptions { max_time_ms: Some(5), ..InsertManyOptions::new() };
+        assert_eq!(Some(&Bson::I64(5)), bson::Document::from(insert).get("maxTimeMS"));
+
+        let delete = FindOneAndDeleteOptions { max_time_ms: Some(5), ..Default::default() };
+        assert_eq!(Some(&Bson::I64(5)), bson::Document::from(delete).get("maxTimeMS"));
+
+        assert!(!bson::Document::from(FindOptions::new()).contains_key("maxTimeMS"));
+
+        let aggregate = AggregateOptions { max_await_time_ms: Some(5), ..AggregateOptions::new() };
+        assert!(!bson::Document::from(aggregate).contains_key("maxTimeMS"));
+    }
+
+    #[test]
+    fn bypass_document_validation_is_sent_when_set() {
+        let insert = InsertManyOptions {
+            bypass_document_validation: Some(true),
+            ..InsertManyOptions::new()
+        };
+        let document = bson::Document::from(insert);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("bypassDocumentValidation"));
+
+        let aggregate = AggregateOptions {
+            bypass_document_validation: Some(true),
+            ..AggregateOptions::new()
+        };
+        let document = bson::Document::from(aggregate);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("bypassDocumentValidation"));
+
+        let document = bson::Document::from(FindOneAndUpdateOptions::new());
+        assert!(!document.contains_key("bypassDocumentValidation"));
+    }
+
+    #[test]
+    fn max_concurrent_batches_is_not_sent() {
+        let insert = InsertManyOptions {
+            ordered: Some(false),
+            max_concurrent_batches: Some(4),
+            ..InsertManyOptions::new()
+        };
+        assert_eq!(doc! { "ordered": false }, bson::Document::from(insert));
+    }
+
+    #[test]
+    fn allow_disk_use_is_sent_when_set() {
+        let aggregate = AggregateOptions { allow_disk_use: Some(true), ..AggregateOptions::new() };
+        let document = bson::Document::from(aggregate);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("allowDiskUse"));
+
+        let document = bson::Document::from(AggregateOptions::new());
+        assert!(!document.contains_key("allowDiskUse"));
+    }
+
+    #[test]
+    fn allow_partial_results_is_sent_when_set() {
+        let find = FindOptions { allow_partial_results: true, ..FindOptions::new() };
+        assert!(OpQueryFlags::with_find_options(&find).contains(OpQueryFlags::PARTIAL));
+
+        let document = bson::Document::from(find);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("allowPartialResults"));
+
+        let document = bson::Document::from(FindOptions::new());
+        assert!(!document.contains_key("allowPartialResults"));
+    }
+
+    #[test]
+    fn no_cursor_timeout_is_sent_when_set() {
+        let find = FindOptions { no_cursor_timeout: true, ..FindOptions::new() };
+        assert!(OpQueryFlags::with_find_options(&find).contains(OpQueryFlags::NO_CURSOR_TIMEOUT));
+
+        let document = bson::Document::from(find);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("noCursorTimeout"));
+
+        let document = bson::Document::from(FindOptions::new());
+        assert!(!document.contains_key("noCursorTimeout"));
+    }
+
+    #[test]
+    fn tailable_cursor_types_are_sent() {
+        let find = FindOptions { cursor_type: CursorType::TailableAwait, ..FindOptions::new() };
+        let document = bson::Document::from(find);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("tailable"));
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("awaitData"));
+
+        let find = FindOptions { cursor_type: CursorType::Tailable, ..FindOptions::new() };
+        let document = bson::Document::from(find);
+        assert_eq!(Some(&Bson::Boolean(true)), document.get("tailable"));
+        assert!(!document.contains_key("awaitData"));
+
+        let document = bson::Document::from(FindOptions::new());
+        assert!(!document.contains_key("tailable"));
+    }
+
+    #[test]
+    fn map_reduce_options_to_bson() {
+        let options = MapReduceOptions {
+            out: MapReduceOutput::Merge(String::from("totals")),
+            out_db: Some(String::from("reports")),
+            finalize: Some(String::from("function(key, value) { return value * 2; }")),
+            limit: Some(10),
+            ..MapReduceOptions::new()
+        };
+
+        let document = bson::Document::from(options);
+        assert_eq!(
+            Some(&Bson::Document(doc! { "merge": "totals", "db": "reports" })),
+            document.get("out")
+        );
+        assert_eq!(
+            Some(&Bson::JavaScriptCode(String::from("function(key, value) { return value * 2; }"))),
+            document.get("finalize")
+        );
+        assert_eq!(Some(&Bson::I64(10)), document.get("limit"));
+
+        let document = bson::Document::from(MapReduceOptions::new());
+        assert_eq!(Some(&Bson::Document(doc! { "inline": 1 })), document.get("out"));
+    }
+
+    #[test]
+    fn comment_and_let_vars_are_sent_when_set() {
+        let find = FindOptions {
+            comment: Some(String::from("report")),
+            let_vars: Some(doc! { "threshold": 8 }),
+            ..FindOptions::new()
+        };
+        let document = bson::Document::from(find);
+        assert_eq!(Some(&Bson::String(String::from("report"))), document.get("comment"));
+        assert_eq!(Some(&Bson::Document(doc! { "threshold": 8 })), document.get("let"));
+
+        let count = CountOptions { comment: Some(String::from("report")), ..CountOptions::new() };
+        assert!(bson::Document::from(count).contains_key("comment"));
+
+        let update = WriteCommandOptions {
+            let_vars: Some(doc! { "threshold": 8 }),
+            ..Default::default()
+        };
+        let document = bson::Document::from(update);
+        assert!(document.contains_key("let"));
+        assert!(!document.contains_key("comment"));
+    }
+
+    #[test]
+    fn create_index_options_send_commit_quorum() {
+        let votes = CreateIndexOptions {
+            commit_quorum: Some(CommitQuorum::VotingMembers),
+            max_time_ms: Some(100),
+            write_concern: Some(WriteConcern::new()),
+        };
+        let document = bson::Document::from(votes);
+        assert_eq!(Some(&Bson::from("votingMembers")), document.get("commitQuorum"));
+        assert_eq!(Some(&Bson::I64(100)), document.get("maxTimeMS"));
+        assert!(!document.contains_key("writeConcern"));
+
+        let nodes = CreateIndexOptions {
+            commit_quorum: Some(CommitQuorum::Nodes(2)),
+            ..CreateIndexOptions::new()
+        };
+        assert_eq!(Some(&Bson::I32(2)), bson::Document::from(nodes).get("commitQuorum"));
+    }
 }
@@ -0,0 +1,220 @@
+//! Collection scans split into independent `_id` ranges.
+//!
+//! A partitioned scan returns one cursor per range of `_id` values, so that a large
+//! collection can be read by several threads at once. Split points are either given
+//! explicitly or taken from a `$sample` of the collection, in which case the ranges hold
+//! roughly the same number of documents.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//! let partitions = coll.partitioned_scan(None, 4, None).unwrap();
+//!
+//! let threads: Vec<_> = partitions
+//!     .into_iter()
+//!     .map(|partition| {
+//!         std::thread::spawn(move || {
+//!             for order in partition.cursor {
+//!                 let order = order.unwrap();
+//!                 // Export the order.
+//!             }
+//!         })
+//!     })
+//!     .collect();
+//!
+//! for thread in threads {
+//!     thread.join().unwrap();
+//! }
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+use super::Collection;
+use super::options::{AggregateOptions, FindOptions};
+use super::pipeline::Pipeline;
+use cursor::Cursor;
+use Error::ArgumentError;
+use Result;
+
+/// How many documents are sampled for each requested partition when choosing split points.
+pub const SAMPLES_PER_PARTITION: i64 = 16;
+
+/// One range of a partitioned scan.
+#[derive(Debug)]
+pub struct ScanPartition {
+    /// The smallest `_id` in the range, or `None` for the first range.
+    pub min: Option<Bson>,
+    /// The `_id` the range ends before, or `None` for the last range.
+    pub max: Option<Bson>,
+    /// The documents of the range.
+    pub cursor: Cursor,
+}
+
+// Returns the `_id` conditions of the ranges between consecutive split points.
+//
+// Comparison operators only match values of the same type as their operand, so the first
+// range is written as a negated `$gte` to also hold documents whose `_id` has another type,
+// which every other range leaves out.
+fn range_filters(split_points: &[Bson]) -> Vec<bson::Document> {
+    let first = match split_points.first() {
+        Some(first) => first,
+        None => return vec![bson::Document::new()],
+    };
+
+    let mut filters = vec![doc! { "_id": { "$not": { "$gte": first.clone() } } }];
+    for bounds in split_points.windows(2) {
+        filters.push(doc! {
+            "_id": { "$gte": bounds[0].clone(), "$lt": bounds[1].clone() },
+        });
+    }
+    filters.push(doc! { "_id": { "$gte": split_points[split_points.len() - 1].clone() } });
+    filters
+}
+
+// Picks `partitions - 1` evenly spaced split points from sampled `_id`s in ascending order,
+// keeping only those of the same type as the median so that every range can be cut by them.
+fn pick_split_points(sampled: &[Bson], partitions: usize) -> Vec<Bson> {
+    if sampled.is_empty() {
+        return Vec::new();
+    }
+
+    let kind = sampled[sampled.len() / 2].element_type();
+    let sampled: Vec<&Bson> = sampled.iter().filter(|id| id.element_type() == kind).collect();
+
+    let mut points: Vec<Bson> = Vec::new();
+    for i in 1..partitions {
+        let point = sampled[i * sampled.len() / partitions];
+        if points.last() != Some(point) && sampled[0] != point {
+            points.push(point.clone());
+        }
+    }
+    points
+}
+
+impl Collection {
+    /// Splits a scan of the documents matching `filter` into up to `partitions` ranges of
+    /// `_id`, with split points chosen from a `$sample` of the collection.
+    ///
+    /// Fewer ranges are returned when the collection is too small to be split that many
+    /// times. Every matching document belongs to exactly one range; those whose `_id` is of a
+    /// different type than most are all returned by the first.
+    pub fn partitioned_scan(
+        &self,
+        filter: Option<bson::Document>,
+        partitions: usize,
+        options: Option<FindOptions>,
+    ) -> Result<Vec<ScanPartition>> {
+        if partitions == 0 {
+            return Err(ArgumentError(
+                String::from("A partitioned scan needs at least one partition."),
+            ));
+        }
+
+        let split_points = if partitions == 1 {
+            Vec::new()
+        } else {
+            let mut pipeline = Pipeline::new();
+            if let Some(ref filter) = filter {
+                pipeline = pipeline.match_(filter.clone());
+            }
+            let pipeline = pipeline
+                .sample(partitions as i64 * SAMPLES_PER_PARTITION)
+                .project(doc! { "_id": 1 })
+                .sort(doc! { "_id": 1 });
+
+            let mut aggregate_options = AggregateOptions::new();
+            aggregate_options.read_preference = options.as_ref().and_then(|options| {
+                options.read_preference.clone()
+            });
+
+            let mut sampled = Vec::new();
+            for doc in self.aggregate(pipeline.into(), Some(aggregate_options))? {
+                if let Some(id) = doc?.get("_id") {
+                    sampled.push(id.clone());
+                }
+            }
+
+            pick_split_points(&sampled, partitions)
+        };
+
+        self.partitioned_scan_at(filter, split_points, options)
+    }
+
+    /// Splits a scan of the documents matching `filter` into the ranges of `_id` between
+    /// consecutive `split_points`, which must be in ascending order and of the same type.
+    ///
+    /// One more range than there are split points is returned: the first holds every `_id`
+    /// before the first split point, and the last every `_id` from the last one on.
+    pub fn partitioned_scan_at(
+        &self,
+        filter: Option<bson::Document>,
+        split_points: Vec<Bson>,
+        options: Option<FindOptions>,
+    ) -> Result<Vec<ScanPartition>> {
+        let options = options.unwrap_or_default();
+
+        if options.skip.is_some() || options.limit.is_some() {
+            return Err(ArgumentError(String::from(
+                "Partitioned scans cannot skip or limit documents, as each range would.",
+            )));
+        }
+
+        let mut partitions = Vec::with_capacity(split_points.len() + 1);
+        for (i, range) in range_filters(&split_points).into_iter().enumerate() {
+            let range_filter = match filter {
+                Some(ref filter) => doc! { "$and": [filter.clone(), range] },
+                None => range,
+            };
+
+            partitions.push(ScanPartition {
+                min: if i == 0 { None } else { Some(split_points[i - 1].clone()) },
+                max: split_points.get(i).cloned(),
+                cursor: self.find(Some(range_filter), Some(options.clone()))?,
+            });
+        }
+
+        Ok(partitions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranges_cover_every_id() {
+        let filters = range_filters(&[Bson::I32(10), Bson::I32(20)]);
+        let expected = vec![
+            doc! { "_id": { "$not": { "$gte": 10 } } },
+            doc! { "_id": { "$gte": 10, "$lt": 20 } },
+            doc! { "_id": { "$gte": 20 } },
+        ];
+        assert_eq!(expected, filters);
+
+        assert_eq!(vec![bson::Document::new()], range_filters(&[]));
+    }
+
+    #[test]
+    fn picks_evenly_spaced_split_points() {
+        let sampled: Vec<Bson> = (0..12).map(Bson::I32).collect();
+        let expected = vec![Bson::I32(3), Bson::I32(6), Bson::I32(9)];
+        assert_eq!(expected, pick_split_points(&sampled, 4));
+
+        // Repeated and mistyped samples are skipped, leaving fewer split points.
+        let sampled = vec![
+            Bson::I32(1),
+            Bson::I32(1),
+            Bson::I32(1),
+            Bson::I32(2),
+            Bson::String(String::from("a")),
+        ];
+        assert_eq!(vec![Bson::I32(2)], pick_split_points(&sampled, 4));
+        assert!(pick_split_points(&[], 4).is_empty());
+    }
+}
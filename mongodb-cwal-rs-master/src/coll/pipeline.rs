@@ -0,0 +1,1046 @@
+//! Builder for aggregation pipelines.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Bson, Client, ThreadedClient};
+//! # use mongodb::coll::pipeline::Pipeline;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//!
+//! let pipeline = Pipeline::new()
+//!     .match_(doc! { "status": "shipped" })
+//!     .group(Bson::from("$customer"), doc! { "total": { "$sum": "$amount" } })
+//!     .sort(doc! { "total": -1 })
+//!     .limit(10);
+//!
+//! let cursor = coll.aggregate(pipeline.build(), None).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+use super::change_stream::ChangeStreamOptions;
+use super::search::Search;
+
+/// An ordered list of aggregation stages.
+///
+/// Each method appends one stage; stages without a dedicated method can be added with `stage`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pipeline {
+    stages: Vec<bson::Document>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Pipeline {
+        Default::default()
+    }
+
+    /// Appends an arbitrary `{ <name>: <spec> }` stage.
+    pub fn stage<T: Into<Bson>>(mut self, name: &str, spec: T) -> Pipeline {
+        let mut stage = bson::Document::new();
+        stage.insert(name, spec.into());
+        self.stages.push(stage);
+        self
+    }
+
+    /// Opens a change stream; must be the first stage.
+    pub fn change_stream(self, options: ChangeStreamOptions) -> Pipeline {
+        self.stage("$changeStream", bson::Document::from(options))
+    }
+
+    /// Runs an Atlas Search query, returning the matching documents from the most relevant
+    /// on; must be the first stage.
+    pub fn search(self, search: Search) -> Pipeline {
+        self.stage("$search", bson::Document::from(search))
+    }
+
+    /// Runs an Atlas Search query, returning a single document with the metadata of the
+    /// results, such as their count; must be the first stage.
+    pub fn search_meta(self, search: Search) -> Pipeline {
+        self.stage("$searchMeta", bson::Document::from(search))
+    }
+
+    /// Filters documents with a query predicate.
+    ///
+    /// Named with a trailing underscore since `match` is a keyword.
+    pub fn match_(self, filter: bson::Document) -> Pipeline {
+        self.stage("$match", filter)
+    }
+
+    /// Reshapes documents, including, excluding or computing fields.
+    pub fn project(self, projection: bson::Document) -> Pipeline {
+        self.stage("$project", projection)
+    }
+
+    /// Adds computed fields to each document.
+    pub fn add_fields(self, fields: bson::Document) -> Pipeline {
+        self.stage("$addFields", fields)
+    }
+
+    /// Groups documents by the `id` expression, computing the given accumulator fields.
+    pub fn group(self, id: Bson, accumulators: bson::Document) -> Pipeline {
+        let mut spec = doc! { "_id": id };
+        spec.extend(accumulators);
+        self.stage("$group", spec)
+    }
+
+    /// Orders documents by the given sort specification.
+    pub fn sort(self, sort: bson::Document) -> Pipeline {
+        self.stage("$sort", sort)
+    }
+
+    /// Skips the first `n` documents.
+    pub fn skip(self, n: i64) -> Pipeline {
+        self.stage("$skip", n)
+    }
+
+    /// Passes at most `n` documents on to the next stage.
+    pub fn limit(self, n: i64) -> Pipeline {
+        self.stage("$limit", n)
+    }
+
+    /// Randomly selects `size` documents.
+    pub fn sample(self, size: i64) -> Pipeline {
+        self.stage("$sample", doc! { "size": size })
+    }
+
+    /// Outputs one document per element of the array at `path`, e.g. `"$items"`.
+    pub fn unwind(self, path: &str) -> Pipeline {
+        self.stage("$unwind", path)
+    }
+
+    /// Joins documents from `from` whose `foreign_field` equals the local `local_field`,
+    /// storing the matches in the array field `as_field`.
+    pub fn lookup(self, from: &str, local_field: &str, foreign_field: &str, as_field: &str)
+        -> Pipeline {
+        self.stage("$lookup", doc! {
+            "from": from,
+            "localField": local_field,
+            "foreignField": foreign_field,
+            "as": as_field,
+        })
+    }
+
+    /// Joins documents from another collection as described by `lookup`, e.g. with `let`
+    /// bindings and a sub-pipeline for correlated joins.
+    pub fn lookup_with(self, lookup: Lookup) -> Pipeline {
+        self.stage("$lookup", bson::Document::from(lookup))
+    }
+
+    /// Recursively follows references between documents of another collection.
+    pub fn graph_lookup(self, graph_lookup: GraphLookup) -> Pipeline {
+        self.stage("$graphLookup", bson::Document::from(graph_lookup))
+    }
+
+    /// Appends the documents of `coll`, optionally processed by `pipeline`, to the results.
+    ///
+    /// The whole aggregation runs as one command, so its read preference and read concern
+    /// apply to `coll` as well.
+    pub fn union_with(self, coll: &str, pipeline: Option<Pipeline>) -> Pipeline {
+        let mut spec = doc! { "coll": coll };
+
+        if let Some(pipeline) = pipeline {
+            let stages: Vec<_> = pipeline.stages.into_iter().map(Bson::Document).collect();
+            spec.insert("pipeline", stages);
+        }
+
+        self.stage("$unionWith", spec)
+    }
+
+    /// Replaces each document with the embedded document produced by `new_root`.
+    pub fn replace_root(self, new_root: Bson) -> Pipeline {
+        self.stage("$replaceRoot", doc! { "newRoot": new_root })
+    }
+
+    /// Replaces the input with a single document holding the number of documents in `field`.
+    pub fn count(self, field: &str) -> Pipeline {
+        self.stage("$count", field)
+    }
+
+    /// Writes the results to the collection `coll`. Must be the last stage.
+    pub fn out(self, coll: &str) -> Pipeline {
+        self.stage("$out", coll)
+    }
+
+    /// Writes the results to the collection `coll` of the database `db`. Must be the last
+    /// stage; requires MongoDB 4.4 or newer.
+    pub fn out_to_db(self, db: &str, coll: &str) -> Pipeline {
+        self.stage("$out", doc! { "db": db, "coll": coll })
+    }
+
+    /// Merges the results into a collection. Must be the last stage; requires MongoDB 4.2 or
+    /// newer.
+    pub fn merge(self, merge: Merge) -> Pipeline {
+        self.stage("$merge", bson::Document::from(merge))
+    }
+
+    /// Computes window functions over partitions of sorted documents.
+    pub fn set_window_fields(self, fields: WindowFields) -> Pipeline {
+        self.stage("$setWindowFields", bson::Document::from(fields))
+    }
+
+    /// Creates documents to fill gaps in a sequence of numbers or dates.
+    pub fn densify(self, densify: Densify) -> Pipeline {
+        self.stage("$densify", bson::Document::from(densify))
+    }
+
+    /// Populates null and missing field values.
+    pub fn fill(self, fill: Fill) -> Pipeline {
+        self.stage("$fill", bson::Document::from(fill))
+    }
+
+    /// Returns the stages built so far.
+    pub fn stages(&self) -> &[bson::Document] {
+        &self.stages
+    }
+
+    /// Consumes the builder, returning the stages in the form taken by `Collection::aggregate`.
+    pub fn build(self) -> Vec<bson::Document> {
+        self.stages
+    }
+}
+
+impl From<Pipeline> for Vec<bson::Document> {
+    fn from(pipeline: Pipeline) -> Self {
+        pipeline.stages
+    }
+}
+
+/// The specification of a `$lookup` stage.
+///
+/// ```
+/// # #[macro_use] extern crate bson;
+/// # extern crate mongodb;
+/// # use mongodb::Bson;
+/// # use mongodb::coll::pipeline::{Lookup, Pipeline};
+/// # fn main() {
+/// // Join the warehouses stocking each ordered item in at least the ordered quantity.
+/// let lookup = Lookup::new("warehouses", "stockdata")
+///     .let_var("order_item", Bson::from("$item"))
+///     .let_var("order_qty", Bson::from("$ordered"))
+///     .pipeline(Pipeline::new()
+///         .match_(doc! { "$expr": { "$and": [
+///             { "$eq": ["$stock_item", "$$order_item"] },
+///             { "$gte": ["$instock", "$$order_qty"] },
+///         ] } })
+///         .project(doc! { "stock_item": 0, "_id": 0 }));
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lookup {
+    from: String,
+    as_field: String,
+    local_field: Option<String>,
+    foreign_field: Option<String>,
+    let_vars: bson::Document,
+    pipeline: Option<Pipeline>,
+}
+
+impl Lookup {
+    /// Joins documents from `from`, storing the matches in the array field `as_field`.
+    pub fn new(from: &str, as_field: &str) -> Lookup {
+        Lookup {
+            from: String::from(from),
+            as_field: String::from(as_field),
+            local_field: None,
+            foreign_field: None,
+            let_vars: bson::Document::new(),
+            pipeline: None,
+        }
+    }
+
+    /// Matches joined documents whose `foreign_field` equals the local `local_field`.
+    pub fn on(mut self, local_field: &str, foreign_field: &str) -> Lookup {
+        self.local_field = Some(String::from(local_field));
+        self.foreign_field = Some(String::from(foreign_field));
+        self
+    }
+
+    /// Binds `$$name` to `expression`, evaluated on the local document, for use in the
+    /// sub-pipeline.
+    pub fn let_var(mut self, name: &str, expression: Bson) -> Lookup {
+        self.let_vars.insert(name, expression);
+        self
+    }
+
+    /// Runs `pipeline` on the joined collection; its results are stored in `as_field`.
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Lookup {
+        self.pipeline = Some(pipeline);
+        self
+    }
+}
+
+impl From<Lookup> for bson::Document {
+    fn from(lookup: Lookup) -> Self {
+        let mut document = doc! { "from": lookup.from };
+
+        if let Some(local_field) = lookup.local_field {
+            document.insert("localField", local_field);
+        }
+
+        if let Some(foreign_field) = lookup.foreign_field {
+            document.insert("foreignField", foreign_field);
+        }
+
+        if !lookup.let_vars.is_empty() {
+            document.insert("let", lookup.let_vars);
+        }
+
+        if let Some(pipeline) = lookup.pipeline {
+            let stages: Vec<_> = pipeline.stages.into_iter().map(Bson::Document).collect();
+            document.insert("pipeline", stages);
+        }
+
+        document.insert("as", lookup.as_field);
+        document
+    }
+}
+
+/// The specification of a `$graphLookup` stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphLookup {
+    from: String,
+    start_with: Bson,
+    connect_from_field: String,
+    connect_to_field: String,
+    as_field: String,
+    max_depth: Option<i64>,
+    depth_field: Option<String>,
+    restrict_search_with_match: Option<bson::Document>,
+}
+
+impl GraphLookup {
+    /// Starting from the values of `start_with`, repeatedly joins documents of `from` whose
+    /// `connect_to_field` matches the `connect_from_field` of the documents found so far,
+    /// storing everything reached in the array field `as_field`.
+    pub fn new(
+        from: &str,
+        start_with: Bson,
+        connect_from_field: &str,
+        connect_to_field: &str,
+        as_field: &str,
+    ) -> GraphLookup {
+        GraphLookup {
+            from: String::from(from),
+            start_with: start_with,
+            connect_from_field: String::from(connect_from_field),
+            connect_to_field: String::from(connect_to_field),
+            as_field: String::from(as_field),
+            max_depth: None,
+            depth_field: None,
+            restrict_search_with_match: None,
+        }
+    }
+
+    /// Stops after `depth` recursions; zero only follows the starting values.
+    pub fn max_depth(mut self, depth: i64) -> GraphLookup {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Records the recursion depth of each found document in `field`.
+    pub fn depth_field(mut self, field: &str) -> GraphLookup {
+        self.depth_field = Some(String::from(field));
+        self
+    }
+
+    /// Only follows documents matching `filter`.
+    pub fn restrict_search_with_match(mut self, filter: bson::Document) -> GraphLookup {
+        self.restrict_search_with_match = Some(filter);
+        self
+    }
+}
+
+impl From<GraphLookup> for bson::Document {
+    fn from(lookup: GraphLookup) -> Self {
+        let mut document = doc! {
+            "from": lookup.from,
+            "startWith": lookup.start_with,
+            "connectFromField": lookup.connect_from_field,
+            "connectToField": lookup.connect_to_field,
+            "as": lookup.as_field,
+        };
+
+        if let Some(max_depth) = lookup.max_depth {
+            document.insert("maxDepth", max_depth);
+        }
+
+        if let Some(depth_field) = lookup.depth_field {
+            document.insert("depthField", depth_field);
+        }
+
+        if let Some(filter) = lookup.restrict_search_with_match {
+            document.insert("restrictSearchWithMatch", filter);
+        }
+
+        document
+    }
+}
+
+/// What `$merge` does with a result matching an existing document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WhenMatched {
+    /// Replaces the existing document with the result.
+    Replace,
+    /// Keeps the existing document.
+    KeepExisting,
+    /// Merges the fields of the result into the existing document.
+    Merge,
+    /// Stops the aggregation; documents already written are kept.
+    Fail,
+    /// Updates the existing document with an update pipeline, where `$$new` is the result.
+    Pipeline(Pipeline),
+}
+
+/// What `$merge` does with a result matching no existing document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WhenNotMatched {
+    Insert,
+    Discard,
+    Fail,
+}
+
+impl WhenNotMatched {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            WhenNotMatched::Insert => "insert",
+            WhenNotMatched::Discard => "discard",
+            WhenNotMatched::Fail => "fail",
+        }
+    }
+}
+
+/// The specification of a `$merge` stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Merge {
+    db: Option<String>,
+    coll: String,
+    on: Vec<String>,
+    let_vars: bson::Document,
+    when_matched: Option<WhenMatched>,
+    when_not_matched: Option<WhenNotMatched>,
+}
+
+impl Merge {
+    /// Merges into the collection `coll` of the aggregated database.
+    pub fn new(coll: &str) -> Merge {
+        Merge {
+            db: None,
+            coll: String::from(coll),
+            on: Vec::new(),
+            let_vars: bson::Document::new(),
+            when_matched: None,
+            when_not_matched: None,
+        }
+    }
+
+    /// Merges into a collection of the database `db` instead.
+    pub fn db(mut self, db: &str) -> Merge {
+        self.db = Some(String::from(db));
+        self
+    }
+
+    /// Matches results to existing documents on `field` rather than `_id`. Called once per
+    /// field of a compound key; the fields need a unique index.
+    pub fn on(mut self, field: &str) -> Merge {
+        self.on.push(String::from(field));
+        self
+    }
+
+    /// Binds `$$name` to `expression`, evaluated on the result, for use in a
+    /// `WhenMatched::Pipeline`.
+    pub fn let_var(mut self, name: &str, expression: Bson) -> Merge {
+        self.let_vars.insert(name, expression);
+        self
+    }
+
+    pub fn when_matched(mut self, when_matched: WhenMatched) -> Merge {
+        self.when_matched = Some(when_matched);
+        self
+    }
+
+    pub fn when_not_matched(mut self, when_not_matched: WhenNotMatched) -> Merge {
+        self.when_not_matched = Some(when_not_matched);
+        self
+    }
+}
+
+impl From<Merge> for bson::Document {
+    fn from(merge: Merge) -> Self {
+        let into = match merge.db {
+            Some(db) => Bson::Document(doc! { "db": db, "coll": merge.coll }),
+            None => Bson::String(merge.coll),
+        };
+
+        let mut document = doc! { "into": into };
+
+        match merge.on.len() {
+            0 => (),
+            1 => {
+                document.insert("on", merge.on.into_iter().next().unwrap());
+            }
+            _ => {
+                document.insert("on", merge.on.into_iter().map(Bson::String).collect::<Vec<_>>());
+            }
+        }
+
+        if !merge.let_vars.is_empty() {
+            document.insert("let", merge.let_vars);
+        }
+
+        match merge.when_matched {
+            Some(WhenMatched::Replace) => {
+                document.insert("whenMatched", "replace");
+            }
+            Some(WhenMatched::KeepExisting) => {
+                document.insert("whenMatched", "keepExisting");
+            }
+            Some(WhenMatched::Merge) => {
+                document.insert("whenMatched", "merge");
+            }
+            Some(WhenMatched::Fail) => {
+                document.insert("whenMatched", "fail");
+            }
+            Some(WhenMatched::Pipeline(pipeline)) => {
+                let stages: Vec<_> = pipeline.stages.into_iter().map(Bson::Document).collect();
+                document.insert("whenMatched", stages);
+            }
+            None => (),
+        }
+
+        if let Some(when_not_matched) = merge.when_not_matched {
+            document.insert("whenNotMatched", when_not_matched.to_str());
+        }
+
+        document
+    }
+}
+
+/// Time units accepted by window ranges and `$densify` steps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeUnit {
+    Millisecond,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl TimeUnit {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            TimeUnit::Millisecond => "millisecond",
+            TimeUnit::Second => "second",
+            TimeUnit::Minute => "minute",
+            TimeUnit::Hour => "hour",
+            TimeUnit::Day => "day",
+            TimeUnit::Week => "week",
+            TimeUnit::Month => "month",
+            TimeUnit::Quarter => "quarter",
+            TimeUnit::Year => "year",
+        }
+    }
+}
+
+/// One end of a window, relative to the current document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowBound {
+    /// The first or last document of the partition.
+    Unbounded,
+    /// The current document.
+    Current,
+    /// A document or value offset; negative offsets look backwards.
+    Offset(Bson),
+}
+
+impl From<WindowBound> for Bson {
+    fn from(bound: WindowBound) -> Self {
+        match bound {
+            WindowBound::Unbounded => Bson::from("unbounded"),
+            WindowBound::Current => Bson::from("current"),
+            WindowBound::Offset(offset) => offset,
+        }
+    }
+}
+
+/// A window function output, such as `{ $sum: "$qty", window: { documents: [-1, 0] } }`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowOutput {
+    operator: String,
+    expression: Bson,
+    window: Option<bson::Document>,
+}
+
+impl WindowOutput {
+    /// Applies `operator` (e.g. `"$sum"`, `"$rank"`) to `expression` over the whole partition.
+    pub fn new(operator: &str, expression: Bson) -> WindowOutput {
+        WindowOutput {
+            operator: String::from(operator),
+            expression: expression,
+            window: None,
+        }
+    }
+
+    /// Bounds the window by document positions in the sort order.
+    pub fn documents(mut self, lower: WindowBound, upper: WindowBound) -> WindowOutput {
+        self.window = Some(doc! { "documents": [Bson::from(lower), Bson::from(upper)] });
+        self
+    }
+
+    /// Bounds the window by values of the sort field, optionally measured in `unit` when
+    /// sorting by a date.
+    pub fn range(mut self, lower: WindowBound, upper: WindowBound, unit: Option<TimeUnit>)
+        -> WindowOutput {
+        let mut window = doc! { "range": [Bson::from(lower), Bson::from(upper)] };
+
+        if let Some(unit) = unit {
+            window.insert("unit", unit.to_str());
+        }
+
+        self.window = Some(window);
+        self
+    }
+}
+
+impl From<WindowOutput> for bson::Document {
+    fn from(output: WindowOutput) -> Self {
+        let mut document = bson::Document::new();
+        document.insert(output.operator, output.expression);
+
+        if let Some(window) = output.window {
+            document.insert("window", window);
+        }
+
+        document
+    }
+}
+
+/// The specification of a `$setWindowFields` stage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowFields {
+    partition_by: Option<Bson>,
+    sort_by: Option<bson::Document>,
+    output: bson::Document,
+}
+
+impl WindowFields {
+    pub fn new() -> WindowFields {
+        Default::default()
+    }
+
+    /// Partitions documents by the given expression, e.g. `"$state"`.
+    pub fn partition_by(mut self, expression: Bson) -> WindowFields {
+        self.partition_by = Some(expression);
+        self
+    }
+
+    /// Sorts documents within each partition. Required by ranked and bounded outputs.
+    pub fn sort_by(mut self, sort: bson::Document) -> WindowFields {
+        self.sort_by = Some(sort);
+        self
+    }
+
+    /// Stores the result of a window function in `field`.
+    pub fn output(mut self, field: &str, output: WindowOutput) -> WindowFields {
+        self.output.insert(field, bson::Document::from(output));
+        self
+    }
+}
+
+impl From<WindowFields> for bson::Document {
+    fn from(fields: WindowFields) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(partition_by) = fields.partition_by {
+            document.insert("partitionBy", partition_by);
+        }
+
+        if let Some(sort_by) = fields.sort_by {
+            document.insert("sortBy", sort_by);
+        }
+
+        document.insert("output", fields.output);
+        document
+    }
+}
+
+/// The values `$densify` fills in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DensifyBounds {
+    /// From the lowest to the highest value across all documents.
+    Full,
+    /// From the lowest to the highest value within each partition.
+    Partition,
+    /// Between the given lower (inclusive) and upper (exclusive) values.
+    Range(Bson, Bson),
+}
+
+/// The specification of a `$densify` stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Densify {
+    field: String,
+    partition_by_fields: Vec<String>,
+    step: Bson,
+    unit: Option<TimeUnit>,
+    bounds: DensifyBounds,
+}
+
+impl Densify {
+    /// Fills in `field` every `step` within `bounds`.
+    pub fn new(field: &str, step: Bson, bounds: DensifyBounds) -> Densify {
+        Densify {
+            field: String::from(field),
+            partition_by_fields: Vec::new(),
+            step: step,
+            unit: None,
+            bounds: bounds,
+        }
+    }
+
+    /// Measures the step in `unit`; required when densifying dates.
+    pub fn unit(mut self, unit: TimeUnit) -> Densify {
+        self.unit = Some(unit);
+        self
+    }
+
+    /// Densifies each combination of the given fields separately.
+    pub fn partition_by_fields(mut self, fields: Vec<String>) -> Densify {
+        self.partition_by_fields = fields;
+        self
+    }
+}
+
+impl From<Densify> for bson::Document {
+    fn from(densify: Densify) -> Self {
+        let bounds = match densify.bounds {
+            DensifyBounds::Full => Bson::from("full"),
+            DensifyBounds::Partition => Bson::from("partition"),
+            DensifyBounds::Range(lower, upper) => Bson::Array(vec![lower, upper]),
+        };
+
+        let mut range = doc! { "step": densify.step, "bounds": bounds };
+
+        if let Some(unit) = densify.unit {
+            range.insert("unit", unit.to_str());
+        }
+
+        let mut document = doc! { "field": densify.field };
+
+        if !densify.partition_by_fields.is_empty() {
+            let fields: Vec<_> = densify.partition_by_fields.into_iter().map(Bson::from).collect();
+            document.insert("partitionByFields", fields);
+        }
+
+        document.insert("range", range);
+        document
+    }
+}
+
+/// How `$fill` derives missing values from the surrounding documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FillMethod {
+    /// Carries the last observed value forward.
+    Locf,
+    /// Interpolates linearly between the neighbouring values.
+    Linear,
+}
+
+/// The specification of a `$fill` stage.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fill {
+    partition_by: Option<Bson>,
+    partition_by_fields: Vec<String>,
+    sort_by: Option<bson::Document>,
+    output: bson::Document,
+}
+
+impl Fill {
+    pub fn new() -> Fill {
+        Default::default()
+    }
+
+    /// Fills each partition of the given expression separately.
+    pub fn partition_by(mut self, expression: Bson) -> Fill {
+        self.partition_by = Some(expression);
+        self
+    }
+
+    /// Fills each combination of the given fields separately.
+    pub fn partition_by_fields(mut self, fields: Vec<String>) -> Fill {
+        self.partition_by_fields = fields;
+        self
+    }
+
+    /// Sorts documents within each partition. Required by fill methods.
+    pub fn sort_by(mut self, sort: bson::Document) -> Fill {
+        self.sort_by = Some(sort);
+        self
+    }
+
+    /// Fills `field` with the result of `expression`.
+    pub fn value(mut self, field: &str, expression: Bson) -> Fill {
+        self.output.insert(field, doc! { "value": expression });
+        self
+    }
+
+    /// Fills `field` from the surrounding documents.
+    pub fn method(mut self, field: &str, method: FillMethod) -> Fill {
+        let method = match method {
+            FillMethod::Locf => "locf",
+            FillMethod::Linear => "linear",
+        };
+
+        self.output.insert(field, doc! { "method": method });
+        self
+    }
+}
+
+impl From<Fill> for bson::Document {
+    fn from(fill: Fill) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(partition_by) = fill.partition_by {
+            document.insert("partitionBy", partition_by);
+        }
+
+        if !fill.partition_by_fields.is_empty() {
+            let fields: Vec<_> = fill.partition_by_fields.into_iter().map(Bson::from).collect();
+            document.insert("partitionByFields", fields);
+        }
+
+        if let Some(sort_by) = fill.sort_by {
+            document.insert("sortBy", sort_by);
+        }
+
+        document.insert("output", fill.output);
+        document
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builds_stages_in_order() {
+        let pipeline = Pipeline::new()
+            .match_(doc! { "status": "A" })
+            .group(Bson::from("$cust_id"), doc! { "total": { "$sum": "$amount" } })
+            .sort(doc! { "total": -1 })
+            .limit(5)
+            .build();
+
+        let expected = vec![
+            doc! { "$match": { "status": "A" } },
+            doc! { "$group": { "_id": "$cust_id", "total": { "$sum": "$amount" } } },
+            doc! { "$sort": { "total": -1 } },
+            doc! { "$limit": 5_i64 },
+        ];
+
+        assert_eq!(expected, pipeline);
+    }
+
+    #[test]
+    fn search_stages() {
+        use coll::search::{SearchCount, Text};
+
+        let pipeline = Pipeline::new()
+            .search_meta(Search::new(Text::new("space", "plot")).count(SearchCount::Total))
+            .build();
+
+        let expected = vec![doc! {
+            "$searchMeta": {
+                "text": { "query": "space", "path": "plot" },
+                "count": { "type": "total" },
+            },
+        }];
+
+        assert_eq!(expected, pipeline);
+    }
+
+    #[test]
+    fn lookup_and_custom_stage() {
+        let pipeline: Vec<bson::Document> = Pipeline::new()
+            .lookup("inventory", "item", "sku", "docs")
+            .stage("$indexStats", bson::Document::new())
+            .into();
+
+        assert_eq!(
+            doc! {
+                "$lookup": {
+                    "from": "inventory",
+                    "localField": "item",
+                    "foreignField": "sku",
+                    "as": "docs",
+                }
+            },
+            pipeline[0]
+        );
+        assert_eq!(doc! { "$indexStats": {} }, pipeline[1]);
+    }
+
+    #[test]
+    fn window_densify_and_fill_stages() {
+        let pipeline = Pipeline::new()
+            .set_window_fields(WindowFields::new()
+                .partition_by(Bson::from("$state"))
+                .sort_by(doc! { "orderDate": 1 })
+                .output("cumulativeQty", WindowOutput::new("$sum", Bson::from("$qty"))
+                    .documents(WindowBound::Unbounded, WindowBound::Current))
+                .output("recentQty", WindowOutput::new("$avg", Bson::from("$qty"))
+                    .range(WindowBound::Offset(Bson::from(-10)), WindowBound::Current,
+                           Some(TimeUnit::Day))))
+            .densify(Densify::new("hour", Bson::from(1), DensifyBounds::Partition)
+                .partition_by_fields(vec![String::from("sensor")]))
+            .fill(Fill::new()
+                .sort_by(doc! { "hour": 1 })
+                .method("reading", FillMethod::Linear)
+                .value("status", Bson::from("missing")))
+            .build();
+
+        let expected = vec![
+            doc! {
+                "$setWindowFields": {
+                    "partitionBy": "$state",
+                    "sortBy": { "orderDate": 1 },
+                    "output": {
+                        "cumulativeQty": {
+                            "$sum": "$qty",
+                            "window": { "documents": ["unbounded", "current"] },
+                        },
+                        "recentQty": {
+                            "$avg": "$qty",
+                            "window": { "range": [-10, "current"], "unit": "day" },
+                        },
+                    },
+                }
+            },
+            doc! {
+                "$densify": {
+                    "field": "hour",
+                    "partitionByFields": ["sensor"],
+                    "range": { "step": 1, "bounds": "partition" },
+                }
+            },
+            doc! {
+                "$fill": {
+                    "sortBy": { "hour": 1 },
+                    "output": {
+                        "reading": { "method": "linear" },
+                        "status": { "value": "missing" },
+                    },
+                }
+            },
+        ];
+
+        assert_eq!(expected, pipeline);
+    }
+
+    #[test]
+    fn correlated_lookup_and_graph_lookup() {
+        let pipeline = Pipeline::new()
+            .lookup_with(Lookup::new("warehouses", "stock")
+                .let_var("item", Bson::from("$item"))
+                .pipeline(Pipeline::new()
+                    .match_(doc! { "$expr": { "$eq": ["$sku", "$$item"] } })))
+            .graph_lookup(GraphLookup::new(
+                "employees",
+                Bson::from("$reportsTo"),
+                "reportsTo",
+                "name",
+                "chain",
+            ).max_depth(2).depth_field("level"))
+            .build();
+
+        assert_eq!(
+            doc! {
+                "$lookup": {
+                    "from": "warehouses",
+                    "let": { "item": "$item" },
+                    "pipeline": [{ "$match": { "$expr": { "$eq": ["$sku", "$$item"] } } }],
+                    "as": "stock",
+                }
+            },
+            pipeline[0]
+        );
+
+        assert_eq!(
+            doc! {
+                "$graphLookup": {
+                    "from": "employees",
+                    "startWith": "$reportsTo",
+                    "connectFromField": "reportsTo",
+                    "connectToField": "name",
+                    "as": "chain",
+                    "maxDepth": 2_i64,
+                    "depthField": "level",
+                }
+            },
+            pipeline[1]
+        );
+    }
+
+    #[test]
+    fn union_with_nested_pipeline() {
+        let pipeline = Pipeline::new()
+            .union_with("sales_2019", None)
+            .union_with("sales_2020", Some(Pipeline::new().match_(doc! { "region": "EU" })))
+            .build();
+
+        assert_eq!(doc! { "$unionWith": { "coll": "sales_2019" } }, pipeline[0]);
+        assert_eq!(
+            doc! {
+                "$unionWith": {
+                    "coll": "sales_2020",
+                    "pipeline": [{ "$match": { "region": "EU" } }],
+                }
+            },
+            pipeline[1]
+        );
+    }
+
+    #[test]
+    fn out_and_merge_to_other_database() {
+        let merge = Merge::new("totals")
+            .db("reporting")
+            .on("region")
+            .on("month")
+            .let_var("total", Bson::from("$total"))
+            .when_matched(WhenMatched::Pipeline(Pipeline::new()
+                .stage("$set", doc! { "total": { "$add": ["$total", "$$total"] } })))
+            .when_not_matched(WhenNotMatched::Insert);
+
+        let pipeline = Pipeline::new()
+            .out_to_db("archive", "orders")
+            .merge(merge)
+            .merge(Merge::new("totals").when_matched(WhenMatched::KeepExisting))
+            .build();
+
+        assert_eq!(doc! { "$out": { "db": "archive", "coll": "orders" } }, pipeline[0]);
+        assert_eq!(
+            doc! {
+                "$merge": {
+                    "into": { "db": "reporting", "coll": "totals" },
+                    "on": ["region", "month"],
+                    "let": { "total": "$total" },
+                    "whenMatched": [{ "$set": { "total": { "$add": ["$total", "$$total"] } } }],
+                    "whenNotMatched": "insert",
+                }
+            },
+            pipeline[1]
+        );
+        assert_eq!(
+            doc! { "$merge": { "into": "totals", "whenMatched": "keepExisting" } },
+            pipeline[2]
+        );
+    }
+}
@@ -2,7 +2,10 @@
 use bson;
 use bson::Bson;
 use std::collections::BTreeMap;
-use super::error::{BulkWriteException, WriteException};
+use super::error::{BulkWriteException, BulkWriteFailure, WriteException};
+use cursor::Cursor;
+use Error::ResponseError;
+use Result;
 use super::options::WriteModel;
 
 /// Results for a bulk write operation.
@@ -71,6 +74,44 @@ pub struct UpdateResult {
     pub write_exception: Option<WriteException>,
 }
 
+/// Results for a findAndModify operation, with what its `lastErrorObject` reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FindAndModifyResult {
+    /// The original or modified document, as selected by `return_document`.
+    pub value: Option<bson::Document>,
+    /// How many documents were removed, updated or inserted.
+    pub n: i64,
+    /// Whether an existing document was updated or replaced, rather than one inserted by an
+    /// upsert. Always false for deletes.
+    pub updated_existing: bool,
+    /// The `_id` of the document inserted by an upsert.
+    pub upserted_id: Option<Bson>,
+}
+
+/// Results for a mapReduce operation.
+#[derive(Debug)]
+pub enum MapReduceResult {
+    /// The results, for inline output.
+    Inline(Cursor),
+    /// A summary of what was written, for output to a collection.
+    Collection(MapReduceStats),
+}
+
+/// What a mapReduce wrote to its output collection.
+///
+/// Servers from 4.4 on no longer report the counts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapReduceStats {
+    pub collection: String,
+    /// The database of the output collection, if the server reported it.
+    pub db: Option<String>,
+    pub time_millis: Option<i64>,
+    pub input_count: Option<i64>,
+    pub emit_count: Option<i64>,
+    pub reduce_count: Option<i64>,
+    pub output_count: Option<i64>,
+}
+
 impl BulkWriteResult {
     /// Extracts server reply information into a result.
     pub fn new() -> BulkWriteResult {
@@ -87,6 +128,41 @@ impl BulkWriteResult {
         }
     }
 
+    /// Returns what failed, if anything did, with the counts of the writes that succeeded.
+    pub fn failure(&self) -> Option<BulkWriteFailure> {
+        self.bulk_write_exception.as_ref().map(|exception| BulkWriteFailure {
+            write_errors: exception.write_errors.clone(),
+            write_concern_error: exception.write_concern_error.clone(),
+            unprocessed_requests: exception.unprocessed_requests.clone(),
+            inserted_count: self.inserted_count,
+            matched_count: self.matched_count,
+            modified_count: self.modified_count,
+            deleted_count: self.deleted_count,
+            upserted_count: self.upserted_count,
+            message: exception.message.clone(),
+        })
+    }
+
+    // Adds the counts and ids of a single batch, whose positions in the original requests
+    // are given by `positions`.
+    pub(crate) fn merge_batch(&mut self, batch: BulkWriteResult, positions: &[i64]) {
+        let position = |index: i64| positions.get(index as usize).cloned().unwrap_or(index);
+
+        self.inserted_count += batch.inserted_count;
+        self.matched_count += batch.matched_count;
+        self.modified_count += batch.modified_count;
+        self.deleted_count += batch.deleted_count;
+        self.upserted_count += batch.upserted_count;
+
+        for (index, id) in batch.inserted_ids {
+            self.inserted_ids.insert(position(index), id);
+        }
+
+        for (index, id) in batch.upserted_ids {
+            self.upserted_ids.insert(position(index), id);
+        }
+    }
+
     /// Adds the data in a BulkDeleteResult to this result.
     pub fn process_bulk_delete_result(
         &mut self,
@@ -262,6 +338,21 @@ impl InsertManyResult {
             bulk_write_exception: exception,
         }
     }
+
+    /// Returns which documents failed to be inserted, if any did, with the number that were.
+    pub fn failure(&self) -> Option<BulkWriteFailure> {
+        self.bulk_write_exception.as_ref().map(|exception| BulkWriteFailure {
+            write_errors: exception.write_errors.clone(),
+            write_concern_error: exception.write_concern_error.clone(),
+            unprocessed_requests: exception.unprocessed_requests.clone(),
+            inserted_count: self.inserted_ids.as_ref().map_or(0, |ids| ids.len() as i32),
+            matched_count: 0,
+            modified_count: 0,
+            deleted_count: 0,
+            upserted_count: 0,
+            message: exception.message.clone(),
+        })
+    }
 }
 
 impl DeleteResult {
@@ -337,3 +428,56 @@ impl UpdateResult {
         }
     }
 }
+
+impl FindAndModifyResult {
+    /// Extracts server reply information into a result.
+    pub fn new(doc: &bson::Document) -> FindAndModifyResult {
+        let last_error = doc.get_document("lastErrorObject").ok();
+
+        FindAndModifyResult {
+            value: doc.get_document("value").ok().cloned(),
+            n: last_error.and_then(|last_error| integer(last_error.get("n"))).unwrap_or(0),
+            updated_existing: last_error
+                .and_then(|last_error| last_error.get_bool("updatedExisting").ok())
+                .unwrap_or(false),
+            upserted_id: last_error.and_then(|last_error| last_error.get("upserted")).cloned(),
+        }
+    }
+}
+
+impl MapReduceStats {
+    /// Extracts server reply information into a result.
+    pub fn new(doc: &bson::Document) -> Result<MapReduceStats> {
+        let (collection, db) = match doc.get("result") {
+            Some(&Bson::String(ref collection)) => (collection.clone(), None),
+            Some(&Bson::Document(ref result)) => match result.get("collection") {
+                Some(&Bson::String(ref collection)) => {
+                    (collection.clone(), result.get_str("db").ok().map(String::from))
+                }
+                _ => return Err(ResponseError(String::from("mapReduce reported no collection."))),
+            },
+            _ => return Err(ResponseError(String::from("mapReduce reported no collection."))),
+        };
+
+        let counts = doc.get_document("counts").ok();
+        let count = |key| counts.and_then(|counts| integer(counts.get(key)));
+
+        Ok(MapReduceStats {
+            collection: collection,
+            db: db,
+            time_millis: integer(doc.get("timeMillis")),
+            input_count: count("input"),
+            emit_count: count("emit"),
+            reduce_count: count("reduce"),
+            output_count: count("output"),
+        })
+    }
+}
+
+fn integer(value: Option<&Bson>) -> Option<i64> {
+    match value {
+        Some(&Bson::I32(v)) => Some(i64::from(v)),
+        Some(&Bson::I64(v)) => Some(v),
+        _ => None,
+    }
+}
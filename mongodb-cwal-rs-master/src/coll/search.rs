@@ -0,0 +1,467 @@
+//! Atlas Search queries for the `$search` and `$searchMeta` stages.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::pipeline::Pipeline;
+//! # use mongodb::coll::search::{search_score, Autocomplete, Compound, Range, Search, Text};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("media").collection("movies");
+//!
+//! let query = Compound::new()
+//!     .must(Text::new("space adventure", "plot").fuzzy(1))
+//!     .should(Autocomplete::new("star", "title"))
+//!     .filter(Range::new("year").gte(1970).lt(2000));
+//!
+//! let pipeline = Pipeline::new()
+//!     .search(Search::new(query).index("movies"))
+//!     .limit(10)
+//!     .project(doc! { "title": 1, "score": search_score() });
+//!
+//! let cursor = coll.aggregate(pipeline.build(), None).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson, doc};
+
+/// A search operator, such as `Text` or `Compound`, ready to be used in a `Search`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOperator {
+    name: &'static str,
+    spec: bson::Document,
+}
+
+impl SearchOperator {
+    /// Creates an operator without a typed builder, such as `"phrase"` or `"exists"`.
+    pub fn custom(name: &'static str, spec: bson::Document) -> SearchOperator {
+        SearchOperator {
+            name: name,
+            spec: spec,
+        }
+    }
+
+    fn to_bson(&self) -> bson::Document {
+        let mut document = bson::Document::new();
+        document.insert(self.name, self.spec.clone());
+        document
+    }
+}
+
+// A single path as a string, several as an array.
+fn paths_to_bson(paths: &[String]) -> Bson {
+    if paths.len() == 1 {
+        Bson::String(paths[0].clone())
+    } else {
+        Bson::Array(paths.iter().cloned().map(Bson::String).collect())
+    }
+}
+
+fn boost(spec: &mut bson::Document, boost: Option<f64>) {
+    if let Some(boost) = boost {
+        let mut score = bson::Document::new();
+        score.insert("boost", doc! { "value": boost });
+        spec.insert("score", score);
+    }
+}
+
+/// Matches the analyzed words of a query against string fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Text {
+    query: Vec<String>,
+    paths: Vec<String>,
+    max_edits: Option<i32>,
+    boost: Option<f64>,
+}
+
+impl Text {
+    /// Searches the field at `path` for `query`.
+    pub fn new(query: &str, path: &str) -> Text {
+        Text {
+            query: vec![String::from(query)],
+            paths: vec![String::from(path)],
+            max_edits: None,
+            boost: None,
+        }
+    }
+
+    /// Searches for `query` as well.
+    pub fn or_query(mut self, query: &str) -> Text {
+        self.query.push(String::from(query));
+        self
+    }
+
+    /// Searches the field at `path` as well.
+    pub fn path(mut self, path: &str) -> Text {
+        self.paths.push(String::from(path));
+        self
+    }
+
+    /// Matches words up to `max_edits` single character edits away, one or two.
+    pub fn fuzzy(mut self, max_edits: i32) -> Text {
+        self.max_edits = Some(max_edits);
+        self
+    }
+
+    /// Multiplies the score of the matching documents by `factor`.
+    pub fn boost(mut self, factor: f64) -> Text {
+        self.boost = Some(factor);
+        self
+    }
+}
+
+impl From<Text> for SearchOperator {
+    fn from(text: Text) -> Self {
+        let query = if text.query.len() == 1 {
+            Bson::String(text.query[0].clone())
+        } else {
+            Bson::Array(text.query.into_iter().map(Bson::String).collect())
+        };
+
+        let mut spec = doc! {
+            "query": query,
+            "path": paths_to_bson(&text.paths),
+        };
+
+        if let Some(max_edits) = text.max_edits {
+            spec.insert("fuzzy", doc! { "maxEdits": max_edits });
+        }
+
+        boost(&mut spec, text.boost);
+        SearchOperator::custom("text", spec)
+    }
+}
+
+/// In what order the words of an autocomplete query must appear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenOrder {
+    /// In any order, which is the server default.
+    Any,
+    /// Next to each other, in the order of the query.
+    Sequential,
+}
+
+/// Matches the beginning of words as they are typed, on a field indexed for autocompletion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Autocomplete {
+    query: String,
+    path: String,
+    max_edits: Option<i32>,
+    token_order: Option<TokenOrder>,
+    boost: Option<f64>,
+}
+
+impl Autocomplete {
+    /// Completes `query` from the words of the field at `path`.
+    pub fn new(query: &str, path: &str) -> Autocomplete {
+        Autocomplete {
+            query: String::from(query),
+            path: String::from(path),
+            max_edits: None,
+            token_order: None,
+            boost: None,
+        }
+    }
+
+    /// Completes words up to `max_edits` single character edits away, one or two.
+    pub fn fuzzy(mut self, max_edits: i32) -> Autocomplete {
+        self.max_edits = Some(max_edits);
+        self
+    }
+
+    pub fn token_order(mut self, token_order: TokenOrder) -> Autocomplete {
+        self.token_order = Some(token_order);
+        self
+    }
+
+    /// Multiplies the score of the matching documents by `factor`.
+    pub fn boost(mut self, factor: f64) -> Autocomplete {
+        self.boost = Some(factor);
+        self
+    }
+}
+
+impl From<Autocomplete> for SearchOperator {
+    fn from(autocomplete: Autocomplete) -> Self {
+        let mut spec = doc! {
+            "query": autocomplete.query,
+            "path": autocomplete.path,
+        };
+
+        if let Some(max_edits) = autocomplete.max_edits {
+            spec.insert("fuzzy", doc! { "maxEdits": max_edits });
+        }
+
+        match autocomplete.token_order {
+            Some(TokenOrder::Any) => spec.insert("tokenOrder", "any"),
+            Some(TokenOrder::Sequential) => spec.insert("tokenOrder", "sequential"),
+            None => None,
+        };
+
+        boost(&mut spec, autocomplete.boost);
+        SearchOperator::custom("autocomplete", spec)
+    }
+}
+
+/// Matches numbers or dates within bounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    paths: Vec<String>,
+    // Bounds by operator, in the order they were set.
+    bounds: Vec<(&'static str, Bson)>,
+    boost: Option<f64>,
+}
+
+impl Range {
+    /// Matches the field at `path` against the bounds set with `gt`, `gte`, `lt` and `lte`.
+    pub fn new(path: &str) -> Range {
+        Range {
+            paths: vec![String::from(path)],
+            bounds: Vec::new(),
+            boost: None,
+        }
+    }
+
+    /// Matches the field at `path` as well.
+    pub fn path(mut self, path: &str) -> Range {
+        self.paths.push(String::from(path));
+        self
+    }
+
+    fn bound<T: Into<Bson>>(mut self, operator: &'static str, value: T) -> Range {
+        self.bounds.retain(|&(existing, _)| existing != operator);
+        self.bounds.push((operator, value.into()));
+        self
+    }
+
+    pub fn gt<T: Into<Bson>>(self, value: T) -> Range {
+        self.bound("gt", value)
+    }
+
+    pub fn gte<T: Into<Bson>>(self, value: T) -> Range {
+        self.bound("gte", value)
+    }
+
+    pub fn lt<T: Into<Bson>>(self, value: T) -> Range {
+        self.bound("lt", value)
+    }
+
+    pub fn lte<T: Into<Bson>>(self, value: T) -> Range {
+        self.bound("lte", value)
+    }
+
+    /// Multiplies the score of the matching documents by `factor`.
+    pub fn boost(mut self, factor: f64) -> Range {
+        self.boost = Some(factor);
+        self
+    }
+}
+
+impl From<Range> for SearchOperator {
+    fn from(range: Range) -> Self {
+        let mut spec = doc! { "path": paths_to_bson(&range.paths) };
+
+        for (operator, value) in range.bounds {
+            spec.insert(operator, value);
+        }
+
+        boost(&mut spec, range.boost);
+        SearchOperator::custom("range", spec)
+    }
+}
+
+/// Combines operators: documents must match every `must` and `filter` clause and no
+/// `must_not` clause, and score higher for each `should` clause they match.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    must: Vec<SearchOperator>,
+    must_not: Vec<SearchOperator>,
+    should: Vec<SearchOperator>,
+    filter: Vec<SearchOperator>,
+    minimum_should_match: Option<i32>,
+}
+
+impl Compound {
+    pub fn new() -> Compound {
+        Default::default()
+    }
+
+    pub fn must<T: Into<SearchOperator>>(mut self, operator: T) -> Compound {
+        self.must.push(operator.into());
+        self
+    }
+
+    pub fn must_not<T: Into<SearchOperator>>(mut self, operator: T) -> Compound {
+        self.must_not.push(operator.into());
+        self
+    }
+
+    pub fn should<T: Into<SearchOperator>>(mut self, operator: T) -> Compound {
+        self.should.push(operator.into());
+        self
+    }
+
+    /// Like `must`, without affecting the score.
+    pub fn filter<T: Into<SearchOperator>>(mut self, operator: T) -> Compound {
+        self.filter.push(operator.into());
+        self
+    }
+
+    /// Only matches documents matching at least `count` of the `should` clauses.
+    pub fn minimum_should_match(mut self, count: i32) -> Compound {
+        self.minimum_should_match = Some(count);
+        self
+    }
+}
+
+impl From<Compound> for SearchOperator {
+    fn from(compound: Compound) -> Self {
+        let mut spec = bson::Document::new();
+
+        let clauses = vec![
+            ("must", compound.must),
+            ("mustNot", compound.must_not),
+            ("should", compound.should),
+            ("filter", compound.filter),
+        ];
+
+        for (clause, operators) in clauses {
+            if !operators.is_empty() {
+                let operators = operators
+                    .iter()
+                    .map(|operator| Bson::Document(operator.to_bson()))
+                    .collect::<Vec<_>>();
+                spec.insert(clause, operators);
+            }
+        }
+
+        if let Some(count) = compound.minimum_should_match {
+            spec.insert("minimumShouldMatch", count);
+        }
+
+        SearchOperator::custom("compound", spec)
+    }
+}
+
+/// How `$searchMeta` and the `$$SEARCH_META` variable count the matching documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SearchCount {
+    /// Counts exactly up to a threshold, then approximately; the server default.
+    LowerBound,
+    /// Counts exactly, which is slower.
+    Total,
+}
+
+/// The operator and settings of a `$search` or `$searchMeta` stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Search {
+    operator: SearchOperator,
+    index: Option<String>,
+    count: Option<SearchCount>,
+}
+
+impl Search {
+    /// Searches with `operator` using the index named `default`.
+    pub fn new<T: Into<SearchOperator>>(operator: T) -> Search {
+        Search {
+            operator: operator.into(),
+            index: None,
+            count: None,
+        }
+    }
+
+    /// Searches with the index named `index` instead.
+    pub fn index(mut self, index: &str) -> Search {
+        self.index = Some(String::from(index));
+        self
+    }
+
+    pub fn count(mut self, count: SearchCount) -> Search {
+        self.count = Some(count);
+        self
+    }
+}
+
+impl From<Search> for bson::Document {
+    fn from(search: Search) -> Self {
+        let mut document = bson::Document::new();
+
+        if let Some(index) = search.index {
+            document.insert("index", index);
+        }
+
+        document.insert(search.operator.name, search.operator.spec);
+
+        match search.count {
+            Some(SearchCount::LowerBound) => {
+                document.insert("count", doc! { "type": "lowerBound" });
+            }
+            Some(SearchCount::Total) => {
+                document.insert("count", doc! { "type": "total" });
+            }
+            None => (),
+        }
+
+        document
+    }
+}
+
+/// Returns `{ "$meta": "searchScore" }`, which projects the relevance of a document to the
+/// `$search` stage, e.g. `doc! { "score": search_score() }`.
+pub fn search_score() -> bson::Document {
+    let mut meta = bson::Document::new();
+    meta.insert("$meta", "searchScore");
+    meta
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builds_compound_queries() {
+        let query = Compound::new()
+            .must(Text::new("space", "plot").path("title").fuzzy(1))
+            .should(Autocomplete::new("sta", "title").token_order(TokenOrder::Sequential))
+            .filter(Range::new("year").gte(1970).lt(2000).gte(1980))
+            .minimum_should_match(0);
+
+        let expected = doc! {
+            "index": "movies",
+            "compound": {
+                "must": [{
+                    "text": {
+                        "query": "space",
+                        "path": ["plot", "title"],
+                        "fuzzy": { "maxEdits": 1 },
+                    },
+                }],
+                "should": [{
+                    "autocomplete": { "query": "sta", "path": "title", "tokenOrder": "sequential" },
+                }],
+                "filter": [{ "range": { "path": "year", "lt": 2000, "gte": 1980 } }],
+                "minimumShouldMatch": 0,
+            },
+            "count": { "type": "total" },
+        };
+
+        let search = Search::new(query).index("movies").count(SearchCount::Total);
+        assert_eq!(expected, bson::Document::from(search));
+    }
+
+    #[test]
+    fn boosts_scores() {
+        let search = Search::new(Text::new("a", "b").or_query("c").boost(2.0));
+        let expected = doc! {
+            "text": {
+                "query": ["a", "c"],
+                "path": "b",
+                "score": { "boost": { "value": 2.0 } },
+            },
+        };
+        assert_eq!(expected, bson::Document::from(search));
+    }
+}
@@ -0,0 +1,176 @@
+//! Grouping of time-series measurements into per-bucket inserts.
+use bson::{self, Bson, doc};
+
+use super::options::TimeSeriesInsertOptions;
+use Error::ArgumentError;
+use Result;
+
+use std::collections::HashMap;
+
+/// Measurements that land in the same bucket, along with their positions in the input.
+#[derive(Debug, Default, PartialEq)]
+pub struct MeasurementGroup {
+    pub indices: Vec<i64>,
+    pub docs: Vec<bson::Document>,
+}
+
+/// Groups measurements by their meta field value and the time window their time field falls
+/// into. Groups are returned in order of their first measurement, and measurements keep
+/// their relative order within a group.
+///
+/// Unless `ordered` is false, only consecutive measurements are grouped together, so that
+/// sending the groups in turn keeps the measurements in their input order.
+pub fn group_measurements(
+    docs: Vec<bson::Document>,
+    options: &TimeSeriesInsertOptions,
+) -> Result<Vec<MeasurementGroup>> {
+    if options.window_secs <= 0 {
+        return Err(ArgumentError(String::from(
+            "The time-series window must be at least one second.",
+        )));
+    }
+
+    let window_ms = match options.window_secs.checked_mul(1000) {
+        Some(window_ms) => window_ms,
+        None => {
+            return Err(ArgumentError(format!(
+                "The time-series window of {} seconds is too large.",
+                options.window_secs
+            )))
+        }
+    };
+
+    let ordered = options.ordered.unwrap_or(true);
+    let mut positions: HashMap<(Vec<u8>, i64), usize> = HashMap::new();
+    let mut groups: Vec<MeasurementGroup> = Vec::new();
+
+    for (index, doc) in docs.into_iter().enumerate() {
+        let millis = match doc.get(&options.time_field) {
+            Some(&Bson::UtcDatetime(ref time)) => time.timestamp_millis(),
+            _ => {
+                return Err(ArgumentError(format!(
+                    "Measurement {} has no date in its '{}' field.",
+                    index,
+                    options.time_field
+                )))
+            }
+        };
+
+        // Encoding the meta value keeps values of different BSON types apart.
+        let meta = match options.meta_field {
+            Some(ref field) => doc.get(field).cloned().unwrap_or(Bson::Null),
+            None => Bson::Null,
+        };
+
+        let mut meta_key = Vec::new();
+        bson::encode_document(&mut meta_key, &doc! { "meta": meta })?;
+
+        let key = (meta_key, div_floor(millis, window_ms));
+        let position = match positions.get(&key) {
+            Some(&position) if !ordered || position + 1 == groups.len() => position,
+            _ => {
+                groups.push(MeasurementGroup::default());
+                positions.insert(key, groups.len() - 1);
+                groups.len() - 1
+            }
+        };
+
+        groups[position].indices.push(index as i64);
+        groups[position].docs.push(doc);
+    }
+
+    Ok(groups)
+}
+
+// Rounds towards negative infinity, so measurements before the epoch share windows too.
+fn div_floor(value: i64, divisor: i64) -> i64 {
+    let quotient = value / divisor;
+
+    if value % divisor < 0 {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn measurement(sensor: i32, secs: i64) -> bson::Document {
+        doc! {
+            "sensor": sensor,
+            "ts": Bson::UtcDatetime(Utc.timestamp_opt(secs, 0).unwrap()),
+        }
+    }
+
+    fn options() -> TimeSeriesInsertOptions {
+        let mut options = TimeSeriesInsertOptions::new("ts");
+        options.meta_field = Some(String::from("sensor"));
+        options.window_secs = 60;
+        options
+    }
+
+    #[test]
+    fn groups_by_meta_and_window() {
+        let docs = vec![
+            measurement(1, 0),
+            measurement(2, 10),
+            measurement(1, 59),
+            measurement(1, 60),
+            measurement(2, 30),
+        ];
+
+        let mut options = options();
+        options.ordered = Some(false);
+        let groups = group_measurements(docs, &options).unwrap();
+        let indices: Vec<_> = groups.iter().map(|group| group.indices.clone()).collect();
+
+        assert_eq!(vec![vec![0, 2], vec![1, 4], vec![3]], indices);
+        assert_eq!(measurement(1, 59), groups[0].docs[1]);
+    }
+
+    #[test]
+    fn ordered_groups_keep_input_order() {
+        let docs = vec![
+            measurement(1, 0),
+            measurement(1, 5),
+            measurement(2, 10),
+            measurement(1, 59),
+        ];
+
+        let groups = group_measurements(docs, &options()).unwrap();
+        let indices: Vec<_> = groups.iter().map(|group| group.indices.clone()).collect();
+
+        assert_eq!(vec![vec![0, 1], vec![2], vec![3]], indices);
+    }
+
+    #[test]
+    fn rejects_overflowing_windows() {
+        let mut options = options();
+        options.window_secs = i64::max_value();
+
+        match group_measurements(vec![measurement(1, 0)], &options) {
+            Err(ArgumentError(msg)) => assert!(msg.contains("too large")),
+            other => panic!("expected an argument error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn windows_before_epoch() {
+        let groups = group_measurements(vec![measurement(1, -1), measurement(1, 0)], &options())
+            .unwrap();
+        assert_eq!(2, groups.len());
+    }
+
+    #[test]
+    fn rejects_missing_time() {
+        let docs = vec![measurement(1, 0), doc! { "sensor": 1 }];
+
+        match group_measurements(docs, &options()) {
+            Err(ArgumentError(msg)) => assert!(msg.starts_with("Measurement 1")),
+            other => panic!("expected an argument error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Collections of documents mapped to and from Rust types with serde.
+//!
+//! Results of untyped collections can be decoded the same way, with `Collection::find_as`,
+//! `Collection::aggregate_as` or `Cursor::deserialize`.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! # #[macro_use] extern crate serde_derive;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! #[derive(Serialize, Deserialize, Debug)]
+//! struct Movie {
+//!     title: String,
+//!     year: i32,
+//! }
+//!
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let movies = client.db("media").typed_collection::<Movie>("movies");
+//!
+//! movies.insert_one(&Movie { title: String::from("Jaws"), year: 1975 }, None).unwrap();
+//!
+//! for movie in movies.find(Some(doc! { "year": { "$lt": 1980 } }), None).unwrap() {
+//!     println!("{:?}", movie.unwrap());
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::Collection;
+use super::options::{AggregateOptions, FindOneAndDeleteOptions, FindOneAndUpdateOptions,
+                     FindOptions, InsertManyOptions, ReplaceOptions};
+use super::results::{InsertManyResult, InsertOneResult, UpdateResult};
+use common::WriteConcern;
+use cursor::Cursor;
+use Error::{ArgumentError, DecoderError};
+use Result;
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// A collection whose documents are (de)serialized as `T`.
+///
+/// Filters, updates and other operations not involving whole documents go through the
+/// untyped `Collection`, available with `untyped`.
+pub struct TypedCollection<T> {
+    coll: Collection,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for TypedCollection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedCollection")
+            .field("namespace", &self.coll.namespace)
+            .finish()
+    }
+}
+
+impl<T> TypedCollection<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Wraps an untyped collection.
+    pub fn new(coll: Collection) -> TypedCollection<T> {
+        TypedCollection {
+            coll: coll,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the underlying untyped collection.
+    pub fn untyped(&self) -> &Collection {
+        &self.coll
+    }
+
+    /// Returns the name of the collection.
+    pub fn name(&self) -> String {
+        self.coll.name()
+    }
+
+    /// Returns the documents matching the filter.
+    pub fn find(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<TypedCursor<T>> {
+        self.coll.find(filter, options).map(TypedCursor::new)
+    }
+
+    /// Returns the first document matching the filter, or None.
+    pub fn find_one(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<Option<T>> {
+        self.coll.find_one(filter, options)?.map(from_document).transpose()
+    }
+
+    /// Finds a single document and deletes it, returning the original.
+    pub fn find_one_and_delete(
+        &self,
+        filter: bson::Document,
+        options: Option<FindOneAndDeleteOptions>,
+    ) -> Result<Option<T>> {
+        self.coll.find_one_and_delete(filter, options)?.map(from_document).transpose()
+    }
+
+    /// Finds a single document and replaces it, returning either the original or the
+    /// replaced document.
+    pub fn find_one_and_replace(
+        &self,
+        filter: bson::Document,
+        replacement: &T,
+        options: Option<FindOneAndUpdateOptions>,
+    ) -> Result<Option<T>> {
+        let replacement = to_document(replacement)?;
+        self.coll.find_one_and_replace(filter, replacement, options)?
+            .map(from_document)
+            .transpose()
+    }
+
+    /// Finds a single document and updates it, returning either the original or the
+    /// updated document.
+    pub fn find_one_and_update(
+        &self,
+        filter: bson::Document,
+        update: bson::Document,
+        options: Option<FindOneAndUpdateOptions>,
+    ) -> Result<Option<T>> {
+        self.coll.find_one_and_update(filter, update, options)?
+            .map(from_document)
+            .transpose()
+    }
+
+    /// Inserts the provided document.
+    pub fn insert_one(&self, doc: &T, write_concern: Option<WriteConcern>)
+        -> Result<InsertOneResult> {
+        self.coll.insert_one(to_document(doc)?, write_concern)
+    }
+
+    /// Inserts the provided documents.
+    pub fn insert_many(&self, docs: &[T], options: Option<InsertManyOptions>)
+        -> Result<InsertManyResult> {
+        let docs = docs.iter().map(to_document).collect::<Result<Vec<_>>>()?;
+        self.coll.insert_many(docs, options)
+    }
+
+    /// Replaces a single document.
+    pub fn replace_one(
+        &self,
+        filter: bson::Document,
+        replacement: &T,
+        options: Option<ReplaceOptions>,
+    ) -> Result<UpdateResult> {
+        self.coll.replace_one(filter, to_document(replacement)?, options)
+    }
+}
+
+/// A cursor decoding each document into `T`.
+pub struct TypedCursor<T> {
+    cursor: Cursor,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for TypedCursor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedCursor").field("cursor", &self.cursor).finish()
+    }
+}
+
+impl<T: DeserializeOwned> TypedCursor<T> {
+    /// Wraps an untyped cursor.
+    pub fn new(cursor: Cursor) -> TypedCursor<T> {
+        TypedCursor {
+            cursor: cursor,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the underlying untyped cursor.
+    pub fn into_inner(self) -> Cursor {
+        self.cursor
+    }
+
+    /// Checks whether there are any more documents for the cursor to return.
+    pub fn has_next(&mut self) -> Result<bool> {
+        self.cursor.has_next()
+    }
+
+    /// Decodes the documents the cursor has buffered, fetching the next batch if there are
+    /// none.
+    pub fn drain_current_batch(&mut self) -> Result<Vec<T>> {
+        self.cursor.drain_current_batch()?.into_iter().map(from_document).collect()
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for TypedCursor<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.cursor.next().map(|result| result.and_then(from_document))
+    }
+}
+
+impl Cursor {
+    /// Decodes each remaining document into `T`.
+    pub fn deserialize<T: DeserializeOwned>(self) -> TypedCursor<T> {
+        TypedCursor::new(self)
+    }
+}
+
+impl Collection {
+    /// Returns the documents matching the filter, decoded into `T`.
+    pub fn find_as<T: DeserializeOwned>(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<TypedCursor<T>> {
+        self.find(filter, options).map(TypedCursor::new)
+    }
+
+    /// Runs an aggregation framework pipeline, decoding each result into `T`.
+    pub fn aggregate_as<T: DeserializeOwned>(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: Option<AggregateOptions>,
+    ) -> Result<TypedCursor<T>> {
+        self.aggregate(pipeline, options).map(TypedCursor::new)
+    }
+}
+
+/// Serializes a value that must map to a BSON document, such as a struct.
+pub fn to_document<T: Serialize>(value: &T) -> Result<bson::Document> {
+    match bson::to_bson(value)? {
+        Bson::Document(doc) => Ok(doc),
+        other => Err(ArgumentError(format!(
+            "Expected a value that serializes to a document, got {:?}.",
+            other
+        ))),
+    }
+}
+
+/// Deserializes a document into a value.
+pub fn from_document<T: DeserializeOwned>(doc: bson::Document) -> Result<T> {
+    bson::from_bson(Bson::Document(doc)).map_err(DecoderError)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use common::{ReadMode, ReadPreference};
+    use {Client, CommandType, ThreadedClient};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Movie {
+        title: String,
+        year: i32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        director: Option<String>,
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let movie = Movie {
+            title: String::from("Jaws"),
+            year: 1975,
+            director: None,
+        };
+
+        let doc = to_document(&movie).unwrap();
+        assert_eq!(doc! { "title": "Jaws", "year": 1975 }, doc);
+        assert_eq!(movie, from_document::<Movie>(doc).unwrap());
+    }
+
+    #[test]
+    fn decodes_cursor_documents() {
+        let cursor = Cursor::with_documents(
+            Client::connect("localhost", 27017).unwrap(),
+            String::from("media.movies"),
+            vec![doc! { "title": "Jaws", "year": 1975 }, doc! { "title": "Jaws" }]
+                .into_iter()
+                .collect(),
+            CommandType::Find,
+            ReadPreference::new(ReadMode::Primary, None),
+        );
+
+        let mut movies = cursor.deserialize::<Movie>();
+        assert_eq!(1975, movies.next().unwrap().unwrap().year);
+
+        match movies.next() {
+            Some(Err(DecoderError(_))) => (),
+            other => panic!("expected a decoder error, got {:?}", other),
+        }
+        assert!(!movies.has_next().unwrap());
+    }
+
+    #[test]
+    fn maps_serde_errors() {
+        match to_document(&5) {
+            Err(ArgumentError(_)) => (),
+            other => panic!("expected an argument error, got {:?}", other),
+        }
+
+        match from_document::<Movie>(doc! { "title": "Jaws" }) {
+            Err(DecoderError(_)) => (),
+            other => panic!("expected a decoder error, got {:?}", other),
+        }
+    }
+}
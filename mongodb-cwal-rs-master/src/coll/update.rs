@@ -0,0 +1,256 @@
+//! Builder for update documents.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::update::Update;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("orders");
+//!
+//! let update = Update::new()
+//!     .set("status", "shipped")
+//!     .inc("attempts", 1)
+//!     .push("history", "shipped")
+//!     .unset("eta")
+//!     .build()
+//!     .unwrap();
+//!
+//! coll.update_one(doc! { "_id": 1 }, update, None).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson};
+
+use Error::ArgumentError;
+use Result;
+
+/// An update made of field operators, such as `$set` and `$inc`.
+///
+/// `build` checks the update before it is sent: it must change something, every operator must
+/// start with `$`, and no two operators may touch the same path or a path and its parent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Update {
+    // (operator, path, argument), in the order they were added.
+    changes: Vec<(String, String, Bson)>,
+}
+
+impl Update {
+    /// Creates an empty update.
+    pub fn new() -> Update {
+        Default::default()
+    }
+
+    /// Adds an arbitrary `{ <operator>: { <path>: <argument> } }` change.
+    pub fn operator<T: Into<Bson>>(mut self, operator: &str, path: &str, argument: T)
+        -> Update {
+        self.changes.push((String::from(operator), String::from(path), argument.into()));
+        self
+    }
+
+    /// Sets the field at `path` to `value`.
+    pub fn set<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$set", path, value)
+    }
+
+    /// Sets the field at `path` to `value` only if the update inserts a document.
+    pub fn set_on_insert<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$setOnInsert", path, value)
+    }
+
+    /// Removes the field at `path`.
+    pub fn unset(self, path: &str) -> Update {
+        self.operator("$unset", path, "")
+    }
+
+    /// Increments the field at `path` by `amount`.
+    pub fn inc<T: Into<Bson>>(self, path: &str, amount: T) -> Update {
+        self.operator("$inc", path, amount)
+    }
+
+    /// Multiplies the field at `path` by `factor`.
+    pub fn mul<T: Into<Bson>>(self, path: &str, factor: T) -> Update {
+        self.operator("$mul", path, factor)
+    }
+
+    /// Sets the field at `path` to `value` if `value` is smaller.
+    pub fn min<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$min", path, value)
+    }
+
+    /// Sets the field at `path` to `value` if `value` is larger.
+    pub fn max<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$max", path, value)
+    }
+
+    /// Renames the field at `path` to `new_path`.
+    pub fn rename(self, path: &str, new_path: &str) -> Update {
+        self.operator("$rename", path, new_path)
+    }
+
+    /// Sets the field at `path` to the current date.
+    pub fn current_date(self, path: &str) -> Update {
+        self.operator("$currentDate", path, true)
+    }
+
+    /// Appends `value` to the array at `path`.
+    pub fn push<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$push", path, value)
+    }
+
+    /// Appends every one of `values` to the array at `path`.
+    pub fn push_each<I, T>(self, path: &str, values: I) -> Update
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$push", path, each(values))
+    }
+
+    /// Adds `value` to the array at `path` unless it is already present.
+    pub fn add_to_set<T: Into<Bson>>(self, path: &str, value: T) -> Update {
+        self.operator("$addToSet", path, value)
+    }
+
+    /// Adds each of `values` missing from the array at `path`.
+    pub fn add_to_set_each<I, T>(self, path: &str, values: I) -> Update
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$addToSet", path, each(values))
+    }
+
+    /// Removes the first element of the array at `path`.
+    pub fn pop_first(self, path: &str) -> Update {
+        self.operator("$pop", path, -1)
+    }
+
+    /// Removes the last element of the array at `path`.
+    pub fn pop_last(self, path: &str) -> Update {
+        self.operator("$pop", path, 1)
+    }
+
+    /// Removes the elements of the array at `path` equal to, or matching, `condition`.
+    pub fn pull<T: Into<Bson>>(self, path: &str, condition: T) -> Update {
+        self.operator("$pull", path, condition)
+    }
+
+    /// Removes every element of the array at `path` equal to one of `values`.
+    pub fn pull_all<I, T>(self, path: &str, values: I) -> Update
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Bson>,
+    {
+        self.operator("$pullAll", path, Bson::Array(values.into_iter().map(Into::into).collect()))
+    }
+
+    /// Checks the update and returns its document.
+    pub fn build(self) -> Result<bson::Document> {
+        if self.changes.is_empty() {
+            return Err(ArgumentError(String::from("Update must change at least one field.")));
+        }
+
+        let mut paths: Vec<String> = Vec::new();
+        let mut doc = bson::Document::new();
+
+        for (operator, path, argument) in self.changes {
+            if !operator.starts_with('$') {
+                return Err(ArgumentError(format!(
+                    "Update only works with $ operators, got '{}'.",
+                    operator
+                )));
+            }
+
+            if path.is_empty() {
+                return Err(ArgumentError(format!("{} requires a non-empty path.", operator)));
+            }
+
+            let mut touched = vec![path.clone()];
+            if operator == "$rename" {
+                if let Bson::String(ref new_path) = argument {
+                    touched.push(new_path.clone());
+                }
+            }
+
+            for path in touched {
+                if let Some(existing) = paths.iter().find(|existing| conflicts(existing, &path)) {
+                    return Err(ArgumentError(format!(
+                        "Updating the path '{}' would create a conflict at '{}'.",
+                        path,
+                        existing
+                    )));
+                }
+                paths.push(path);
+            }
+
+            if !doc.contains_key(&operator) {
+                doc.insert(operator.clone(), bson::Document::new());
+            }
+
+            if let Some(Bson::Document(fields)) = doc.get_mut(&operator) {
+                fields.insert(path, argument);
+            }
+        }
+
+        Ok(doc)
+    }
+}
+
+// Whether two paths name the same field or one lies within the other.
+fn conflicts(a: &str, b: &str) -> bool {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    longer.starts_with(shorter) &&
+        (longer.len() == shorter.len() || longer.as_bytes()[shorter.len()] == b'.')
+}
+
+fn each<I, T>(values: I) -> Bson
+where
+    I: IntoIterator<Item = T>,
+    T: Into<Bson>,
+{
+    let mut modifier = bson::Document::new();
+    modifier.insert("$each", Bson::Array(values.into_iter().map(Into::into).collect()));
+    Bson::Document(modifier)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn groups_changes_by_operator() {
+        let update = Update::new()
+            .set("status", "shipped")
+            .inc("attempts", 1)
+            .set("carrier.name", "ups")
+            .push_each("history", vec!["packed", "shipped"])
+            .unset("eta")
+            .build()
+            .unwrap();
+
+        let expected = doc! {
+            "$set": { "status": "shipped", "carrier.name": "ups" },
+            "$inc": { "attempts": 1 },
+            "$push": { "history": { "$each": ["packed", "shipped"] } },
+            "$unset": { "eta": "" },
+        };
+
+        assert_eq!(expected, update);
+    }
+
+    #[test]
+    fn rejects_invalid_updates() {
+        assert!(Update::new().build().is_err());
+        assert!(Update::new().operator("status", "a", 1).build().is_err());
+        assert!(Update::new().set("a.b", 1).unset("a").build().is_err());
+        assert!(Update::new().inc("n", 1).rename("m", "n").build().is_err());
+
+        // Sibling paths sharing a prefix do not conflict.
+        assert!(Update::new().set("ab", 1).unset("a").set("a2.b", 2).build().is_ok());
+    }
+}
@@ -4,11 +4,17 @@
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum CommandType {
     Aggregate,
+    AggregateWrite,
     BuildInfo,
+    CloneCollectionAsCapped,
+    CollStats,
+    ConfigureFailPoint,
+    ConvertToCapped,
     Count,
     CreateCollection,
     CreateIndexes,
     CreateUser,
+    DbStats,
     DeleteMany,
     DeleteOne,
     Distinct,
@@ -17,32 +23,54 @@ pub enum CommandType {
     DropDatabase,
     DropIndexes,
     DropUser,
+    Explain,
     Find,
     FindOneAndDelete,
     FindOneAndReplace,
     FindOneAndUpdate,
+    FsyncLock,
+    FsyncUnlock,
+    GetCmdLineOpts,
     GetUser,
     GetUsers,
+    HostInfo,
     InsertMany,
     InsertOne,
     IsMaster,
+    KillSessions,
     ListCollections,
     ListDatabases,
     ListIndexes,
+    ListSessions,
+    MapReduce,
+    MapReduceWrite,
+    ModifyCollection,
+    Ping,
+    Profile,
+    RenameCollection,
+    RunCommand,
+    ServerStatus,
     Suppressed,
     UpdateMany,
     UpdateOne,
+    UpdateUser,
 }
 
 impl CommandType {
     pub fn to_str(&self) -> &str {
         match *self {
             CommandType::Aggregate => "aggregate",
+            CommandType::AggregateWrite => "aggregate_write",
             CommandType::BuildInfo => "buildinfo",
+            CommandType::CloneCollectionAsCapped => "clone_collection_as_capped",
+            CommandType::CollStats => "coll_stats",
+            CommandType::ConfigureFailPoint => "configure_fail_point",
+            CommandType::ConvertToCapped => "convert_to_capped",
             CommandType::Count => "count",
             CommandType::CreateCollection => "create_collection",
             CommandType::CreateIndexes => "create_indexes",
             CommandType::CreateUser => "create_user",
+            CommandType::DbStats => "db_stats",
             CommandType::DeleteMany => "delete_many",
             CommandType::DeleteOne => "delete_one",
             CommandType::Distinct => "distinct",
@@ -51,26 +79,45 @@ impl CommandType {
             CommandType::DropDatabase => "drop_database",
             CommandType::DropIndexes => "drop_indexes",
             CommandType::DropUser => "drop_user",
+            CommandType::Explain => "explain",
             CommandType::Find => "find",
             CommandType::FindOneAndDelete => "find_one_and_delete",
             CommandType::FindOneAndReplace => "find_one_and_replace",
             CommandType::FindOneAndUpdate => "find_one_and_update",
+            CommandType::FsyncLock => "fsync_lock",
+            CommandType::FsyncUnlock => "fsync_unlock",
+            CommandType::GetCmdLineOpts => "get_cmd_line_opts",
             CommandType::GetUser => "get_user",
             CommandType::GetUsers => "get_users",
+            CommandType::HostInfo => "host_info",
             CommandType::InsertMany => "insert_many",
             CommandType::InsertOne => "insert_one",
             CommandType::IsMaster => "is_master",
+            CommandType::KillSessions => "kill_sessions",
             CommandType::ListCollections => "list_collections",
             CommandType::ListDatabases => "list_databases",
             CommandType::ListIndexes => "list_indexes",
+            CommandType::ListSessions => "list_sessions",
+            CommandType::MapReduce => "map_reduce",
+            CommandType::MapReduceWrite => "map_reduce_write",
+            CommandType::ModifyCollection => "modify_collection",
+            CommandType::Ping => "ping",
+            CommandType::Profile => "profile",
+            CommandType::RenameCollection => "rename_collection",
+            CommandType::RunCommand => "run_command",
+            CommandType::ServerStatus => "server_status",
             CommandType::Suppressed => "suppressed",
             CommandType::UpdateMany => "update_many",
             CommandType::UpdateOne => "update_one",
+            CommandType::UpdateUser => "update_user",
         }
     }
 
     pub fn is_write_command(&self) -> bool {
         match *self {
+            CommandType::AggregateWrite |
+            CommandType::CloneCollectionAsCapped |
+            CommandType::ConvertToCapped |
             CommandType::CreateCollection |
             CommandType::CreateIndexes |
             CommandType::CreateUser |
@@ -84,21 +131,40 @@ impl CommandType {
             CommandType::FindOneAndDelete |
             CommandType::FindOneAndReplace |
             CommandType::FindOneAndUpdate |
+            CommandType::FsyncLock |
+            CommandType::FsyncUnlock |
             CommandType::InsertMany |
             CommandType::InsertOne |
+            CommandType::MapReduceWrite |
+            CommandType::ModifyCollection |
+            CommandType::RenameCollection |
             CommandType::UpdateMany |
-            CommandType::UpdateOne => true,
+            CommandType::UpdateOne |
+            CommandType::UpdateUser => true,
             CommandType::Aggregate |
             CommandType::BuildInfo |
+            CommandType::CollStats |
+            CommandType::ConfigureFailPoint |
             CommandType::Count |
+            CommandType::DbStats |
             CommandType::Distinct |
+            CommandType::Explain |
             CommandType::Find |
+            CommandType::GetCmdLineOpts |
             CommandType::GetUser |
             CommandType::GetUsers |
+            CommandType::HostInfo |
             CommandType::IsMaster |
+            CommandType::KillSessions |
             CommandType::ListCollections |
             CommandType::ListDatabases |
             CommandType::ListIndexes |
+            CommandType::ListSessions |
+            CommandType::MapReduce |
+            CommandType::Ping |
+            CommandType::Profile |
+            CommandType::RunCommand |
+            CommandType::ServerStatus |
             CommandType::Suppressed => false,
         }
     }
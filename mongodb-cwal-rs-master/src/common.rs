@@ -4,6 +4,7 @@ use Result;
 
 use bson::{self, bson, Bson, doc};
 use std::collections::BTreeMap;
+use std::fmt;
 use std::str::FromStr;
 
 /// Indicates how a server should be selected during read operations.
@@ -40,6 +41,11 @@ pub struct ReadPreference {
     pub mode: ReadMode,
     /// Filters servers based on the first tag set that matches at least one server.
     pub tag_sets: Vec<BTreeMap<String, String>>,
+    /// Preferences tried in order when no server matches this one.
+    ///
+    /// Fallbacks are evaluated by the driver during server selection; a mongos only
+    /// receives the first preference of the chain.
+    pub fallbacks: Vec<ReadPreference>,
 }
 
 impl ReadPreference {
@@ -47,23 +53,55 @@ impl ReadPreference {
         ReadPreference {
             mode: mode,
             tag_sets: tag_sets.unwrap_or_else(Vec::new),
+            fallbacks: Vec::new(),
         }
     }
 
+    /// Appends a preference to the fallback chain.
+    ///
+    /// ```
+    /// # use mongodb::common::{ReadMode, ReadPreference};
+    /// # use std::collections::BTreeMap;
+    /// let mut local = BTreeMap::new();
+    /// local.insert(String::from("dc"), String::from("east"));
+    ///
+    /// // Any secondary if none is in the local data center, then the primary.
+    /// let read_pref = ReadPreference::new(ReadMode::Secondary, Some(vec![local]))
+    ///     .with_fallback(ReadPreference::new(ReadMode::Secondary, None))
+    ///     .with_fallback(ReadPreference::new(ReadMode::Primary, None));
+    /// ```
+    pub fn with_fallback(mut self, fallback: ReadPreference) -> ReadPreference {
+        self.fallbacks.push(fallback);
+        self
+    }
+
+    /// Returns the preference in the form of the `$readPreference` sent to a mongos.
     pub fn to_document(&self) -> bson::Document {
-        let mut doc = doc! { "mode": stringify!(self.mode).to_ascii_lowercase() };
-        let bson_tag_sets: Vec<_> = self.tag_sets
-            .iter()
-            .map(|map| {
-                let mut bson_map = bson::Document::new();
-                for (key, val) in map.iter() {
-                    bson_map.insert(&key[..], Bson::String(val.to_owned()));
-                }
-                Bson::Document(bson_map)
-            })
-            .collect();
-
-        doc.insert("tag_sets", Bson::Array(bson_tag_sets));
+        let mode = match self.mode {
+            ReadMode::Primary => "primary",
+            ReadMode::PrimaryPreferred => "primaryPreferred",
+            ReadMode::Secondary => "secondary",
+            ReadMode::SecondaryPreferred => "secondaryPreferred",
+            ReadMode::Nearest => "nearest",
+        };
+
+        let mut doc = doc! { "mode": mode };
+
+        if !self.tag_sets.is_empty() {
+            let bson_tag_sets: Vec<_> = self.tag_sets
+                .iter()
+                .map(|map| {
+                    let mut bson_map = bson::Document::new();
+                    for (key, val) in map.iter() {
+                        bson_map.insert(&key[..], Bson::String(val.to_owned()));
+                    }
+                    Bson::Document(bson_map)
+                })
+                .collect();
+
+            doc.insert("tags", Bson::Array(bson_tag_sets));
+        }
+
         doc
     }
 }
@@ -105,6 +143,103 @@ impl Default for WriteConcern {
     }
 }
 
+/// A user name and password to authenticate connections with.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Credential {
+    pub user: String,
+    pub password: String,
+}
+
+impl Credential {
+    pub fn new(user: &str, password: &str) -> Credential {
+        Credential {
+            user: String::from(user),
+            password: String::from(password),
+        }
+    }
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credential")
+            .field("user", &self.user)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The consistency and isolation level of read operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReadConcernLevel {
+    Local,
+    Available,
+    Majority,
+    Linearizable,
+    Snapshot,
+}
+
+impl ReadConcernLevel {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            ReadConcernLevel::Local => "local",
+            ReadConcernLevel::Available => "available",
+            ReadConcernLevel::Majority => "majority",
+            ReadConcernLevel::Linearizable => "linearizable",
+            ReadConcernLevel::Snapshot => "snapshot",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReadConcern {
+    /// The level of the read concern; servers default to `Local`.
+    pub level: ReadConcernLevel,
+}
+
+impl ReadConcern {
+    pub fn new(level: ReadConcernLevel) -> ReadConcern {
+        ReadConcern { level: level }
+    }
+
+    pub fn to_bson(&self) -> bson::Document {
+        doc! { "level": self.level.to_str() }
+    }
+}
+
+/// A version of the Stable API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerApiVersion {
+    V1,
+}
+
+impl ServerApiVersion {
+    pub fn to_str(&self) -> &str {
+        match *self {
+            ServerApiVersion::V1 => "1",
+        }
+    }
+}
+
+/// Pins every command to a version of the Stable API, which requires MongoDB 5.0 or newer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServerApi {
+    pub version: ServerApiVersion,
+    /// Fails commands and options that are not part of the version.
+    pub strict: Option<bool>,
+    /// Fails commands and options that the version deprecates.
+    pub deprecation_errors: Option<bool>,
+}
+
+impl ServerApi {
+    pub fn new(version: ServerApiVersion) -> ServerApi {
+        ServerApi {
+            version: version,
+            strict: None,
+            deprecation_errors: None,
+        }
+    }
+}
+
 pub fn merge_options<T: Into<bson::Document>>(
     document: bson::Document,
     options: T,
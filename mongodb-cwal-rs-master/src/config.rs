@@ -0,0 +1,364 @@
+//! Client configuration read from JSON settings files.
+//!
+//! Every section is optional except for the servers, given as either `hosts` or `srv`.
+//! Unknown keys are rejected, so misspelled settings do not go unnoticed. Credentials can
+//! name an environment variable (`{ "env": "NAME" }`) in place of a literal value, so secrets
+//! stay out of the file.
+//!
+//! ```json
+//! {
+//!     "hosts": ["db1.example.com:27017", "db2.example.com"],
+//!     "replica_set": "rs0",
+//!     "database": "shop",
+//!     "credentials": {
+//!         "user": "shop",
+//!         "password": { "env": "SHOP_DB_PASSWORD" },
+//!         "auth_source": "admin"
+//!     },
+//!     "tls": { "ca_file": "/etc/ssl/mongo-ca.pem", "verify_peer": true },
+//!     "pool": { "size": 10, "idle_timeout_ms": 60000 },
+//!     "timeouts": {
+//!         "heartbeat_frequency_ms": 10000,
+//!         "server_selection_timeout_ms": 30000,
+//!         "local_threshold_ms": 15
+//!     }
+//! }
+//! ```
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::config::ClientConfig;
+//! #
+//! # fn main() {
+//! let client = ClientConfig::from_json_file("/etc/shop/mongodb.json")
+//!     .and_then(ClientConfig::connect)
+//!     .unwrap();
+//! # }
+//! ```
+use serde_json::{self, Map, Value};
+
+use connstring::{self, ConnectionOptions, ConnectionProtocol, ConnectionString, DNS};
+use Error::ArgumentError;
+use {Client, ClientOptions, Result, ThreadedClient};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// The servers to connect to and the options to connect with.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub connection_string: ConnectionString,
+    pub options: ClientOptions,
+}
+
+impl ClientConfig {
+    /// Reads a configuration from a JSON document.
+    pub fn from_json(json: &str) -> Result<ClientConfig> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|err| ArgumentError(format!("Invalid client configuration: {}", err)))?;
+
+        let root = object(&value, "configuration")?;
+        check_keys(
+            root,
+            "configuration",
+            &["hosts", "srv", "replica_set", "database", "credentials", "tls", "pool", "timeouts"],
+        )?;
+
+        let hosts = match (root.get("hosts"), root.get("srv")) {
+            (Some(hosts), None) => {
+                let hosts = array(hosts, "hosts")?;
+                if hosts.is_empty() {
+                    return Err(ArgumentError(String::from("hosts must not be empty.")));
+                }
+
+                let hosts = hosts.iter()
+                    .map(|host| connstring::parse_host(string(host, "hosts")?))
+                    .collect::<Result<_>>()?;
+                ConnectionProtocol::Hosts(hosts)
+            }
+            (None, Some(srv)) => ConnectionProtocol::DNS(DNS::new(string(srv, "srv")?)),
+            _ => {
+                return Err(ArgumentError(
+                    String::from("Exactly one of hosts and srv must be given."),
+                ))
+            }
+        };
+
+        let mut connection_options = BTreeMap::new();
+        if let Some(replica_set) = root.get("replica_set") {
+            connection_options.insert(
+                String::from("replicaSet"),
+                String::from(string(replica_set, "replica_set")?),
+            );
+        }
+
+        let (user, password) = match root.get("credentials") {
+            Some(credentials) => {
+                let credentials = object(credentials, "credentials")?;
+                check_keys(credentials, "credentials", &["user", "password", "auth_source"])?;
+
+                if let Some(auth_source) = credentials.get("auth_source") {
+                    connection_options.insert(
+                        String::from("authSource"),
+                        String::from(string(auth_source, "credentials.auth_source")?),
+                    );
+                }
+
+                match (credentials.get("user"), credentials.get("password")) {
+                    (Some(user), Some(password)) => (
+                        Some(secret(user, "credentials.user")?),
+                        Some(secret(password, "credentials.password")?),
+                    ),
+                    _ => {
+                        return Err(ArgumentError(String::from(
+                            "credentials must give both a user and a password.",
+                        )))
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
+        let database = match root.get("database") {
+            Some(database) => String::from(string(database, "database")?),
+            None => String::from("test"),
+        };
+
+        let connection_string = ConnectionString {
+            hosts: hosts,
+            string: None,
+            user: user,
+            password: password,
+            database: Some(database),
+            collection: None,
+            options: Some(ConnectionOptions::new(connection_options, Vec::new())),
+        };
+
+        let mut options = ClientOptions::new();
+
+        if let Some(tls) = root.get("tls") {
+            configure_tls(&mut options, object(tls, "tls")?)?;
+        }
+
+        if let Some(pool) = root.get("pool") {
+            let pool = object(pool, "pool")?;
+            check_keys(pool, "pool", &["size", "idle_timeout_ms"])?;
+
+            if let Some(size) = pool.get("size") {
+                let size = unsigned(size, "pool.size")?;
+                if size == 0 {
+                    return Err(ArgumentError(String::from("pool.size must be at least 1.")));
+                }
+                options.pool_size = Some(size as usize);
+            }
+
+            if let Some(idle_timeout_ms) = pool.get("idle_timeout_ms") {
+                options.idle_connection_timeout =
+                    Some(Duration::from_millis(unsigned(idle_timeout_ms, "pool.idle_timeout_ms")?));
+            }
+        }
+
+        if let Some(timeouts) = root.get("timeouts") {
+            let timeouts = object(timeouts, "timeouts")?;
+            check_keys(
+                timeouts,
+                "timeouts",
+                &["heartbeat_frequency_ms", "server_selection_timeout_ms", "local_threshold_ms"],
+            )?;
+
+            if let Some(value) = timeouts.get("heartbeat_frequency_ms") {
+                let value = unsigned(value, "timeouts.heartbeat_frequency_ms")?;
+                if value == 0 || value > u64::from(u32::max_value()) {
+                    return Err(ArgumentError(String::from(
+                        "timeouts.heartbeat_frequency_ms is out of range.",
+                    )));
+                }
+                options.heartbeat_frequency_ms = value as u32;
+            }
+
+            if let Some(value) = timeouts.get("server_selection_timeout_ms") {
+                options.server_selection_timeout_ms =
+                    signed(value, "timeouts.server_selection_timeout_ms")?;
+            }
+
+            if let Some(value) = timeouts.get("local_threshold_ms") {
+                options.local_threshold_ms = signed(value, "timeouts.local_threshold_ms")?;
+            }
+        }
+
+        Ok(ClientConfig {
+            connection_string: connection_string,
+            options: options,
+        })
+    }
+
+    /// Reads a configuration from a JSON file.
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<ClientConfig> {
+        ClientConfig::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Creates a client with this configuration.
+    pub fn connect(self) -> Result<Client> {
+        Client::with_config(self.connection_string, Some(self.options), None)
+    }
+}
+
+#[cfg(feature = "ssl")]
+fn configure_tls(options: &mut ClientOptions, tls: &Map<String, Value>) -> Result<()> {
+    use stream::StreamConnector;
+
+    check_keys(tls, "tls", &["ca_file", "certificate_file", "key_file", "verify_peer"])?;
+
+    let optional = |key: &str, name: &str| match tls.get(key) {
+        Some(value) => string(value, name).map(Some),
+        None => Ok(None),
+    };
+
+    let ca_file = optional("ca_file", "tls.ca_file")?;
+    let certificate_file = optional("certificate_file", "tls.certificate_file")?;
+    let key_file = optional("key_file", "tls.key_file")?;
+    let verify_peer = match tls.get("verify_peer") {
+        Some(&Value::Bool(verify_peer)) => verify_peer,
+        Some(_) => return Err(ArgumentError(String::from("tls.verify_peer must be a boolean."))),
+        None => true,
+    };
+
+    options.stream_connector = match (certificate_file, key_file) {
+        (Some(certificate_file), Some(key_file)) => {
+            StreamConnector::with_ssl(ca_file, certificate_file, key_file, verify_peer)
+        }
+        (None, None) => StreamConnector::with_unauthenticated_ssl(ca_file, verify_peer),
+        _ => {
+            return Err(ArgumentError(String::from(
+                "tls.certificate_file and tls.key_file must be given together.",
+            )))
+        }
+    };
+
+    Ok(())
+}
+
+#[cfg(not(feature = "ssl"))]
+fn configure_tls(_: &mut ClientOptions, _: &Map<String, Value>) -> Result<()> {
+    Err(ArgumentError(String::from(
+        "tls settings require the driver to be built with the `ssl` feature.",
+    )))
+}
+
+fn check_keys(object: &Map<String, Value>, name: &str, allowed: &[&str]) -> Result<()> {
+    match object.keys().find(|key| !allowed.contains(&key.as_str())) {
+        Some(key) => Err(ArgumentError(format!("Unknown setting '{}' in {}.", key, name))),
+        None => Ok(()),
+    }
+}
+
+fn object<'a>(value: &'a Value, name: &str) -> Result<&'a Map<String, Value>> {
+    value.as_object().ok_or_else(|| ArgumentError(format!("{} must be an object.", name)))
+}
+
+fn array<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<Value>> {
+    value.as_array().ok_or_else(|| ArgumentError(format!("{} must be an array.", name)))
+}
+
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str> {
+    value.as_str().ok_or_else(|| ArgumentError(format!("{} must be a string.", name)))
+}
+
+fn unsigned(value: &Value, name: &str) -> Result<u64> {
+    value.as_u64().ok_or_else(|| {
+        ArgumentError(format!("{} must be a non-negative integer.", name))
+    })
+}
+
+fn signed(value: &Value, name: &str) -> Result<i64> {
+    unsigned(value, name).and_then(|value| if value > i64::max_value() as u64 {
+        Err(ArgumentError(format!("{} is out of range.", name)))
+    } else {
+        Ok(value as i64)
+    })
+}
+
+// Reads a literal string, or the environment variable named by `{ "env": "NAME" }`.
+fn secret(value: &Value, name: &str) -> Result<String> {
+    if let Some(literal) = value.as_str() {
+        return Ok(String::from(literal));
+    }
+
+    let indirect = value.as_object().ok_or_else(|| {
+        ArgumentError(format!("{} must be a string or an {{ \"env\": ... }} object.", name))
+    })?;
+    check_keys(indirect, name, &["env"])?;
+
+    let variable = match indirect.get("env") {
+        Some(variable) => string(variable, name)?,
+        None => return Err(ArgumentError(format!("{} must name an environment variable.", name))),
+    };
+
+    env::var(variable).map_err(|_| {
+        ArgumentError(format!("Environment variable {} for {} is not set.", variable, name))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_hosts_credentials_and_options() {
+        env::set_var("MONGODB_CONFIG_TEST_PASSWORD", "hunter2");
+
+        let config = ClientConfig::from_json(r#"{
+            "hosts": ["db1.example.com:27018", "db2.example.com"],
+            "replica_set": "rs0",
+            "database": "shop",
+            "credentials": {
+                "user": "shop",
+                "password": { "env": "MONGODB_CONFIG_TEST_PASSWORD" },
+                "auth_source": "admin"
+            },
+            "pool": { "size": 10, "idle_timeout_ms": 60000 },
+            "timeouts": { "server_selection_timeout_ms": 5000 }
+        }"#).unwrap();
+
+        let connection_string = config.connection_string;
+        let hosts: Vec<_> = connection_string.hosts.iter()
+            .map(|host| (host.host_name.clone(), host.port))
+            .collect();
+        let expected = vec![
+            (String::from("db1.example.com"), 27018),
+            (String::from("db2.example.com"), 27017),
+        ];
+        assert_eq!(expected, hosts);
+        assert_eq!(Some(String::from("shop")), connection_string.user);
+        assert_eq!(Some(String::from("hunter2")), connection_string.password);
+        assert_eq!(Some(String::from("shop")), connection_string.database);
+
+        let connection_options = connection_string.options.unwrap();
+        assert_eq!(Some(&String::from("rs0")), connection_options.get("replicaSet"));
+        assert_eq!(Some(&String::from("admin")), connection_options.get("authSource"));
+
+        assert_eq!(Some(10), config.options.pool_size);
+        assert_eq!(Some(Duration::from_secs(60)), config.options.idle_connection_timeout);
+        assert_eq!(5000, config.options.server_selection_timeout_ms);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        for json in &[
+            r#"{}"#,
+            r#"{ "hosts": [] }"#,
+            r#"{ "hosts": ["a"], "srv": "b" }"#,
+            r#"{ "hosts": ["a"], "pol": { "size": 1 } }"#,
+            r#"{ "hosts": ["a"], "pool": { "size": 0 } }"#,
+            r#"{ "hosts": ["a"], "pool": { "size": "10" } }"#,
+            r#"{ "hosts": ["a"], "credentials": { "user": "u" } }"#,
+            r#"{ "hosts": ["a"], "credentials": { "user": "u", "password": { "env": "" } } }"#,
+        ] {
+            assert!(ClientConfig::from_json(json).is_err(), "accepted {}", json);
+        }
+    }
+}
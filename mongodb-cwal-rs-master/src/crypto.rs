@@ -0,0 +1,171 @@
+//! The hash, HMAC and PBKDF2 primitives authentication is computed with.
+//!
+//! The pure-Rust RustCrypto implementations are used by default. The `openssl-crypto` feature
+//! switches to OpenSSL instead, whose FIPS module can be required by the system
+//! configuration; note that such a module rejects the MD5 digest SCRAM-SHA-1 hashes passwords
+//! with.
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use pbkdf2::pbkdf2;
+use sha1::{Digest, Sha1};
+
+use Result;
+
+/// The length in bytes of SHA-1 digests and the keys derived from them.
+pub const SHA1_OUTPUT: usize = 20;
+
+/// A source of the primitives SCRAM-SHA-1 authentication needs.
+pub trait CryptoBackend {
+    fn md5(data: &[u8]) -> Result<[u8; 16]>;
+    fn sha1(data: &[u8]) -> Result<[u8; SHA1_OUTPUT]>;
+    fn hmac_sha1(key: &[u8], data: &[u8]) -> Result<[u8; SHA1_OUTPUT]>;
+    fn pbkdf2_hmac_sha1(password: &[u8], salt: &[u8], iterations: u32)
+        -> Result<[u8; SHA1_OUTPUT]>;
+}
+
+/// The backend selected by the crate features.
+#[cfg(not(feature = "openssl-crypto"))]
+pub type Backend = RustCrypto;
+#[cfg(feature = "openssl-crypto")]
+pub type Backend = OpenSsl;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// The pure-Rust implementations of the RustCrypto project.
+// Only tests use it when OpenSSL is selected.
+#[cfg_attr(feature = "openssl-crypto", allow(dead_code))]
+#[derive(Debug)]
+pub struct RustCrypto;
+
+impl CryptoBackend for RustCrypto {
+    fn md5(data: &[u8]) -> Result<[u8; 16]> {
+        let mut digest = [0u8; 16];
+        digest.copy_from_slice(&Md5::digest(data));
+        Ok(digest)
+    }
+
+    fn sha1(data: &[u8]) -> Result<[u8; SHA1_OUTPUT]> {
+        let mut digest = [0u8; SHA1_OUTPUT];
+        digest.copy_from_slice(&Sha1::digest(data));
+        Ok(digest)
+    }
+
+    fn hmac_sha1(key: &[u8], data: &[u8]) -> Result<[u8; SHA1_OUTPUT]> {
+        let mut hmac = HmacSha1::new_varkey(key).expect("HMAC can take key of any size");
+        hmac.input(data);
+
+        let mut code = [0u8; SHA1_OUTPUT];
+        code.copy_from_slice(&hmac.result().code());
+        Ok(code)
+    }
+
+    fn pbkdf2_hmac_sha1(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; SHA1_OUTPUT]> {
+        let mut key = [0u8; SHA1_OUTPUT];
+        pbkdf2::<HmacSha1>(password, salt, iterations as usize, &mut key);
+        Ok(key)
+    }
+}
+
+#[cfg(feature = "openssl-crypto")]
+pub use self::openssl_backend::OpenSsl;
+
+#[cfg(feature = "openssl-crypto")]
+mod openssl_backend {
+    use openssl::error::ErrorStack;
+    use openssl::hash::{self, MessageDigest};
+    use openssl::pkcs5::pbkdf2_hmac;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
+
+    use super::{CryptoBackend, SHA1_OUTPUT};
+    use Error::DefaultError;
+    use Result;
+
+    /// The implementations of the OpenSSL library the driver is linked against.
+    #[derive(Debug)]
+    pub struct OpenSsl;
+
+    fn openssl_error(err: ErrorStack) -> ::Error {
+        DefaultError(format!("OpenSSL failed to authenticate: {}", err))
+    }
+
+    fn digest(message_digest: MessageDigest, data: &[u8], out: &mut [u8]) -> Result<()> {
+        let digest = hash::hash(message_digest, data).map_err(openssl_error)?;
+        out.copy_from_slice(&digest);
+        Ok(())
+    }
+
+    impl CryptoBackend for OpenSsl {
+        fn md5(data: &[u8]) -> Result<[u8; 16]> {
+            let mut out = [0u8; 16];
+            digest(MessageDigest::md5(), data, &mut out)?;
+            Ok(out)
+        }
+
+        fn sha1(data: &[u8]) -> Result<[u8; SHA1_OUTPUT]> {
+            let mut out = [0u8; SHA1_OUTPUT];
+            digest(MessageDigest::sha1(), data, &mut out)?;
+            Ok(out)
+        }
+
+        fn hmac_sha1(key: &[u8], data: &[u8]) -> Result<[u8; SHA1_OUTPUT]> {
+            let key = PKey::hmac(key).map_err(openssl_error)?;
+            let mut signer = Signer::new(MessageDigest::sha1(), &key).map_err(openssl_error)?;
+            signer.update(data).map_err(openssl_error)?;
+
+            let mut out = [0u8; SHA1_OUTPUT];
+            out.copy_from_slice(&signer.sign_to_vec().map_err(openssl_error)?);
+            Ok(out)
+        }
+
+        fn pbkdf2_hmac_sha1(
+            password: &[u8],
+            salt: &[u8],
+            iterations: u32,
+        ) -> Result<[u8; SHA1_OUTPUT]> {
+            let mut out = [0u8; SHA1_OUTPUT];
+            pbkdf2_hmac(password, salt, iterations as usize, MessageDigest::sha1(), &mut out)
+                .map_err(openssl_error)?;
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex;
+
+    fn check_vectors<B: CryptoBackend>() {
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", hex::encode(B::md5(b"abc").unwrap()));
+        assert_eq!(
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            hex::encode(B::sha1(b"abc").unwrap())
+        );
+
+        // RFC 2202, test case 2.
+        let code = B::hmac_sha1(b"Jefe", b"what do ya want for nothing?").unwrap();
+        assert_eq!("effcdf6ae5eb2fa2d27416d5f184df9c259a7c79", hex::encode(code));
+
+        // RFC 6070, with 1 and 4096 iterations.
+        let key = B::pbkdf2_hmac_sha1(b"password", b"salt", 1).unwrap();
+        assert_eq!("0c60c80f961f0e71f3a9b524af6012062fe037a6", hex::encode(key));
+        let key = B::pbkdf2_hmac_sha1(b"password", b"salt", 4096).unwrap();
+        assert_eq!("4b007901b765489abead49d926f721d065a429c1", hex::encode(key));
+    }
+
+    #[test]
+    fn rust_crypto_matches_test_vectors() {
+        check_vectors::<RustCrypto>();
+    }
+
+    #[cfg(feature = "openssl-crypto")]
+    #[test]
+    fn openssl_matches_test_vectors() {
+        check_vectors::<OpenSsl>();
+    }
+}
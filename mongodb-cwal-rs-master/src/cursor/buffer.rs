@@ -0,0 +1,233 @@
+//! The batch a cursor returns documents from.
+use bson;
+use Result;
+use wire_protocol::reply::ReplyBuffer;
+
+use std::collections::VecDeque;
+use std::mem;
+
+// Documents that arrived decoded, such as a first batch nested in a command reply, followed
+// by a getMore reply whose documents are only decoded as they are returned.
+//
+// With a limit on how many documents are kept decoded, documents arriving decoded beyond it
+// are encoded again instead, and later batches are appended to the encoded documents rather
+// than decoding those first.
+#[derive(Debug)]
+pub(crate) struct BatchBuffer {
+    decoded: VecDeque<Result<bson::Document>>,
+    encoded: ReplyBuffer,
+    // The first document of `encoded` that has not been returned.
+    next: usize,
+    max_decoded: Option<usize>,
+}
+
+impl BatchBuffer {
+    pub fn new() -> BatchBuffer {
+        BatchBuffer {
+            decoded: VecDeque::new(),
+            encoded: ReplyBuffer::new(),
+            next: 0,
+            max_decoded: None,
+        }
+    }
+
+    // Limits how many documents are kept decoded, encoding the surplus again.
+    pub fn set_max_decoded(&mut self, max: usize) {
+        self.max_decoded = Some(max);
+        if self.decoded.len() <= max {
+            return;
+        }
+
+        // Documents that failed to decode stay where they are, along with those before them.
+        let mut spilled = VecDeque::new();
+        while self.decoded.len() > max {
+            match self.decoded.pop_back() {
+                Some(Ok(doc)) => spilled.push_front(doc),
+                Some(err) => {
+                    self.decoded.push_back(err);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        let mut encoded = ReplyBuffer::new();
+        for doc in &spilled {
+            if encoded.push_document(doc).is_err() {
+                self.decoded.extend(spilled.into_iter().map(Ok));
+                return;
+            }
+        }
+        for document in self.encoded.documents().skip(self.next) {
+            encoded.push_encoded(document);
+        }
+
+        self.encoded = encoded;
+        self.next = 0;
+    }
+
+    // Drops the documents of `encoded` that were returned, so more can be appended.
+    fn compact(&mut self) {
+        self.encoded.remove_front(self.next);
+        self.next = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.decoded.len() + self.encoded.len() - self.next
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Decodes the documents of `encoded` that have not been returned, so that documents
+    // added after them are returned after them.
+    fn decode_remaining(&mut self) {
+        while let Some(doc) = self.encoded.decode(self.next) {
+            self.decoded.push_back(doc);
+            self.next += 1;
+        }
+    }
+
+    pub fn extend(&mut self, batch: VecDeque<bson::Document>) {
+        let max = match self.max_decoded {
+            Some(max) => max,
+            None => {
+                self.decode_remaining();
+                self.decoded.extend(batch.into_iter().map(Ok));
+                return;
+            }
+        };
+
+        self.compact();
+        for doc in batch {
+            if self.encoded.is_empty() && self.decoded.len() < max {
+                self.decoded.push_back(Ok(doc));
+            } else if self.encoded.push_document(&doc).is_err() {
+                self.decode_remaining();
+                self.decoded.push_back(Ok(doc));
+            }
+        }
+    }
+
+    // Appends the documents of `reply`, handing back the buffer they replace for the next
+    // reply to be read into.
+    pub fn push_reply(&mut self, reply: &mut ReplyBuffer) {
+        if self.max_decoded.is_none() || self.next == self.encoded.len() {
+            self.decode_remaining();
+            mem::swap(&mut self.encoded, reply);
+            self.next = 0;
+            return;
+        }
+
+        self.compact();
+        for document in reply.documents() {
+            self.encoded.push_encoded(document);
+        }
+        reply.remove_front(reply.len());
+    }
+
+    pub fn pop_front(&mut self) -> Option<Result<bson::Document>> {
+        if let Some(doc) = self.decoded.pop_front() {
+            return Some(doc);
+        }
+
+        let doc = self.encoded.decode(self.next);
+        if doc.is_some() {
+            self.next += 1;
+        }
+        doc
+    }
+
+    // Returns the first `len` documents, failing on the first that cannot be decoded.
+    pub fn take(&mut self, len: usize) -> Result<Vec<bson::Document>> {
+        let len = len.min(self.len());
+        (0..len).filter_map(|_| self.pop_front()).collect()
+    }
+
+    // Removes the documents, leaving those that were not decoded yet encoded.
+    pub fn drain_encoded(&mut self) -> (Vec<Result<bson::Document>>, Vec<Vec<u8>>) {
+        let decoded = self.decoded.drain(..).collect();
+        let encoded = self.encoded.documents().skip(self.next).map(<[u8]>::to_vec).collect();
+        self.next = self.encoded.len();
+        (decoded, encoded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use wire_protocol::replay::Replay;
+
+    #[test]
+    fn decodes_replies_as_documents_are_returned() {
+        let mut buffer = BatchBuffer::new();
+        buffer.extend((0..2).map(|i| doc! { "i": i }).collect());
+
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut Replay::recording("get_more_batch")).unwrap();
+        buffer.push_reply(&mut reply);
+        assert!(reply.is_empty());
+        assert_eq!(4, buffer.len());
+
+        assert_eq!(vec![doc! { "i": 0 }], buffer.take(1).unwrap());
+        assert_eq!(Some(doc! { "i": 1 }), buffer.pop_front().map(Result::unwrap));
+        assert_eq!(Some(doc! { "_id": 1 }), buffer.pop_front().map(Result::unwrap));
+
+        // Documents added later are returned after those still encoded.
+        buffer.extend(vec![doc! { "i": 2 }].into_iter().collect());
+        let docs = buffer.take(5).unwrap();
+        assert_eq!(vec![doc! { "_id": 2, "name": "two" }, doc! { "i": 2 }], docs);
+        assert!(buffer.is_empty());
+        assert!(buffer.pop_front().is_none());
+    }
+
+    #[test]
+    fn drains_documents_without_decoding_them() {
+        let mut buffer = BatchBuffer::new();
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut Replay::recording("get_more_batch")).unwrap();
+        buffer.push_reply(&mut reply);
+        buffer.pop_front().unwrap().unwrap();
+
+        let (decoded, encoded) = buffer.drain_encoded();
+        assert!(decoded.is_empty());
+        assert_eq!(1, encoded.len());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn keeps_at_most_max_decoded_documents_decoded() {
+        let mut buffer = BatchBuffer::new();
+        buffer.extend((0..3).map(|i| doc! { "i": i }).collect());
+        buffer.set_max_decoded(1);
+        assert_eq!(1, buffer.decoded.len());
+        assert_eq!(3, buffer.len());
+
+        // Later batches are appended after the encoded documents without decoding them.
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut Replay::recording("get_more_batch")).unwrap();
+        buffer.push_reply(&mut reply);
+        assert!(reply.is_empty());
+        buffer.extend(vec![doc! { "i": 3 }].into_iter().collect());
+        assert_eq!(1, buffer.decoded.len());
+        assert_eq!(6, buffer.len());
+
+        let mut docs = Vec::new();
+        while let Some(doc) = buffer.pop_front() {
+            docs.push(doc.unwrap());
+            assert!(buffer.decoded.is_empty());
+        }
+
+        let expected = vec![
+            doc! { "i": 0 },
+            doc! { "i": 1 },
+            doc! { "i": 2 },
+            doc! { "_id": 1 },
+            doc! { "_id": 2, "name": "two" },
+            doc! { "i": 3 },
+        ];
+        assert_eq!(expected, docs);
+    }
+}
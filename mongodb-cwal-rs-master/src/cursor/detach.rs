@@ -0,0 +1,172 @@
+//! Cursors detached from their client and re-attached later.
+//!
+//! A detached cursor is only the state needed to continue the server-side cursor, so it can
+//! be serialized and handed to another thread or process, which re-attaches it to its own
+//! client. The server-side cursor is not killed while detached, and times out as usual if
+//! it is never re-attached.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::cursor::Cursor;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("jobs");
+//! let mut cursor = coll.find(None, None).unwrap();
+//! let first_batch = cursor.drain_current_batch().unwrap();
+//!
+//! let detached = cursor.detach().unwrap();
+//! let saved = bson::to_bson(&detached).unwrap();
+//!
+//! // Possibly elsewhere, with another client.
+//! let cursor = Cursor::attach(client.clone(), bson::from_bson(saved).unwrap());
+//! # }
+//! ```
+use super::Cursor;
+use common::{ReadMode, ReadPreference};
+use CommandType;
+use Client;
+use Error::ArgumentError;
+use Result;
+
+use std::collections::VecDeque;
+
+/// The state of a cursor detached from its client.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DetachedCursor {
+    /// The namespace the cursor reads from.
+    pub namespace: String,
+    /// The id of the server-side cursor.
+    pub cursor_id: i64,
+    /// How many documents the cursor returned before it was detached.
+    pub position: i32,
+    /// How many documents each getMore requests; zero lets the server decide.
+    pub batch_size: i32,
+    /// The most documents the cursor returns in total, counting those returned before it
+    /// was detached; zero for no limit.
+    pub limit: i32,
+    /// How long getMores of a tailable cursor wait for new documents.
+    pub max_await_time_ms: Option<i64>,
+}
+
+impl Cursor {
+    /// Detaches the cursor from its client, leaving the server-side cursor open.
+    ///
+    /// Fails with an `ArgumentError` while documents are buffered, since they would be lost;
+    /// drain the current batch first.
+    pub fn detach(mut self) -> Result<DetachedCursor> {
+        if !self.buffer.is_empty() {
+            return Err(ArgumentError(String::from(
+                "Cannot detach a cursor with buffered documents; drain the current batch first.",
+            )));
+        }
+
+        let detached = DetachedCursor {
+            namespace: self.namespace.clone(),
+            cursor_id: self.cursor_id,
+            position: self.count,
+            batch_size: self.batch_size,
+            limit: self.limit,
+            max_await_time_ms: self.max_await_time_ms,
+        };
+
+        // The server-side cursor now belongs to whoever re-attaches it.
+        self.cursor_id = 0;
+        Ok(detached)
+    }
+
+    /// Re-attaches a detached cursor, continuing from its position.
+    ///
+    /// GetMores are sent with a primary read preference, so cursors opened on a secondary
+    /// should be re-attached with `attach_with_read_preference`.
+    pub fn attach(client: Client, detached: DetachedCursor) -> Cursor {
+        Cursor::attach_with_read_preference(
+            client,
+            detached,
+            ReadPreference::new(ReadMode::Primary, None),
+        )
+    }
+
+    /// Re-attaches a detached cursor, sending getMores to a server selected with
+    /// `read_preference`.
+    pub fn attach_with_read_preference(
+        client: Client,
+        detached: DetachedCursor,
+        read_preference: ReadPreference,
+    ) -> Cursor {
+        let mut cursor = Cursor::with_documents(
+            client,
+            detached.namespace,
+            VecDeque::new(),
+            CommandType::Find,
+            read_preference,
+        );
+
+        cursor.cursor_id = detached.cursor_id;
+        cursor.count = detached.position;
+        cursor.batch_size = detached.batch_size;
+        cursor.limit = detached.limit;
+        cursor.max_await_time_ms = detached.max_await_time_ms;
+        cursor
+    }
+
+    /// Attaches to the existing server-side cursor `cursor_id` of `namespace`, fetching its
+    /// remaining documents with getMores sent to the primary.
+    pub fn from_existing(client: Client, namespace: &str, cursor_id: i64) -> Cursor {
+        Cursor::attach(
+            client,
+            DetachedCursor {
+                namespace: String::from(namespace),
+                cursor_id: cursor_id,
+                position: 0,
+                batch_size: 0,
+                limit: 0,
+                max_await_time_ms: None,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{self, doc};
+    use ThreadedClient;
+
+    #[test]
+    fn detaches_and_reattaches() {
+        let client = Client::connect("localhost", 27017).unwrap();
+        let mut cursor = Cursor::with_documents(
+            client.clone(),
+            String::from("test.detach"),
+            vec![doc! { "_id": 1 }].into_iter().collect(),
+            CommandType::Find,
+            ReadPreference::new(ReadMode::Primary, None),
+        );
+        cursor.cursor_id = 4660;
+        cursor.limit = 10;
+
+        let failed = cursor.detach();
+        assert!(failed.is_err());
+
+        let mut cursor = Cursor::from_existing(client.clone(), "test.detach", 4660);
+        cursor.count = 1;
+        cursor.limit = 10;
+
+        let detached = cursor.detach().unwrap();
+        assert_eq!(4660, detached.cursor_id);
+        assert_eq!(1, detached.position);
+
+        let saved = bson::to_bson(&detached).unwrap();
+        let detached: DetachedCursor = bson::from_bson(saved).unwrap();
+
+        let cursor = Cursor::attach(client, detached);
+        assert_eq!(4660, cursor.id());
+        assert_eq!("test.detach", cursor.namespace());
+        assert_eq!(1, cursor.position());
+    }
+}
@@ -0,0 +1,242 @@
+//! Cursor results and documents converted to MongoDB Extended JSON.
+//!
+//! Extended JSON keeps the BSON types plain JSON cannot tell apart: canonical mode wraps every
+//! number and date so they round-trip exactly, while relaxed mode writes numbers as JSON
+//! numbers and dates as ISO-8601 strings where that loses nothing, for output meant to be
+//! read by people.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::cursor::extended_json::ExtendedJsonMode;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("events");
+//! let cursor = coll.find(None, None).unwrap();
+//!
+//! for json in cursor.into_extended_json(ExtendedJsonMode::Canonical) {
+//!     println!("{}", json.unwrap());
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use chrono::{Datelike, Timelike};
+use data_encoding::BASE64;
+use serde_json::{Map, Number, Value};
+
+use super::Cursor;
+use Result;
+
+/// How numbers and dates are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendedJsonMode {
+    /// Every number and date keeps its BSON type, e.g. `{"$numberInt": "1"}`.
+    Canonical,
+    /// Numbers are JSON numbers and dates between the years 1970 and 9999 are ISO-8601
+    /// strings, e.g. `{"$date": "2018-03-01T12:00:00Z"}`.
+    Relaxed,
+}
+
+/// Converts a document to Extended JSON.
+pub fn document_to_extended_json(doc: &bson::Document, mode: ExtendedJsonMode) -> Value {
+    let mut object = Map::new();
+    for (key, value) in doc {
+        object.insert(key.clone(), to_extended_json(value, mode));
+    }
+    Value::Object(object)
+}
+
+/// Converts a value to Extended JSON.
+pub fn to_extended_json(bson: &Bson, mode: ExtendedJsonMode) -> Value {
+    let relaxed = mode == ExtendedJsonMode::Relaxed;
+
+    match *bson {
+        Bson::FloatingPoint(value) => {
+            if relaxed && value.is_finite() {
+                if let Some(number) = Number::from_f64(value) {
+                    return Value::Number(number);
+                }
+            }
+            wrap("$numberDouble", Value::String(format_double(value)))
+        }
+        Bson::String(ref s) => Value::String(s.clone()),
+        Bson::Array(ref values) => {
+            Value::Array(values.iter().map(|value| to_extended_json(value, mode)).collect())
+        }
+        Bson::Document(ref doc) => document_to_extended_json(doc, mode),
+        Bson::Boolean(value) => Value::Bool(value),
+        Bson::Null => Value::Null,
+        Bson::RegExp(ref pattern, ref options) => {
+            // Options are sorted so that equal expressions have equal representations.
+            let mut options: Vec<_> = options.chars().collect();
+            options.sort();
+
+            let mut regex = Map::new();
+            regex.insert(String::from("pattern"), Value::String(pattern.clone()));
+            regex.insert(String::from("options"), Value::String(options.into_iter().collect()));
+            wrap("$regularExpression", Value::Object(regex))
+        }
+        Bson::JavaScriptCode(ref code) => wrap("$code", Value::String(code.clone())),
+        Bson::JavaScriptCodeWithScope(ref code, ref scope) => {
+            let mut object = Map::new();
+            object.insert(String::from("$code"), Value::String(code.clone()));
+            object.insert(String::from("$scope"), document_to_extended_json(scope, mode));
+            Value::Object(object)
+        }
+        Bson::I32(value) if relaxed => Value::from(value),
+        Bson::I32(value) => wrap("$numberInt", Value::String(value.to_string())),
+        Bson::I64(value) if relaxed => Value::from(value),
+        Bson::I64(value) => wrap("$numberLong", Value::String(value.to_string())),
+        Bson::TimeStamp(value) => {
+            let mut timestamp = Map::new();
+            timestamp.insert(String::from("t"), Value::from((value as u64 >> 32) as u32));
+            timestamp.insert(String::from("i"), Value::from(value as u32));
+            wrap("$timestamp", Value::Object(timestamp))
+        }
+        Bson::Binary(subtype, ref bytes) => {
+            let mut binary = Map::new();
+            binary.insert(String::from("base64"), Value::String(BASE64.encode(bytes)));
+            binary.insert(
+                String::from("subType"),
+                Value::String(format!("{:02x}", u8::from(subtype))),
+            );
+            wrap("$binary", Value::Object(binary))
+        }
+        Bson::ObjectId(ref id) => wrap("$oid", Value::String(id.to_hex())),
+        Bson::UtcDatetime(ref datetime) => {
+            let millis = datetime.timestamp_millis();
+            if relaxed && datetime.year() >= 1970 && datetime.year() <= 9999 {
+                let format = if datetime.nanosecond() / 1_000_000 == 0 {
+                    "%Y-%m-%dT%H:%M:%SZ"
+                } else {
+                    "%Y-%m-%dT%H:%M:%S%.3fZ"
+                };
+                return wrap("$date", Value::String(datetime.format(format).to_string()));
+            }
+            wrap("$date", wrap("$numberLong", Value::String(millis.to_string())))
+        }
+        Bson::Symbol(ref symbol) => wrap("$symbol", Value::String(symbol.clone())),
+        #[cfg(feature = "decimal128")]
+        Bson::Decimal128(ref value) => wrap("$numberDecimal", Value::String(value.to_string())),
+    }
+}
+
+fn wrap(key: &str, value: Value) -> Value {
+    let mut object = Map::new();
+    object.insert(String::from(key), value);
+    Value::Object(object)
+}
+
+// Formats a double as the Extended JSON spec does: with a fractional part, an exponent in the
+// form `E+308`, and `Infinity`, `-Infinity` or `NaN` for values that are not finite.
+fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return String::from("NaN");
+    }
+    if value.is_infinite() {
+        return String::from(if value > 0.0 { "Infinity" } else { "-Infinity" });
+    }
+
+    let formatted = format!("{:?}", value);
+    match formatted.find('e') {
+        Some(index) => {
+            let (mantissa, exponent) = (&formatted[..index], &formatted[index + 1..]);
+            let point = if mantissa.contains('.') { "" } else { ".0" };
+            let sign = if exponent.starts_with('-') { "" } else { "+" };
+            format!("{}{}E{}{}", mantissa, point, sign, exponent)
+        }
+        None => formatted,
+    }
+}
+
+/// Lazily returns the results of a query as Extended JSON.
+#[derive(Debug)]
+pub struct ExtendedJsonCursor {
+    cursor: Cursor,
+    mode: ExtendedJsonMode,
+}
+
+impl Iterator for ExtendedJsonCursor {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        let mode = self.mode;
+        self.cursor
+            .next()
+            .map(|result| result.map(|doc| document_to_extended_json(&doc, mode)))
+    }
+}
+
+impl Cursor {
+    /// Converts the cursor into one returning each result as Extended JSON.
+    pub fn into_extended_json(self, mode: ExtendedJsonMode) -> ExtendedJsonCursor {
+        ExtendedJsonCursor {
+            cursor: self,
+            mode: mode,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use bson::oid::ObjectId;
+    use bson::spec::BinarySubtype;
+    use chrono::{TimeZone, Utc};
+
+    fn sample() -> bson::Document {
+        doc! {
+            "_id": ObjectId::with_bytes([0x5a; 12]),
+            "n": 1,
+            "big": 3_000_000_000_i64,
+            "ratio": 0.5,
+            "huge": 1e300,
+            "at": Utc.timestamp_millis_opt(1_520_000_000_123).unwrap(),
+            "data": Bson::Binary(BinarySubtype::Uuid, vec![1, 2, 3]),
+            "pattern": Bson::RegExp(String::from("^a"), String::from("mi")),
+            "ts": Bson::TimeStamp((7 << 32) + 3),
+            "tags": ["a", Bson::Null],
+        }
+    }
+
+    #[test]
+    fn writes_canonical_json() {
+        let json = document_to_extended_json(&sample(), ExtendedJsonMode::Canonical);
+        assert_eq!(
+            concat!(
+                r#"{"_id":{"$oid":"5a5a5a5a5a5a5a5a5a5a5a5a"},"n":{"$numberInt":"1"},"#,
+                r#""big":{"$numberLong":"3000000000"},"ratio":{"$numberDouble":"0.5"},"#,
+                r#""huge":{"$numberDouble":"1.0E+300"},"#,
+                r#""at":{"$date":{"$numberLong":"1520000000123"}},"#,
+                r#""data":{"$binary":{"base64":"AQID","subType":"04"}},"#,
+                r#""pattern":{"$regularExpression":{"pattern":"^a","options":"im"}},"#,
+                r#""ts":{"$timestamp":{"t":7,"i":3}},"tags":["a",null]}"#
+            ),
+            json.to_string()
+        );
+    }
+
+    #[test]
+    fn writes_relaxed_json() {
+        let json = document_to_extended_json(&sample(), ExtendedJsonMode::Relaxed);
+        assert_eq!(Value::from(1), json["n"]);
+        assert_eq!(Value::from(3_000_000_000_i64), json["big"]);
+        assert_eq!(Value::from(0.5), json["ratio"]);
+        assert_eq!(r#"{"$date":"2018-03-02T14:13:20.123Z"}"#, json["at"].to_string());
+
+        let early = Bson::UtcDatetime(Utc.timestamp_millis_opt(-1).unwrap());
+        assert_eq!(
+            r#"{"$date":{"$numberLong":"-1"}}"#,
+            to_extended_json(&early, ExtendedJsonMode::Relaxed).to_string()
+        );
+        assert_eq!(
+            r#"{"$numberDouble":"-Infinity"}"#,
+            to_extended_json(&Bson::FloatingPoint(-1.0 / 0.0), ExtendedJsonMode::Relaxed)
+                .to_string()
+        );
+    }
+}
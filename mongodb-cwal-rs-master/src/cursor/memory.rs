@@ -0,0 +1,153 @@
+//! Accounting of the memory held by buffered cursor batches.
+use poison::Recover;
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a `getMore` waits for other cursors to release memory once the client-wide limit
+/// is reached, unless configured otherwise.
+pub const DEFAULT_CURSOR_MEMORY_WAIT: Duration = Duration::from_secs(1);
+
+// The bytes of decoded batches buffered by every cursor of a client, along with the room
+// reserved for batches being fetched.
+#[derive(Debug)]
+pub(crate) struct CursorMemory {
+    limit: Option<usize>,
+    max_wait: Duration,
+    used: Mutex<usize>,
+    released: Condvar,
+}
+
+impl CursorMemory {
+    pub fn new(limit: Option<usize>, max_wait: Duration) -> CursorMemory {
+        CursorMemory {
+            limit: limit,
+            max_wait: max_wait,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        *self.used.lock().recover()
+    }
+
+    // Replaces `reserved` bytes, such as the room reserved for a batch, by the `bytes` the
+    // batch actually takes.
+    pub fn exchange(&self, reserved: usize, bytes: usize) {
+        let mut used = self.used.lock().recover();
+        *used = used.saturating_sub(reserved).saturating_add(bytes);
+
+        if bytes < reserved {
+            self.released.notify_all();
+        }
+    }
+
+    pub fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+
+        let mut used = self.used.lock().recover();
+        *used = used.saturating_sub(bytes);
+        self.released.notify_all();
+    }
+
+    // Blocks until `bytes` more fit under the limit and reserves them, so that consuming
+    // buffered batches comes before fetching new ones. The wait is bounded, since the memory
+    // may be held by cursors of the calling thread that cannot be consumed in the meantime;
+    // the bytes are reserved over the limit once it expires. Nothing buffered leaves room for
+    // any batch, so that batches larger than the limit can still be read one at a time.
+    pub fn reserve_room(&self, bytes: usize) {
+        let mut used = self.used.lock().recover();
+
+        if let Some(limit) = self.limit {
+            let deadline = Instant::now() + self.max_wait;
+
+            while *used > 0 && (*used >= limit || used.saturating_add(bytes) > limit) {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+
+                used = self.released.wait_timeout(used, deadline - now).recover().0;
+            }
+        }
+
+        *used = used.saturating_add(bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn waits_until_memory_is_released() {
+        let memory = Arc::new(CursorMemory::new(Some(100), Duration::from_secs(10)));
+        memory.exchange(0, 150);
+
+        let releaser = memory.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            releaser.release(100);
+        });
+
+        let start = Instant::now();
+        memory.reserve_room(10);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(60, memory.used());
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn exceeds_the_limit_after_max_wait() {
+        let memory = CursorMemory::new(Some(100), Duration::from_millis(20));
+        memory.exchange(0, 100);
+
+        let start = Instant::now();
+        memory.reserve_room(50);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(150, memory.used());
+
+        let unlimited = CursorMemory::new(None, Duration::from_secs(10));
+        unlimited.exchange(0, usize::max_value());
+        unlimited.reserve_room(1);
+
+        let empty = CursorMemory::new(Some(100), Duration::from_millis(20));
+        empty.reserve_room(500);
+        assert_eq!(500, empty.used());
+    }
+
+    #[test]
+    fn concurrent_cursors_stay_under_the_limit() {
+        let memory = Arc::new(CursorMemory::new(Some(1000), Duration::from_secs(10)));
+
+        let cursors: Vec<_> = (0..8)
+            .map(|_| {
+                let memory = memory.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        memory.reserve_room(300);
+                        assert!(memory.used() <= 1000);
+
+                        // The batch arrives, is buffered, and is consumed.
+                        memory.exchange(300, 250);
+                        assert!(memory.used() <= 1000);
+                        thread::sleep(Duration::from_millis(1));
+                        memory.release(250);
+                    }
+                })
+            })
+            .collect();
+
+        for cursor in cursors {
+            cursor.join().unwrap();
+        }
+        assert_eq!(0, memory.used());
+    }
+}
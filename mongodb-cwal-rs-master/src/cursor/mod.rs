@@ -0,0 +1,1583 @@
+//! Iterable network cursor for MongoDB queries.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use bson::Bson;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("info");
+//! #
+//! coll.insert_one(doc!{ "spirit_animal": "ferret" }, None).unwrap();
+//!
+//! let mut cursor = coll.find(None, None).unwrap();
+//! for result in cursor {
+//!     let doc = result.expect("Received network error during cursor operations.");
+//!     if let Some(&Bson::String(ref value)) = doc.get("spirit_animal") {
+//!         println!("My spirit animal is {}", value);
+//!     }
+//! }
+//! # }
+//! ```
+mod buffer;
+mod memory;
+pub mod detach;
+pub mod extended_json;
+pub mod prefetch;
+pub mod raw;
+pub mod spool;
+
+use self::buffer::BatchBuffer;
+use self::raw::RawDocumentRef;
+pub(crate) use self::memory::CursorMemory;
+pub use self::memory::DEFAULT_CURSOR_MEMORY_WAIT;
+
+use {Client, CommandType, Error, ErrorCode, Result, ThreadedClient};
+use error::ErrorContext;
+use apm::{CommandStarted, CommandResult, EventRunner};
+
+use bson::{self, bson, doc, Bson};
+use common::{merge_options, ReadMode, ReadPreference, ServerApi};
+use connstring::Host;
+use interceptor::CommandContext;
+use coll::options::FindOptions;
+use metrics;
+use bufstream::BufStream;
+use pool::{FlushMode, PooledStream};
+use poison::Recover;
+use rate_limit;
+use stream::Stream;
+use time;
+use topology::WriteLimits;
+use wire_protocol::flags::{OpQueryFlags, OpReplyFlags};
+use wire_protocol::operations::{ByteLength, Message};
+use wire_protocol::reply::ReplyBuffer;
+
+use std::{ i32, usize };
+use std::io::{Read, Write};
+use std::mem::{self, size_of};
+use std::thread;
+use std::time::Instant;
+use std::collections::vec_deque::VecDeque;
+
+// Allows the server to decide the batch size.
+pub const DEFAULT_BATCH_SIZE: i32 = 0;
+
+// How much larger than the largest document servers accept a command, leaving room for the
+// fields around the documents it carries.
+const COMMAND_OVERHEAD: i64 = 16 * 1024;
+
+/// Maintains a connection to the server and lazily returns documents from a
+/// query.
+#[derive(Debug)]
+pub struct Cursor {
+    // The client to read from.
+    client: Client,
+    // The namespace to read and write from.
+    namespace: String,
+    // How many documents to fetch at a given time from the server.
+    batch_size: i32,
+    // Uniquely identifies the cursor being returned by the reply.
+    cursor_id: i64,
+    // An upper bound on the total number of documents this cursor should return.
+    limit: i32,
+    // How many documents have been returned so far.
+    count: i32,
+    // A cache for documents received from the query that have not yet been returned.
+    buffer: BatchBuffer,
+    // The buffer getMore replies are read into, swapped with the one `buffer` is done with.
+    receive: ReplyBuffer,
+    // The decoded size of the batches in `buffer`, counted against the client's limit until
+    // the buffer is emptied.
+    buffered_bytes: usize,
+    // The size of the last batch, which room is reserved for ahead of the next getMore.
+    batch_bytes: usize,
+    // The room reserved for the batch being fetched, until it arrives.
+    reserved_bytes: usize,
+    // How long tailable getMores wait for new documents; when set, getMores are sent as
+    // commands, since OP_GET_MORE cannot carry it.
+    max_await_time_ms: Option<i64>,
+    // The server holding the cursor, which it is killed on if dropped before it is exhausted.
+    server: Option<Host>,
+    read_preference: ReadPreference,
+    cmd_type: CommandType,
+}
+
+macro_rules! try_or_emit {
+    ($cmd_type:expr, $cmd_name:expr, $req_id:expr, $connstring:expr, $result:expr, $client:expr) =>
+    {
+        match $result {
+            Ok(val) => val,
+            Err(e) => {
+                $client.health.record_failure(&e);
+
+                if $cmd_type != CommandType::Suppressed {
+                    let command_name = String::from($cmd_name);
+
+                    warn!(
+                        target: "mongodb::command",
+                        "{} (request {}) to {} failed: {}",
+                        command_name,
+                        $req_id,
+                        $connstring,
+                        e
+                    );
+
+                    $client.metrics.counter(
+                        metrics::ERRORS,
+                        &[("command", &command_name[..]), ("class", metrics::error_class(&e))],
+                        1,
+                    );
+
+                    let hook_result = $client.run_completion_hooks(&CommandResult::Failure {
+                        duration: 0,
+                        command_name: command_name,
+                        failure: &e,
+                        request_id: $req_id as i64,
+                        connection_string: $connstring,
+                    });
+
+                    if hook_result.is_err() {
+                        return Err(Error::EventListenerError(Some(Box::new(e))));
+                    }
+                }
+
+                return Err(e)
+            }
+        }
+    };
+}
+
+impl Cursor {
+    /// Construcs a new Cursor for a database command.
+    ///
+    /// # Arguments
+    ///
+    /// `client` - Client making the request.
+    /// `db` - Which database the command is being sent to.
+    /// `doc` - Specifies the command that is being run.
+    /// `cmd_type` - The type of command, which will be used for monitoring events.
+    /// `read_pref` - The read preference for the query.
+    ///
+    /// # Return value
+    ///
+    /// Returns the newly created Cursor on success, or an Error on failure.
+    pub fn command_cursor(
+        client: Client,
+        db: &str,
+        doc: bson::Document,
+        cmd_type: CommandType,
+        read_pref: ReadPreference,
+    ) -> Result<Cursor> {
+        let mut options = FindOptions::new();
+        options.batch_size = Some(1);
+
+        Cursor::query(
+            client.clone(),
+            format!("{}.$cmd", db),
+            OpQueryFlags::empty(),
+            doc,
+            options,
+            cmd_type,
+            true,
+            read_pref,
+        )
+    }
+
+    // Returns an exhausted cursor over documents that were all returned in a command reply.
+    pub(crate) fn with_documents(
+        client: Client,
+        namespace: String,
+        documents: VecDeque<bson::Document>,
+        cmd_type: CommandType,
+        read_preference: ReadPreference,
+    ) -> Cursor {
+        let mut cursor = Cursor {
+            client: client,
+            namespace: namespace,
+            batch_size: documents.len() as i32,
+            cursor_id: 0,
+            limit: 0,
+            count: 0,
+            buffer: BatchBuffer::new(),
+            receive: ReplyBuffer::new(),
+            buffered_bytes: 0,
+            batch_bytes: 0,
+            reserved_bytes: 0,
+            max_await_time_ms: None,
+            server: None,
+            read_preference: read_preference,
+            cmd_type: cmd_type,
+        };
+
+        cursor.buffer_batch(documents);
+        cursor
+    }
+
+    // Appends a batch to the buffer and counts it against the client's memory limit.
+    fn buffer_batch(&mut self, batch: VecDeque<bson::Document>) {
+        let bytes = batch.iter().map(|doc| doc.byte_length().unwrap_or(0) as usize).sum();
+        self.reserve_buffered(bytes);
+        self.buffer.extend(batch);
+    }
+
+    fn reserve_buffered(&mut self, bytes: usize) {
+        let reserved = mem::replace(&mut self.reserved_bytes, 0);
+        self.client.cursor_memory.exchange(reserved, bytes);
+        self.buffered_bytes += bytes;
+        self.batch_bytes += bytes;
+    }
+
+    // Waits until the client's cursors leave room for another batch the size of the last
+    // one, for at most the client's wait, and reserves it.
+    fn reserve_room(&mut self) {
+        let estimate = mem::replace(&mut self.batch_bytes, 0);
+        self.client.cursor_memory.reserve_room(estimate);
+        self.reserved_bytes = estimate;
+    }
+
+    // Returns the room reserved for a batch that did not arrive.
+    fn release_reserved(&mut self) {
+        let reserved = mem::replace(&mut self.reserved_bytes, 0);
+        self.client.cursor_memory.release(reserved);
+    }
+
+    fn release_buffered(&mut self) {
+        self.client.cursor_memory.release(self.buffered_bytes);
+        self.buffered_bytes = 0;
+    }
+
+    // Returns the memory of the buffered batches once they have all been consumed.
+    fn release_if_consumed(&mut self) {
+        if self.buffer.is_empty() {
+            self.release_buffered();
+        }
+    }
+
+    /// Returns whether the server still holds the cursor, so more documents may be returned.
+    ///
+    /// Tailable cursors stay alive after returning every document available so far: once
+    /// iteration ends, it can be continued later to receive documents inserted since.
+    pub fn is_alive(&self) -> bool {
+        self.cursor_id != 0
+    }
+
+    pub(crate) fn set_max_await_time_ms(&mut self, max_await_time_ms: i64) {
+        self.max_await_time_ms = Some(max_await_time_ms);
+    }
+
+    /// Limits how many of the documents the cursor buffers are kept decoded at once. The
+    /// rest stay encoded, or are encoded again if they arrived decoded, and are decoded one
+    /// at a time as they are returned, so that iterating over large batches of large
+    /// documents holds little more than their encoded size.
+    pub fn set_max_decoded_documents(&mut self, max: usize) {
+        self.buffer.set_max_decoded(max);
+    }
+
+    /// Returns the decoded size in bytes of the documents this cursor has buffered but not
+    /// yet returned.
+    ///
+    /// Batches are released as a whole, once their last document is returned.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    pub(crate) fn get_bson_and_cid_from_message(
+        message: Message,
+    ) -> Result<(bson::Document, VecDeque<bson::Document>, i64)> {
+        match message {
+            Message::OpReply {
+                cursor_id: cid,
+                documents: docs,
+                ..
+            } => {
+                let out_doc = if let Some(out_doc) = docs.get(0) {
+                    Cursor::check_reply_document(out_doc)?;
+                    out_doc.clone()
+                } else {
+                    bson::Document::new()
+                };
+
+                Ok((out_doc, docs.into_iter().collect(), cid))
+            }
+            _ => Err(Error::CursorNotFoundError),
+        }
+    }
+
+    // Fails if the first document of a reply reports an error.
+    fn check_reply_document(out_doc: &bson::Document) -> Result<()> {
+        if let Some(&Bson::I32(code)) = out_doc.get("code") {
+            // Legacy query and getMore failures report `$err` instead of `errmsg`.
+            if code == ErrorCode::ExceededTimeLimit as i32 {
+                let message = match (out_doc.get("errmsg"), out_doc.get("$err")) {
+                    (Some(&Bson::String(ref msg)), _) |
+                    (None, Some(&Bson::String(ref msg))) => msg.to_owned(),
+                    _ => String::from("operation exceeded time limit"),
+                };
+                return Err(Error::MaxTimeMSExpired(message));
+            }
+
+            // If command doesn't exist or namespace not found, return
+            // an empty array instead of throwing an error.
+            if code != ErrorCode::CommandNotFound as i32 &&
+                code != ErrorCode::NamespaceNotFound as i32
+            {
+                if let Some(&Bson::String(ref msg)) = out_doc.get("errmsg") {
+                    return Err(Error::OperationError(msg.to_owned()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_bson_and_cursor_info_from_command_message(
+        message: Message,
+    ) -> Result<(bson::Document, VecDeque<bson::Document>, i64, String)> {
+
+        let (first, mut v, _) = Cursor::get_bson_and_cid_from_message(message)?;
+
+        // Extract cursor information
+        let mut cursor = match v.remove(0).and_then(|mut doc| doc.remove("cursor")) {
+            Some(Bson::Document(cursor)) => cursor,
+            _ => return Err(Error::CursorNotFoundError),
+        };
+
+        match (cursor.remove("id"), cursor.remove("ns"), cursor.remove("firstBatch")) {
+            (Some(Bson::I64(id)),
+             Some(Bson::String(ns)),
+             Some(Bson::Array(batch))) => {
+                // Extract first batch documents
+                let map = batch
+                    .into_iter()
+                    .filter_map(|bdoc| if let Bson::Document(doc) = bdoc {
+                        Some(doc)
+                    } else {
+                        None
+                    })
+                    .collect();
+
+                Ok((first, map, id, ns))
+            }
+            _ => Err(Error::CursorNotFoundError)
+        }
+    }
+
+    // Extracts the batch and cursor id from the reply to a getMore command.
+    fn get_next_batch_from_command_reply(
+        mut reply: bson::Document,
+    ) -> Result<(VecDeque<bson::Document>, i64)> {
+        let mut cursor = match reply.remove("cursor") {
+            Some(Bson::Document(cursor)) => cursor,
+            _ => return Err(Error::CursorNotFoundError),
+        };
+
+        match (cursor.remove("id"), cursor.remove("nextBatch")) {
+            (Some(Bson::I64(id)), Some(Bson::Array(batch))) => {
+                let batch = batch
+                    .into_iter()
+                    .filter_map(|bdoc| if let Bson::Document(doc) = bdoc {
+                        Some(doc)
+                    } else {
+                        None
+                    })
+                    .collect();
+
+                Ok((batch, id))
+            }
+            _ => Err(Error::CursorNotFoundError),
+        }
+    }
+
+    /// Executes a query where the batch size of the returned cursor is
+    /// specified.
+    ///
+    /// # Arguments
+    ///
+    /// `client` - The client to read from.
+    /// `namespace` - The namespace to read and write from.
+    /// `flags` - Bit vector of query options.
+    /// `query` - Document describing the query to make.
+    /// `options` - Options for the query.
+    /// `cmd_type` - The type of command, which will be used for monitoring events.
+    /// `is_cmd_cursor` - Whether or not the Cursor is for a database command.
+    /// `read_pref` - The read preference for the query.
+    ///
+    /// # Return value
+    ///
+    /// Returns the cursor for the query results on success, or an Error on
+    /// failure.
+    pub fn query(
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: ReadPreference,
+    ) -> Result<Cursor> {
+        let _span = enter_span!(
+            INFO,
+            "mongodb.operation",
+            namespace = &namespace[..],
+            command = cmd_type.to_str()
+        );
+
+        // Throttle before selecting a server, so waiting operations hold no connection.
+        if cmd_type != CommandType::Suppressed {
+            let operation_namespace = rate_limit::operation_namespace(&namespace, &query);
+            client.rate_limiter.acquire(&operation_namespace, cmd_type);
+        }
+
+        // Select a server stream from the topology.
+        let (mut stream, slave_ok, send_read_pref) = if cmd_type.is_write_command() {
+            (client.acquire_write_stream()?, false, false)
+        } else {
+            client.acquire_stream(read_pref.to_owned())?
+        };
+
+        // Set slave_ok flag based on the result from server selection.
+        let new_flags = if slave_ok {
+            flags | OpQueryFlags::SLAVE_OK
+        } else {
+            flags
+        };
+
+        // Send read_preference to the server based on the result from server selection.
+        let new_query = if send_read_pref {
+            with_read_preference(query, &read_pref)
+        } else {
+            query
+        };
+
+        Cursor::query_with_stream(
+            &mut stream,
+            client,
+            namespace,
+            new_flags,
+            new_query,
+            options,
+            cmd_type,
+            is_cmd_cursor,
+            Some(read_pref),
+        )
+    }
+
+    pub fn query_with_stream(
+        stream: &mut PooledStream,
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: Option<ReadPreference>,
+    ) -> Result<Cursor> {
+        let req_id = client.get_req_id();
+        if !client.error_context {
+            return Cursor::send_query(
+                stream,
+                client,
+                namespace,
+                flags,
+                query,
+                options,
+                cmd_type,
+                is_cmd_cursor,
+                read_pref,
+                req_id,
+            );
+        }
+
+        let started = Instant::now();
+        let server = stream.host().clone();
+        let context = ErrorContext {
+            command_name: String::from(cmd_type.to_str()),
+            namespace: namespace.clone(),
+            server: format!("{}:{}", server.host_name, server.port),
+            request_id: req_id,
+            elapsed: Default::default(),
+        };
+
+        Cursor::send_query(
+            stream,
+            client,
+            namespace,
+            flags,
+            query,
+            options,
+            cmd_type,
+            is_cmd_cursor,
+            read_pref,
+            req_id,
+        ).map_err(|err| with_context(err, context, started))
+    }
+
+    // Sends a query as request `req_id` and reads the first batch of its reply.
+    fn send_query(
+        stream: &mut PooledStream,
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: Option<ReadPreference>,
+        req_id: i32,
+    ) -> Result<Cursor> {
+
+        let server = stream.host().clone();
+        let server_name = format!("{}:{}", server.host_name, server.port);
+
+        let _span = enter_span!(
+            INFO,
+            "mongodb.command",
+            namespace = &namespace[..],
+            command = cmd_type.to_str(),
+            server = &server_name[..],
+            request_id = req_id
+        );
+
+        let index = namespace.find('.').unwrap_or_else(|| namespace.len());
+        let db_name = String::from(&namespace[..index]);
+        let coll_name = String::from(&namespace[index + 1..]);
+        let cmd_name = cmd_type.to_str();
+        let connstring = stream.get_socket().get_ref().peer_addr()?.to_string();
+
+        // Only commands declare the API version, not legacy finds.
+        let mut query = if coll_name == "$cmd" {
+            with_server_api(query, client.server_api.as_ref())
+        } else {
+            query
+        };
+
+        let context = CommandContext {
+            command_name: cmd_name,
+            database_name: &db_name,
+            namespace: &namespace,
+            connection_string: &connstring,
+            request_id: req_id,
+        };
+        let intercepted = cmd_type != CommandType::Suppressed && !client.interceptors.is_empty();
+        if intercepted {
+            client.interceptors.intercept_command(&context, &mut query)?;
+        }
+
+        let filter = match query.get("$query") {
+            Some(&Bson::Document(ref doc)) => doc.clone(),
+            _ => query.clone(),
+        };
+
+        // Unacknowledged writes of a client with `FlushMode::Coalesce` are held on the
+        // connection instead, to be sent with its next message, and report success without
+        // any counts.
+        let held = client.flush_mode == FlushMode::Coalesce && cmd_type.is_write_command() &&
+            is_unacknowledged(&filter);
+
+        let command = match cmd_type {
+            CommandType::Find => {
+                let document = doc! {
+                    "find": coll_name,
+                    "filter": filter
+                };
+
+                merge_options(document, options.clone())
+            }
+            _ => query.clone(),
+        };
+
+        let init_time = time::precise_time_ns();
+        let message = Message::new_query(
+            req_id,
+            flags,
+            namespace.clone(),
+            options.skip.unwrap_or(0) as i32,
+            options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            query,
+            options.projection,
+        )?;
+
+        if cmd_type != CommandType::Suppressed && cmd_type != CommandType::IsMaster {
+            let limits = client.topology.description.read().recover().lowest_write_limits();
+            check_command_size(limits, cmd_name, &message)?;
+        }
+
+        if cmd_type != CommandType::Suppressed {
+            debug!(
+                target: "mongodb::command",
+                "Sending {} (request {}) to {} on database {}",
+                cmd_name,
+                req_id,
+                connstring,
+                db_name
+            );
+            trace!(target: "mongodb::command", "Request {}: {}", req_id, command);
+
+            client.metrics.counter(metrics::OPERATIONS, &[("command", cmd_name)], 1);
+
+            let hook_result = client.run_start_hooks(&CommandStarted {
+                command: command,
+                database_name: db_name.clone(),
+                command_name: String::from(cmd_name),
+                request_id: req_id as i64,
+                connection_string: connstring.clone(),
+            });
+
+            if hook_result.is_err() {
+                return Err(Error::EventListenerError(None));
+            }
+        }
+
+        let labels = [("server", &server_name[..])];
+        let written = if held {
+            stream.hold(&message)
+        } else {
+            message.write(&mut stream.writer())
+        };
+        try_or_emit!(cmd_type, cmd_name, req_id, connstring, written, client);
+        client.metrics.counter(metrics::BYTES_SENT, &labels, message.message_length() as u64);
+
+        let reply = if held {
+            None
+        } else {
+            let mut reply = try_or_emit!(
+                cmd_type,
+                cmd_name,
+                req_id,
+                connstring,
+                stream.reader().and_then(|socket| Message::read(socket)),
+                client
+            );
+            let length = reply.message_length() as u64;
+            client.metrics.counter(metrics::BYTES_RECEIVED, &labels, length);
+            client.health.record_success();
+
+            if intercepted {
+                try_or_emit!(
+                    cmd_type,
+                    cmd_name,
+                    req_id,
+                    connstring,
+                    client.interceptors.intercept_reply(&context, &mut reply),
+                    client
+                );
+            }
+
+            Some(reply)
+        };
+
+        let fin_time = time::precise_time_ns();
+
+        let (doc, buf, cursor_id, namespace) = match reply {
+            None => {
+                let ok = doc! { "ok": 1 };
+                (ok.clone(), vec![ok].into_iter().collect(), 0, namespace)
+            }
+            Some(reply) => if is_cmd_cursor {
+                try_or_emit!(
+                    cmd_type,
+                    cmd_name,
+                    req_id,
+                    connstring,
+                    Cursor::get_bson_and_cursor_info_from_command_message(reply),
+                    client
+                )
+            } else {
+                let (doc, buf, id) = try_or_emit!(
+                    cmd_type,
+                    cmd_name,
+                    req_id,
+                    connstring,
+                    Cursor::get_bson_and_cid_from_message(reply),
+                    client
+                );
+                (doc, buf, id, namespace)
+            },
+        };
+
+        let reply = match cmd_type {
+            CommandType::Find => doc! {
+                "cursor": {
+                    "id": cursor_id,
+                    "ns": &namespace,
+                    "firstBatch": buf.iter().cloned().map(Bson::from).collect::<Vec<_>>(),
+                },
+                "ok": 1
+            },
+            _ => doc,
+        };
+
+        if cmd_type != CommandType::Suppressed {
+            debug!(
+                target: "mongodb::command",
+                "{} (request {}) succeeded in {:.3} ms",
+                cmd_name,
+                req_id,
+                (fin_time - init_time) as f64 / 1_000_000.0
+            );
+
+            client.metrics.histogram(
+                metrics::COMMAND_DURATION,
+                &[("command", cmd_name)],
+                (fin_time - init_time) as f64 / 1_000_000_000.0,
+            );
+            client.latency.record(cmd_name, &server_name, fin_time - init_time);
+
+            let _hook_result = client.run_completion_hooks(&CommandResult::Success {
+                duration: fin_time - init_time,
+                reply: reply,
+                command_name: String::from(cmd_name),
+                request_id: req_id as i64,
+                connection_string: connstring,
+            });
+        }
+
+        let read_preference =
+            read_pref.unwrap_or_else(|| ReadPreference::new(ReadMode::Primary, None));
+
+        // Check if actual batch size fits into an `i32`.
+        if size_of::<i32>() <= size_of::<usize>() && buf.len() > i32::MAX as usize {
+            return Err(Error::DefaultError(
+                format!("Batch buffer size {} overflows i32", buf.len())
+            ));
+        }
+
+        let mut cursor = Cursor {
+            client: client,
+            namespace: namespace,
+            batch_size: buf.len() as i32,
+            cursor_id: cursor_id,
+            limit: options.limit.unwrap_or(0) as i32,
+            count: 0,
+            buffer: BatchBuffer::new(),
+            receive: ReplyBuffer::new(),
+            buffered_bytes: 0,
+            batch_bytes: 0,
+            reserved_bytes: 0,
+            max_await_time_ms: None,
+            server: Some(server),
+            read_preference: read_preference,
+            cmd_type: cmd_type.clone(),
+        };
+
+        cursor.buffer_batch(buf);
+        Ok(cursor)
+    }
+
+    fn get_from_stream(&mut self) -> Result<()> {
+        // Hold off fetching another batch while the client's cursors buffer too much.
+        self.reserve_room();
+
+        let result = self.get_more(|cursor, socket| cursor.read_get_more_reply(socket));
+        self.release_reserved();
+        result
+    }
+
+    // Counts the bytes of a getMore or its reply against the cursor's server.
+    fn count_bytes(&self, name: &'static str, message_length: i32) {
+        if let Some(ref server) = self.server {
+            let server_name = format!("{}:{}", server.host_name, server.port);
+            let labels = [("server", &server_name[..])];
+            self.client.metrics.counter(name, &labels, message_length as u64);
+        }
+    }
+
+    // Reads the reply to a getMore into the buffer. The documents of a legacy reply are left
+    // encoded until they are returned; a getMore command nests its batch in a single reply
+    // document, which is decoded whole.
+    fn read_get_more_reply<T: Read + Write>(&mut self, socket: &mut T) -> Result<()> {
+        self.receive.read_from(socket)?;
+        self.count_bytes(metrics::BYTES_RECEIVED, self.receive.message_length());
+
+        let flags = self.receive.flags();
+
+        // A getMore sent to a server that does not own the cursor, such as a different
+        // mongos, is answered with an empty batch and this flag.
+        if flags.contains(OpReplyFlags::CURSOR_NOT_FOUND) {
+            return Err(Error::CursorNotFoundError);
+        }
+
+        // Failed getMores, such as on a cursor killed in the meantime, report `$err` in place
+        // of a batch.
+        if flags.contains(OpReplyFlags::QUERY_FAILURE) {
+            let doc = self.receive.decode(0).unwrap_or_else(|| Ok(bson::Document::new()))?;
+            Cursor::check_reply_document(&doc)?;
+            return Err(Error::OperationError(match doc.get("$err") {
+                Some(&Bson::String(ref message)) => message.to_owned(),
+                _ => String::from("getMore failed"),
+            }));
+        }
+
+        if self.max_await_time_ms.is_some() {
+            let mut reply = self.receive.decode(0).unwrap_or_else(|| Ok(bson::Document::new()))?;
+            Cursor::check_reply_document(&reply)?;
+            self.client.decrypt_reply(&mut reply)?;
+            let (v, cursor_id) = Cursor::get_next_batch_from_command_reply(reply)?;
+            self.cursor_id = cursor_id;
+            self.buffer_batch(v);
+            return Ok(());
+        }
+
+        // Only a first document with a code can report an error, so others stay encoded.
+        if let Some(bytes) = self.receive.document(0) {
+            if RawDocumentRef::from_bytes(bytes)?.get("code")?.is_some() {
+                Cursor::check_reply_document(&bson::decode_document(&mut &bytes[..])?)?;
+            }
+        }
+
+        self.cursor_id = self.receive.cursor_id();
+
+        // getMores bypass the interceptors, so encrypted values are decrypted here.
+        if self.client.decrypts_replies() {
+            let mut batch = VecDeque::with_capacity(self.receive.len());
+            for index in 0..self.receive.len() {
+                if let Some(doc) = self.receive.decode(index) {
+                    let mut doc = doc?;
+                    self.client.decrypt_reply(&mut doc)?;
+                    batch.push_back(doc);
+                }
+            }
+            self.buffer_batch(batch);
+            return Ok(());
+        }
+
+        self.reserve_buffered(self.receive.documents_length());
+        self.buffer.push_reply(&mut self.receive);
+        Ok(())
+    }
+
+    // Sends a getMore and hands the socket to `read` to consume the reply.
+    fn get_more<F>(&mut self, read: F) -> Result<()>
+    where
+        F: FnOnce(&mut Cursor, &mut BufStream<Stream>) -> Result<()>,
+    {
+        let _span = enter_span!(
+            INFO,
+            "mongodb.operation",
+            namespace = &self.namespace[..],
+            command = "get_more"
+        );
+
+        // Failing to check out a connection happens before the getMore is sent, so a fresh
+        // server selection can be attempted once without losing a batch. Anything that fails
+        // after the request reaches the wire leaves the cursor position unknown.
+        let mut stream = match self.client.acquire_stream(self.read_preference.to_owned()) {
+            Ok((stream, _, _)) => stream,
+            Err(ref err) if err.is_network_error() => {
+                self.client.acquire_stream(self.read_preference.to_owned())?.0
+            }
+            Err(err) => return Err(err),
+        };
+
+        // Re-attached cursors learn which server holds them from their first getMore.
+        if self.server.is_none() {
+            self.server = Some(stream.host().clone());
+        }
+
+        let _command_span = enter_span!(
+            INFO,
+            "mongodb.command",
+            namespace = &self.namespace[..],
+            command = "get_more",
+            server = %format!("{}:{}", stream.host().host_name, stream.host().port),
+            request_id = ::tracing::field::Empty
+        );
+
+        let req_id = self.client.get_req_id();
+        let started = Instant::now();
+        let result = self.send_get_more(&mut stream, req_id)
+            .and_then(|()| stream.reader())
+            .and_then(|socket| read(self, socket));
+        match result {
+            Ok(()) => self.client.health.record_success(),
+            Err(ref err) => self.client.health.record_failure(err),
+        }
+
+        let result = match result {
+            Err(Error::IoError(err)) => {
+                Err(Error::CursorInvalidatedError(
+                    self.cursor_id,
+                    self.count,
+                    Box::new(Error::IoError(err)),
+                ))
+            }
+            result => result,
+        };
+
+        if !self.client.error_context {
+            return result;
+        }
+
+        result.map_err(|err| {
+            let host = stream.host();
+            let context = ErrorContext {
+                command_name: String::from("get_more"),
+                namespace: self.namespace.clone(),
+                server: format!("{}:{}", host.host_name, host.port),
+                request_id: req_id,
+                elapsed: Default::default(),
+            };
+            with_context(err, context, started)
+        })
+    }
+
+    // Returns the getMore command that waits up to `max_await_time_ms` for new documents.
+    fn get_more_command(&self, max_await_time_ms: i64) -> bson::Document {
+        let index = self.namespace.find('.').unwrap_or_else(|| self.namespace.len());
+
+        let mut command = doc! {
+            "getMore": self.cursor_id,
+            "collection": self.namespace.get(index + 1..).unwrap_or(""),
+            "maxTimeMS": max_await_time_ms,
+        };
+
+        if self.batch_size > 0 {
+            command.insert("batchSize", self.batch_size);
+        }
+
+        with_server_api(command, self.client.server_api.as_ref())
+    }
+
+    fn send_get_more(&mut self, stream: &mut PooledStream, req_id: i32) -> Result<()> {
+        record_span!("request_id", req_id);
+
+        let index = self.namespace.find('.').unwrap_or_else(
+            || self.namespace.len(),
+        );
+        let db_name = String::from(&self.namespace[..index]);
+
+        let (get_more, command) = match self.max_await_time_ms {
+            Some(max_await_time_ms) => {
+                let command = self.get_more_command(max_await_time_ms);
+
+                // Tailable cursors may live on a secondary, which only runs commands sent
+                // with slaveOk.
+                let get_more = Message::new_query(
+                    req_id,
+                    OpQueryFlags::SLAVE_OK,
+                    format!("{}.$cmd", db_name),
+                    0,
+                    -1,
+                    command.clone(),
+                    None,
+                )?;
+
+                (get_more, command)
+            }
+            None => {
+                let get_more = Message::new_get_more(
+                    req_id,
+                    self.namespace.to_owned(),
+                    self.batch_size,
+                    self.cursor_id,
+                );
+
+                (get_more, doc! { "cursor_id": self.cursor_id })
+            }
+        };
+
+        let cmd_name = String::from("get_more");
+        let connstring = stream.get_socket().get_ref().peer_addr()?.to_string();
+
+        if self.cmd_type != CommandType::Suppressed {
+            debug!(
+                target: "mongodb::command",
+                "Sending getMore (request {}) for cursor {} on {} to {}",
+                req_id,
+                self.cursor_id,
+                self.namespace,
+                connstring
+            );
+
+            self.client.metrics.counter(metrics::OPERATIONS, &[("command", &cmd_name[..])], 1);
+
+            let hook_result = self.client.run_start_hooks(&CommandStarted {
+                command: command,
+                database_name: db_name,
+                command_name: cmd_name.clone(),
+                request_id: req_id as i64,
+                connection_string: connstring.clone(),
+            });
+
+            if hook_result.is_err() {
+                return Err(Error::EventListenerError(None));
+            }
+        }
+
+        try_or_emit!(
+            self.cmd_type,
+            cmd_name,
+            req_id,
+            connstring,
+            get_more.write(&mut stream.writer()),
+            self.client
+        );
+        self.count_bytes(metrics::BYTES_SENT, get_more.message_length());
+
+        Ok(())
+    }
+
+    /// Attempts to read a specified number of BSON documents from the cursor.
+    ///
+    /// # Arguments
+    ///
+    /// `n` - The number of documents to read.
+    ///
+    /// # Return value
+    ///
+    /// Returns a vector containing the BSON documents that were read.
+    pub fn next_n(&mut self, n: usize) -> Result<Vec<bson::Document>> {
+        self.take(n).collect()
+    }
+
+    /// # Return value
+    ///
+    /// Returns a vector containing the BSON documents that were read.
+    #[deprecated(since = "0.2.8",
+                 note = "this method uses 20 as the default batch size instead of letting the server decide; using `drain_current_batch` is recommended instead")]
+    pub fn next_batch(&mut self) -> Result<Vec<bson::Document>> {
+        let batch_size = if self.batch_size == 0 {
+            20
+        } else {
+            self.batch_size
+        };
+
+        // If `usize` is at least as wide as `i32`, then all non-negative values
+        // of `i32` are guaranteed to fit into a `usize`. Otherwise `usize::MAX`
+        // fits into an `i32`.
+        if
+            batch_size < 0
+            ||
+            (
+                size_of::<usize>() < size_of::<i32>()
+                &&
+                batch_size > usize::MAX as i32
+            )
+        {
+            return Err(Error::DefaultError(
+                format!("batch size {} is out of range", batch_size)
+            ));
+        }
+
+        self.next_n(batch_size as usize)
+    }
+
+    /// Attempts to read a batch of BSON documents from the cursor.
+    ///
+    /// # Return value
+    ///
+    /// Returns a vector containing the BSON documents that were read.
+    pub fn drain_current_batch(&mut self) -> Result<Vec<bson::Document>> {
+        if self.buffer.is_empty() {
+            self.get_from_stream()?;
+        }
+
+        let len = self.buffer.len();
+        let batch = self.buffer.take(len);
+        self.release_if_consumed();
+        batch
+    }
+
+    /// Returns an iterator over whole batches of documents, as they are received from the
+    /// server.
+    ///
+    /// Each batch starts with the documents already buffered by the cursor, so batch and
+    /// per-document iteration can be mixed.
+    pub fn batches(&mut self) -> Batches<'_> {
+        Batches { cursor: self }
+    }
+
+    /// Returns how many documents the cursor has buffered but not yet returned.
+    pub fn buffered_count(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the id of the server-side cursor, or zero once it is exhausted.
+    pub fn id(&self) -> i64 {
+        self.cursor_id
+    }
+
+    /// Returns the namespace the cursor reads from.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns how many documents the cursor has returned so far.
+    pub fn position(&self) -> i32 {
+        self.count
+    }
+
+    // Returns the rest of the buffered batch, up to the cursor's limit.
+    fn take_batch(&mut self) -> Result<Vec<bson::Document>> {
+        let mut len = self.buffer.len();
+        if self.limit > 0 {
+            len = len.min((self.limit - self.count).max(0) as usize);
+        }
+
+        let batch = self.buffer.take(len);
+        self.count += len as i32;
+        self.release_if_consumed();
+        batch
+    }
+
+    /// Checks whether there are any more documents for the cursor to return.
+    ///
+    /// # Return value
+    ///
+    /// Returns `true` if the cursor is not yet exhausted, or `false` if it is.
+    pub fn has_next(&mut self) -> Result<bool> {
+        if self.limit > 0 && self.count >= self.limit {
+            Ok(false)
+        } else {
+            if self.buffer.is_empty() && self.limit != 1 && self.cursor_id != 0 {
+                self.get_from_stream()?;
+            }
+            Ok(!self.buffer.is_empty())
+        }
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = Result<bson::Document>;
+
+    /// Attempts to read a BSON document from the cursor.
+    ///
+    /// # Return value
+    ///
+    /// Returns a BSON document if there is another one to return; `None` if
+    /// there are no more documents to return; or an Error if the request for
+    /// another document fails.
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        match self.has_next() {
+            Ok(true) => {
+                self.count += 1;
+                let doc = self.buffer.pop_front();
+                self.release_if_consumed();
+                doc
+            }
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Iterates over the batches of a cursor; created by `Cursor::batches`.
+#[derive(Debug)]
+pub struct Batches<'a> {
+    cursor: &'a mut Cursor,
+}
+
+impl<'a> Iterator for Batches<'a> {
+    type Item = Result<Vec<bson::Document>>;
+
+    /// Returns the next non-empty batch, `None` once the cursor is exhausted, or an Error if
+    /// requesting the batch fails.
+    fn next(&mut self) -> Option<Result<Vec<bson::Document>>> {
+        match self.cursor.has_next() {
+            Ok(true) => Some(self.cursor.take_batch()),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl Cursor {
+    // Closes the server-side cursor of a cursor dropped before it was exhausted. The
+    // killCursors is only sent on an idle connection, so that dropping never waits for one;
+    // otherwise, or if sending it fails, the server's monitor kills the cursor later. With
+    // `FlushMode::Coalesce` it is held on the connection until its next message instead.
+    fn kill_on_drop(&mut self) {
+        let host = match self.server.take() {
+            Some(host) if self.cursor_id != 0 => host,
+            _ => return,
+        };
+
+        let pool = match self.client.topology.description.read().recover().servers.get(&host) {
+            Some(server) => server.pool(),
+            // The cursor went away with its server.
+            None => return,
+        };
+
+        // A thread that is unwinding does not start new network round trips.
+        if !thread::panicking() {
+            if let Some(mut stream) = pool.try_acquire_idle_stream() {
+                let req_id = self.client.get_req_id();
+                let cursor_ids = vec![self.cursor_id];
+                let server_api = self.client.server_api.as_ref();
+                let killed = if self.client.flush_mode == FlushMode::Coalesce {
+                    kill_cursors_message(req_id, &self.namespace, cursor_ids, server_api)
+                        .and_then(|message| stream.hold(&message))
+                } else {
+                    kill_cursors(&mut stream, req_id, &self.namespace, cursor_ids, server_api)
+                };
+
+                match killed {
+                    Ok(()) => return,
+                    Err(_) => stream.discard(),
+                }
+            }
+        }
+
+        if let Some(server) = self.client.topology.description.read().recover().servers.get(&host) {
+            server.kill_cursor_later(self.namespace.clone(), self.cursor_id);
+        }
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        self.release_buffered();
+        self.kill_on_drop();
+    }
+}
+
+// Sends killCursors for cursors of `namespace` and waits for the reply, so the connection can
+// be reused.
+pub(crate) fn kill_cursors(
+    stream: &mut PooledStream,
+    req_id: i32,
+    namespace: &str,
+    cursor_ids: Vec<i64>,
+    server_api: Option<&ServerApi>,
+) -> Result<()> {
+    let message = kill_cursors_message(req_id, namespace, cursor_ids, server_api)?;
+    message.write(&mut stream.writer())?;
+    Message::read(stream.reader()?)?;
+    Ok(())
+}
+
+// Returns the killCursors command for cursors of `namespace`.
+fn kill_cursors_message(
+    req_id: i32,
+    namespace: &str,
+    cursor_ids: Vec<i64>,
+    server_api: Option<&ServerApi>,
+) -> Result<Message> {
+    let index = namespace.find('.').unwrap_or_else(|| namespace.len());
+    let cursor_ids: Vec<_> = cursor_ids.into_iter().map(Bson::I64).collect();
+
+    let command = doc! {
+        "killCursors": namespace.get(index + 1..).unwrap_or(""),
+        "cursors": cursor_ids,
+    };
+
+    // Cursors may live on a secondary, which only runs commands sent with slaveOk.
+    Message::new_query(
+        req_id,
+        OpQueryFlags::SLAVE_OK,
+        format!("{}.$cmd", &namespace[..index]),
+        0,
+        -1,
+        with_server_api(command, server_api),
+        None,
+    )
+}
+
+// Returns whether a command asks for its write not to be acknowledged, so that nobody
+// waits for its reply.
+fn is_unacknowledged(command: &bson::Document) -> bool {
+    match command.get("writeConcern") {
+        Some(&Bson::Document(ref write_concern)) => match write_concern.get("w") {
+            Some(&Bson::I32(0)) | Some(&Bson::I64(0)) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Adds `$readPreference` to a query or command sent to a mongos, which only reads it from a
+// `$query` document.
+fn with_read_preference(query: bson::Document, read_pref: &ReadPreference) -> bson::Document {
+    let mut query = if query.contains_key("$query") {
+        query
+    } else {
+        doc! { "$query": query }
+    };
+
+    query.insert("$readPreference", read_pref.to_document());
+    query
+}
+
+// Adds the Stable API fields to a command, within its `$query` document if it has one, when
+// the client declares a server API.
+pub(crate) fn with_server_api(
+    mut command: bson::Document,
+    server_api: Option<&ServerApi>,
+) -> bson::Document {
+    let server_api = match server_api {
+        Some(server_api) => server_api,
+        None => return command,
+    };
+
+    if let Some(Bson::Document(query)) = command.remove("$query") {
+        command.insert("$query", with_server_api(query, Some(server_api)));
+        return command;
+    }
+
+    command.insert("apiVersion", server_api.version.to_str());
+
+    if let Some(strict) = server_api.strict {
+        command.insert("apiStrict", strict);
+    }
+
+    if let Some(deprecation_errors) = server_api.deprecation_errors {
+        command.insert("apiDeprecationErrors", deprecation_errors);
+    }
+
+    command
+}
+
+// Attaches the context of a failed command, timed from `started`, to its error.
+fn with_context(err: Error, mut context: ErrorContext, started: Instant) -> Error {
+    context.elapsed = started.elapsed();
+    Error::Contextual(Box::new(context), Box::new(err))
+}
+
+// Fails before sending a command larger than the servers accept, which they would answer by
+// closing the connection.
+fn check_command_size(limits: WriteLimits, cmd_name: &str, message: &Message) -> Result<()> {
+    let length = i64::from(message.message_length());
+    if length > limits.max_message_size_bytes {
+        return Err(Error::ArgumentError(format!(
+            "The {} message is {} bytes, more than the maximum of {} bytes.",
+            cmd_name,
+            length,
+            limits.max_message_size_bytes
+        )));
+    }
+
+    // The command is smaller than its message, so only measure it when it might be too large.
+    if length <= limits.max_bson_object_size + COMMAND_OVERHEAD {
+        return Ok(());
+    }
+
+    if let Message::OpQuery { ref query, .. } = *message {
+        let size = i64::from(query.byte_length()?);
+        let max = limits.max_bson_object_size + COMMAND_OVERHEAD;
+        if size > max {
+            return Err(Error::ArgumentError(format!(
+                "The {} command is {} bytes, more than the maximum of {} bytes.",
+                cmd_name,
+                size,
+                max
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::ServerApiVersion;
+    use wire_protocol::replay::Replay;
+    use ClientOptions;
+
+    use std::collections::BTreeMap;
+
+    fn buffered_cursor(len: i32, limit: i32) -> Cursor {
+        let mut cursor = Cursor::with_documents(
+            Client::connect("localhost", 27017).unwrap(),
+            String::from("test.batches"),
+            (0..len).map(|i| doc! { "_id": i }).collect(),
+            CommandType::Find,
+            ReadPreference::new(ReadMode::Primary, None),
+        );
+        cursor.limit = limit;
+        cursor
+    }
+
+    #[test]
+    fn batches_continue_after_documents() {
+        let mut cursor = buffered_cursor(5, 0);
+        assert_eq!(Some(doc! { "_id": 0 }), cursor.next().map(|doc| doc.unwrap()));
+        assert_eq!(4, cursor.buffered_count());
+
+        let batches: Vec<_> = cursor.batches().map(|batch| batch.unwrap()).collect();
+        assert_eq!(1, batches.len());
+        assert_eq!(4, batches[0].len());
+        assert_eq!(0, cursor.buffered_count());
+        assert_eq!(0, cursor.buffered_bytes());
+    }
+
+    #[test]
+    fn batches_respect_the_limit() {
+        let mut cursor = buffered_cursor(5, 3);
+        let batches: Vec<_> = cursor.batches().map(|batch| batch.unwrap()).collect();
+        assert_eq!(vec![(0..3).map(|i| doc! { "_id": i }).collect::<Vec<_>>()], batches);
+    }
+
+    // A cursor with nothing buffered whose getMore replies are replayed from a recording.
+    fn replayed_cursor() -> Cursor {
+        let mut cursor = buffered_cursor(0, 0);
+        cursor.cursor_id = 4660;
+        cursor
+    }
+
+    #[test]
+    fn buffers_replayed_batches() {
+        let mut cursor = replayed_cursor();
+        let mut replay = Replay::recording("get_more_batch").in_chunks(1);
+        cursor.read_get_more_reply(&mut replay).unwrap();
+
+        assert_eq!(4660, cursor.cursor_id);
+        assert_eq!(2, cursor.buffered_count());
+        assert!(cursor.buffered_bytes() > 0);
+        assert_eq!(Some(doc! { "_id": 1 }), cursor.next().map(|doc| doc.unwrap()));
+
+        // An empty batch keeps the cursor alive without buffering anything.
+        let mut cursor = replayed_cursor();
+        cursor.read_get_more_reply(&mut Replay::recording("empty_batch")).unwrap();
+        assert!(cursor.is_alive());
+        assert_eq!(0, cursor.buffered_count());
+
+        let mut cursor = replayed_cursor();
+        cursor.max_await_time_ms = Some(100);
+        cursor.read_get_more_reply(&mut Replay::recording("command_get_more")).unwrap();
+        assert!(!cursor.is_alive());
+        assert_eq!(Some(doc! { "_id": 3 }), cursor.next().map(|doc| doc.unwrap()));
+    }
+
+    #[test]
+    fn reports_replayed_failures() {
+        let mut cursor = replayed_cursor();
+        match cursor.read_get_more_reply(&mut Replay::recording("cursor_not_found")) {
+            Err(Error::CursorNotFoundError) => (),
+            result => panic!("Expected CursorNotFoundError, got {:?}", result),
+        }
+
+        match cursor.read_get_more_reply(&mut Replay::recording("query_failure")) {
+            Err(Error::OperationError(ref message)) if message == "operation was interrupted" => (),
+            result => panic!("Expected an OperationError, got {:?}", result),
+        }
+
+        match cursor.read_get_more_reply(&mut Replay::recording("exceeded_time_limit")) {
+            Err(Error::MaxTimeMSExpired(_)) => (),
+            result => panic!("Expected MaxTimeMSExpired, got {:?}", result),
+        }
+
+        // The failure after a batch leaves the batch buffered.
+        let mut replay = Replay::recording("batch_then_failure");
+        cursor.read_get_more_reply(&mut replay).unwrap();
+        assert!(cursor.read_get_more_reply(&mut replay).is_err());
+        assert_eq!(1, cursor.buffered_count());
+        assert_eq!(0, replay.remaining());
+    }
+
+    #[test]
+    fn sends_read_preference_to_mongos() {
+        let mut tags = BTreeMap::new();
+        tags.insert(String::from("dc"), String::from("east"));
+        let read_pref = ReadPreference::new(ReadMode::SecondaryPreferred, Some(vec![tags]));
+
+        let expected = doc! {
+            "$query": { "dbStats": 1 },
+            "$readPreference": { "mode": "secondaryPreferred", "tags": [{ "dc": "east" }] },
+        };
+        assert_eq!(expected, with_read_preference(doc! { "dbStats": 1 }, &read_pref));
+
+        let nearest = ReadPreference::new(ReadMode::Nearest, None);
+        let query = doc! { "$query": { "a": 1 }, "$orderby": { "a": 1 } };
+        let expected = doc! {
+            "$query": { "a": 1 },
+            "$orderby": { "a": 1 },
+            "$readPreference": { "mode": "nearest" },
+        };
+        assert_eq!(expected, with_read_preference(query, &nearest));
+    }
+
+    #[test]
+    fn declares_server_api() {
+        let mut server_api = ServerApi::new(ServerApiVersion::V1);
+        assert_eq!(
+            doc! { "ping": 1, "apiVersion": "1" },
+            with_server_api(doc! { "ping": 1 }, Some(&server_api))
+        );
+        assert_eq!(doc! { "ping": 1 }, with_server_api(doc! { "ping": 1 }, None));
+
+        server_api.strict = Some(true);
+        server_api.deprecation_errors = Some(false);
+        let query = doc! { "$query": { "count": "a" }, "$readPreference": { "mode": "nearest" } };
+        let expected = doc! {
+            "$readPreference": { "mode": "nearest" },
+            "$query": {
+                "count": "a",
+                "apiVersion": "1",
+                "apiStrict": true,
+                "apiDeprecationErrors": false,
+            },
+        };
+        assert_eq!(expected, with_server_api(query, Some(&server_api)));
+    }
+
+    #[test]
+    fn declares_server_api_on_get_more_and_kill_cursors() {
+        let server_api = ServerApi::new(ServerApiVersion::V1);
+        let mut options = ClientOptions::new();
+        options.server_api = Some(server_api);
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let mut cursor = Cursor::with_documents(
+            client,
+            String::from("test.batches"),
+            VecDeque::new(),
+            CommandType::Find,
+            ReadPreference::new(ReadMode::Primary, None),
+        );
+        cursor.cursor_id = 4660;
+
+        let expected = doc! {
+            "getMore": 4660_i64,
+            "collection": "batches",
+            "maxTimeMS": 100_i64,
+            "apiVersion": "1",
+        };
+        assert_eq!(expected, cursor.get_more_command(100));
+
+        let message = kill_cursors_message(1, "test.batches", vec![4660], Some(&server_api))
+            .unwrap();
+        let expected = doc! {
+            "killCursors": "batches",
+            "cursors": [4660_i64],
+            "apiVersion": "1",
+        };
+        match message {
+            Message::OpQuery { query, .. } => assert_eq!(expected, query),
+            _ => panic!("Expected killCursors to be sent as a command."),
+        }
+    }
+
+    #[test]
+    fn checks_command_sizes() {
+        let limits = WriteLimits {
+            max_bson_object_size: 100,
+            max_message_size_bytes: 1 << 20,
+            max_write_batch_size: 1000,
+        };
+        let message = |size: usize| {
+            let command = doc! { "insert": "coll", "blob": "x".repeat(size) };
+            let namespace = String::from("db.$cmd");
+            Message::new_query(1, OpQueryFlags::empty(), namespace, 0, -1, command, None).unwrap()
+        };
+
+        assert!(check_command_size(limits, "insert_one", &message(16 * 1024)).is_ok());
+        match check_command_size(limits, "insert_one", &message(17 * 1024)) {
+            Err(Error::ArgumentError(reason)) => assert_eq!(
+                "The insert_one command is 17441 bytes, more than the maximum of 16484 bytes.",
+                reason
+            ),
+            other => panic!("Expected an argument error, got {:?}", other),
+        }
+
+        let limits = WriteLimits { max_message_size_bytes: 1000, ..limits };
+        assert!(check_command_size(limits, "insert_one", &message(1000)).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Background prefetching of cursor batches.
+//!
+//! A prefetching cursor requests each batch on a background thread as soon as the previous
+//! one is handed over, so the `getMore` round trip overlaps with processing the current
+//! batch instead of following it. At most one batch is fetched ahead.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("events");
+//! let cursor = coll.find(None, None).unwrap().prefetch();
+//!
+//! for result in cursor {
+//!     let doc = result.unwrap();
+//!     // The next batch is already being fetched.
+//! }
+//! # }
+//! ```
+use bson;
+
+use super::Cursor;
+use Result;
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Iterates over cursor results, fetching the next batch on a background thread.
+///
+/// Dropping the iterator stops the thread once its pending request completes, after which
+/// the server-side cursor is killed.
+#[derive(Debug)]
+pub struct PrefetchCursor {
+    batch: VecDeque<bson::Document>,
+    batches: Receiver<Result<Vec<bson::Document>>>,
+}
+
+impl PrefetchCursor {
+    /// Starts prefetching the batches of `cursor`.
+    pub fn new(cursor: Cursor) -> PrefetchCursor {
+        // The channel holds no batches, so the thread only requests the next batch once the
+        // previous one has been taken.
+        let (sender, receiver) = mpsc::sync_channel(0);
+
+        thread::spawn(move || {
+            let mut cursor = cursor;
+
+            for batch in cursor.batches() {
+                let failed = batch.is_err();
+                if sender.send(batch).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        PrefetchCursor {
+            batch: VecDeque::new(),
+            batches: receiver,
+        }
+    }
+
+    /// Returns how many documents of the current batch have not yet been returned.
+    pub fn buffered_count(&self) -> usize {
+        self.batch.len()
+    }
+}
+
+impl Iterator for PrefetchCursor {
+    type Item = Result<bson::Document>;
+
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        loop {
+            if let Some(doc) = self.batch.pop_front() {
+                return Some(Ok(doc));
+            }
+
+            match self.batches.recv() {
+                Ok(Ok(batch)) => self.batch = VecDeque::from(batch),
+                Ok(Err(err)) => return Some(Err(err)),
+                // The thread finished, exhausting the cursor.
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl Cursor {
+    /// Fetches the remaining batches in the background while the current one is consumed.
+    pub fn prefetch(self) -> PrefetchCursor {
+        PrefetchCursor::new(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use common::{ReadMode, ReadPreference};
+    use {Client, CommandType, ThreadedClient};
+
+    #[test]
+    fn returns_every_document_in_order() {
+        let docs: Vec<_> = (0..50).map(|i| doc! { "_id": i }).collect();
+
+        let cursor = Cursor::with_documents(
+            Client::connect("localhost", 27017).unwrap(),
+            String::from("test.prefetch"),
+            docs.iter().cloned().collect(),
+            CommandType::Find,
+            ReadPreference::new(ReadMode::Primary, None),
+        );
+
+        let mut prefetched = cursor.prefetch();
+        assert_eq!(docs[0], prefetched.next().unwrap().unwrap());
+        assert_eq!(49, prefetched.buffered_count());
+
+        let rest: Vec<_> = prefetched.by_ref().map(|result| result.unwrap()).collect();
+        assert_eq!(&docs[1..], &rest[..]);
+        assert!(prefetched.next().is_none());
+    }
+}
@@ -0,0 +1,711 @@
+//! Cursor results left as encoded BSON.
+//!
+//! A raw cursor hands out each result as the bytes the server sent, skipping the cost of
+//! building a `Document` for every result. Fields can still be read one at a time with
+//! `RawDocument::get`, which only decodes the requested value, or viewed in place with
+//! `RawDocument::get_path`, which follows a dotted path through nested documents and arrays
+//! and borrows strings, binaries and subdocuments from the encoded bytes.
+//!
+//! The first batch of a cursor has already been decoded by the time it is converted, so it
+//! is re-encoded once; documents of later batches that were not returned yet are handed
+//! over as they were received, and every batch after that is read straight off the wire.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use std::io::Write;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("events");
+//! # let mut out = std::io::sink();
+//! let cursor = coll.find(None, None).unwrap().into_raw().unwrap();
+//!
+//! for result in cursor {
+//!     let doc = result.unwrap();
+//!     if let Some(city) = doc.get_path("address.city").unwrap().and_then(|city| city.as_str()) {
+//!         println!("{}", city);
+//!     }
+//!     out.write_all(doc.as_bytes()).unwrap();
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::{LocalResult, TimeZone, Utc};
+
+use super::Cursor;
+use Error::{ArgumentError, CursorNotFoundError, MaxTimeMSExpired, OperationError, ResponseError};
+use {ErrorCode, Result};
+use wire_protocol::flags::OpReplyFlags;
+use wire_protocol::operations::Message;
+
+use std::collections::VecDeque;
+use std::str;
+
+// BSON element type tags.
+const ELEMENT_DOUBLE: u8 = 0x01;
+const ELEMENT_STRING: u8 = 0x02;
+const ELEMENT_DOCUMENT: u8 = 0x03;
+const ELEMENT_ARRAY: u8 = 0x04;
+const ELEMENT_BINARY: u8 = 0x05;
+const ELEMENT_UNDEFINED: u8 = 0x06;
+const ELEMENT_OBJECT_ID: u8 = 0x07;
+const ELEMENT_BOOLEAN: u8 = 0x08;
+const ELEMENT_DATETIME: u8 = 0x09;
+const ELEMENT_NULL: u8 = 0x0A;
+const ELEMENT_REGEX: u8 = 0x0B;
+const ELEMENT_DB_POINTER: u8 = 0x0C;
+const ELEMENT_JAVASCRIPT: u8 = 0x0D;
+const ELEMENT_SYMBOL: u8 = 0x0E;
+const ELEMENT_JAVASCRIPT_WITH_SCOPE: u8 = 0x0F;
+const ELEMENT_I32: u8 = 0x10;
+const ELEMENT_TIMESTAMP: u8 = 0x11;
+const ELEMENT_I64: u8 = 0x12;
+const ELEMENT_DECIMAL128: u8 = 0x13;
+const ELEMENT_MAX_KEY: u8 = 0x7F;
+const ELEMENT_MIN_KEY: u8 = 0xFF;
+
+/// An encoded BSON document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawDocument {
+    bytes: Vec<u8>,
+}
+
+impl RawDocument {
+    /// Wraps encoded BSON, checking that its length prefix and terminator are consistent.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<RawDocument> {
+        if bytes.len() < 5 || read_i32(&bytes, 0)? as usize != bytes.len() ||
+            bytes[bytes.len() - 1] != 0
+        {
+            return Err(malformed("document length does not match its contents"));
+        }
+
+        Ok(RawDocument { bytes: bytes })
+    }
+
+    /// Encodes a document.
+    pub fn from_document(doc: &bson::Document) -> Result<RawDocument> {
+        let mut bytes = Vec::new();
+        bson::encode_document(&mut bytes, doc)?;
+        Ok(RawDocument { bytes: bytes })
+    }
+
+    /// Returns the encoded document.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the encoded document, consuming the raw document.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Borrows the document as a view over its bytes.
+    pub fn as_document_ref(&self) -> RawDocumentRef<'_> {
+        RawDocumentRef { bytes: &self.bytes }
+    }
+
+    /// Iterates over the top-level elements without decoding their values.
+    pub fn iter(&self) -> RawElements<'_> {
+        self.as_document_ref().iter()
+    }
+
+    /// Decodes the value of the first top-level field named `key`, if there is one.
+    pub fn get(&self, key: &str) -> Result<Option<Bson>> {
+        for element in self.iter() {
+            let element = element?;
+            if element.key == key {
+                return element.to_bson().map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Views the value of the first top-level field named `key` in place, if there is one.
+    pub fn get_raw(&self, key: &str) -> Result<Option<RawBson<'_>>> {
+        self.as_document_ref().get(key)
+    }
+
+    /// Views the value at a dotted `path` such as `address.city` or `items.0.sku`, where
+    /// numeric segments index into arrays.
+    pub fn get_path(&self, path: &str) -> Result<Option<RawBson<'_>>> {
+        self.as_document_ref().get_path(path)
+    }
+
+    /// Decodes the whole document.
+    pub fn to_document(&self) -> Result<bson::Document> {
+        Ok(bson::decode_document(&mut &self.bytes[..])?)
+    }
+}
+
+/// A view over an encoded document or array, such as a nested value of a `RawDocument`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawDocumentRef<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> RawDocumentRef<'a> {
+    /// Views encoded BSON, checking that its length prefix and terminator are consistent.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<RawDocumentRef<'a>> {
+        if bytes.len() < 5 || read_i32(bytes, 0)? as usize != bytes.len() ||
+            bytes[bytes.len() - 1] != 0
+        {
+            return Err(malformed("document length does not match its contents"));
+        }
+
+        Ok(RawDocumentRef { bytes: bytes })
+    }
+
+    /// Returns the encoded document.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Iterates over the top-level elements without decoding their values.
+    pub fn iter(&self) -> RawElements<'a> {
+        RawElements {
+            bytes: self.bytes,
+            position: 4,
+        }
+    }
+
+    /// Views the value of the first top-level field named `key`, if there is one.
+    pub fn get(&self, key: &str) -> Result<Option<RawBson<'a>>> {
+        for element in self.iter() {
+            let element = element?;
+            if element.key == key {
+                return element.to_raw_bson().map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Views the value at a dotted `path`, where numeric segments index into arrays.
+    ///
+    /// Returns `None` if a segment is missing or a segment other than the last is neither a
+    /// document nor an array.
+    pub fn get_path(&self, path: &str) -> Result<Option<RawBson<'a>>> {
+        let mut segments = path.split('.');
+        let mut value = match segments.next() {
+            Some(key) => self.get(key)?,
+            None => None,
+        };
+
+        for key in segments {
+            value = match value {
+                Some(RawBson::Document(doc)) | Some(RawBson::Array(doc)) => doc.get(key)?,
+                _ => return Ok(None),
+            };
+        }
+
+        Ok(value)
+    }
+
+    /// Copies the document into an owned `RawDocument`.
+    pub fn to_raw_document(&self) -> RawDocument {
+        RawDocument { bytes: self.bytes.to_vec() }
+    }
+
+    /// Decodes the whole document.
+    pub fn to_document(&self) -> Result<bson::Document> {
+        Ok(bson::decode_document(&mut &self.bytes[..])?)
+    }
+}
+
+/// A value viewed in place within an encoded document.
+///
+/// Values without a variant of their own, such as regular expressions and JavaScript code,
+/// are left as `Other` and can still be decoded with `to_bson`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RawBson<'a> {
+    FloatingPoint(f64),
+    String(&'a str),
+    Document(RawDocumentRef<'a>),
+    Array(RawDocumentRef<'a>),
+    Binary(BinarySubtype, &'a [u8]),
+    ObjectId(&'a [u8]),
+    Boolean(bool),
+    /// Milliseconds since the Unix epoch.
+    UtcDatetime(i64),
+    Null,
+    I32(i32),
+    TimeStamp(i64),
+    I64(i64),
+    Other(RawElement<'a>),
+}
+
+impl<'a> RawBson<'a> {
+    /// Returns the string, if the value is one.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            RawBson::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value, if it is a 32-bit integer.
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            RawBson::I32(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value, if it is a 64-bit integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            RawBson::I64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value, if it is a double.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            RawBson::FloatingPoint(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value, if it is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            RawBson::Boolean(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the subdocument, if the value is one.
+    pub fn as_document(&self) -> Option<RawDocumentRef<'a>> {
+        match *self {
+            RawBson::Document(doc) => Some(doc),
+            _ => None,
+        }
+    }
+
+    /// Returns the array, whose keys are the indexes of its elements, if the value is one.
+    pub fn as_array(&self) -> Option<RawDocumentRef<'a>> {
+        match *self {
+            RawBson::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Decodes the value.
+    pub fn to_bson(&self) -> Result<Bson> {
+        let value = match *self {
+            RawBson::FloatingPoint(value) => Bson::FloatingPoint(value),
+            RawBson::String(s) => Bson::String(String::from(s)),
+            RawBson::Document(doc) => Bson::Document(doc.to_document()?),
+            RawBson::Array(array) => {
+                let values = array.iter()
+                    .map(|element| element?.to_bson())
+                    .collect::<Result<_>>()?;
+                Bson::Array(values)
+            }
+            RawBson::Binary(subtype, bytes) => Bson::Binary(subtype, bytes.to_vec()),
+            RawBson::ObjectId(bytes) => {
+                let mut id = [0; 12];
+                id.copy_from_slice(bytes);
+                Bson::ObjectId(ObjectId::with_bytes(id))
+            }
+            RawBson::Boolean(value) => Bson::Boolean(value),
+            RawBson::UtcDatetime(millis) => match Utc.timestamp_millis_opt(millis) {
+                LocalResult::Single(datetime) => Bson::UtcDatetime(datetime),
+                _ => return Err(malformed("datetime is out of range")),
+            },
+            RawBson::Null => Bson::Null,
+            RawBson::I32(value) => Bson::I32(value),
+            RawBson::TimeStamp(value) => Bson::TimeStamp(value),
+            RawBson::I64(value) => Bson::I64(value),
+            RawBson::Other(element) => return element.to_bson(),
+        };
+
+        Ok(value)
+    }
+}
+
+/// A top-level element of a raw document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawElement<'a> {
+    /// The BSON type tag of the value.
+    pub element_type: u8,
+    pub key: &'a str,
+    /// The encoded value, without type tag or key.
+    pub value: &'a [u8],
+}
+
+impl<'a> RawElement<'a> {
+    /// Decodes the value.
+    pub fn to_bson(&self) -> Result<Bson> {
+        // Wraps the element in a document of its own so the bson crate can decode it.
+        let length = 4 + 1 + 1 + self.value.len() + 1;
+        let mut doc = Vec::with_capacity(length);
+        doc.extend_from_slice(&[0; 4]);
+        LittleEndian::write_i32(&mut doc[..4], length as i32);
+        doc.push(self.element_type);
+        doc.push(0);
+        doc.extend_from_slice(self.value);
+        doc.push(0);
+
+        let mut decoded = bson::decode_document(&mut &doc[..])?;
+        decoded.remove("").ok_or_else(|| malformed("element could not be decoded"))
+    }
+
+    /// Views the value in place.
+    pub fn to_raw_bson(&self) -> Result<RawBson<'a>> {
+        let value = self.value;
+        let raw = match self.element_type {
+            ELEMENT_DOUBLE => RawBson::FloatingPoint(LittleEndian::read_f64(value)),
+            ELEMENT_STRING => {
+                // The length prefix counts the terminator, which `value_length` included.
+                if value.len() < 5 || value[value.len() - 1] != 0 {
+                    return Err(malformed("string is not terminated"));
+                }
+                let s = str::from_utf8(&value[4..value.len() - 1])
+                    .map_err(|_| malformed("string is not valid UTF-8"))?;
+                RawBson::String(s)
+            }
+            ELEMENT_DOCUMENT => RawBson::Document(RawDocumentRef::from_bytes(value)?),
+            ELEMENT_ARRAY => RawBson::Array(RawDocumentRef::from_bytes(value)?),
+            ELEMENT_BINARY => RawBson::Binary(BinarySubtype::from(value[4]), &value[5..]),
+            ELEMENT_OBJECT_ID => RawBson::ObjectId(value),
+            ELEMENT_BOOLEAN => RawBson::Boolean(value[0] != 0),
+            ELEMENT_DATETIME => RawBson::UtcDatetime(LittleEndian::read_i64(value)),
+            ELEMENT_NULL => RawBson::Null,
+            ELEMENT_I32 => RawBson::I32(LittleEndian::read_i32(value)),
+            ELEMENT_TIMESTAMP => RawBson::TimeStamp(LittleEndian::read_i64(value)),
+            ELEMENT_I64 => RawBson::I64(LittleEndian::read_i64(value)),
+            _ => RawBson::Other(*self),
+        };
+
+        Ok(raw)
+    }
+}
+
+/// Iterates over the elements of a raw document.
+#[derive(Clone, Debug)]
+pub struct RawElements<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> RawElements<'a> {
+    fn next_element(&mut self) -> Result<Option<RawElement<'a>>> {
+        let bytes = self.bytes;
+        let element_type = match bytes.get(self.position) {
+            Some(&0) | None => return Ok(None),
+            Some(&element_type) => element_type,
+        };
+
+        let key_start = self.position + 1;
+        let key_end = cstring_end(bytes, key_start)?;
+        let key = str::from_utf8(&bytes[key_start..key_end])
+            .map_err(|_| malformed("key is not valid UTF-8"))?;
+
+        let value_start = key_end + 1;
+        let value_length = value_length(bytes, element_type, value_start)?;
+        let value_end = value_start + value_length;
+
+        // The last byte of the document is its terminator.
+        if value_end >= bytes.len() {
+            return Err(malformed("element overruns the document"));
+        }
+
+        self.position = value_end;
+
+        Ok(Some(RawElement {
+            element_type: element_type,
+            key: key,
+            value: &bytes[value_start..value_end],
+        }))
+    }
+}
+
+impl<'a> Iterator for RawElements<'a> {
+    type Item = Result<RawElement<'a>>;
+
+    fn next(&mut self) -> Option<Result<RawElement<'a>>> {
+        match self.next_element() {
+            Ok(Some(element)) => Some(Ok(element)),
+            Ok(None) => None,
+            Err(err) => {
+                // Stop after reporting a malformed element.
+                self.position = self.bytes.len();
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+// Measures the encoded value of an element starting at `start`.
+fn value_length(bytes: &[u8], element_type: u8, start: usize) -> Result<usize> {
+    let length = match element_type {
+        ELEMENT_UNDEFINED | ELEMENT_NULL | ELEMENT_MAX_KEY | ELEMENT_MIN_KEY => 0,
+        ELEMENT_BOOLEAN => 1,
+        ELEMENT_I32 => 4,
+        ELEMENT_DOUBLE | ELEMENT_DATETIME | ELEMENT_TIMESTAMP | ELEMENT_I64 => 8,
+        ELEMENT_OBJECT_ID => 12,
+        ELEMENT_DECIMAL128 => 16,
+        ELEMENT_STRING | ELEMENT_JAVASCRIPT | ELEMENT_SYMBOL => 4 + length_prefix(bytes, start)?,
+        ELEMENT_DB_POINTER => 4 + length_prefix(bytes, start)? + 12,
+        ELEMENT_BINARY => 4 + 1 + length_prefix(bytes, start)?,
+        ELEMENT_DOCUMENT | ELEMENT_ARRAY | ELEMENT_JAVASCRIPT_WITH_SCOPE => {
+            length_prefix(bytes, start)?
+        }
+        ELEMENT_REGEX => {
+            let pattern_end = cstring_end(bytes, start)?;
+            cstring_end(bytes, pattern_end + 1)? + 1 - start
+        }
+        other => return Err(malformed(&format!("unknown element type {:#04x}", other))),
+    };
+
+    Ok(length)
+}
+
+fn length_prefix(bytes: &[u8], start: usize) -> Result<usize> {
+    match read_i32(bytes, start)? {
+        length if length >= 0 => Ok(length as usize),
+        _ => Err(malformed("negative length prefix")),
+    }
+}
+
+fn read_i32(bytes: &[u8], start: usize) -> Result<i32> {
+    match bytes.get(start..start + 4) {
+        Some(prefix) => Ok(LittleEndian::read_i32(prefix)),
+        None => Err(malformed("truncated length prefix")),
+    }
+}
+
+// Returns the index of the terminator of the cstring starting at `start`.
+fn cstring_end(bytes: &[u8], start: usize) -> Result<usize> {
+    bytes.get(start..)
+        .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+        .map(|offset| start + offset)
+        .ok_or_else(|| malformed("unterminated cstring"))
+}
+
+fn malformed(reason: &str) -> ::Error {
+    ResponseError(format!("Malformed BSON document: {}", reason))
+}
+
+/// Lazily returns encoded documents from a query.
+#[derive(Debug)]
+pub struct RawCursor {
+    cursor: Cursor,
+    buffer: VecDeque<RawDocument>,
+}
+
+impl RawCursor {
+    /// Checks whether there are any more documents for the cursor to return.
+    pub fn has_next(&mut self) -> Result<bool> {
+        if self.cursor.limit > 0 && self.cursor.count >= self.cursor.limit {
+            return Ok(false);
+        }
+
+        if self.buffer.is_empty() && self.cursor.limit != 1 && self.cursor.cursor_id != 0 {
+            self.cursor.reserve_room();
+
+            let buffer = &mut self.buffer;
+            let fetched = self.cursor.get_more(|cursor, socket| {
+                let reply = Message::read_raw(socket)?;
+
+                if reply.flags.contains(OpReplyFlags::CURSOR_NOT_FOUND) {
+                    return Err(CursorNotFoundError);
+                }
+
+                if reply.flags.contains(OpReplyFlags::QUERY_FAILURE) {
+                    let (message, code) = match reply.documents.into_iter().next() {
+                        Some(bytes) => {
+                            let doc = RawDocument::from_bytes(bytes)?;
+                            let message = match doc.get("$err")? {
+                                Some(Bson::String(message)) => message,
+                                _ => String::from("getMore failed"),
+                            };
+                            (message, doc.get("code")?)
+                        }
+                        None => (String::from("getMore failed"), None),
+                    };
+
+                    if code == Some(Bson::I32(ErrorCode::ExceededTimeLimit as i32)) {
+                        return Err(MaxTimeMSExpired(message));
+                    }
+                    return Err(OperationError(message));
+                }
+
+                cursor.cursor_id = reply.cursor_id;
+                for bytes in reply.documents {
+                    cursor.reserve_buffered(bytes.len());
+                    buffer.push_back(RawDocument::from_bytes(bytes)?);
+                }
+
+                Ok(())
+            });
+            self.cursor.release_reserved();
+            fetched?;
+        }
+
+        Ok(!self.buffer.is_empty())
+    }
+
+    /// Attempts to read a batch of encoded documents from the cursor.
+    pub fn drain_current_batch(&mut self) -> Result<Vec<RawDocument>> {
+        if !self.has_next()? {
+            return Ok(Vec::new());
+        }
+
+        self.cursor.count += self.buffer.len() as i32;
+        let batch = self.buffer.drain(..).collect();
+        self.cursor.release_buffered();
+        Ok(batch)
+    }
+
+    /// Returns the size in bytes of the documents this cursor has buffered but not yet
+    /// returned.
+    pub fn buffered_bytes(&self) -> usize {
+        self.cursor.buffered_bytes
+    }
+}
+
+impl Iterator for RawCursor {
+    type Item = Result<RawDocument>;
+
+    fn next(&mut self) -> Option<Result<RawDocument>> {
+        match self.has_next() {
+            Ok(true) => {
+                self.cursor.count += 1;
+                let doc = self.buffer.pop_front();
+                if self.buffer.is_empty() {
+                    self.cursor.release_buffered();
+                }
+                doc.map(Ok)
+            }
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl Cursor {
+    /// Converts the cursor into one returning documents as encoded BSON.
+    ///
+    /// The buffered batch keeps counting against the client's memory limit, as encoding
+    /// preserves its size.
+    pub fn into_raw(mut self) -> Result<RawCursor> {
+        if self.max_await_time_ms.is_some() {
+            return Err(ArgumentError(String::from(
+                "Cursors with an await time cannot return encoded documents.",
+            )));
+        }
+
+        let (decoded, encoded) = self.buffer.drain_encoded();
+        let mut buffer = decoded
+            .into_iter()
+            .map(|doc| RawDocument::from_document(&doc?))
+            .collect::<Result<VecDeque<_>>>()?;
+        for bytes in encoded {
+            buffer.push_back(RawDocument::from_bytes(bytes)?);
+        }
+
+        Ok(RawCursor {
+            cursor: self,
+            buffer: buffer,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use bson::oid::ObjectId;
+    use bson::spec::BinarySubtype;
+
+    #[test]
+    fn reads_fields_lazily() {
+        let id = ObjectId::with_bytes([7; 12]);
+        let doc = doc! {
+            "_id": id.clone(),
+            "name": "blob",
+            "size": 3_i64,
+            "tags": ["a", "b"],
+            "pattern": Bson::RegExp(String::from("^a"), String::from("i")),
+            "data": Bson::Binary(BinarySubtype::Generic, vec![1, 2, 3]),
+            "nested": { "ok": true },
+            "missing": Bson::Null,
+            "ratio": 0.5,
+        };
+
+        let raw = RawDocument::from_document(&doc).unwrap();
+        let keys: Vec<_> = raw.iter().map(|element| element.unwrap().key).collect();
+        assert_eq!(
+            vec!["_id", "name", "size", "tags", "pattern", "data", "nested", "missing", "ratio"],
+            keys
+        );
+
+        assert_eq!(Some(Bson::ObjectId(id)), raw.get("_id").unwrap());
+        assert_eq!(Some(Bson::I64(3)), raw.get("size").unwrap());
+        assert_eq!(Some(Bson::FloatingPoint(0.5)), raw.get("ratio").unwrap());
+        assert_eq!(Some(Bson::Document(doc! { "ok": true })), raw.get("nested").unwrap());
+        assert_eq!(None, raw.get("absent").unwrap());
+        assert_eq!(doc, raw.to_document().unwrap());
+    }
+
+    #[test]
+    fn views_values_by_path() {
+        let doc = doc! {
+            "name": "blob",
+            "address": { "city": "Lyon", "zip": 69001 },
+            "items": [{ "sku": "x1", "qty": 2_i64 }, { "sku": "x2", "in_stock": false }],
+            "data": Bson::Binary(BinarySubtype::Generic, vec![1, 2, 3]),
+            "pattern": Bson::RegExp(String::from("^a"), String::from("i")),
+        };
+        let raw = RawDocument::from_document(&doc).unwrap();
+
+        assert_eq!(Some("blob"), raw.get_raw("name").unwrap().and_then(|v| v.as_str()));
+        assert_eq!(Some("Lyon"), raw.get_path("address.city").unwrap().and_then(|v| v.as_str()));
+        assert_eq!(Some(69001), raw.get_path("address.zip").unwrap().and_then(|v| v.as_i32()));
+        assert_eq!(Some(2), raw.get_path("items.0.qty").unwrap().and_then(|v| v.as_i64()));
+        assert_eq!(
+            Some(false),
+            raw.get_path("items.1.in_stock").unwrap().and_then(|v| v.as_bool())
+        );
+        assert_eq!(
+            Some(RawBson::Binary(BinarySubtype::Generic, &[1, 2, 3][..])),
+            raw.get_path("data").unwrap()
+        );
+        assert_eq!(None, raw.get_path("items.2.sku").unwrap());
+        assert_eq!(None, raw.get_path("name.first").unwrap());
+
+        let array = raw.get_path("items").unwrap().and_then(|v| v.as_array()).unwrap();
+        assert_eq!(2, array.iter().count());
+        let items = raw.get_raw("items").unwrap().unwrap();
+        assert_eq!(doc.get("items").cloned(), Some(items.to_bson().unwrap()));
+        let pattern = raw.get_raw("pattern").unwrap().unwrap();
+        assert_eq!(doc.get("pattern").cloned(), Some(pattern.to_bson().unwrap()));
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let mut bytes = RawDocument::from_document(&doc! { "name": "blob" })
+            .unwrap()
+            .into_bytes();
+
+        assert!(RawDocument::from_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+
+        // Claim the string runs past the end of the document.
+        bytes[4 + 1 + 5] = 0x7F;
+        let raw = RawDocument::from_bytes(bytes).unwrap();
+        match raw.get("name") {
+            Err(ResponseError(_)) => (),
+            other => panic!("expected a response error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Disk-backed buffering of cursor results.
+//!
+//! A spooled cursor drains its server cursor on a background thread into a temporary file,
+//! so the server-side cursor is read as fast as the server produces batches and never times
+//! out, however slowly the results are consumed. Only the document being returned is held
+//! in memory.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("events");
+//! let cursor = coll.aggregate(vec![], None).unwrap().spool_to_disk().unwrap();
+//!
+//! for result in cursor {
+//!     let doc = result.unwrap();
+//!     // Slow processing no longer holds up the server cursor.
+//! }
+//! # }
+//! ```
+use bson;
+
+use super::Cursor;
+use {Error, Result};
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Default)]
+struct SpoolState {
+    // The number of documents flushed to the file.
+    written: u64,
+    // Whether the server cursor is exhausted or failed.
+    done: bool,
+    // The error that stopped the server cursor, until it is returned.
+    error: Option<Error>,
+    // Whether the consumer went away.
+    stopped: bool,
+}
+
+type SharedState = Arc<(Mutex<SpoolState>, Condvar)>;
+
+/// Iterates over cursor results spooled to a temporary file.
+///
+/// The file is deleted when the iterator is dropped.
+#[derive(Debug)]
+pub struct SpooledCursor {
+    path: PathBuf,
+    reader: BufReader<File>,
+    read: u64,
+    state: SharedState,
+}
+
+impl SpooledCursor {
+    /// Starts spooling `cursor` into a new file within `dir`.
+    pub fn new(cursor: Cursor, dir: &Path) -> Result<SpooledCursor> {
+        let path = dir.join(format!(
+            "mongodb-spool-{}-{}",
+            process::id(),
+            SPOOL_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let reader = BufReader::new(File::open(&path)?);
+        let state: SharedState = Arc::new((Mutex::new(SpoolState::default()), Condvar::new()));
+
+        let writer_state = state.clone();
+        let writer_path = path.clone();
+        thread::spawn(move || {
+            spool(cursor, BufWriter::new(file), &writer_state);
+
+            // The consumer may have been unable to delete a file that was still open.
+            if let Ok(locked) = writer_state.0.lock() {
+                if locked.stopped {
+                    let _ = fs::remove_file(&writer_path);
+                }
+            }
+        });
+
+        Ok(SpooledCursor {
+            path: path,
+            reader: reader,
+            read: 0,
+            state: state,
+        })
+    }
+
+    /// Returns the path of the spool file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of documents spooled but not yet returned.
+    pub fn backlog(&self) -> Result<u64> {
+        Ok(self.state.0.lock()?.written - self.read)
+    }
+}
+
+// Copies the cursor into the file, flushing and publishing progress after every batch.
+fn spool(mut cursor: Cursor, mut file: BufWriter<File>, state: &SharedState) {
+    let mut pending = 0;
+
+    let error = loop {
+        let doc = match cursor.next() {
+            Some(Ok(doc)) => doc,
+            Some(Err(err)) => break Some(err),
+            None => break None,
+        };
+
+        if let Err(err) = bson::encode_document(&mut file, &doc) {
+            break Some(Error::from(err));
+        }
+
+        pending += 1;
+
+        if cursor.buffer.is_empty() {
+            if let Err(err) = file.flush() {
+                break Some(Error::from(err));
+            }
+
+            match publish(state, pending, false, None) {
+                Ok(true) => pending = 0,
+                _ => return,
+            }
+        }
+    };
+
+    let error = match file.flush() {
+        Ok(()) => error,
+        Err(err) => error.or_else(|| Some(Error::from(err))),
+    };
+
+    let _ = publish(state, pending, true, error);
+}
+
+// Records flushed documents and wakes the consumer. Returns whether spooling should go on.
+fn publish(state: &SharedState, written: u64, done: bool, error: Option<Error>) -> Result<bool> {
+    let mut locked = state.0.lock()?;
+    locked.written += written;
+    locked.done = done;
+    locked.error = error;
+    state.1.notify_all();
+    Ok(!locked.stopped)
+}
+
+impl Iterator for SpooledCursor {
+    type Item = Result<bson::Document>;
+
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        {
+            let mut locked = match self.state.0.lock() {
+                Ok(locked) => locked,
+                Err(err) => return Some(Err(Error::from(err))),
+            };
+
+            while locked.written == self.read && !locked.done {
+                locked = match self.state.1.wait(locked) {
+                    Ok(locked) => locked,
+                    Err(err) => return Some(Err(Error::from(err))),
+                };
+            }
+
+            if locked.written == self.read {
+                return locked.error.take().map(Err);
+            }
+        }
+
+        self.read += 1;
+        Some(bson::decode_document(&mut self.reader).map_err(Error::from))
+    }
+}
+
+impl Drop for SpooledCursor {
+    fn drop(&mut self) {
+        if let Ok(mut locked) = self.state.0.lock() {
+            locked.stopped = true;
+        }
+
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Cursor {
+    /// Spools the remaining results into a temporary file in the system temporary directory.
+    pub fn spool_to_disk(self) -> Result<SpooledCursor> {
+        SpooledCursor::new(self, &env::temp_dir())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use common::{ReadMode, ReadPreference};
+    use std::collections::VecDeque;
+    use {Client, CommandType, ThreadedClient};
+
+    // A cursor whose results are all buffered, so it never contacts a server.
+    fn buffered_cursor(docs: Vec<bson::Document>) -> Cursor {
+        Cursor::with_documents(
+            Client::connect("localhost", 27017).unwrap(),
+            String::from("test.spool"),
+            docs.into_iter().collect::<VecDeque<_>>(),
+            CommandType::Aggregate,
+            ReadPreference::new(ReadMode::Primary, None),
+        )
+    }
+
+    #[test]
+    fn replays_documents_and_removes_file() {
+        let docs: Vec<_> = (0..100).map(|i| doc! { "_id": i, "pad": "x".repeat(i as usize) })
+            .collect();
+
+        let mut spooled = buffered_cursor(docs.clone()).spool_to_disk().unwrap();
+        let path = spooled.path().to_path_buf();
+
+        let first = spooled.next().unwrap().unwrap();
+        assert_eq!(docs[0], first);
+
+        let rest: Vec<_> = spooled.by_ref().map(|result| result.unwrap()).collect();
+        assert_eq!(&docs[1..], &rest[..]);
+        assert!(spooled.next().is_none());
+
+        drop(spooled);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,107 @@
+//! Serde helpers storing `chrono::DateTime<Utc>` fields as BSON UTC datetimes.
+//!
+//! Without them, typed documents have to hold `bson::UtcDateTime` or milliseconds since the
+//! epoch. Annotate `DateTime<Utc>` fields with `#[serde(with = "mongodb::datetime")]`, or
+//! `Option<DateTime<Utc>>` fields with `#[serde(with = "mongodb::datetime::optional")]`, and
+//! they are read from and written as BSON datetimes, which keep millisecond precision.
+//!
+//! Date ranges can be queried with `Filter::field(..).between(start, end)` or
+//! `Filter::field(..).on_day(date)`.
+//!
+//! ```no_run
+//! # extern crate chrono;
+//! # extern crate mongodb;
+//! # #[macro_use] extern crate serde_derive;
+//! # use chrono::{DateTime, NaiveDate, Utc};
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::filter::Filter;
+//! # use mongodb::db::ThreadedDatabase;
+//! #
+//! #[derive(Serialize, Deserialize)]
+//! struct Order {
+//!     #[serde(with = "mongodb::datetime")]
+//!     placed_at: DateTime<Utc>,
+//!     #[serde(with = "mongodb::datetime::optional")]
+//!     shipped_at: Option<DateTime<Utc>>,
+//! }
+//!
+//! # fn main() {
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! let orders = client.db("shop").typed_collection::<Order>("orders");
+//!
+//! let day = NaiveDate::from_ymd_opt(2018, 3, 1).unwrap();
+//! let filter = Filter::field("placed_at").on_day(day);
+//! for order in orders.find(Some(filter.build()), None).unwrap() {
+//!     println!("placed at {}", order.unwrap().placed_at);
+//! }
+//! # }
+//! ```
+use bson::UtcDateTime;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serializes `datetime` as a BSON datetime.
+pub fn serialize<S: Serializer>(
+    datetime: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    UtcDateTime(*datetime).serialize(serializer)
+}
+
+/// Deserializes a BSON datetime.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+    UtcDateTime::deserialize(deserializer).map(|datetime| datetime.0)
+}
+
+/// The same helpers for optional fields, which are `None` when the field is null.
+pub mod optional {
+    use bson::UtcDateTime;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serializes `datetime` as a BSON datetime, or null.
+    pub fn serialize<S: Serializer>(
+        datetime: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        datetime.map(UtcDateTime).serialize(serializer)
+    }
+
+    /// Deserializes a BSON datetime or null.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::<UtcDateTime>::deserialize(deserializer).map(|datetime| datetime.map(|d| d.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bson::{self, doc, Bson};
+    use chrono::{DateTime, TimeZone, Utc};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Order {
+        #[serde(with = "super")]
+        placed_at: DateTime<Utc>,
+        #[serde(with = "super::optional")]
+        shipped_at: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn round_trips_datetimes() {
+        let placed_at = Utc.timestamp_millis_opt(1_520_000_000_123).unwrap();
+        let order = Order { placed_at: placed_at, shipped_at: None };
+
+        let document = doc! { "placed_at": placed_at, "shipped_at": Bson::Null };
+        assert_eq!(Bson::Document(document.clone()), bson::to_bson(&order).unwrap());
+        assert_eq!(order, bson::from_bson(Bson::Document(document)).unwrap());
+
+        let shipped = Order { placed_at: placed_at, shipped_at: Some(placed_at) };
+        let document = doc! { "placed_at": placed_at, "shipped_at": placed_at };
+        assert_eq!(Bson::Document(document.clone()), bson::to_bson(&shipped).unwrap());
+        assert_eq!(shipped, bson::from_bson(Bson::Document(document)).unwrap());
+    }
+}
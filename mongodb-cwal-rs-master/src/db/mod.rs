@@ -58,16 +58,28 @@
 pub mod options;
 pub mod roles;
 
+use admin::{self, CollectionInfo, DatabaseStats, ProfileEntry, ProfilingLevel, ProfilingStatus,
+            ServerStatus, ServerStatusOptions};
 use auth::Authenticator;
 use bson::{self, bson, doc, Bson};
 use {Client, CommandType, ThreadedClient, Result};
-use Error::{CursorNotFoundError, OperationError, ResponseError};
+use Error::{ArgumentError, CursorNotFoundError, MaxTimeMSExpired, OperationError,
+            ResponseError};
+use ErrorCode;
 use coll::Collection;
+use coll::error::WriteException;
+use coll::typed::{from_document, TypedCollection, TypedCursor};
 use coll::options::FindOptions;
 use common::{ReadPreference, merge_options, WriteConcern};
 use cursor::{Cursor, DEFAULT_BATCH_SIZE};
-use self::options::{CreateCollectionOptions, CreateUserOptions, UserInfoOptions};
+use gridfs::bucket::{GridFsBucket, GridFsBucketOptions};
+use self::options::{CreateCollectionOptions, CreateUserOptions, CreateViewOptions,
+                    ListCollectionsOptions, ModifyCollectionOptions, UpdateUserOptions,
+                    UserInfoOptions};
+use self::roles::UserInfo;
 use semver::Version;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
 
 /// Interfaces with a MongoDB database.
@@ -86,6 +98,37 @@ pub struct DatabaseInner {
 
 pub type Database = Arc<DatabaseInner>;
 
+impl DatabaseInner {
+    /// Opens another database on the same client with this database's read preference and
+    /// write concern, without contacting the server.
+    pub fn sibling(&self, name: &str) -> Database {
+        Database::open(
+            self.client.clone(),
+            name,
+            Some(self.read_preference.clone()),
+            Some(self.write_concern.clone()),
+        )
+    }
+
+    /// Opens this database again with a different read preference, e.g. to send analytics
+    /// reads to secondaries through a connection checked out for primary writes.
+    pub fn with_read_preference(&self, read_preference: ReadPreference) -> Database {
+        Database::open(
+            self.client.clone(),
+            &self.name,
+            Some(read_preference),
+            Some(self.write_concern.clone()),
+        )
+    }
+
+    // Drops the cached view information for a collection whose type may have changed.
+    fn forget_view(&self, coll_name: &str) -> Result<()> {
+        let namespace = format!("{}.{}", self.name, coll_name);
+        self.client.views.lock()?.remove(&namespace);
+        Ok(())
+    }
+}
+
 pub trait ThreadedDatabase {
     /// Creates a database representation with optional read and write controls.
     fn open(
@@ -96,10 +139,31 @@ pub trait ThreadedDatabase {
     ) -> Database;
     // Returns the version of the MongoDB instance.
     fn version(&self) -> Result<Version>;
+    /// Returns the storage statistics of the database, with sizes divided by `scale`.
+    fn stats(&self, scale: Option<i32>) -> Result<DatabaseStats>;
+    /// Returns the profiler settings of the database.
+    fn get_profiling_level(&self) -> Result<ProfilingStatus>;
+    /// Sets which operations the profiler records, and optionally the threshold in
+    /// milliseconds above which operations count as slow. Returns the previous settings.
+    fn set_profiling_level(&self, level: ProfilingLevel, slow_ms: Option<i32>)
+        -> Result<ProfilingStatus>;
+    /// Returns the operations recorded by the profiler that match the filter, newest first
+    /// unless the options sort them otherwise.
+    fn profile_entries(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<TypedCursor<ProfileEntry>>;
+    /// Returns the state of the server, with the default sections adjusted by `sections`.
+    fn server_status(&self, sections: Option<ServerStatusOptions>) -> Result<ServerStatus>;
     /// Logs in a user using the SCRAM-SHA-1 mechanism.
     fn auth(&self, user: &str, password: &str) -> Result<()>;
     /// Creates a collection representation with inherited read and write controls.
     fn collection(&self, coll_name: &str) -> Collection;
+    /// Creates a collection representation whose documents are (de)serialized as `T`.
+    fn typed_collection<T>(&self, coll_name: &str) -> TypedCollection<T>
+    where
+        T: Serialize + DeserializeOwned;
     /// Creates a collection representation with custom read and write controls.
     fn collection_with_prefs(
         &self,
@@ -108,6 +172,8 @@ pub trait ThreadedDatabase {
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
     ) -> Collection;
+    /// Opens a GridFS bucket within the database, named `fs` unless the options say otherwise.
+    fn gridfs_bucket(&self, options: Option<GridFsBucketOptions>) -> GridFsBucket;
     /// Return a unique operational request id.
     fn get_req_id(&self) -> i32;
     /// Generates a cursor for a relevant operational command.
@@ -118,12 +184,27 @@ pub trait ThreadedDatabase {
         read_pref: ReadPreference,
     ) -> Result<Cursor>;
     /// Sends an administrative command over find_one.
+    ///
+    /// The server is selected with the given read preference, or the database's; a mongos
+    /// receives the preference as `$readPreference`. Write commands always go to a primary.
     fn command(
         &self,
         spec: bson::Document,
         cmd_type: CommandType,
         read_preference: Option<ReadPreference>,
     ) -> Result<bson::Document>;
+    /// Runs a command and deserializes its reply into `T`, failing with the server's error
+    /// if the reply does not report `ok: 1`.
+    ///
+    /// The server is selected as for `command`, using the given read preference or the
+    /// database's.
+    fn run_command_as<T>(
+        &self,
+        spec: bson::Document,
+        read_preference: Option<ReadPreference>,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned;
     /// Returns a list of collections within the database.
     fn list_collections(&self, filter: Option<bson::Document>) -> Result<Cursor>;
     /// Returns a list of collections within the database with a custom batch size.
@@ -132,6 +213,13 @@ pub trait ThreadedDatabase {
         filter: Option<bson::Document>,
         batch_size: i32,
     ) -> Result<Cursor>;
+    /// Returns the collections and views within the database matching the filter, decoded
+    /// into typed entries.
+    fn list_collection_infos(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListCollectionsOptions>,
+    ) -> Result<Vec<CollectionInfo>>;
     /// Returns a list of collection names within the database.
     fn collection_names(&self, filter: Option<bson::Document>) -> Result<Vec<String>>;
     /// Creates a new collection.
@@ -140,6 +228,15 @@ pub trait ThreadedDatabase {
     /// method should only be used to instantiate capped collections.
     fn create_collection(&self, name: &str, options: Option<CreateCollectionOptions>)
         -> Result<()>;
+    /// Creates a read-only view named `name` of the collection or view `view_on`, whose
+    /// documents are the results of running `pipeline` on it.
+    fn create_view(
+        &self,
+        name: &str,
+        view_on: &str,
+        pipeline: Vec<bson::Document>,
+        options: Option<CreateViewOptions>,
+    ) -> Result<()>;
     /// Creates a new user.
     fn create_user(
         &self,
@@ -147,12 +244,26 @@ pub trait ThreadedDatabase {
         password: &str,
         options: Option<CreateUserOptions>,
     ) -> Result<()>;
+    /// Changes the validator, an index or the capped limits of the collection `name` with
+    /// `collMod`.
+    fn modify_collection(&self, name: &str, changes: ModifyCollectionOptions) -> Result<()>;
     /// Permanently deletes all users from the database.
     fn drop_all_users(&self, write_concern: Option<WriteConcern>) -> Result<i32>;
     /// Permanently deletes the collection from the database.
     fn drop_collection(&self, name: &str) -> Result<()>;
+    /// Permanently deletes the collection from the database, acknowledged with the given
+    /// write concern instead of the database's.
+    fn drop_collection_with_write_concern(
+        &self,
+        name: &str,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<()>;
     /// Permanently deletes the database from the server.
     fn drop_database(&self) -> Result<()>;
+    /// Permanently deletes the database from the server, acknowledged with the given write
+    /// concern instead of the database's.
+    fn drop_database_with_write_concern(&self, write_concern: Option<WriteConcern>)
+        -> Result<()>;
     /// Permanently deletes the user from the database.
     fn drop_user(&self, name: &str, Option<WriteConcern>) -> Result<()>;
     /// Retrieves information about all users in the database.
@@ -165,6 +276,12 @@ pub trait ThreadedDatabase {
         users: Vec<&str>,
         options: Option<UserInfoOptions>,
     ) -> Result<Vec<bson::Document>>;
+    /// Retrieves typed information about the given users, or about every user of the database
+    /// if `users` is empty.
+    fn users_info(&self, users: Vec<&str>, options: Option<UserInfoOptions>)
+        -> Result<Vec<UserInfo>>;
+    /// Changes the password, roles, custom data or authentication restrictions of a user.
+    fn update_user(&self, name: &str, options: UpdateUserOptions) -> Result<()>;
 }
 
 impl ThreadedDatabase for Database {
@@ -174,7 +291,7 @@ impl ThreadedDatabase for Database {
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
     ) -> Database {
-        let rp = read_preference.unwrap_or_else(|| client.read_preference.to_owned());
+        let rp = read_preference.unwrap_or_else(|| client.current_read_preference());
         let wc = write_concern.unwrap_or_else(|| client.write_concern.to_owned());
 
         Arc::new(DatabaseInner {
@@ -200,6 +317,13 @@ impl ThreadedDatabase for Database {
         )
     }
 
+    fn typed_collection<T>(&self, coll_name: &str) -> TypedCollection<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        TypedCollection::new(self.collection(coll_name))
+    }
+
     fn collection_with_prefs(
         &self,
         coll_name: &str,
@@ -216,6 +340,10 @@ impl ThreadedDatabase for Database {
         )
     }
 
+    fn gridfs_bucket(&self, options: Option<GridFsBucketOptions>) -> GridFsBucket {
+        GridFsBucket::new(self.clone(), options)
+    }
+
     fn get_req_id(&self) -> i32 {
         self.client.get_req_id()
     }
@@ -258,6 +386,18 @@ impl ThreadedDatabase for Database {
         })
     }
 
+    fn run_command_as<T>(
+        &self,
+        spec: bson::Document,
+        read_preference: Option<ReadPreference>,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let reply = self.command(spec, CommandType::RunCommand, read_preference)?;
+        from_document(check_command_reply(reply)?)
+    }
+
     fn list_collections(&self, filter: Option<bson::Document>) -> Result<Cursor> {
         self.list_collections_with_batch_size(filter, DEFAULT_BATCH_SIZE)
     }
@@ -285,6 +425,32 @@ impl ThreadedDatabase for Database {
         )
     }
 
+    fn list_collection_infos(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListCollectionsOptions>,
+    ) -> Result<Vec<CollectionInfo>> {
+        let options = options.unwrap_or_default();
+
+        let mut spec = doc! {
+            "listCollections": 1,
+            "cursor": {
+                "batchSize": options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            },
+        };
+        if let Some(f) = filter {
+            spec.insert("filter", f);
+        }
+
+        self.command_cursor(
+            merge_options(spec, options),
+            CommandType::ListCollections,
+            self.read_preference.to_owned(),
+        )?
+            .map(|doc| doc.and_then(admin::decode_reply))
+            .collect()
+    }
+
     fn collection_names(&self, filter: Option<bson::Document>) -> Result<Vec<String>> {
         self.list_collections(filter)?
             .filter_map(|result| match result {
@@ -297,6 +463,60 @@ impl ThreadedDatabase for Database {
             .collect()
     }
 
+    fn stats(&self, scale: Option<i32>) -> Result<DatabaseStats> {
+        let mut doc = doc! { "dbStats": 1 };
+        if let Some(scale) = scale {
+            doc.insert("scale", admin::check_scale(scale)?);
+        }
+
+        let mut res = self.command(doc, CommandType::DbStats, None)?;
+        res.remove("ok");
+        admin::decode_reply(res)
+    }
+
+    fn get_profiling_level(&self) -> Result<ProfilingStatus> {
+        let res = self.command(doc! { "profile": -1 }, CommandType::Profile, None)?;
+        ProfilingStatus::from_reply(&res)
+    }
+
+    fn set_profiling_level(
+        &self,
+        level: ProfilingLevel,
+        slow_ms: Option<i32>,
+    ) -> Result<ProfilingStatus> {
+        let mut doc = doc! { "profile": level.to_i32() };
+        if let Some(slow_ms) = slow_ms {
+            doc.insert("slowms", slow_ms);
+        }
+
+        let res = self.command(doc, CommandType::Profile, None)?;
+        ProfilingStatus::from_reply(&res)
+    }
+
+    fn profile_entries(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<TypedCursor<ProfileEntry>> {
+        let mut options = options.unwrap_or_default();
+        if options.sort.is_none() {
+            options.sort = Some(doc! { "ts": -1 });
+        }
+
+        self.collection("system.profile").find_as(filter, Some(options))
+    }
+
+    fn server_status(&self, sections: Option<ServerStatusOptions>) -> Result<ServerStatus> {
+        let mut doc = doc! { "serverStatus": 1 };
+        if let Some(sections) = sections {
+            doc = merge_options(doc, sections);
+        }
+
+        let mut res = self.command(doc, CommandType::ServerStatus, None)?;
+        res.remove("ok");
+        admin::decode_reply(res)
+    }
+
     fn version(&self) -> Result<Version> {
         let doc = doc! { "buildinfo": 1 };
         let out = self.command(doc, CommandType::BuildInfo, None)?;
@@ -319,17 +539,78 @@ impl ThreadedDatabase for Database {
         name: &str,
         options: Option<CreateCollectionOptions>,
     ) -> Result<()> {
-        let mut doc = doc! { "create": name };
+        let mut options = options.unwrap_or_default();
+        let wc = options.write_concern.unwrap_or(self.write_concern);
+        options.write_concern = Some(wc);
+
+        if options.expire_after_seconds.is_some() && options.clustered_index.is_none() &&
+            options.timeseries.is_none()
+        {
+            return Err(ArgumentError(String::from(
+                "Only clustered and time-series collections can expire documents.",
+            )));
+        }
 
-        if let Some(create_collection_options) = options {
-            doc = merge_options(doc, create_collection_options);
+        if options.capped == Some(true) && options.size.is_none() {
+            return Err(ArgumentError(String::from("Capped collections need a size.")));
         }
 
-        self.command(doc, CommandType::CreateCollection, None)?;
+        let doc = merge_options(doc! { "create": name }, options);
+
+        let res = self.command(doc, CommandType::CreateCollection, None)?;
+        WriteException::validate_write_result(res, wc)?;
+        self.forget_view(name)?;
+
+        Ok(())
+    }
+
+    fn create_view(
+        &self,
+        name: &str,
+        view_on: &str,
+        pipeline: Vec<bson::Document>,
+        options: Option<CreateViewOptions>,
+    ) -> Result<()> {
+        let mut options = options.unwrap_or_default();
+        let wc = options.write_concern.unwrap_or(self.write_concern);
+        options.write_concern = Some(wc);
+
+        let stages: Vec<_> = pipeline.into_iter().map(Bson::Document).collect();
+        let doc = merge_options(
+            doc! {
+                "create": name,
+                "viewOn": view_on,
+                "pipeline": stages,
+            },
+            options,
+        );
+
+        let res = self.command(doc, CommandType::CreateCollection, None)?;
+        WriteException::validate_write_result(res, wc)?;
+
+        let namespace = format!("{}.{}", self.name, name);
+        self.client.views.lock()?.insert(namespace, Some(String::from(view_on)));
 
         Ok(())
     }
 
+    fn modify_collection(&self, name: &str, changes: ModifyCollectionOptions) -> Result<()> {
+        if changes.is_empty() {
+            return Err(ArgumentError(String::from(
+                "modify_collection needs at least one change.",
+            )));
+        }
+
+        let mut changes = changes;
+        let wc = changes.write_concern.unwrap_or(self.write_concern);
+        changes.write_concern = Some(wc);
+
+        let doc = merge_options(doc! { "collMod": name }, changes);
+
+        let res = self.command(doc, CommandType::ModifyCollection, None)?;
+        WriteException::validate_write_result(res, wc)
+    }
+
     fn create_user(
         &self,
         name: &str,
@@ -370,13 +651,43 @@ impl ThreadedDatabase for Database {
     }
 
     fn drop_collection(&self, name: &str) -> Result<()> {
-        let spec = doc!{ "drop": name };
-        self.command(spec, CommandType::DropCollection, None).map(drop)
+        self.drop_collection_with_write_concern(name, None)
+    }
+
+    fn drop_collection_with_write_concern(
+        &self,
+        name: &str,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<()> {
+        let wc = write_concern.unwrap_or(self.write_concern);
+        let spec = doc! {
+            "drop": name,
+            "writeConcern": wc.to_bson(),
+        };
+
+        let res = self.command(spec, CommandType::DropCollection, None)?;
+        WriteException::validate_write_result(res, wc)?;
+        self.forget_view(name)
     }
 
     fn drop_database(&self) -> Result<()> {
-        let spec = doc!{ "dropDatabase": 1 };
-        self.command(spec, CommandType::DropDatabase, None).map(drop)
+        self.drop_database_with_write_concern(None)
+    }
+
+    fn drop_database_with_write_concern(&self, write_concern: Option<WriteConcern>)
+        -> Result<()> {
+        let wc = write_concern.unwrap_or(self.write_concern);
+        let spec = doc! {
+            "dropDatabase": 1,
+            "writeConcern": wc.to_bson(),
+        };
+
+        let res = self.command(spec, CommandType::DropDatabase, None)?;
+        WriteException::validate_write_result(res, wc)?;
+
+        let prefix = format!("{}.", self.name);
+        self.client.views.lock()?.retain(|namespace, _| !namespace.starts_with(&prefix));
+        Ok(())
     }
 
     fn drop_user(&self, name: &str, write_concern: Option<WriteConcern>) -> Result<()> {
@@ -466,4 +777,96 @@ impl ThreadedDatabase for Database {
             })
             .collect()
     }
+
+    fn users_info(&self, users: Vec<&str>, options: Option<UserInfoOptions>)
+        -> Result<Vec<UserInfo>> {
+        let users: Bson = if users.is_empty() {
+            Bson::I32(1)
+        } else {
+            users.into_iter().map(|user| bson!({ "user": user, "db": &self.name })).collect()
+        };
+
+        let mut doc = doc! { "usersInfo": users };
+
+        if let Some(user_info_options) = options {
+            doc = merge_options(doc, user_info_options);
+        }
+
+        let out = self.command(doc, CommandType::GetUsers, None)?;
+        match out.get("users") {
+            Some(&Bson::Array(ref users)) => users
+                .iter()
+                .map(|user| match *user {
+                    Bson::Document(ref doc) => UserInfo::from_document(doc),
+                    _ => Err(ResponseError(String::from("User info must be a document."))),
+                })
+                .collect(),
+            _ => Err(ResponseError(String::from("No users received from server."))),
+        }
+    }
+
+    fn update_user(&self, name: &str, options: UpdateUserOptions) -> Result<()> {
+        let doc = merge_options(doc! { "updateUser": name }, options);
+
+        if doc.len() == 1 || (doc.len() == 2 && doc.contains_key("writeConcern")) {
+            return Err(ArgumentError(String::from("Updating a user needs something to change.")));
+        }
+
+        self.command(doc, CommandType::UpdateUser, None).map(drop)
+    }
+}
+
+// Returns a command reply that reports `ok: 1`, or the error it reports otherwise.
+pub(crate) fn check_command_reply(reply: bson::Document) -> Result<bson::Document> {
+    let ok = match reply.get("ok") {
+        Some(&Bson::I32(v)) => v == 1,
+        Some(&Bson::I64(v)) => v == 1,
+        Some(&Bson::FloatingPoint(v)) => v == 1.0,
+        Some(&Bson::Boolean(b)) => b,
+        _ => return Err(ResponseError(String::from("Command reply does not contain `ok`."))),
+    };
+
+    if ok {
+        return Ok(reply);
+    }
+
+    let message = match reply.get("errmsg") {
+        Some(&Bson::String(ref message)) => message.to_owned(),
+        _ => format!("Command failed: {}", reply),
+    };
+
+    match reply.get("code") {
+        Some(&Bson::I32(code)) if code == ErrorCode::ExceededTimeLimit as i32 => {
+            Err(MaxTimeMSExpired(message))
+        }
+        _ => Err(OperationError(message)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Error;
+
+    #[test]
+    fn checks_command_replies() {
+        let reply = doc! { "ok": 1.0, "n": 2 };
+        assert_eq!(reply.clone(), check_command_reply(reply).unwrap());
+        assert!(check_command_reply(doc! { "ok": 1, "n": 2 }).is_ok());
+
+        match check_command_reply(doc! { "ok": 0.0, "errmsg": "no such command", "code": 59 }) {
+            Err(Error::OperationError(message)) => assert_eq!("no such command", message),
+            other => panic!("Expected an operation error, got {:?}", other),
+        }
+
+        match check_command_reply(doc! { "ok": 0, "errmsg": "too slow", "code": 50 }) {
+            Err(Error::MaxTimeMSExpired(message)) => assert_eq!("too slow", message),
+            other => panic!("Expected an expired time limit, got {:?}", other),
+        }
+
+        match check_command_reply(doc! { "n": 2 }) {
+            Err(Error::ResponseError(_)) => (),
+            other => panic!("Expected a response error, got {:?}", other),
+        }
+    }
 }
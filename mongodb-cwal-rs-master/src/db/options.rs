@@ -1,9 +1,163 @@
 //! Options for database-level commands.
-use bson::{Bson, Document};
+use bson::{Bson, Document, doc};
+use coll::options::Collation;
 use common::WriteConcern;
-use db::roles::Role;
+use db::roles::{AuthenticationRestriction, Role};
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// Which inserts and updates a collection's validator checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationLevel {
+    /// Checks nothing.
+    Off,
+    /// Checks every insert and update, which is the server default.
+    Strict,
+    /// Checks inserts, and updates to documents that already pass the validator.
+    Moderate,
+}
+
+impl ValidationLevel {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ValidationLevel::Off => "off",
+            ValidationLevel::Strict => "strict",
+            ValidationLevel::Moderate => "moderate",
+        }
+    }
+}
+
+/// What happens to documents failing a collection's validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationAction {
+    /// Rejects the write, which is the server default.
+    Error,
+    /// Accepts the write and logs a warning.
+    Warn,
+}
+
+impl ValidationAction {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ValidationAction::Error => "error",
+            ValidationAction::Warn => "warn",
+        }
+    }
+}
+
+/// The time span the buckets of a time-series collection are sized for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeSeriesGranularity {
+    /// For measurements arriving seconds apart, which is the server default.
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+impl TimeSeriesGranularity {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            TimeSeriesGranularity::Seconds => "seconds",
+            TimeSeriesGranularity::Minutes => "minutes",
+            TimeSeriesGranularity::Hours => "hours",
+        }
+    }
+
+    fn from_str(granularity: &str) -> Option<TimeSeriesGranularity> {
+        match granularity {
+            "seconds" => Some(TimeSeriesGranularity::Seconds),
+            "minutes" => Some(TimeSeriesGranularity::Minutes),
+            "hours" => Some(TimeSeriesGranularity::Hours),
+            _ => None,
+        }
+    }
+}
+
+/// Makes a collection store measurements in time-series buckets, which requires MongoDB 5.0
+/// or newer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TimeSeriesOptions {
+    /// The field holding the date of each measurement.
+    pub time_field: String,
+    /// The field identifying the source of each measurement, such as a sensor id.
+    pub meta_field: Option<String>,
+    pub granularity: Option<TimeSeriesGranularity>,
+}
+
+impl TimeSeriesOptions {
+    pub fn new(time_field: &str) -> TimeSeriesOptions {
+        TimeSeriesOptions {
+            time_field: String::from(time_field),
+            meta_field: None,
+            granularity: None,
+        }
+    }
+
+    /// Reads the `timeseries` specification of a collection, as reported by
+    /// `listCollections`.
+    pub fn from_document(document: &Document) -> Option<TimeSeriesOptions> {
+        let time_field = match document.get("timeField") {
+            Some(&Bson::String(ref time_field)) => time_field.clone(),
+            _ => return None,
+        };
+
+        let meta_field = match document.get("metaField") {
+            Some(&Bson::String(ref meta_field)) => Some(meta_field.clone()),
+            _ => None,
+        };
+
+        let granularity = match document.get("granularity") {
+            Some(&Bson::String(ref granularity)) => TimeSeriesGranularity::from_str(granularity),
+            _ => None,
+        };
+
+        Some(TimeSeriesOptions {
+            time_field: time_field,
+            meta_field: meta_field,
+            granularity: granularity,
+        })
+    }
+
+    fn to_bson(&self) -> Document {
+        let mut document = doc! { "timeField": self.time_field.clone() };
+
+        if let Some(ref meta_field) = self.meta_field {
+            document.insert("metaField", meta_field.clone());
+        }
+
+        if let Some(granularity) = self.granularity {
+            document.insert("granularity", granularity.as_str());
+        }
+
+        document
+    }
+}
+
+/// Stores the documents of a collection ordered by `_id`, which requires MongoDB 5.3 or newer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ClusteredIndex {
+    /// Defaults to a name derived from the key.
+    pub name: Option<String>,
+}
+
+impl ClusteredIndex {
+    pub fn new() -> ClusteredIndex {
+        Default::default()
+    }
+
+    fn to_bson(&self) -> Document {
+        let mut document = doc! {
+            "key": { "_id": 1 },
+            "unique": true,
+        };
+
+        if let Some(ref name) = self.name {
+            document.insert("name", name.clone());
+        }
+
+        document
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct CreateCollectionOptions {
     pub capped: Option<bool>,
     pub auto_index_id: Option<bool>,
@@ -11,6 +165,22 @@ pub struct CreateCollectionOptions {
     pub max: Option<i64>,
     pub use_power_of_two_sizes: Option<bool>,
     pub no_padding: Option<bool>,
+    /// A query filter, such as `{ "$jsonSchema": ... }`, that inserted and updated documents
+    /// must match.
+    pub validator: Option<Document>,
+    pub validation_level: Option<ValidationLevel>,
+    pub validation_action: Option<ValidationAction>,
+    /// The default collation of the collection and its indexes.
+    pub collation: Option<Collation>,
+    /// Configuration for the storage engine, keyed by engine name.
+    pub storage_engine: Option<Document>,
+    pub clustered_index: Option<ClusteredIndex>,
+    pub timeseries: Option<TimeSeriesOptions>,
+    /// Removes documents this many seconds after their `_id` in a clustered collection, or
+    /// after their time field in a time-series collection.
+    pub expire_after_seconds: Option<i64>,
+    /// Defaults to the write concern of the database.
+    pub write_concern: Option<WriteConcern>,
 }
 
 impl CreateCollectionOptions {
@@ -53,6 +223,199 @@ impl From<CreateCollectionOptions> for Document {
             document.insert("flags", flags);
         }
 
+        if let Some(validator) = options.validator {
+            document.insert("validator", validator);
+        }
+
+        if let Some(validation_level) = options.validation_level {
+            document.insert("validationLevel", validation_level.as_str());
+        }
+
+        if let Some(validation_action) = options.validation_action {
+            document.insert("validationAction", validation_action.as_str());
+        }
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", Document::from(collation));
+        }
+
+        if let Some(storage_engine) = options.storage_engine {
+            document.insert("storageEngine", storage_engine);
+        }
+
+        if let Some(clustered_index) = options.clustered_index {
+            document.insert("clusteredIndex", clustered_index.to_bson());
+        }
+
+        if let Some(timeseries) = options.timeseries {
+            document.insert("timeseries", timeseries.to_bson());
+        }
+
+        if let Some(expire_after_seconds) = options.expire_after_seconds {
+            document.insert("expireAfterSeconds", Bson::I64(expire_after_seconds));
+        }
+
+        if let Some(write_concern) = options.write_concern {
+            document.insert("writeConcern", write_concern.to_bson());
+        }
+
+        document
+    }
+}
+
+/// Picks the index an `IndexChange` applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexTarget {
+    Name(String),
+    Keys(Document),
+}
+
+/// Changes to one index of a collection, made with `collMod`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexChange {
+    pub index: IndexTarget,
+    /// The new lifetime in seconds of the documents in a TTL index.
+    pub expire_after_seconds: Option<i64>,
+    /// Hides the index from the query planner, or unhides it.
+    pub hidden: Option<bool>,
+}
+
+impl IndexChange {
+    /// Changes nothing yet about the index named `name`.
+    pub fn named(name: &str) -> IndexChange {
+        IndexChange::new(IndexTarget::Name(String::from(name)))
+    }
+
+    /// Changes nothing yet about the index on `keys`.
+    pub fn with_keys(keys: Document) -> IndexChange {
+        IndexChange::new(IndexTarget::Keys(keys))
+    }
+
+    fn new(index: IndexTarget) -> IndexChange {
+        IndexChange {
+            index: index,
+            expire_after_seconds: None,
+            hidden: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.expire_after_seconds.is_none() && self.hidden.is_none()
+    }
+
+    fn to_bson(&self) -> Document {
+        let mut document = Document::new();
+
+        match self.index {
+            IndexTarget::Name(ref name) => document.insert("name", name.clone()),
+            IndexTarget::Keys(ref keys) => document.insert("keyPattern", keys.clone()),
+        };
+
+        if let Some(expire_after_seconds) = self.expire_after_seconds {
+            document.insert("expireAfterSeconds", Bson::I64(expire_after_seconds));
+        }
+
+        if let Some(hidden) = self.hidden {
+            document.insert("hidden", hidden);
+        }
+
+        document
+    }
+}
+
+/// Changes to a collection, made with `collMod`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModifyCollectionOptions {
+    /// Replaces the validator; an empty document removes it.
+    pub validator: Option<Document>,
+    pub validation_level: Option<ValidationLevel>,
+    pub validation_action: Option<ValidationAction>,
+    pub index: Option<IndexChange>,
+    /// The new maximum size in bytes of a capped collection, from MongoDB 6.0 on.
+    pub capped_size: Option<i64>,
+    /// The new maximum number of documents in a capped collection, from MongoDB 6.0 on.
+    pub capped_max: Option<i64>,
+    /// Defaults to the write concern of the database.
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl ModifyCollectionOptions {
+    pub fn new() -> ModifyCollectionOptions {
+        Default::default()
+    }
+
+    /// Returns whether the options change nothing about the collection.
+    pub fn is_empty(&self) -> bool {
+        self.validator.is_none() && self.validation_level.is_none() &&
+            self.validation_action.is_none() && self.capped_size.is_none() &&
+            self.capped_max.is_none() &&
+            self.index.as_ref().map_or(true, IndexChange::is_empty)
+    }
+}
+
+impl From<ModifyCollectionOptions> for Document {
+    fn from(options: ModifyCollectionOptions) -> Self {
+        let mut document = Document::new();
+
+        if let Some(validator) = options.validator {
+            document.insert("validator", validator);
+        }
+
+        if let Some(validation_level) = options.validation_level {
+            document.insert("validationLevel", validation_level.as_str());
+        }
+
+        if let Some(validation_action) = options.validation_action {
+            document.insert("validationAction", validation_action.as_str());
+        }
+
+        if let Some(index) = options.index {
+            document.insert("index", index.to_bson());
+        }
+
+        if let Some(capped_size) = options.capped_size {
+            document.insert("cappedSize", Bson::I64(capped_size));
+        }
+
+        if let Some(capped_max) = options.capped_max {
+            document.insert("cappedMax", Bson::I64(capped_max));
+        }
+
+        if let Some(write_concern) = options.write_concern {
+            document.insert("writeConcern", write_concern.to_bson());
+        }
+
+        document
+    }
+}
+
+/// Options for creating a read-only view.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CreateViewOptions {
+    /// The default collation of the view; views do not inherit the collation of their source.
+    pub collation: Option<Collation>,
+    /// Defaults to the write concern of the database.
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl CreateViewOptions {
+    pub fn new() -> CreateViewOptions {
+        Default::default()
+    }
+}
+
+impl From<CreateViewOptions> for Document {
+    fn from(options: CreateViewOptions) -> Self {
+        let mut document = Document::new();
+
+        if let Some(collation) = options.collation {
+            document.insert("collation", Document::from(collation));
+        }
+
+        if let Some(write_concern) = options.write_concern {
+            document.insert("writeConcern", write_concern.to_bson());
+        }
+
         document
     }
 }
@@ -61,6 +424,7 @@ impl From<CreateCollectionOptions> for Document {
 pub struct CreateUserOptions {
     pub custom_data: Option<Document>,
     pub roles: Vec<Role>,
+    pub authentication_restrictions: Vec<AuthenticationRestriction>,
     pub write_concern: Option<WriteConcern>,
 }
 
@@ -82,6 +446,65 @@ impl From<CreateUserOptions> for Document {
 
         document.insert("roles", Bson::Array(roles_barr));
 
+        if !options.authentication_restrictions.is_empty() {
+            let restrictions = options.authentication_restrictions
+                .iter()
+                .map(AuthenticationRestriction::to_bson)
+                .collect();
+            document.insert("authenticationRestrictions", Bson::Array(restrictions));
+        }
+
+        if let Some(write_concern) = options.write_concern {
+            document.insert("writeConcern", write_concern.to_bson());
+        }
+
+        document
+    }
+}
+
+/// Changes made by `updateUser`; fields left unset are kept as they are.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct UpdateUserOptions {
+    pub password: Option<String>,
+    /// Replaces the custom data of the user.
+    pub custom_data: Option<Document>,
+    /// Replaces the roles of the user.
+    pub roles: Option<Vec<Role>>,
+    /// Replaces the authentication restrictions of the user.
+    pub authentication_restrictions: Option<Vec<AuthenticationRestriction>>,
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl UpdateUserOptions {
+    pub fn new() -> UpdateUserOptions {
+        Default::default()
+    }
+}
+
+impl From<UpdateUserOptions> for Document {
+    fn from(options: UpdateUserOptions) -> Self {
+        let mut document = Document::new();
+
+        if let Some(password) = options.password {
+            document.insert("pwd", password);
+        }
+
+        if let Some(custom_data) = options.custom_data {
+            document.insert("customData", Bson::Document(custom_data));
+        }
+
+        if let Some(roles) = options.roles {
+            document.insert("roles", Bson::Array(roles.iter().map(Role::to_bson).collect()));
+        }
+
+        if let Some(restrictions) = options.authentication_restrictions {
+            let restrictions = restrictions
+                .iter()
+                .map(AuthenticationRestriction::to_bson)
+                .collect();
+            document.insert("authenticationRestrictions", Bson::Array(restrictions));
+        }
+
         if let Some(write_concern) = options.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
@@ -117,3 +540,103 @@ impl From<UserInfoOptions> for Document {
         document
     }
 }
+
+/// Selects what `listCollections` reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ListCollectionsOptions {
+    /// Reports only the names and types of the collections, which needs no collection locks.
+    pub name_only: Option<bool>,
+    /// With `name_only`, reports only the collections the user has privileges on, so that
+    /// users without the `listCollections` privilege can list them.
+    pub authorized_collections: Option<bool>,
+    pub batch_size: Option<i32>,
+}
+
+impl ListCollectionsOptions {
+    pub fn new() -> ListCollectionsOptions {
+        Default::default()
+    }
+}
+
+impl From<ListCollectionsOptions> for Document {
+    fn from(options: ListCollectionsOptions) -> Self {
+        let mut document = Document::new();
+
+        if let Some(name_only) = options.name_only {
+            document.insert("nameOnly", name_only);
+        }
+
+        if let Some(authorized_collections) = options.authorized_collections {
+            document.insert("authorizedCollections", authorized_collections);
+        }
+
+        document
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_collection_options() {
+        let options = CreateCollectionOptions {
+            validator: Some(doc! { "qty": { "$gte": 0 } }),
+            validation_level: Some(ValidationLevel::Moderate),
+            validation_action: Some(ValidationAction::Warn),
+            collation: Some(Collation::new("fr")),
+            clustered_index: Some(ClusteredIndex::new()),
+            expire_after_seconds: Some(3600),
+            ..CreateCollectionOptions::new()
+        };
+
+        let expected = doc! {
+            "validator": { "qty": { "$gte": 0 } },
+            "validationLevel": "moderate",
+            "validationAction": "warn",
+            "collation": { "locale": "fr" },
+            "clusteredIndex": { "key": { "_id": 1 }, "unique": true },
+            "expireAfterSeconds": 3600i64,
+        };
+        assert_eq!(expected, Document::from(options));
+
+        let mut timeseries = TimeSeriesOptions::new("ts");
+        timeseries.meta_field = Some(String::from("sensor"));
+        timeseries.granularity = Some(TimeSeriesGranularity::Minutes);
+        let options = CreateCollectionOptions {
+            timeseries: Some(timeseries.clone()),
+            ..CreateCollectionOptions::new()
+        };
+
+        let expected = doc! {
+            "timeField": "ts",
+            "metaField": "sensor",
+            "granularity": "minutes",
+        };
+        assert_eq!(doc! { "timeseries": expected.clone() }, Document::from(options));
+        assert_eq!(Some(timeseries), TimeSeriesOptions::from_document(&expected));
+    }
+
+    #[test]
+    fn converts_collection_changes() {
+        let mut options = ModifyCollectionOptions {
+            index: Some(IndexChange::named("created_1")),
+            ..ModifyCollectionOptions::new()
+        };
+        assert!(options.is_empty());
+
+        options.index.as_mut().unwrap().expire_after_seconds = Some(60);
+        options.capped_size = Some(4096);
+        assert!(!options.is_empty());
+
+        let expected = doc! {
+            "index": { "name": "created_1", "expireAfterSeconds": 60i64 },
+            "cappedSize": 4096i64,
+        };
+        assert_eq!(expected, Document::from(options));
+
+        let mut hide = IndexChange::with_keys(doc! { "a": 1 });
+        hide.hidden = Some(true);
+        assert_eq!(doc! { "keyPattern": { "a": 1 }, "hidden": true }, hide.to_bson());
+    }
+}
@@ -1,7 +1,9 @@
 //! Role-based database and command authorization.
 use std::string::ToString;
 
-use bson::{Bson, bson, doc};
+use bson::{self, Bson, bson, doc};
+use Error::ResponseError;
+use Result;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum SingleDatabaseRole {
@@ -36,6 +38,28 @@ impl SingleDatabaseRole {
     }
 }
 
+impl SingleDatabaseRole {
+    // The built-in role with the given name, if any.
+    fn from_name(name: &str) -> Option<SingleDatabaseRole> {
+        let role = match name {
+            "read" => SingleDatabaseRole::Read,
+            "readWrite" => SingleDatabaseRole::ReadWrite,
+            "dbAdmin" => SingleDatabaseRole::DbAdmin,
+            "dbOwner" => SingleDatabaseRole::DbOwner,
+            "userAdmin" => SingleDatabaseRole::UserAdmin,
+            "clusterAdmin" => SingleDatabaseRole::ClusterAdmin,
+            "clusterManager" => SingleDatabaseRole::ClusterManager,
+            "clusterMonitor" => SingleDatabaseRole::ClusterMonitor,
+            "hostManager" => SingleDatabaseRole::HostManager,
+            "backup" => SingleDatabaseRole::Backup,
+            "restore" => SingleDatabaseRole::Restore,
+            _ => return None,
+        };
+
+        Some(role)
+    }
+}
+
 impl ToString for SingleDatabaseRole {
     fn to_string(&self) -> String {
         self.to_str().into()
@@ -74,6 +98,11 @@ pub enum Role {
         role: SingleDatabaseRole,
         db: String,
     },
+    /// A user-defined role, created with `createRole` in `db`.
+    Custom {
+        role: String,
+        db: String,
+    },
 }
 
 impl From<Role> for Bson {
@@ -86,6 +115,7 @@ impl From<Role> for Bson {
                   "db": db
               })
             }
+            Role::Custom { role, db } => Bson::Document(doc! { "role": role, "db": db }),
         }
     }
 }
@@ -95,8 +125,191 @@ impl Role {
         self.clone().into()
     }
 
+    /// Decodes a `{ role, db }` document as reported by `usersInfo`, with built-in roles as
+    /// `Single` and any other role as `Custom`.
+    pub fn from_document(doc: &bson::Document) -> Result<Role> {
+        let (role, db) = match (doc.get("role"), doc.get("db")) {
+            (Some(&Bson::String(ref role)), Some(&Bson::String(ref db))) => (role, db),
+            _ => {
+                return Err(ResponseError(format!(
+                    "Role must name a role and a database, got {}.",
+                    doc
+                )))
+            }
+        };
+
+        Ok(match SingleDatabaseRole::from_name(role) {
+            Some(builtin) => Role::Single {
+                role: builtin,
+                db: db.clone(),
+            },
+            None => Role::Custom {
+                role: role.clone(),
+                db: db.clone(),
+            },
+        })
+    }
+
     #[deprecated(since = "0.2.4", note = "this method will be removed in the next major release")]
     pub fn to_bson_array(vec: Vec<Role>) -> Bson {
         Bson::Array(vec.iter().map(Self::to_bson).collect())
     }
 }
+
+/// Limits the addresses a user may authenticate from and to (MongoDB 3.6+).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AuthenticationRestriction {
+    /// The client IP addresses or CIDR ranges the user may connect from; empty for any.
+    pub client_source: Vec<String>,
+    /// The server IP addresses or CIDR ranges the user may connect to; empty for any.
+    pub server_address: Vec<String>,
+}
+
+impl AuthenticationRestriction {
+    pub fn to_bson(&self) -> Bson {
+        let mut doc = bson::Document::new();
+
+        if !self.client_source.is_empty() {
+            doc.insert("clientSource", strings_to_bson(&self.client_source));
+        }
+
+        if !self.server_address.is_empty() {
+            doc.insert("serverAddress", strings_to_bson(&self.server_address));
+        }
+
+        Bson::Document(doc)
+    }
+
+    fn from_document(doc: &bson::Document) -> AuthenticationRestriction {
+        AuthenticationRestriction {
+            client_source: strings_from_bson(doc.get("clientSource")),
+            server_address: strings_from_bson(doc.get("serverAddress")),
+        }
+    }
+}
+
+/// A user as reported by `usersInfo`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserInfo {
+    pub user: String,
+    /// The database the user was created in.
+    pub db: String,
+    pub roles: Vec<Role>,
+    pub custom_data: Option<bson::Document>,
+    pub authentication_restrictions: Vec<AuthenticationRestriction>,
+    /// The SCRAM mechanisms the user has credentials for, reported by MongoDB 4.0 and newer.
+    pub mechanisms: Vec<String>,
+}
+
+impl UserInfo {
+    /// Decodes an element of the `users` array of a `usersInfo` reply.
+    pub fn from_document(doc: &bson::Document) -> Result<UserInfo> {
+        let (user, db) = match (doc.get("user"), doc.get("db")) {
+            (Some(&Bson::String(ref user)), Some(&Bson::String(ref db))) => {
+                (user.clone(), db.clone())
+            }
+            _ => {
+                return Err(ResponseError(
+                    String::from("User info must name a user and a database."),
+                ))
+            }
+        };
+
+        let mut roles = Vec::new();
+        for role in documents(doc.get("roles")) {
+            roles.push(Role::from_document(role)?);
+        }
+
+        let authentication_restrictions = documents(doc.get("authenticationRestrictions"))
+            .into_iter()
+            .map(AuthenticationRestriction::from_document)
+            .collect();
+
+        let custom_data = match doc.get("customData") {
+            Some(&Bson::Document(ref data)) => Some(data.clone()),
+            _ => None,
+        };
+
+        Ok(UserInfo {
+            user: user,
+            db: db,
+            roles: roles,
+            custom_data: custom_data,
+            authentication_restrictions: authentication_restrictions,
+            mechanisms: strings_from_bson(doc.get("mechanisms")),
+        })
+    }
+}
+
+fn strings_to_bson(strings: &[String]) -> Bson {
+    Bson::Array(strings.iter().cloned().map(Bson::String).collect())
+}
+
+fn strings_from_bson(bson: Option<&Bson>) -> Vec<String> {
+    match bson {
+        Some(&Bson::Array(ref values)) => values
+            .iter()
+            .filter_map(|value| match *value {
+                Bson::String(ref value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect(),
+        // A single address may be given without an array.
+        Some(&Bson::String(ref value)) => vec![value.clone()],
+        _ => Vec::new(),
+    }
+}
+
+fn documents(bson: Option<&Bson>) -> Vec<&bson::Document> {
+    match bson {
+        Some(&Bson::Array(ref values)) => values
+            .iter()
+            .filter_map(|value| match *value {
+                Bson::Document(ref doc) => Some(doc),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_user_info() {
+        let reply = doc! {
+            "_id": "shop.app",
+            "user": "app",
+            "db": "shop",
+            "roles": [
+                { "role": "readWrite", "db": "shop" },
+                { "role": "reporting", "db": "admin" },
+            ],
+            "customData": { "team": "orders" },
+            "authenticationRestrictions": [{ "clientSource": ["10.0.0.0/8"] }],
+            "mechanisms": ["SCRAM-SHA-256"],
+        };
+
+        let info = UserInfo::from_document(&reply).unwrap();
+        assert_eq!("app", info.user);
+        assert_eq!(
+            vec![
+                Role::Single { role: SingleDatabaseRole::ReadWrite, db: String::from("shop") },
+                Role::Custom { role: String::from("reporting"), db: String::from("admin") },
+            ],
+            info.roles
+        );
+        assert_eq!(Some(doc! { "team": "orders" }), info.custom_data);
+        assert_eq!(vec![String::from("10.0.0.0/8")],
+                   info.authentication_restrictions[0].client_source);
+        assert_eq!(
+            Bson::Document(doc! { "clientSource": ["10.0.0.0/8"] }),
+            info.authentication_restrictions[0].to_bson()
+        );
+        assert_eq!(vec![String::from("SCRAM-SHA-256")], info.mechanisms);
+
+        assert!(UserInfo::from_document(&doc! { "user": "app" }).is_err());
+    }
+}
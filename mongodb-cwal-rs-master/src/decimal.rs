@@ -0,0 +1,115 @@
+//! Decimal128 values, available with the `decimal128` feature.
+//!
+//! With the feature, decimal fields are decoded as `Bson::Decimal128` wherever documents are
+//! read, including query results, aggregation results and cursors, and are sent back to the
+//! server unchanged. Typed collections may use `Decimal128` as a field type, and
+//! `Document::get_decimal128` reads decimal fields without converting them.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! # use mongodb::{Bson, Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::decimal;
+//! #
+//! # fn main() {
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("ledger").collection("entries");
+//!
+//! let amount = decimal::parse("1234.56").unwrap();
+//! coll.insert_one(doc!{ "_id": 1, "amount": Bson::Decimal128(amount) }, None).unwrap();
+//!
+//! let entry = coll.find_one(None, None).unwrap().unwrap();
+//! assert_eq!("1234.56", entry.get_decimal128("amount").unwrap().to_string());
+//! # }
+//! ```
+pub use bson::decimal128::Decimal128;
+
+use Error::ArgumentError;
+use Result;
+
+/// Parses a decimal string such as `12.30`, `-1.5E+3`, `NaN` or `Infinity`.
+///
+/// Unlike `Decimal128::from_str`, malformed strings are reported as an `ArgumentError` rather
+/// than a panic. Digits beyond the 34 a Decimal128 holds are rounded.
+pub fn parse(s: &str) -> Result<Decimal128> {
+    if is_decimal_string(s) {
+        Ok(Decimal128::from_str(s))
+    } else {
+        Err(ArgumentError(format!("'{}' is not a decimal number.", s)))
+    }
+}
+
+// Checks the syntax of a decimal string: an optional sign followed by NaN, Inf or Infinity, or
+// by digits with an optional decimal point and exponent.
+fn is_decimal_string(s: &str) -> bool {
+    let unsigned = s.trim_start_matches(|c| c == '+' || c == '-');
+    if s.len() - unsigned.len() > 1 {
+        return false;
+    }
+
+    let lower = unsigned.to_lowercase();
+    if lower == "nan" || lower == "inf" || lower == "infinity" {
+        return true;
+    }
+
+    let (mantissa, exponent) = match lower.find('e') {
+        Some(index) => (&lower[..index], Some(&lower[index + 1..])),
+        None => (&lower[..], None),
+    };
+
+    let mut parts = mantissa.splitn(2, '.');
+    let integer = parts.next().unwrap_or("");
+    let fraction = parts.next().unwrap_or("");
+    let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if integer.is_empty() && fraction.is_empty() || !all_digits(integer) || !all_digits(fraction) {
+        return false;
+    }
+
+    match exponent {
+        Some(exponent) => {
+            let digits = exponent.trim_start_matches(|c| c == '+' || c == '-');
+            exponent.len() - digits.len() <= 1 && !digits.is_empty() && all_digits(digits)
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{self, doc, Bson};
+    use wire_protocol::operations::ByteLength;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Entry {
+        amount: Decimal128,
+    }
+
+    #[test]
+    fn parses_decimal_strings() {
+        for s in &["0", "12.30", "-1.5E+3", ".5", "5.", "+7e-2", "NaN", "-Infinity", "inf"] {
+            assert!(parse(s).is_ok(), "{} should parse", s);
+        }
+        for s in &["", ".", "1.2.3", "--1", "1e", "1e+-2", "12a", "0x10", "infinite"] {
+            assert!(parse(s).is_err(), "{} should not parse", s);
+        }
+        assert_eq!("12.30", parse("12.30").unwrap().to_string());
+    }
+
+    #[test]
+    fn round_trips_through_documents() {
+        let amount = parse("1234.56").unwrap();
+        let document = doc! { "amount": Bson::Decimal128(amount.clone()) };
+        assert_eq!(Ok(&amount), document.get_decimal128("amount"));
+
+        let mut bytes = Vec::new();
+        bson::encode_document(&mut bytes, &document).unwrap();
+        assert_eq!(bytes.len() as i32, document.byte_length().unwrap());
+        assert_eq!(document, bson::decode_document(&mut &bytes[..]).unwrap());
+
+        let entry: Entry = bson::from_bson(Bson::Document(document.clone())).unwrap();
+        assert_eq!(amount, entry.amount);
+        assert_eq!(Bson::Document(document), bson::to_bson(&entry).unwrap());
+    }
+}
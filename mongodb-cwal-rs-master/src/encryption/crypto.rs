@@ -0,0 +1,135 @@
+//! The `AEAD_AES_256_CBC_HMAC_SHA_512` authenticated encryption of field values and data keys.
+//!
+//! A 96 byte key holds a MAC key, an encryption key and, for deterministic encryption, a key
+//! the IV is derived with, in that order. The ciphertext is the IV, the AES-256-CBC encrypted
+//! plaintext and the first 32 bytes of an HMAC-SHA-512 over the associated data, the IV and
+//! encrypted plaintext, and the length of the associated data in bits.
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use openssl::symm::{self, Cipher};
+
+use Error::EncryptionError;
+use Result;
+
+/// The length in bytes of data keys and local master keys.
+pub const KEY_LENGTH: usize = 96;
+
+const IV_LENGTH: usize = 16;
+const TAG_LENGTH: usize = 32;
+
+fn crypto_error<E: ::std::fmt::Display>(err: E) -> ::Error {
+    EncryptionError(err.to_string())
+}
+
+fn split_key(key: &[u8]) -> Result<(&[u8], &[u8], &[u8])> {
+    if key.len() != KEY_LENGTH {
+        return Err(EncryptionError(format!(
+            "Encryption keys must be {} bytes long, not {}.",
+            KEY_LENGTH,
+            key.len()
+        )));
+    }
+
+    Ok((&key[..32], &key[32..64], &key[64..]))
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key).map_err(crypto_error)?;
+    let mut signer = Signer::new(MessageDigest::sha512(), &key).map_err(crypto_error)?;
+    for part in parts {
+        signer.update(part).map_err(crypto_error)?;
+    }
+    signer.sign_to_vec().map_err(crypto_error)
+}
+
+// The length of the associated data in bits, as a big-endian 64-bit integer.
+fn associated_data_length(associated_data: &[u8]) -> [u8; 8] {
+    ((associated_data.len() as u64) * 8).to_be_bytes()
+}
+
+/// Encrypts `plaintext`, with a random IV unless `deterministic`, in which case equal
+/// plaintexts encrypted with the same key and associated data give equal ciphertexts.
+pub fn encrypt(
+    key: &[u8],
+    associated_data: &[u8],
+    plaintext: &[u8],
+    deterministic: bool,
+) -> Result<Vec<u8>> {
+    let (mac_key, encryption_key, iv_key) = split_key(key)?;
+    let length = associated_data_length(associated_data);
+
+    let mut iv = [0; IV_LENGTH];
+    if deterministic {
+        let derived = hmac_sha512(iv_key, &[associated_data, &length, plaintext])?;
+        iv.copy_from_slice(&derived[..IV_LENGTH]);
+    } else {
+        rand_bytes(&mut iv).map_err(crypto_error)?;
+    }
+
+    let encrypted = symm::encrypt(Cipher::aes_256_cbc(), encryption_key, Some(&iv), plaintext)
+        .map_err(crypto_error)?;
+
+    let mut ciphertext = Vec::with_capacity(IV_LENGTH + encrypted.len() + TAG_LENGTH);
+    ciphertext.extend_from_slice(&iv);
+    ciphertext.extend_from_slice(&encrypted);
+
+    let tag = hmac_sha512(mac_key, &[associated_data, &ciphertext, &length])?;
+    ciphertext.extend_from_slice(&tag[..TAG_LENGTH]);
+    Ok(ciphertext)
+}
+
+/// Decrypts a ciphertext returned by `encrypt`, failing if it was not encrypted with `key`
+/// and `associated_data` or was altered since.
+pub fn decrypt(key: &[u8], associated_data: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let (mac_key, encryption_key, _) = split_key(key)?;
+
+    if ciphertext.len() < IV_LENGTH + 16 + TAG_LENGTH {
+        return Err(EncryptionError(String::from("Ciphertext is too short.")));
+    }
+
+    let (encrypted, tag) = ciphertext.split_at(ciphertext.len() - TAG_LENGTH);
+    let length = associated_data_length(associated_data);
+    let expected = hmac_sha512(mac_key, &[associated_data, encrypted, &length])?;
+    if !memcmp::eq(&expected[..TAG_LENGTH], tag) {
+        return Err(EncryptionError(String::from("HMAC validation failure.")));
+    }
+
+    let (iv, encrypted) = encrypted.split_at(IV_LENGTH);
+    symm::decrypt(Cipher::aes_256_cbc(), encryption_key, Some(iv), encrypted)
+        .map_err(crypto_error)
+}
+
+/// Returns `len` random bytes.
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    rand_bytes(&mut bytes).map_err(crypto_error)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_authenticates() {
+        let key: Vec<u8> = (0..KEY_LENGTH as u8).collect();
+        let random = encrypt(&key, b"ad", b"secret", false).unwrap();
+        assert_eq!(IV_LENGTH + 16 + TAG_LENGTH, random.len());
+        assert_eq!(b"secret".to_vec(), decrypt(&key, b"ad", &random).unwrap());
+        assert_ne!(random, encrypt(&key, b"ad", b"secret", false).unwrap());
+
+        let deterministic = encrypt(&key, b"ad", b"secret", true).unwrap();
+        assert_eq!(deterministic, encrypt(&key, b"ad", b"secret", true).unwrap());
+        assert_ne!(deterministic, encrypt(&key, b"other", b"secret", true).unwrap());
+
+        // Altered ciphertexts, and those checked with other associated data, are rejected.
+        let mut altered = deterministic.clone();
+        altered[IV_LENGTH] ^= 1;
+        assert!(decrypt(&key, b"ad", &altered).is_err());
+        assert!(decrypt(&key, b"other", &deterministic).is_err());
+        assert!(encrypt(&key[1..], b"ad", b"secret", true).is_err());
+    }
+}
@@ -0,0 +1,838 @@
+//! Client-side field level encryption.
+//!
+//! Clients created with `ClientOptions::auto_encryption` encrypt the fields their schema map
+//! marks for encryption before commands are sent, and decrypt every encrypted value of the
+//! replies, so that the server only ever stores and returns ciphertexts. Values are encrypted
+//! with `AEAD_AES_256_CBC_HMAC_SHA_512` under data keys kept in a key vault collection, each
+//! itself encrypted with a master key from a KMS provider. `ClientEncryption` creates and
+//! manages data keys, and encrypts or decrypts values explicitly, whether or not any client
+//! encrypts automatically.
+//!
+//! Unlike other drivers, this one does not spawn `mongocryptd` or load `crypt_shared` to
+//! analyse commands: it finds the encrypted fields of inserted documents, update operators
+//! and query filters itself, from the schema map alone. Collections with a server-side
+//! `$jsonSchema` validator are not encrypted unless the client's schema map covers them.
+//! Only the `local` KMS provider, whose master key is kept by the application, is supported.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ClientOptions, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::encryption::{Algorithm, AutoEncryptionOptions, ClientEncryption, KmsProviders};
+//! # use std::collections::HashMap;
+//! #
+//! # fn main() {
+//! # let master_key = vec![0; 96];
+//! let kms_providers = KmsProviders { local: Some(master_key) };
+//! let vault = Client::connect("localhost", 27017).unwrap();
+//! let encryption = ClientEncryption::new(vault, "keys.vault", kms_providers.clone()).unwrap();
+//! let key_id = encryption.create_data_key("local", Vec::new()).unwrap();
+//!
+//! let mut schema_map = HashMap::new();
+//! schema_map.insert(String::from("hr.people"), doc! {
+//!     "bsonType": "object",
+//!     "properties": {
+//!         "ssn": {
+//!             "encrypt": { "keyId": [key_id], "algorithm": Algorithm::Deterministic.name() },
+//!         },
+//!     },
+//! });
+//!
+//! let mut auto_encryption = AutoEncryptionOptions::new("keys.vault", kms_providers);
+//! auto_encryption.schema_map = schema_map;
+//! let mut options = ClientOptions::new();
+//! options.auto_encryption = Some(auto_encryption);
+//!
+//! let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+//! let people = client.db("hr").collection("people");
+//! people.insert_one(doc! { "name": "Ada", "ssn": "078-05-1120" }, None).unwrap();
+//! let ada = people.find_one(Some(doc! { "ssn": "078-05-1120" }), None).unwrap();
+//! # }
+//! ```
+mod crypto;
+mod schema;
+
+pub use self::crypto::KEY_LENGTH;
+
+use bson::{self, doc, Bson, Document};
+use bson::spec::{BinarySubtype, ElementType};
+use chrono::Utc;
+
+use self::schema::{EncryptedField, Schema};
+use coll::Collection;
+use coll::options::UpdateOptions;
+use coll::results::DeleteResult;
+use common::WriteConcern;
+use cursor::Cursor;
+use db::ThreadedDatabase;
+use interceptor::{CommandContext, CommandInterceptor};
+use poison::Recover;
+use Client;
+use Error::{ArgumentError, EncryptionError, WriteError};
+use Result;
+use ThreadedClient;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+// The binary subtype of encrypted values.
+const ENCRYPTED_SUBTYPE: u8 = 6;
+
+// The first byte of an encrypted value, naming its algorithm.
+const DETERMINISTIC_BLOB: u8 = 1;
+const RANDOM_BLOB: u8 = 2;
+
+// An encrypted value starts with its algorithm, the UUID of its data key and the type of the
+// plaintext, which together are the associated data of its ciphertext.
+const BLOB_HEADER_LENGTH: usize = 18;
+
+/// How a value is encrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Equal values under the same data key give equal ciphertexts, so they can be queried
+    /// for equality.
+    Deterministic,
+    /// Every encryption gives a different ciphertext, so the values cannot be queried.
+    Random,
+}
+
+impl Algorithm {
+    /// Returns the name of the algorithm, as given in schemas.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Algorithm::Deterministic => "AEAD_AES_256_CBC_HMAC_SHA_512-Deterministic",
+            Algorithm::Random => "AEAD_AES_256_CBC_HMAC_SHA_512-Random",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Result<Algorithm> {
+        match name {
+            "AEAD_AES_256_CBC_HMAC_SHA_512-Deterministic" => Ok(Algorithm::Deterministic),
+            "AEAD_AES_256_CBC_HMAC_SHA_512-Random" => Ok(Algorithm::Random),
+            _ => Err(ArgumentError(format!("Unknown encryption algorithm {}.", name))),
+        }
+    }
+}
+
+/// The master keys data keys are encrypted with.
+#[derive(Clone, Default)]
+pub struct KmsProviders {
+    /// The 96 byte master key of the `local` provider.
+    pub local: Option<Vec<u8>>,
+}
+
+impl KmsProviders {
+    /// Creates a new KmsProviders struct without any provider.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl fmt::Debug for KmsProviders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KmsProviders")
+            .field("local", &self.local.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Options for the automatic encryption of a client's commands.
+#[derive(Clone, Debug)]
+pub struct AutoEncryptionOptions {
+    /// The client data keys are read with. None means a client connected to the same
+    /// servers as the encrypting client, without encryption.
+    pub key_vault_client: Option<Client>,
+    /// The collection holding the data keys, as `db.coll`.
+    pub key_vault_namespace: String,
+    pub kms_providers: KmsProviders,
+    /// The JSON schema of each namespace, as `db.coll`, whose fields are encrypted.
+    pub schema_map: HashMap<String, Document>,
+    /// Whether commands are sent as they are, only decrypting replies; default false.
+    pub bypass_auto_encryption: bool,
+}
+
+impl AutoEncryptionOptions {
+    /// Creates options reading data keys from `key_vault_namespace`, without any schema.
+    pub fn new(key_vault_namespace: &str, kms_providers: KmsProviders) -> Self {
+        AutoEncryptionOptions {
+            key_vault_client: None,
+            key_vault_namespace: String::from(key_vault_namespace),
+            kms_providers: kms_providers,
+            schema_map: HashMap::new(),
+            bypass_auto_encryption: false,
+        }
+    }
+}
+
+/// Options for encrypting a value explicitly; exactly one of `key_id` and `key_alt_name`
+/// names its data key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptOptions {
+    /// The UUID of the data key, as returned by `create_data_key`.
+    pub key_id: Option<Bson>,
+    /// One of the alternate names of the data key.
+    pub key_alt_name: Option<String>,
+    pub algorithm: Algorithm,
+}
+
+impl EncryptOptions {
+    /// Creates options encrypting with `algorithm`, under a data key still to be named.
+    pub fn new(algorithm: Algorithm) -> Self {
+        EncryptOptions {
+            key_id: None,
+            key_alt_name: None,
+            algorithm: algorithm,
+        }
+    }
+}
+
+/// Options for rewrapping data keys.
+#[derive(Clone, Default)]
+pub struct RewrapManyDataKeyOptions {
+    /// The KMS provider the keys are encrypted with from then on, which must be `local`;
+    /// default `local`.
+    pub provider: Option<String>,
+    /// The 96 byte local master key the keys are encrypted with from then on. None means
+    /// the current local master key, giving the keys a new ciphertext under the same key.
+    pub local_master_key: Option<Vec<u8>>,
+    /// The write concern of the key updates; default that of the key vault collection.
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl RewrapManyDataKeyOptions {
+    /// Creates a new RewrapManyDataKeyOptions struct with default parameters.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl fmt::Debug for RewrapManyDataKeyOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RewrapManyDataKeyOptions")
+            .field("provider", &self.provider)
+            .field("local_master_key", &self.local_master_key.as_ref().map(|_| "<redacted>"))
+            .field("write_concern", &self.write_concern)
+            .finish()
+    }
+}
+
+/// How many data keys a rewrap matched and updated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RewrapManyDataKeyResult {
+    pub matched_count: i32,
+    pub modified_count: i32,
+}
+
+// The data keys of a key vault collection, decrypted as they are first used.
+struct KeyVault {
+    client: Client,
+    db: String,
+    coll: String,
+    kms_providers: KmsProviders,
+    keys: Mutex<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl fmt::Debug for KeyVault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyVault")
+            .field("client", &self.client)
+            .field("db", &self.db)
+            .field("coll", &self.coll)
+            .field("kms_providers", &self.kms_providers)
+            .field("keys", &self.keys.lock().recover().len())
+            .finish()
+    }
+}
+
+fn uuid_bytes(id: &Bson) -> Result<Vec<u8>> {
+    match *id {
+        Bson::Binary(BinarySubtype::Uuid, ref id) if id.len() == 16 => Ok(id.clone()),
+        _ => Err(ArgumentError(String::from("Data key ids must be UUIDs."))),
+    }
+}
+
+impl KeyVault {
+    fn new(client: Client, namespace: &str, kms_providers: KmsProviders) -> Result<KeyVault> {
+        let index = match namespace.find('.') {
+            Some(index) => index,
+            None => {
+                return Err(ArgumentError(
+                    format!("The key vault namespace {} has no collection name.", namespace),
+                ))
+            }
+        };
+
+        if let Some(ref key) = kms_providers.local {
+            if key.len() != KEY_LENGTH {
+                return Err(ArgumentError(
+                    format!("The local master key must be {} bytes long.", KEY_LENGTH),
+                ));
+            }
+        }
+
+        Ok(KeyVault {
+            client: client,
+            db: String::from(&namespace[..index]),
+            coll: String::from(&namespace[index + 1..]),
+            kms_providers: kms_providers,
+            keys: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn master_key(&self, provider: &str) -> Result<&[u8]> {
+        match (provider, self.kms_providers.local.as_ref()) {
+            ("local", Some(key)) => Ok(key),
+            ("local", None) => Err(EncryptionError(String::from("No local master key is set."))),
+            _ => Err(EncryptionError(
+                format!("The KMS provider {} is not supported.", provider),
+            )),
+        }
+    }
+
+    fn collection(&self) -> Collection {
+        self.client.db(&self.db).collection(&self.coll)
+    }
+
+    fn find_key(&self, filter: Document) -> Result<Document> {
+        match self.collection().find_one(Some(filter.clone()), None)? {
+            Some(key) => Ok(key),
+            None => Err(EncryptionError(format!("No data key matches {}.", filter))),
+        }
+    }
+
+    // Returns the UUID of the data key with an alternate name.
+    fn key_id_by_alt_name(&self, name: &str) -> Result<Vec<u8>> {
+        let key = self.find_key(doc! { "keyAltNames": name })?;
+        match key.get("_id") {
+            Some(id) => uuid_bytes(id),
+            None => Err(EncryptionError(String::from("The data key has no _id."))),
+        }
+    }
+
+    // Returns the decrypted material of a data key.
+    fn data_key(&self, id: &[u8]) -> Result<Vec<u8>> {
+        if let Some(key) = self.keys.lock().recover().get(id) {
+            return Ok(key.clone());
+        }
+
+        let key = self.find_key(doc! { "_id": Bson::Binary(BinarySubtype::Uuid, id.to_vec()) })?;
+        let material = self.unwrap_key(&key)?;
+        self.keys.lock().recover().insert(id.to_vec(), material.clone());
+        Ok(material)
+    }
+
+    // Decrypts the material of a data key document with its master key.
+    fn unwrap_key(&self, key: &Document) -> Result<Vec<u8>> {
+        let provider = match key.get("masterKey") {
+            Some(&Bson::Document(ref master_key)) => match master_key.get("provider") {
+                Some(&Bson::String(ref provider)) => provider.clone(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        let material = match key.get("keyMaterial") {
+            Some(&Bson::Binary(_, ref material)) => material,
+            _ => return Err(EncryptionError(String::from("The data key has no keyMaterial."))),
+        };
+
+        crypto::decrypt(self.master_key(&provider)?, &[], material)
+    }
+
+    fn encrypt(&self, key_id: &[u8], algorithm: Algorithm, value: Bson) -> Result<Bson> {
+        let kind = value.element_type();
+        let supported = match kind {
+            ElementType::NullValue | ElementType::Undefined | ElementType::MinKey |
+            ElementType::MaxKey => false,
+            ElementType::FloatingPoint | ElementType::EmbeddedDocument | ElementType::Array |
+            ElementType::Boolean | ElementType::JavaScriptCodeWithScope => {
+                algorithm == Algorithm::Random
+            }
+            #[cfg(feature = "decimal128")]
+            ElementType::Decimal128Bit => algorithm == Algorithm::Random,
+            // Values that are already encrypted are stored as they are.
+            ElementType::Binary => match value {
+                Bson::Binary(BinarySubtype::UserDefined(ENCRYPTED_SUBTYPE), _) => return Ok(value),
+                _ => true,
+            },
+            _ => true,
+        };
+        if !supported {
+            return Err(EncryptionError(format!(
+                "Values of type {:?} cannot be encrypted with {}.",
+                kind,
+                algorithm.name()
+            )));
+        }
+
+        let mut blob = Vec::with_capacity(BLOB_HEADER_LENGTH);
+        blob.push(match algorithm {
+            Algorithm::Deterministic => DETERMINISTIC_BLOB,
+            Algorithm::Random => RANDOM_BLOB,
+        });
+        blob.extend_from_slice(key_id);
+        blob.push(kind as u8);
+
+        let data_key = self.data_key(key_id)?;
+        let ciphertext = crypto::encrypt(
+            &data_key,
+            &blob,
+            &value_bytes(value)?,
+            algorithm == Algorithm::Deterministic,
+        )?;
+        blob.extend_from_slice(&ciphertext);
+        Ok(Bson::Binary(BinarySubtype::UserDefined(ENCRYPTED_SUBTYPE), blob))
+    }
+
+    fn decrypt(&self, blob: &[u8]) -> Result<Bson> {
+        if blob.len() <= BLOB_HEADER_LENGTH ||
+            (blob[0] != DETERMINISTIC_BLOB && blob[0] != RANDOM_BLOB)
+        {
+            return Err(EncryptionError(String::from("The encrypted value is malformed.")));
+        }
+
+        let (header, ciphertext) = blob.split_at(BLOB_HEADER_LENGTH);
+        let data_key = self.data_key(&header[1..17])?;
+        let plaintext = crypto::decrypt(&data_key, header, ciphertext)?;
+        value_from_bytes(header[17], &plaintext)
+    }
+
+    // Decrypts the encrypted values found anywhere in `value`.
+    fn decrypt_all(&self, value: &mut Bson) -> Result<()> {
+        let plaintext = match *value {
+            Bson::Binary(BinarySubtype::UserDefined(ENCRYPTED_SUBTYPE), ref blob) => {
+                self.decrypt(blob)?
+            }
+            Bson::Document(ref mut doc) => return self.decrypt_document(doc),
+            Bson::Array(ref mut values) => {
+                for value in values {
+                    self.decrypt_all(value)?;
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        *value = plaintext;
+        Ok(())
+    }
+
+    fn decrypt_document(&self, doc: &mut Document) -> Result<()> {
+        let keys: Vec<String> = doc.keys().cloned().collect();
+        for key in keys {
+            if let Some(value) = doc.get_mut(&key) {
+                self.decrypt_all(value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Returns the encoding of a value, without the type and name of the element holding it.
+fn value_bytes(value: Bson) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bson::encode_document(&mut bytes, &doc! { "": value })?;
+    // A length, the element type and an empty name come first, and a null byte last.
+    Ok(bytes[6..bytes.len() - 1].to_vec())
+}
+
+fn value_from_bytes(kind: u8, bytes: &[u8]) -> Result<Bson> {
+    let mut encoded = Vec::with_capacity(bytes.len() + 7);
+    encoded.extend_from_slice(&((bytes.len() + 7) as i32).to_le_bytes());
+    encoded.push(kind);
+    encoded.push(0);
+    encoded.extend_from_slice(bytes);
+    encoded.push(0);
+
+    let mut doc = bson::decode_document(&mut &encoded[..])?;
+    doc.remove("").ok_or_else(|| EncryptionError(String::from("The plaintext is empty.")))
+}
+
+/// Creates data keys, and encrypts and decrypts values explicitly.
+#[derive(Debug)]
+pub struct ClientEncryption {
+    vault: KeyVault,
+}
+
+impl ClientEncryption {
+    /// Creates a ClientEncryption keeping data keys in the `key_vault_namespace` collection,
+    /// as `db.coll`, through `key_vault_client`.
+    pub fn new(
+        key_vault_client: Client,
+        key_vault_namespace: &str,
+        kms_providers: KmsProviders,
+    ) -> Result<ClientEncryption> {
+        Ok(ClientEncryption {
+            vault: KeyVault::new(key_vault_client, key_vault_namespace, kms_providers)?,
+        })
+    }
+
+    /// Creates a data key encrypted with the master key of `kms_provider`, which must be
+    /// `local`, and inserts it into the key vault. Returns the UUID of the key.
+    pub fn create_data_key(&self, kms_provider: &str, key_alt_names: Vec<String>) -> Result<Bson> {
+        let master_key = self.vault.master_key(kms_provider)?;
+        let key_material = crypto::encrypt(
+            master_key,
+            &[],
+            &crypto::random_bytes(KEY_LENGTH)?,
+            false,
+        )?;
+
+        // A version 4 UUID, from random bytes.
+        let mut id = crypto::random_bytes(16)?;
+        id[6] = (id[6] & 0x0f) | 0x40;
+        id[8] = (id[8] & 0x3f) | 0x80;
+        let id = Bson::Binary(BinarySubtype::Uuid, id);
+
+        let now = Utc::now();
+        let mut key = doc! {
+            "_id": id.clone(),
+            "keyMaterial": Bson::Binary(BinarySubtype::Generic, key_material),
+            "creationDate": Bson::UtcDatetime(now),
+            "updateDate": Bson::UtcDatetime(now),
+            "status": 0,
+            "masterKey": { "provider": kms_provider },
+        };
+        if !key_alt_names.is_empty() {
+            let names = key_alt_names.into_iter().map(Bson::String).collect::<Vec<_>>();
+            key.insert("keyAltNames", names);
+        }
+
+        self.vault.collection().insert_one(key, None)?;
+        Ok(id)
+    }
+
+    /// Encrypts `value` under the data key named by `options`.
+    pub fn encrypt(&self, value: Bson, options: EncryptOptions) -> Result<Bson> {
+        let key_id = match (options.key_id, options.key_alt_name) {
+            (Some(ref key_id), None) => uuid_bytes(key_id)?,
+            (None, Some(ref name)) => self.vault.key_id_by_alt_name(name)?,
+            _ => {
+                return Err(ArgumentError(
+                    String::from("Exactly one of key_id and key_alt_name must be set."),
+                ))
+            }
+        };
+
+        self.vault.encrypt(&key_id, options.algorithm, value)
+    }
+
+    /// Decrypts a value returned by `encrypt`, or read from an encrypted field.
+    pub fn decrypt(&self, value: Bson) -> Result<Bson> {
+        match value {
+            Bson::Binary(BinarySubtype::UserDefined(ENCRYPTED_SUBTYPE), ref blob) => {
+                self.vault.decrypt(blob)
+            }
+            _ => Err(ArgumentError(String::from("The value is not encrypted."))),
+        }
+    }
+
+    /// Returns the data key with the UUID `id`, if any.
+    pub fn get_key(&self, id: &Bson) -> Result<Option<Document>> {
+        let filter = doc! { "_id": Bson::Binary(BinarySubtype::Uuid, uuid_bytes(id)?) };
+        self.vault.collection().find_one(Some(filter), None)
+    }
+
+    /// Returns the data key with the alternate name `name`, if any.
+    pub fn get_key_by_alt_name(&self, name: &str) -> Result<Option<Document>> {
+        self.vault.collection().find_one(Some(doc! { "keyAltNames": name }), None)
+    }
+
+    /// Returns every data key of the key vault.
+    pub fn get_keys(&self) -> Result<Cursor> {
+        self.vault.collection().find(None, None)
+    }
+
+    /// Deletes the data key with the UUID `id`. Values encrypted under it can no longer be
+    /// decrypted, except by clients that already read the key.
+    pub fn delete_key(&self, id: &Bson) -> Result<DeleteResult> {
+        let key_id = uuid_bytes(id)?;
+        self.vault.keys.lock().recover().remove(&key_id);
+        let filter = doc! { "_id": Bson::Binary(BinarySubtype::Uuid, key_id) };
+        self.vault.collection().delete_one(filter, None)
+    }
+
+    /// Adds `name` to the alternate names of the data key with the UUID `id`, returning the
+    /// key as it was before, or None if there is no such key.
+    pub fn add_key_alt_name(&self, id: &Bson, name: &str) -> Result<Option<Document>> {
+        let filter = doc! { "_id": Bson::Binary(BinarySubtype::Uuid, uuid_bytes(id)?) };
+        let update = doc! { "$addToSet": { "keyAltNames": name } };
+        self.vault.collection().find_one_and_update(filter, update, None)
+    }
+
+    /// Removes `name` from the alternate names of the data key with the UUID `id`, returning
+    /// the key as it was before, or None if there is no such key.
+    pub fn remove_key_alt_name(&self, id: &Bson, name: &str) -> Result<Option<Document>> {
+        let filter = doc! { "_id": Bson::Binary(BinarySubtype::Uuid, uuid_bytes(id)?) };
+        let update = doc! { "$pull": { "keyAltNames": name } };
+        self.vault.collection().find_one_and_update(filter, update, None)
+    }
+
+    /// Decrypts the data keys matching `filter` and encrypts them again under the master key
+    /// named by `options`, so that an old master key can be retired. Values encrypted under
+    /// the data keys are unaffected. Clients reading the keys afterwards need the new master
+    /// key; this `ClientEncryption` keeps its own.
+    pub fn rewrap_many_data_key(
+        &self,
+        filter: Option<Document>,
+        options: Option<RewrapManyDataKeyOptions>,
+    ) -> Result<RewrapManyDataKeyResult> {
+        let options = options.unwrap_or_default();
+        let provider = options.provider.unwrap_or_else(|| String::from("local"));
+        let master_key = match options.local_master_key {
+            Some(ref key) if provider == "local" && key.len() != KEY_LENGTH => {
+                return Err(ArgumentError(
+                    format!("The local master key must be {} bytes long.", KEY_LENGTH),
+                ))
+            }
+            Some(ref key) if provider == "local" => &key[..],
+            _ => self.vault.master_key(&provider)?,
+        };
+
+        // Every key is decrypted before any is updated, so that none is left half rotated
+        // by a key the current master key cannot decrypt.
+        let coll = self.vault.collection();
+        let mut rewrapped = Vec::new();
+        for key in coll.find(filter, None)? {
+            let key = key?;
+            let id = match key.get("_id") {
+                Some(id) => id.clone(),
+                None => continue,
+            };
+            let material = self.vault.unwrap_key(&key)?;
+            rewrapped.push((id, crypto::encrypt(master_key, &[], &material, false)?));
+        }
+
+        let mut result = RewrapManyDataKeyResult::default();
+        for (id, key_material) in rewrapped {
+            let update = doc! {
+                "$set": {
+                    "keyMaterial": Bson::Binary(BinarySubtype::Generic, key_material),
+                    "masterKey": { "provider": provider.clone() },
+                },
+                "$currentDate": { "updateDate": true },
+            };
+            let updated = coll.update_one(
+                doc! { "_id": id },
+                update,
+                Some(UpdateOptions {
+                    write_concern: options.write_concern.clone(),
+                    ..UpdateOptions::new()
+                }),
+            )?;
+            if let Some(exception) = updated.write_exception {
+                return Err(WriteError(exception));
+            }
+            result.matched_count += updated.matched_count;
+            result.modified_count += updated.modified_count;
+        }
+        Ok(result)
+    }
+}
+
+/// Encrypts the fields of commands its schemas cover, and decrypts every encrypted value in
+/// replies. Added at the end of the interceptor chain of clients with `auto_encryption`, so
+/// that other interceptors see commands and replies as the application does.
+#[derive(Debug)]
+pub(crate) struct AutoEncrypter {
+    vault: KeyVault,
+    schemas: HashMap<String, Schema>,
+    bypass: bool,
+}
+
+// Calls `f` with each document of the array under `key`.
+fn each_document<F>(command: &mut Document, key: &str, mut f: F) -> Result<()>
+where
+    F: FnMut(&mut Document) -> Result<()>,
+{
+    if let Some(&mut Bson::Array(ref mut docs)) = command.get_mut(key) {
+        for doc in docs {
+            if let Bson::Document(ref mut doc) = *doc {
+                f(doc)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn document_mut<'a>(doc: &'a mut Document, key: &str) -> Option<&'a mut Document> {
+    match doc.get_mut(key) {
+        Some(&mut Bson::Document(ref mut doc)) => Some(doc),
+        _ => None,
+    }
+}
+
+impl AutoEncrypter {
+    pub fn new(options: AutoEncryptionOptions, key_vault_client: Client) -> Result<AutoEncrypter> {
+        let mut schemas = HashMap::new();
+        for (namespace, schema) in &options.schema_map {
+            let schema = Schema::parse(schema)?;
+            if !schema.is_empty() {
+                schemas.insert(namespace.clone(), schema);
+            }
+        }
+
+        Ok(AutoEncrypter {
+            vault: KeyVault::new(
+                key_vault_client,
+                &options.key_vault_namespace,
+                options.kms_providers,
+            )?,
+            schemas: schemas,
+            bypass: options.bypass_auto_encryption,
+        })
+    }
+
+    /// Returns whether documents written to `namespace` have fields to encrypt.
+    pub fn encrypts(&self, namespace: &str) -> bool {
+        !self.bypass && self.schemas.contains_key(namespace)
+    }
+
+    /// Decrypts the encrypted values found anywhere in `doc`.
+    pub fn decrypt_document(&self, doc: &mut Document) -> Result<()> {
+        self.vault.decrypt_document(doc)
+    }
+
+    fn encrypt_command(&self, context: &CommandContext, command: &mut Document) -> Result<()> {
+        // Commands sent with a read preference, and legacy finds sent with modifiers, are
+        // wrapped in `$query`.
+        if let Some(inner) = document_mut(command, "$query") {
+            return self.encrypt_command(context, inner);
+        }
+
+        let mut encrypt = |field: &EncryptedField, value: Bson| {
+            self.vault.encrypt(&field.key_id, field.algorithm, value)
+        };
+
+        // The command of a legacy find is its filter.
+        if !context.namespace.ends_with(".$cmd") {
+            return match self.schemas.get(context.namespace) {
+                Some(schema) => schema.encrypt_filter(command, &mut encrypt),
+                None => Ok(()),
+            };
+        }
+
+        let (name, coll) = match command.iter().next() {
+            Some((name, &Bson::String(ref coll))) => (name.clone(), coll.clone()),
+            _ => return Ok(()),
+        };
+        let schema = match self.schemas.get(&format!("{}.{}", context.database_name, coll)) {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+
+        match &name[..] {
+            "insert" => each_document(command, "documents", |doc| {
+                schema.encrypt_document(doc, &mut encrypt)
+            }),
+            "update" => each_document(command, "updates", |update| {
+                if let Some(filter) = document_mut(update, "q") {
+                    schema.encrypt_filter(filter, &mut encrypt)?;
+                }
+                match update.get_mut("u") {
+                    Some(update) => schema.encrypt_update(update, &mut encrypt),
+                    None => Ok(()),
+                }
+            }),
+            "delete" => each_document(command, "deletes", |delete| match document_mut(delete, "q") {
+                Some(filter) => schema.encrypt_filter(filter, &mut encrypt),
+                None => Ok(()),
+            }),
+            "findAndModify" | "findandmodify" => {
+                if let Some(filter) = document_mut(command, "query") {
+                    schema.encrypt_filter(filter, &mut encrypt)?;
+                }
+                match command.get_mut("update") {
+                    Some(update) => schema.encrypt_update(update, &mut encrypt),
+                    None => Ok(()),
+                }
+            }
+            "find" => match document_mut(command, "filter") {
+                Some(filter) => schema.encrypt_filter(filter, &mut encrypt),
+                None => Ok(()),
+            },
+            "count" | "distinct" => match document_mut(command, "query") {
+                Some(filter) => schema.encrypt_filter(filter, &mut encrypt),
+                None => Ok(()),
+            },
+            "aggregate" => each_document(command, "pipeline", |stage| {
+                match document_mut(stage, "$match") {
+                    Some(filter) => schema.encrypt_filter(filter, &mut encrypt),
+                    None => Ok(()),
+                }
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CommandInterceptor for AutoEncrypter {
+    fn on_command(&self, context: &CommandContext, command: &mut Document) -> Result<()> {
+        if self.bypass {
+            return Ok(());
+        }
+        self.encrypt_command(context, command)
+    }
+
+    fn on_reply(&self, _: &CommandContext, reply: &mut Document) -> Result<()> {
+        self.decrypt_document(reply)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::oid;
+
+    #[test]
+    fn encrypts_explicitly_with_cached_keys() {
+        let client = Client::connect("localhost", 27017).unwrap();
+        let kms_providers = KmsProviders { local: Some(vec![1; KEY_LENGTH]) };
+        let encryption = ClientEncryption::new(client, "keys.vault", kms_providers).unwrap();
+
+        // Cached keys are used without reading the key vault.
+        let key_id = vec![3; 16];
+        encryption.vault.keys.lock().recover().insert(key_id.clone(), vec![5; KEY_LENGTH]);
+        let mut options = EncryptOptions::new(Algorithm::Deterministic);
+        options.key_id = Some(Bson::Binary(BinarySubtype::Uuid, key_id));
+
+        let value = Bson::String(String::from("078-05-1120"));
+        let deterministic = encryption.encrypt(value.clone(), options.clone()).unwrap();
+        assert_eq!(deterministic, encryption.encrypt(value.clone(), options.clone()).unwrap());
+        assert_eq!(value, encryption.decrypt(deterministic).unwrap());
+
+        options.algorithm = Algorithm::Random;
+        let random = encryption.encrypt(value.clone(), options.clone()).unwrap();
+        assert_ne!(random, encryption.encrypt(value.clone(), options.clone()).unwrap());
+        assert_eq!(value, encryption.decrypt(random).unwrap());
+
+        // Documents can only be encrypted with the random algorithm, and null never.
+        let doc = Bson::Document(doc! { "a": 1 });
+        assert!(encryption.encrypt(doc.clone(), options.clone()).is_ok());
+        assert!(encryption.encrypt(Bson::Null, options.clone()).is_err());
+        options.algorithm = Algorithm::Deterministic;
+        assert!(encryption.encrypt(doc, options.clone()).is_err());
+
+        options.key_alt_name = Some(String::from("both"));
+        assert!(encryption.encrypt(value.clone(), options).is_err());
+        assert!(encryption.decrypt(value).is_err());
+    }
+
+    #[test]
+    fn values_round_trip_through_their_encoding() {
+        let values = vec![
+            Bson::String(String::from("078-05-1120")),
+            Bson::I64(42),
+            Bson::ObjectId(oid::ObjectId::with_bytes([7; 12])),
+            Bson::Document(doc! { "street": "a", "number": 1 }),
+            Bson::Array(vec![Bson::I32(1), Bson::Boolean(true)]),
+        ];
+
+        for value in values {
+            let kind = value.element_type() as u8;
+            let bytes = value_bytes(value.clone()).unwrap();
+            assert_eq!(value, value_from_bytes(kind, &bytes).unwrap());
+        }
+    }
+}
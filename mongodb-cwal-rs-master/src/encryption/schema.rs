@@ -0,0 +1,444 @@
+//! The fields a JSON schema marks for encryption, and where they appear in commands.
+//!
+//! Schemas follow the subset of JSON schema understood by `mongocryptd`: nested `properties`,
+//! each field to encrypt holding an `encrypt` object, and `encryptMetadata` providing the
+//! `keyId` and `algorithm` of the fields below it that do not name their own.
+use bson::{self, Bson};
+use bson::spec::BinarySubtype;
+
+use super::Algorithm;
+use Error::ArgumentError;
+use Result;
+
+use std::collections::BTreeMap;
+use std::mem;
+
+/// How a field is encrypted.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct EncryptedField {
+    /// The UUID of the data key.
+    pub key_id: Vec<u8>,
+    pub algorithm: Algorithm,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Rule {
+    Encrypt(EncryptedField),
+    Object(Schema),
+}
+
+// What a dotted path names.
+enum Path<'a> {
+    Plain,
+    Encrypted(&'a EncryptedField),
+    Object(&'a Schema),
+    // A path below an encrypted field, whose value the server cannot see.
+    Inside,
+}
+
+// Replaces a field value with its encryption.
+pub(crate) type Encrypt<'a> = dyn FnMut(&EncryptedField, Bson) -> Result<Bson> + 'a;
+
+#[derive(Clone, Debug, Default)]
+struct Metadata {
+    key_id: Option<Vec<u8>>,
+    algorithm: Option<Algorithm>,
+}
+
+impl Metadata {
+    fn parse(doc: &bson::Document) -> Result<Metadata> {
+        let key_id = match doc.get("keyId") {
+            None => None,
+            Some(&Bson::Array(ref ids)) => match ids.as_slice() {
+                [Bson::Binary(BinarySubtype::Uuid, ref id)] if id.len() == 16 => Some(id.clone()),
+                _ => {
+                    return Err(ArgumentError(
+                        String::from("keyId must be an array holding a single UUID."),
+                    ))
+                }
+            },
+            Some(&Bson::String(_)) => {
+                return Err(ArgumentError(
+                    String::from("keyId JSON pointers to key alt names are not supported."),
+                ))
+            }
+            Some(_) => return Err(ArgumentError(String::from("keyId must be an array."))),
+        };
+
+        let algorithm = match doc.get("algorithm") {
+            None => None,
+            Some(&Bson::String(ref name)) => Some(Algorithm::from_name(name)?),
+            Some(_) => return Err(ArgumentError(String::from("algorithm must be a string."))),
+        };
+
+        Ok(Metadata {
+            key_id: key_id,
+            algorithm: algorithm,
+        })
+    }
+
+    // Returns these defaults, overridden by those of `metadata` that are set.
+    fn merge(&self, metadata: Metadata) -> Metadata {
+        Metadata {
+            key_id: metadata.key_id.or_else(|| self.key_id.clone()),
+            algorithm: metadata.algorithm.or(self.algorithm),
+        }
+    }
+}
+
+/// The fields of a collection's documents that are encrypted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Schema {
+    properties: BTreeMap<String, Rule>,
+}
+
+fn field_schema<'a>(name: &str, schema: &'a Bson) -> Result<&'a bson::Document> {
+    match *schema {
+        Bson::Document(ref schema) => Ok(schema),
+        _ => Err(ArgumentError(format!("The schema of {} must be a document.", name))),
+    }
+}
+
+fn is_operator_document(doc: &bson::Document) -> bool {
+    doc.keys().next().map_or(false, |key| key.starts_with('$'))
+}
+
+fn encrypt_in_place(field: &EncryptedField, value: &mut Bson, encrypt: &mut Encrypt) -> Result<()> {
+    let plaintext = mem::replace(value, Bson::Null);
+    *value = encrypt(field, plaintext)?;
+    Ok(())
+}
+
+// Encrypts a value a field is compared with, which only matches deterministic encryptions.
+fn encrypt_operand(
+    path: &str,
+    field: &EncryptedField,
+    value: &mut Bson,
+    encrypt: &mut Encrypt,
+) -> Result<()> {
+    if field.algorithm != Algorithm::Deterministic {
+        return Err(ArgumentError(format!(
+            "Cannot query {}, which is encrypted with the random algorithm.",
+            path
+        )));
+    }
+
+    encrypt_in_place(field, value, encrypt)
+}
+
+impl Schema {
+    /// Reads the encrypted fields of a JSON schema.
+    pub fn parse(schema: &bson::Document) -> Result<Schema> {
+        Schema::parse_with(schema, &Metadata::default())
+    }
+
+    fn parse_with(schema: &bson::Document, inherited: &Metadata) -> Result<Schema> {
+        if schema.contains_key("patternProperties") {
+            return Err(ArgumentError(
+                String::from("patternProperties are not supported in encryption schemas."),
+            ));
+        }
+
+        let metadata = match schema.get("encryptMetadata") {
+            None => inherited.clone(),
+            Some(&Bson::Document(ref metadata)) => inherited.merge(Metadata::parse(metadata)?),
+            Some(_) => {
+                return Err(ArgumentError(String::from("encryptMetadata must be a document.")))
+            }
+        };
+
+        let mut properties = BTreeMap::new();
+        let fields = match schema.get("properties") {
+            None => return Ok(Schema { properties: properties }),
+            Some(&Bson::Document(ref fields)) => fields,
+            Some(_) => return Err(ArgumentError(String::from("properties must be a document."))),
+        };
+
+        for (name, field) in fields {
+            let field = field_schema(name, field)?;
+
+            if let Some(encrypt) = field.get("encrypt") {
+                let encrypt = field_schema(name, encrypt)?;
+                let metadata = metadata.merge(Metadata::parse(encrypt)?);
+                let field = match (metadata.key_id, metadata.algorithm) {
+                    (Some(key_id), Some(algorithm)) => EncryptedField {
+                        key_id: key_id,
+                        algorithm: algorithm,
+                    },
+                    _ => {
+                        return Err(ArgumentError(format!(
+                            "The encrypted field {} needs a keyId and an algorithm.",
+                            name
+                        )))
+                    }
+                };
+                properties.insert(name.clone(), Rule::Encrypt(field));
+            } else {
+                let nested = Schema::parse_with(field, &metadata)?;
+                if !nested.properties.is_empty() {
+                    properties.insert(name.clone(), Rule::Object(nested));
+                }
+            }
+        }
+
+        Ok(Schema { properties: properties })
+    }
+
+    /// Returns whether the schema has no encrypted fields.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    fn lookup(&self, path: &str) -> Path<'_> {
+        let mut schema = self;
+        let mut parts = path.split('.').peekable();
+
+        while let Some(part) = parts.next() {
+            let last = parts.peek().is_none();
+            match schema.properties.get(part) {
+                None => return Path::Plain,
+                Some(&Rule::Encrypt(ref field)) if last => return Path::Encrypted(field),
+                Some(&Rule::Encrypt(_)) => return Path::Inside,
+                Some(&Rule::Object(ref nested)) if last => return Path::Object(nested),
+                Some(&Rule::Object(ref nested)) => schema = nested,
+            }
+        }
+
+        Path::Plain
+    }
+
+    /// Encrypts the fields of a document as it is stored.
+    pub fn encrypt_document(&self, doc: &mut bson::Document, encrypt: &mut Encrypt) -> Result<()> {
+        for (name, rule) in &self.properties {
+            match (rule, doc.get_mut(name)) {
+                (&Rule::Encrypt(ref field), Some(value)) => {
+                    encrypt_in_place(field, value, encrypt)?
+                }
+                (&Rule::Object(ref nested), Some(&mut Bson::Document(ref mut value))) => {
+                    nested.encrypt_document(value, encrypt)?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the values a query filter compares encrypted fields with.
+    pub fn encrypt_filter(&self, filter: &mut bson::Document, encrypt: &mut Encrypt) -> Result<()> {
+        let keys: Vec<String> = filter.keys().cloned().collect();
+        for key in keys {
+            let value = match filter.get_mut(&key) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match &key[..] {
+                "$and" | "$or" | "$nor" => {
+                    if let Bson::Array(ref mut clauses) = *value {
+                        for clause in clauses {
+                            if let Bson::Document(ref mut clause) = *clause {
+                                self.encrypt_filter(clause, encrypt)?;
+                            }
+                        }
+                    }
+                }
+                // Operators such as `$expr` are passed on as they are.
+                operator if operator.starts_with('$') => (),
+                path => self.encrypt_condition(path, value, encrypt)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn encrypt_condition(&self, path: &str, value: &mut Bson, encrypt: &mut Encrypt) -> Result<()> {
+        let field = match self.lookup(path) {
+            Path::Plain => return Ok(()),
+            Path::Inside => {
+                return Err(ArgumentError(
+                    format!("Cannot query below the encrypted field of {}.", path),
+                ))
+            }
+            Path::Object(nested) => {
+                return match *value {
+                    Bson::Document(ref doc) if is_operator_document(doc) => {
+                        Err(ArgumentError(format!(
+                            "Cannot query {} with operators, as it holds encrypted fields.",
+                            path
+                        )))
+                    }
+                    Bson::Document(ref mut doc) => nested.encrypt_document(doc, encrypt),
+                    _ => Ok(()),
+                };
+            }
+            Path::Encrypted(field) => field,
+        };
+
+        let operators = match *value {
+            Bson::Document(ref mut doc) if is_operator_document(doc) => doc,
+            _ => return encrypt_operand(path, field, value, encrypt),
+        };
+
+        let keys: Vec<String> = operators.keys().cloned().collect();
+        for operator in keys {
+            let operand = match operators.get_mut(&operator) {
+                Some(operand) => operand,
+                None => continue,
+            };
+
+            match (&operator[..], operand) {
+                ("$eq", operand) | ("$ne", operand) => {
+                    encrypt_operand(path, field, operand, encrypt)?
+                }
+                ("$in", &mut Bson::Array(ref mut operands)) |
+                ("$nin", &mut Bson::Array(ref mut operands)) => {
+                    for operand in operands {
+                        encrypt_operand(path, field, operand, encrypt)?;
+                    }
+                }
+                ("$exists", _) => (),
+                (operator, _) => {
+                    return Err(ArgumentError(
+                        format!("Cannot query the encrypted field {} with {}.", path, operator),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the fields set by an update, or the fields of a replacement document.
+    pub fn encrypt_update(&self, update: &mut Bson, encrypt: &mut Encrypt) -> Result<()> {
+        let update = match *update {
+            Bson::Document(ref mut update) if is_operator_document(update) => update,
+            Bson::Document(ref mut replacement) => {
+                return self.encrypt_document(replacement, encrypt)
+            }
+            Bson::Array(_) if !self.is_empty() => {
+                return Err(ArgumentError(String::from(
+                    "Update pipelines cannot be used on collections with encrypted fields.",
+                )))
+            }
+            _ => return Ok(()),
+        };
+
+        let operators: Vec<String> = update.keys().cloned().collect();
+        for operator in operators {
+            let fields = match update.get_mut(&operator) {
+                Some(&mut Bson::Document(ref mut fields)) => fields,
+                _ => continue,
+            };
+
+            let paths: Vec<String> = fields.keys().cloned().collect();
+            for path in paths {
+                let value = match fields.get_mut(&path) {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                let sets = operator == "$set" || operator == "$setOnInsert";
+                match self.lookup(&path) {
+                    Path::Plain => (),
+                    _ if operator == "$unset" => (),
+                    Path::Encrypted(field) if sets => encrypt_in_place(field, value, encrypt)?,
+                    Path::Object(nested) if sets => {
+                        if let Bson::Document(ref mut value) = *value {
+                            nested.encrypt_document(value, encrypt)?;
+                        }
+                    }
+                    _ => {
+                        return Err(ArgumentError(format!(
+                            "Cannot update the encrypted field {} with {}.",
+                            path,
+                            operator
+                        )))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    fn schema() -> Schema {
+        let key = Bson::Binary(BinarySubtype::Uuid, vec![1; 16]);
+        Schema::parse(&doc! {
+            "bsonType": "object",
+            "encryptMetadata": { "keyId": [key], "algorithm": Algorithm::Deterministic.name() },
+            "properties": {
+                "ssn": { "encrypt": { "bsonType": "string" } },
+                "notes": { "encrypt": { "algorithm": Algorithm::Random.name() } },
+                "address": { "properties": { "street": { "encrypt": {} } } },
+                "name": { "bsonType": "string" },
+            },
+        }).unwrap()
+    }
+
+    // Marks encrypted values instead of encrypting them.
+    fn mark(field: &EncryptedField, value: Bson) -> Result<Bson> {
+        let random = field.algorithm == Algorithm::Random;
+        Ok(Bson::Document(doc! { "encrypted": value, "random": random }))
+    }
+
+    #[test]
+    fn encrypts_documents_and_filters() {
+        let schema = schema();
+        let mut doc = doc! { "name": "a", "ssn": "123", "address": { "street": "b", "city": "c" } };
+        schema.encrypt_document(&mut doc, &mut mark).unwrap();
+        let expected = doc! {
+            "name": "a",
+            "ssn": { "encrypted": "123", "random": false },
+            "address": { "street": { "encrypted": "b", "random": false }, "city": "c" },
+        };
+        assert_eq!(expected, doc);
+
+        let mut filter = doc! {
+            "$or": [{ "ssn": "123" }, { "address.street": { "$in": ["b"] } }],
+            "name": { "$gt": "a" },
+        };
+        schema.encrypt_filter(&mut filter, &mut mark).unwrap();
+        let expected = doc! {
+            "$or": [
+                { "ssn": { "encrypted": "123", "random": false } },
+                { "address.street": { "$in": [{ "encrypted": "b", "random": false }] } },
+            ],
+            "name": { "$gt": "a" },
+        };
+        assert_eq!(expected, filter);
+
+        // Only equality with deterministically encrypted fields can be queried.
+        assert!(schema.encrypt_filter(&mut doc! { "notes": "x" }, &mut mark).is_err());
+        assert!(schema.encrypt_filter(&mut doc! { "ssn": { "$gt": "1" } }, &mut mark).is_err());
+        assert!(schema.encrypt_filter(&mut doc! { "ssn.x": 1 }, &mut mark).is_err());
+    }
+
+    #[test]
+    fn encrypts_updates() {
+        let schema = schema();
+        let mut update = Bson::Document(doc! {
+            "$set": { "notes": "n", "address": { "street": "s" } },
+            "$unset": { "ssn": "" },
+            "$inc": { "visits": 1 },
+        });
+        schema.encrypt_update(&mut update, &mut mark).unwrap();
+        let expected = Bson::Document(doc! {
+            "$set": {
+                "notes": { "encrypted": "n", "random": true },
+                "address": { "street": { "encrypted": "s", "random": false } },
+            },
+            "$unset": { "ssn": "" },
+            "$inc": { "visits": 1 },
+        });
+        assert_eq!(expected, update);
+
+        let mut increment = Bson::Document(doc! { "$inc": { "ssn": 1 } });
+        assert!(schema.encrypt_update(&mut increment, &mut mark).is_err());
+
+        let json_pointer = doc! { "properties": { "a": { "encrypt": { "keyId": "/name" } } } };
+        assert!(Schema::parse(&json_pointer).is_err());
+    }
+}
@@ -1,8 +1,11 @@
 //! MongoDB Errors and Error Codes.
 use bson::{self, oid};
+use coll::checkpoint::ScanInterruption;
 use coll::error::{WriteException, BulkWriteException};
 use data_encoding;
+use health::HealthReport;
 use std::{error, fmt, io, result, sync};
+use std::time::Duration;
 use trust_dns_resolver::error::ResolveError;
 
 /// A type for results generated by MongoDB related functions, where the Err type is
@@ -56,8 +59,17 @@ pub enum Error {
     OperationError(String),
     /// A database operation returned an invalid reply.
     ResponseError(String),
+    /// The server stopped an operation because it ran past its `max_time_ms`.
+    MaxTimeMSExpired(String),
     /// A cursor operation failed to return a cursor.
     CursorNotFoundError,
+    /// A getMore failed after it was sent to the server, so the cursor cannot be resumed
+    /// without risking skipped documents. Holds the cursor id, the number of documents the
+    /// cursor had already returned, and the underlying failure.
+    CursorInvalidatedError(i64, i32, Box<Error>),
+    /// A resumable scan lost its cursor on a sharded cluster; holds what is needed to
+    /// continue it through another `mongos`.
+    ScanInterruptedError(ScanInterruption),
     /// The application failed to secure a mutex due to a poisoned lock.
     PoisonLockError,
     /// A server error with a given code.
@@ -75,6 +87,42 @@ pub enum Error {
     DefaultError(String),
     /// Error related to DNS resolution
     DNSResolutionError(ResolveError),
+    /// A value could not be encrypted or decrypted, or a data key could not be found.
+    EncryptionError(String),
+    /// No server accepting writes became available in time; holds the topology as monitoring
+    /// last saw it.
+    PrimaryUnavailable(Box<HealthReport>),
+    /// An error raised while sending a command or reading its reply, with where and when it
+    /// happened; only returned by clients created with `ClientOptions::error_context` set.
+    Contextual(Box<ErrorContext>, Box<Error>),
+}
+
+/// Where and when a failed command was sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The name the command is monitored under, e.g. `find` or `insert_one`.
+    pub command_name: String,
+    /// The namespace the command was sent to, e.g. `shop.$cmd`.
+    pub namespace: String,
+    /// The address of the server, as `host:port`.
+    pub server: String,
+    pub request_id: i32,
+    /// How long after sending started the command failed.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} on {} to {} (request {}) after {:.3} ms",
+            self.command_name,
+            self.namespace,
+            self.server,
+            self.request_id,
+            self.elapsed.as_secs_f64() * 1000.0
+        )
+    }
 }
 
 impl<'a> From<Error> for io::Error {
@@ -162,7 +210,18 @@ impl fmt::Display for Error {
             Error::ArgumentError(ref inner) => inner.fmt(fmt),
             Error::OperationError(ref inner) => inner.fmt(fmt),
             Error::ResponseError(ref inner) => inner.fmt(fmt),
+            Error::MaxTimeMSExpired(ref inner) => inner.fmt(fmt),
             Error::CursorNotFoundError => fmt.write_str("No cursor found for cursor operation."),
+            Error::CursorInvalidatedError(id, count, ref err) => {
+                write!(
+                    fmt,
+                    "Cursor {} was invalidated after returning {} documents: {}",
+                    id,
+                    count,
+                    err
+                )
+            }
+            Error::ScanInterruptedError(ref inner) => inner.fmt(fmt),
             Error::PoisonLockError => fmt.write_str("Socket lock poisoned while attempting to access."),
             Error::CodedError(ref err) => write!(fmt, "{}", err),
             Error::EventListenerError(ref err) => {
@@ -180,6 +239,11 @@ impl fmt::Display for Error {
             Error::MaliciousServerError(ref err) => write!(fmt, "{}", err),
             Error::DefaultError(ref inner) => inner.fmt(fmt),
             Error::DNSResolutionError(ref inner) => inner.fmt(fmt),
+            Error::EncryptionError(ref inner) => inner.fmt(fmt),
+            Error::PrimaryUnavailable(ref health) => {
+                write!(fmt, "No writable primary available: {}", health)
+            }
+            Error::Contextual(ref context, ref inner) => write!(fmt, "{} ({})", inner, context),
         }
     }
 }
@@ -195,6 +259,8 @@ impl error::Error for Error {
             Error::FromHexError(ref inner) => inner.description(),
             Error::IoError(ref inner) => inner.description(),
             Error::CursorNotFoundError => "No cursor found for cursor operation.",
+            Error::CursorInvalidatedError(..) => "Cursor was invalidated while fetching more documents.",
+            Error::ScanInterruptedError(_) => "Resumable scan lost its cursor.",
             Error::PoisonLockError => "Socket lock poisoned while attempting to access.",
             Error::CodedError(ref err) => err.to_str(),
             Error::EventListenerError(ref err) => {
@@ -207,12 +273,17 @@ impl error::Error for Error {
             Error::ArgumentError(ref inner) |
             Error::OperationError(ref inner) |
             Error::ResponseError(ref inner) |
+            Error::MaxTimeMSExpired(ref inner) |
+            Error::EncryptionError(ref inner) |
             Error::DefaultError(ref inner) => inner,
             Error::DNSResolutionError(_) => "couldn't resolve DNS",
+            Error::PrimaryUnavailable(_) => "No writable primary available.",
+            #[allow(deprecated)]
+            Error::Contextual(_, ref inner) => inner.description(),
         }
     }
 
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             Error::WriteError(ref inner) => Some(inner),
             Error::BulkWriteError(ref inner) => Some(inner),
@@ -221,22 +292,150 @@ impl error::Error for Error {
             Error::OIDError(ref inner) => Some(inner),
             Error::FromHexError(ref inner) => Some(inner),
             Error::IoError(ref inner) => Some(inner),
+            Error::CursorInvalidatedError(_, _, ref inner) => Some(inner.as_ref()),
+            Error::ScanInterruptedError(ref inner) => Some(inner),
+            Error::EventListenerError(Some(ref inner)) => Some(inner.as_ref()),
+            Error::Contextual(_, ref inner) => Some(inner.as_ref()),
+            // Resolver errors implement `failure::Fail` rather than `std::error::Error`.
             Error::DNSResolutionError(_) |
             Error::ArgumentError(_) |
             Error::OperationError(_) |
             Error::ResponseError(_) |
+            Error::MaxTimeMSExpired(_) |
+            Error::EncryptionError(_) |
+            Error::PrimaryUnavailable(_) |
             Error::CursorNotFoundError |
             Error::PoisonLockError |
             Error::CodedError(_) |
-            Error::EventListenerError(_) |
+            Error::EventListenerError(None) |
             Error::MaliciousServerError(_) |
             Error::DefaultError(_) => None,
         }
     }
 }
 
+impl Error {
+    /// Returns the server error code behind this error, if the server reported a known one.
+    ///
+    /// Write failures report the code of their first write error, or else that of their write
+    /// concern error. Commands failing with an `OperationError` only keep the server's
+    /// message.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match *self {
+            Error::CodedError(code) => Some(code),
+            Error::MaxTimeMSExpired(_) => Some(ErrorCode::ExceededTimeLimit),
+            Error::WriteError(ref inner) => {
+                let code = match (&inner.write_error, &inner.write_concern_error) {
+                    (&Some(ref err), _) => err.code,
+                    (&None, &Some(ref err)) => err.code,
+                    (&None, &None) => return None,
+                };
+                ErrorCode::from_i32(code)
+            }
+            Error::BulkWriteError(ref inner) => {
+                let code = match (inner.write_errors.first(), &inner.write_concern_error) {
+                    (Some(err), _) => err.code,
+                    (None, &Some(ref err)) => err.code,
+                    (None, &None) => return None,
+                };
+                ErrorCode::from_i32(code)
+            }
+            Error::CursorInvalidatedError(_, _, ref inner) => inner.code(),
+            Error::EventListenerError(Some(ref inner)) => inner.code(),
+            Error::Contextual(_, ref inner) => inner.code(),
+            _ => None,
+        }
+    }
+
+    /// Returns where and when the failed command was sent, if the client attached it.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match *self {
+            Error::Contextual(ref context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without its context, e.g. to match on its variant.
+    pub fn without_context(&self) -> &Error {
+        match *self {
+            Error::Contextual(_, ref inner) => inner.without_context(),
+            _ => self,
+        }
+    }
+
+    /// Consumes the error, returning it without its context.
+    pub fn into_without_context(self) -> Error {
+        match self {
+            Error::Contextual(_, inner) => inner.into_without_context(),
+            err => err,
+        }
+    }
+
+    /// Returns whether a write violated a unique index.
+    pub fn is_duplicate_key(&self) -> bool {
+        self.code().map_or(false, |code| code.is_duplicate_key())
+    }
+
+    /// Returns whether the server ran past the operation's `max_time_ms`.
+    pub fn is_max_time_expired(&self) -> bool {
+        self.code().map_or(false, |code| code.is_max_time_expired())
+    }
+
+    /// Returns whether the server was not a writable primary, e.g. after a step-down.
+    pub fn is_not_writable_primary(&self) -> bool {
+        self.code().map_or(false, |code| code.is_not_writable_primary())
+    }
+
+    /// Returns whether the driver could not reach the server or lost the connection to it.
+    ///
+    /// A getMore that fails this way reports a `CursorInvalidatedError` instead, since the
+    /// cursor cannot be resumed.
+    pub fn is_network_error(&self) -> bool {
+        match *self.without_context() {
+            Error::IoError(_) | Error::DNSResolutionError(_) => true,
+            Error::CodedError(code) => code.is_network_error(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the server received the operation and reported its failure.
+    pub fn is_server_error(&self) -> bool {
+        match *self.without_context() {
+            Error::CodedError(code) => !code.is_network_error(),
+            Error::OperationError(_) |
+            Error::MaxTimeMSExpired(_) |
+            Error::CursorNotFoundError |
+            Error::WriteError(_) |
+            Error::BulkWriteError(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether a write that failed this way may be retried once, on the primary
+    /// selected anew: the connection failed, or the server stepped down or shut down.
+    pub fn is_retryable_write(&self) -> bool {
+        self.is_network_error() || self.code().map_or(false, |code| code.is_retryable_write())
+    }
+
+    /// Returns whether a read that failed this way may be retried once, on a server
+    /// selected anew.
+    pub fn is_retryable_read(&self) -> bool {
+        self.is_network_error() || self.code().map_or(false, |code| code.is_retryable_read())
+    }
+
+    /// Returns whether the operation may succeed if retried later without changes, e.g.
+    /// after a network error, an election, or a write conflict.
+    pub fn is_transient(&self) -> bool {
+        self.is_network_error() || self.code().map_or(false, |code| code.is_transient())
+    }
+}
+
+/// Error codes reported by the server, as found in the `code` field of replies.
+///
+/// New codes may be added in minor releases; `from_i32` returns `None` for codes not listed.
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum ErrorCode {
     OK = 0,
     InternalError = 1,
@@ -377,10 +576,13 @@ pub enum ErrorCode {
     IncompatibleShardingConfigVersion = 137,
     RemoteOplogStale = 138,
     JSInterpreterFailure = 139,
+    PrimarySteppedDown = 189,
+    SocketException = 9001,
     NotMaster = 10107,
     DuplicateKey = 11000,
     InterruptedAtShutdown = 11600,
     Interrupted = 11601,
+    InterruptedDueToReplStateChange = 11602,
     BackgroundOperationInProgressForDatabase = 12586,
     BackgroundOperationInProgressForNamespace = 12587,
     PrepareConfigsFailedCode = 13104,
@@ -395,9 +597,218 @@ pub enum ErrorCode {
 }
 
 impl ErrorCode {
+    /// Returns the variant for a numeric server error code, if it is a known one.
+    pub fn from_i32(code: i32) -> Option<ErrorCode> {
+        match code {
+            0 => Some(ErrorCode::OK),
+            1 => Some(ErrorCode::InternalError),
+            2 => Some(ErrorCode::BadValue),
+            3 => Some(ErrorCode::OBSOLETE_DuplicateKey),
+            4 => Some(ErrorCode::NoSuchKey),
+            5 => Some(ErrorCode::GraphContainsCycle),
+            6 => Some(ErrorCode::HostUnreachable),
+            7 => Some(ErrorCode::HostNotFound),
+            8 => Some(ErrorCode::UnknownError),
+            9 => Some(ErrorCode::FailedToParse),
+            10 => Some(ErrorCode::CannotMutateObject),
+            11 => Some(ErrorCode::UserNotFound),
+            12 => Some(ErrorCode::UnsupportedFormat),
+            13 => Some(ErrorCode::Unauthorized),
+            14 => Some(ErrorCode::TypeMismatch),
+            15 => Some(ErrorCode::Overflow),
+            16 => Some(ErrorCode::InvalidLength),
+            17 => Some(ErrorCode::ProtocolError),
+            18 => Some(ErrorCode::AuthenticationFailed),
+            19 => Some(ErrorCode::CannotReuseObject),
+            20 => Some(ErrorCode::IllegalOperation),
+            21 => Some(ErrorCode::EmptyArrayOperation),
+            22 => Some(ErrorCode::InvalidBSON),
+            23 => Some(ErrorCode::AlreadyInitialized),
+            24 => Some(ErrorCode::LockTimeout),
+            25 => Some(ErrorCode::RemoteValidationError),
+            26 => Some(ErrorCode::NamespaceNotFound),
+            27 => Some(ErrorCode::IndexNotFound),
+            28 => Some(ErrorCode::PathNotViable),
+            29 => Some(ErrorCode::NonExistentPath),
+            30 => Some(ErrorCode::InvalidPath),
+            31 => Some(ErrorCode::RoleNotFound),
+            32 => Some(ErrorCode::RolesNotRelated),
+            33 => Some(ErrorCode::PrivilegeNotFound),
+            34 => Some(ErrorCode::CannotBackfillArray),
+            35 => Some(ErrorCode::UserModificationFailed),
+            36 => Some(ErrorCode::RemoteChangeDetected),
+            37 => Some(ErrorCode::FileRenameFailed),
+            38 => Some(ErrorCode::FileNotOpen),
+            39 => Some(ErrorCode::FileStreamFailed),
+            40 => Some(ErrorCode::ConflictingUpdateOperators),
+            41 => Some(ErrorCode::FileAlreadyOpen),
+            42 => Some(ErrorCode::LogWriteFailed),
+            43 => Some(ErrorCode::CursorNotFound),
+            45 => Some(ErrorCode::UserDataInconsistent),
+            46 => Some(ErrorCode::LockBusy),
+            47 => Some(ErrorCode::NoMatchingDocument),
+            48 => Some(ErrorCode::NamespaceExists),
+            49 => Some(ErrorCode::InvalidRoleModification),
+            50 => Some(ErrorCode::ExceededTimeLimit),
+            51 => Some(ErrorCode::ManualInterventionRequired),
+            52 => Some(ErrorCode::DollarPrefixedFieldName),
+            53 => Some(ErrorCode::InvalidIdField),
+            54 => Some(ErrorCode::NotSingleValueField),
+            55 => Some(ErrorCode::InvalidDBRef),
+            56 => Some(ErrorCode::EmptyFieldName),
+            57 => Some(ErrorCode::DottedFieldName),
+            58 => Some(ErrorCode::RoleModificationFailed),
+            59 => Some(ErrorCode::CommandNotFound),
+            60 => Some(ErrorCode::DatabaseNotFound),
+            61 => Some(ErrorCode::ShardKeyNotFound),
+            62 => Some(ErrorCode::OplogOperationUnsupported),
+            63 => Some(ErrorCode::StaleShardVersion),
+            64 => Some(ErrorCode::WriteConcernFailed),
+            65 => Some(ErrorCode::MultipleErrorsOccurred),
+            66 => Some(ErrorCode::ImmutableField),
+            67 => Some(ErrorCode::CannotCreateIndex),
+            68 => Some(ErrorCode::IndexAlreadyExists),
+            69 => Some(ErrorCode::AuthSchemaIncompatible),
+            70 => Some(ErrorCode::ShardNotFound),
+            71 => Some(ErrorCode::ReplicaSetNotFound),
+            72 => Some(ErrorCode::InvalidOptions),
+            73 => Some(ErrorCode::InvalidNamespace),
+            74 => Some(ErrorCode::NodeNotFound),
+            75 => Some(ErrorCode::WriteConcernLegacyOK),
+            76 => Some(ErrorCode::NoReplicationEnabled),
+            77 => Some(ErrorCode::OperationIncomplete),
+            78 => Some(ErrorCode::CommandResultSchemaViolation),
+            79 => Some(ErrorCode::UnknownReplWriteConcern),
+            80 => Some(ErrorCode::RoleDataInconsistent),
+            81 => Some(ErrorCode::NoWhereParseContext),
+            82 => Some(ErrorCode::NoProgressMade),
+            83 => Some(ErrorCode::RemoteResultsUnavailable),
+            84 => Some(ErrorCode::DuplicateKeyValue),
+            85 => Some(ErrorCode::IndexOptionsConflict),
+            86 => Some(ErrorCode::IndexKeySpecsConflict),
+            87 => Some(ErrorCode::CannotSplit),
+            88 => Some(ErrorCode::SplitFailed),
+            89 => Some(ErrorCode::NetworkTimeout),
+            90 => Some(ErrorCode::CallbackCanceled),
+            91 => Some(ErrorCode::ShutdownInProgress),
+            92 => Some(ErrorCode::SecondaryAheadOfPrimary),
+            93 => Some(ErrorCode::InvalidReplicaSetConfig),
+            94 => Some(ErrorCode::NotYetInitialized),
+            95 => Some(ErrorCode::NotSecondary),
+            96 => Some(ErrorCode::OperationFailed),
+            97 => Some(ErrorCode::NoProjectionFound),
+            98 => Some(ErrorCode::DBPathInUse),
+            99 => Some(ErrorCode::WriteConcernNotDefined),
+            100 => Some(ErrorCode::CannotSatisfyWriteConcern),
+            101 => Some(ErrorCode::OutdatedClient),
+            102 => Some(ErrorCode::IncompatibleAuditMetadata),
+            103 => Some(ErrorCode::NewReplicaSetConfigurationIncompatible),
+            104 => Some(ErrorCode::NodeNotElectable),
+            105 => Some(ErrorCode::IncompatibleShardingMetadata),
+            106 => Some(ErrorCode::DistributedClockSkewed),
+            107 => Some(ErrorCode::LockFailed),
+            108 => Some(ErrorCode::InconsistentReplicaSetNames),
+            109 => Some(ErrorCode::ConfigurationInProgress),
+            110 => Some(ErrorCode::CannotInitializeNodeWithData),
+            111 => Some(ErrorCode::NotExactValueField),
+            112 => Some(ErrorCode::WriteConflict),
+            113 => Some(ErrorCode::InitialSyncFailure),
+            114 => Some(ErrorCode::InitialSyncOplogSourceMissing),
+            115 => Some(ErrorCode::CommandNotSupported),
+            116 => Some(ErrorCode::DocTooLargeForCapped),
+            117 => Some(ErrorCode::ConflictingOperationInProgress),
+            118 => Some(ErrorCode::NamespaceNotSharded),
+            119 => Some(ErrorCode::InvalidSyncSource),
+            120 => Some(ErrorCode::OplogStartMissing),
+            121 => Some(ErrorCode::DocumentValidationFailure),
+            122 => Some(ErrorCode::OBSOLETE_ReadAfterOptimeTimeout),
+            123 => Some(ErrorCode::NotAReplicaSet),
+            124 => Some(ErrorCode::IncompatibleElectionProtocol),
+            125 => Some(ErrorCode::CommandFailed),
+            126 => Some(ErrorCode::RPCProtocolNegotiationFailed),
+            127 => Some(ErrorCode::UnrecoverableRollbackError),
+            128 => Some(ErrorCode::LockNotFound),
+            129 => Some(ErrorCode::LockStateChangeFailed),
+            130 => Some(ErrorCode::SymbolNotFound),
+            131 => Some(ErrorCode::RLPInitializationFailed),
+            132 => Some(ErrorCode::ConfigServersInconsistent),
+            133 => Some(ErrorCode::FailedToSatisfyReadPreference),
+            134 => Some(ErrorCode::XXX_TEMP_NAME_ReadCommittedCurrentlyUnavailable),
+            135 => Some(ErrorCode::StaleTerm),
+            136 => Some(ErrorCode::CappedPositionLost),
+            137 => Some(ErrorCode::IncompatibleShardingConfigVersion),
+            138 => Some(ErrorCode::RemoteOplogStale),
+            139 => Some(ErrorCode::JSInterpreterFailure),
+            189 => Some(ErrorCode::PrimarySteppedDown),
+            9001 => Some(ErrorCode::SocketException),
+            10107 => Some(ErrorCode::NotMaster),
+            11000 => Some(ErrorCode::DuplicateKey),
+            11600 => Some(ErrorCode::InterruptedAtShutdown),
+            11601 => Some(ErrorCode::Interrupted),
+            11602 => Some(ErrorCode::InterruptedDueToReplStateChange),
+            12586 => Some(ErrorCode::BackgroundOperationInProgressForDatabase),
+            12587 => Some(ErrorCode::BackgroundOperationInProgressForNamespace),
+            13104 => Some(ErrorCode::PrepareConfigsFailedCode),
+            13297 => Some(ErrorCode::DatabaseDifferCase),
+            13334 => Some(ErrorCode::ShardKeyTooBig),
+            13388 => Some(ErrorCode::SendStaleConfig),
+            13435 => Some(ErrorCode::NotMasterNoSlaveOkCode),
+            13436 => Some(ErrorCode::NotMasterOrSecondaryCode),
+            14031 => Some(ErrorCode::OutOfDiskSpace),
+            17280 => Some(ErrorCode::KeyTooLong),
+            _ => None,
+        }
+    }
+
+    /// Returns the numeric server error code.
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Returns whether a write violated a unique index.
+    pub fn is_duplicate_key(&self) -> bool {
+        *self == ErrorCode::DuplicateKey
+    }
+
+    /// Returns whether the server ran past the operation's `max_time_ms`.
+    pub fn is_max_time_expired(&self) -> bool {
+        *self == ErrorCode::ExceededTimeLimit
+    }
+
+    /// Returns whether the server was not a writable primary, e.g. after a step-down.
+    pub fn is_not_writable_primary(&self) -> bool {
+        *self == ErrorCode::NotMaster || *self == ErrorCode::NotMasterNoSlaveOkCode ||
+            *self == ErrorCode::NotMasterOrSecondaryCode ||
+            *self == ErrorCode::PrimarySteppedDown ||
+            *self == ErrorCode::InterruptedDueToReplStateChange
+    }
+
+    /// Returns whether the server is shutting down.
+    pub fn is_shutdown(&self) -> bool {
+        *self == ErrorCode::ShutdownInProgress || *self == ErrorCode::InterruptedAtShutdown
+    }
+
     pub fn is_network_error(&self) -> bool {
         *self == ErrorCode::HostUnreachable || *self == ErrorCode::HostNotFound ||
-            *self == ErrorCode::NetworkTimeout
+            *self == ErrorCode::NetworkTimeout || *self == ErrorCode::SocketException
+    }
+
+    /// Returns whether a write failing with this code may be retried once on another primary.
+    pub fn is_retryable_write(&self) -> bool {
+        self.is_network_error() || self.is_not_writable_primary() || self.is_shutdown()
+    }
+
+    /// Returns whether a read failing with this code may be retried once on another server.
+    pub fn is_retryable_read(&self) -> bool {
+        self.is_retryable_write() ||
+            *self == ErrorCode::XXX_TEMP_NAME_ReadCommittedCurrentlyUnavailable
+    }
+
+    /// Returns whether the failure is expected to pass, such as a conflict with a concurrent
+    /// operation, so the operation may succeed if retried later.
+    pub fn is_transient(&self) -> bool {
+        self.is_retryable_read() || *self == ErrorCode::WriteConflict ||
+            *self == ErrorCode::LockTimeout || *self == ErrorCode::LockBusy
     }
 
     pub fn is_interruption(&self) -> bool {
@@ -556,10 +967,13 @@ impl ErrorCode {
             ErrorCode::IncompatibleShardingConfigVersion => "IncompatibleShardingConfigVersion",
             ErrorCode::RemoteOplogStale => "RemoteOplogStale",
             ErrorCode::JSInterpreterFailure => "JSInterpreterFailure",
+            ErrorCode::PrimarySteppedDown => "PrimarySteppedDown",
+            ErrorCode::SocketException => "SocketException",
             ErrorCode::NotMaster => "NotMaster",
             ErrorCode::DuplicateKey => "DuplicateKey",
             ErrorCode::InterruptedAtShutdown => "InterruptedAtShutdown",
             ErrorCode::Interrupted => "Interrupted",
+            ErrorCode::InterruptedDueToReplStateChange => "InterruptedDueToReplStateChange",
             ErrorCode::BackgroundOperationInProgressForDatabase => {
                 "BackgroundOperationInProgressForDatabase"
             }
@@ -584,3 +998,94 @@ impl fmt::Display for ErrorCode {
         fmt.write_str(self.to_str())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coll::error::{WriteConcernError, WriteError};
+    use common::WriteConcern;
+
+    #[test]
+    fn maps_server_error_codes() {
+        assert_eq!(Some(ErrorCode::DuplicateKey), ErrorCode::from_i32(11000));
+        assert_eq!(Some(ErrorCode::NotMaster), ErrorCode::from_i32(10107));
+        assert_eq!(None, ErrorCode::from_i32(123_456));
+        assert_eq!(91, ErrorCode::ShutdownInProgress.code());
+        assert!(ErrorCode::ShutdownInProgress.is_shutdown());
+
+        let duplicate = WriteException::new(None, Some(WriteError::new(11000, "E11000")));
+        assert!(Error::WriteError(duplicate).is_duplicate_key());
+
+        let concern = WriteConcernError::new(10107, WriteConcern::new(), "not master");
+        let not_primary = WriteException::new(Some(concern), None);
+        assert!(Error::WriteError(not_primary).is_not_writable_primary());
+
+        assert!(Error::MaxTimeMSExpired(String::from("too slow")).is_max_time_expired());
+        assert_eq!(None, Error::OperationError(String::from("failed")).code());
+    }
+
+    #[test]
+    fn classifies_retryable_errors() {
+        let reset = Error::IoError(io::Error::new(io::ErrorKind::ConnectionReset, "reset"));
+        assert!(reset.is_network_error() && !reset.is_server_error());
+        assert!(reset.is_retryable_write() && reset.is_retryable_read() && reset.is_transient());
+
+        let stepped_down = Error::CodedError(ErrorCode::NotMaster);
+        assert!(stepped_down.is_server_error() && !stepped_down.is_network_error());
+        assert!(stepped_down.is_retryable_write() && stepped_down.is_retryable_read());
+
+        let code = ErrorCode::XXX_TEMP_NAME_ReadCommittedCurrentlyUnavailable;
+        let unavailable = Error::CodedError(code);
+        assert!(unavailable.is_retryable_read() && !unavailable.is_retryable_write());
+
+        let conflict = Error::CodedError(ErrorCode::WriteConflict);
+        assert!(conflict.is_transient() && !conflict.is_retryable_write());
+
+        let duplicate = WriteException::new(None, Some(WriteError::new(11000, "E11000")));
+        let duplicate = Error::WriteError(duplicate);
+        assert!(duplicate.is_server_error() && !duplicate.is_transient());
+
+        let argument = Error::ArgumentError(String::from("bad"));
+        assert!(!argument.is_server_error() && !argument.is_retryable_read());
+
+        let invalidated = Error::CursorInvalidatedError(1, 0, Box::new(reset));
+        assert!(!invalidated.is_network_error() && !invalidated.is_retryable_read());
+    }
+
+    #[test]
+    fn sources_lead_to_the_underlying_error() {
+        use std::error::Error as StdError;
+
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset");
+        let err = Error::CursorInvalidatedError(7, 2, Box::new(Error::IoError(reset)));
+
+        let wrapped = err.source().expect("Expected the getMore failure.");
+        let root = wrapped.source().and_then(|source| source.downcast_ref::<io::Error>());
+        assert_eq!(Some(io::ErrorKind::ConnectionReset), root.map(|err| err.kind()));
+        assert!(Error::ArgumentError(String::from("bad")).source().is_none());
+    }
+
+    #[test]
+    fn contexts_describe_failed_commands() {
+        let context = ErrorContext {
+            command_name: String::from("insert_one"),
+            namespace: String::from("shop.$cmd"),
+            server: String::from("localhost:27017"),
+            request_id: 12,
+            elapsed: Duration::from_micros(1500),
+        };
+        let duplicate = WriteException::new(None, Some(WriteError::new(11000, "E11000")));
+        let inner = Error::WriteError(duplicate);
+        let err = Error::Contextual(Box::new(context.clone()), Box::new(inner));
+
+        assert_eq!(Some(&context), err.context());
+        assert!(err.is_duplicate_key());
+        assert!(err.to_string().ends_with(
+            "(insert_one on shop.$cmd to localhost:27017 (request 12) after 1.500 ms)"
+        ));
+        match err.into_without_context() {
+            Error::WriteError(_) => (),
+            other => panic!("Expected a write error, got {:?}", other),
+        }
+    }
+}
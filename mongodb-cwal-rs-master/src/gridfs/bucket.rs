@@ -0,0 +1,480 @@
+//! Uploads and downloads over a GridFS store, of whole files or through `std::io` streams.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use std::io::{self, Write};
+//! #
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! let bucket = client.db("reports").gridfs_bucket(None);
+//!
+//! let id = bucket.upload_from_bytes("monthly.pdf", b"%PDF-1.4 ...", None).unwrap();
+//!
+//! let mut contents = Vec::new();
+//! bucket.download_to_writer(id.clone(), &mut contents).unwrap();
+//!
+//! // Streams hold at most a few chunks in memory.
+//! let mut upload = bucket.open_upload_stream("copy.pdf", None).unwrap();
+//! let mut download = bucket.open_download_stream(id).unwrap();
+//! io::copy(&mut download, &mut upload).unwrap();
+//! let copy_id = upload.finish().unwrap();
+//! ```
+use bson::{self, doc, oid, Bson, UtcDateTime};
+
+use admin::deserialize_count;
+use coll::options::{FindOptions, IndexModel, IndexOptions};
+use coll::typed::TypedCursor;
+use db::Database;
+use Error::{ArgumentError, OperationError};
+use Result;
+
+use super::{Store, ThreadedStore};
+use super::file::{File, GfsFile, DEFAULT_CHUNK_SIZE};
+
+use md5::{Digest, Md5};
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Options for opening a GridFS bucket.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GridFsBucketOptions {
+    /// The prefix of the files and chunks collections; defaults to `fs`.
+    pub bucket_name: Option<String>,
+    /// The size in bytes of the chunks of uploaded files; defaults to 255 KiB.
+    pub chunk_size_bytes: Option<i32>,
+}
+
+impl GridFsBucketOptions {
+    pub fn new() -> GridFsBucketOptions {
+        Default::default()
+    }
+}
+
+/// Options for uploading a single file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GridFsUploadOptions {
+    /// The size in bytes of the chunks of the file; defaults to that of the bucket.
+    pub chunk_size_bytes: Option<i32>,
+}
+
+impl GridFsUploadOptions {
+    pub fn new() -> GridFsUploadOptions {
+        Default::default()
+    }
+}
+
+/// A GridFS store that uploads and downloads whole files, creating the indexes of the files
+/// and chunks collections before the first upload to an empty bucket.
+#[derive(Debug)]
+pub struct GridFsBucket {
+    store: Store,
+    chunk_size: i32,
+    indexes_checked: AtomicBool,
+}
+
+impl GridFsBucket {
+    /// Opens the bucket in `db`.
+    pub fn new(db: Database, options: Option<GridFsBucketOptions>) -> GridFsBucket {
+        let options = options.unwrap_or_default();
+        let prefix = options.bucket_name.unwrap_or_else(|| String::from("fs"));
+
+        GridFsBucket {
+            store: Store::with_prefix(db, prefix),
+            chunk_size: options.chunk_size_bytes.unwrap_or(DEFAULT_CHUNK_SIZE),
+            indexes_checked: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the underlying store, for finding and removing files.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Stores `bytes` as a new file named `filename` and returns its id.
+    pub fn upload_from_bytes(
+        &self,
+        filename: &str,
+        bytes: &[u8],
+        options: Option<GridFsUploadOptions>,
+    ) -> Result<oid::ObjectId> {
+        self.upload_from_reader(filename, &mut &bytes[..], options)
+    }
+
+    /// Stores everything `reader` returns as a new file named `filename` and returns its id.
+    pub fn upload_from_reader<R: Read>(
+        &self,
+        filename: &str,
+        reader: &mut R,
+        options: Option<GridFsUploadOptions>,
+    ) -> Result<oid::ObjectId> {
+        let mut stream = self.open_upload_stream(filename, options)?;
+
+        if let Err(err) = io::copy(reader, &mut stream) {
+            let _ = stream.abort();
+            return Err(err.into());
+        }
+
+        stream.finish()
+    }
+
+    /// Starts a new file named `filename` whose contents are written to the returned stream.
+    ///
+    /// The id of the file is known from the start, so it can be recorded to resume or abort
+    /// the upload should it be interrupted.
+    pub fn open_upload_stream(&self, filename: &str, options: Option<GridFsUploadOptions>)
+        -> Result<GridFsUploadStream> {
+        let chunk_size = self.upload_chunk_size(options)?;
+        self.ensure_indexes()?;
+
+        let mut file = self.store.create(String::from(filename))?;
+        file.chunk_size = chunk_size;
+
+        Ok(GridFsUploadStream {
+            store: self.store.clone(),
+            file: Some(file),
+        })
+    }
+
+    /// Continues the interrupted upload of the file with the given id, which must not have
+    /// been finished, from the end of its last complete chunk.
+    ///
+    /// Chunks after the first missing or short one are removed. The returned stream expects
+    /// the data from `GridFsUploadStream::position` on, and the chunk size must be the one
+    /// the upload started with.
+    pub fn resume_upload(
+        &self,
+        id: oid::ObjectId,
+        filename: &str,
+        options: Option<GridFsUploadOptions>,
+    ) -> Result<GridFsUploadStream> {
+        let chunk_size = self.upload_chunk_size(options)?;
+        self.check_unfinished(&id)?;
+        self.ensure_indexes()?;
+
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "n": 1 });
+
+        // Hash the stored chunks again, since the checksum covers the whole file.
+        let mut wsum = Md5::new();
+        let mut complete = 0;
+
+        for chunk in self.store.chunks.find(Some(doc! { "files_id": id.clone() }), Some(options))? {
+            let chunk = chunk?;
+
+            match (chunk.get("n").and_then(Bson::as_i32), chunk.get("data")) {
+                (Some(n), Some(&Bson::Binary(_, ref data)))
+                    if n == complete && data.len() == chunk_size as usize => {
+                    wsum.input(data);
+                    complete += 1;
+                }
+                _ => break,
+            }
+        }
+
+        self.store.chunks.delete_many(
+            doc! { "files_id": id.clone(), "n": { "$gte": complete } },
+            None,
+        )?;
+
+        let file = File::resumed(
+            self.store.clone(),
+            String::from(filename),
+            id,
+            chunk_size,
+            complete,
+            wsum,
+        );
+
+        Ok(GridFsUploadStream {
+            store: self.store.clone(),
+            file: Some(file),
+        })
+    }
+
+    /// Removes the chunks of the interrupted upload of the file with the given id, which must
+    /// not have been finished.
+    pub fn abort_upload(&self, id: oid::ObjectId) -> Result<()> {
+        self.check_unfinished(&id)?;
+        self.store.chunks.delete_many(doc! { "files_id": id }, None)?;
+        Ok(())
+    }
+
+    /// Opens the file with the given id for reading.
+    pub fn open_download_stream(&self, id: oid::ObjectId) -> Result<GridFsDownloadStream> {
+        Ok(GridFsDownloadStream { file: self.store.open_id(id)? })
+    }
+
+    /// Writes the contents of the file with the given id to `writer` and returns how many
+    /// bytes were written.
+    pub fn download_to_writer<W: Write>(&self, id: oid::ObjectId, writer: &mut W)
+        -> Result<u64> {
+        let mut stream = self.open_download_stream(id)?;
+        let written = io::copy(&mut stream, writer)?;
+        stream.close()?;
+        Ok(written)
+    }
+
+    /// Returns the files documents matching `filter`.
+    pub fn find(&self, filter: Option<bson::Document>, options: Option<FindOptions>)
+        -> Result<TypedCursor<GridFsFileInfo>> {
+        self.store.files.find_as(filter, options)
+    }
+
+    /// Removes the file with the given id: first its files document, so that it can no
+    /// longer be found or opened, then its chunks. Stray chunks are removed even if the files
+    /// document is already gone, which is reported as an error.
+    pub fn delete(&self, id: oid::ObjectId) -> Result<()> {
+        let deleted = self.store.files.delete_one(doc! { "_id": id.clone() }, None)?;
+        self.store.chunks.delete_many(doc! { "files_id": id.clone() }, None)?;
+
+        if deleted.deleted_count == 0 {
+            return Err(OperationError(format!("GridFS file {} does not exist.", id)));
+        }
+
+        Ok(())
+    }
+
+    /// Changes the filename of the file with the given id.
+    pub fn rename(&self, id: oid::ObjectId, new_name: &str) -> Result<()> {
+        let result = self.store.files.update_one(
+            doc! { "_id": id.clone() },
+            doc! { "$set": { "filename": new_name } },
+            None,
+        )?;
+
+        if result.matched_count == 0 {
+            return Err(OperationError(format!("GridFS file {} does not exist.", id)));
+        }
+
+        Ok(())
+    }
+
+    /// Drops the files and chunks collections, removing every file of the bucket.
+    pub fn drop(&self) -> Result<()> {
+        self.store.files.drop()?;
+        self.store.chunks.drop()?;
+
+        // The next upload recreates the indexes.
+        self.indexes_checked.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn upload_chunk_size(&self, options: Option<GridFsUploadOptions>) -> Result<i32> {
+        let chunk_size = options
+            .and_then(|options| options.chunk_size_bytes)
+            .unwrap_or(self.chunk_size);
+
+        if chunk_size <= 0 {
+            return Err(ArgumentError(format!("Invalid GridFS chunk size {}.", chunk_size)));
+        }
+
+        Ok(chunk_size)
+    }
+
+    fn check_unfinished(&self, id: &oid::ObjectId) -> Result<()> {
+        let mut options = FindOptions::new();
+        options.projection = Some(doc! { "_id": 1 });
+
+        match self.store.files.find_one(Some(doc! { "_id": id.clone() }), Some(options))? {
+            Some(_) => Err(ArgumentError(format!("The upload of GridFS file {} is finished.", id))),
+            None => Ok(()),
+        }
+    }
+
+    // Creates the indexes the GridFS specification requires, once per bucket, if the files
+    // collection is still empty; buckets with files are expected to have them already.
+    fn ensure_indexes(&self) -> Result<()> {
+        if self.indexes_checked.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut options = FindOptions::new();
+        options.projection = Some(doc! { "_id": 1 });
+
+        if self.store.files.find_one(None, Some(options))?.is_none() {
+            let by_name = IndexModel::new(doc! { "filename": 1, "uploadDate": 1 }, None);
+            self.store.files.create_indexes(vec![by_name])?;
+
+            let mut unique = IndexOptions::new();
+            unique.unique = Some(true);
+            let by_position = IndexModel::new(doc! { "files_id": 1, "n": 1 }, Some(unique));
+            self.store.chunks.create_indexes(vec![by_position])?;
+        }
+
+        self.indexes_checked.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// The files document of a file stored in a bucket.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GridFsFileInfo {
+    #[serde(rename="_id")]
+    pub id: oid::ObjectId,
+    /// The length of the file in bytes.
+    #[serde(deserialize_with="deserialize_count")]
+    pub length: i64,
+    /// The size in bytes of every chunk but the last.
+    #[serde(rename="chunkSize")]
+    pub chunk_size: i32,
+    #[serde(rename="uploadDate")]
+    pub upload_date: UtcDateTime,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(rename="contentType", default, skip_serializing_if="Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Whatever the uploader stored alongside the file.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub metadata: Option<Bson>,
+}
+
+/// A new file in a bucket, written chunk by chunk as the data arrives.
+///
+/// The file is only stored once `finish` succeeds; a stream dropped before that removes the
+/// chunks written so far, unless it was suspended. `flush` is a no-op, since every chunk but
+/// the last must be full.
+#[derive(Debug)]
+pub struct GridFsUploadStream {
+    store: Store,
+    // Taken by `finish`, `abort` and `suspend`.
+    file: Option<File>,
+}
+
+impl GridFsUploadStream {
+    /// Returns the id the file is stored under.
+    pub fn id(&self) -> &oid::ObjectId {
+        &self.file.as_ref().expect("upload stream already closed").id
+    }
+
+    /// Writes the last chunk and stores the file, returning its id.
+    pub fn finish(mut self) -> Result<oid::ObjectId> {
+        let mut file = self.file.take().expect("upload stream already closed");
+        let id = file.id.clone();
+
+        if let Err(err) = file.close() {
+            // Closing removes the chunks if one failed to insert, but not the files document.
+            let _ = self.store.remove_id(id);
+            return Err(err);
+        }
+
+        Ok(id)
+    }
+
+    /// Returns how many bytes of the file have been written, including those stored before a
+    /// resumed upload was interrupted.
+    pub fn position(&self) -> i64 {
+        self.file.as_ref().expect("upload stream already closed").len()
+    }
+
+    /// Stops the upload and removes the chunks written so far.
+    pub fn abort(mut self) -> Result<()> {
+        self.abort_file()
+    }
+
+    /// Stops the upload but keeps the chunks written so far, to resume it later with
+    /// `GridFsBucket::resume_upload`. Returns the id of the file.
+    pub fn suspend(mut self) -> Result<oid::ObjectId> {
+        let mut file = self.file.take().expect("upload stream already closed");
+        file.suspend()?;
+        Ok(file.id.clone())
+    }
+
+    fn abort_file(&mut self) -> Result<()> {
+        match self.file.take() {
+            Some(mut file) => {
+                let id = file.id.clone();
+                let _ = file.close();
+                self.store.remove_id(id)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for GridFsUploadStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file {
+            Some(ref mut file) => file.write(buf),
+            None => Err(io::Error::new(io::ErrorKind::Other, "upload stream already closed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for GridFsUploadStream {
+    fn drop(&mut self) {
+        let _ = self.abort_file();
+    }
+}
+
+/// A stored file, read chunk by chunk as the data is consumed.
+#[derive(Debug)]
+pub struct GridFsDownloadStream {
+    file: File,
+}
+
+impl GridFsDownloadStream {
+    /// Returns the files document, with the name, chunk size and metadata of the file.
+    pub fn info(&self) -> &GfsFile {
+        &self.file.doc
+    }
+
+    /// Returns the length of the file in bytes.
+    pub fn len(&self) -> i64 {
+        self.file.len()
+    }
+
+    /// Returns whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.file.is_empty()
+    }
+
+    /// Waits for pending chunk reads and closes the file.
+    pub fn close(mut self) -> Result<()> {
+        self.file.close()
+    }
+}
+
+impl Read for GridFsDownloadStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use coll::typed::from_document;
+
+    #[test]
+    fn decodes_files_documents() {
+        let id = oid::ObjectId::new().unwrap();
+        let uploaded = ::chrono::Utc::now();
+        let document = doc! {
+            "_id": id.clone(),
+            "length": 2500,
+            "chunkSize": 1024,
+            "uploadDate": Bson::UtcDatetime(uploaded),
+            "filename": "report.pdf",
+            "metadata": { "owner": "finance" },
+        };
+
+        let info: GridFsFileInfo = from_document(document).unwrap();
+        assert_eq!(id, info.id);
+        assert_eq!(2500, info.length);
+        assert_eq!(1024, info.chunk_size);
+        assert_eq!(uploaded.timestamp_millis(), info.upload_date.timestamp_millis());
+        assert_eq!(Some(String::from("report.pdf")), info.filename);
+        assert_eq!(None, info.md5);
+        assert!(info.aliases.is_empty());
+        assert_eq!(Some(Bson::Document(doc! { "owner": "finance" })), info.metadata);
+    }
+}
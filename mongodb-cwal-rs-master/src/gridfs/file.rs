@@ -150,6 +150,43 @@ impl File {
         }
     }
 
+    // A write stream continuing a file whose first `chunk_num` chunks are already stored and
+    // hashed into `wsum`.
+    pub(crate) fn resumed(
+        gfs: Store,
+        name: String,
+        id: oid::ObjectId,
+        chunk_size: i32,
+        chunk_num: i32,
+        wsum: Md5,
+    ) -> File {
+        let mut file = File::with_name(gfs, name, id, Mode::Write);
+        file.doc.chunk_size = chunk_size;
+        file.doc.len = chunk_num as i64 * chunk_size as i64;
+        file.chunk_num = chunk_num;
+        file.wsum = wsum;
+        file
+    }
+
+    // Waits for pending chunk inserts and closes a write stream without storing its file
+    // document or removing its chunks, so that the upload can be resumed. Buffered data short
+    // of a full chunk is discarded.
+    pub(crate) fn suspend(&mut self) -> Result<()> {
+        if self.mode != Mode::Write {
+            return Ok(());
+        }
+
+        let mut guard = self.mutex.lock()?;
+        while self.wpending.load(Ordering::SeqCst) > 0 {
+            guard = self.condvar.wait(guard)?;
+        }
+        drop(guard);
+
+        self.wbuf.clear();
+        self.mode = Mode::Closed;
+        Ok(())
+    }
+
     /// Returns the byte length of the file.
     pub fn len(&self) -> i64 {
         self.len
@@ -260,7 +297,8 @@ impl File {
             "data": (BinarySubtype::Generic, vec_buf)
         };
 
-        // Insert chunk asynchronously into the database.
+        // Insert chunk asynchronously into the database, within the writer's span.
+        let span = current_span!();
         let arc_gfs = self.gfs.clone();
         let arc_mutex = self.mutex.clone();
         let arc_wpending = self.wpending.clone();
@@ -268,6 +306,7 @@ impl File {
         let err = self.err.clone();
 
         thread::spawn(move || {
+            let _span = enter_captured!(span);
             let result = arc_gfs.chunks.insert_one(document, None);
 
             // Complete pending write
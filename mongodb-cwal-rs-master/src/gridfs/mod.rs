@@ -28,6 +28,7 @@
 //! let chunk_bytes = file.find_chunk(id, 5).unwrap();
 //! file.close().unwrap();
 //! ```
+pub mod bucket;
 pub mod file;
 
 use bson::{self, bson, doc, oid};
@@ -0,0 +1,240 @@
+//! Health of the connections a client has used, as reported by `connection_health`, and of
+//! its topology, as reported by `health`.
+//!
+//! Every command outcome is recorded: a network error, such as a socket closed by an idle
+//! timeout, marks the client broken until a later command succeeds. The r2d2 managers use
+//! this to discard broken clients instead of handing them back to the application.
+//!
+//! `health` only reads what monitoring and the connection pools already know, so it is
+//! cheap enough to back liveness and readiness probes; `ping_with_timeout` checks that a
+//! server actually answers.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ThreadedClient};
+//! #
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! // ...
+//! let health = client.connection_health();
+//! if health.broken {
+//!     println!("last network error: {:?}", health.last_error);
+//! }
+//!
+//! let ready = client.health().is_writable();
+//! # let _ = ready;
+//! ```
+use pool::PoolStats;
+use poison::Recover;
+use topology::TopologyType;
+use topology::server::ServerType;
+use Error;
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A snapshot of the health of a client's connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionHealth {
+    /// How long ago the client was created.
+    pub age: Duration,
+    /// How long ago a command last succeeded, if one has.
+    pub since_last_success: Option<Duration>,
+    /// The last network error, if one occurred.
+    pub last_error: Option<String>,
+    /// Whether a network error occurred after the last successful command.
+    pub broken: bool,
+}
+
+/// The state of one server of the topology, as reported by `ThreadedClient::health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerHealth {
+    /// The `host:port` address of the server.
+    pub address: String,
+    pub server_type: ServerType,
+    /// The round trip time of the last successful heartbeat, in milliseconds.
+    pub round_trip_time: Option<i64>,
+    /// The error of the last heartbeat, if it failed.
+    pub error: Option<String>,
+    /// The connections the pool for the server holds.
+    pub pool: PoolStats,
+}
+
+impl ServerHealth {
+    /// Returns whether the server accepts writes.
+    pub fn is_writable(&self) -> bool {
+        match self.server_type {
+            ServerType::Standalone | ServerType::Mongos | ServerType::RSPrimary => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the server holds data that can be read.
+    pub fn is_readable(&self) -> bool {
+        self.is_writable() || self.server_type == ServerType::RSSecondary
+    }
+}
+
+/// A summary of a client's topology and connection pools, as reported by
+/// `ThreadedClient::health`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub topology_type: TopologyType,
+    /// The known servers, by address.
+    pub servers: Vec<ServerHealth>,
+    /// The health of the connections the client has used.
+    pub connection: ConnectionHealth,
+}
+
+impl HealthReport {
+    /// Returns whether a server that accepts writes is known.
+    pub fn is_writable(&self) -> bool {
+        self.servers.iter().any(ServerHealth::is_writable)
+    }
+
+    /// Returns whether a server that can be read from is known.
+    pub fn is_readable(&self) -> bool {
+        self.servers.iter().any(ServerHealth::is_readable)
+    }
+
+    /// Returns whether every connection to every readable server is checked out, so that
+    /// operations wait for one to be returned.
+    pub fn is_saturated(&self) -> bool {
+        let mut readable = self.servers.iter().filter(|server| server.is_readable()).peekable();
+        readable.peek().is_some() && readable.all(|server| server.pool.is_saturated())
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:?} topology", self.topology_type)?;
+
+        for (i, server) in self.servers.iter().enumerate() {
+            let separator = if i == 0 { " of " } else { ", " };
+            write!(fmt, "{}{} ({:?}", separator, server.address, server.server_type)?;
+            if let Some(ref error) = server.error {
+                write!(fmt, ": {}", error)?;
+            }
+            fmt.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last_success: Option<Instant>,
+    last_error: Option<(Instant, String)>,
+}
+
+// Records the outcomes of a client's commands.
+#[derive(Debug)]
+pub(crate) struct HealthTracker {
+    created: Instant,
+    state: Mutex<State>,
+}
+
+impl HealthTracker {
+    pub(crate) fn new() -> HealthTracker {
+        HealthTracker {
+            created: Instant::now(),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub(crate) fn record_success(&self) {
+        self.state.lock().recover().last_success = Some(Instant::now());
+    }
+
+    // Records `err` if it is a network error; other errors say nothing about the connection.
+    pub(crate) fn record_failure(&self, err: &Error) {
+        if err.is_network_error() {
+            self.state.lock().recover().last_error = Some((Instant::now(), err.to_string()));
+        }
+    }
+
+    pub(crate) fn report(&self) -> ConnectionHealth {
+        let state = self.state.lock().recover();
+        let broken = match (state.last_error.as_ref(), state.last_success) {
+            (Some(&(failed, _)), Some(succeeded)) => failed >= succeeded,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        ConnectionHealth {
+            age: self.created.elapsed(),
+            since_last_success: state.last_success.map(|succeeded| succeeded.elapsed()),
+            last_error: state.last_error.as_ref().map(|&(_, ref message)| message.clone()),
+            broken: broken,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn network_errors_break_until_a_success() {
+        let tracker = HealthTracker::new();
+        assert!(!tracker.report().broken);
+        assert_eq!(None, tracker.report().since_last_success);
+
+        tracker.record_failure(&Error::ArgumentError(String::from("bad filter")));
+        assert!(!tracker.report().broken);
+        assert_eq!(None, tracker.report().last_error);
+
+        tracker.record_success();
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "connection reset");
+        tracker.record_failure(&Error::IoError(reset));
+        let health = tracker.report();
+        assert!(health.broken);
+        assert_eq!(Some(String::from("connection reset")), health.last_error);
+        assert!(health.since_last_success.is_some());
+
+        tracker.record_success();
+        let health = tracker.report();
+        assert!(!health.broken);
+        assert_eq!(Some(String::from("connection reset")), health.last_error);
+    }
+
+    fn server(address: &str, server_type: ServerType, open: usize, idle: usize) -> ServerHealth {
+        ServerHealth {
+            address: String::from(address),
+            server_type: server_type,
+            round_trip_time: Some(1),
+            error: None,
+            pool: PoolStats { size: 2, open: open, idle: idle },
+        }
+    }
+
+    #[test]
+    fn summarizes_servers() {
+        let mut report = HealthReport {
+            topology_type: TopologyType::ReplicaSetNoPrimary,
+            servers: vec![
+                server("a:27017", ServerType::RSSecondary, 2, 0),
+                server("b:27017", ServerType::Unknown, 0, 0),
+            ],
+            connection: HealthTracker::new().report(),
+        };
+        assert!(!report.is_writable());
+        assert!(report.is_readable());
+        assert!(report.is_saturated());
+
+        report.topology_type = TopologyType::ReplicaSetWithPrimary;
+        report.servers[1] = server("b:27017", ServerType::RSPrimary, 2, 1);
+        assert!(report.is_writable());
+        assert!(!report.is_saturated());
+
+        assert_eq!(
+            "ReplicaSetWithPrimary topology of a:27017 (RSSecondary), b:27017 (RSPrimary)",
+            report.to_string()
+        );
+
+        report.servers.clear();
+        assert!(!report.is_readable());
+        assert!(!report.is_saturated());
+    }
+}
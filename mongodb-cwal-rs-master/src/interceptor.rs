@@ -0,0 +1,231 @@
+//! Middleware that inspects and rewrites commands on their way to the server, and their replies
+//! on the way back.
+//!
+//! Interceptors are registered as an ordered chain on `ClientOptions`. Commands pass through
+//! the chain in the order the interceptors were added, and replies in the reverse order, so
+//! the first interceptor sees the command first and the reply last. Authentication and other
+//! suppressed commands, as well as getMores, bypass the chain.
+//!
+//! ```no_run
+//! # use mongodb::{Bson, Client, ClientOptions, Document, Result, ThreadedClient};
+//! # use mongodb::interceptor::{CommandContext, CommandInterceptor, Interceptors};
+//! # use std::sync::Arc;
+//! #
+//! struct TraceComment {
+//!     trace_id: String,
+//! }
+//!
+//! impl CommandInterceptor for TraceComment {
+//!     fn on_command(&self, context: &CommandContext, command: &mut Document) -> Result<()> {
+//!         if context.command_name == "find" || context.command_name == "aggregate" {
+//!             command.insert("comment", Bson::String(self.trace_id.clone()));
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut interceptors = Interceptors::new();
+//! interceptors.push(Arc::new(TraceComment { trace_id: String::from("4bf92f3577b34da6") }));
+//!
+//! let mut options = ClientOptions::new();
+//! options.interceptors = interceptors;
+//! let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+//! ```
+use bson::Document;
+use wire_protocol::operations::Message;
+use Result;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Describes the command an interceptor is called for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandContext<'a> {
+    /// The name the command is monitored under, e.g. `insert_one` or `find`.
+    pub command_name: &'a str,
+    /// The database the command runs on.
+    pub database_name: &'a str,
+    /// The namespace the command is sent to, e.g. `db.$cmd`, or `db.coll` for legacy finds.
+    pub namespace: &'a str,
+    /// The address of the server the command is sent to.
+    pub connection_string: &'a str,
+    pub request_id: i32,
+}
+
+/// Inspects or rewrites commands and replies. Each method does nothing unless implemented.
+///
+/// Interceptors are called on the thread running the operation, with the connection to the
+/// server checked out, so they should not block for long.
+pub trait CommandInterceptor: Send + Sync {
+    /// Called with each command before it is sent. For legacy finds, `command` is the
+    /// filter, wrapped in `$query` when sent alongside modifiers. Returning an error fails the
+    /// operation without sending the command.
+    fn on_command(&self, context: &CommandContext, command: &mut Document) -> Result<()> {
+        let _ = (context, command);
+        Ok(())
+    }
+
+    /// Called with each document of the reply before it is read: the command's reply, or
+    /// for legacy finds, each document of the first batch. Returning an error fails the operation
+    /// with that error.
+    fn on_reply(&self, context: &CommandContext, reply: &mut Document) -> Result<()> {
+        let _ = (context, reply);
+        Ok(())
+    }
+}
+
+/// The ordered chain of interceptors a client passes its commands through; by default, none.
+#[derive(Clone, Default)]
+pub struct Interceptors {
+    chain: Vec<Arc<dyn CommandInterceptor>>,
+}
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interceptors").field("len", &self.chain.len()).finish()
+    }
+}
+
+impl Interceptors {
+    /// Creates an empty chain.
+    pub fn new() -> Interceptors {
+        Interceptors::default()
+    }
+
+    /// Adds `interceptor` to the end of the chain.
+    pub fn push(&mut self, interceptor: Arc<dyn CommandInterceptor>) {
+        self.chain.push(interceptor);
+    }
+
+    /// Returns the number of interceptors in the chain.
+    pub fn len(&self) -> usize {
+        self.chain.len()
+    }
+
+    /// Returns whether the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    pub(crate) fn intercept_command(
+        &self,
+        context: &CommandContext,
+        command: &mut Document,
+    ) -> Result<()> {
+        for interceptor in &self.chain {
+            interceptor.on_command(context, command)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn intercept_reply(
+        &self,
+        context: &CommandContext,
+        reply: &mut Message,
+    ) -> Result<()> {
+        if let Message::OpReply { ref mut documents, .. } = *reply {
+            for interceptor in self.chain.iter().rev() {
+                for document in documents.iter_mut() {
+                    interceptor.on_reply(context, document)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{doc, Bson};
+    use std::sync::Mutex;
+    use wire_protocol::replay::Replay;
+    use Error;
+
+    struct Tag {
+        name: &'static str,
+        seen: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CommandInterceptor for Tag {
+        fn on_command(&self, _: &CommandContext, command: &mut Document) -> Result<()> {
+            self.seen.lock().unwrap().push(format!("command {}", self.name));
+            command.insert(self.name, true);
+            Ok(())
+        }
+
+        fn on_reply(&self, _: &CommandContext, reply: &mut Document) -> Result<()> {
+            self.seen.lock().unwrap().push(format!("reply {}", self.name));
+            reply.remove("name");
+            Ok(())
+        }
+    }
+
+    struct RejectSecondDocument;
+
+    impl CommandInterceptor for RejectSecondDocument {
+        fn on_reply(&self, _: &CommandContext, reply: &mut Document) -> Result<()> {
+            match reply.get("_id") {
+                Some(&Bson::I32(2)) => Err(Error::ArgumentError(String::from("rejected"))),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn context() -> CommandContext<'static> {
+        CommandContext {
+            command_name: "find",
+            database_name: "db",
+            namespace: "db.coll",
+            connection_string: "127.0.0.1:27017",
+            request_id: 1,
+        }
+    }
+
+    #[test]
+    fn runs_the_chain_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut interceptors = Interceptors::new();
+        interceptors.push(Arc::new(Tag { name: "first", seen: seen.clone() }));
+        interceptors.push(Arc::new(Tag { name: "second", seen: seen.clone() }));
+        assert_eq!(2, interceptors.len());
+
+        let mut command = doc! { "x": 1 };
+        interceptors.intercept_command(&context(), &mut command).unwrap();
+        assert_eq!(doc! { "x": 1, "first": true, "second": true }, command);
+
+        let mut message = Message::read(&mut Replay::recording("get_more_batch")).unwrap();
+        interceptors.intercept_reply(&context(), &mut message).unwrap();
+        match message {
+            Message::OpReply { ref documents, .. } => {
+                assert_eq!(vec![doc! { "_id": 1 }, doc! { "_id": 2 }], *documents);
+            }
+            _ => panic!("Expected a reply"),
+        }
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            vec![
+                "command first",
+                "command second",
+                "reply second",
+                "reply second",
+                "reply first",
+                "reply first",
+            ],
+            *seen
+        );
+    }
+
+    #[test]
+    fn fails_on_the_first_error() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut interceptors = Interceptors::new();
+        interceptors.push(Arc::new(Tag { name: "first", seen: seen.clone() }));
+        interceptors.push(Arc::new(RejectSecondDocument));
+
+        let mut message = Message::read(&mut Replay::recording("get_more_batch")).unwrap();
+        assert!(interceptors.intercept_reply(&context(), &mut message).is_err());
+        assert!(seen.lock().unwrap().is_empty());
+    }
+}
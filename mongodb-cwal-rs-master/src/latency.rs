@@ -0,0 +1,228 @@
+//! Latency histograms of the commands a client sends, by command and by server.
+//!
+//! Every command other than those authenticating connections is recorded from sending it to
+//! reading its reply, so services can report percentiles of driver latency without further
+//! instrumentation.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ThreadedClient};
+//! #
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! // ...
+//! let report = client.latency_report();
+//! if let Some(finds) = report.commands.get("find") {
+//!     println!("find p99: {:?} over {} commands", finds.p99, finds.count);
+//! }
+//! client.reset_latency_report();
+//! ```
+use poison::Recover;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Each doubling of latency is split into this many equal buckets, so recorded values are
+// within about 3% of the latency they stand for.
+const SUB_BUCKETS: u64 = 32;
+const SUB_BUCKET_BITS: u32 = 5;
+
+/// Latency percentiles of the commands recorded under a command name or server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    /// How many commands were recorded.
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// The latencies a client recorded since it was created or last reset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// Summaries by the name commands are monitored under, e.g. `find` or `insert_one`.
+    pub commands: BTreeMap<String, LatencySummary>,
+    /// Summaries by server address, as `host:port`.
+    pub servers: BTreeMap<String, LatencySummary>,
+}
+
+// Counts of latencies in microseconds, in log-linear buckets.
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+}
+
+// Returns the bucket holding `value`. Values below `SUB_BUCKETS` have a bucket each; above,
+// each doubling has `SUB_BUCKETS` buckets.
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+
+    let shift = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) - SUB_BUCKETS;
+    ((u64::from(shift) + 1) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+// Returns the highest value held by a bucket.
+fn bucket_value(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    let sub_bucket = index % SUB_BUCKETS + SUB_BUCKETS;
+    (sub_bucket << shift) + ((1 << shift) - 1)
+}
+
+impl Histogram {
+    fn record(&mut self, micros: u64) {
+        let index = bucket_index(micros);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+
+        if self.count == 0 || micros < self.min {
+            self.min = micros;
+        }
+        if micros > self.max {
+            self.max = micros;
+        }
+        self.count += 1;
+        self.sum = self.sum.saturating_add(micros);
+    }
+
+    // Returns the latency at or below which `quantile` of the recorded latencies fall.
+    fn value_at_quantile(&self, quantile: f64) -> u64 {
+        let target = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return bucket_value(index).max(self.min).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count,
+            min: Duration::from_micros(self.min),
+            max: Duration::from_micros(self.max),
+            mean: Duration::from_micros(self.sum / self.count.max(1)),
+            p50: Duration::from_micros(self.value_at_quantile(0.5)),
+            p95: Duration::from_micros(self.value_at_quantile(0.95)),
+            p99: Duration::from_micros(self.value_at_quantile(0.99)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histograms {
+    commands: HashMap<String, Histogram>,
+    servers: HashMap<String, Histogram>,
+}
+
+// Records the latencies of a client's commands.
+#[derive(Debug, Default)]
+pub(crate) struct LatencyTracker {
+    histograms: Mutex<Histograms>,
+}
+
+impl LatencyTracker {
+    pub(crate) fn new() -> LatencyTracker {
+        LatencyTracker::default()
+    }
+
+    // Records a command that took `nanos` nanoseconds.
+    pub(crate) fn record(&self, command_name: &str, server: &str, nanos: u64) {
+        let micros = nanos / 1000;
+        let mut histograms = self.histograms.lock().recover();
+
+        if let Some(histogram) = histograms.commands.get_mut(command_name) {
+            histogram.record(micros);
+        } else {
+            let mut histogram = Histogram::default();
+            histogram.record(micros);
+            histograms.commands.insert(String::from(command_name), histogram);
+        }
+
+        if let Some(histogram) = histograms.servers.get_mut(server) {
+            histogram.record(micros);
+        } else {
+            let mut histogram = Histogram::default();
+            histogram.record(micros);
+            histograms.servers.insert(String::from(server), histogram);
+        }
+    }
+
+    pub(crate) fn report(&self) -> LatencyReport {
+        let histograms = self.histograms.lock().recover();
+        LatencyReport {
+            commands: histograms.commands
+                .iter()
+                .map(|(name, histogram)| (name.clone(), histogram.summary()))
+                .collect(),
+            servers: histograms.servers
+                .iter()
+                .map(|(server, histogram)| (server.clone(), histogram.summary()))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        let mut histograms = self.histograms.lock().recover();
+        histograms.commands.clear();
+        histograms.servers.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets_keep_values_within_their_precision() {
+        for &value in &[0, 1, 31, 32, 63, 64, 1000, 123_456, 10_000_000, u64::max_value()] {
+            let index = bucket_index(value);
+            let highest = bucket_value(index);
+            assert!(highest >= value, "{} exceeds its bucket", value);
+            assert!(highest - value <= value / SUB_BUCKETS, "{} lost precision", value);
+            assert_eq!(index, bucket_index(highest));
+        }
+    }
+
+    #[test]
+    fn reports_percentiles() {
+        let tracker = LatencyTracker::new();
+        for millis in 1..101 {
+            let server = if millis % 2 == 0 { "a:27017" } else { "b:27017" };
+            tracker.record("find", server, millis * 1_000_000);
+        }
+        tracker.record("insert_one", "a:27017", 5_000_000);
+
+        let report = tracker.report();
+        let finds = report.commands["find"];
+        assert_eq!(100, finds.count);
+        assert_eq!(Duration::from_millis(1), finds.min);
+        assert_eq!(Duration::from_millis(100), finds.max);
+        assert!(finds.p50 >= Duration::from_millis(50) && finds.p50 < Duration::from_millis(52));
+        assert!(finds.p95 >= Duration::from_millis(95) && finds.p95 < Duration::from_millis(98));
+        assert!(finds.p99 >= Duration::from_millis(99) && finds.p99 <= finds.max);
+        assert_eq!(Duration::from_millis(5), report.commands["insert_one"].p99);
+        assert_eq!(51, report.servers["a:27017"].count);
+        assert_eq!(50, report.servers["b:27017"].count);
+
+        tracker.reset();
+        assert_eq!(LatencyReport::default(), tracker.report());
+    }
+}
@@ -86,6 +86,33 @@
 //!
 //! Each server within a MongoDB server set is maintained by the driver with a separate connection
 //! pool. By default, each pool has a maximum of 5 concurrent open connections.
+//!
+//! ## Logging
+//!
+//! The driver logs through the `log` crate, under one target per subsystem so that their
+//! verbosity can be set separately, e.g. with `RUST_LOG=mongodb::topology=debug` for
+//! `env_logger`:
+//!
+//! - `mongodb::pool` for connections being opened, authenticated, closed and drained.
+//! - `mongodb::topology` for server selection, heartbeat failures and topology changes.
+//! - `mongodb::command` for commands being sent, their durations and their failures. Command
+//!   documents are only logged at the trace level, and authentication commands never are.
+//!
+//! With the `tracing` feature, operations are also wrapped in `tracing` spans, nested under the
+//! caller's current span: `mongodb.operation` with the `namespace` and `command`, containing
+//! `mongodb.server_selection`, `mongodb.checkout` and `mongodb.command`, which also records the
+//! `server` and `request_id`.
+//!
+//! ## Decimal128
+//!
+//! Without the `decimal128` feature, documents holding Decimal128 values cannot be decoded.
+//! With it, decimals are read and written as `Bson::Decimal128`, and the `decimal` module
+//! parses and reads them.
+//!
+//! ## UUIDs
+//!
+//! With the `uuid` feature, the `uuid` module converts `Uuid`s to and from BSON binary
+//! subtype 4, and reads and writes the legacy subtype 3 in the byte orders of older drivers.
 
 // Clippy lints
 #![cfg_attr(feature = "clippy", feature(plugin))]
@@ -131,7 +158,7 @@ extern crate bufstream;
 extern crate byteorder;
 extern crate chrono;
 extern crate data_encoding;
-#[cfg(feature = "ssl")]
+#[cfg(any(feature = "ssl", feature = "encryption", feature = "openssl-crypto"))]
 extern crate openssl;
 extern crate rand;
 #[macro_use]
@@ -141,6 +168,7 @@ extern crate serde;
 #[macro_use(Serialize, Deserialize)]
 extern crate serde_derive;
 extern crate separator;
+extern crate serde_json;
 extern crate textnonce;
 extern crate time;
 extern crate md5;
@@ -148,55 +176,104 @@ extern crate sha1;
 extern crate hmac;
 extern crate pbkdf2;
 extern crate hex;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 extern crate trust_dns_resolver;
+#[cfg(feature = "uuid")]
+extern crate uuid as uuid_crate;
+
+#[macro_use]
+mod spans;
 
+pub mod admin;
 pub mod db;
 pub mod coll;
 pub mod common;
+pub mod config;
 pub mod connstring;
 pub mod cursor;
+pub mod datetime;
+#[cfg(feature = "decimal128")]
+pub mod decimal;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
 pub mod gridfs;
+pub mod health;
+pub mod interceptor;
+pub mod latency;
+pub mod metrics;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod object_id;
+pub mod oplog;
 pub mod pool;
 pub mod r2d2_mongo;
+pub mod rate_limit;
 pub mod stream;
 pub mod topology;
+#[cfg(feature = "uuid")]
+pub mod uuid;
 pub mod wire_protocol;
 
 mod apm;
 mod auth;
 mod command_type;
+mod crypto;
+mod poison;
 
 pub use bson::*;
 
-pub use apm::{CommandStarted, CommandResult};
+pub use apm::{CommandStarted, CommandResult, CredentialFallback, ServerHeartbeatFailed,
+              ServerHeartbeatStarted, ServerHeartbeatSucceeded};
 pub use command_type::CommandType;
 pub use error::{Error, ErrorCode, Result};
 
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::ops::DerefMut;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::sync::atomic::{AtomicIsize, Ordering};
+use std::thread;
 
+use admin::{ChunkInfo, CmdLineOpts, DatabaseInfo, FailPoint, FailPointMode, FsyncLock, HostInfo,
+            ListDatabasesOptions, ListSessionsOptions, ShardInfo, ShardedCollectionInfo};
 use apm::Listener;
-use common::{ReadPreference, ReadMode, WriteConcern};
+use coll::options::FindOptions;
+use common::{merge_options, Credential, ReadPreference, ReadMode, ServerApi, WriteConcern};
 use connstring::{ConnectionString, ConnectionProtocol};
+use cursor::{Cursor, CursorMemory, DEFAULT_CURSOR_MEMORY_WAIT};
 use db::{Database, ThreadedDatabase};
+#[cfg(feature = "encryption")]
+use encryption::{AutoEncrypter, AutoEncryptionOptions};
 use error::Error::ResponseError;
-use pool::PooledStream;
+use interceptor::Interceptors;
+use health::{ConnectionHealth, HealthReport, HealthTracker, ServerHealth};
+use latency::{LatencyReport, LatencyTracker};
+use metrics::Metrics;
+use object_id::ObjectIdGenerator;
+use pool::{CheckoutStrategy, ConnectionPool, FlushMode, PooledStream};
+use rate_limit::{RateLimit, RateLimiter};
+use poison::Recover;
 use stream::StreamConnector;
 use topology::{Topology, TopologyDescription, TopologyType, DEFAULT_HEARTBEAT_FREQUENCY_MS,
                DEFAULT_LOCAL_THRESHOLD_MS, DEFAULT_SERVER_SELECTION_TIMEOUT_MS};
-use topology::server::Server;
-use std::time::Duration;
+use topology::server::{Server, ServerDescription};
+use wire_protocol::flags::OpQueryFlags;
+use std::time::{Duration, Instant};
 
 pub const DRIVER_NAME: &str = "mongodb-cwal-rs";
 
 /// Interfaces with a MongoDB server or replica set.
 pub struct ClientInner {
-    /// Indicates how a server should be selected for read operations.
+    /// Indicates how a server should be selected for read operations, as configured when the
+    /// client was created. Databases opened after a `reconfigure` use
+    /// `current_read_preference` instead.
     pub read_preference: ReadPreference,
     /// Describes the guarantees provided by MongoDB when reporting the success of a write
     /// operation.
@@ -205,18 +282,99 @@ pub struct ClientInner {
     topology: Topology,
     listener: Listener,
     log_file: Option<Mutex<File>>,
+    // Cached listCollections results by namespace: the source of each known view, or None
+    // for namespaces known not to be views.
+    views: Arc<Mutex<HashMap<String, Option<String>>>>,
+    // The read preference for newly opened databases, as last set by `reconfigure`.
+    default_read_preference: RwLock<ReadPreference>,
+    // The batches buffered by this client's cursors, shared with clients on its topology.
+    cursor_memory: Arc<CursorMemory>,
+    rate_limiter: RateLimiter,
+    server_api: Option<ServerApi>,
+    metrics: Metrics,
+    interceptors: Interceptors,
+    latency: LatencyTracker,
+    health: HealthTracker,
+    object_ids: ObjectIdGenerator,
+    error_context: bool,
+    flush_mode: FlushMode,
+    #[cfg(feature = "encryption")]
+    auto_encrypter: Option<Arc<AutoEncrypter>>,
 }
 
 impl fmt::Debug for ClientInner {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("ClientInner")
+        let mut debug = f.debug_struct("ClientInner");
+        debug
             .field("read_preference", &self.read_preference)
             .field("write_concern", &self.write_concern)
             .field("req_id", &self.req_id)
             .field("topology", &self.topology)
             .field("listener", &"Listener { .. }")
             .field("log_file", &self.log_file)
-            .finish()
+            .field("views", &self.views)
+            .field("default_read_preference", &self.default_read_preference)
+            .field("cursor_memory", &self.cursor_memory)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("server_api", &self.server_api)
+            .field("metrics", &self.metrics)
+            .field("interceptors", &self.interceptors)
+            .field("latency", &self.latency)
+            .field("health", &self.health)
+            .field("object_ids", &self.object_ids)
+            .field("error_context", &self.error_context)
+            .field("flush_mode", &self.flush_mode);
+        #[cfg(feature = "encryption")]
+        debug.field("auto_encrypter", &self.auto_encrypter);
+        debug.finish()
+    }
+}
+
+impl ClientInner {
+    /// Returns the read preference used by databases opened without one.
+    pub fn current_read_preference(&self) -> ReadPreference {
+        match self.default_read_preference.read() {
+            Ok(read_preference) => read_preference.clone(),
+            Err(_) => self.read_preference.clone(),
+        }
+    }
+
+    // Whether documents written to `namespace` have fields the client encrypts, so that they
+    // must be sent through the interceptors.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn auto_encrypts(&self, namespace: &str) -> bool {
+        self.auto_encrypter.as_ref().map_or(false, |encrypter| encrypter.encrypts(namespace))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn auto_encrypts(&self, _: &str) -> bool {
+        false
+    }
+
+    // Whether replies may hold values the client decrypts, so that getMore batches must be
+    // decoded as they are read.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn decrypts_replies(&self) -> bool {
+        self.auto_encrypter.is_some()
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn decrypts_replies(&self) -> bool {
+        false
+    }
+
+    // Decrypts the encrypted values of a reply that bypassed the interceptors.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn decrypt_reply(&self, reply: &mut bson::Document) -> Result<()> {
+        match self.auto_encrypter {
+            Some(ref encrypter) => encrypter.decrypt_document(reply),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub(crate) fn decrypt_reply(&self, _: &mut bson::Document) -> Result<()> {
+        Ok(())
     }
 }
 
@@ -241,6 +399,49 @@ pub struct ClientOptions {
     pub local_threshold_ms: i64,
     /// Options for how to connect to the server.
     pub stream_connector: StreamConnector,
+    /// The credential being rotated out. Connections the server refuses to authenticate
+    /// with the credential from the connection string try this one instead.
+    pub previous_credential: Option<Credential>,
+    /// The most bytes of decoded batches the client's cursors may buffer together before
+    /// further `getMore` requests are delayed until buffered documents are consumed. Room
+    /// for a batch the size of a cursor's last one is reserved before its `getMore`. None
+    /// means no limit.
+    pub cursor_memory_limit: Option<usize>,
+    /// How long a `getMore` is delayed at most once `cursor_memory_limit` is reached, after
+    /// which it is sent over the limit; default 1 sec.
+    pub cursor_memory_wait: Option<Duration>,
+    /// Limits on how often operations may start, by namespace and operation type.
+    pub rate_limits: Vec<RateLimit>,
+    /// The Stable API version declared with every command, including the handshake and
+    /// monitoring of the servers.
+    pub server_api: Option<ServerApi>,
+    /// Where the client reports operation counts, command latencies and pool usage.
+    pub metrics: Metrics,
+    /// The chain of interceptors commands and their replies pass through, in order.
+    pub interceptors: Interceptors,
+    /// Generates the ids of inserted documents lacking an `_id`.
+    pub object_ids: ObjectIdGenerator,
+    /// Whether errors of failed commands are wrapped in `Error::Contextual`, naming the
+    /// command, namespace, server, request id and elapsed time; default false.
+    pub error_context: bool,
+    /// The size in bytes of the buffer replies are read through on each pooled connection;
+    /// default 8 KiB. Replies smaller than the buffer are read off the socket at once.
+    pub read_buffer_size: Option<usize>,
+    /// Whether pooled connections disable Nagle's algorithm, sending small messages at once
+    /// rather than waiting to coalesce them; default true. Throughput-oriented clients may
+    /// turn it off, usually together with `FlushMode::Coalesce`.
+    pub tcp_nodelay: Option<bool>,
+    /// When `killCursors` for dropped cursors and unacknowledged writes are sent; default
+    /// `FlushMode::Immediate`.
+    pub flush_mode: FlushMode,
+    /// Which idle connection each checkout takes from the pool; default
+    /// `CheckoutStrategy::Lifo`, which keeps as few connections busy as the load needs so
+    /// that the others are closed once idle for `idle_connection_timeout`.
+    pub checkout_strategy: Option<CheckoutStrategy>,
+    /// Encrypts the fields named by a schema map on their way to the server, and decrypts
+    /// encrypted values in replies; default none.
+    #[cfg(feature = "encryption")]
+    pub auto_encryption: Option<AutoEncryptionOptions>,
 }
 
 impl ClientOptions {
@@ -256,6 +457,21 @@ impl ClientOptions {
             server_selection_timeout_ms: DEFAULT_SERVER_SELECTION_TIMEOUT_MS,
             local_threshold_ms: DEFAULT_LOCAL_THRESHOLD_MS,
             stream_connector: StreamConnector::default(),
+            previous_credential: None,
+            cursor_memory_limit: None,
+            cursor_memory_wait: None,
+            rate_limits: Vec::new(),
+            server_api: None,
+            metrics: Metrics::default(),
+            interceptors: Interceptors::new(),
+            object_ids: ObjectIdGenerator::new(),
+            error_context: false,
+            read_buffer_size: None,
+            tcp_nodelay: None,
+            flush_mode: FlushMode::Immediate,
+            checkout_strategy: None,
+            #[cfg(feature = "encryption")]
+            auto_encryption: None,
         }
     }
 
@@ -289,6 +505,32 @@ impl ClientOptions {
     }
 }
 
+/// Options that can be changed on a running client with `reconfigure`.
+///
+/// Fields left as `None` keep their current value.
+#[derive(Default, Debug, Clone)]
+pub struct ClientOptionsDelta {
+    /// The maximum number of connections per server.
+    pub pool_size: Option<usize>,
+    /// How long pooled connections may sit unused before being closed.
+    pub idle_connection_timeout: Option<Duration>,
+    /// The frequency of server monitor updates.
+    pub heartbeat_frequency_ms: Option<u32>,
+    /// The timeout for selecting an appropriate server for operations.
+    pub server_selection_timeout_ms: Option<i64>,
+    /// The size of the latency window for selecting suitable servers.
+    pub local_threshold_ms: Option<i64>,
+    /// The read preference for databases opened without one.
+    pub read_preference: Option<ReadPreference>,
+}
+
+impl ClientOptionsDelta {
+    /// Creates a delta changing nothing.
+    pub fn new() -> ClientOptionsDelta {
+        Default::default()
+    }
+}
+
 pub trait ThreadedClient: Sync + Sized {
     /// Creates a new Client directly connected to a single MongoDB server.
     fn connect(host: &str, port: u16) -> Result<Self>;
@@ -307,6 +549,17 @@ pub trait ThreadedClient: Sync + Sized {
         options: Option<ClientOptions>,
         description: Option<TopologyDescription>,
     ) -> Result<Self>;
+    /// Creates a new Client attached to this client's topology, sharing its server monitors
+    /// and connection pools while using its own read preference, write concern and log file.
+    ///
+    /// The read preference, write concern, log file, rate limits, metrics, interceptors,
+    /// ObjectId generator, error context, flush mode and automatic encryption are taken from
+    /// `options`; the remaining options describe the shared topology and are ignored.
+    /// Connections, and therefore credentials, are shared with this client, and so is the
+    /// cursor memory limit. The shared connections are already authenticated and handshaken,
+    /// so this fails with an `ArgumentError` if `options` carries a credential or a server
+    /// API other than this client's; without a server API, this client's is used.
+    fn with_shared_topology(&self, options: Option<ClientOptions>) -> Result<Self>;
     /// Creates a database representation.
     fn db(&self, db_name: &str) -> Database;
     /// Creates a database representation with custom read and write controls.
@@ -324,14 +577,113 @@ pub trait ThreadedClient: Sync + Sized {
     fn get_req_id(&self) -> i32;
     /// Returns a list of all database names that exist on the server.
     fn database_names(&self) -> Result<Vec<String>>;
+    /// Returns the databases matching the filter, decoded into typed entries.
+    fn list_databases(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListDatabasesOptions>,
+    ) -> Result<Vec<DatabaseInfo>>;
     /// Drops the database defined by `db_name`.
     fn drop_database(&self, db_name: &str) -> Result<()>;
     /// Reports whether this instance is a primary, master, mongos, or standalone mongod instance.
     fn is_master(&self) -> Result<bool>;
+    /// Checks that the server responds, with a `ping` command and no further work.
+    fn ping(&self) -> Result<()>;
+    /// Sends a `ping` to a server selected with `read_preference`, or the primary, and
+    /// returns the round trip time. Fails if no server is selected or none answers within
+    /// `timeout`, which bounds both server selection and the round trip.
+    fn ping_with_timeout(
+        &self,
+        read_preference: Option<ReadPreference>,
+        timeout: Duration,
+    ) -> Result<Duration>;
+    /// Returns the command line and configuration file options the server was started with.
+    fn cmd_line_opts(&self) -> Result<CmdLineOpts>;
+    /// Returns information about the host the server is running on.
+    fn host_info(&self) -> Result<HostInfo>;
+    /// Flushes all pending writes to disk and blocks further writes until the returned guard
+    /// is released, so the data files can be backed up with a filesystem snapshot.
+    fn fsync_lock(&self) -> Result<FsyncLock>;
+    /// Releases one fsync lock and returns how many the server still holds.
+    fn fsync_unlock(&self) -> Result<i32>;
+    /// Lists the sessions recorded in the `config.system.sessions` collection.
+    fn list_sessions(&self, options: Option<ListSessionsOptions>) -> Result<Cursor>;
+    /// Lists the sessions cached in memory by the connected server.
+    fn list_local_sessions(&self, options: Option<ListSessionsOptions>) -> Result<Cursor>;
+    /// Lists the shards of the cluster, from `config.shards`.
+    fn list_shards(&self) -> Result<Vec<ShardInfo>>;
+    /// Lists the sharded collections, from `config.collections`, skipping dropped ones.
+    fn list_sharded_collections(&self) -> Result<Vec<ShardedCollectionInfo>>;
+    /// Returns the sharding metadata of the collection `namespace`, or None if it is not
+    /// sharded.
+    fn sharded_collection(&self, namespace: &str) -> Result<Option<ShardedCollectionInfo>>;
+    /// Lists the chunks of the sharded collection `namespace` by their lower bound, from
+    /// `config.chunks`.
+    fn list_chunks(&self, namespace: &str) -> Result<Vec<ChunkInfo>>;
+    /// Kills the sessions with the given `{ id: <UUID> }` identifiers.
+    fn kill_sessions(&self, ids: Vec<bson::Document>) -> Result<()>;
+    /// Kills every session matching one of the given `{ users: ... }` or `{ roles: ... }`
+    /// patterns. An empty list kills all sessions.
+    fn kill_all_sessions_by_pattern(&self, patterns: Vec<bson::Document>) -> Result<()>;
+    /// Configures a server-side failpoint on the primary, such as one built with
+    /// `FailPoint::fail_command`. The server must run with `enableTestCommands=1`.
+    fn enable_fail_point(&self, fail_point: FailPoint) -> Result<()>;
+    /// Disables the failpoint named `name`, e.g. `failCommand`.
+    fn disable_fail_point(&self, name: &str) -> Result<()>;
+    /// Stops checking out connections to the server at `address` (`host:port`) and closes
+    /// its connections as they are returned, waiting up to `deadline` for the checked-out
+    /// connections to come back. Returns how many were still checked out at the deadline.
+    fn drain_server(&self, address: &str, deadline: Duration) -> Result<usize>;
+    /// Allows connections to a server drained with `drain_server` again.
+    fn resume_server(&self, address: &str) -> Result<()>;
+    /// Returns what monitoring last learned about the server at `address` (`host:port`),
+    /// such as its type, version and read-only flag, without contacting the server.
+    fn server_info(&self, address: &str) -> Result<ServerDescription>;
+    /// Returns the decoded size in bytes of the batches buffered by the cursors of this
+    /// client and the clients sharing its topology.
+    fn buffered_cursor_bytes(&self) -> usize;
+    /// Returns the latency percentiles of the commands sent by this client, by command and
+    /// by server, since it was created or `reset_latency_report` was last called.
+    fn latency_report(&self) -> LatencyReport;
+    /// Forgets the latencies recorded so far.
+    fn reset_latency_report(&self);
+    /// Reports whether a network error occurred since the last successful command, along with
+    /// the age of the client.
+    fn connection_health(&self) -> ConnectionHealth;
+    /// Summarizes the topology and connection pools as monitoring last saw them, without
+    /// contacting the servers.
+    fn health(&self) -> HealthReport;
+    /// Blocks until a server that accepts writes is known, such as while a service starts or
+    /// a replica set elects a primary. Fails with `PrimaryUnavailable` after `timeout`.
+    fn await_primary(&self, timeout: Duration) -> Result<()>;
+    /// Applies the given option changes at runtime. Pool and monitoring changes apply to the
+    /// whole topology, including clients sharing it through `with_shared_topology`.
+    fn reconfigure(&self, delta: ClientOptionsDelta) -> Result<()>;
+    /// Starts a credential rotation: new connections authenticate with `credential`, and
+    /// with the credential used until now if the server rejects it. Existing connections stay
+    /// authenticated as they were.
+    fn rotate_credential(&self, credential: Credential) -> Result<()>;
+    /// Ends a credential rotation, so new connections no longer fall back to the previous
+    /// credential.
+    fn finish_credential_rotation(&self) -> Result<()>;
     /// Sets a function to be run every time a command starts.
     fn add_start_hook(&mut self, hook: fn(Client, &CommandStarted)) -> Result<()>;
     /// Sets a function to be run every time a command completes.
     fn add_completion_hook(&mut self, hook: fn(Client, &CommandResult)) -> Result<()>;
+    /// Sets a function to be run every time a connection authenticates with the previous
+    /// credential of a rotation.
+    fn add_credential_fallback_hook(&mut self, hook: fn(Client, &CredentialFallback))
+        -> Result<()>;
+    /// Sets a function to be run every time a server monitor sends a heartbeat.
+    fn add_heartbeat_started_hook(&mut self, hook: fn(Client, &ServerHeartbeatStarted))
+        -> Result<()>;
+    /// Sets a function to be run every time a server answers a heartbeat.
+    fn add_heartbeat_succeeded_hook(&mut self, hook: fn(Client, &ServerHeartbeatSucceeded))
+        -> Result<()>;
+    /// Sets a function to be run every time a heartbeat fails, whether the server could not be
+    /// reached or did not answer.
+    fn add_heartbeat_failed_hook(&mut self, hook: fn(Client, &ServerHeartbeatFailed))
+        -> Result<()>;
 }
 
 pub type Client = Arc<ClientInner>;
@@ -370,6 +722,12 @@ impl ThreadedClient for Client {
 
         let client_options = options.unwrap_or_else(ClientOptions::new);
 
+        if client_options.read_buffer_size == Some(0) {
+            return Err(Error::ArgumentError(String::from(
+                "The read buffer size must be greater than zero.",
+            )));
+        }
+
         let rp = client_options.read_preference.unwrap_or_else(|| {
             ReadPreference::new(ReadMode::Primary, None)
         });
@@ -378,19 +736,17 @@ impl ThreadedClient for Client {
         );
 
         let listener = Listener::new();
-        let file = match client_options.log_file {
-            Some(string) => {
-                let _ = listener.add_start_hook(log_command_started);
-                let _ = listener.add_completion_hook(log_command_completed);
-                Some(Mutex::new(
-                    OpenOptions::new()
-                        .write(true)
-                        .append(true)
-                        .create(true)
-                        .open(&string)?
-                ))
-            }
-            None => None,
+        let file = open_command_log(&listener, client_options.log_file)?;
+
+        let interceptors = client_options.interceptors;
+        #[cfg(feature = "encryption")]
+        let (interceptors, auto_encrypter) = {
+            let stream_connector = &client_options.stream_connector;
+            attach_auto_encrypter(interceptors, client_options.auto_encryption, || {
+                let mut key_vault_options = ClientOptions::new();
+                key_vault_options.stream_connector = stream_connector.clone();
+                Client::with_config(config.clone(), Some(key_vault_options), description.clone())
+            })?
         };
 
         let client = Arc::new(ClientInner {
@@ -401,18 +757,44 @@ impl ThreadedClient for Client {
                 client_options.stream_connector.clone(),
             )?,
             listener: listener,
-            read_preference: rp,
+            read_preference: rp.clone(),
             write_concern: wc,
             log_file: file,
+            views: Arc::new(Mutex::new(HashMap::new())),
+            default_read_preference: RwLock::new(rp),
+            cursor_memory: Arc::new(CursorMemory::new(
+                client_options.cursor_memory_limit,
+                client_options.cursor_memory_wait.unwrap_or(DEFAULT_CURSOR_MEMORY_WAIT),
+            )),
+            rate_limiter: RateLimiter::new(client_options.rate_limits)?,
+            server_api: client_options.server_api,
+            metrics: client_options.metrics,
+            interceptors: interceptors,
+            latency: LatencyTracker::new(),
+            health: HealthTracker::new(),
+            object_ids: client_options.object_ids,
+            error_context: client_options.error_context,
+            flush_mode: client_options.flush_mode,
+            #[cfg(feature = "encryption")]
+            auto_encrypter: auto_encrypter,
         });
 
+        if let Some(previous) = client_options.previous_credential {
+            client.topology.credentials.write().recover().previous = Some(previous);
+        }
+
         // Fill servers array and set options
         {
             let top_description = &client.topology.description;
-            let mut top = top_description.write()?;
+            let mut top = top_description.write().recover();
             top.heartbeat_frequency_ms = client_options.heartbeat_frequency_ms;
             top.server_selection_timeout_ms = client_options.server_selection_timeout_ms;
             top.local_threshold_ms = client_options.local_threshold_ms;
+            top.pool_size = client_options.pool_size;
+            top.idle_connection_timeout = client_options.idle_connection_timeout;
+            top.read_buffer_size = client_options.read_buffer_size;
+            top.tcp_nodelay = client_options.tcp_nodelay;
+            top.checkout_strategy = client_options.checkout_strategy;
 
             if let ConnectionProtocol::DNS(dns) = &mut config.hosts {
                 dns.discover_hosts()?;
@@ -427,6 +809,9 @@ impl ThreadedClient for Client {
                     client_options.stream_connector.clone(),
                     client_options.pool_size,
                     client_options.idle_connection_timeout,
+                    client_options.read_buffer_size,
+                    client_options.tcp_nodelay,
+                    client_options.checkout_strategy,
                 );
 
                 top.servers.insert(host, server);
@@ -436,6 +821,67 @@ impl ThreadedClient for Client {
         Ok(client)
     }
 
+    fn with_shared_topology(&self, options: Option<ClientOptions>) -> Result<Client> {
+        let client_options = options.unwrap_or_else(ClientOptions::new);
+
+        if client_options.previous_credential.is_some() {
+            return Err(Error::ArgumentError(String::from(
+                "Clients sharing a topology share its authenticated connections, so they \
+                 cannot use credentials of their own.",
+            )));
+        }
+
+        let server_api = match client_options.server_api {
+            Some(server_api) if Some(server_api) != self.server_api => {
+                return Err(Error::ArgumentError(String::from(
+                    "Clients sharing a topology share its handshaken connections, so they \
+                     cannot declare a server API of their own.",
+                )));
+            }
+            _ => self.server_api,
+        };
+
+        let rp = client_options.read_preference.unwrap_or_else(|| {
+            ReadPreference::new(ReadMode::Primary, None)
+        });
+        let wc = client_options.write_concern.unwrap_or_else(
+            WriteConcern::new,
+        );
+
+        let listener = Listener::new();
+        let file = open_command_log(&listener, client_options.log_file)?;
+
+        let interceptors = client_options.interceptors;
+        #[cfg(feature = "encryption")]
+        let (interceptors, auto_encrypter) =
+            attach_auto_encrypter(interceptors, client_options.auto_encryption, || {
+                self.with_shared_topology(None)
+            })?;
+
+        Ok(Arc::new(ClientInner {
+            req_id: self.req_id.clone(),
+            topology: self.topology.clone(),
+            listener: listener,
+            read_preference: rp.clone(),
+            write_concern: wc,
+            log_file: file,
+            views: self.views.clone(),
+            default_read_preference: RwLock::new(rp),
+            cursor_memory: self.cursor_memory.clone(),
+            rate_limiter: RateLimiter::new(client_options.rate_limits)?,
+            server_api: server_api,
+            metrics: client_options.metrics,
+            interceptors: interceptors,
+            latency: LatencyTracker::new(),
+            health: HealthTracker::new(),
+            object_ids: client_options.object_ids,
+            error_context: client_options.error_context,
+            flush_mode: client_options.flush_mode,
+            #[cfg(feature = "encryption")]
+            auto_encrypter: auto_encrypter,
+        }))
+    }
+
     fn db(&self, db_name: &str) -> Database {
         Database::open(self.clone(), db_name, None, None)
     }
@@ -491,6 +937,35 @@ impl ThreadedClient for Client {
         }
     }
 
+    fn list_databases(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListDatabasesOptions>,
+    ) -> Result<Vec<DatabaseInfo>> {
+        let mut doc = doc! { "listDatabases": 1 };
+        if let Some(filter) = filter {
+            doc.insert("filter", filter);
+        }
+        if let Some(options) = options {
+            doc = merge_options(doc, options);
+        }
+
+        let res = self.db("admin").command(doc, CommandType::ListDatabases, None)?;
+
+        match res.get("databases") {
+            Some(&Bson::Array(ref databases)) => databases
+                .iter()
+                .map(|database| match *database {
+                    Bson::Document(ref doc) => admin::decode_reply(doc.clone()),
+                    _ => Err(ResponseError(String::from("Database entry must be a document."))),
+                })
+                .collect(),
+            _ => Err(ResponseError(
+                String::from("Server reply does not contain 'databases'."),
+            )),
+        }
+    }
+
     fn drop_database(&self, db_name: &str) -> Result<()> {
         self.db(db_name).drop_database()
     }
@@ -508,6 +983,329 @@ impl ThreadedClient for Client {
         }
     }
 
+    fn ping(&self) -> Result<()> {
+        let doc = doc!{ "ping": 1 };
+        self.db("admin").command(doc, CommandType::Ping, None)?;
+        Ok(())
+    }
+
+    fn ping_with_timeout(
+        &self,
+        read_preference: Option<ReadPreference>,
+        timeout: Duration,
+    ) -> Result<Duration> {
+        let started = Instant::now();
+        let read_preference =
+            read_preference.unwrap_or_else(|| ReadPreference::new(ReadMode::Primary, None));
+        let (mut stream, slave_ok, _) =
+            self.topology.acquire_stream_within(self.clone(), read_preference.clone(), timeout)?;
+
+        // What is left of the timeout bounds the round trip; sockets reject a zero timeout.
+        let remaining = timeout.checked_sub(started.elapsed()).unwrap_or_default();
+        let remaining = cmp::max(remaining, Duration::from_millis(1));
+
+        let mut options = FindOptions::new();
+        options.limit = Some(1);
+        options.batch_size = Some(1);
+
+        let mut flags = OpQueryFlags::with_find_options(&options);
+        if slave_ok {
+            flags |= OpQueryFlags::SLAVE_OK;
+        }
+
+        let result = stream
+            .get_socket()
+            .get_ref()
+            .set_timeout(Some(remaining))
+            .map_err(Error::from)
+            .and_then(|_| {
+                Cursor::query_with_stream(
+                    &mut stream,
+                    self.clone(),
+                    String::from("admin.$cmd"),
+                    flags,
+                    doc!{ "ping": 1 },
+                    options,
+                    CommandType::Ping,
+                    false,
+                    Some(read_preference),
+                )
+            })
+            .and_then(|mut cursor| match cursor.next() {
+                Some(reply) => reply,
+                None => Err(ResponseError(String::from("No reply received to ping."))),
+            })
+            .and_then(db::check_command_reply)
+            .and_then(|_| stream.get_socket().get_ref().set_timeout(None).map_err(Error::from));
+
+        match result {
+            Ok(()) => Ok(started.elapsed()),
+            Err(err) => {
+                // A timed-out read leaves the reply unread on the socket.
+                stream.discard();
+                Err(err)
+            }
+        }
+    }
+
+    fn cmd_line_opts(&self) -> Result<CmdLineOpts> {
+        let doc = doc!{ "getCmdLineOpts": 1 };
+        let res = self.db("admin").command(doc, CommandType::GetCmdLineOpts, None)?;
+        admin::decode_reply(res)
+    }
+
+    fn host_info(&self) -> Result<HostInfo> {
+        let doc = doc!{ "hostInfo": 1 };
+        let res = self.db("admin").command(doc, CommandType::HostInfo, None)?;
+        admin::decode_reply(res)
+    }
+
+    fn fsync_lock(&self) -> Result<FsyncLock> {
+        let doc = doc!{ "fsync": 1, "lock": true };
+        let res = self.db("admin").command(doc, CommandType::FsyncLock, None)?;
+        Ok(FsyncLock::new(self.clone(), &res))
+    }
+
+    fn fsync_unlock(&self) -> Result<i32> {
+        let doc = doc!{ "fsyncUnlock": 1 };
+        let res = self.db("admin").command(doc, CommandType::FsyncUnlock, None)?;
+
+        match res.get("lockCount") {
+            Some(&Bson::I32(count)) => Ok(count),
+            Some(&Bson::I64(count)) => Ok(count as i32),
+            _ => Err(ResponseError(String::from("No lock count received from server."))),
+        }
+    }
+
+    fn list_sessions(&self, options: Option<ListSessionsOptions>) -> Result<Cursor> {
+        let stage: bson::Document = options.unwrap_or_else(ListSessionsOptions::new).into();
+        let pipeline = vec![doc! { "$listSessions": stage }];
+        self.db("config").collection("system.sessions").aggregate(pipeline, None)
+    }
+
+    fn list_local_sessions(&self, options: Option<ListSessionsOptions>) -> Result<Cursor> {
+        let stage: bson::Document = options.unwrap_or_else(ListSessionsOptions::new).into();
+        let spec = doc! {
+            "aggregate": 1,
+            "pipeline": [{ "$listLocalSessions": stage }],
+            "cursor": {},
+        };
+
+        let db = self.db("admin");
+        let read_preference = db.read_preference.clone();
+        db.command_cursor(spec, CommandType::ListSessions, read_preference)
+    }
+
+    fn list_shards(&self) -> Result<Vec<ShardInfo>> {
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "_id": 1 });
+
+        config_db(self)
+            .collection("shards")
+            .find(None, Some(options))?
+            .map(|doc| doc.and_then(admin::decode_reply))
+            .collect()
+    }
+
+    fn list_sharded_collections(&self) -> Result<Vec<ShardedCollectionInfo>> {
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "_id": 1 });
+
+        config_db(self)
+            .collection("collections")
+            .find(Some(doc! { "dropped": { "$ne": true } }), Some(options))?
+            .map(|doc| doc.and_then(admin::decode_reply))
+            .collect()
+    }
+
+    fn sharded_collection(&self, namespace: &str) -> Result<Option<ShardedCollectionInfo>> {
+        let filter = doc! { "_id": namespace, "dropped": { "$ne": true } };
+
+        match config_db(self).collection("collections").find_one(Some(filter), None)? {
+            Some(doc) => admin::decode_reply(doc).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn list_chunks(&self, namespace: &str) -> Result<Vec<ChunkInfo>> {
+        let collection = match self.sharded_collection(namespace)? {
+            Some(collection) => collection,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut options = FindOptions::new();
+        options.sort = Some(doc! { "min": 1 });
+
+        config_db(self)
+            .collection("chunks")
+            .find(Some(collection.chunk_filter()), Some(options))?
+            .map(|doc| doc.and_then(admin::decode_reply))
+            .collect()
+    }
+
+    fn kill_sessions(&self, ids: Vec<bson::Document>) -> Result<()> {
+        let ids: Vec<_> = ids.into_iter().map(Bson::Document).collect();
+        let doc = doc!{ "killSessions": ids };
+        self.db("admin").command(doc, CommandType::KillSessions, None).map(drop)
+    }
+
+    fn kill_all_sessions_by_pattern(&self, patterns: Vec<bson::Document>) -> Result<()> {
+        let patterns: Vec<_> = patterns.into_iter().map(Bson::Document).collect();
+        let doc = doc!{ "killAllSessionsByPattern": patterns };
+        self.db("admin").command(doc, CommandType::KillSessions, None).map(drop)
+    }
+
+    fn enable_fail_point(&self, fail_point: FailPoint) -> Result<()> {
+        // Servers without test commands answer with CommandNotFound, which `command` ignores.
+        let doc = bson::Document::from(fail_point);
+        let reply = self.db("admin").command(doc, CommandType::ConfigureFailPoint, None)?;
+        db::check_command_reply(reply).map(drop)
+    }
+
+    fn disable_fail_point(&self, name: &str) -> Result<()> {
+        self.enable_fail_point(FailPoint::new(name, FailPointMode::Off))
+    }
+
+    fn drain_server(&self, address: &str, deadline: Duration) -> Result<usize> {
+        // Release the topology lock before waiting, so monitors can keep updating it.
+        let pool = server_pool(self, address)?;
+        pool.drain(deadline)
+    }
+
+    fn resume_server(&self, address: &str) -> Result<()> {
+        server_pool(self, address)?.resume()
+    }
+
+    fn server_info(&self, address: &str) -> Result<ServerDescription> {
+        with_server(self, address, |server| server.description.read().recover().clone())
+    }
+
+    fn buffered_cursor_bytes(&self) -> usize {
+        self.cursor_memory.used()
+    }
+
+    fn latency_report(&self) -> LatencyReport {
+        self.latency.report()
+    }
+
+    fn reset_latency_report(&self) {
+        self.latency.reset()
+    }
+
+    fn connection_health(&self) -> ConnectionHealth {
+        self.health.report()
+    }
+
+    fn health(&self) -> HealthReport {
+        let description = self.topology.description.read().recover();
+        let mut servers: Vec<_> = description
+            .servers
+            .iter()
+            .map(|(host, server)| {
+                let server_description = server.description.read().recover();
+                ServerHealth {
+                    address: format!("{}:{}", host.host_name, host.port),
+                    server_type: server_description.server_type,
+                    round_trip_time: server_description.round_trip_time,
+                    error: server_description.err.as_ref().as_ref().map(Error::to_string),
+                    pool: server.pool().stats(),
+                }
+            })
+            .collect();
+        servers.sort_by(|a, b| a.address.cmp(&b.address));
+
+        HealthReport {
+            topology_type: description.topology_type,
+            servers: servers,
+            connection: self.health.report(),
+        }
+    }
+
+    fn await_primary(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let health = self.health();
+            if health.is_writable() {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::PrimaryUnavailable(Box::new(health)));
+            }
+
+            // Check the servers again rather than waiting for their next heartbeats.
+            for server in self.topology.description.read().recover().servers.values() {
+                server.request_update();
+            }
+
+            thread::sleep(cmp::min(deadline - now, Duration::from_millis(500)));
+        }
+    }
+
+    fn reconfigure(&self, delta: ClientOptionsDelta) -> Result<()> {
+        if delta.pool_size == Some(0) {
+            return Err(Error::ArgumentError(String::from(
+                "The connection pool size must be greater than zero.",
+            )));
+        }
+
+        {
+            let mut top = self.topology.description.write().recover();
+
+            if let Some(size) = delta.pool_size {
+                top.pool_size = Some(size);
+                for server in top.servers.values() {
+                    server.pool().set_size(size)?;
+                }
+            }
+
+            if let Some(timeout) = delta.idle_connection_timeout {
+                top.idle_connection_timeout = Some(timeout);
+                for server in top.servers.values() {
+                    server.pool().set_idle_connection_timeout(timeout)?;
+                }
+            }
+
+            if let Some(timeout) = delta.server_selection_timeout_ms {
+                top.server_selection_timeout_ms = timeout;
+            }
+
+            if let Some(threshold) = delta.local_threshold_ms {
+                top.local_threshold_ms = threshold;
+            }
+
+            if let Some(frequency) = delta.heartbeat_frequency_ms {
+                top.heartbeat_frequency_ms = frequency;
+                // Wake the monitors so they pick up the new frequency now rather than after
+                // the current wait.
+                for server in top.servers.values() {
+                    server.request_update();
+                }
+            }
+        }
+
+        if let Some(read_preference) = delta.read_preference {
+            *self.default_read_preference.write()? = read_preference;
+        }
+
+        Ok(())
+    }
+
+    fn rotate_credential(&self, credential: Credential) -> Result<()> {
+        let mut credentials = self.topology.credentials.write().recover();
+        let current = credentials.current.take();
+        credentials.previous = current;
+        credentials.current = Some(credential);
+        Ok(())
+    }
+
+    fn finish_credential_rotation(&self) -> Result<()> {
+        self.topology.credentials.write().recover().previous = None;
+        Ok(())
+    }
+
     fn add_start_hook(&mut self, hook: fn(Client, &CommandStarted)) -> Result<()> {
         self.listener.add_start_hook(hook)
     }
@@ -515,6 +1313,100 @@ impl ThreadedClient for Client {
     fn add_completion_hook(&mut self, hook: fn(Client, &CommandResult)) -> Result<()> {
         self.listener.add_completion_hook(hook)
     }
+
+    fn add_credential_fallback_hook(&mut self, hook: fn(Client, &CredentialFallback))
+        -> Result<()> {
+        self.listener.add_credential_fallback_hook(hook)
+    }
+
+    fn add_heartbeat_started_hook(&mut self, hook: fn(Client, &ServerHeartbeatStarted))
+        -> Result<()> {
+        self.listener.add_heartbeat_started_hook(hook)
+    }
+
+    fn add_heartbeat_succeeded_hook(&mut self, hook: fn(Client, &ServerHeartbeatSucceeded))
+        -> Result<()> {
+        self.listener.add_heartbeat_succeeded_hook(hook)
+    }
+
+    fn add_heartbeat_failed_hook(&mut self, hook: fn(Client, &ServerHeartbeatFailed))
+        -> Result<()> {
+        self.listener.add_heartbeat_failed_hook(hook)
+    }
+}
+
+// Opens the config database for metadata reads. Secondary config servers are read from when
+// the primary is unavailable, so clusters can still be audited during a config server election.
+fn config_db(client: &Client) -> Database {
+    let read_preference = ReadPreference::new(ReadMode::PrimaryPreferred, None);
+    client.db_with_prefs("config", Some(read_preference), None)
+}
+
+// Looks up the connection pool of a server in the topology by its `host:port` address.
+fn server_pool(client: &Client, address: &str) -> Result<Arc<ConnectionPool>> {
+    with_server(client, address, Server::pool)
+}
+
+// Runs `f` on the server in the topology with the given `host:port` address.
+fn with_server<T, F>(client: &Client, address: &str, f: F) -> Result<T>
+where
+    F: FnOnce(&Server) -> T,
+{
+    let host = connstring::parse_host(address)?;
+    let description = client.topology.description.read().recover();
+
+    match description.servers.get(&host) {
+        Some(server) => Ok(f(server)),
+        None => Err(Error::ArgumentError(format!(
+            "{} is not a server in the topology.",
+            address
+        ))),
+    }
+}
+
+// Adds the automatic encrypter of `options` to the end of the interceptor chain, so that
+// other interceptors see plaintexts. Data keys are read with the client the options name, or
+// with the one `key_vault_client` creates.
+#[cfg(feature = "encryption")]
+fn attach_auto_encrypter<F>(
+    mut interceptors: Interceptors,
+    options: Option<AutoEncryptionOptions>,
+    key_vault_client: F,
+) -> Result<(Interceptors, Option<Arc<AutoEncrypter>>)>
+where
+    F: FnOnce() -> Result<Client>,
+{
+    let options = match options {
+        Some(options) => options,
+        None => return Ok((interceptors, None)),
+    };
+
+    let key_vault_client = match options.key_vault_client.clone() {
+        Some(key_vault_client) => key_vault_client,
+        None => key_vault_client()?,
+    };
+
+    let encrypter = Arc::new(AutoEncrypter::new(options, key_vault_client)?);
+    interceptors.push(encrypter.clone());
+    Ok((interceptors, Some(encrypter)))
+}
+
+// Registers the command logging hooks and opens the log file, if one was requested.
+fn open_command_log(listener: &Listener, log_file: Option<String>) -> Result<Option<Mutex<File>>> {
+    match log_file {
+        Some(string) => {
+            let _ = listener.add_start_hook(log_command_started);
+            let _ = listener.add_completion_hook(log_command_completed);
+            Ok(Some(Mutex::new(
+                OpenOptions::new()
+                    .write(true)
+                    .append(true)
+                    .create(true)
+                    .open(&string)?
+            )))
+        }
+        None => Ok(None),
+    }
 }
 
 fn log_command_started(client: Client, command_started: &CommandStarted) {
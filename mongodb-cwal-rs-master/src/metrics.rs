@@ -0,0 +1,205 @@
+//! Counters, gauges and histograms about the client's work, reported to a `MetricsSink`.
+//!
+//! The sink receives every measurement with the name of its metric and a few labels, and is
+//! free to aggregate them however its monitoring system expects, e.g. into the counters of a
+//! Prometheus registry.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ClientOptions, ThreadedClient};
+//! # use mongodb::metrics::{self, Metrics, MetricsSink};
+//! # use std::sync::Arc;
+//! # use std::sync::atomic::{AtomicUsize, Ordering};
+//! #
+//! #[derive(Default)]
+//! struct CommandCounter {
+//!     commands: AtomicUsize,
+//! }
+//!
+//! impl MetricsSink for CommandCounter {
+//!     fn increment_counter(&self, name: &'static str, _: &[(&'static str, &str)], value: u64) {
+//!         if name == metrics::OPERATIONS {
+//!             self.commands.fetch_add(value as usize, Ordering::SeqCst);
+//!         }
+//!     }
+//! }
+//!
+//! let counter = Arc::new(CommandCounter::default());
+//!
+//! let mut options = ClientOptions::new();
+//! options.metrics = Metrics::new(counter.clone());
+//! let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+//! ```
+use error::Error;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Commands and getMores sent, other than those authenticating connections, labelled by
+/// `command`.
+pub const OPERATIONS: &str = "mongodb_operations_total";
+/// Commands that failed to be sent or were answered with an error, labelled by `command` and
+/// by `class`, one of `network`, `server`, `write`, `protocol`, `argument` or `client`.
+pub const ERRORS: &str = "mongodb_errors_total";
+/// How long commands took in seconds, from sending them to reading their reply, labelled by
+/// `command`.
+pub const COMMAND_DURATION: &str = "mongodb_command_duration_seconds";
+/// Connections checked out of a server's pool, labelled by `server`.
+pub const POOL_CHECKOUTS: &str = "mongodb_pool_checkouts_total";
+/// Checkouts that failed because the pool was draining or could not connect, labelled by
+/// `server`.
+pub const POOL_CHECKOUT_FAILURES: &str = "mongodb_pool_checkout_failures_total";
+/// The connections open to a server as of its last checkout, labelled by `server`.
+pub const POOL_CONNECTIONS: &str = "mongodb_pool_connections";
+/// Operations that found no suitable server within the server selection timeout, labelled by
+/// `operation`, either `read` or `write`.
+pub const SERVER_SELECTION_TIMEOUTS: &str = "mongodb_server_selection_timeouts_total";
+/// Bytes of command and getMore messages written, labelled by `server`.
+pub const BYTES_SENT: &str = "mongodb_bytes_sent_total";
+/// Bytes of replies to commands and getMores read, labelled by `server`.
+pub const BYTES_RECEIVED: &str = "mongodb_bytes_received_total";
+
+/// Receives the measurements of a client. Each method does nothing unless implemented.
+///
+/// Measurements are reported from whichever thread made them, including the threads that
+/// monitor the servers, so they should be recorded quickly and without blocking.
+pub trait MetricsSink: Send + Sync {
+    /// Adds `value` to the counter `name`.
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        let _ = (name, labels, value);
+    }
+
+    /// Sets the gauge `name` to `value`.
+    fn set_gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let _ = (name, labels, value);
+    }
+
+    /// Records `value` as an observation of the histogram `name`.
+    fn record_histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let _ = (name, labels, value);
+    }
+}
+
+/// Where a client reports its measurements; by default, nowhere.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    sink: Option<Arc<dyn MetricsSink>>,
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("sink", &self.sink.as_ref().map(|_| "MetricsSink { .. }"))
+            .finish()
+    }
+}
+
+impl Metrics {
+    /// Reports measurements to `sink`.
+    pub fn new(sink: Arc<dyn MetricsSink>) -> Metrics {
+        Metrics { sink: Some(sink) }
+    }
+
+    /// Returns whether measurements are reported anywhere.
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub(crate) fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64) {
+        if let Some(ref sink) = self.sink {
+            sink.increment_counter(name, labels, value);
+        }
+    }
+
+    pub(crate) fn gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        if let Some(ref sink) = self.sink {
+            sink.set_gauge(name, labels, value);
+        }
+    }
+
+    pub(crate) fn histogram(
+        &self,
+        name: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        if let Some(ref sink) = self.sink {
+            sink.record_histogram(name, labels, value);
+        }
+    }
+}
+
+/// Returns the class an error is counted under in `ERRORS`.
+pub fn error_class(err: &Error) -> &'static str {
+    match *err {
+        Error::Contextual(_, ref inner) => error_class(inner),
+        _ if err.is_network_error() => "network",
+        Error::OperationError(_) |
+        Error::CodedError(_) |
+        Error::MaxTimeMSExpired(_) |
+        Error::CursorNotFoundError |
+        Error::CursorInvalidatedError(..) |
+        Error::ScanInterruptedError(_) => "server",
+        Error::WriteError(_) | Error::BulkWriteError(_) => "write",
+        Error::EncoderError(_) |
+        Error::DecoderError(_) |
+        Error::ResponseError(_) |
+        Error::MaliciousServerError(_) => "protocol",
+        Error::ArgumentError(_) => "argument",
+        _ => "client",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::ErrorCode;
+    use std::io;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        counters: Mutex<Vec<(String, Vec<(String, String)>, u64)>>,
+    }
+
+    impl MetricsSink for Recorder {
+        fn increment_counter(
+            &self,
+            name: &'static str,
+            labels: &[(&'static str, &str)],
+            value: u64,
+        ) {
+            let labels = labels.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect();
+            self.counters.lock().unwrap().push((name.to_owned(), labels, value));
+        }
+    }
+
+    #[test]
+    fn reports_to_the_sink() {
+        let recorder = Arc::new(Recorder::default());
+        let metrics = Metrics::new(recorder.clone());
+        assert!(metrics.is_enabled());
+
+        metrics.counter(OPERATIONS, &[("command", "find")], 1);
+        // Unimplemented methods do nothing.
+        metrics.histogram(COMMAND_DURATION, &[("command", "find")], 0.5);
+
+        let counters = recorder.counters.lock().unwrap();
+        let labels = vec![(String::from("command"), String::from("find"))];
+        assert_eq!(vec![(String::from(OPERATIONS), labels, 1)], *counters);
+
+        assert!(!Metrics::default().is_enabled());
+        Metrics::default().counter(OPERATIONS, &[], 1);
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!("network", error_class(&Error::IoError(reset)));
+        assert_eq!("network", error_class(&Error::CodedError(ErrorCode::HostUnreachable)));
+        assert_eq!("server", error_class(&Error::CodedError(ErrorCode::DuplicateKey)));
+        assert_eq!("server", error_class(&Error::OperationError(String::from("failed"))));
+        assert_eq!("protocol", error_class(&Error::ResponseError(String::from("bad"))));
+        assert_eq!("argument", error_class(&Error::ArgumentError(String::from("bad"))));
+        assert_eq!("client", error_class(&Error::PoisonLockError));
+    }
+}
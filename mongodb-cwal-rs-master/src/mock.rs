@@ -0,0 +1,656 @@
+//! An in-memory server for testing code that uses the driver without a running mongod.
+//!
+//! A `MockServer` answers the connections of clients created with
+//! `StreamConnector::Mock`: it replies to `isMaster` as a standalone server, to other
+//! commands with the replies scripted for them, and records every command it receives so
+//! that tests can assert on exactly what the driver sent.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Bson, Client, ClientOptions, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::mock::MockServer;
+//! # use mongodb::stream::StreamConnector;
+//! #
+//! # fn main() {
+//! let server = MockServer::new();
+//! server.reply_to("find", doc! {
+//!     "cursor": { "id": 0_i64, "firstBatch": [{ "_id": 1, "name": "Ada" }] },
+//!     "ok": 1,
+//! });
+//!
+//! let mut options = ClientOptions::new();
+//! options.stream_connector = StreamConnector::Mock(server.clone());
+//! let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+//!
+//! let people = client.db("hr").collection("people");
+//! let found = people.find_one(Some(doc! { "name": "Ada" }), None).unwrap();
+//! assert!(found.is_some());
+//!
+//! let commands = server.commands();
+//! assert_eq!("find", commands[0].name);
+//! assert_eq!(Some(&Bson::from(doc! { "name": "Ada" })), commands[0].command.get("filter"));
+//! # }
+//! ```
+use bson::{self, doc, Bson, Document};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use poison::Recover;
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+const OP_REPLY: i32 = 1;
+const OP_QUERY: i32 = 2004;
+const OP_GET_MORE: i32 = 2005;
+
+const QUERY_FAILURE: i32 = 2;
+
+/// A command received by a `MockServer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCommand {
+    /// The database the command was sent to.
+    pub database: String,
+    /// The name of the command, e.g. `insert` or `find`.
+    pub name: String,
+    /// The command as sent. Legacy finds and getMores are recorded as the equivalent `find`
+    /// and `getMore` commands.
+    pub command: Document,
+}
+
+#[derive(Debug)]
+struct State {
+    is_master: Document,
+    replies: HashMap<String, VecDeque<Document>>,
+    commands: Vec<MockCommand>,
+}
+
+/// A scripted server, shared by the connections made to it.
+#[derive(Clone)]
+pub struct MockServer {
+    state: Arc<Mutex<State>>,
+}
+
+impl fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().recover();
+        f.debug_struct("MockServer")
+            .field("is_master", &state.is_master)
+            .field("commands", &state.commands.len())
+            .finish()
+    }
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        MockServer::new()
+    }
+}
+
+impl MockServer {
+    /// Creates a server answering as a standalone mongod, with no replies scripted.
+    pub fn new() -> MockServer {
+        let is_master = doc! {
+            "ismaster": true,
+            "maxBsonObjectSize": 16 * 1024 * 1024,
+            "maxMessageSizeBytes": 48_000_000,
+            "maxWriteBatchSize": 100_000,
+            "minWireVersion": 0_i64,
+            "maxWireVersion": 6_i64,
+            "ok": 1,
+        };
+
+        MockServer {
+            state: Arc::new(Mutex::new(State {
+                is_master: is_master,
+                replies: HashMap::new(),
+                commands: Vec::new(),
+            })),
+        }
+    }
+
+    /// Replaces the reply to `isMaster`, such as to act as a replica set member or mongos.
+    pub fn set_is_master(&self, reply: Document) {
+        self.state.lock().recover().is_master = reply;
+    }
+
+    /// Queues `reply` as the answer to the next command named `command_name`.
+    ///
+    /// Replies to a command are used in the order they were queued. Commands without a
+    /// queued reply are answered with `{ ok: 1 }`, with an empty batch for those returning a
+    /// cursor.
+    /// Finds and getMores take a command reply, whose `cursor` holds the id and batch to
+    /// return; an `ok: 0` reply fails them with its `errmsg` and `code`.
+    pub fn reply_to(&self, command_name: &str, reply: Document) {
+        let mut state = self.state.lock().recover();
+        state
+            .replies
+            .entry(String::from(command_name))
+            .or_insert_with(VecDeque::new)
+            .push_back(reply);
+    }
+
+    /// Returns the commands received so far, other than the `isMaster` handshakes and
+    /// heartbeats, in the order they arrived.
+    pub fn commands(&self) -> Vec<MockCommand> {
+        let state = self.state.lock().recover();
+        state
+            .commands
+            .iter()
+            .filter(|command| command.name.to_lowercase() != "ismaster")
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets the commands received so far.
+    pub fn clear_commands(&self) {
+        self.state.lock().recover().commands.clear();
+    }
+
+    /// Opens a connection to the server.
+    pub fn connect(&self, host_name: &str, port: u16) -> MockStream {
+        MockStream {
+            server: self.clone(),
+            peer: format!("{}:{}", host_name, port)
+                .parse()
+                .unwrap_or_else(|_| SocketAddr::from(([127, 0, 0, 1], port))),
+            written: Vec::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    // Records a command and returns the reply to it.
+    fn answer(&self, database: &str, name: &str, command: Document) -> Document {
+        let mut state = self.state.lock().recover();
+        state.commands.push(MockCommand {
+            database: String::from(database),
+            name: String::from(name),
+            command: command,
+        });
+
+        if name.to_lowercase() == "ismaster" {
+            return state.is_master.clone();
+        }
+
+        let scripted = state.replies.get_mut(name).and_then(VecDeque::pop_front);
+        scripted.unwrap_or_else(|| match name {
+            "find" | "aggregate" | "listCollections" | "listIndexes" => {
+                doc! { "cursor": { "id": 0_i64, "firstBatch": [] }, "ok": 1 }
+            }
+            "getMore" => doc! { "cursor": { "id": 0_i64, "nextBatch": [] }, "ok": 1 },
+            _ => doc! { "ok": 1 },
+        })
+    }
+}
+
+/// A connection to a `MockServer`.
+#[derive(Debug)]
+pub struct MockStream {
+    server: MockServer,
+    peer: SocketAddr,
+    // The bytes of a message not yet written in full.
+    written: Vec<u8>,
+    // The replies not yet read.
+    replies: VecDeque<u8>,
+}
+
+fn invalid<E: fmt::Display>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn read_cstring(bytes: &mut &[u8]) -> io::Result<String> {
+    let end = match bytes.iter().position(|&byte| byte == 0) {
+        Some(end) => end,
+        None => return Err(invalid("unterminated string")),
+    };
+    let string = String::from_utf8_lossy(&bytes[..end]).into_owned();
+    *bytes = &bytes[end + 1..];
+    Ok(string)
+}
+
+fn split_namespace(namespace: &str) -> (&str, &str) {
+    match namespace.find('.') {
+        Some(index) => (&namespace[..index], &namespace[index + 1..]),
+        None => (namespace, ""),
+    }
+}
+
+// Returns the cursor id and batch of a find or getMore command reply, as a legacy reply
+// carries them, or the error document of a failed one.
+fn cursor_reply(reply: Document, batch_key: &str) -> (i32, i64, Vec<Document>) {
+    if let Some(&Bson::I32(0)) = reply.get("ok") {
+        let message = reply.get_str("errmsg").unwrap_or("mock failure");
+        let code = reply.get_i32("code").unwrap_or(0);
+        let error = doc! { "$err": message, "errmsg": message, "code": code, "ok": 0 };
+        return (QUERY_FAILURE, 0, vec![error]);
+    }
+
+    let cursor = reply.get_document("cursor").ok().cloned().unwrap_or_default();
+    let id = match cursor.get("id") {
+        Some(&Bson::I64(id)) => id,
+        Some(&Bson::I32(id)) => i64::from(id),
+        _ => 0,
+    };
+    let batch = match cursor.get(batch_key) {
+        Some(&Bson::Array(ref batch)) => batch
+            .iter()
+            .filter_map(|doc| match *doc {
+                Bson::Document(ref doc) => Some(doc.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    (0, id, batch)
+}
+
+// Fills in the namespace and 64-bit id the driver expects of a command cursor, which scripted
+// replies may leave out.
+fn complete_cursor(reply: &mut Document, namespace: &str) {
+    if let Some(&mut Bson::Document(ref mut cursor)) = reply.get_mut("cursor") {
+        if !cursor.contains_key("ns") {
+            cursor.insert("ns", namespace);
+        }
+        if let Some(&Bson::I32(id)) = cursor.get("id") {
+            cursor.insert("id", i64::from(id));
+        }
+    }
+}
+
+impl MockStream {
+    // Answers a whole message, queueing its reply to be read.
+    fn receive(&mut self, message: &[u8]) -> io::Result<()> {
+        let mut bytes = message;
+        let _length = bytes.read_i32::<LittleEndian>()?;
+        let request_id = bytes.read_i32::<LittleEndian>()?;
+        let _response_to = bytes.read_i32::<LittleEndian>()?;
+        let op_code = bytes.read_i32::<LittleEndian>()?;
+
+        let (flags, cursor_id, documents) = match op_code {
+            OP_QUERY => {
+                let _flags = bytes.read_i32::<LittleEndian>()?;
+                let namespace = read_cstring(&mut bytes)?;
+                let _skip = bytes.read_i32::<LittleEndian>()?;
+                let _limit = bytes.read_i32::<LittleEndian>()?;
+                let query = bson::decode_document(&mut bytes).map_err(invalid)?;
+                self.query(&namespace, query)
+            }
+            OP_GET_MORE => {
+                let _zero = bytes.read_i32::<LittleEndian>()?;
+                let namespace = read_cstring(&mut bytes)?;
+                let _batch_size = bytes.read_i32::<LittleEndian>()?;
+                let id = bytes.read_i64::<LittleEndian>()?;
+                let (db, coll) = split_namespace(&namespace);
+                let command = doc! { "getMore": id, "collection": coll };
+                let reply = self.server.answer(db, "getMore", command);
+                cursor_reply(reply, "nextBatch")
+            }
+            // Other messages, such as legacy writes, expect no reply.
+            _ => return Ok(()),
+        };
+
+        let mut encoded = Vec::new();
+        for doc in &documents {
+            bson::encode_document(&mut encoded, doc).map_err(invalid)?;
+        }
+
+        let mut reply = Vec::with_capacity(36 + encoded.len());
+        reply.write_i32::<LittleEndian>(36 + encoded.len() as i32)?;
+        reply.write_i32::<LittleEndian>(0)?;
+        reply.write_i32::<LittleEndian>(request_id)?;
+        reply.write_i32::<LittleEndian>(OP_REPLY)?;
+        reply.write_i32::<LittleEndian>(flags)?;
+        reply.write_i64::<LittleEndian>(cursor_id)?;
+        reply.write_i32::<LittleEndian>(0)?;
+        reply.write_i32::<LittleEndian>(documents.len() as i32)?;
+        reply.extend_from_slice(&encoded);
+        self.replies.extend(reply);
+        Ok(())
+    }
+
+    fn query(&self, namespace: &str, query: Document) -> (i32, i64, Vec<Document>) {
+        let (db, coll) = split_namespace(namespace);
+
+        // Commands and filters sent with a read preference or modifiers come in `$query`.
+        let (query, modifiers) = match query.get("$query") {
+            Some(&Bson::Document(ref inner)) => (inner.clone(), query.clone()),
+            _ => (query, Document::new()),
+        };
+
+        if coll == "$cmd" {
+            let name = query.keys().next().cloned().unwrap_or_default();
+            // Commands without a collection, like listCollections, report a `$cmd` namespace.
+            let namespace = match query.get_str(&name) {
+                Ok(coll) => format!("{}.{}", db, coll),
+                Err(_) => format!("{}.$cmd.{}", db, name),
+            };
+            let mut reply = self.server.answer(db, &name, query);
+            complete_cursor(&mut reply, &namespace);
+            return (0, 0, vec![reply]);
+        }
+
+        let mut command = doc! { "find": coll, "filter": query };
+        for (key, value) in modifiers {
+            match &key[..] {
+                "$query" => (),
+                "$orderby" => {
+                    command.insert("sort", value);
+                }
+                _ => {
+                    command.insert(key.trim_start_matches('$'), value);
+                }
+            }
+        }
+        cursor_reply(self.server.answer(db, "find", command), "firstBatch")
+    }
+
+    /// Returns the address the connection was opened to.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.replies.len());
+        for (byte, reply) in buf.iter_mut().zip(self.replies.drain(..len)) {
+            *byte = reply;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+
+        while self.written.len() >= 4 {
+            let length = (&self.written[..4]).read_i32::<LittleEndian>()? as usize;
+            if length < 16 {
+                return Err(invalid("invalid message length"));
+            }
+            if self.written.len() < length {
+                break;
+            }
+
+            let message: Vec<u8> = self.written.drain(..length).collect();
+            self.receive(&message)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::spec::BinarySubtype;
+    use common::{ServerApi, ServerApiVersion, WriteConcern};
+    use db::ThreadedDatabase;
+    use interceptor::{CommandContext, CommandInterceptor};
+    use pool::FlushMode;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use stream::StreamConnector;
+    use topology::TopologyType;
+    use wire_protocol::streamed::StreamedBinary;
+    use {Client, ClientOptions, Error, Result, ThreadedClient};
+
+    fn client(server: &MockServer) -> Client {
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        Client::connect_with_options("localhost", 27017, options).unwrap()
+    }
+
+    #[test]
+    fn records_commands_and_returns_scripted_replies() {
+        let server = MockServer::new();
+        server.reply_to("buildInfo", doc! { "version": "3.6.0", "ok": 1 });
+        server.reply_to("listCollections", doc! {
+            "cursor": { "id": 0_i64, "firstBatch": [{ "name": "orders", "type": "collection" }] },
+            "ok": 1,
+        });
+        server.reply_to("find", doc! {
+            "cursor": { "id": 0_i64, "firstBatch": [{ "_id": 1 }, { "_id": 2 }] },
+            "ok": 1,
+        });
+        let client = client(&server);
+
+        // Monitoring asks for the server version once it first reaches the server; wait for
+        // it, so that only the commands below are recorded.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !server.commands().iter().any(|command| command.name == "buildInfo") {
+            assert!(Instant::now() < deadline, "The monitor never sent buildInfo.");
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.clear_commands();
+
+        let coll = client.db("shop").collection("orders");
+        let found: Vec<_> = coll.find(Some(doc! { "paid": true }), None).unwrap().collect();
+        assert_eq!(2, found.len());
+        coll.insert_one(doc! { "_id": 3 }, None).unwrap();
+
+        // Inserts first check that the collection is not a view.
+        let commands = server.commands();
+        let names: Vec<_> = commands
+            .iter()
+            .map(|command| (&command.database[..], &command.name[..]))
+            .collect();
+        assert_eq!(
+            vec![("shop", "find"), ("shop", "listCollections"), ("shop", "insert")],
+            names
+        );
+        assert_eq!(doc! { "find": "orders", "filter": { "paid": true } }, commands[0].command);
+        assert_eq!(
+            Some(&Bson::Array(vec![Bson::Document(doc! { "_id": 3 })])),
+            commands[2].command.get("documents")
+        );
+    }
+
+    #[test]
+    fn fails_finds_with_scripted_errors() {
+        let server = MockServer::new();
+        server.reply_to("find", doc! { "ok": 0, "errmsg": "not authorized", "code": 13 });
+        let coll = client(&server).db("shop").collection("orders");
+
+        assert!(coll.find(None, None).is_err());
+        assert!(coll.find(None, None).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn pings_and_reports_health() {
+        let server = MockServer::new();
+        let client = client(&server);
+
+        client.ping_with_timeout(None, Duration::from_secs(5)).unwrap();
+        assert!(server.commands().iter().any(|command| command.name == "ping"));
+
+        // The monitor may not have reported the server type yet, and may hold a connection
+        // of its own.
+        let health = client.health();
+        assert_eq!(TopologyType::Single, health.topology_type);
+        assert_eq!("localhost:27017", health.servers[0].address);
+        assert!(health.servers[0].pool.idle >= 1);
+        assert!(!health.is_saturated());
+    }
+
+    #[test]
+    fn awaits_a_writable_primary() {
+        let server = MockServer::new();
+        server.set_is_master(doc! {
+            "ismaster": false,
+            "secondary": true,
+            "setName": "rs0",
+            "hosts": ["localhost:27017"],
+            "minWireVersion": 0,
+            "maxWireVersion": 6,
+            "ok": 1,
+        });
+
+        match client(&server).await_primary(Duration::from_millis(200)) {
+            Err(Error::PrimaryUnavailable(health)) => {
+                assert!(!health.is_writable());
+                assert_eq!("localhost:27017", health.servers[0].address);
+            }
+            result => panic!("Expected PrimaryUnavailable, got {:?}", result),
+        }
+
+        client(&MockServer::new()).await_primary(Duration::from_secs(5)).unwrap();
+    }
+
+    // Marks the documents of inserts, to show that they went through the interceptors.
+    struct Tagger;
+
+    impl CommandInterceptor for Tagger {
+        fn on_command(&self, _: &CommandContext, command: &mut Document) -> Result<()> {
+            if let Some(&mut Bson::Array(ref mut documents)) = command.get_mut("documents") {
+                for document in documents.iter_mut() {
+                    if let Bson::Document(ref mut document) = *document {
+                        document.insert("tagged", true);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_inserts_through_interceptors() {
+        let server = MockServer::new();
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        options.interceptors.push(Arc::new(Tagger));
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let binary = StreamedBinary::new("data", BinarySubtype::Generic, 4, &b"blob"[..]);
+        let coll = client.db("shop").collection("blobs");
+        coll.insert_one_streamed(doc! { "_id": 1 }, binary, None).unwrap();
+
+        let insert = server
+            .commands()
+            .into_iter()
+            .find(|command| command.name == "insert")
+            .expect("The insert was not sent.");
+        let expected = doc! {
+            "_id": 1,
+            "tagged": true,
+            "data": Bson::Binary(BinarySubtype::Generic, b"blob".to_vec()),
+        };
+        assert_eq!(
+            Some(&Bson::Array(vec![Bson::Document(expected)])),
+            insert.command.get("documents")
+        );
+    }
+
+    #[test]
+    fn declares_the_server_api_on_inserts() {
+        let server = MockServer::new();
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        options.server_api = Some(ServerApi::new(ServerApiVersion::V1));
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let coll = client.db("shop").collection("blobs");
+        let binary = StreamedBinary::new("data", BinarySubtype::Generic, 4, &b"blob"[..]);
+        coll.insert_one_streamed(doc! { "_id": 1 }, binary, None).unwrap();
+
+        let mut raw = Vec::new();
+        bson::encode_document(&mut raw, &doc! { "_id": 2 }).unwrap();
+        coll.insert_many_unchecked(&[raw], None).unwrap();
+
+        let inserts: Vec<_> = server
+            .commands()
+            .into_iter()
+            .filter(|command| command.name == "insert")
+            .collect();
+        assert_eq!(2, inserts.len());
+        for insert in inserts {
+            assert_eq!(Some(&Bson::from("1")), insert.command.get("apiVersion"));
+        }
+    }
+
+    #[test]
+    fn holds_unacknowledged_writes_through_interceptors() {
+        let server = MockServer::new();
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        options.flush_mode = FlushMode::Coalesce;
+        options.interceptors.push(Arc::new(Tagger));
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let coll = client.db("shop").collection("events");
+        let mut write_concern = WriteConcern::new();
+        write_concern.w = 0;
+        coll.insert_one(doc! { "_id": 1 }, Some(write_concern)).unwrap();
+
+        let inserts = || {
+            server
+                .commands()
+                .into_iter()
+                .filter(|command| command.name == "insert")
+                .collect::<Vec<_>>()
+        };
+
+        // The insert waits in the connection's write buffer until the next command.
+        assert!(inserts().is_empty());
+        coll.find_one(None, None).unwrap();
+
+        let inserts = inserts();
+        assert_eq!(1, inserts.len());
+        assert_eq!(
+            Some(&Bson::Array(vec![Bson::Document(doc! { "_id": 1, "tagged": true })])),
+            inserts[0].command.get("documents")
+        );
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn refuses_streamed_inserts_into_encrypted_collections() {
+        use encryption::{Algorithm, AutoEncryptionOptions, KmsProviders};
+        use std::collections::HashMap;
+
+        let server = MockServer::new();
+        let key_id = Bson::Binary(BinarySubtype::Uuid, vec![1; 16]);
+        let mut schema_map = HashMap::new();
+        schema_map.insert(String::from("hr.people"), doc! {
+            "bsonType": "object",
+            "properties": {
+                "ssn": {
+                    "encrypt": { "keyId": [key_id], "algorithm": Algorithm::Deterministic.name() },
+                },
+            },
+        });
+
+        let kms_providers = KmsProviders { local: Some(vec![1; 96]) };
+        let mut auto_encryption = AutoEncryptionOptions::new("keys.vault", kms_providers);
+        auto_encryption.key_vault_client = Some(client(&server));
+        auto_encryption.schema_map = schema_map;
+
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        options.auto_encryption = Some(auto_encryption);
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let binary = StreamedBinary::new("ssn", BinarySubtype::Generic, 4, &b"1120"[..]);
+        let people = client.db("hr").collection("people");
+        match people.insert_one_streamed(doc! { "_id": 1 }, binary, None) {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected an argument error, got {:?}", other),
+        }
+        assert!(!server.commands().iter().any(|command| command.name == "insert"));
+
+        // Collections without encrypted fields still take streamed inserts.
+        let binary = StreamedBinary::new("data", BinarySubtype::Generic, 4, &b"blob"[..]);
+        let blobs = client.db("hr").collection("blobs");
+        blobs.insert_one_streamed(doc! { "_id": 1 }, binary, None).unwrap();
+    }
+}
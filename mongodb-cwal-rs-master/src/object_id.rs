@@ -0,0 +1,172 @@
+//! Client-side generation of the ObjectIds given to inserted documents lacking an `_id`.
+//!
+//! An ObjectId is made of the seconds since the Unix epoch, a random value fixed for the
+//! generator, and a counter incremented for every id. By default the timestamp comes from the
+//! system clock and the random value and counter seed are picked at random; each part can be
+//! set on an `ObjectIdGenerator`, for instance to make ids reproducible in tests:
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! # use mongodb::{Client, ClientOptions, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::object_id::{Clock, ObjectIdGenerator};
+//! # use std::sync::Arc;
+//! #
+//! struct FrozenClock;
+//!
+//! impl Clock for FrozenClock {
+//!     fn now(&self) -> u32 {
+//!         1_500_000_000
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let mut options = ClientOptions::new();
+//! options.object_ids = ObjectIdGenerator::new()
+//!     .random_value([1, 2, 3, 4, 5])
+//!     .counter_seed(0)
+//!     .clock(Arc::new(FrozenClock));
+//!
+//! let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+//! let coll = client.db("test").collection("events");
+//! let result = coll.insert_one(doc!{ "x": 1 }, None).unwrap();
+//! println!("inserted {}", result.inserted_id.unwrap());
+//! # }
+//! ```
+use bson::oid::ObjectId;
+use byteorder::{BigEndian, ByteOrder};
+use rand::{thread_rng, Rng};
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The counter takes the last three bytes of an ObjectId.
+const COUNTER_MASK: usize = 0x00FF_FFFF;
+
+/// The source of the timestamps ObjectIds are generated with.
+pub trait Clock: Send + Sync {
+    /// Returns the seconds since the Unix epoch.
+    fn now(&self) -> u32;
+}
+
+/// Reads the timestamp from the system clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u32 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as u32)
+            .unwrap_or(0)
+    }
+}
+
+/// Generates ObjectIds; clones share the counter, so their ids stay distinct.
+#[derive(Clone)]
+pub struct ObjectIdGenerator {
+    random: [u8; 5],
+    counter: Arc<AtomicUsize>,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for ObjectIdGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ObjectIdGenerator")
+            .field("random", &self.random)
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+impl Default for ObjectIdGenerator {
+    fn default() -> Self {
+        let mut rng = thread_rng();
+        let mut random = [0; 5];
+        rng.fill_bytes(&mut random);
+
+        ObjectIdGenerator {
+            random: random,
+            counter: Arc::new(AtomicUsize::new(rng.gen::<usize>() & COUNTER_MASK)),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl ObjectIdGenerator {
+    /// Creates a generator with a random value and counter seed, reading the system clock.
+    pub fn new() -> ObjectIdGenerator {
+        ObjectIdGenerator::default()
+    }
+
+    /// Sets the five bytes following the timestamp, which usually identify the process.
+    pub fn random_value(mut self, random: [u8; 5]) -> ObjectIdGenerator {
+        self.random = random;
+        self
+    }
+
+    /// Sets the counter value of the next id; only its lower 24 bits are used.
+    ///
+    /// The counter is no longer shared with generators this one was cloned from.
+    pub fn counter_seed(mut self, seed: u32) -> ObjectIdGenerator {
+        self.counter = Arc::new(AtomicUsize::new(seed as usize & COUNTER_MASK));
+        self
+    }
+
+    /// Sets the clock the timestamps are read from.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> ObjectIdGenerator {
+        self.clock = clock;
+        self
+    }
+
+    /// Generates an id from the current time and the next counter value, which wraps around
+    /// after 2^24 ids.
+    pub fn generate(&self) -> ObjectId {
+        let timestamp = self.clock.now();
+        let counter = self.counter.fetch_add(1, Ordering::SeqCst) & COUNTER_MASK;
+
+        let mut bytes = [0; 12];
+        BigEndian::write_u32(&mut bytes[..4], timestamp);
+        bytes[4..9].copy_from_slice(&self.random);
+        BigEndian::write_uint(&mut bytes[9..], counter as u64, 3);
+        ObjectId::with_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FixedClock(u32);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn generates_ids_from_their_parts() {
+        let generator = ObjectIdGenerator::new()
+            .random_value([1, 2, 3, 4, 5])
+            .counter_seed(0x00FF_FFFE)
+            .clock(Arc::new(FixedClock(0x5A0B_0C0D)));
+        let clone = generator.clone();
+
+        assert_eq!("5a0b0c0d0102030405fffffe", generator.generate().to_hex());
+        assert_eq!("5a0b0c0d0102030405ffffff", clone.generate().to_hex());
+        assert_eq!("5a0b0c0d0102030405000000", generator.generate().to_hex());
+        assert_eq!(0x5A0B_0C0D, generator.generate().timestamp());
+    }
+
+    #[test]
+    fn defaults_to_the_system_clock() {
+        let before = SystemClock.now();
+        let id = ObjectIdGenerator::new().generate();
+        assert!(id.timestamp() >= before && id.timestamp() <= SystemClock.now());
+        assert_ne!(id, ObjectIdGenerator::new().generate());
+    }
+}
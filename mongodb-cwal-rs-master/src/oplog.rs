@@ -0,0 +1,428 @@
+//! Tailing the replica set oplog.
+//!
+//! An `Oplog` follows `local.oplog.rs` with a tailable, awaiting cursor, starting after a
+//! given BSON timestamp. If the cursor fails, such as when the server steps down or a
+//! connection drops, it is reopened after the timestamp of the last entry returned, so that
+//! no entry is skipped or returned twice.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::oplog::{Oplog, OplogOperation};
+//! #
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! // Timestamps hold the seconds in their high 32 bits.
+//! let start = 1_600_000_000_i64 << 32;
+//!
+//! for entry in Oplog::new(client, start, None).unwrap() {
+//!     let entry = entry.unwrap();
+//!     if entry.operation == OplogOperation::Insert {
+//!         println!("{} inserted {:?}", entry.namespace, entry.document_id());
+//!     }
+//! }
+//! ```
+use bson::{self, doc, Bson, UtcDateTime};
+use coll::options::{CursorType, FindOptions};
+use common::ReadPreference;
+use cursor::Cursor;
+use db::ThreadedDatabase;
+use Error::ResponseError;
+use {Client, Error, ErrorCode, Result, ThreadedClient};
+
+use std::thread;
+use std::time::Duration;
+
+/// How many times in a row a failed cursor is reopened before the error is returned.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+// How long to wait before reopening a failed cursor, or one that died without results.
+const REOPEN_INTERVAL_MS: u64 = 500;
+
+/// The kind of operation an oplog entry records.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OplogOperation {
+    Insert,
+    Update,
+    Delete,
+    /// A database command, such as `create` or `drop`, or a transaction's `applyOps`.
+    Command,
+    /// A no-op, written for instance to keep idle replica sets advancing.
+    Noop,
+    /// An operation type unknown to the driver.
+    Other(String),
+}
+
+impl OplogOperation {
+    pub fn from_str(op: &str) -> OplogOperation {
+        match op {
+            "i" => OplogOperation::Insert,
+            "u" => OplogOperation::Update,
+            "d" => OplogOperation::Delete,
+            "c" => OplogOperation::Command,
+            "n" => OplogOperation::Noop,
+            other => OplogOperation::Other(String::from(other)),
+        }
+    }
+
+    pub fn to_str(&self) -> &str {
+        match *self {
+            OplogOperation::Insert => "i",
+            OplogOperation::Update => "u",
+            OplogOperation::Delete => "d",
+            OplogOperation::Command => "c",
+            OplogOperation::Noop => "n",
+            OplogOperation::Other(ref op) => op,
+        }
+    }
+}
+
+/// An entry of the oplog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OplogEntry {
+    /// When the operation was applied, as a BSON timestamp: the seconds in the high 32 bits
+    /// and the increment in the low 32 bits.
+    pub ts: i64,
+    /// The election term of the primary that applied the operation.
+    pub term: Option<i64>,
+    pub operation: OplogOperation,
+    /// The namespace the operation applies to, e.g. `shop.orders`, or `shop.$cmd` for
+    /// commands.
+    pub namespace: String,
+    /// The inserted document, the update applied, the `_id` of the deleted document, or the
+    /// command, depending on the operation.
+    pub object: bson::Document,
+    /// The `_id` of the document an update applies to.
+    pub query: Option<bson::Document>,
+    /// The wall clock time of the operation.
+    pub wall: Option<UtcDateTime>,
+}
+
+impl OplogEntry {
+    /// Decodes a document of the oplog.
+    pub fn from_document(mut document: bson::Document) -> Result<OplogEntry> {
+        let ts = match document.get("ts") {
+            Some(&Bson::TimeStamp(ts)) => ts,
+            _ => return Err(ResponseError(String::from("Oplog entry has no timestamp."))),
+        };
+
+        let operation = match document.get_str("op") {
+            Ok(op) => OplogOperation::from_str(op),
+            Err(_) => return Err(ResponseError(String::from("Oplog entry has no operation."))),
+        };
+
+        let term = match document.get("t") {
+            Some(&Bson::I64(term)) => Some(term),
+            Some(&Bson::I32(term)) => Some(i64::from(term)),
+            _ => None,
+        };
+
+        let wall = match document.get("wall") {
+            Some(&Bson::UtcDatetime(wall)) => Some(UtcDateTime(wall)),
+            _ => None,
+        };
+
+        let object = match document.remove("o") {
+            Some(Bson::Document(object)) => object,
+            _ => bson::Document::new(),
+        };
+
+        let query = match document.remove("o2") {
+            Some(Bson::Document(query)) => Some(query),
+            _ => None,
+        };
+
+        Ok(OplogEntry {
+            ts: ts,
+            term: term,
+            operation: operation,
+            namespace: document.get_str("ns").map(String::from).unwrap_or_default(),
+            object: object,
+            query: query,
+            wall: wall,
+        })
+    }
+
+    /// Returns the `_id` of the document an insert, update or delete applies to.
+    pub fn document_id(&self) -> Option<&Bson> {
+        match self.operation {
+            OplogOperation::Insert | OplogOperation::Delete => self.object.get("_id"),
+            OplogOperation::Update => self.query.as_ref().and_then(|query| query.get("_id")),
+            _ => None,
+        }
+    }
+}
+
+/// Options for tailing the oplog.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OplogOptions {
+    /// Only returns the entries matching this filter as well, e.g. `{ ns: "shop.orders" }`.
+    pub filter: Option<bson::Document>,
+    /// How long getMores wait for new entries before returning an empty batch; the server
+    /// waits one second by default.
+    pub max_await_time_ms: Option<i64>,
+    pub batch_size: Option<i32>,
+    /// Tails a secondary, for instance, instead of the primary.
+    pub read_preference: Option<ReadPreference>,
+    /// How many times in a row a failed cursor is reopened before the error is returned;
+    /// `DEFAULT_MAX_RETRIES` by default.
+    pub max_retries: Option<u32>,
+}
+
+impl OplogOptions {
+    pub fn new() -> OplogOptions {
+        Default::default()
+    }
+}
+
+/// Iterates over the oplog entries after a timestamp as they are written, blocking while
+/// there are none.
+///
+/// Errors that persist after `max_retries` reopenings are returned; iterating again retries
+/// once more. Entries the oplog no longer holds cannot be resumed from, so those errors are
+/// returned right away.
+#[derive(Debug)]
+pub struct Oplog {
+    client: Client,
+    options: OplogOptions,
+    last_timestamp: i64,
+    cursor: Option<Cursor>,
+    failures: u32,
+}
+
+impl Oplog {
+    /// Opens a cursor over the entries of `local.oplog.rs` after the timestamp `start`.
+    pub fn new(client: Client, start: i64, options: Option<OplogOptions>) -> Result<Oplog> {
+        let mut oplog = Oplog {
+            client: client,
+            options: options.unwrap_or_default(),
+            last_timestamp: start,
+            cursor: None,
+            failures: 0,
+        };
+
+        oplog.cursor = Some(oplog.open()?);
+        Ok(oplog)
+    }
+
+    /// Returns the timestamp of the last entry returned, or the start timestamp before any
+    /// was; tailing can be continued from it later with `Oplog::new`.
+    pub fn last_timestamp(&self) -> i64 {
+        self.last_timestamp
+    }
+
+    /// Returns the next entry, or `None` once a getMore waited for new entries in vain.
+    ///
+    /// Entries that cannot be decoded are returned as errors, and tailing continues after
+    /// them.
+    pub fn try_next(&mut self) -> Result<Option<OplogEntry>> {
+        loop {
+            if self.cursor.is_none() {
+                match self.open() {
+                    Ok(cursor) => self.cursor = Some(cursor),
+                    Err(err) => {
+                        self.fail(err)?;
+                        continue;
+                    }
+                }
+            }
+
+            let (result, dead, returned) = {
+                let cursor = self.cursor.as_mut().unwrap();
+                let result = cursor.next();
+                (result, cursor.id() == 0, Cursor::position(cursor))
+            };
+
+            match result {
+                Some(Ok(document)) => {
+                    self.failures = 0;
+
+                    // An entry that fails to decode is moved past, whether by the cursor or
+                    // by reopening it.
+                    if let Some(&Bson::TimeStamp(ts)) = document.get("ts") {
+                        self.last_timestamp = ts;
+                    }
+                    return OplogEntry::from_document(document).map(Some);
+                }
+                Some(Err(err)) => {
+                    self.cursor = None;
+                    self.fail(err)?;
+                }
+                None => {
+                    self.failures = 0;
+
+                    // Cursors whose first batch came back empty may be closed by the server;
+                    // wait before reopening them, so that an idle oplog is not polled.
+                    if dead {
+                        self.cursor = None;
+                        if returned == 0 {
+                            thread::sleep(Duration::from_millis(REOPEN_INTERVAL_MS));
+                        }
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn open(&self) -> Result<Cursor> {
+        let after = doc! { "ts": { "$gt": Bson::TimeStamp(self.last_timestamp) } };
+        let filter = match self.options.filter {
+            Some(ref filter) => doc! { "$and": [after, filter.clone()] },
+            None => after,
+        };
+
+        let mut options = FindOptions::new();
+        options.cursor_type = CursorType::TailableAwait;
+        options.oplog_replay = true;
+        options.no_cursor_timeout = true;
+        options.max_await_time_ms = self.options.max_await_time_ms;
+        options.batch_size = self.options.batch_size;
+        options.read_preference = self.options.read_preference.clone();
+
+        self.client.db("local").collection("oplog.rs").find(Some(filter), Some(options))
+    }
+
+    // Counts a failure of the cursor, returning `err` once too many happened in a row or if
+    // the oplog no longer holds the resume point.
+    fn fail(&mut self, err: Error) -> Result<()> {
+        self.failures += 1;
+        let max_retries = self.options.max_retries.unwrap_or(DEFAULT_MAX_RETRIES);
+        if self.failures > max_retries || err.code() == Some(ErrorCode::CappedPositionLost) {
+            self.failures = 0;
+            return Err(err);
+        }
+
+        warn!(
+            target: "mongodb::oplog",
+            "Reopening the oplog cursor after timestamp {}: {}",
+            self.last_timestamp,
+            err
+        );
+        thread::sleep(Duration::from_millis(REOPEN_INTERVAL_MS));
+        Ok(())
+    }
+}
+
+impl Iterator for Oplog {
+    type Item = Result<OplogEntry>;
+
+    /// Blocks until another entry is written, or returns the error that stopped the cursor.
+    fn next(&mut self) -> Option<Result<OplogEntry>> {
+        loop {
+            match self.try_next() {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => (),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_entries() {
+        let entry = OplogEntry::from_document(doc! {
+            "ts": Bson::TimeStamp(5 << 32 | 1),
+            "t": 3_i64,
+            "op": "u",
+            "ns": "shop.orders",
+            "o": { "$set": { "status": "shipped" } },
+            "o2": { "_id": 7 },
+        }).unwrap();
+
+        assert_eq!(5 << 32 | 1, entry.ts);
+        assert_eq!(Some(3), entry.term);
+        assert_eq!(OplogOperation::Update, entry.operation);
+        assert_eq!("shop.orders", entry.namespace);
+        assert_eq!(Some(&Bson::I32(7)), entry.document_id());
+
+        let entry = OplogEntry::from_document(doc! {
+            "ts": Bson::TimeStamp(6 << 32),
+            "op": "xi",
+            "ns": "",
+            "o": { "msg": "periodic noop" },
+        }).unwrap();
+        assert_eq!(OplogOperation::Other(String::from("xi")), entry.operation);
+        assert_eq!(None, entry.document_id());
+
+        assert!(OplogEntry::from_document(doc! { "op": "n" }).is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn reopens_after_the_last_entry() {
+        use mock::MockServer;
+        use stream::StreamConnector;
+        use ClientOptions;
+
+        let server = MockServer::new();
+        let entries = vec![
+            Bson::from(doc! { "ts": Bson::TimeStamp(11), "op": "i", "ns": "a.b", "o": {} }),
+            Bson::from(doc! { "ts": Bson::TimeStamp(12), "op": "d", "ns": "a.b", "o": {} }),
+        ];
+        server.reply_to("find", doc! { "cursor": { "id": 0_i64, "firstBatch": entries } });
+        server.reply_to("find", doc! { "ok": 0, "errmsg": "not master", "code": 10107 });
+
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let mut oplog = Oplog::new(client, 10, None).unwrap();
+        assert_eq!(11, oplog.try_next().unwrap().unwrap().ts);
+        assert_eq!(12, oplog.try_next().unwrap().unwrap().ts);
+        assert_eq!(None, oplog.try_next().unwrap());
+        assert_eq!(12, oplog.last_timestamp());
+
+        // The failed reopening is retried, after the last entry returned.
+        assert_eq!(None, oplog.try_next().unwrap());
+
+        let finds: Vec<_> = server
+            .commands()
+            .into_iter()
+            .filter(|command| command.name == "find")
+            .map(|command| command.command.get_document("filter").unwrap().clone())
+            .collect();
+        assert_eq!(3, finds.len());
+        assert_eq!(doc! { "ts": { "$gt": Bson::TimeStamp(10) } }, finds[0]);
+        assert_eq!(doc! { "ts": { "$gt": Bson::TimeStamp(12) } }, finds[2]);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn moves_past_malformed_entries() {
+        use mock::MockServer;
+        use stream::StreamConnector;
+        use ClientOptions;
+
+        let server = MockServer::new();
+        let entries = vec![
+            Bson::from(doc! { "ts": Bson::TimeStamp(11), "op": "i", "ns": "a.b", "o": {} }),
+            Bson::from(doc! { "ts": Bson::TimeStamp(12), "ns": "a.b", "o": {} }),
+            Bson::from(doc! { "ts": Bson::TimeStamp(13), "op": "d", "ns": "a.b", "o": {} }),
+        ];
+        server.reply_to("find", doc! { "cursor": { "id": 0_i64, "firstBatch": entries } });
+
+        let mut options = ClientOptions::new();
+        options.stream_connector = StreamConnector::Mock(server.clone());
+        let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+        let mut oplog = Oplog::new(client, 10, None).unwrap();
+        assert_eq!(11, oplog.try_next().unwrap().unwrap().ts);
+        assert!(oplog.try_next().is_err());
+        assert_eq!(12, oplog.last_timestamp());
+        assert_eq!(13, oplog.try_next().unwrap().unwrap().ts);
+        assert_eq!(None, oplog.try_next().unwrap());
+
+        // Reopening resumes after the last entry, malformed or not.
+        assert_eq!(None, oplog.try_next().unwrap());
+        let filters: Vec<_> = server
+            .commands()
+            .into_iter()
+            .filter(|command| command.name == "find")
+            .map(|command| command.command.get_document("filter").unwrap().clone())
+            .collect();
+        assert_eq!(doc! { "ts": { "$gt": Bson::TimeStamp(13) } }, filters[1]);
+    }
+}
@@ -0,0 +1,44 @@
+//! Recovery from poisoned locks.
+//!
+//! A thread that panics while holding a connection pool or topology lock poisons it, after
+//! which every `lock`, `read` or `write` would fail. The state behind these locks is only
+//! changed a field at a time and remains usable, so the guard is taken back instead of
+//! failing every later server selection and connection checkout.
+use std::sync::{LockResult, PoisonError};
+
+pub trait Recover {
+    type Guard;
+
+    /// Returns the guard, whether or not the lock was poisoned.
+    fn recover(self) -> Self::Guard;
+}
+
+impl<G> Recover for LockResult<G> {
+    type Guard = G;
+
+    fn recover(self) -> G {
+        self.unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[test]
+    fn recovers_poisoned_mutex() {
+        let mutex = Arc::new(Mutex::new(1));
+
+        let poisoner = mutex.clone();
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poisoning the lock");
+        }).join();
+
+        assert!(mutex.is_poisoned());
+        *mutex.lock().recover() += 1;
+        assert_eq!(2, *mutex.lock().recover());
+    }
+}
@@ -1,13 +1,16 @@
 //! Connection pooling for a single MongoDB server.
 use std::collections::VecDeque;
 use std::fmt;
+use std::io::Write;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Condvar, Mutex, TryLockError};
+use std::thread;
 use std::time::{Duration, Instant};
 
 use bson::{bson, doc};
 use bufstream::BufStream;
 
+use apm::{CredentialFallback, EventRunner};
 use auth::Authenticator;
 use coll::options::FindOptions;
 use command_type::CommandType;
@@ -15,12 +18,79 @@ use connstring::Host;
 use cursor::Cursor;
 use error::Error::{self, ArgumentError, OperationError};
 use error::Result;
+use metrics;
+use poison::Recover;
 use stream::{Stream, StreamConnector};
+use wire_protocol::buffer_pool::BufferPool;
 use wire_protocol::flags::OpQueryFlags;
+use wire_protocol::operations::Message;
 use Client;
 
 pub static DEFAULT_POOL_SIZE: usize = 5;
 pub static DEFAULT_TIMEOUT_ON_IDLE: Duration = Duration::from_secs(30);
+pub static DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+// The size of the buffer streamed binaries and held messages are written through.
+const DEFAULT_WRITE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// When messages whose replies nobody waits for are sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FlushMode {
+    /// Every message is sent as soon as it is written.
+    Immediate,
+    /// `killCursors` for dropped cursors and unacknowledged writes are held in the write
+    /// buffer of their connection, and sent in the same write as the next message on it.
+    /// Their replies are read and discarded before that message's reply. Held writes are
+    /// intercepted and monitored like any other command; only when they are sent changes.
+    /// Held messages are sent when their connection is closed, should it be closed first.
+    Coalesce,
+}
+
+impl Default for FlushMode {
+    fn default() -> Self {
+        FlushMode::Immediate
+    }
+}
+
+/// Which idle connection a checkout takes from the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CheckoutStrategy {
+    /// The most recently returned connection, so that bursts are served by a few hot
+    /// connections while the rest sit idle long enough to be closed.
+    Lifo,
+    /// The least recently returned connection, spreading use evenly over every open one.
+    Fifo,
+}
+
+impl Default for CheckoutStrategy {
+    fn default() -> Self {
+        CheckoutStrategy::Lifo
+    }
+}
+
+/// How many connections a pool holds, as reported by `ConnectionPool::stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The maximum number of open connections.
+    pub size: usize,
+    /// The number of open connections, whether idle or checked out.
+    pub open: usize,
+    /// The number of open connections waiting in the pool.
+    pub idle: usize,
+}
+
+impl PoolStats {
+    /// Returns the number of connections currently checked out.
+    pub fn checked_out(&self) -> usize {
+        self.open.saturating_sub(self.idle)
+    }
+
+    /// Returns whether every connection the pool may open is checked out, so that further
+    /// checkouts wait for one to be returned.
+    pub fn is_saturated(&self) -> bool {
+        self.checked_out() >= self.size
+    }
+}
 
 /// Handles threaded connections to a MongoDB server.
 #[derive(Clone)]
@@ -32,7 +102,6 @@ pub struct ConnectionPool {
     // A condition variable used for threads waiting for the pool
     // to be repopulated with available connections.
     wait_lock: Arc<Condvar>,
-    idle_connection_timeout: Duration,
     stream_connector: StreamConnector,
 }
 
@@ -49,11 +118,50 @@ struct Pool {
     pub size: usize,
     // The current number of open connections.
     pub len: Arc<AtomicUsize>,
-    // The idle socket pool.
-    sockets: VecDeque<(BufStream<Stream>, Instant)>,
+    // The idle socket pool, with how many held messages each socket has not read the
+    // replies of.
+    sockets: VecDeque<(BufStream<Stream>, usize, Instant)>,
     // The pool iteration. When a server monitor fails to execute ismaster,
     // the connection pool is cleared and the iteration is incremented.
     iteration: usize,
+    // Whether the pool refuses checkouts and closes connections as they are returned.
+    draining: bool,
+    // How long a connection may sit unused in the pool before it is closed.
+    idle_connection_timeout: Duration,
+    // The size of the buffer replies are read through on new connections.
+    read_buffer_size: usize,
+    // Whether new connections disable Nagle's algorithm.
+    nodelay: bool,
+    // Which idle socket a checkout takes.
+    checkout_strategy: CheckoutStrategy,
+}
+
+impl Pool {
+    // Takes the idle socket the checkout strategy selects.
+    fn pop_idle(&mut self) -> Option<(BufStream<Stream>, usize, Instant)> {
+        match self.checkout_strategy {
+            CheckoutStrategy::Lifo => self.sockets.pop_back(),
+            CheckoutStrategy::Fifo => self.sockets.pop_front(),
+        }
+    }
+}
+
+// Whether an idle socket must be closed rather than checked out. Sockets with held messages
+// expect the replies to them, so only a closed one is.
+fn is_stale(socket: &BufStream<Stream>, held_replies: usize) -> bool {
+    if held_replies > 0 {
+        socket.get_ref().is_closed()
+    } else {
+        socket.get_ref().is_stale()
+    }
+}
+
+// Closes a socket that leaves the pool, first sending the messages it holds, so that held
+// unacknowledged writes are not lost.
+fn close(mut socket: BufStream<Stream>, held_replies: usize) {
+    if held_replies > 0 {
+        let _ = socket.flush();
+    }
 }
 
 /// Holds an available socket, with logic to return the socket
@@ -70,6 +178,10 @@ pub struct PooledStream {
     iteration: usize,
     // Whether the handshake occurred successfully.
     successful_handshake: bool,
+    // The host the socket is connected to.
+    host: Host,
+    // How many held messages have replies that were not read yet.
+    held_replies: usize,
 }
 
 impl PooledStream {
@@ -77,6 +189,77 @@ impl PooledStream {
     pub fn get_socket(&mut self) -> &mut BufStream<Stream> {
         self.socket.as_mut().unwrap()
     }
+
+    /// Returns where to write the next message. While messages are held, this is the write
+    /// buffer behind them, so that they are sent together once the message is flushed;
+    /// otherwise messages skip the buffer.
+    pub fn writer(&mut self) -> &mut dyn Write {
+        let socket = self.socket.as_mut().unwrap();
+        if self.held_replies > 0 {
+            socket
+        } else {
+            socket.get_mut()
+        }
+    }
+
+    /// Returns the socket to read the reply of the last message from, once the replies of
+    /// the messages held before it were read and discarded.
+    pub fn reader(&mut self) -> Result<&mut BufStream<Stream>> {
+        let socket = self.socket.as_mut().unwrap();
+        if self.held_replies > 0 {
+            socket.flush()?;
+            while self.held_replies > 0 {
+                Message::read(socket)?;
+                self.held_replies -= 1;
+            }
+        }
+
+        Ok(socket)
+    }
+
+    /// Holds `message` in the write buffer of the socket, to be sent with the next message
+    /// written to it. Its reply is discarded.
+    pub fn hold(&mut self, message: &Message) -> Result<()> {
+        let mut bytes = BufferPool::shared().get();
+        message.write(&mut *bytes)?;
+
+        self.get_socket().write_all(&bytes)?;
+        self.held_replies += 1;
+        Ok(())
+    }
+
+    /// Returns how many held messages have replies that were not read yet.
+    pub fn held_replies(&self) -> usize {
+        self.held_replies
+    }
+
+    /// Returns the host the socket is connected to.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Closes the socket instead of returning it to the pool, e.g. after a message
+    /// was only partially written.
+    pub fn discard(&mut self) {
+        if !self.successful_handshake {
+            return;
+        }
+
+        self.successful_handshake = false;
+        debug!(
+            target: "mongodb::pool",
+            "Closing a connection to {}:{} instead of returning it to the pool",
+            self.host.host_name,
+            self.host.port
+        );
+
+        // The socket was counted as open when it was checked out.
+        let locked = self.pool.lock().recover();
+        if self.iteration == locked.iteration {
+            let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+            self.wait_lock.notify_all();
+        }
+    }
 }
 
 impl Drop for PooledStream {
@@ -86,16 +269,37 @@ impl Drop for PooledStream {
             return;
         }
 
-        // Attempt to lock and return the socket to the pool,
-        // or give up if the pool lock has been poisoned.
-        if let Ok(mut locked) = self.pool.lock() {
-            if self.iteration == locked.iteration {
-                locked
-                    .sockets
-                    .push_back((self.socket.take().unwrap(), Instant::now()));
-                // Notify waiting threads that the pool has been repopulated.
-                self.wait_lock.notify_one();
+        // A stream dropped while its thread unwinds may be halfway through a message, so it
+        // is closed rather than handed to the next caller.
+        if thread::panicking() {
+            self.discard();
+            return;
+        }
+
+        // Return the socket to the pool. The pool's state is only changed in single steps,
+        // so it stays consistent even if another thread panicked while holding the lock.
+        let mut locked = self.pool.lock().recover();
+        if self.iteration != locked.iteration {
+            return;
+        }
+
+        if locked.draining || locked.len.load(Ordering::SeqCst) > locked.size {
+            // Close the socket, waking both a drain waiting for the pool to empty and
+            // checkouts that must now fail. Pools shrunk by `set_size` also close their
+            // surplus connections this way.
+            let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+            self.wait_lock.notify_all();
+            drop(locked);
+
+            if let Some(socket) = self.socket.take() {
+                close(socket, self.held_replies);
             }
+        } else {
+            locked
+                .sockets
+                .push_back((self.socket.take().unwrap(), self.held_replies, Instant::now()));
+            // Notify waiting threads that the pool has been repopulated.
+            self.wait_lock.notify_one();
         }
     }
 }
@@ -126,9 +330,13 @@ impl ConnectionPool {
                 size,
                 sockets: VecDeque::with_capacity(size),
                 iteration: 0,
+                draining: false,
+                idle_connection_timeout,
+                read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+                nodelay: true,
+                checkout_strategy: CheckoutStrategy::default(),
             })),
             stream_connector: connector,
-            idle_connection_timeout,
         }
     }
 
@@ -139,39 +347,167 @@ impl ConnectionPool {
                 "The connection pool size must be greater than zero.",
             )))
         } else {
-            let mut locked = self.inner.lock()?;
+            let mut locked = self.inner.lock().recover();
             locked.size = size;
             Ok(())
         }
     }
 
+    /// Sets how long a connection may sit unused in the pool before it is closed.
+    pub fn set_idle_connection_timeout(&self, timeout: Duration) -> Result<()> {
+        self.inner.lock().recover().idle_connection_timeout = timeout;
+        Ok(())
+    }
+
+    /// Sets the size of the buffer replies are read through, so that a reply smaller than
+    /// the buffer is read off the socket with a single call. Only connections opened
+    /// afterwards use the new size.
+    pub fn set_read_buffer_size(&self, size: usize) -> Result<()> {
+        if size < 1 {
+            Err(ArgumentError(String::from(
+                "The read buffer size must be greater than zero.",
+            )))
+        } else {
+            self.inner.lock().recover().read_buffer_size = size;
+            Ok(())
+        }
+    }
+
+    /// Sets whether connections disable Nagle's algorithm, sending small messages without
+    /// waiting to coalesce them; the default. Only connections opened afterwards use the new
+    /// setting.
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        self.inner.lock().recover().nodelay = nodelay;
+        Ok(())
+    }
+
+    /// Sets which idle connection a checkout takes; `CheckoutStrategy::Lifo` by default.
+    pub fn set_checkout_strategy(&self, strategy: CheckoutStrategy) -> Result<()> {
+        self.inner.lock().recover().checkout_strategy = strategy;
+        Ok(())
+    }
+
     // Clear all open socket connections.
     pub fn clear(&self) {
-        if let Ok(mut locked) = self.inner.lock() {
+        debug!(
+            target: "mongodb::pool",
+            "Closing every connection to {}:{}",
+            self.host.host_name,
+            self.host.port
+        );
+
+        let idle: Vec<_> = {
+            let mut locked = self.inner.lock().recover();
             locked.iteration += 1;
-            locked.sockets.clear();
             locked.len.store(0, Ordering::SeqCst);
+            locked.sockets.drain(..).collect()
+        };
+
+        for (socket, held_replies, _) in idle {
+            close(socket, held_replies);
+        }
+    }
+
+    /// Stops handing out connections and closes them as they are returned, waiting up to
+    /// `deadline` for every checked-out connection to come back.
+    ///
+    /// Returns how many connections were still checked out at the deadline; those are
+    /// closed whenever they are returned. The pool keeps refusing checkouts until `resume`
+    /// is called.
+    pub fn drain(&self, deadline: Duration) -> Result<usize> {
+        let end = Instant::now() + deadline;
+
+        let idle: Vec<_> = {
+            let mut locked = self.inner.lock().recover();
+            locked.draining = true;
+            let idle: Vec<_> = locked.sockets.drain(..).collect();
+            let _ = locked.len.fetch_sub(idle.len(), Ordering::SeqCst);
+            idle
+        };
+
+        // Fail checkouts waiting for a connection.
+        self.wait_lock.notify_all();
+
+        for (socket, held_replies, _) in idle {
+            close(socket, held_replies);
+        }
+
+        let mut locked = self.inner.lock().recover();
+        loop {
+            let open = locked.len.load(Ordering::SeqCst);
+            let now = Instant::now();
+
+            if open == 0 || now >= end {
+                info!(
+                    target: "mongodb::pool",
+                    "Drained the connection pool for {}:{}; {} connections still checked out",
+                    self.host.host_name,
+                    self.host.port,
+                    open
+                );
+                return Ok(open);
+            }
+
+            locked = self.wait_lock.wait_timeout(locked, end - now).recover().0;
+        }
+    }
+
+    /// Allows connections to be checked out again after a `drain`.
+    pub fn resume(&self) -> Result<()> {
+        self.inner.lock().recover().draining = false;
+        info!(
+            target: "mongodb::pool",
+            "Resumed the connection pool for {}:{}",
+            self.host.host_name,
+            self.host.port
+        );
+        Ok(())
+    }
+
+    /// Returns whether the pool is draining.
+    pub fn is_draining(&self) -> Result<bool> {
+        Ok(self.inner.lock().recover().draining)
+    }
+
+    /// Returns how many connections the pool holds.
+    pub fn stats(&self) -> PoolStats {
+        let locked = self.inner.lock().recover();
+        PoolStats {
+            size: locked.size,
+            open: locked.len.load(Ordering::SeqCst),
+            idle: locked.sockets.len(),
         }
     }
 
     pub fn prune_idle(&self) {
-        if let Ok(mut locked) = self.inner.lock() {
-            let len = locked.len.load(Ordering::SeqCst);
-            if len > 1 {
-                let mut prune_front = false;
-
-                {
-                    if let Some(front) = locked.sockets.front() {
-                        if Instant::now().duration_since(front.1.clone()) > DEFAULT_TIMEOUT_ON_IDLE
-                        {
-                            prune_front = true;
-                        }
+        let mut locked = self.inner.lock().recover();
+        let len = locked.len.load(Ordering::SeqCst);
+        if len > 1 {
+            let mut prune_front = false;
+
+            {
+                if let Some(front) = locked.sockets.front() {
+                    if Instant::now().duration_since(front.2.clone()) >
+                        locked.idle_connection_timeout
+                    {
+                        prune_front = true;
                     }
                 }
+            }
 
-                if prune_front {
-                    locked.sockets.pop_front();
-                    let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+            if prune_front {
+                trace!(
+                    target: "mongodb::pool",
+                    "Closing an idle connection to {}:{}",
+                    self.host.host_name,
+                    self.host.port
+                );
+                let pruned = locked.sockets.pop_front();
+                let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+                drop(locked);
+
+                if let Some((socket, held_replies, _)) = pruned {
+                    close(socket, held_replies);
                 }
             }
         }
@@ -181,7 +517,28 @@ impl ConnectionPool {
     /// the pool has not reached its maximum size, a new socket will connect.
     /// Otherwise, the function will block until a socket is returned to the pool.
     pub fn acquire_stream(&self, client: Client) -> Result<PooledStream> {
-        let mut locked = self.inner.lock()?;
+        let server = format!("{}:{}", self.host.host_name, self.host.port);
+        let labels = [("server", &server[..])];
+        let _span = enter_span!(DEBUG, "mongodb.checkout", server = &server[..]);
+
+        match self.checkout(client.clone()) {
+            Ok((stream, open)) => {
+                client.metrics.counter(metrics::POOL_CHECKOUTS, &labels, 1);
+                client.metrics.gauge(metrics::POOL_CONNECTIONS, &labels, open as f64);
+                Ok(stream)
+            }
+            Err(err) => {
+                client.metrics.counter(metrics::POOL_CHECKOUT_FAILURES, &labels, 1);
+                client.health.record_failure(&err);
+                Err(err)
+            }
+        }
+    }
+
+    // Checks out a socket as described for `acquire_stream`, also returning how many
+    // connections are open.
+    fn checkout(&self, client: Client) -> Result<(PooledStream, usize)> {
+        let mut locked = self.inner.lock().recover();
         if locked.size == 0 {
             return Err(OperationError(String::from(
                 "The connection pool does not allow connections; increase the size of the pool.",
@@ -189,56 +546,200 @@ impl ConnectionPool {
         }
 
         loop {
-            // Acquire available existing socket
-            if let Some((stream, _)) = locked.sockets.pop_back() {
-                return Ok(PooledStream {
+            if locked.draining {
+                return Err(OperationError(format!(
+                    "The connection pool for {}:{} is draining.",
+                    self.host.host_name,
+                    self.host.port
+                )));
+            }
+
+            // Acquire available existing socket, discarding any that were closed while idle.
+            if let Some((stream, held_replies, _)) = locked.pop_idle() {
+                if is_stale(&stream, held_replies) {
+                    trace!(
+                        target: "mongodb::pool",
+                        "Discarding a connection to {}:{} closed while idle",
+                        self.host.host_name,
+                        self.host.port
+                    );
+                    let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+                    close(stream, held_replies);
+                    continue;
+                }
+
+                let stream = PooledStream {
                     socket: Some(stream),
                     pool: self.inner.clone(),
                     wait_lock: self.wait_lock.clone(),
                     iteration: locked.iteration,
                     successful_handshake: true,
-                });
+                    host: self.host.clone(),
+                    held_replies: held_replies,
+                };
+
+                return Ok((stream, locked.len.load(Ordering::SeqCst)));
             }
 
             // Attempt to make a new connection
             let len = locked.len.load(Ordering::SeqCst);
             if len < locked.size {
-                let socket = self.connect()?;
+                debug!(
+                    target: "mongodb::pool",
+                    "Opening connection {} of at most {} to {}:{}",
+                    len + 1,
+                    locked.size,
+                    self.host.host_name,
+                    self.host.port
+                );
+
+                let socket = self.connect(locked.read_buffer_size, locked.nodelay)?;
                 let mut stream = PooledStream {
                     socket: Some(socket),
                     pool: self.inner.clone(),
                     wait_lock: self.wait_lock.clone(),
                     iteration: locked.iteration,
                     successful_handshake: false,
+                    host: self.host.clone(),
+                    held_replies: 0,
                 };
 
                 self.handshake(client.clone(), &mut stream)?;
 
-                // authentication
-                if let (Some(user), Some(password)) = (
-                    client.topology.config.user.clone(),
-                    client.topology.config.password.clone(),
-                ) {
-                    let _ = Authenticator::new(&mut stream, client).auth(&user, &password);
+                let fallback = self.authenticate(&client, &mut stream)?;
+
+                let open = locked.len.fetch_add(1, Ordering::SeqCst) + 1;
+                drop(locked);
+
+                if let Some(fallback) = fallback {
+                    warn!(
+                        target: "mongodb::pool",
+                        "{} was rejected by {}; authenticated as {} instead",
+                        fallback.rejected_user,
+                        fallback.connection_string,
+                        fallback.user
+                    );
+                    let _ = client.run_credential_fallback_hooks(&fallback);
                 }
 
-                let _ = locked.len.fetch_add(1, Ordering::SeqCst);
-                return Ok(stream);
+                return Ok((stream, open));
             }
 
             // Release lock and wait for pool to be repopulated
-            locked = self.wait_lock.wait(locked)?;
+            locked = self.wait_lock.wait(locked).recover();
+        }
+    }
+
+    /// Returns an idle connected socket if one is available, without connecting or waiting,
+    /// including for the pool's lock while another thread connects.
+    pub fn try_acquire_idle_stream(&self) -> Option<PooledStream> {
+        let mut locked = match self.inner.try_lock() {
+            Ok(locked) => locked,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+
+        if locked.draining {
+            return None;
+        }
+
+        while let Some((stream, held_replies, _)) = locked.pop_idle() {
+            if is_stale(&stream, held_replies) {
+                let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+                close(stream, held_replies);
+                continue;
+            }
+
+            return Some(PooledStream {
+                socket: Some(stream),
+                pool: self.inner.clone(),
+                wait_lock: self.wait_lock.clone(),
+                iteration: locked.iteration,
+                successful_handshake: true,
+                host: self.host.clone(),
+                held_replies: held_replies,
+            });
+        }
+
+        None
+    }
+
+    // Authenticates a new connection with the topology's current credential, falling back to
+    // the previous one during a rotation. Returns the fallback to report, if one occurred.
+    fn authenticate(
+        &self,
+        client: &Client,
+        stream: &mut PooledStream,
+    ) -> Result<Option<CredentialFallback>> {
+        let credentials = client.topology.credentials.read()?.clone();
+
+        let current = match credentials.current {
+            Some(current) => current,
+            None => return Ok(None),
+        };
+
+        let failure = match Authenticator::new(stream, client.clone())
+            .auth(&current.user, &current.password)
+        {
+            Ok(()) => {
+                debug!(
+                    target: "mongodb::pool",
+                    "Authenticated as {} on {}:{}",
+                    current.user,
+                    self.host.host_name,
+                    self.host.port
+                );
+                return Ok(None);
+            }
+            Err(err) => err,
+        };
+
+        warn!(
+            target: "mongodb::pool",
+            "Authentication as {} on {}:{} failed: {}",
+            current.user,
+            self.host.host_name,
+            self.host.port,
+            failure
+        );
+
+        let previous = match credentials.previous {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+
+        match Authenticator::new(stream, client.clone()).auth(&previous.user, &previous.password) {
+            Ok(()) => Ok(Some(CredentialFallback {
+                rejected_user: current.user,
+                user: previous.user,
+                failure: failure.to_string(),
+                connection_string: format!("{}:{}", self.host.host_name, self.host.port),
+            })),
+            Err(_) => Ok(None),
         }
     }
 
     // Connects to a MongoDB server as defined by the initial configuration.
-    fn connect(&self) -> Result<BufStream<Stream>> {
-        match self
+    fn connect(&self, read_buffer_size: usize, nodelay: bool) -> Result<BufStream<Stream>> {
+        let connected = self
             .stream_connector
             .connect(&self.host.host_name[..], self.host.port)
-        {
-            Ok(s) => Ok(BufStream::new(s)),
-            Err(e) => Err(Error::from(e)),
+            .and_then(|s| s.set_nodelay(nodelay).map(|()| s));
+
+        match connected {
+            Ok(s) => {
+                Ok(BufStream::with_capacities(read_buffer_size, DEFAULT_WRITE_BUFFER_SIZE, s))
+            }
+            Err(e) => {
+                warn!(
+                    target: "mongodb::pool",
+                    "Failed to connect to {}:{}: {}",
+                    self.host.host_name,
+                    self.host.port,
+                    e
+                );
+                Err(Error::from(e))
+            }
         }
     }
 
@@ -281,3 +782,67 @@ impl ConnectionPool {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn checkout_strategy_selects_idle_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let host = Host {
+            host_name: String::from("127.0.0.1"),
+            ipc: String::new(),
+            port: port,
+        };
+        let pool = ConnectionPool::new(host, StreamConnector::Tcp);
+
+        // Sockets are told apart by their held reply counts, and returned 0, 1, then 2.
+        let mut locked = pool.inner.lock().recover();
+        for marker in 0..3 {
+            let stream = StreamConnector::Tcp.connect("127.0.0.1", port).unwrap();
+            locked.sockets.push_back((BufStream::new(stream), marker, Instant::now()));
+        }
+
+        assert_eq!(Some(2), locked.pop_idle().map(|idle| idle.1));
+        locked.checkout_strategy = CheckoutStrategy::Fifo;
+        assert_eq!(Some(0), locked.pop_idle().map(|idle| idle.1));
+        assert_eq!(Some(1), locked.pop_idle().map(|idle| idle.1));
+        assert!(locked.pop_idle().is_none());
+    }
+
+    #[test]
+    fn keeps_sockets_awaiting_held_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = StreamConnector::Tcp.connect("127.0.0.1", port).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        // The replies to held messages arrive while the socket is idle.
+        peer.write_all(&[0; 16]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let socket = BufStream::new(stream);
+        assert!(is_stale(&socket, 0));
+        assert!(!is_stale(&socket, 1));
+    }
+
+    #[test]
+    fn sends_held_messages_before_closing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = StreamConnector::Tcp.connect("127.0.0.1", port).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        let mut socket = BufStream::new(stream);
+        socket.write_all(b"held").unwrap();
+        close(socket, 1);
+
+        let mut received = Vec::new();
+        peer.read_to_end(&mut received).unwrap();
+        assert_eq!(b"held".to_vec(), received);
+    }
+}
@@ -1,16 +1,49 @@
 use crate::{
-    connstring::ConnectionString, db::ThreadedDatabase, Client, ClientOptions, ThreadedClient,
+    coll::Collection, common::ReadPreference, connstring::ConnectionString, db::ThreadedDatabase,
+    Client, ClientOptions, ThreadedClient,
 };
 
+use std::time::Duration;
+
+// Connects a client, failing unless a stream to a server can be acquired.
+fn connect_client(
+    conn_str: &ConnectionString,
+    client_options: &Option<ClientOptions>,
+) -> Result<Client, crate::error::Error> {
+    let client = Client::with_config(conn_str.clone(), client_options.clone(), None)?;
+
+    // Try to acquire a stream to establish a connection. If we can't, the connection can't be used.
+    client.acquire_stream(client.current_read_preference())?;
+
+    Ok(client)
+}
+
+// Pings the server, unless a command succeeded on the client within `max_staleness`.
+fn validate(client: &Client, max_staleness: Option<Duration>) -> Result<(), crate::error::Error> {
+    let health = client.connection_health();
+    match (health.since_last_success, max_staleness) {
+        (Some(since), Some(max)) if !health.broken && since <= max => Ok(()),
+        _ => client.ping(),
+    }
+}
+
 /// A basic r2d2 connection manager for this driver.
 ///
 /// - returns a Database object matching the provided database name, not a Client
 /// - takes a parsed connection string and client options
+/// - reports connections broken when their last command failed with a network error, as do
+///   the other managers
+/// - validates connections with a `ping`, skipped for connections used within
+///   `max_staleness`
+/// - opens the database with the configured `read_preference`; checked out databases open
+///   others with `sibling` and other read preferences with `with_read_preference`
 #[derive(Debug)]
 pub struct MongoConnectionManager {
     conn_str: ConnectionString,
     db_name: String,
     client_options: Option<ClientOptions>,
+    max_staleness: Option<Duration>,
+    read_preference: Option<ReadPreference>,
 }
 
 impl MongoConnectionManager {
@@ -23,8 +56,23 @@ impl MongoConnectionManager {
             conn_str: connection_str,
             db_name: db_name.into(),
             client_options: client_options.into(),
+            max_staleness: None,
+            read_preference: None,
         }
     }
+
+    /// Sets the read preference of the databases handed out, in place of the client's.
+    pub fn read_preference(mut self, read_preference: ReadPreference) -> Self {
+        self.read_preference = Some(read_preference);
+        self
+    }
+
+    /// Skips the `ping` validating a connection if a command succeeded on it within
+    /// `max_staleness`.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
 }
 
 impl r2d2::ManageConnection for MongoConnectionManager {
@@ -32,20 +80,123 @@ impl r2d2::ManageConnection for MongoConnectionManager {
     type Error = crate::error::Error;
 
     fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        let client = Client::with_config(self.conn_str.clone(), self.client_options.clone(), None)?;
+        let client = connect_client(&self.conn_str, &self.client_options)?;
+        Ok(client.db_with_prefs(&self.db_name, self.read_preference.clone(), None))
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        validate(&conn.client, self.max_staleness)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.client.connection_health().broken
+    }
+}
+
+/// An r2d2 connection manager handing out clients, for code that works with several
+/// databases or runs admin commands.
+///
+/// - returns a Client connected with the provided connection string and client options
+#[derive(Debug)]
+pub struct MongoClientManager {
+    conn_str: ConnectionString,
+    client_options: Option<ClientOptions>,
+    max_staleness: Option<Duration>,
+}
+
+impl MongoClientManager {
+    pub fn new<CO>(connection_str: ConnectionString, client_options: CO) -> Self
+    where
+        CO: Into<Option<ClientOptions>>,
+    {
+        Self {
+            conn_str: connection_str,
+            client_options: client_options.into(),
+            max_staleness: None,
+        }
+    }
+
+    /// Skips the `ping` validating a connection if a command succeeded on it within
+    /// `max_staleness`.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+}
+
+impl r2d2::ManageConnection for MongoClientManager {
+    type Connection = Client;
+    type Error = crate::error::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        connect_client(&self.conn_str, &self.client_options)
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        validate(conn, self.max_staleness)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.connection_health().broken
+    }
+}
+
+/// An r2d2 connection manager handing out a collection.
+///
+/// - returns a Collection matching the provided database and collection names
+/// - takes a parsed connection string and client options
+#[derive(Debug)]
+pub struct MongoCollectionManager {
+    conn_str: ConnectionString,
+    db_name: String,
+    coll_name: String,
+    client_options: Option<ClientOptions>,
+    max_staleness: Option<Duration>,
+}
+
+impl MongoCollectionManager {
+    pub fn new<S, T, CO>(
+        connection_str: ConnectionString,
+        db_name: S,
+        coll_name: T,
+        client_options: CO,
+    ) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+        CO: Into<Option<ClientOptions>>,
+    {
+        Self {
+            conn_str: connection_str,
+            db_name: db_name.into(),
+            coll_name: coll_name.into(),
+            client_options: client_options.into(),
+            max_staleness: None,
+        }
+    }
 
-        // Try to acquire a stream to establish a connection. If we can't, the connection can't be used.
-        client.acquire_stream(client.read_preference.clone())?;
+    /// Skips the `ping` validating a connection if a command succeeded on it within
+    /// `max_staleness`.
+    pub fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+}
+
+impl r2d2::ManageConnection for MongoCollectionManager {
+    type Connection = Collection;
+    type Error = crate::error::Error;
 
-        Ok(client.db(&self.db_name))
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let client = connect_client(&self.conn_str, &self.client_options)?;
+        Ok(client.db(&self.db_name).collection(&self.coll_name))
     }
 
     fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
-        conn.version()?;
-        Ok(())
+        validate(&conn.db.client, self.max_staleness)
     }
 
-    fn has_broken(&self, _: &mut Self::Connection) -> bool {
-        false
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.db.client.connection_health().broken
     }
 }
@@ -0,0 +1,214 @@
+//! Client-side throttling of operations by namespace and type.
+//!
+//! Each `RateLimit` is a token bucket shared by every operation it matches. An operation
+//! that finds its buckets empty waits for them to refill before it checks out a connection,
+//! so throttled work holds no connection while it waits.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ClientOptions, CommandType, ThreadedClient};
+//! # use mongodb::rate_limit::RateLimit;
+//! #
+//! # fn main() {
+//! let mut options = ClientOptions::new();
+//! options.rate_limits.push(RateLimit {
+//!     namespace: Some(String::from("reports")),
+//!     command_type: Some(CommandType::Aggregate),
+//!     per_second: 5.0,
+//!     burst: 10,
+//! });
+//!
+//! let client = Client::with_uri_and_options("mongodb://localhost:27017", options).unwrap();
+//! # }
+//! ```
+use bson::{self, Bson};
+
+use poison::Recover;
+use CommandType;
+use Error::ArgumentError;
+use Result;
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits how often the operations matching a namespace and operation type may start.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// The namespace to throttle: a database name matches all of its collections, and
+    /// `db.collection` a single collection. None matches every namespace.
+    pub namespace: Option<String>,
+    /// The operation type to throttle, or None for all of them.
+    pub command_type: Option<CommandType>,
+    /// How many operations may start per second on average.
+    pub per_second: f64,
+    /// How many operations may start back to back after an idle period.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    fn matches(&self, namespace: &str, command_type: CommandType) -> bool {
+        let namespace_matches = match self.namespace {
+            Some(ref limited) => {
+                namespace == limited ||
+                    (namespace.starts_with(limited.as_str()) &&
+                         namespace.as_bytes()[limited.len()] == b'.')
+            }
+            None => true,
+        };
+
+        namespace_matches && self.command_type.map_or(true, |limited| limited == command_type)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    // Tokens available; negative while operations are waiting on earlier reservations.
+    tokens: Mutex<(f64, Instant)>,
+}
+
+impl Bucket {
+    // Takes a token, returning how long to wait before it becomes available.
+    fn take(&self) -> Duration {
+        let mut state = self.tokens.lock().recover();
+        let (ref mut tokens, ref mut refilled) = *state;
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(*refilled);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+        *tokens = (*tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        *refilled = now;
+        *tokens -= 1.0;
+
+        if *tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            let wait = -*tokens / self.limit.per_second;
+            Duration::new(wait as u64, (wait.fract() * 1e9) as u32)
+        }
+    }
+}
+
+// The rate limits configured for a client.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    buckets: Vec<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Vec<RateLimit>) -> Result<RateLimiter> {
+        let mut buckets = Vec::with_capacity(limits.len());
+
+        for limit in limits {
+            if !(limit.per_second > 0.0 && limit.per_second.is_finite()) || limit.burst == 0 {
+                return Err(ArgumentError(String::from(
+                    "Rate limits must allow a positive rate and a burst of at least one.",
+                )));
+            }
+
+            buckets.push(Bucket {
+                tokens: Mutex::new((f64::from(limit.burst), Instant::now())),
+                limit: limit,
+            });
+        }
+
+        Ok(RateLimiter { buckets: buckets })
+    }
+
+    // Blocks until every limit matching the operation allows it to start.
+    pub fn acquire(&self, namespace: &str, command_type: CommandType) {
+        let wait = self.buckets
+            .iter()
+            .filter(|bucket| bucket.limit.matches(namespace, command_type))
+            .map(Bucket::take)
+            .max();
+
+        if let Some(wait) = wait {
+            if wait > Duration::from_secs(0) {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+// Returns the namespace a query or command sent to `namespace` operates on: commands sent to
+// `db.$cmd` name their collection as their first value.
+pub(crate) fn operation_namespace(namespace: &str, query: &bson::Document) -> String {
+    match namespace.rfind(".$cmd") {
+        Some(end) if end + 5 == namespace.len() => {
+            let db = &namespace[..end];
+            match query.iter().next() {
+                Some((_, &Bson::String(ref coll))) => format!("{}.{}", db, coll),
+                _ => String::from(db),
+            }
+        }
+        _ => String::from(namespace),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    fn limit(namespace: Option<&str>, command_type: Option<CommandType>) -> RateLimit {
+        RateLimit {
+            namespace: namespace.map(String::from),
+            command_type: command_type,
+            per_second: 1.0,
+            burst: 1,
+        }
+    }
+
+    #[test]
+    fn matches_namespaces_and_types() {
+        let db = limit(Some("reports"), None);
+        assert!(db.matches("reports", CommandType::Find));
+        assert!(db.matches("reports.daily", CommandType::Find));
+        assert!(!db.matches("reports2.daily", CommandType::Find));
+
+        let coll = limit(Some("reports.daily"), Some(CommandType::Aggregate));
+        assert!(coll.matches("reports.daily", CommandType::Aggregate));
+        assert!(!coll.matches("reports.daily", CommandType::Find));
+        assert!(!coll.matches("reports.weekly", CommandType::Aggregate));
+
+        assert!(limit(None, None).matches("any.thing", CommandType::InsertOne));
+    }
+
+    #[test]
+    fn waits_once_the_burst_is_used() {
+        let bucket = Bucket {
+            limit: RateLimit { per_second: 10.0, burst: 2, ..limit(None, None) },
+            tokens: Mutex::new((2.0, Instant::now())),
+        };
+
+        assert_eq!(Duration::from_secs(0), bucket.take());
+        assert_eq!(Duration::from_secs(0), bucket.take());
+
+        let wait = bucket.take();
+        assert!(wait > Duration::from_millis(50) && wait <= Duration::from_millis(100));
+
+        // Each waiting operation reserves its own token.
+        assert!(bucket.take() > wait);
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        assert!(RateLimiter::new(vec![RateLimit { burst: 0, ..limit(None, None) }]).is_err());
+        assert!(RateLimiter::new(vec![RateLimit { per_second: 0.0, ..limit(None, None) }])
+            .is_err());
+    }
+
+    #[test]
+    fn resolves_command_namespaces() {
+        assert_eq!("shop.orders", operation_namespace("shop.orders", &doc! { "a": 1 }));
+        assert_eq!(
+            "shop.orders",
+            operation_namespace("shop.$cmd", &doc! { "insert": "orders", "documents": [] })
+        );
+        assert_eq!("shop", operation_namespace("shop.$cmd", &doc! { "aggregate": 1 }));
+    }
+}
@@ -0,0 +1,69 @@
+//! Spans around operations, server selection, checkouts and command round trips.
+//!
+//! With the `tracing` feature, these macros create `tracing` spans, which nest under whatever
+//! span is current when the driver is called. Without it, they expand to nothing and their
+//! arguments are never evaluated.
+
+/// Stands in for spans and their guards without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+// Enters a span at the given level for the rest of the scope, e.g.
+// `let _span = enter_span!(DEBUG, "mongodb.checkout", server = %name);`.
+#[cfg(feature = "tracing")]
+macro_rules! enter_span {
+    ($level:ident, $($args:tt)*) => {
+        ::tracing::span!(::tracing::Level::$level, $($args)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! enter_span {
+    ($($args:tt)*) => {
+        ::spans::NoSpan
+    };
+}
+
+// Records a field declared as `::tracing::field::Empty` on the current span.
+#[cfg(feature = "tracing")]
+macro_rules! record_span {
+    ($field:expr, $value:expr) => {
+        ::tracing::Span::current().record($field, &::tracing::field::display($value));
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! record_span {
+    ($($args:tt)*) => {
+        ()
+    };
+}
+
+// Captures the current span, to enter it with `enter_captured!` on another thread.
+#[cfg(feature = "tracing")]
+macro_rules! current_span {
+    () => {
+        ::tracing::Span::current()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! current_span {
+    () => {
+        ::spans::NoSpan
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! enter_captured {
+    ($span:expr) => {
+        $span.entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! enter_captured {
+    ($span:expr) => {
+        $span
+    };
+}
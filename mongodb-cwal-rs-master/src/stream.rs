@@ -1,8 +1,11 @@
-use std::io::{BufReader, Read, Result, Write};
+use std::io::{BufReader, ErrorKind, IoSlice, Read, Result, Write};
 #[cfg(feature = "ssl")]
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
 
+#[cfg(feature = "test-util")]
+use mock::{MockServer, MockStream};
 #[cfg(feature = "ssl")]
 use openssl::ssl::{Ssl, SslContext, SslFiletype, SslMethod, SslOptions, SslStream, SslVerifyMode};
 
@@ -21,6 +24,9 @@ pub enum StreamConnector {
         key_file: Option<String>,
         verify_peer: bool,
     },
+    #[cfg(feature = "test-util")]
+    /// Connect to an in-memory server, answering with scripted replies.
+    Mock(MockServer),
 }
 
 impl Default for StreamConnector {
@@ -141,6 +147,8 @@ impl StreamConnector {
                     Err(e) => Err(Error::new(ErrorKind::Other, e)),
                 }
             }
+            #[cfg(feature = "test-util")]
+            StreamConnector::Mock(ref server) => Ok(Stream::Mock(server.connect(hostname, port))),
         }
     }
 }
@@ -152,6 +160,8 @@ pub enum Stream {
     },
     #[cfg(feature = "ssl")]
     Ssl(SslStream<TcpStream>),
+    #[cfg(feature = "test-util")]
+    Mock(MockStream),
 }
 
 impl Read for Stream {
@@ -162,6 +172,8 @@ impl Read for Stream {
             } => read_half.read(buf),
             #[cfg(feature = "ssl")]
             Stream::Ssl(ref mut s) => s.read(buf),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(ref mut s) => s.read(buf),
         }
     }
 }
@@ -174,6 +186,20 @@ impl Write for Stream {
             } => write_half.write(buf),
             #[cfg(feature = "ssl")]
             Stream::Ssl(ref mut s) => s.write(buf),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> Result<usize> {
+        match *self {
+            Stream::Tcp {
+                ref mut write_half, ..
+            } => write_half.write_vectored(bufs),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref mut s) => s.write_vectored(bufs),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(ref mut s) => s.write_vectored(bufs),
         }
     }
 
@@ -184,6 +210,8 @@ impl Write for Stream {
             } => write_half.flush(),
             #[cfg(feature = "ssl")]
             Stream::Ssl(ref mut s) => s.flush(),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(ref mut s) => s.flush(),
         }
     }
 }
@@ -194,6 +222,116 @@ impl Stream {
             Stream::Tcp { ref write_half, .. } => write_half.peer_addr(),
             #[cfg(feature = "ssl")]
             Stream::Ssl(ref stream) => stream.get_ref().peer_addr(),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(ref stream) => Ok(stream.peer_addr()),
+        }
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on the connection.
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        match *self {
+            Stream::Tcp { ref write_half, .. } => write_half.set_nodelay(nodelay),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => stream.get_ref().set_nodelay(nodelay),
+            #[cfg(feature = "test-util")]
+            Stream::Mock(_) => Ok(()),
         }
     }
+
+    /// Sets how long reads and writes on the connection may block before failing, or lets
+    /// them block indefinitely with `None`.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        let tcp = match *self {
+            Stream::Tcp { ref write_half, .. } => write_half,
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => stream.get_ref(),
+            // In-memory connections answer immediately.
+            #[cfg(feature = "test-util")]
+            Stream::Mock(_) => return Ok(()),
+        };
+
+        tcp.set_read_timeout(timeout)?;
+        tcp.set_write_timeout(timeout)
+    }
+
+    /// Checks whether an idle connection was closed by the peer, or by a firewall or proxy
+    /// in between, without blocking.
+    ///
+    /// The server never sends replies that were not requested, so data pending on an idle
+    /// plain connection is treated as stale as well. TLS connections can receive records of
+    /// their own, such as session tickets, so only a closed or failed one is stale.
+    pub fn is_stale(&self) -> bool {
+        self.is_idle_stale(true)
+    }
+
+    /// Checks whether a connection that still expects replies, such as those of held
+    /// messages, was closed or failed, without blocking.
+    pub fn is_closed(&self) -> bool {
+        self.is_idle_stale(false)
+    }
+
+    fn is_idle_stale(&self, unrequested: bool) -> bool {
+        let (tcp, pending_is_stale) = match *self {
+            Stream::Tcp { ref write_half, .. } => (write_half, unrequested),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => (stream.get_ref(), false),
+            // In-memory connections are never closed by the peer.
+            #[cfg(feature = "test-util")]
+            Stream::Mock(_) => return false,
+        };
+
+        if tcp.set_nonblocking(true).is_err() {
+            return true;
+        }
+
+        let mut buf = [0; 1];
+        let stale = match tcp.peek(&mut buf) {
+            Ok(0) => true,
+            Ok(_) => pending_is_stale,
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => false,
+            Err(_) => true,
+        };
+
+        tcp.set_nonblocking(false).is_err() || stale
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn detects_closed_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let stream = StreamConnector::Tcp.connect("127.0.0.1", port).unwrap();
+        let (peer, _) = listener.accept().unwrap();
+        assert!(!stream.is_stale());
+
+        drop(peer);
+        thread::sleep(Duration::from_millis(50));
+        assert!(stream.is_stale());
+        assert!(stream.is_closed());
+    }
+
+    #[test]
+    fn treats_unrequested_data_as_stale() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let stream = StreamConnector::Tcp.connect("127.0.0.1", port).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        assert!(!stream.is_stale());
+
+        peer.write_all(&[0; 16]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(stream.is_stale());
+
+        // Connections awaiting replies expect the data.
+        assert!(!stream.is_closed());
+    }
 }
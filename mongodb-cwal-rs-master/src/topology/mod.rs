@@ -7,13 +7,16 @@ use Error::{self, ArgumentError, OperationError};
 
 use bson::oid;
 
-use common::{ReadPreference, ReadMode};
+use common::{Credential, ReadPreference, ReadMode};
 use connstring::{ConnectionString, Host};
-use pool::PooledStream;
+use metrics;
+use pool::{CheckoutStrategy, PooledStream};
+use poison::Recover;
 use stream::StreamConnector;
 
 use rand::{thread_rng, Rng};
 
+use std::cmp;
 use std::collections::HashMap;
 use std::fmt;
 use std::i64;
@@ -57,6 +60,16 @@ pub struct TopologyDescription {
     /// This defines how long to block for server selection before
     /// returning an error. The default is 30 seconds.
     pub server_selection_timeout_ms: i64,
+    /// The connection pool size for servers in the topology, or `None` for the default.
+    pub pool_size: Option<usize>,
+    /// How long pooled connections may sit unused, or `None` for the default.
+    pub idle_connection_timeout: Option<Duration>,
+    /// The size of the buffer replies are read through, or `None` for the default.
+    pub read_buffer_size: Option<usize>,
+    /// Whether connections disable Nagle's algorithm, or `None` for the default.
+    pub tcp_nodelay: Option<bool>,
+    /// Which idle connection a checkout takes, or `None` for the default.
+    pub checkout_strategy: Option<CheckoutStrategy>,
     // The largest election id seen from a server in the topology.
     max_election_id: Option<oid::ObjectId>,
     // If true, all servers in the topology fall within the compatible
@@ -77,6 +90,11 @@ impl fmt::Debug for TopologyDescription {
             .field("heartbeat_frequency_ms", &self.heartbeat_frequency_ms)
             .field("local_threshold_ms", &self.local_threshold_ms)
             .field("server_selection_timeout_ms", &self.server_selection_timeout_ms)
+            .field("pool_size", &self.pool_size)
+            .field("idle_connection_timeout", &self.idle_connection_timeout)
+            .field("read_buffer_size", &self.read_buffer_size)
+            .field("tcp_nodelay", &self.tcp_nodelay)
+            .field("checkout_strategy", &self.checkout_strategy)
             .field("max_election_id", &self.max_election_id)
             .field("compatible", &self.compatible)
             .field("max_set_version", &self.max_set_version)
@@ -86,6 +104,27 @@ impl fmt::Debug for TopologyDescription {
     }
 }
 
+/// Size limits on write commands, as reported by the servers during the handshake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteLimits {
+    /// The largest document accepted, in bytes.
+    pub max_bson_object_size: i64,
+    /// The largest message accepted, in bytes.
+    pub max_message_size_bytes: i64,
+    /// The most write operations accepted in one command.
+    pub max_write_batch_size: i64,
+}
+
+impl Default for WriteLimits {
+    fn default() -> Self {
+        WriteLimits {
+            max_bson_object_size: 16 * 1024 * 1024,
+            max_message_size_bytes: 48000000,
+            max_write_batch_size: 1000,
+        }
+    }
+}
+
 /// Holds status and connection information about a server set.
 #[derive(Clone, Debug)]
 pub struct Topology {
@@ -93,6 +132,18 @@ pub struct Topology {
     pub config: ConnectionString,
     /// Monitored topology information.
     pub description: Arc<RwLock<TopologyDescription>>,
+    /// The credentials new connections authenticate with.
+    pub credentials: Arc<RwLock<Credentials>>,
+}
+
+/// The credentials new connections authenticate with.
+///
+/// While a credential rotation is in progress, connections the server refuses to
+/// authenticate with `current` try `previous` instead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub current: Option<Credential>,
+    pub previous: Option<Credential>,
 }
 
 impl FromStr for TopologyType {
@@ -117,6 +168,11 @@ impl Default for TopologyDescription {
             heartbeat_frequency_ms: DEFAULT_HEARTBEAT_FREQUENCY_MS,
             server_selection_timeout_ms: DEFAULT_SERVER_SELECTION_TIMEOUT_MS,
             local_threshold_ms: DEFAULT_LOCAL_THRESHOLD_MS,
+            pool_size: None,
+            idle_connection_timeout: None,
+            read_buffer_size: None,
+            tcp_nodelay: None,
+            checkout_strategy: None,
             servers: HashMap::new(),
             max_election_id: None,
             compatible: true,
@@ -133,20 +189,72 @@ impl TopologyDescription {
         TopologyDescription { stream_connector, ..Default::default() }
     }
 
+    /// Returns the lowest maximum wire version reported by any known server, or `None` if no
+    /// server has been successfully monitored yet.
+    pub fn lowest_max_wire_version(&self) -> Option<i64> {
+        self.servers
+            .values()
+            .map(|server| server.description.read().recover())
+            .filter(|description| description.server_type != ServerType::Unknown)
+            .map(|description| description.max_wire_version)
+            .min()
+    }
+
+    /// Returns the smallest write limits reported by any known server, falling back to the
+    /// defaults for limits no server has reported yet.
+    pub fn lowest_write_limits(&self) -> WriteLimits {
+        let mut limits: Option<WriteLimits> = None;
+
+        let descriptions = self.servers
+            .values()
+            .map(|server| server.description.read().recover())
+            .filter(|description| description.server_type != ServerType::Unknown);
+
+        for description in descriptions {
+            let reported = WriteLimits {
+                max_bson_object_size: description.max_bson_object_size,
+                max_message_size_bytes: description.max_message_size_bytes,
+                max_write_batch_size: description.max_write_batch_size,
+            };
+
+            limits = Some(match limits {
+                Some(current) => WriteLimits {
+                    max_bson_object_size: current.max_bson_object_size
+                        .min(reported.max_bson_object_size),
+                    max_message_size_bytes: current.max_message_size_bytes
+                        .min(reported.max_message_size_bytes),
+                    max_write_batch_size: current.max_write_batch_size
+                        .min(reported.max_write_batch_size),
+                },
+                None => reported,
+            });
+        }
+
+        let defaults = WriteLimits::default();
+
+        match limits {
+            Some(limits) => WriteLimits {
+                max_bson_object_size: positive_or(limits.max_bson_object_size,
+                                                  defaults.max_bson_object_size),
+                max_message_size_bytes: positive_or(limits.max_message_size_bytes,
+                                                    defaults.max_message_size_bytes),
+                max_write_batch_size: positive_or(limits.max_write_batch_size,
+                                                  defaults.max_write_batch_size),
+            },
+            None => defaults,
+        }
+    }
+
     /// Returns the nearest server stream, calculated by round trip time.
     fn get_nearest_from_vec(&self, client: Client, servers: &mut Vec<Host>) -> Result<(PooledStream, ServerType)> {
         servers.sort_by(|a, b| {
             let mut a_rtt = i64::MAX;
             let mut b_rtt = i64::MAX;
             if let Some(server) = self.servers.get(a) {
-                if let Ok(a_description) = server.description.read() {
-                    a_rtt = a_description.round_trip_time.unwrap_or(i64::MAX);
-                }
+                a_rtt = server.description.read().recover().round_trip_time.unwrap_or(i64::MAX);
             }
             if let Some(server) = self.servers.get(b) {
-                if let Ok(b_description) = server.description.read() {
-                    b_rtt = b_description.round_trip_time.unwrap_or(i64::MAX);
-                }
+                b_rtt = server.description.read().recover().round_trip_time.unwrap_or(i64::MAX);
             }
 
             a_rtt.cmp(&b_rtt)
@@ -155,12 +263,11 @@ impl TopologyDescription {
         // Iterate over each host until one's stream can be acquired.
         for host in servers {
             if let Some(server) = self.servers.get(host) {
-                if let Ok(description) = server.description.read() {
-                    if description.round_trip_time.is_none() {
-                        break;
-                    } else if let Ok(stream) = server.acquire_stream(client.clone()) {
-                        return Ok((stream, description.server_type));
-                    }
+                let description = server.description.read().recover();
+                if description.round_trip_time.is_none() {
+                    break;
+                } else if let Ok(stream) = server.acquire_stream(client.clone()) {
+                    return Ok((stream, description.server_type));
                 }
             }
         }
@@ -177,9 +284,7 @@ impl TopologyDescription {
 
             if let Some(server) = self.servers.get(&servers[index]) {
                 if let Ok(stream) = server.acquire_stream(client.clone()) {
-                    if let Ok(description) = server.description.read() {
-                        return Ok((stream, description.server_type));
-                    }
+                    return Ok((stream, server.description.read().recover().server_type));
                 }
             }
             servers.remove(index);
@@ -204,6 +309,15 @@ impl TopologyDescription {
             self.filter_hosts(&mut hosts, read_preference);
         }
 
+        // Walk the fallback chain before the implicit fallbacks of the read mode.
+        if hosts.is_empty() {
+            if let Some((fallback, rest)) = read_preference.fallbacks.split_first() {
+                let mut read_pref = fallback.clone();
+                read_pref.fallbacks.extend(rest.iter().cloned());
+                return self.acquire_stream(client, &read_pref);
+            }
+        }
+
         // Special case - If secondaries are found, by are filtered out by tag sets,
         // the topology should return any available primaries instead.
         if hosts.is_empty() && read_preference.mode == ReadMode::SecondaryPreferred {
@@ -301,7 +415,7 @@ impl TopologyDescription {
         for tags in &read_preference.tag_sets {
             for host in &*hosts {
                 if let Some(server) = self.servers.get(host) {
-                    let description = server.description.read().unwrap();
+                    let description = server.description.read().recover();
 
                     // Check whether the read preference tags are contained
                     // within the server description tags.
@@ -344,7 +458,7 @@ impl TopologyDescription {
                 {
                     // Retain primaries.
                     hosts.retain(|host| if let Some(server) = self.servers.get(host) {
-                        let description = server.description.read().unwrap();
+                        let description = server.description.read().recover();
                         description.server_type == ServerType::RSPrimary
                     } else {
                         false
@@ -359,7 +473,7 @@ impl TopologyDescription {
                 // Filter out hosts by the discovered matching tagset.
                 hosts.retain(|host| {
                     if let Some(server) = self.servers.get(host) {
-                        let description = server.description.read().unwrap();
+                        let description = server.description.read().recover();
 
                         // Validate tag sets.
                         for (key, val) in tag_filter {
@@ -393,11 +507,7 @@ impl TopologyDescription {
             {
                 // Initialize the value to the first server's round-trip-time, or i64::MAX.
                 if let Some(server) = self.servers.get(&hosts[0]) {
-                    if let Ok(description) = server.description.read() {
-                        description.round_trip_time.unwrap_or(i64::MAX)
-                    } else {
-                        i64::MAX
-                    }
+                    server.description.read().recover().round_trip_time.unwrap_or(i64::MAX)
                 } else {
                     i64::MAX
                 }
@@ -405,13 +515,12 @@ impl TopologyDescription {
             |acc, host| {
                 // Compare the previous shortest rtt with the host rtt.
                 if let Some(server) = self.servers.get(host) {
-                    if let Ok(description) = server.description.read() {
-                        let item_rtt = description.round_trip_time.unwrap_or(i64::MAX);
-                        if acc < item_rtt {
-                            return acc;
-                        } else {
-                            return item_rtt;
-                        }
+                    let item_rtt =
+                        server.description.read().recover().round_trip_time.unwrap_or(i64::MAX);
+                    if acc < item_rtt {
+                        return acc;
+                    } else {
+                        return item_rtt;
                     }
                 }
                 acc
@@ -428,10 +537,8 @@ impl TopologyDescription {
         // Filter hosts by the latency window [shortest_rtt, high_rtt].
         hosts.retain(|host| {
             if let Some(server) = self.servers.get(host) {
-                if let Ok(description) = server.description.read() {
-                    let rtt = description.round_trip_time.unwrap_or(i64::MAX);
-                    return shortest_rtt <= rtt && rtt <= high_rtt;
-                }
+                let rtt = server.description.read().recover().round_trip_time.unwrap_or(i64::MAX);
+                return shortest_rtt <= rtt && rtt <= high_rtt;
             }
             false
         });
@@ -456,10 +563,9 @@ impl TopologyDescription {
                         .keys()
                         .filter_map(|host| {
                             if let Some(server) = self.servers.get(host) {
-                                if let Ok(description) = server.description.read() {
-                                    if description.server_type == ServerType::RSPrimary {
-                                        return Some(host.clone());
-                                    }
+                                let server_type = server.description.read().recover().server_type;
+                                if server_type == ServerType::RSPrimary {
+                                    return Some(host.clone());
                                 }
                             }
                             None
@@ -491,7 +597,7 @@ impl TopologyDescription {
                     let mut hosts = Vec::new();
 
                     for (host, server) in &self.servers {
-                        if server.description.read()?.server_type == ServerType::Unknown {
+                        if server.description.read().recover().server_type == ServerType::Unknown {
                             continue;
                         }
 
@@ -506,7 +612,7 @@ impl TopologyDescription {
 
                 // Collect a list of primaries and secondaries in the set
                 for (host, server) in &self.servers {
-                    let stype = server.description.read().unwrap().server_type;
+                    let stype = server.description.read().recover().server_type;
                     match stype {
                         ServerType::RSPrimary => primaries.push(host.clone()),
                         ServerType::RSSecondary => secondaries.push(host.clone()),
@@ -572,7 +678,16 @@ impl TopologyDescription {
         run_monitor: bool,
     ) {
 
-        let stype = description.read().unwrap().server_type;
+        let stype = description.read().recover().server_type;
+        let previous_type = self.topology_type;
+        trace!(
+            target: "mongodb::topology",
+            "{}:{} is now {:?}",
+            host.host_name,
+            host.port,
+            stype
+        );
+
         match self.topology_type {
             TopologyType::Unknown => {
                 match stype {
@@ -639,12 +754,21 @@ impl TopologyDescription {
             }
             TopologyType::Single => (),
         }
+
+        if self.topology_type != previous_type {
+            info!(
+                target: "mongodb::topology",
+                "Topology changed from {:?} to {:?}",
+                previous_type,
+                self.topology_type
+            );
+        }
     }
 
     // Sets the correct replica set topology type.
     fn check_if_has_primary(&mut self) {
         for server in self.servers.values() {
-            let stype = server.description.read().unwrap().server_type;
+            let stype = server.description.read().recover().server_type;
             if stype == ServerType::RSPrimary {
                 self.topology_type = TopologyType::ReplicaSetWithPrimary;
                 return;
@@ -681,7 +805,7 @@ impl TopologyDescription {
             return;
         }
 
-        let description_set_name = description.read().unwrap().set_name.clone();
+        let description_set_name = description.read().recover().set_name.clone();
 
         if self.set_name.is_empty() {
             self.set_name = description_set_name;
@@ -694,7 +818,7 @@ impl TopologyDescription {
         }
 
         let (description_set_version, description_election_id) = {
-            let description_guard = description.read().unwrap();
+            let description_guard = description.read().recover();
 
             (
                 description_guard.set_version,
@@ -712,7 +836,7 @@ impl TopologyDescription {
                 // Stale primary
                 if let Some(server) = self.servers.get(&host) {
                     {
-                        let mut server_description = server.description.write().unwrap();
+                        let mut server_description = server.description.write().recover();
                         server_description.server_type = ServerType::Unknown;
                         server_description.set_name = String::new();
                         server_description.election_id = None;
@@ -735,7 +859,7 @@ impl TopologyDescription {
         // Invalidate any old primaries
         for (top_host, server) in &self.servers {
             if *top_host != host {
-                let mut server_description = server.description.write().unwrap();
+                let mut server_description = server.description.write().recover();
                 if server_description.server_type == ServerType::RSPrimary {
                     server_description.server_type = ServerType::Unknown;
                     server_description.set_name = String::new();
@@ -748,7 +872,7 @@ impl TopologyDescription {
 
         // Remove hosts that are not reported by the primary.
         let valid_hosts: Vec<_> = {
-            let description_guard = description.read().unwrap();
+            let description_guard = description.read().recover();
 
             description_guard
                 .hosts
@@ -779,7 +903,7 @@ impl TopologyDescription {
             return;
         }
 
-        let set_name = description.read().unwrap().set_name.clone();
+        let set_name = description.read().recover().set_name.clone();
 
         if self.set_name.is_empty() {
             self.set_name = set_name;
@@ -791,7 +915,7 @@ impl TopologyDescription {
 
         self.add_missing_hosts(description.clone(), client, top_arc, run_monitor);
 
-        let description_me = description.read().unwrap().me.clone();
+        let description_me = description.read().recover().me.clone();
 
         if let Some(me) = description_me {
             if host != me {
@@ -811,11 +935,11 @@ impl TopologyDescription {
             return;
         }
 
-        if self.set_name != description.read().unwrap().set_name {
+        if self.set_name != description.read().recover().set_name {
             self.servers.remove(&host);
         }
 
-        let description_me = description.read().unwrap().me.clone();
+        let description_me = description.read().recover().me.clone();
 
         if let Some(me) = description_me {
             if host != me {
@@ -837,7 +961,7 @@ impl TopologyDescription {
     ) {
 
         let hosts: Vec<_> = {
-            let description_guard = description.read().unwrap();
+            let description_guard = description.read().recover();
 
             description_guard
                 .hosts
@@ -856,8 +980,11 @@ impl TopologyDescription {
                     top_arc.clone(),
                     run_monitor,
                     self.stream_connector.clone(),
-                    None,
-                    None,
+                    self.pool_size,
+                    self.idle_connection_timeout,
+                    self.read_buffer_size,
+                    self.tcp_nodelay,
+                    self.checkout_strategy,
                 );
                 self.servers.insert(host, server);
             }
@@ -898,9 +1025,20 @@ impl Topology {
 
         let top_description = Arc::new(RwLock::new(options));
 
+        let current = match (config.user.as_ref(), config.password.as_ref()) {
+            (Some(user), Some(password)) => Some(Credential::new(user, password)),
+            _ => None,
+        };
+
+        let credentials = Credentials {
+            current: current,
+            previous: None,
+        };
+
         Ok(Topology {
             config: config,
             description: top_description,
+            credentials: Arc::new(RwLock::new(credentials)),
         })
     }
 
@@ -910,39 +1048,83 @@ impl Topology {
         client: Client,
         read_preference: Option<ReadPreference>,
         write: bool,
+        timeout: Option<Duration>,
     ) -> Result<(PooledStream, bool, bool)> {
+        let _span = enter_span!(
+            DEBUG,
+            "mongodb.server_selection",
+            operation = if write { "write" } else { "read" },
+            server = ::tracing::field::Empty
+        );
+
         // Note start of server selection.
         let time = time::get_time();
         let start_ms = time.sec * 1000 + (time.nsec as i64) / 1000000;
 
         loop {
             let result = if write {
-                match self.description.read()?.acquire_write_stream(client.clone()) {
+                match self.description.read().recover().acquire_write_stream(client.clone()) {
                     Ok(stream) => Ok((stream, false, false)),
                     Err(err) => Err(err),
                 }
             } else {
-                self.description.read()?.acquire_stream(
+                self.description.read().recover().acquire_stream(
                     client.clone(),
                     read_preference.as_ref().unwrap(),
                 )
             };
 
             match result {
-                Ok(stream) => return Ok(stream),
+                Ok(stream) => {
+                    debug!(
+                        target: "mongodb::topology",
+                        "Selected {}:{} for a {} operation",
+                        stream.0.host().host_name,
+                        stream.0.host().port,
+                        if write { "write" } else { "read" }
+                    );
+                    record_span!(
+                        "server",
+                        format!("{}:{}", stream.0.host().host_name, stream.0.host().port)
+                    );
+                    return Ok(stream);
+                }
                 Err(err) => {
                     // Check duration of current server selection and return an error if
                     // overdue.
                     let end_time = time::get_time();
                     let end_ms = end_time.sec * 1000 + (end_time.nsec as i64) / 1000000;
-                    if end_ms - start_ms >= self.description.read()?.server_selection_timeout_ms {
+                    let timeout_ms = match timeout {
+                        Some(timeout) => timeout.as_millis() as i64,
+                        None => self.description.read().recover().server_selection_timeout_ms,
+                    };
+                    if end_ms - start_ms >= timeout_ms {
+                        warn!(
+                            target: "mongodb::topology",
+                            "Server selection timed out after {} ms: {}",
+                            end_ms - start_ms,
+                            err
+                        );
+                        let operation = if write { "write" } else { "read" };
+                        client.metrics.counter(
+                            metrics::SERVER_SELECTION_TIMEOUTS,
+                            &[("operation", operation)],
+                            1,
+                        );
                         return Err(err);
                     }
+
+                    trace!(
+                        target: "mongodb::topology",
+                        "No suitable server yet, retrying server selection: {}",
+                        err
+                    );
+
+                    // Otherwise, sleep for a little while, without overshooting the timeout.
+                    let remaining_ms = (timeout_ms - (end_ms - start_ms)) as u64;
+                    thread::sleep(Duration::from_millis(cmp::min(500, remaining_ms)));
                 }
             };
-
-            // Otherwise, sleep for a little while.
-            thread::sleep(Duration::from_millis(500));
         }
     }
 
@@ -952,12 +1134,28 @@ impl Topology {
         client: Client,
         read_preference: ReadPreference,
     ) -> Result<(PooledStream, bool, bool)> {
-        self.acquire_stream_private(client, Some(read_preference), false)
+        self.acquire_stream_private(client, Some(read_preference), false, None)
+    }
+
+    /// Returns a server stream for read operations, giving up on server selection after
+    /// `timeout` instead of the topology's server selection timeout.
+    pub fn acquire_stream_within(
+        &self,
+        client: Client,
+        read_preference: ReadPreference,
+        timeout: Duration,
+    ) -> Result<(PooledStream, bool, bool)> {
+        self.acquire_stream_private(client, Some(read_preference), false, Some(timeout))
     }
 
     /// Returns a server stream for write operations.
     pub fn acquire_write_stream(&self, client: Client) -> Result<PooledStream> {
-        let (stream, _, _) = self.acquire_stream_private(client, None, true)?;
+        let (stream, _, _) = self.acquire_stream_private(client, None, true, None)?;
         Ok(stream)
     }
 }
+
+// Server descriptions not yet updated by a handshake hold zero limits.
+fn positive_or(value: i64, default: i64) -> i64 {
+    if value > 0 { value } else { default }
+}
@@ -1,19 +1,22 @@
 //! Asynchronous server and topology discovery and monitoring using isMaster results.
-use {Client, Result};
+use {Client, Result, ThreadedClient};
 use Error::{self, ArgumentError, OperationError};
 
 use bson::{self, bson, Bson, doc, oid};
+
+use apm::{EventRunner, ServerHeartbeatFailed, ServerHeartbeatStarted, ServerHeartbeatSucceeded};
 use chrono::{DateTime, Utc};
 
 use coll::options::FindOptions;
 use command_type::CommandType;
 use connstring::{self, Host};
-use cursor::Cursor;
+use cursor::{self, Cursor};
 use pool::ConnectionPool;
+use poison::Recover;
 use stream::StreamConnector;
 use wire_protocol::flags::OpQueryFlags;
 
-use std::fmt;
+use std::{fmt, mem};
 use std::collections::BTreeMap;
 use std::sync::{Arc, Weak, Condvar, Mutex, RwLock};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
@@ -26,6 +29,10 @@ use super::{DEFAULT_HEARTBEAT_FREQUENCY_MS, TopologyDescription};
 
 const DEFAULT_MAX_BSON_OBJECT_SIZE: i64 = 16 * 1024 * 1024;
 const DEFAULT_MAX_MESSAGE_SIZE_BYTES: i64 = 48000000;
+const DEFAULT_MAX_WRITE_BATCH_SIZE: i64 = 1000;
+
+// How many dropped cursors may wait to be killed; any more are left to time out on the server.
+const MAX_PENDING_CURSOR_KILLS: usize = 1000;
 
 /// The result of an isMaster operation.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -34,6 +41,7 @@ pub struct IsMasterResult {
     pub is_master: bool,
     pub max_bson_object_size: i64,
     pub max_message_size_bytes: i64,
+    pub max_write_batch_size: i64,
     pub local_time: Option<DateTime<Utc>>,
     pub min_wire_version: i64,
     pub max_wire_version: i64,
@@ -56,6 +64,9 @@ pub struct IsMasterResult {
     pub primary: Option<Host>,
     pub hidden: bool,
     pub set_version: Option<i64>,
+
+    /// Whether the server was started in read-only mode.
+    pub read_only: bool,
 }
 
 /// Monitors and updates server and topology information.
@@ -79,6 +90,8 @@ pub struct Monitor {
     // To allow servers to request an immediate update, this
     // condvar can be notified to wake up the monitor.
     condvar: Condvar,
+    // Namespaces and ids of server-side cursors to kill after the next successful check.
+    pending_cursor_kills: Mutex<Vec<(String, i64)>>,
     /// While true, the monitor will check server connection health
     /// at the topology's heartbeat frequency rate.
     pub running: Arc<AtomicBool>,
@@ -110,6 +123,7 @@ impl IsMasterResult {
             is_master: false,
             max_bson_object_size: DEFAULT_MAX_BSON_OBJECT_SIZE,
             max_message_size_bytes: DEFAULT_MAX_MESSAGE_SIZE_BYTES,
+            max_write_batch_size: DEFAULT_MAX_WRITE_BATCH_SIZE,
             local_time: None,
             min_wire_version: -1,
             max_wire_version: -1,
@@ -127,6 +141,7 @@ impl IsMasterResult {
             primary: None,
             hidden: false,
             set_version: None,
+            read_only: false,
         };
 
         if let Some(&Bson::Boolean(b)) = doc.get("ismaster") {
@@ -137,6 +152,18 @@ impl IsMasterResult {
             result.local_time = Some(datetime);
         }
 
+        if let Some(v) = integer(doc.get("maxBsonObjectSize")) {
+            result.max_bson_object_size = v;
+        }
+
+        if let Some(v) = integer(doc.get("maxMessageSizeBytes")) {
+            result.max_message_size_bytes = v;
+        }
+
+        if let Some(v) = integer(doc.get("maxWriteBatchSize")) {
+            result.max_write_batch_size = v;
+        }
+
         if let Some(&Bson::I64(v)) = doc.get("minWireVersion") {
             result.min_wire_version = v;
         }
@@ -204,10 +231,13 @@ impl IsMasterResult {
             result.hidden = h;
         }
 
-        if let Some(&Bson::I64(v)) = doc.get("setVersion") {
-            result.set_version = Some(v);
+        if let Some(&Bson::Boolean(b)) = doc.get("readOnly") {
+            result.read_only = b;
         }
 
+        // Replica set members report their configuration version as a 32-bit integer.
+        result.set_version = integer(doc.get("setVersion"));
+
         if let Some(&Bson::Document(ref doc)) = doc.get("tags") {
             for (k, v) in doc {
                 if let Bson::String(ref tag) = *v {
@@ -230,6 +260,15 @@ impl IsMasterResult {
     }
 }
 
+// Reads a size limit, which servers report as either a 32- or 64-bit integer.
+fn integer(value: Option<&Bson>) -> Option<i64> {
+    match value {
+        Some(&Bson::I32(v)) => Some(i64::from(v)),
+        Some(&Bson::I64(v)) => Some(v),
+        _ => None,
+    }
+}
+
 impl Monitor {
     /// Returns a new monitor connected to the server.
     pub fn new(
@@ -250,14 +289,23 @@ impl Monitor {
             heartbeat_frequency_ms: AtomicUsize::new(DEFAULT_HEARTBEAT_FREQUENCY_MS as usize),
             dummy_lock: Mutex::new(()),
             condvar: Condvar::new(),
+            pending_cursor_kills: Mutex::new(Vec::new()),
             running: Arc::new(AtomicBool::new(false)),
         }
     }
 
     // Set server description error field.
     fn set_err(&self, err: Error) {
+        warn!(
+            target: "mongodb::topology",
+            "Heartbeat to {}:{} failed: {}",
+            self.host.host_name,
+            self.host.port,
+            err
+        );
+
         {
-            let mut server_description = self.server_description.write().unwrap();
+            let mut server_description = self.server_description.write().recover();
             server_description.set_err(err);
         }
 
@@ -274,19 +322,49 @@ impl Monitor {
         let filter = doc!{ "isMaster": 1_i32 };
         let time_start = time::get_time();
         if let Some(client_arc) = self.client.upgrade() {
-            let mut stream = self.personal_pool.acquire_stream(client_arc.clone())?;
-
-            let cursor = Cursor::query_with_stream(
-                &mut stream,
-                client_arc,
-                String::from("local.$cmd"),
-                flags,
-                filter,
-                options,
-                CommandType::IsMaster,
-                false,
-                None,
-            )?;
+            let connection_string = format!("{}:{}", self.host.host_name, self.host.port);
+            let _ = client_arc.run_heartbeat_started_hooks(&ServerHeartbeatStarted {
+                connection_string: connection_string.clone(),
+                awaited: false,
+            });
+
+            let init_time = time::precise_time_ns();
+            let result = self.personal_pool.acquire_stream(client_arc.clone()).and_then(
+                |mut stream| {
+                    Cursor::query_with_stream(
+                        &mut stream,
+                        client_arc.clone(),
+                        String::from("local.$cmd"),
+                        flags,
+                        filter,
+                        options,
+                        CommandType::IsMaster,
+                        false,
+                        None,
+                    )
+                },
+            );
+            let duration = time::precise_time_ns() - init_time;
+
+            let cursor = match result {
+                Ok(cursor) => {
+                    let _ = client_arc.run_heartbeat_succeeded_hooks(&ServerHeartbeatSucceeded {
+                        duration: duration,
+                        connection_string: connection_string,
+                        awaited: false,
+                    });
+                    cursor
+                }
+                Err(err) => {
+                    let _ = client_arc.run_heartbeat_failed_hooks(&ServerHeartbeatFailed {
+                        duration: duration,
+                        failure: &err,
+                        connection_string: connection_string,
+                        awaited: false,
+                    });
+                    return Err(err);
+                }
+            };
             let time_end = time::get_time();
 
             let sec_start_ms: i64 = time_start.sec * 1000;
@@ -303,10 +381,107 @@ impl Monitor {
         }
     }
 
+    // Returns the version reported by buildInfo, using the owned monitor socket.
+    fn build_info_version(&self) -> Result<String> {
+        let mut options = FindOptions::new();
+        options.limit = Some(1);
+        options.batch_size = Some(1);
+
+        let flags = OpQueryFlags::with_find_options(&options);
+        let client_arc = match self.client.upgrade() {
+            Some(client_arc) => client_arc,
+            None => {
+                return Err(OperationError(
+                    String::from("Unable to upgrade client weak reference"),
+                ))
+            }
+        };
+
+        let mut stream = self.personal_pool.acquire_stream(client_arc.clone())?;
+        let mut cursor = Cursor::query_with_stream(
+            &mut stream,
+            client_arc,
+            String::from("admin.$cmd"),
+            flags,
+            doc!{ "buildInfo": 1_i32 },
+            options,
+            CommandType::BuildInfo,
+            false,
+            None,
+        )?;
+
+        match cursor.next() {
+            Some(Ok(doc)) => match doc.get("version") {
+                Some(&Bson::String(ref version)) => Ok(version.clone()),
+                _ => Err(OperationError(String::from("buildInfo returned no version."))),
+            },
+            Some(Err(err)) => Err(err),
+            None => Err(OperationError(String::from("buildInfo returned no response."))),
+        }
+    }
+
+    // Fetches the server version the first time the server is reached after being unknown.
+    // Failures are left for the next check to retry.
+    fn update_version(&self) {
+        {
+            let description = self.server_description.read().recover();
+            if description.version.is_some() || description.server_type == ServerType::Unknown {
+                return;
+            }
+        }
+
+        if let Ok(version) = self.build_info_version() {
+            self.server_description.write().recover().version = Some(version);
+        }
+    }
+
     pub fn request_update(&self) {
         self.condvar.notify_one();
     }
 
+    /// Queues a server-side cursor to be killed once the server is next reached.
+    pub fn kill_cursor_later(&self, namespace: String, cursor_id: i64) {
+        let mut pending = self.pending_cursor_kills.lock().recover();
+        if pending.len() < MAX_PENDING_CURSOR_KILLS {
+            pending.push((namespace, cursor_id));
+        }
+    }
+
+    // Kills the queued cursors with one killCursors per namespace, using the owned monitor
+    // socket. Cursors that cannot be killed are left to time out on the server.
+    fn kill_pending_cursors(&self) {
+        let pending = mem::replace(&mut *self.pending_cursor_kills.lock().recover(), Vec::new());
+        if pending.is_empty() {
+            return;
+        }
+
+        let client_arc = match self.client.upgrade() {
+            Some(client_arc) => client_arc,
+            None => return,
+        };
+
+        let mut by_namespace: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for (namespace, cursor_id) in pending {
+            by_namespace.entry(namespace).or_insert_with(Vec::new).push(cursor_id);
+        }
+
+        for (namespace, cursor_ids) in by_namespace {
+            let mut stream = match self.personal_pool.acquire_stream(client_arc.clone()) {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+
+            let req_id = client_arc.get_req_id();
+            let server_api = client_arc.server_api.as_ref();
+            if cursor::kill_cursors(&mut stream, req_id, &namespace, cursor_ids, server_api)
+                .is_err()
+            {
+                stream.discard();
+                return;
+            }
+        }
+    }
+
     // Updates the server description associated with this monitor using an isMaster server
     // response.
     fn update_server_description(
@@ -317,7 +492,7 @@ impl Monitor {
 
         let ismaster_result = IsMasterResult::new(doc);
         {
-            let mut server_description = self.server_description.write().unwrap();
+            let mut server_description = self.server_description.write().recover();
             match ismaster_result {
                 Ok(ismaster) => server_description.update(ismaster, round_trip_time),
                 Err(err) => {
@@ -340,7 +515,7 @@ impl Monitor {
             return
         };
 
-        let mut top_description = top_description_arc.write().unwrap();
+        let mut top_description = top_description_arc.write().recover();
 
         if let Some(client_arc) = self.client.upgrade() {
             top_description.update(
@@ -377,6 +552,8 @@ impl Monitor {
         match self.is_master() {
             Ok((mut cursor, rtt)) => {
                 self.update_with_is_master_cursor(&mut cursor, rtt);
+                self.update_version();
+                self.kill_pending_cursors();
                 self.server_pool.prune_idle();
                 self.personal_pool.prune_idle();
             },
@@ -385,7 +562,7 @@ impl Monitor {
                 self.server_pool.clear();
                 self.personal_pool.clear();
 
-                if self.server_description.read().unwrap().server_type == ServerType::Unknown {
+                if self.server_description.read().recover().server_type == ServerType::Unknown {
                     self.set_err(err);
                     return;
                 }
@@ -407,7 +584,7 @@ impl Monitor {
 
         self.running.store(true, Ordering::SeqCst);
 
-        let mut guard = self.dummy_lock.lock().unwrap();
+        let mut guard = self.dummy_lock.lock().recover();
 
         loop {
             if !self.running.load(Ordering::SeqCst) {
@@ -417,19 +594,62 @@ impl Monitor {
             self.execute_update();
 
             if let Some(top_description) = self.top_description.upgrade() {
-                if let Ok(description) = top_description.read() {
-                    self.heartbeat_frequency_ms.store(
-                        description.heartbeat_frequency_ms as usize,
-                        Ordering::SeqCst,
-                    );
-                }
+                let description = top_description.read().recover();
+                self.heartbeat_frequency_ms.store(
+                    description.heartbeat_frequency_ms as usize,
+                    Ordering::SeqCst,
+                );
             }
 
             let frequency = self.heartbeat_frequency_ms.load(Ordering::SeqCst) as u64;
             guard = self.condvar
                 .wait_timeout(guard, Duration::from_millis(frequency))
-                .unwrap()
+                .recover()
                 .0;
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+
+    #[test]
+    fn parses_replica_set_member_details() {
+        let election_id = oid::ObjectId::with_string("7fffffff0000000000000004").unwrap();
+        let ismaster = IsMasterResult::new(doc! {
+            "ok": 1.0,
+            "ismaster": true,
+            "setName": "rs",
+            "setVersion": 3,
+            "electionId": election_id.clone(),
+            "tags": { "dc": "east", "rack": "r1", "weight": 2 },
+        }).unwrap();
+
+        assert_eq!(Some(3), ismaster.set_version);
+        assert_eq!(Some(election_id), ismaster.election_id);
+        assert_eq!(2, ismaster.tags.len());
+        assert_eq!(Some(&String::from("east")), ismaster.tags.get("dc"));
+    }
+
+    #[test]
+    fn caps_pending_cursor_kills() {
+        let client = Client::connect("localhost", 27017).unwrap();
+        let host = connstring::parse_host("localhost:27017").unwrap();
+        let monitor = Monitor::new(
+            client.clone(),
+            host.clone(),
+            Arc::new(ConnectionPool::new(host, StreamConnector::Tcp)),
+            client.topology.description.clone(),
+            Arc::new(RwLock::new(ServerDescription::new())),
+            StreamConnector::Tcp,
+        );
+
+        for cursor_id in 0..MAX_PENDING_CURSOR_KILLS + 10 {
+            monitor.kill_cursor_later(String::from("test.coll"), cursor_id as i64);
+        }
+
+        assert_eq!(MAX_PENDING_CURSOR_KILLS, monitor.pending_cursor_kills.lock().recover().len());
+    }
+}
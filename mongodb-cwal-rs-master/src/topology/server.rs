@@ -3,8 +3,10 @@ use {Client, Result};
 use Error::{self, OperationError};
 
 use bson::oid;
+use chrono::{DateTime, Utc};
 use connstring::Host;
-use pool::{ConnectionPool, PooledStream, DEFAULT_TIMEOUT_ON_IDLE};
+use pool::{CheckoutStrategy, ConnectionPool, PooledStream, DEFAULT_POOL_SIZE,
+           DEFAULT_TIMEOUT_ON_IDLE};
 use stream::StreamConnector;
 
 use std::collections::BTreeMap;
@@ -63,6 +65,12 @@ pub struct ServerDescription {
     pub min_wire_version: i64,
     /// The maximum wire version supported by this server.
     pub max_wire_version: i64,
+    /// The largest document this server accepts, in bytes; zero if unknown.
+    pub max_bson_object_size: i64,
+    /// The largest message this server accepts, in bytes; zero if unknown.
+    pub max_message_size_bytes: i64,
+    /// The most write operations this server accepts in one command; zero if unknown.
+    pub max_write_batch_size: i64,
     /// The server's host information, if it is part of a replica set.
     pub me: Option<Host>,
     /// All hosts in the replica set known by this server.
@@ -71,7 +79,8 @@ pub struct ServerDescription {
     pub passives: Vec<Host>,
     /// All arbiters in the replica set known by this server.
     pub arbiters: Vec<Host>,
-    /// Server tags for targeted read operations on specific replica set members.
+    /// Server tags for targeted read operations on specific replica set members. Tags with
+    /// values other than strings are left out.
     pub tags: BTreeMap<String, String>,
     /// The replica set name.
     pub set_name: String,
@@ -81,6 +90,14 @@ pub struct ServerDescription {
     pub primary: Option<Host>,
     /// The current replica set version number.
     pub set_version: Option<i64>,
+    /// Whether the server was started in read-only mode.
+    pub read_only: bool,
+    /// The server's clock at its last successful check.
+    pub local_time: Option<DateTime<Utc>>,
+    /// When the last successful check completed, by this process's clock.
+    pub last_update_time: Option<DateTime<Utc>>,
+    /// The server version from `buildInfo`, fetched after the server was first reached.
+    pub version: Option<String>,
 }
 
 /// Holds status and connection information about a single server.
@@ -130,6 +147,9 @@ impl ServerDescription {
 
         self.min_wire_version = ismaster.min_wire_version;
         self.max_wire_version = ismaster.max_wire_version;
+        self.max_bson_object_size = ismaster.max_bson_object_size;
+        self.max_message_size_bytes = ismaster.max_message_size_bytes;
+        self.max_write_batch_size = ismaster.max_write_batch_size;
         self.me = ismaster.me;
         self.hosts = ismaster.hosts;
         self.passives = ismaster.passives;
@@ -139,6 +159,9 @@ impl ServerDescription {
         self.election_id = ismaster.election_id;
         self.primary = ismaster.primary;
         self.set_version = ismaster.set_version;
+        self.read_only = ismaster.read_only;
+        self.local_time = ismaster.local_time;
+        self.last_update_time = Some(Utc::now());
         self.round_trip_time = match self.round_trip_time {
             Some(old_rtt) => {
                 // (rtt / div) + (old_rtt * (div-1)/div)
@@ -181,9 +204,13 @@ impl ServerDescription {
     // Reset the server type to unknown.
     pub fn clear(&mut self) {
         self.election_id = None;
+        self.set_version = None;
+        self.tags = BTreeMap::new();
         self.round_trip_time = None;
         self.server_type = ServerType::Unknown;
         self.set_name = String::new();
+        // The server may come back as a different build.
+        self.version = None;
     }
 }
 
@@ -203,6 +230,9 @@ impl Server {
         connector: StreamConnector,
         pool_size: Option<usize>,
         idle_timeout: Option<Duration>,
+        read_buffer_size: Option<usize>,
+        nodelay: Option<bool>,
+        checkout_strategy: Option<CheckoutStrategy>,
     ) -> Server {
         let description = Arc::new(RwLock::new(ServerDescription::new()));
 
@@ -210,13 +240,25 @@ impl Server {
         let host_clone = host.clone();
         let desc_clone = description.clone();
 
-        let pool_inner = if let Some(size) = pool_size {
-            ConnectionPool::with_options(host.clone(), connector.clone(), size, idle_timeout.unwrap_or_else(|| DEFAULT_TIMEOUT_ON_IDLE))
-        } else {
-            ConnectionPool::new(host.clone(), connector.clone())
-        };
+        let pool = Arc::new(ConnectionPool::with_options(
+            host.clone(),
+            connector.clone(),
+            pool_size.unwrap_or(DEFAULT_POOL_SIZE),
+            idle_timeout.unwrap_or(DEFAULT_TIMEOUT_ON_IDLE),
+        ));
+
+        if let Some(size) = read_buffer_size {
+            // Sizes of zero are rejected when the client is created.
+            let _ = pool.set_read_buffer_size(size);
+        }
+
+        if let Some(nodelay) = nodelay {
+            let _ = pool.set_nodelay(nodelay);
+        }
 
-        let pool = Arc::new(pool_inner);
+        if let Some(strategy) = checkout_strategy {
+            let _ = pool.set_checkout_strategy(strategy);
+        }
 
         // Fails silently
         let monitor = Arc::new(Monitor::new(
@@ -246,8 +288,19 @@ impl Server {
         self.pool.acquire_stream(client)
     }
 
+    /// Returns a handle to the connection pool for this server.
+    pub fn pool(&self) -> Arc<ConnectionPool> {
+        self.pool.clone()
+    }
+
     /// Request an update from the monitor on the server status.
     pub fn request_update(&self) {
         self.monitor.request_update();
     }
+
+    /// Queues a cursor on this server to be killed by the monitor once the server is next
+    /// reached.
+    pub(crate) fn kill_cursor_later(&self, namespace: String, cursor_id: i64) {
+        self.monitor.kill_cursor_later(namespace, cursor_id);
+    }
 }
@@ -0,0 +1,179 @@
+//! UUIDs stored as BSON binary values, available with the `uuid` feature.
+//!
+//! `to_bson` and `from_bson` convert `Uuid`s to and from binary subtype 4, the standard UUID
+//! representation. Fields of typed documents may be annotated with
+//! `#[serde(with = "mongodb::uuid")]` to be stored the same way.
+//!
+//! Older drivers wrote UUIDs as binary subtype 3, each in its own byte order. Data written by
+//! them is read and written with `from_legacy_bson` and `to_legacy_bson`, naming the driver's
+//! `LegacyEncoding`; subtype 3 values are never converted implicitly, since their byte order
+//! can't be told from the value itself.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! # extern crate uuid;
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use uuid::Uuid;
+//! #
+//! # fn main() {
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! let coll = client.db("shop").collection("sessions");
+//!
+//! let id = Uuid::parse_str("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+//! coll.insert_one(doc!{ "_id": mongodb::uuid::to_bson(&id) }, None).unwrap();
+//!
+//! let session = coll.find_one(Some(doc!{ "_id": mongodb::uuid::to_bson(&id) }), None).unwrap();
+//! assert_eq!(id, mongodb::uuid::from_bson(session.unwrap().get("_id").unwrap()).unwrap());
+//! # }
+//! ```
+pub use uuid_crate::Uuid;
+
+use bson::Bson;
+use bson::spec::BinarySubtype;
+use serde::de::Error as SerdeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use Error::ArgumentError;
+use Result;
+
+/// The byte order a legacy driver used for UUIDs stored as binary subtype 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyEncoding {
+    /// The C# driver: the first three fields of the UUID are little-endian.
+    CSharp,
+    /// The Java driver: each half of the UUID is little-endian.
+    Java,
+    /// The Python driver: the bytes of the UUID in order, as for subtype 4.
+    Python,
+}
+
+impl LegacyEncoding {
+    // Swaps between the standard byte order and the legacy one; each swap is its own inverse.
+    fn swap(&self, bytes: &mut [u8; 16]) {
+        match *self {
+            LegacyEncoding::CSharp => {
+                bytes[0..4].reverse();
+                bytes[4..6].reverse();
+                bytes[6..8].reverse();
+            }
+            LegacyEncoding::Java => {
+                bytes[0..8].reverse();
+                bytes[8..16].reverse();
+            }
+            LegacyEncoding::Python => (),
+        }
+    }
+}
+
+/// Returns `uuid` as binary subtype 4.
+pub fn to_bson(uuid: &Uuid) -> Bson {
+    Bson::Binary(BinarySubtype::Uuid, uuid.as_bytes().to_vec())
+}
+
+/// Reads a UUID stored as binary subtype 4.
+///
+/// Subtype 3 values are rejected; read them with `from_legacy_bson` instead.
+pub fn from_bson(bson: &Bson) -> Result<Uuid> {
+    match *bson {
+        Bson::Binary(BinarySubtype::Uuid, ref bytes) => uuid_from_slice(bytes),
+        Bson::Binary(BinarySubtype::UuidOld, _) => Err(ArgumentError(String::from(
+            "UUID is stored as legacy binary subtype 3; read it with from_legacy_bson.",
+        ))),
+        _ => Err(ArgumentError(format!("{} is not a UUID.", bson))),
+    }
+}
+
+/// Returns `uuid` as binary subtype 3, in the byte order of `encoding`.
+pub fn to_legacy_bson(uuid: &Uuid, encoding: LegacyEncoding) -> Bson {
+    let mut bytes = *uuid.as_bytes();
+    encoding.swap(&mut bytes);
+    Bson::Binary(BinarySubtype::UuidOld, bytes.to_vec())
+}
+
+/// Reads a UUID stored as binary subtype 3 in the byte order of `encoding`.
+pub fn from_legacy_bson(bson: &Bson, encoding: LegacyEncoding) -> Result<Uuid> {
+    match *bson {
+        Bson::Binary(BinarySubtype::UuidOld, ref bytes) => {
+            let mut bytes = *uuid_from_slice(bytes)?.as_bytes();
+            encoding.swap(&mut bytes);
+            Ok(Uuid::from_bytes(bytes))
+        }
+        _ => Err(ArgumentError(format!("{} is not a legacy UUID.", bson))),
+    }
+}
+
+/// Serializes `uuid` as binary subtype 4, for `#[serde(with = "mongodb::uuid")]`.
+pub fn serialize<S: Serializer>(
+    uuid: &Uuid,
+    serializer: S,
+) -> ::std::result::Result<S::Ok, S::Error> {
+    to_bson(uuid).serialize(serializer)
+}
+
+/// Deserializes a UUID stored as binary subtype 4.
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> ::std::result::Result<Uuid, D::Error> {
+    let bson = Bson::deserialize(deserializer)?;
+    from_bson(&bson).map_err(D::Error::custom)
+}
+
+fn uuid_from_slice(bytes: &[u8]) -> Result<Uuid> {
+    Uuid::from_slice(bytes)
+        .map_err(|_| ArgumentError(format!("A UUID has 16 bytes, not {}.", bytes.len())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{self, doc};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Session {
+        #[serde(with = "super")]
+        id: Uuid,
+    }
+
+    fn uuid() -> Uuid {
+        Uuid::parse_str("00112233-4455-6677-8899-aabbccddeeff").unwrap()
+    }
+
+    #[test]
+    fn converts_standard_uuids() {
+        let bson = to_bson(&uuid());
+        assert_eq!(Bson::Binary(BinarySubtype::Uuid, uuid().as_bytes().to_vec()), bson);
+        assert_eq!(uuid(), from_bson(&bson).unwrap());
+        assert!(from_bson(&to_legacy_bson(&uuid(), LegacyEncoding::Python)).is_err());
+        assert!(from_bson(&Bson::Binary(BinarySubtype::Uuid, vec![1, 2])).is_err());
+        assert!(from_bson(&Bson::String(uuid().to_string())).is_err());
+
+        let session = Session { id: uuid() };
+        let document = doc! { "id": bson.clone() };
+        assert_eq!(Bson::Document(document.clone()), bson::to_bson(&session).unwrap());
+        assert_eq!(session, bson::from_bson(Bson::Document(document)).unwrap());
+    }
+
+    #[test]
+    fn converts_legacy_uuids() {
+        let expected = [
+            (LegacyEncoding::CSharp, "33221100554477668899aabbccddeeff"),
+            (LegacyEncoding::Java, "7766554433221100ffeeddccbbaa9988"),
+            (LegacyEncoding::Python, "00112233445566778899aabbccddeeff"),
+        ];
+
+        for &(encoding, hex) in &expected {
+            let bson = to_legacy_bson(&uuid(), encoding);
+            match bson {
+                Bson::Binary(BinarySubtype::UuidOld, ref bytes) => {
+                    let bytes: Vec<_> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                    assert_eq!(hex, bytes.concat());
+                }
+                _ => panic!("Expected binary subtype 3"),
+            }
+            assert_eq!(uuid(), from_legacy_bson(&bson, encoding).unwrap());
+            assert!(from_legacy_bson(&to_bson(&uuid()), encoding).is_err());
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Scratch buffers for encoding messages, reused across operations.
+//!
+//! Encoding a document with `bson::encode_document` allocates a buffer for the document and
+//! for every subdocument and array in it. Messages are instead encoded into one buffer
+//! borrowed from a pool shared by every client, writing the length of each nested document
+//! once its end is known, so that sending a command allocates nothing once the pool has
+//! warmed up.
+use bson::{self, Bson};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use chrono::Timelike;
+use poison::Recover;
+use Result;
+
+use std::io::Write;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// The most buffers the shared pool keeps for reuse.
+pub const DEFAULT_MAX_POOLED_BUFFERS: usize = 64;
+
+/// The largest buffer the shared pool keeps for reuse, in bytes. Buffers that grew larger to
+/// encode a big write are freed, so that it does not hold on to their memory.
+pub const DEFAULT_MAX_POOLED_CAPACITY: usize = 1024 * 1024;
+
+static SHARED: BufferPool = BufferPool::new(
+    DEFAULT_MAX_POOLED_BUFFERS,
+    DEFAULT_MAX_POOLED_CAPACITY,
+);
+
+/// A thread-safe pool of byte buffers.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// Returns a pool keeping up to `max_buffers` buffers of at most `max_capacity` bytes.
+    pub const fn new(max_buffers: usize, max_capacity: usize) -> BufferPool {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers: max_buffers,
+            max_capacity: max_capacity,
+        }
+    }
+
+    /// Returns the pool messages are encoded with.
+    pub fn shared() -> &'static BufferPool {
+        &SHARED
+    }
+
+    /// Borrows an empty buffer, which is returned to the pool when dropped.
+    pub fn get(&self) -> PooledBuffer<'_> {
+        let buffer = self.buffers.lock().recover().pop().unwrap_or_default();
+
+        PooledBuffer {
+            pool: self,
+            buffer: buffer,
+        }
+    }
+
+    /// Returns how many buffers are waiting to be reused.
+    pub fn pooled(&self) -> usize {
+        self.buffers.lock().recover().len()
+    }
+
+    fn put(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_capacity {
+            return;
+        }
+
+        buffer.clear();
+        let mut buffers = self.buffers.lock().recover();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
+
+/// A buffer borrowed from a `BufferPool`.
+#[derive(Debug)]
+pub struct PooledBuffer<'a> {
+    pool: &'a BufferPool,
+    buffer: Vec<u8>,
+}
+
+impl<'a> Deref for PooledBuffer<'a> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl<'a> DerefMut for PooledBuffer<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl<'a> AsRef<[u8]> for PooledBuffer<'a> {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl<'a> Drop for PooledBuffer<'a> {
+    fn drop(&mut self) {
+        let buffer = ::std::mem::replace(&mut self.buffer, Vec::new());
+        self.pool.put(buffer);
+    }
+}
+
+/// Appends `doc` to `buffer`, encoded exactly as `bson::encode_document` would encode it.
+pub fn encode_document(buffer: &mut Vec<u8>, doc: &bson::Document) -> Result<()> {
+    let start = begin_length(buffer);
+    for (key, value) in doc {
+        encode_element(buffer, key.as_bytes(), value)?;
+    }
+    buffer.push(0);
+    end_length(buffer, start);
+    Ok(())
+}
+
+// Reserves room for a length prefix, returning where it starts.
+fn begin_length(buffer: &mut Vec<u8>) -> usize {
+    let start = buffer.len();
+    buffer.extend_from_slice(&[0; 4]);
+    start
+}
+
+// Fills in the length prefix at `start` with the length of everything written since.
+fn end_length(buffer: &mut Vec<u8>, start: usize) {
+    let length = buffer.len() - start;
+    LittleEndian::write_i32(&mut buffer[start..start + 4], length as i32);
+}
+
+fn write_cstring(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(bytes);
+    buffer.push(0);
+}
+
+fn write_string(buffer: &mut Vec<u8>, string: &str) -> Result<()> {
+    buffer.write_i32::<LittleEndian>(string.len() as i32 + 1)?;
+    write_cstring(buffer, string.as_bytes());
+    Ok(())
+}
+
+fn encode_element(buffer: &mut Vec<u8>, key: &[u8], value: &Bson) -> Result<()> {
+    buffer.push(value.element_type() as u8);
+    write_cstring(buffer, key);
+
+    match *value {
+        Bson::FloatingPoint(v) => buffer.write_f64::<LittleEndian>(v)?,
+        Bson::String(ref v) | Bson::JavaScriptCode(ref v) | Bson::Symbol(ref v) => {
+            write_string(buffer, v)?
+        }
+        Bson::Array(ref values) => {
+            let start = begin_length(buffer);
+            let mut digits = [0; 20];
+            for (i, value) in values.iter().enumerate() {
+                let len = {
+                    let mut rest = &mut digits[..];
+                    write!(rest, "{}", i)?;
+                    20 - rest.len()
+                };
+                encode_element(buffer, &digits[..len], value)?;
+            }
+            buffer.push(0);
+            end_length(buffer, start);
+        }
+        Bson::Document(ref doc) => encode_document(buffer, doc)?,
+        Bson::Boolean(v) => buffer.push(if v { 0x01 } else { 0x00 }),
+        Bson::RegExp(ref pattern, ref options) => {
+            write_cstring(buffer, pattern.as_bytes());
+            write_cstring(buffer, options.as_bytes());
+        }
+        Bson::ObjectId(ref id) => buffer.extend_from_slice(&id.bytes()),
+        Bson::JavaScriptCodeWithScope(ref code, ref scope) => {
+            let start = begin_length(buffer);
+            write_string(buffer, code)?;
+            encode_document(buffer, scope)?;
+            end_length(buffer, start);
+        }
+        Bson::I32(v) => buffer.write_i32::<LittleEndian>(v)?,
+        Bson::I64(v) | Bson::TimeStamp(v) => buffer.write_i64::<LittleEndian>(v)?,
+        Bson::Binary(subtype, ref data) => {
+            buffer.write_i32::<LittleEndian>(data.len() as i32)?;
+            buffer.push(u8::from(subtype));
+            buffer.extend_from_slice(data);
+        }
+        Bson::UtcDatetime(ref v) => {
+            let millis = v.timestamp() * 1000 + (v.nanosecond() / 1_000_000) as i64;
+            buffer.write_i64::<LittleEndian>(millis)?;
+        }
+        Bson::Null => (),
+        #[cfg(feature = "decimal128")]
+        Bson::Decimal128(ref v) => buffer.extend_from_slice(&v.to_raw_bytes_le()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use bson::oid::ObjectId;
+    use bson::spec::BinarySubtype;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn encodes_like_the_bson_crate() {
+        let tags: Vec<Bson> = (0..12).map(Bson::I32).collect();
+        let doc = doc! {
+            "_id": ObjectId::with_bytes([3; 12]),
+            "ratio": 0.25,
+            "name": "encoded",
+            "nested": { "tags": tags, "empty": [] },
+            "data": Bson::Binary(BinarySubtype::Generic, vec![1, 2, 3]),
+            "old": Bson::Binary(BinarySubtype::BinaryOld, vec![4]),
+            "flag": true,
+            "at": Utc.timestamp_millis_opt(1_520_000_000_123).unwrap(),
+            "missing": Bson::Null,
+            "pattern": Bson::RegExp(String::from("^a"), String::from("i")),
+            "code": Bson::JavaScriptCode(String::from("x")),
+            "scoped": Bson::JavaScriptCodeWithScope(String::from("y"), doc! { "y": 1 }),
+            "symbol": Bson::Symbol(String::from("s")),
+            "n": 7,
+            "big": 1_i64 << 40,
+            "ts": Bson::TimeStamp(5),
+        };
+
+        let mut expected = Vec::new();
+        bson::encode_document(&mut expected, &doc).unwrap();
+
+        let mut buffer = vec![0xAA];
+        encode_document(&mut buffer, &doc).unwrap();
+        assert_eq!(0xAA, buffer[0]);
+        assert_eq!(expected, &buffer[1..]);
+    }
+
+    #[test]
+    fn reuses_returned_buffers() {
+        let pool = BufferPool::new(1, 64);
+        let mut first = pool.get();
+        first.extend_from_slice(b"first");
+        let second = pool.get();
+        drop(first);
+        drop(second);
+        assert_eq!(1, pool.pooled());
+
+        let buffer = pool.get();
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() >= 5);
+        assert_eq!(0, pool.pooled());
+
+        // Buffers that grew too large are freed instead.
+        let mut large = buffer;
+        large.extend_from_slice(&[0; 65]);
+        drop(large);
+        assert_eq!(0, pool.pooled());
+    }
+}
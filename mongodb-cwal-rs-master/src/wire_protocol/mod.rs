@@ -1,5 +1,11 @@
 //! Low-level client-server communication over the MongoDB wire protocol.
 
 mod header;
+pub mod buffer_pool;
 pub mod flags;
 pub mod operations;
+#[cfg(test)]
+pub(crate) mod replay;
+pub mod reply;
+pub mod streamed;
+mod vectored;
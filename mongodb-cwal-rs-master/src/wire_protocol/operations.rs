@@ -3,14 +3,17 @@ use bson;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use Error::{ArgumentError, ResponseError};
 use Result;
+use wire_protocol::buffer_pool::{self, BufferPool};
 use wire_protocol::header::{Header, OpCode};
 use wire_protocol::flags::{OpInsertFlags, OpQueryFlags, OpReplyFlags, OpUpdateFlags};
+use wire_protocol::reply::ReplyBuffer;
+use wire_protocol::vectored;
 
-use std::io::{Read, Write};
+use std::io::{IoSlice, Read, Write};
 use std::mem;
 use std::result::Result::{Ok, Err};
 
-trait ByteLength {
+pub(crate) trait ByteLength {
     /// Calculates the number of bytes in the serialized version of the struct.
     fn byte_length(&self) -> Result<i32>;
 }
@@ -65,6 +68,8 @@ impl ByteLength for bson::Bson {
             bson::Bson::String(s) => 5 + s.len() as i32,
             bson::Bson::Symbol(s) => 5 + s.len() as i32,
             bson::Bson::FloatingPoint(_) => 8,
+            #[cfg(feature = "decimal128")]
+            bson::Bson::Decimal128(_) => 16,
             bson::Bson::JavaScriptCode(js) => 5 + js.len() as i32,
             bson::Bson::JavaScriptCodeWithScope(js, s) => 9 + js.len() as i32 + s.byte_length()?,
         })
@@ -205,7 +210,29 @@ pub enum Message {
     },
 }
 
+/// An OP_REPLY message whose documents were left encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawReply {
+    /// A Bit vector of reply options.
+    pub flags: OpReplyFlags,
+    /// Uniquely identifies the cursor being returned.
+    pub cursor_id: i64,
+    /// The encoded documents being returned.
+    pub documents: Vec<Vec<u8>>,
+}
+
 impl Message {
+    /// Returns the length of the whole message in bytes, as declared by its header.
+    pub fn message_length(&self) -> i32 {
+        match *self {
+            Message::OpReply { ref header, .. } |
+            Message::OpUpdate { ref header, .. } |
+            Message::OpInsert { ref header, .. } |
+            Message::OpQuery { ref header, .. } |
+            Message::OpGetMore { ref header, .. } => header.message_length,
+        }
+    }
+
     /// Constructs a new message for a reply.
     fn new_reply(
         header: Header,
@@ -359,22 +386,22 @@ impl Message {
         }
     }
 
-    /// Writes a serialized BSON document to a given buffer.
+    /// Writes the fixed fields of a message followed by its encoded documents, handing both
+    /// to the buffer at once rather than copying them into one contiguous buffer first.
     ///
     /// # Arguments
     ///
     /// `buffer` - The buffer to write to.
-    /// `bson` - The document to serialize and write.
+    /// `fields` - The header and the fields preceding the documents.
+    /// `documents` - The documents of the message, encoded back to back.
     ///
     /// # Return value
     ///
     /// Returns nothing on success, or an Error on failure.
-    fn write_bson_document<W: Write>(buffer: &mut W, bson: &bson::Document) -> Result<()> {
-        let mut temp_buffer = Vec::new();
-
-        bson::encode_document(&mut temp_buffer, bson)?;
-        buffer.write_all(&temp_buffer)?;
+    fn write_parts<W: Write>(buffer: &mut W, fields: &[u8], documents: &[u8]) -> Result<()> {
+        vectored::write_all(buffer, &mut [IoSlice::new(fields), IoSlice::new(documents)])?;
 
+        let _ = buffer.flush();
         Ok(())
     }
 
@@ -402,25 +429,26 @@ impl Message {
         update: &bson::Document,
     ) -> Result<()> {
 
-        header.write(buffer)?;
+        let mut fields = BufferPool::shared().get();
+        let mut encoded = BufferPool::shared().get();
+        header.write(&mut *fields)?;
 
         // Write ZERO field
-        buffer.write_i32::<LittleEndian>(0)?;
+        fields.write_i32::<LittleEndian>(0)?;
 
         for byte in namespace.bytes() {
-            buffer.write_u8(byte)?;
+            fields.write_u8(byte)?;
         }
 
         // Writes the null terminator for the collection name string.
-        buffer.write_u8(0)?;
+        fields.write_u8(0)?;
 
-        buffer.write_i32::<LittleEndian>(flags.bits())?;
+        fields.write_i32::<LittleEndian>(flags.bits())?;
 
-        Message::write_bson_document(buffer, selector)?;
-        Message::write_bson_document(buffer, update)?;
+        buffer_pool::encode_document(&mut encoded, selector)?;
+        buffer_pool::encode_document(&mut encoded, update)?;
 
-        let _ = buffer.flush();
-        Ok(())
+        Message::write_parts(buffer, &fields, &encoded)
     }
 
     /// Writes a serialized update message to a given buffer.
@@ -445,22 +473,23 @@ impl Message {
         documents: &[bson::Document],
     ) -> Result<()> {
 
-        header.write(buffer)?;
-        buffer.write_i32::<LittleEndian>(flags.bits())?;
+        let mut fields = BufferPool::shared().get();
+        let mut encoded = BufferPool::shared().get();
+        header.write(&mut *fields)?;
+        fields.write_i32::<LittleEndian>(flags.bits())?;
 
         for byte in namespace.bytes() {
-            buffer.write_u8(byte)?;
+            fields.write_u8(byte)?;
         }
 
         // Writes the null terminator for the collection name string.
-        buffer.write_u8(0)?;
+        fields.write_u8(0)?;
 
         for doc in documents {
-            Message::write_bson_document(buffer, doc)?;
+            buffer_pool::encode_document(&mut encoded, doc)?;
         }
 
-        let _ = buffer.flush();
-        Ok(())
+        Message::write_parts(buffer, &fields, &encoded)
     }
 
     /// Writes a serialized query message to a given buffer.
@@ -495,26 +524,27 @@ impl Message {
         return_field_selector: &Option<bson::Document>,
     ) -> Result<()> {
 
-        header.write(buffer)?;
-        buffer.write_i32::<LittleEndian>(flags.bits())?;
+        let mut fields = BufferPool::shared().get();
+        let mut encoded = BufferPool::shared().get();
+        header.write(&mut *fields)?;
+        fields.write_i32::<LittleEndian>(flags.bits())?;
 
         for byte in namespace.bytes() {
-            buffer.write_u8(byte)?;
+            fields.write_u8(byte)?;
         }
 
         // Writes the null terminator for the collection name string.
-        buffer.write_u8(0)?;
+        fields.write_u8(0)?;
 
-        buffer.write_i32::<LittleEndian>(number_to_skip)?;
-        buffer.write_i32::<LittleEndian>(number_to_return)?;
-        Message::write_bson_document(buffer, query)?;
+        fields.write_i32::<LittleEndian>(number_to_skip)?;
+        fields.write_i32::<LittleEndian>(number_to_return)?;
+        buffer_pool::encode_document(&mut encoded, query)?;
 
         if let Some(ref doc) = *return_field_selector {
-            Message::write_bson_document(buffer, doc)?;
+            buffer_pool::encode_document(&mut encoded, doc)?;
         }
 
-        let _ = buffer.flush();
-        Ok(())
+        Message::write_parts(buffer, &fields, &encoded)
     }
 
     /// Writes a serialized "get more" request to a given buffer.
@@ -540,23 +570,23 @@ impl Message {
         cursor_id: i64,
     ) -> Result<()> {
 
-        header.write(buffer)?;
+        let mut fields = BufferPool::shared().get();
+        header.write(&mut *fields)?;
 
         // Write ZERO field
-        buffer.write_i32::<LittleEndian>(0)?;
+        fields.write_i32::<LittleEndian>(0)?;
 
         for byte in namespace.bytes() {
-            buffer.write_u8(byte)?;
+            fields.write_u8(byte)?;
         }
 
         // Writes the null terminator for the collection name string.
-        buffer.write_u8(0)?;
+        fields.write_u8(0)?;
 
-        buffer.write_i32::<LittleEndian>(number_to_return)?;
-        buffer.write_i64::<LittleEndian>(cursor_id)?;
+        fields.write_i32::<LittleEndian>(number_to_return)?;
+        fields.write_i64::<LittleEndian>(cursor_id)?;
 
-        let _ = buffer.flush();
-        Ok(())
+        Message::write_parts(buffer, &fields, &[])
     }
 
     /// Attemps to write the serialized message to a buffer.
@@ -657,6 +687,28 @@ impl Message {
         Ok(Message::new_reply(header, flags, cid, sf, nr, v))
     }
 
+    /// Reads a whole message, header included, into `frame`.
+    ///
+    /// # Arguments
+    ///
+    /// `buffer` - The buffer to read from.
+    /// `frame` - The buffer to read the message into.
+    ///
+    /// # Return value
+    ///
+    /// Returns nothing on success, or an Error on failure.
+    fn read_frame<R: Read>(buffer: &mut R, frame: &mut Vec<u8>) -> Result<()> {
+        let message_length = buffer.read_i32::<LittleEndian>()?;
+        if message_length < mem::size_of::<Header>() as i32 {
+            return Err(ResponseError(format!("Invalid message length {}", message_length)));
+        }
+
+        frame.write_i32::<LittleEndian>(message_length)?;
+        frame.resize(message_length as usize, 0);
+        buffer.read_exact(&mut frame[4..])?;
+        Ok(())
+    }
+
     /// Attempts to read a serialized reply Message from a buffer.
     ///
     /// # Arguments
@@ -670,9 +722,14 @@ impl Message {
     where
         T: Read + Write,
     {
-        let header = Header::read(buffer)?;
+        // The whole message is pulled from the buffer before any of it is decoded.
+        let mut frame = BufferPool::shared().get();
+        Message::read_frame(buffer, &mut frame)?;
+
+        let mut bytes = &frame[..];
+        let header = Header::read(&mut bytes)?;
         match header.op_code {
-            OpCode::Reply => Message::read_reply(buffer, header),
+            OpCode::Reply => Message::read_reply(&mut bytes, header),
             opcode => {
                 Err(ResponseError(format!(
                     "Expected to read OpCode::Reply but instead found \
@@ -682,4 +739,27 @@ impl Message {
             }
         }
     }
+
+    /// Attempts to read a serialized reply from a buffer without decoding its documents.
+    ///
+    /// # Arguments
+    ///
+    /// `buffer` - The buffer to read from.
+    ///
+    /// # Return value
+    ///
+    /// Returns the reply on success, or an Error on failure.
+    pub fn read_raw<T>(buffer: &mut T) -> Result<RawReply>
+    where
+        T: Read + Write,
+    {
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(buffer)?;
+
+        Ok(RawReply {
+            flags: reply.flags(),
+            cursor_id: reply.cursor_id(),
+            documents: reply.documents().map(<[u8]>::to_vec).collect(),
+        })
+    }
 }
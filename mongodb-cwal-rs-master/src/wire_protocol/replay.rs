@@ -0,0 +1,192 @@
+//! Replay of recorded server traffic, for testing the wire and cursor layers without a server.
+//!
+//! Recordings live in `tests/wire` as hex dumps of the bytes a server sent: whitespace
+//! separated byte pairs, with `#` starting a comment that runs to the end of the line. A
+//! recording may hold several messages back to back.
+use std::cmp;
+use std::fs;
+use std::io::{self, Read, Write};
+
+// Reads the hex dump `tests/wire/<name>.hex`.
+fn read_recording(name: &str) -> String {
+    let path = format!("{}/tests/wire/{}.hex", env!("CARGO_MANIFEST_DIR"), name);
+    fs::read_to_string(&path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err))
+}
+
+/// A stream that returns a recording to readers and collects whatever is written to it.
+#[derive(Debug)]
+pub struct Replay {
+    data: Vec<u8>,
+    position: usize,
+    // The most bytes returned by a single read, to split messages the way a socket may.
+    chunk_size: usize,
+    /// The bytes written to the stream, such as requests sent in response to the replies.
+    pub written: Vec<u8>,
+    /// How many times the stream was read from.
+    pub reads: usize,
+}
+
+impl Replay {
+    /// Returns a stream replaying the hex dump `recording`.
+    pub fn from_hex(recording: &str) -> Replay {
+        let data = recording
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+            .map(|byte| {
+                u8::from_str_radix(byte, 16).unwrap_or_else(|_| {
+                    panic!("Invalid byte '{}' in wire recording.", byte)
+                })
+            })
+            .collect();
+
+        Replay {
+            data: data,
+            position: 0,
+            chunk_size: usize::max_value(),
+            written: Vec::new(),
+            reads: 0,
+        }
+    }
+
+    /// Returns a stream replaying the recording `tests/wire/<name>.hex`.
+    pub fn recording(name: &str) -> Replay {
+        Replay::from_hex(&read_recording(name))
+    }
+
+    /// Returns the recording in reads of at most `chunk_size` bytes.
+    pub fn in_chunks(mut self, chunk_size: usize) -> Replay {
+        self.chunk_size = cmp::max(chunk_size, 1);
+        self
+    }
+
+    /// Returns how many recorded bytes have not been read yet.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(cmp::min(buf.len(), self.chunk_size), self.remaining());
+        buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+        self.position += len;
+        self.reads += 1;
+        Ok(len)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{Bson, doc};
+
+    use wire_protocol::flags::OpReplyFlags;
+    use wire_protocol::operations::Message;
+    use Error::IoError;
+
+    #[test]
+    fn decodes_batches_split_across_reads() {
+        for &chunk_size in &[1, 5, usize::max_value()] {
+            let mut replay = Replay::recording("get_more_batch").in_chunks(chunk_size);
+
+            match Message::read(&mut replay).unwrap() {
+                Message::OpReply {
+                    flags,
+                    cursor_id,
+                    starting_from,
+                    number_returned,
+                    documents,
+                    ..
+                } => {
+                    assert_eq!(OpReplyFlags::AWAIT_CAPABLE, flags);
+                    assert_eq!(4660, cursor_id);
+                    assert_eq!(2, starting_from);
+                    assert_eq!(2, number_returned);
+                    assert_eq!(
+                        vec![doc! { "_id": 1 }, doc! { "_id": 2, "name": "two" }],
+                        documents
+                    );
+                }
+                message => panic!("Expected a reply, got {:?}", message),
+            }
+
+            assert_eq!(0, replay.remaining());
+            if chunk_size == usize::max_value() {
+                // The length prefix, then the rest of the message at once.
+                assert_eq!(2, replay.reads);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_raw_batches_split_across_reads() {
+        let mut replay = Replay::recording("get_more_batch").in_chunks(3);
+        let reply = Message::read_raw(&mut replay).unwrap();
+
+        assert_eq!(4660, reply.cursor_id);
+        assert_eq!(2, reply.documents.len());
+        assert_eq!(14, reply.documents[0].len());
+        assert_eq!(0, replay.remaining());
+    }
+
+    #[test]
+    fn decodes_empty_payloads() {
+        let mut replay = Replay::recording("empty_batch");
+
+        match Message::read(&mut replay).unwrap() {
+            Message::OpReply { cursor_id, number_returned, documents, .. } => {
+                assert_eq!(4660, cursor_id);
+                assert_eq!(0, number_returned);
+                assert!(documents.is_empty());
+            }
+            message => panic!("Expected a reply, got {:?}", message),
+        }
+
+        let reply = Message::read_raw(&mut Replay::recording("empty_batch")).unwrap();
+        assert!(reply.documents.is_empty());
+    }
+
+    #[test]
+    fn reads_consecutive_replies() {
+        let mut replay = Replay::recording("batch_then_failure").in_chunks(7);
+
+        match Message::read(&mut replay).unwrap() {
+            Message::OpReply { documents, .. } => assert_eq!(vec![doc! { "_id": 1 }], documents),
+            message => panic!("Expected a reply, got {:?}", message),
+        }
+
+        match Message::read(&mut replay).unwrap() {
+            Message::OpReply { flags, documents, .. } => {
+                assert_eq!(OpReplyFlags::QUERY_FAILURE, flags);
+                assert_eq!(Some(&Bson::from("cursor killed")), documents[0].get("$err"));
+            }
+            message => panic!("Expected a reply, got {:?}", message),
+        }
+
+        match Message::read(&mut replay) {
+            Err(IoError(_)) => (),
+            result => panic!("Expected the recording to be exhausted, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_on_truncated_replies() {
+        let mut recording = read_recording("get_more_batch");
+        let len = recording.trim_end().len();
+        recording.truncate(len - 3);
+
+        let mut replay = Replay::from_hex(&recording);
+        assert!(Message::read(&mut replay).is_err());
+    }
+}
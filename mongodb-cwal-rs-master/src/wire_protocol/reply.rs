@@ -0,0 +1,284 @@
+//! Replies read into a reusable buffer, with their documents left encoded.
+//!
+//! A `ReplyBuffer` keeps its allocation from one reply to the next, so once it has grown to
+//! the size of a batch, reading a reply copies its bytes off the socket once and allocates
+//! nothing. Documents are handed out as slices of the buffer and only decoded on request.
+//!
+//! The fields preceding the documents are read with a single call, and the documents with
+//! another, so a reply is pulled from a buffered stream in two copies.
+use bson;
+use byteorder::{ByteOrder, LittleEndian};
+use Error::ResponseError;
+use Result;
+use wire_protocol::buffer_pool::encode_document;
+use wire_protocol::flags::OpReplyFlags;
+use wire_protocol::header::{Header, OpCode};
+
+use std::io::Read;
+use std::mem;
+use std::ops::Range;
+
+const HEADER_LENGTH: usize = mem::size_of::<Header>();
+// flags, cursor_id, starting_from and number_returned
+const REPLY_FIELDS_LENGTH: usize = 20;
+const REPLY_PREFIX_LENGTH: usize = HEADER_LENGTH + REPLY_FIELDS_LENGTH;
+
+/// The last OP_REPLY read from a stream, with its documents left encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplyBuffer {
+    message_length: i32,
+    flags: OpReplyFlags,
+    cursor_id: i64,
+    // The documents of the reply, back to back.
+    bytes: Vec<u8>,
+    // Where each document lies in `bytes`.
+    documents: Vec<Range<usize>>,
+}
+
+impl ReplyBuffer {
+    /// Returns an empty buffer, which grows to the size of the largest reply read into it.
+    pub fn new() -> ReplyBuffer {
+        ReplyBuffer {
+            message_length: 0,
+            flags: OpReplyFlags::empty(),
+            cursor_id: 0,
+            bytes: Vec::new(),
+            documents: Vec::new(),
+        }
+    }
+
+    /// Reads a reply from `buffer`, replacing the one held so far.
+    ///
+    /// If reading fails, the buffer is left empty.
+    pub fn read_from<R: Read>(&mut self, buffer: &mut R) -> Result<()> {
+        self.message_length = 0;
+        self.bytes.clear();
+        self.documents.clear();
+
+        let mut prefix = [0; REPLY_PREFIX_LENGTH];
+        buffer.read_exact(&mut prefix)?;
+
+        let header = Header::read(&mut &prefix[..HEADER_LENGTH])?;
+        if header.op_code != OpCode::Reply {
+            return Err(ResponseError(format!(
+                "Expected to read OpCode::Reply but instead found opcode {}",
+                header.op_code
+            )));
+        }
+
+        let length = header.message_length - REPLY_PREFIX_LENGTH as i32;
+        if length < 0 {
+            return Err(ResponseError(format!("Invalid reply length {}", header.message_length)));
+        }
+
+        // starting_from and number_returned are not kept.
+        let flags = LittleEndian::read_i32(&prefix[HEADER_LENGTH..]);
+        let cursor_id = LittleEndian::read_i64(&prefix[HEADER_LENGTH + 4..]);
+
+        self.bytes.resize(length as usize, 0);
+        if let Err(err) = buffer.read_exact(&mut self.bytes) {
+            self.bytes.clear();
+            return Err(err.into());
+        }
+
+        let mut start = 0;
+        while start < self.bytes.len() {
+            let left = self.bytes.len() - start;
+            let doc_length = if left < 4 {
+                left as i32
+            } else {
+                LittleEndian::read_i32(&self.bytes[start..])
+            };
+
+            if doc_length < 5 || doc_length as usize > left {
+                self.bytes.clear();
+                self.documents.clear();
+                return Err(ResponseError(format!(
+                    "Invalid document length {} in a reply with {} bytes left",
+                    doc_length,
+                    left
+                )));
+            }
+
+            self.documents.push(start..start + doc_length as usize);
+            start += doc_length as usize;
+        }
+
+        self.message_length = header.message_length;
+        self.flags = OpReplyFlags::from_bits_truncate(flags);
+        self.cursor_id = cursor_id;
+        Ok(())
+    }
+
+    /// Returns the length of the whole message in bytes, as declared by its header.
+    pub fn message_length(&self) -> i32 {
+        self.message_length
+    }
+
+    /// Returns the bit vector of reply options.
+    pub fn flags(&self) -> OpReplyFlags {
+        self.flags
+    }
+
+    /// Returns the id of the cursor the reply belongs to.
+    pub fn cursor_id(&self) -> i64 {
+        self.cursor_id
+    }
+
+    /// Returns how many documents the reply holds.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns whether the reply holds no documents.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Returns the size in bytes of the encoded documents.
+    pub fn documents_length(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns the encoded document at `index`.
+    pub fn document(&self, index: usize) -> Option<&[u8]> {
+        self.documents.get(index).map(|range| &self.bytes[range.clone()])
+    }
+
+    /// Returns an iterator over the encoded documents.
+    pub fn documents(&self) -> Documents<'_> {
+        Documents {
+            reply: self,
+            index: 0,
+        }
+    }
+
+    /// Decodes the document at `index`.
+    pub fn decode(&self, index: usize) -> Option<Result<bson::Document>> {
+        self.document(index).map(|mut bytes| {
+            bson::decode_document(&mut bytes).map_err(::Error::from)
+        })
+    }
+
+    /// Appends an encoded document after those of the reply.
+    pub fn push_encoded(&mut self, document: &[u8]) {
+        let start = self.bytes.len();
+        self.bytes.extend_from_slice(document);
+        self.documents.push(start..self.bytes.len());
+    }
+
+    /// Encodes `doc` after the documents of the reply.
+    pub fn push_document(&mut self, doc: &bson::Document) -> Result<()> {
+        let start = self.bytes.len();
+        if let Err(err) = encode_document(&mut self.bytes, doc) {
+            self.bytes.truncate(start);
+            return Err(err);
+        }
+        self.documents.push(start..self.bytes.len());
+        Ok(())
+    }
+
+    /// Removes the first `len` documents, keeping the allocation.
+    pub fn remove_front(&mut self, len: usize) {
+        let len = len.min(self.documents.len());
+        if len == self.documents.len() {
+            self.bytes.clear();
+            self.documents.clear();
+            return;
+        }
+
+        let end = self.documents[len].start;
+        self.bytes.drain(..end);
+        self.documents.drain(..len);
+        for range in &mut self.documents {
+            *range = range.start - end..range.end - end;
+        }
+    }
+}
+
+/// Iterates over the encoded documents of a reply; created by `ReplyBuffer::documents`.
+#[derive(Debug)]
+pub struct Documents<'a> {
+    reply: &'a ReplyBuffer,
+    index: usize,
+}
+
+impl<'a> Iterator for Documents<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let document = self.reply.document(self.index);
+        self.index += 1;
+        document
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::doc;
+    use wire_protocol::replay::Replay;
+
+    #[test]
+    fn reuses_the_buffer_between_replies() {
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut Replay::recording("get_more_batch").in_chunks(3)).unwrap();
+
+        assert_eq!(4660, reply.cursor_id());
+        assert_eq!(OpReplyFlags::AWAIT_CAPABLE, reply.flags());
+        assert_eq!(2, reply.len());
+        assert_eq!(14, reply.document(0).unwrap().len());
+        assert_eq!(Some(doc! { "_id": 2, "name": "two" }), reply.decode(1).map(Result::unwrap));
+
+        let capacity = reply.bytes.capacity();
+        reply.read_from(&mut Replay::recording("empty_batch")).unwrap();
+        assert!(reply.is_empty());
+        assert_eq!(0, reply.documents().count());
+        assert_eq!(capacity, reply.bytes.capacity());
+    }
+
+    #[test]
+    fn reads_each_reply_in_two_calls() {
+        let mut replay = Replay::recording("get_more_batch");
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut replay).unwrap();
+
+        assert_eq!(2, reply.len());
+        assert_eq!(2, replay.reads);
+    }
+
+    #[test]
+    fn fails_on_invalid_document_lengths() {
+        let mut reply = ReplyBuffer::new();
+        let mut replay = Replay::from_hex(
+            "30 00 00 00  00 00 00 00  01 00 00 00  01 00 00 00 # header
+             00 00 00 00  00 00 00 00  00 00 00 00  00 00 00 00  01 00 00 00
+             ff 00 00 00  00 00 00 00  00 00 00 00 # a 255 byte document",
+        );
+
+        match reply.read_from(&mut replay) {
+            Err(ResponseError(ref message)) if message.starts_with("Invalid document length") => (),
+            result => panic!("Expected an invalid document length, got {:?}", result),
+        }
+        assert!(reply.is_empty());
+    }
+
+    #[test]
+    fn appends_and_removes_documents() {
+        let mut reply = ReplyBuffer::new();
+        reply.read_from(&mut Replay::recording("get_more_batch")).unwrap();
+        reply.push_document(&doc! { "_id": 3 }).unwrap();
+        let first = reply.document(0).unwrap().to_vec();
+        reply.push_encoded(&first);
+        assert_eq!(4, reply.len());
+
+        reply.remove_front(2);
+        assert_eq!(Some(doc! { "_id": 3 }), reply.decode(0).map(Result::unwrap));
+        assert_eq!(Some(doc! { "_id": 1 }), reply.decode(1).map(Result::unwrap));
+        assert_eq!(2, reply.len());
+
+        reply.remove_front(5);
+        assert!(reply.is_empty());
+        assert_eq!(0, reply.documents_length());
+    }
+}
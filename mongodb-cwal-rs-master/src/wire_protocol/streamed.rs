@@ -0,0 +1,357 @@
+//! Insert commands written straight onto the socket.
+//!
+//! A binary field can be streamed from a reader, so a large blob never has to be buffered in
+//! memory before it is sent. Its length must be known up front, since BSON prefixes every
+//! document with its size.
+//!
+//! Documents that are already encoded can be sent as they are, without being decoded and
+//! encoded again.
+use bson::{self, spec::BinarySubtype};
+use byteorder::{LittleEndian, WriteBytesExt};
+use Error::{ArgumentError, IoError};
+use Result;
+use wire_protocol::buffer_pool::{self, BufferPool, PooledBuffer};
+use wire_protocol::flags::OpQueryFlags;
+use wire_protocol::header::Header;
+use wire_protocol::vectored;
+
+use std::i32;
+use std::io::{self, IoSlice, Read, Write};
+use std::mem;
+
+// BSON element type tags used when splicing documents together.
+const ELEMENT_DOCUMENT: u8 = 0x03;
+const ELEMENT_ARRAY: u8 = 0x04;
+const ELEMENT_BINARY: u8 = 0x05;
+
+/// A binary field whose contents are read from `source` while the message is written.
+#[derive(Debug)]
+pub struct StreamedBinary<R: Read> {
+    /// The field name the binary is stored under.
+    pub key: String,
+    /// The binary subtype. The deprecated `BinaryOld` subtype is not supported.
+    pub subtype: BinarySubtype,
+    /// The exact number of bytes that will be read from `source`.
+    pub length: usize,
+    /// The reader producing the binary contents.
+    pub source: R,
+}
+
+impl<R: Read> StreamedBinary<R> {
+    pub fn new(key: &str, subtype: BinarySubtype, length: usize, source: R) -> StreamedBinary<R> {
+        StreamedBinary {
+            key: String::from(key),
+            subtype: subtype,
+            length: length,
+            source: source,
+        }
+    }
+}
+
+/// Writes an OP_QUERY message running `command` against `namespace` (`<db>.$cmd`), with a
+/// `documents` array holding `document` extended by the streamed `binary` field.
+///
+/// If this fails after the header was sent, the stream holds a partial message and must not
+/// be reused.
+pub fn write_insert_command<W: Write, R: Read>(
+    buffer: &mut W,
+    request_id: i32,
+    namespace: &str,
+    command: &bson::Document,
+    document: &bson::Document,
+    binary: StreamedBinary<R>,
+) -> Result<()> {
+    if binary.subtype == BinarySubtype::BinaryOld {
+        return Err(ArgumentError(String::from(
+            "The BinaryOld subtype cannot be streamed.",
+        )));
+    }
+
+    if binary.key.contains('\0') {
+        return Err(ArgumentError(format!(
+            "The streamed field name '{}' contains a null byte.",
+            binary.key.replace('\0', "\\0")
+        )));
+    }
+
+    let command_bytes = encode(command)?;
+    let document_bytes = encode(document)?;
+
+    // type tag, key cstring, length, subtype, payload
+    let binary_length = 1 + binary.key.len() + 1 + 4 + 1 + binary.length;
+    let document_length = document_bytes.len() + binary_length;
+    // length, { "0": document }, terminator
+    let array_length = 4 + 1 + 2 + document_length + 1;
+    // type tag, "documents" cstring, array
+    let command_length = command_bytes.len() + 1 + 10 + array_length;
+
+    let header_length = mem::size_of::<Header>();
+    let message_length = header_length + 4 + namespace.len() + 1 + 4 + 4 + command_length;
+
+    if message_length > i32::MAX as usize {
+        return Err(ArgumentError(format!(
+            "A streamed insert of {} bytes exceeds the maximum message size.",
+            message_length
+        )));
+    }
+
+    Header::new_query(message_length as i32, request_id).write(buffer)?;
+    buffer.write_i32::<LittleEndian>(OpQueryFlags::empty().bits())?;
+    write_cstring(buffer, namespace)?;
+    buffer.write_i32::<LittleEndian>(0)?;
+    buffer.write_i32::<LittleEndian>(-1)?;
+
+    // The command, reopened to append the documents array.
+    buffer.write_i32::<LittleEndian>(command_length as i32)?;
+    buffer.write_all(elements(&command_bytes))?;
+    buffer.write_u8(ELEMENT_ARRAY)?;
+    write_cstring(buffer, "documents")?;
+    buffer.write_i32::<LittleEndian>(array_length as i32)?;
+    buffer.write_u8(ELEMENT_DOCUMENT)?;
+    write_cstring(buffer, "0")?;
+
+    // The document, reopened to append the binary field.
+    buffer.write_i32::<LittleEndian>(document_length as i32)?;
+    buffer.write_all(elements(&document_bytes))?;
+    buffer.write_u8(ELEMENT_BINARY)?;
+    write_cstring(buffer, &binary.key)?;
+    buffer.write_i32::<LittleEndian>(binary.length as i32)?;
+    buffer.write_u8(u8::from(binary.subtype))?;
+
+    let copied = io::copy(&mut binary.source.take(binary.length as u64), buffer)?;
+    if copied != binary.length as u64 {
+        return Err(IoError(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Streamed binary '{}' ended after {} of {} bytes.",
+                binary.key,
+                copied,
+                binary.length
+            ),
+        )));
+    }
+
+    // Terminate the document, the array and the command.
+    buffer.write_all(&[0, 0, 0])?;
+    buffer.flush()?;
+    Ok(())
+}
+
+/// Writes an OP_QUERY message running `command` against `namespace` (`<db>.$cmd`), with a
+/// `documents` array holding the already encoded `documents`.
+///
+/// The documents are copied as they are; they are not checked to be valid BSON. They are
+/// written to `buffer` in place, alongside the fields of the message, rather than copied into
+/// the message first.
+pub fn write_raw_insert_command<W: Write, D: AsRef<[u8]>>(
+    buffer: &mut W,
+    request_id: i32,
+    namespace: &str,
+    command: &bson::Document,
+    documents: &[D],
+) -> Result<()> {
+    let command_bytes = encode(command)?;
+
+    // The type tag and "<index>" key of each document, back to back.
+    let mut keys = BufferPool::shared().get();
+    let mut key_ends = Vec::with_capacity(documents.len());
+    let mut documents_length = 0;
+    for (index, document) in documents.iter().enumerate() {
+        keys.write_u8(ELEMENT_DOCUMENT)?;
+        write!(&mut *keys, "{}", index)?;
+        keys.write_u8(0)?;
+        key_ends.push(keys.len());
+        documents_length += document.as_ref().len();
+    }
+
+    // length, { "<index>": document }..., terminator
+    let array_length = 4 + keys.len() + documents_length + 1;
+    // type tag, "documents" cstring, array
+    let command_length = command_bytes.len() + 1 + 10 + array_length;
+
+    let header_length = mem::size_of::<Header>();
+    let message_length = header_length + 4 + namespace.len() + 1 + 4 + 4 + command_length;
+
+    if message_length > i32::MAX as usize {
+        return Err(ArgumentError(format!(
+            "An insert of {} bytes exceeds the maximum message size.",
+            message_length
+        )));
+    }
+
+    let mut fields = BufferPool::shared().get();
+    Header::new_query(message_length as i32, request_id).write(&mut *fields)?;
+    fields.write_i32::<LittleEndian>(OpQueryFlags::empty().bits())?;
+    write_cstring(&mut *fields, namespace)?;
+    fields.write_i32::<LittleEndian>(0)?;
+    fields.write_i32::<LittleEndian>(-1)?;
+
+    // The command, reopened to append the documents array.
+    fields.write_i32::<LittleEndian>(command_length as i32)?;
+    fields.write_all(elements(&command_bytes))?;
+    fields.write_u8(ELEMENT_ARRAY)?;
+    write_cstring(&mut *fields, "documents")?;
+    fields.write_i32::<LittleEndian>(array_length as i32)?;
+
+    let mut parts = Vec::with_capacity(2 * documents.len() + 2);
+    parts.push(IoSlice::new(&fields));
+    let mut key_start = 0;
+    for (document, &key_end) in documents.iter().zip(&key_ends) {
+        parts.push(IoSlice::new(&keys[key_start..key_end]));
+        parts.push(IoSlice::new(document.as_ref()));
+        key_start = key_end;
+    }
+
+    // Terminate the array and the command.
+    parts.push(IoSlice::new(&[0, 0]));
+
+    vectored::write_all(buffer, &mut parts)?;
+    buffer.flush()?;
+    Ok(())
+}
+
+fn encode(document: &bson::Document) -> Result<PooledBuffer<'static>> {
+    let mut bytes = BufferPool::shared().get();
+    buffer_pool::encode_document(&mut bytes, document)?;
+    Ok(bytes)
+}
+
+// Strips the length prefix and the terminator from an encoded document.
+fn elements(bytes: &[u8]) -> &[u8] {
+    &bytes[4..bytes.len() - 1]
+}
+
+fn write_cstring<W: Write>(buffer: &mut W, string: &str) -> Result<()> {
+    buffer.write_all(string.as_bytes())?;
+    buffer.write_u8(0)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bson::{Bson, doc};
+    use byteorder::ReadBytesExt;
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_valid_bson() {
+        let payload = vec![7u8; 300];
+        let binary = StreamedBinary::new("data", BinarySubtype::Generic, 300, &payload[..]);
+
+        let mut buffer = Vec::new();
+        write_insert_command(
+            &mut buffer,
+            42,
+            "test.$cmd",
+            &doc! { "insert": "blobs", "ordered": true },
+            &doc! { "_id": 1, "name": "blob" },
+            binary,
+        ).unwrap();
+
+        let mut reader = Cursor::new(&buffer[..]);
+        let header = Header::read(&mut reader).unwrap();
+        assert_eq!(buffer.len() as i32, header.message_length);
+        assert_eq!(42, header.request_id);
+
+        reader.read_i32::<LittleEndian>().unwrap();
+        let mut namespace = Vec::new();
+        loop {
+            match reader.read_u8().unwrap() {
+                0 => break,
+                byte => namespace.push(byte),
+            }
+        }
+        assert_eq!(b"test.$cmd", &namespace[..]);
+        reader.read_i32::<LittleEndian>().unwrap();
+        reader.read_i32::<LittleEndian>().unwrap();
+
+        let command = bson::decode_document(&mut reader).unwrap();
+        assert_eq!(reader.position() as usize, buffer.len());
+
+        let expected = doc! {
+            "insert": "blobs",
+            "ordered": true,
+            "documents": [{
+                "_id": 1,
+                "name": "blob",
+                "data": Bson::Binary(BinarySubtype::Generic, payload),
+            }],
+        };
+        assert_eq!(expected, command);
+    }
+
+    #[test]
+    fn writes_raw_documents_unchanged() {
+        let documents = vec![
+            encode(&doc! { "_id": 1, "name": "a" }).unwrap(),
+            encode(&doc! { "_id": 2, "tags": ["b", "c"] }).unwrap(),
+        ];
+
+        let mut buffer = Vec::new();
+        write_raw_insert_command(
+            &mut buffer,
+            7,
+            "test.$cmd",
+            &doc! { "insert": "events", "ordered": false },
+            &documents,
+        ).unwrap();
+
+        let mut reader = Cursor::new(&buffer[..]);
+        let header = Header::read(&mut reader).unwrap();
+        assert_eq!(buffer.len() as i32, header.message_length);
+
+        // flags, "test.$cmd", skip and limit
+        reader.set_position(reader.position() + 4 + 10 + 4 + 4);
+
+        let command = bson::decode_document(&mut reader).unwrap();
+        assert_eq!(reader.position() as usize, buffer.len());
+
+        let expected = doc! {
+            "insert": "events",
+            "ordered": false,
+            "documents": [{ "_id": 1, "name": "a" }, { "_id": 2, "tags": ["b", "c"] }],
+        };
+        assert_eq!(expected, command);
+    }
+
+    #[test]
+    fn rejects_short_source() {
+        let binary = StreamedBinary::new("data", BinarySubtype::Generic, 10, &b"short"[..]);
+        let mut buffer = Vec::new();
+
+        let result = write_insert_command(
+            &mut buffer,
+            1,
+            "test.$cmd",
+            &doc! { "insert": "blobs" },
+            &doc! { "_id": 1 },
+            binary,
+        );
+
+        match result {
+            Err(IoError(ref err)) => assert_eq!(io::ErrorKind::UnexpectedEof, err.kind()),
+            other => panic!("expected an unexpected EOF error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_null_bytes_in_the_field_name() {
+        let binary = StreamedBinary::new("da\0ta", BinarySubtype::Generic, 4, &b"blob"[..]);
+        let mut buffer = Vec::new();
+
+        let result = write_insert_command(
+            &mut buffer,
+            1,
+            "test.$cmd",
+            &doc! { "insert": "blobs" },
+            &doc! { "_id": 1 },
+            binary,
+        );
+
+        match result {
+            Err(ArgumentError(_)) => assert!(buffer.is_empty()),
+            other => panic!("expected an argument error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Messages written as a list of parts.
+//!
+//! A message is assembled from its fixed fields and its encoded documents, which are handed to
+//! the socket together with `write_vectored` rather than copied into one contiguous buffer
+//! first.
+use std::io::{self, IoSlice, Write};
+
+/// Writes every byte of `parts` to `buffer`, in order.
+///
+/// Writers without vectored support write the first non-empty part on each call, which is
+/// what `Write::write_all` would have done for each part in turn.
+pub(crate) fn write_all<W: Write>(buffer: &mut W, mut parts: &mut [IoSlice]) -> io::Result<()> {
+    IoSlice::advance_slices(&mut parts, 0);
+
+    while !parts.is_empty() {
+        match buffer.write_vectored(parts) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole message",
+                ))
+            }
+            Ok(written) => IoSlice::advance_slices(&mut parts, written),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Accepts at most `limit` bytes per call, counting the parts it is offered.
+    struct Trickle {
+        written: Vec<u8>,
+        limit: usize,
+        calls: Vec<usize>,
+        interrupt: bool,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+            if self.interrupt {
+                self.interrupt = false;
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+            }
+
+            self.calls.push(bufs.len());
+            let mut left = self.limit;
+            for buf in bufs {
+                let len = buf.len().min(left);
+                self.written.extend_from_slice(&buf[..len]);
+                left -= len;
+            }
+            Ok(self.limit - left)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn resumes_partial_writes() {
+        let mut trickle = Trickle {
+            written: Vec::new(),
+            limit: 4,
+            calls: Vec::new(),
+            interrupt: true,
+        };
+
+        let document = vec![9u8; 6];
+        let mut parts = [
+            IoSlice::new(&[]),
+            IoSlice::new(&[1, 2, 3]),
+            IoSlice::new(&document),
+            IoSlice::new(&[]),
+            IoSlice::new(&[0]),
+        ];
+        write_all(&mut trickle, &mut parts).unwrap();
+
+        assert_eq!(vec![1, 2, 3, 9, 9, 9, 9, 9, 9, 0], trickle.written);
+        // Leading empty parts are skipped, and every call sees what is left of the message.
+        assert_eq!(vec![4, 3, 3], trickle.calls);
+    }
+
+    #[test]
+    fn fails_when_nothing_is_written() {
+        let mut full = Trickle {
+            written: Vec::new(),
+            limit: 0,
+            calls: Vec::new(),
+            interrupt: false,
+        };
+
+        let err = write_all(&mut full, &mut [IoSlice::new(&[1])]).unwrap_err();
+        assert_eq!(io::ErrorKind::WriteZero, err.kind());
+    }
+}
@@ -1,8 +1,12 @@
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
 use bson::Bson;
-use mongodb::{Client, ClientOptions, CommandResult, ThreadedClient};
+use mongodb::{Client, ClientOptions, CommandResult, ServerHeartbeatFailed, ServerHeartbeatStarted,
+              ServerHeartbeatSucceeded, ThreadedClient};
 use mongodb::db::ThreadedDatabase;
 use rand;
 
@@ -203,3 +207,35 @@ fn logging() {
 
     fs::remove_file("test_log.txt").unwrap();
 }
+
+static HEARTBEATS_STARTED: AtomicUsize = AtomicUsize::new(0);
+static HEARTBEATS_SUCCEEDED: AtomicUsize = AtomicUsize::new(0);
+
+fn heartbeat_started(_client: Client, started: &ServerHeartbeatStarted) {
+    assert!(!started.awaited);
+    HEARTBEATS_STARTED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn heartbeat_succeeded(_client: Client, succeeded: &ServerHeartbeatSucceeded) {
+    assert_eq!("localhost:27017", succeeded.connection_string);
+    HEARTBEATS_SUCCEEDED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn heartbeat_failed(_client: Client, failed: &ServerHeartbeatFailed) {
+    panic!("Heartbeat failed: {}", failed);
+}
+
+#[test]
+fn heartbeats() {
+    let mut options = ClientOptions::new();
+    options.heartbeat_frequency_ms = 500;
+    let mut client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    client.add_heartbeat_started_hook(heartbeat_started).unwrap();
+    client.add_heartbeat_succeeded_hook(heartbeat_succeeded).unwrap();
+    client.add_heartbeat_failed_hook(heartbeat_failed).unwrap();
+
+    thread::sleep(Duration::from_millis(1500));
+
+    assert!(HEARTBEATS_STARTED.load(Ordering::SeqCst) >= 1);
+    assert!(HEARTBEATS_SUCCEEDED.load(Ordering::SeqCst) >= 1);
+}
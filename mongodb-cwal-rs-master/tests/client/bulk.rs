@@ -1,5 +1,5 @@
 use bson::Bson;
-use mongodb::coll::options::WriteModel;
+use mongodb::coll::options::{BulkWriteOptions, InsertManyOptions, WriteModel};
 use mongodb::{Client, ThreadedClient};
 use mongodb::db::ThreadedDatabase;
 
@@ -21,7 +21,7 @@ fn bulk_ordered_insert_only() {
         })
         .collect();
 
-    coll.bulk_write(models, true);
+    coll.bulk_write(models, None);
 
     let cursor: Vec<_> = coll.find(None, None).unwrap().collect();
 
@@ -62,7 +62,13 @@ fn bulk_unordered_insert_only() {
         })
         .collect();
 
-    coll.bulk_write(models, false);
+    coll.bulk_write(
+        models,
+        Some(BulkWriteOptions {
+            ordered: Some(false),
+            ..Default::default()
+        }),
+    );
 
     let cursor: Vec<_> = coll.find(None, None).unwrap().collect();
 
@@ -173,7 +179,7 @@ fn bulk_ordered_mix() {
     let coll = db.collection("bulk_ordered_mix");
     coll.drop().unwrap();
 
-    let result = coll.bulk_write(models, true);
+    let result = coll.bulk_write(models, None);
 
     assert_eq!(result.inserted_count, 9);
     assert_eq!(result.inserted_ids.len() as i32, result.inserted_count);
@@ -204,3 +210,56 @@ fn bulk_ordered_mix() {
     check_value_in_tree!(result.inserted_ids, 12, 104);
     check_value_in_tree!(result.upserted_ids, 8, 6);
 }
+
+#[test]
+fn bulk_unordered_failures() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-bulk");
+    let coll = db.collection("bulk_unordered_failures");
+    coll.drop().unwrap();
+
+    coll.insert_one(doc! { "_id": 1, "x": 11 }, None).unwrap();
+
+    let models = vec![
+        WriteModel::UpdateOne {
+            filter: doc! { "_id": 1 },
+            update: doc! { "$inc": { "x": 1 } },
+            upsert: None,
+        },
+        WriteModel::InsertOne { document: doc! { "_id": 1 } },
+        WriteModel::InsertOne { document: doc! { "_id": 2 } },
+        WriteModel::InsertOne { document: doc! { "_id": 2 } },
+        WriteModel::DeleteOne { filter: doc! { "_id": 2 } },
+    ];
+
+    let result = coll.bulk_write(
+        models,
+        Some(BulkWriteOptions {
+            ordered: Some(false),
+            ..Default::default()
+        }),
+    );
+
+    let failure = result.failure().expect("Expected duplicate key errors.");
+    let indexes: Vec<_> = failure.write_errors.iter().map(|error| error.index).collect();
+    assert_eq!(vec![1, 3], indexes);
+    assert!(failure.write_errors.iter().all(|error| error.is_duplicate_key()));
+    assert!(failure.unprocessed_requests.is_empty());
+    assert_eq!(1, failure.inserted_count);
+    assert_eq!(1, failure.modified_count);
+    assert_eq!(1, failure.deleted_count);
+    assert_eq!(Some(&Bson::I32(2)), result.inserted_ids.get(&2));
+
+    let options = InsertManyOptions {
+        ordered: Some(false),
+        ..Default::default()
+    };
+    let docs = vec![doc! { "_id": 1 }, doc! { "_id": 3 }, doc! { "_id": 1 }, doc! { "_id": 4 }];
+    let result = coll.insert_many(docs, Some(options)).unwrap();
+
+    let failure = result.failure().expect("Expected duplicate key errors.");
+    let indexes: Vec<_> = failure.write_errors.iter().map(|error| error.index).collect();
+    assert_eq!(vec![0, 2], indexes);
+    assert_eq!(2, failure.inserted_count);
+    assert_eq!(3, coll.count(None, None).unwrap());
+}
@@ -1,9 +1,29 @@
-use bson::Bson;
-
-use mongodb::{Client, ThreadedClient};
+use bson::{Bson, Document};
+use bson::spec::BinarySubtype;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use mongodb::{Client, ClientOptions, CommandType, Error, ErrorCode, Result, ThreadedClient};
+use mongodb::common::{ReadConcern, ReadConcernLevel, WriteConcern};
+use mongodb::coll::checkpoint::Checkpoint;
+use mongodb::coll::explain::Verbosity;
+use mongodb::coll::filter::{Filter, TextSearch};
+use mongodb::coll::geo::{Geometry, Near, Shape};
+use mongodb::coll::pipeline::{Merge, Pipeline, WhenMatched};
 use mongodb::db::ThreadedDatabase;
-use mongodb::coll::options::{FindOptions, FindOneAndUpdateOptions, IndexModel, IndexOptions,
-                             ReturnDocument};
+use mongodb::interceptor::{CommandContext, CommandInterceptor, Interceptors};
+use mongodb::object_id::ObjectIdGenerator;
+use mongodb::wire_protocol::streamed::StreamedBinary;
+use mongodb::coll::options::{AggregateOptions, Collation, CommitQuorum, CreateIndexOptions,
+                             DeleteOptions, DistinctOptions, FindOptions,
+                             FindOneAndUpdateOptions, IndexDescription, IndexModel,
+                             IndexOptions, InsertManyOptions, MapReduceOptions,
+                             MapReduceOutput, ReturnDocument, UpdateOptions};
+use mongodb::coll::results::MapReduceResult;
 
 #[test]
 fn find_sorted() {
@@ -50,6 +70,67 @@ fn find_sorted() {
     assert!(cursor.next().is_none());
 }
 
+#[test]
+fn find_and_update_with_collation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_and_update_with_collation");
+
+    coll.drop().expect("Failed to drop collection");
+
+    coll.insert_many(vec![doc! { "name": "Ferret" }, doc! { "name": "ferret" }], None)
+        .expect("Failed to insert documents.");
+
+    // Strength 2 compares letters without regard to case.
+    let collation = Collation { strength: Some(2), ..Collation::new("en") };
+
+    let mut opts = FindOptions::new();
+    opts.collation = Some(collation.clone());
+
+    let results: Vec<_> = coll.find(Some(doc! { "name": "FERRET" }), Some(opts))
+        .expect("Failed to execute find command.")
+        .collect();
+    assert_eq!(2, results.len());
+
+    let mut opts = UpdateOptions::new();
+    opts.collation = Some(collation);
+
+    let result = coll.update_many(
+        doc! { "name": "FERRET" },
+        doc! { "$set": { "spirit_animal": true } },
+        Some(opts),
+    ).expect("Failed to update documents.");
+    assert_eq!(2, result.modified_count);
+}
+
+#[test]
+fn find_with_hint() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_with_hint");
+
+    coll.drop().expect("Failed to drop collection");
+
+    coll.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }], None)
+        .expect("Failed to insert documents.");
+    coll.create_index(doc! { "x": 1 }, None).expect("Failed to create index.");
+
+    let mut opts = FindOptions::new();
+    opts.hint = Some(String::from("x_1"));
+
+    let results: Vec<_> = coll.find(Some(doc! { "x": 2 }), Some(opts))
+        .expect("Failed to execute find command.")
+        .collect();
+    assert_eq!(1, results.len());
+
+    // Hinting an index that does not exist is rejected by the server.
+    let mut opts = FindOptions::new();
+    opts.hint_doc = Some(doc! { "missing": 1 });
+
+    let mut cursor = coll.find(None, Some(opts)).expect("Failed to execute find command.");
+    assert!(cursor.next().map_or(true, |result| result.is_err()));
+}
+
 #[test]
 fn find_and_insert() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -304,6 +385,68 @@ fn aggregate() {
     assert!(vec.contains(&"f".to_owned()));
 }
 
+#[test]
+fn aggregate_out() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("aggregate_out");
+    let out = db.collection("aggregate_out_results");
+
+    coll.drop().expect("Failed to drop collection");
+    out.drop().expect("Failed to drop collection");
+
+    coll.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }, doc! { "x": 3 }], None)
+        .expect("Failed to execute insert_many command.");
+
+    let pipeline = vec![
+        doc! { "$match": { "x": { "$gt": 1 } } },
+        doc! { "$out": "aggregate_out_results" },
+    ];
+
+    let mut cursor = coll.aggregate(pipeline, None).expect(
+        "Failed to execute aggregate command.",
+    );
+    assert!(cursor.next().is_none());
+
+    assert_eq!(2, out.count(None, None).expect("Failed to execute count."));
+}
+
+#[test]
+fn aggregate_merge_to_other_database() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    skip_if_db_version_below!(db, 4, 4);
+
+    let coll = db.collection("aggregate_merge");
+    let archive = client.db("test-client-coll-archive");
+    let out = archive.collection("aggregate_out_results");
+    let merged = archive.collection("aggregate_merge_results");
+
+    coll.drop().expect("Failed to drop collection");
+    out.drop().expect("Failed to drop collection");
+    merged.drop().expect("Failed to drop collection");
+
+    coll.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }, doc! { "x": 3 }], None)
+        .expect("Failed to execute insert_many command.");
+
+    let pipeline = Pipeline::new()
+        .match_(doc! { "x": { "$gt": 1 } })
+        .out_to_db("test-client-coll-archive", "aggregate_out_results");
+
+    coll.aggregate(pipeline.build(), None).expect("Failed to execute aggregate command.");
+    assert_eq!(2, out.count(None, None).expect("Failed to execute count."));
+
+    let pipeline = Pipeline::new().merge(
+        Merge::new("aggregate_merge_results")
+            .db("test-client-coll-archive")
+            .when_matched(WhenMatched::KeepExisting),
+    );
+
+    coll.aggregate(pipeline.clone().build(), None).expect("Failed to execute aggregate command.");
+    coll.aggregate(pipeline.build(), None).expect("Failed to execute aggregate command.");
+    assert_eq!(3, merged.count(None, None).expect("Failed to execute count."));
+}
+
 #[test]
 fn count() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -375,6 +518,30 @@ fn distinct_one() {
     assert_eq!(1, distinct_titles.len());
 }
 
+#[test]
+fn distinct_with_options() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("distinct_with_options");
+
+    coll.drop().expect("Failed to drop database");
+    coll.insert_many(vec![doc! { "title": "Jaws" }, doc! { "title": "JAWS" }], None)
+        .expect("Failed to insert documents.");
+
+    let mut collation = Collation::new("en");
+    collation.strength = Some(2);
+
+    let mut options = DistinctOptions::new();
+    options.max_time_ms = Some(5000);
+    options.collation = Some(collation);
+    options.read_concern = Some(ReadConcern::new(ReadConcernLevel::Local));
+
+    let distinct_titles = coll.distinct("title", None, Some(options)).expect(
+        "Failed to execute 'distinct'.",
+    );
+    assert_eq!(1, distinct_titles.len());
+}
+
 #[test]
 fn distinct() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -484,6 +651,134 @@ fn insert_many() {
     }
 }
 
+#[test]
+fn insert_many_split_into_batches() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_many_split_into_batches");
+
+    coll.drop().expect("Failed to drop database");
+
+    // Well over the 16MB a single insert command can carry.
+    let padding = "x".repeat(1024 * 1024);
+    let mut docs: Vec<_> = (0..40).map(|i| doc! { "_id": i, "padding": padding.clone() })
+        .collect();
+
+    let result = coll.insert_many(docs.clone(), None).expect("Failed to insert documents.");
+    assert!(result.bulk_write_exception.is_none());
+    assert_eq!(40, result.inserted_ids.unwrap().len());
+    assert_eq!(40, coll.count(None, None).expect("Failed to execute count."));
+
+    // Ordered inserts stop at the first batch with a write error, reported by input position.
+    coll.drop().expect("Failed to drop database");
+    docs[25] = doc! { "_id": 0, "padding": padding.clone() };
+
+    let result = coll.insert_many(docs, None).expect("Failed to insert documents.");
+    let exception = result.bulk_write_exception.expect("Expected a duplicate key error.");
+    assert_eq!(1, exception.write_errors.len());
+    assert_eq!(25, exception.write_errors[0].index);
+    assert_eq!(25, coll.count(None, None).expect("Failed to execute count."));
+}
+
+#[test]
+fn writes_to_views_rejected() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    skip_if_db_version_below!(db, 3, 4);
+
+    let source = db.collection("writes_to_views_source");
+    let view = db.collection("writes_to_views");
+
+    view.drop().expect("Failed to drop view");
+    source.drop().expect("Failed to drop collection");
+
+    db.command(
+        doc! {
+            "create": "writes_to_views",
+            "viewOn": "writes_to_views_source",
+            "pipeline": [{ "$match": { "visible": true } }],
+        },
+        CommandType::CreateCollection,
+        None,
+    ).expect("Failed to create view");
+
+    match view.insert_one(doc! { "visible": true }, None) {
+        Err(Error::ArgumentError(message)) => {
+            assert!(message.contains("test-client-coll.writes_to_views"));
+            assert!(message.contains("test-client-coll.writes_to_views_source"));
+        }
+        other => panic!("Expected an argument error, got {:?}", other),
+    }
+
+    assert!(view.delete_many(doc! {}, None).is_err());
+    assert!(source.insert_one(doc! { "visible": true }, None).is_ok());
+    assert_eq!(1, view.count(None, None).expect("Failed to count view."));
+}
+
+#[test]
+fn insert_one_streamed() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_one_streamed");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let payload: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    let binary = StreamedBinary::new("data", BinarySubtype::Generic, payload.len(), &payload[..]);
+
+    let result = coll.insert_one_streamed(doc! { "name": "blob" }, binary, None)
+        .expect("Failed to execute streamed insert.");
+    assert!(result.write_exception.is_none());
+
+    let doc = coll.find_one(Some(doc! { "name": "blob" }), None)
+        .expect("Failed to execute find command.")
+        .expect("Streamed document was not inserted.");
+
+    assert_eq!(Some(&Bson::Binary(BinarySubtype::Generic, payload)), doc.get("data"));
+    assert_eq!(result.inserted_id.as_ref(), doc.get("_id"));
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Movie {
+    title: String,
+    year: i32,
+}
+
+#[test]
+fn typed_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.typed_collection::<Movie>("typed_collection");
+
+    coll.untyped().drop().expect("Failed to drop collection");
+
+    let jaws = Movie { title: String::from("Jaws"), year: 1975 };
+    let back = Movie { title: String::from("Back to the Future"), year: 1985 };
+
+    coll.insert_many(&[jaws, back], None).expect("Failed to insert documents.");
+
+    let mut opts = FindOptions::new();
+    opts.sort = Some(doc! { "year": 1 });
+    opts.projection = Some(doc! { "_id": 0 });
+
+    let movies: Vec<Movie> = coll.find(None, Some(opts))
+        .expect("Failed to execute find.")
+        .map(|movie| movie.expect("Failed to decode document."))
+        .collect();
+
+    assert_eq!(2, movies.len());
+    assert_eq!("Jaws", movies[0].title);
+    assert_eq!(1985, movies[1].year);
+
+    let remake = Movie { title: String::from("Jaws"), year: 2030 };
+    coll.replace_one(doc! { "title": "Jaws" }, &remake, None)
+        .expect("Failed to replace document.");
+
+    let found = coll.find_one(Some(doc! { "title": "Jaws" }), None)
+        .expect("Failed to execute find.");
+    assert_eq!(Some(2030), found.map(|movie| movie.year));
+}
+
 #[test]
 fn delete_one() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -635,6 +930,41 @@ fn update_one() {
     }
 }
 
+#[test]
+fn update_one_with_array_filters() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("update_one_with_array_filters");
+
+    coll.drop().expect("Failed to drop collection");
+
+    coll.insert_one(doc! { "_id": 1, "grades": [95, 102, 90, 150] }, None)
+        .expect("Failed to insert document.");
+
+    let mut opts = UpdateOptions::new();
+    opts.array_filters = Some(vec![doc! { "elem": { "$gte": 100 } }]);
+
+    coll.update_one(
+        doc! { "_id": 1 },
+        doc! { "$set": { "grades.$[elem]": 100 } },
+        Some(opts),
+    ).expect("Failed to update document.");
+
+    let result = coll.find_one(Some(doc! { "_id": 1 }), None)
+        .expect("Failed to execute find command.")
+        .expect("Expected a document.");
+
+    match result.get("grades") {
+        Some(&Bson::Array(ref grades)) => {
+            assert_eq!(
+                &vec![Bson::I32(95), Bson::I32(100), Bson::I32(90), Bson::I32(100)],
+                grades
+            );
+        }
+        _ => panic!("Expected Bson::Array!"),
+    }
+}
+
 #[test]
 fn update_many() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -910,3 +1240,1164 @@ fn drop_all_indexes() {
 
     assert_eq!(1, results.len());
 }
+
+#[test]
+fn resumable_scan() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("resumable_scan");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs = (0..10).map(|i| doc! { "_id": i, "group": i % 3 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut scan = coll.find_resumable(None, doc! { "group": 1 }, None)
+        .expect("Failed to start scan.");
+
+    let first: Vec<_> = scan.by_ref()
+        .take(4)
+        .map(|doc| doc.expect("Failed to read document.").get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(vec![0, 3, 6, 9], first);
+
+    // Round-trip the checkpoint as a job would when persisting it.
+    let saved = bson::Document::from(scan.checkpoint());
+    let checkpoint = Checkpoint::from_document(saved).expect("Failed to decode checkpoint.");
+
+    let rest: Vec<_> = coll.resume_scan(None, &checkpoint, None)
+        .expect("Failed to resume scan.")
+        .map(|doc| doc.expect("Failed to read document.").get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(vec![1, 4, 7, 2, 5, 8], rest);
+}
+
+#[test]
+fn explain_find() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("explain_find");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs = (0..10).map(|i| doc! { "_id": i, "customer": i % 3 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+    coll.create_index(doc! { "customer": 1 }, None).expect("Failed to create index.");
+
+    let explain = coll.explain_find(Some(doc! { "customer": 1 }), None, Verbosity::ExecutionStats)
+        .expect("Failed to explain find.");
+
+    let plan = explain.winning_plan().expect("No winning plan reported.");
+    assert!(plan.uses_stage("IXSCAN"));
+    assert_eq!(vec![String::from("customer_1")], plan.index_names());
+
+    let stats = explain.execution_stats.expect("No execution stats reported.");
+    assert_eq!(3, stats.n_returned);
+    assert_eq!(3, stats.total_docs_examined);
+
+    let explain = coll.explain_aggregate(
+        vec![doc! { "$match": { "customer": 2 } }],
+        None,
+        Verbosity::QueryPlanner,
+    ).expect("Failed to explain aggregate.");
+    assert!(explain.winning_plan().expect("No winning plan reported.").uses_stage("IXSCAN"));
+}
+
+#[test]
+fn shard_distribution_of_unsharded_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("shard_distribution_of_unsharded_collection");
+
+    coll.insert_one(doc! { "x": 1 }, None).expect("Failed to insert document.");
+
+    match coll.shard_distribution() {
+        Err(Error::OperationError(_)) => (),
+        other => panic!("expected an operation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn max_time_ms_expired() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("max_time_ms_expired");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_one(doc! { "x": 1 }, None).expect("Failed to insert document.");
+
+    let filter = doc! { "$where": "sleep(100) || true" };
+    let mut options = FindOptions::new();
+    options.max_time_ms = Some(1);
+
+    match coll.find(Some(filter), Some(options)).and_then(|mut cursor| {
+        cursor.next().unwrap_or(Ok(doc! {}))
+    }) {
+        Err(Error::MaxTimeMSExpired(_)) => (),
+        other => panic!("expected the time limit to expire, got {:?}", other),
+    }
+}
+
+#[test]
+fn index_consistency_of_unsharded_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("index_consistency_of_unsharded_collection");
+
+    coll.insert_one(doc! { "x": 1 }, None).expect("Failed to insert document.");
+
+    match coll.check_index_consistency() {
+        Err(Error::OperationError(_)) => (),
+        other => panic!("expected an operation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn bypass_document_validation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("bypass_document_validation");
+
+    coll.drop().expect("Failed to drop collection");
+    db.command(
+        doc! {
+            "create": "bypass_document_validation",
+            "validator": { "email": { "$exists": true } },
+        },
+        CommandType::CreateCollection,
+        None,
+    ).expect("Failed to create collection");
+
+    let result = coll.insert_many(vec![doc! { "_id": 1 }], None)
+        .expect("Failed to send insert.");
+    assert!(result.bulk_write_exception.is_some());
+
+    let mut options = InsertManyOptions::new();
+    options.bypass_document_validation = Some(true);
+    let result = coll.insert_many(vec![doc! { "_id": 2 }], Some(options))
+        .expect("Failed to insert document.");
+    assert!(result.bulk_write_exception.is_none());
+
+    let mut options = UpdateOptions::new();
+    options.bypass_document_validation = Some(true);
+    coll.update_one(doc! { "_id": 2 }, doc! { "$set": { "name": "x" } }, Some(options))
+        .expect("Failed to update document.");
+
+    let doc = coll.find_one(Some(doc! { "_id": 2 }), None).unwrap().unwrap();
+    assert_eq!(Some(&Bson::String(String::from("x"))), doc.get("name"));
+}
+
+#[test]
+fn let_vars_and_comment() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("let_vars_and_comment");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_many(vec![doc! { "_id": 1, "qty": 5 }, doc! { "_id": 2, "qty": 10 }], None)
+        .expect("Failed to insert documents.");
+
+    let filter = doc! { "$expr": { "$gte": ["$qty", "$$threshold"] } };
+
+    let mut options = FindOptions::new();
+    options.let_vars = Some(doc! { "threshold": 8 });
+    options.comment = Some(String::from("let_vars_and_comment find"));
+    let found: Vec<_> = coll.find(Some(filter.clone()), Some(options))
+        .expect("Failed to execute find.")
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(vec![doc! { "_id": 2, "qty": 10 }], found);
+
+    let mut options = UpdateOptions::new();
+    options.let_vars = Some(doc! { "threshold": 8 });
+    options.comment = Some(String::from("let_vars_and_comment update"));
+    let result = coll.update_many(
+        doc! { "$expr": { "$lt": ["$qty", "$$threshold"] } },
+        doc! { "$inc": { "qty": 15 } },
+        Some(options),
+    ).expect("Failed to update documents.");
+    assert_eq!(1, result.matched_count);
+
+    let mut options = AggregateOptions::new();
+    options.let_vars = Some(doc! { "threshold": 15 });
+    options.comment = Some(String::from("let_vars_and_comment aggregate"));
+    let matched = coll.aggregate(vec![doc! { "$match": filter.clone() }], Some(options))
+        .expect("Failed to execute aggregation.")
+        .count();
+    assert_eq!(1, matched);
+
+    let mut options = DeleteOptions::new();
+    options.let_vars = Some(doc! { "threshold": 0 });
+    options.comment = Some(String::from("let_vars_and_comment delete"));
+    let result = coll.delete_many_with_options(filter, Some(options))
+        .expect("Failed to delete documents.");
+    assert_eq!(2, result.deleted_count);
+}
+
+#[test]
+fn insert_many_unchecked() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_many_unchecked");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs: Vec<_> = (0..3)
+        .map(|i| {
+            let mut bytes = Vec::new();
+            bson::encode_document(&mut bytes, &doc! { "_id": i, "value": i * 10 }).unwrap();
+            bytes
+        })
+        .collect();
+
+    let result = coll.insert_many_unchecked(&docs, None).expect("Failed to insert documents.");
+    assert!(result.bulk_write_exception.is_none());
+    assert!(result.inserted_ids.is_none());
+
+    let doc = coll.find_one(Some(doc! { "_id": 2 }), None).unwrap().unwrap();
+    assert_eq!(Some(&Bson::I32(20)), doc.get("value"));
+
+    // Duplicate ids are still reported by the server.
+    let result = coll.insert_many_unchecked(&docs[..1], None).expect("Failed to send insert.");
+    assert!(result.bulk_write_exception.is_some());
+}
+
+#[test]
+fn aggregate_allow_disk_use() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("aggregate_allow_disk_use");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_many(vec![doc! { "n": 2 }, doc! { "n": 1 }], None)
+        .expect("Failed to insert documents.");
+
+    let mut options = AggregateOptions::new();
+    options.allow_disk_use = Some(true);
+    let sorted: Vec<_> = coll.aggregate(
+        vec![doc! { "$sort": { "n": 1 } }, doc! { "$project": { "_id": 0, "n": 1 } }],
+        Some(options),
+    ).expect("Failed to execute aggregation.")
+        .map(|doc| doc.unwrap())
+        .collect();
+
+    assert_eq!(vec![doc! { "n": 1 }, doc! { "n": 2 }], sorted);
+}
+
+#[test]
+fn map_reduce() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("map_reduce");
+    let output = db.collection("map_reduce_output");
+
+    coll.drop().expect("Failed to drop collection");
+    output.drop().expect("Failed to drop collection");
+    coll.insert_many(
+        vec![
+            doc! { "customer": "a", "total": 5 },
+            doc! { "customer": "b", "total": 7 },
+            doc! { "customer": "a", "total": 3 },
+        ],
+        None,
+    ).expect("Failed to insert documents.");
+
+    let map = "function() { emit(this.customer, this.total); }";
+    let reduce = "function(key, values) { return Array.sum(values); }";
+
+    let mut options = MapReduceOptions::new();
+    options.query = Some(doc! { "total": { "$gt": 4 } });
+    let results: Vec<_> = match coll.map_reduce(map, reduce, Some(options)) {
+        Ok(MapReduceResult::Inline(cursor)) => cursor.map(|doc| doc.unwrap()).collect(),
+        other => panic!("expected inline results, got {:?}", other),
+    };
+    assert_eq!(2, results.len());
+
+    let mut options = MapReduceOptions::new();
+    options.out = MapReduceOutput::Replace(String::from("map_reduce_output"));
+    match coll.map_reduce(map, reduce, Some(options)) {
+        Ok(MapReduceResult::Collection(stats)) => assert_eq!("map_reduce_output", stats.collection),
+        other => panic!("expected output statistics, got {:?}", other),
+    }
+
+    let total = output.find_one(Some(doc! { "_id": "a" }), None).unwrap().unwrap();
+    assert_eq!(Some(&Bson::FloatingPoint(8.0)), total.get("value"));
+}
+
+#[test]
+fn find_allow_partial_results() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_allow_partial_results");
+
+    coll.drop().unwrap();
+    coll.insert_many((0..5).map(|i| doc! { "_id": i }).collect(), None).unwrap();
+
+    // Without unreachable shards, every document is returned, through either wire path.
+    let mut options = FindOptions::new();
+    options.allow_partial_results = true;
+    assert_eq!(5, coll.find(None, Some(options.clone())).unwrap().count());
+
+    options.let_vars = Some(doc! { "min": 2 });
+    let filter = doc! { "$expr": { "$gte": ["$_id", "$$min"] } };
+    assert_eq!(3, coll.find(Some(filter), Some(options)).unwrap().count());
+}
+
+#[test]
+fn find_one_and_update_with_result() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_one_and_update_with_result");
+
+    coll.drop().unwrap();
+
+    let mut options = FindOneAndUpdateOptions::new();
+    options.upsert = Some(true);
+    options.return_document = Some(ReturnDocument::After);
+
+    let update = doc! { "$setOnInsert": { "created": true }, "$inc": { "visits": 1 } };
+
+    let inserted = coll.find_one_and_update_with_result(
+        doc! { "_id": "home" },
+        update.clone(),
+        Some(options.clone()),
+    ).unwrap();
+    assert_eq!(1, inserted.n);
+    assert!(!inserted.updated_existing);
+    assert_eq!(Some(Bson::String(String::from("home"))), inserted.upserted_id);
+
+    let updated = coll.find_one_and_update_with_result(
+        doc! { "_id": "home" },
+        update,
+        Some(options),
+    ).unwrap();
+    assert!(updated.updated_existing);
+    assert_eq!(None, updated.upserted_id);
+    assert_eq!(Some(&Bson::I32(2)), updated.value.unwrap().get("visits"));
+
+    let deleted = coll.find_one_and_delete_with_result(doc! { "_id": "home" }, None).unwrap();
+    assert_eq!(1, deleted.n);
+    assert!(!deleted.updated_existing);
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct YearCount {
+    #[serde(rename = "_id")]
+    year: i32,
+    count: i32,
+}
+
+#[test]
+fn find_and_aggregate_as() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_and_aggregate_as");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs = vec![
+        doc! { "title": "Jaws", "year": 1975 },
+        doc! { "title": "Dog Day Afternoon", "year": 1975 },
+        doc! { "title": "Back to the Future", "year": 1985 },
+    ];
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut opts = FindOptions::new();
+    opts.sort = Some(doc! { "title": 1 });
+    opts.projection = Some(doc! { "_id": 0 });
+
+    let titles: Vec<String> = coll.find_as::<Movie>(None, Some(opts))
+        .expect("Failed to execute find.")
+        .map(|movie| movie.expect("Failed to decode document.").title)
+        .collect();
+    assert_eq!(vec!["Back to the Future", "Dog Day Afternoon", "Jaws"], titles);
+
+    let pipeline = vec![
+        doc! { "$group": { "_id": "$year", "count": { "$sum": 1 } } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+
+    let counts: Vec<YearCount> = coll.aggregate_as(pipeline, None)
+        .expect("Failed to execute aggregate.")
+        .map(|count| count.expect("Failed to decode document."))
+        .collect();
+    assert_eq!(
+        vec![YearCount { year: 1975, count: 2 }, YearCount { year: 1985, count: 1 }],
+        counts
+    );
+}
+
+#[test]
+fn aggregate_max_await_time_requires_change_stream() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("aggregate_max_await_time");
+
+    let mut options = AggregateOptions::new();
+    options.max_await_time_ms = Some(100);
+
+    match coll.aggregate(vec![doc! { "$match": {} }], Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        result => panic!("Expected an ArgumentError, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[test]
+fn create_indexes_with_options() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("create_indexes_with_options");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let mut unique = IndexOptions::new();
+    unique.unique = Some(true);
+    unique.sparse = Some(true);
+    unique.collation = Some(Collation { strength: Some(2), ..Collation::new("en") });
+
+    let mut ttl = IndexOptions::new();
+    ttl.name = Some(String::from("expiry"));
+    ttl.expire_after_seconds = Some(3600);
+    ttl.partial_filter_expression = Some(doc! { "temporary": true });
+    ttl.hidden = Some(true);
+
+    let names = coll.create_indexes_with_write_concern(
+        vec![
+            IndexModel::new(doc! { "email": 1 }, Some(unique)),
+            IndexModel::new(doc! { "created": 1 }, Some(ttl)),
+        ],
+        Some(WriteConcern::new()),
+    ).expect("Failed to create indexes.");
+
+    assert_eq!(vec!["email_1", "expiry"], names);
+
+    let models: Vec<IndexModel> = coll.list_index_models()
+        .expect("Failed to list indexes.")
+        .map(|model| model.expect("Failed to decode index."))
+        .collect();
+
+    let email = models.iter().find(|model| model.name().unwrap() == "email_1").unwrap();
+    assert_eq!(Some(true), email.options.unique);
+    assert_eq!(Some(true), email.options.sparse);
+    assert_eq!(Some(2), email.options.collation.as_ref().and_then(|c| c.strength));
+
+    let expiry = models.iter().find(|model| model.name().unwrap() == "expiry").unwrap();
+    assert_eq!(Some(3600), expiry.options.expire_after_seconds);
+    assert_eq!(Some(doc! { "temporary": true }), expiry.options.partial_filter_expression);
+    assert_eq!(Some(true), expiry.options.hidden);
+}
+
+#[test]
+fn list_index_descriptions() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("list_index_descriptions");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let mut unique = IndexOptions::new();
+    unique.unique = Some(true);
+    let desired = vec![
+        IndexModel::new(doc! { "sku": 1 }, Some(unique)),
+        IndexModel::new(doc! { "store": 1, "created": -1 }, None),
+    ];
+
+    coll.create_indexes(desired.clone()).expect("Failed to create indexes.");
+
+    let actual: Vec<IndexDescription> = coll.list_index_descriptions()
+        .expect("Failed to list indexes.")
+        .map(|description| description.expect("Failed to decode index."))
+        .collect();
+
+    assert_eq!(3, actual.len());
+    for model in &desired {
+        assert!(actual.iter().any(|description| description.matches(model)));
+    }
+
+    coll.drop_index_string(String::from("sku_1")).expect("Failed to drop index.");
+
+    let names: Vec<String> = coll.list_index_descriptions()
+        .expect("Failed to list indexes.")
+        .map(|description| description.expect("Failed to decode index.").name)
+        .collect();
+
+    assert_eq!(vec!["_id_", "store_1_created_-1"], names);
+
+    coll.drop_indexes().expect("Failed to drop indexes.");
+    assert_eq!(1, coll.list_index_descriptions().unwrap().count());
+}
+
+#[test]
+fn create_built_indexes() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("create_built_indexes");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let compound = IndexModel::builder()
+        .ascending("customer")
+        .descending("created")
+        .build()
+        .unwrap();
+    let wildcard = IndexModel::builder()
+        .wildcard()
+        .wildcard_projection(doc! { "secret": 0 })
+        .build()
+        .unwrap();
+
+    let names = coll.create_indexes(vec![compound, wildcard]).expect("Failed to create indexes.");
+    assert_eq!(vec!["customer_1_created_-1", "$**_1"], names);
+
+    let wildcard = coll.list_index_descriptions()
+        .expect("Failed to list indexes.")
+        .map(|description| description.expect("Failed to decode index."))
+        .find(|description| description.name == "$**_1")
+        .expect("Expected the wildcard index to be listed.");
+
+    assert_eq!(Some(doc! { "secret": 0 }), wildcard.options.wildcard_projection);
+}
+
+#[test]
+fn create_indexes_with_commit_quorum() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("create_indexes_with_commit_quorum");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_one(doc! { "sku": 1 }, None).expect("Failed to insert document.");
+
+    let options = CreateIndexOptions {
+        commit_quorum: Some(CommitQuorum::VotingMembers),
+        ..CreateIndexOptions::new()
+    };
+    let model = IndexModel::builder().ascending("sku").build().unwrap();
+
+    // Standalone servers reject commit quorums, which only apply to replica sets.
+    match coll.create_indexes_with_options(vec![model], Some(options)) {
+        Ok(names) => assert_eq!(vec!["sku_1"], names),
+        Err(Error::ArgumentError(_)) | Err(Error::OperationError(_)) => (),
+        Err(err) => panic!("Unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn text_search() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("text_search");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_many(
+        vec![
+            doc! { "_id": 1, "title": "Coffee and tea", "stock": 0 },
+            doc! { "_id": 2, "title": "Coffee, coffee, coffee", "stock": 4 },
+            doc! { "_id": 3, "title": "Decaf coffee", "stock": 6 },
+            doc! { "_id": 4, "title": "Green tea", "stock": 2 },
+        ],
+        None,
+    ).expect("Failed to insert documents.");
+
+    let model = IndexModel::builder().text("title").build().unwrap();
+    coll.create_index_model(model).expect("Failed to create text index.");
+
+    let ids: Vec<Bson> = coll.text_search(
+        TextSearch::new("coffee -decaf"),
+        Some(doc! { "stock": { "$gt": 0 } }),
+        None,
+    ).expect("Failed to search.")
+        .map(|doc| {
+            let doc = doc.expect("Failed to get next document.");
+            assert!(doc.get_f64("score").unwrap() > 0.0);
+            doc.get("_id").unwrap().clone()
+        })
+        .collect();
+
+    assert_eq!(vec![Bson::I32(2)], ids);
+
+    let mut options = FindOptions::new();
+    options.sort = Some(doc! { "_id": 1 });
+    let ids: Vec<Bson> = coll.text_search(TextSearch::new("tea"), None, Some(options))
+        .expect("Failed to search.")
+        .map(|doc| doc.expect("Failed to get next document.").get("_id").unwrap().clone())
+        .collect();
+
+    assert_eq!(vec![Bson::I32(1), Bson::I32(4)], ids);
+
+    assert!(coll.text_search(TextSearch::new("tea"), Some(Filter::text("x").build()), None)
+        .is_err());
+}
+
+#[test]
+fn geospatial_queries() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("geospatial_queries");
+
+    coll.drop().expect("Failed to drop collection");
+    let place = |id: i32, longitude: f64, latitude: f64, xy: i32| {
+        let loc = Geometry::point(longitude, latitude).unwrap();
+        doc! { "_id": id, "loc": bson::Document::from(loc), "xy": [xy, xy] }
+    };
+
+    coll.insert_many(
+        vec![place(1, 2.35, 48.85, 1), place(2, 2.36, 48.86, 5), place(3, 13.4, 52.5, 9)],
+        None,
+    ).expect("Failed to insert documents.");
+
+    coll.create_indexes(vec![
+        IndexModel::builder().sphere("loc").build().unwrap(),
+        IndexModel::builder().planar("xy").planar_bounds(0.0, 10.0).build().unwrap(),
+    ]).expect("Failed to create geospatial indexes.");
+
+    let ids = |filter: Filter| -> Vec<Bson> {
+        coll.find(Some(filter.build()), None)
+            .expect("Failed to query.")
+            .map(|doc| doc.expect("Failed to get next document.").get("_id").unwrap().clone())
+            .collect()
+    };
+
+    let near = Near::point(2.36, 48.86).unwrap().max_distance(5000.0);
+    assert_eq!(vec![Bson::I32(2), Bson::I32(1)], ids(Filter::field("loc").near(near)));
+
+    let paris = Geometry::polygon(vec![
+        vec![(2.2, 48.8), (2.5, 48.8), (2.5, 48.9), (2.2, 48.9), (2.2, 48.8)],
+    ]).unwrap();
+    assert_eq!(2, ids(Filter::field("loc").geo_within_geometry(paris.clone())).len());
+    assert_eq!(2, ids(Filter::field("loc").geo_intersects(paris)).len());
+
+    let nearest = Filter::field("xy").near(Near::legacy(8.0, 8.0).max_distance(2.0));
+    assert_eq!(vec![Bson::I32(3)], ids(nearest));
+
+    let corner = Filter::field("xy").geo_within(Shape::Box((0.0, 0.0), (6.0, 6.0)));
+    assert_eq!(vec![Bson::I32(1), Bson::I32(2)], ids(corner));
+}
+
+#[test]
+fn collection_stats() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll-collection_stats");
+    let coll = db.collection("orders");
+    coll.drop().unwrap();
+
+    coll.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }], None).unwrap();
+    coll.create_index(doc! { "x": 1 }, None).unwrap();
+
+    let stats = coll.stats(Some(1024)).unwrap();
+    assert_eq!("test-client-coll-collection_stats.orders", stats.ns);
+    assert_eq!(2, stats.count);
+    assert_eq!(2, stats.index_count);
+    assert!(stats.index_sizes.contains_key("_id_"));
+    assert!(!stats.capped);
+    assert_eq!(1024, stats.scale_factor);
+}
+
+#[test]
+fn rename_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll-rename_collection");
+    db.drop_database().unwrap();
+
+    let green = db.collection("green");
+    green.insert_one(doc! { "_id": 1 }, None).unwrap();
+    db.collection("blue").insert_one(doc! { "_id": 2 }, None).unwrap();
+
+    assert!(green.rename("blue", false).is_err());
+    assert!(green.rename("bad$name", false).is_err());
+
+    let blue = green.rename("blue", true).unwrap();
+    assert_eq!("blue", blue.name());
+    assert_eq!(Some(doc! { "_id": 1 }), blue.find_one(None, None).unwrap());
+    assert_eq!(vec![String::from("blue")], db.collection_names(None).unwrap());
+}
+
+#[test]
+fn convert_to_capped() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll-convert_to_capped");
+    db.drop_database().unwrap();
+
+    let logs = db.collection("logs");
+    logs.insert_many(vec![doc! { "n": 1 }, doc! { "n": 2 }], None).unwrap();
+
+    let archive = logs.clone_as_capped("archive", 4096).unwrap();
+    assert!(archive.stats(None).unwrap().capped);
+    assert_eq!(2, archive.count(None, None).unwrap());
+    assert!(!logs.stats(None).unwrap().capped);
+
+    assert!(logs.convert_to_capped(0).is_err());
+    logs.convert_to_capped(4096).unwrap();
+    assert!(logs.stats(None).unwrap().capped);
+}
+
+struct TenantFilter;
+
+impl CommandInterceptor for TenantFilter {
+    fn on_command(&self, context: &CommandContext, command: &mut Document) -> Result<()> {
+        if context.command_name == "insert_one" {
+            if let Some(&mut Bson::Array(ref mut documents)) = command.get_mut("documents") {
+                for document in documents.iter_mut() {
+                    if let Bson::Document(ref mut document) = *document {
+                        document.insert("tenant", "a");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_reply(&self, context: &CommandContext, reply: &mut Document) -> Result<()> {
+        if context.command_name == "find" {
+            reply.remove("tenant");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn intercepted_commands() {
+    let mut interceptors = Interceptors::new();
+    interceptors.push(Arc::new(TenantFilter));
+
+    let mut options = ClientOptions::new();
+    options.interceptors = interceptors;
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = client.db("test-client-coll").collection("intercepted_commands");
+    coll.drop().unwrap();
+
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    let plain = Client::connect("localhost", 27017).unwrap();
+    let plain_coll = plain.db("test-client-coll").collection("intercepted_commands");
+    assert_eq!(
+        Some(doc! { "_id": 1, "tenant": "a" }),
+        plain_coll.find_one(None, None).unwrap()
+    );
+    assert_eq!(Some(doc! { "_id": 1 }), coll.find_one(None, None).unwrap());
+}
+
+#[cfg(feature = "decimal128")]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct LedgerEntry {
+    #[serde(rename = "_id")]
+    id: i32,
+    amount: mongodb::decimal::Decimal128,
+}
+
+#[cfg(feature = "decimal128")]
+#[test]
+fn decimal128_round_trip() {
+    use mongodb::decimal;
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("decimal128_round_trip");
+    coll.drop().unwrap();
+
+    let amounts = ["1234.56", "0.01", "-99.90"];
+    let docs = amounts
+        .iter()
+        .enumerate()
+        .map(|(i, amount)| {
+            doc! { "_id": i as i32, "amount": Bson::Decimal128(decimal::parse(amount).unwrap()) }
+        })
+        .collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let found = coll.find_one(Some(doc! { "_id": 2 }), None).unwrap().unwrap();
+    assert_eq!("-99.90", found.get_decimal128("amount").unwrap().to_string());
+
+    let filter = doc! { "amount": { "$gt": Bson::Decimal128(decimal::parse("1").unwrap()) } };
+    assert_eq!(1, coll.count(Some(filter), None).unwrap());
+
+    let pipeline = vec![doc! { "$group": { "_id": Bson::Null, "total": { "$sum": "$amount" } } }];
+    let total = coll.aggregate(pipeline, None).unwrap().next().unwrap().unwrap();
+    assert_eq!("1134.67", total.get_decimal128("total").unwrap().to_string());
+
+    let typed = db.typed_collection::<LedgerEntry>("decimal128_round_trip");
+    let entry = typed.find_one(Some(doc! { "_id": 1 }), None).unwrap().unwrap();
+    assert_eq!(LedgerEntry { id: 1, amount: decimal::parse("0.01").unwrap() }, entry);
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Order {
+    #[serde(rename = "_id")]
+    id: i32,
+    #[serde(with = "mongodb::datetime")]
+    placed_at: DateTime<Utc>,
+    #[serde(with = "mongodb::datetime::optional")]
+    shipped_at: Option<DateTime<Utc>>,
+}
+
+#[test]
+fn datetime_round_trip() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    db.collection("datetime_round_trip").drop().unwrap();
+    let coll = db.typed_collection::<Order>("datetime_round_trip");
+
+    let shipped_at = Utc.timestamp_millis_opt(1_519_900_000_123).unwrap();
+    let orders: Vec<_> = (0..3)
+        .map(|i| Order {
+            id: i,
+            placed_at: Utc.with_ymd_and_hms(2018, 2, 28 + i as u32 % 2, 12, 0, 0).unwrap(),
+            shipped_at: if i == 0 { Some(shipped_at) } else { None },
+        })
+        .collect();
+    coll.insert_many(&orders, None).unwrap();
+
+    let found = coll.find_one(Some(doc! { "_id": 0 }), None).unwrap().unwrap();
+    assert_eq!(Utc.with_ymd_and_hms(2018, 2, 28, 12, 0, 0).unwrap(), found.placed_at);
+    assert_eq!(Some(shipped_at), found.shipped_at);
+
+    let day = NaiveDate::from_ymd_opt(2018, 3, 1).unwrap();
+    let filter = Filter::field("placed_at").on_day(day);
+    let ids: Vec<i32> = coll.find(Some(filter.build()), None)
+        .unwrap()
+        .map(|order| order.unwrap().id)
+        .collect();
+    assert_eq!(vec![1], ids);
+
+    let start = Utc.with_ymd_and_hms(2018, 2, 28, 0, 0, 0).unwrap();
+    let end = Utc.with_ymd_and_hms(2018, 2, 28, 12, 0, 0).unwrap();
+    let filter = Filter::field("placed_at").between(start, end);
+    assert!(coll.find_one(Some(filter.build()), None).unwrap().is_none());
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn uuid_round_trip() {
+    use mongodb::uuid::{self, LegacyEncoding, Uuid};
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("uuid_round_trip");
+    coll.drop().unwrap();
+
+    let id = Uuid::parse_str("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+    let legacy = uuid::to_legacy_bson(&id, LegacyEncoding::CSharp);
+    coll.insert_one(doc! { "_id": uuid::to_bson(&id), "legacy": legacy.clone() }, None)
+        .unwrap();
+
+    let found = coll.find_one(Some(doc! { "_id": uuid::to_bson(&id) }), None).unwrap().unwrap();
+    assert_eq!(id, uuid::from_bson(found.get("_id").unwrap()).unwrap());
+    assert_eq!(
+        id,
+        uuid::from_legacy_bson(found.get("legacy").unwrap(), LegacyEncoding::CSharp).unwrap()
+    );
+    assert!(uuid::from_bson(found.get("legacy").unwrap()).is_err());
+
+    let filter = Filter::field("legacy").eq(legacy);
+    assert_eq!(1, coll.count(Some(filter.build()), None).unwrap());
+}
+
+struct FrozenClock;
+
+impl mongodb::object_id::Clock for FrozenClock {
+    fn now(&self) -> u32 {
+        1_500_000_000
+    }
+}
+
+#[test]
+fn insert_generated_ids() {
+    let mut options = ClientOptions::new();
+    options.object_ids = ObjectIdGenerator::new()
+        .random_value([1, 2, 3, 4, 5])
+        .counter_seed(0)
+        .clock(Arc::new(FrozenClock));
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_generated_ids");
+    coll.drop().unwrap();
+
+    let result = coll.insert_one(doc! { "x": 1 }, None).unwrap();
+    let id = match result.inserted_id {
+        Some(Bson::ObjectId(id)) => id,
+        other => panic!("Expected a generated ObjectId, got {:?}", other),
+    };
+    assert_eq!("59682f000102030405000000", id.to_hex());
+
+    let docs = vec![doc! { "x": 2 }, doc! { "_id": 7, "x": 3 }, doc! { "x": 4 }];
+    let ids = coll.insert_many(docs, None).unwrap().inserted_ids.unwrap();
+    assert_eq!(3, ids.len());
+    assert_eq!(Some(&Bson::I32(7)), ids.get(&1));
+    match (ids.get(&0), ids.get(&2)) {
+        (Some(&Bson::ObjectId(ref first)), Some(&Bson::ObjectId(ref second))) => {
+            assert_eq!("59682f000102030405000001", first.to_hex());
+            assert_eq!("59682f000102030405000002", second.to_hex());
+        }
+        other => panic!("Expected generated ObjectIds, got {:?}", other),
+    }
+
+    let stored = coll.find_one(Some(doc! { "x": 1 }), None).unwrap().unwrap();
+    assert_eq!(Ok(&id), stored.get_object_id("_id"));
+}
+
+#[test]
+fn oversized_writes_fail_fast() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("oversized_writes_fail_fast");
+    coll.drop().unwrap();
+
+    let blob = "x".repeat(17 * 1024 * 1024);
+
+    match coll.insert_one(doc! { "_id": 1, "blob": blob.clone() }, None) {
+        Err(Error::ArgumentError(message)) => {
+            assert!(message.starts_with("Document 0 is "), "{}", message);
+            assert!(message.ends_with(" more than the maximum of 16777216 bytes."), "{}", message);
+        }
+        other => panic!("Expected an argument error, got {:?}", other),
+    }
+
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+    match coll.replace_one(doc! { "_id": 1 }, doc! { "blob": blob.clone() }, None) {
+        Err(Error::ArgumentError(message)) => {
+            assert!(message.starts_with("Update document 0 is "), "{}", message);
+        }
+        other => panic!("Expected an argument error, got {:?}", other),
+    }
+
+    match coll.find_one(Some(doc! { "blob": blob }), None) {
+        Err(Error::ArgumentError(message)) => {
+            assert!(message.starts_with("The find command is "), "{}", message);
+        }
+        other => panic!("Expected an argument error, got {:?}", other),
+    }
+
+    assert_eq!(1, coll.count(None, None).unwrap());
+}
+
+#[test]
+fn duplicate_key_error_code() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("duplicate_key_error_code");
+    coll.drop().unwrap();
+
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+    let result = coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+    let err = Error::WriteError(result.write_exception.expect("Expected a duplicate key error."));
+    assert!(err.is_duplicate_key());
+    assert_eq!(Some(ErrorCode::DuplicateKey), err.code());
+}
+
+#[test]
+fn insert_many_batches_sent_concurrently() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_many_batches_sent_concurrently");
+
+    coll.drop().expect("Failed to drop database");
+
+    // Enough documents for several insert commands, one of which reports a write error.
+    let padding = "x".repeat(1024 * 1024);
+    let mut docs: Vec<_> = (0..60).map(|i| doc! { "_id": i, "padding": padding.clone() })
+        .collect();
+    docs[45] = doc! { "_id": 0, "padding": padding.clone() };
+
+    let options = InsertManyOptions {
+        ordered: Some(false),
+        max_concurrent_batches: Some(3),
+        ..InsertManyOptions::new()
+    };
+    let result = coll.insert_many(docs, Some(options)).expect("Failed to insert documents.");
+
+    let exception = result.bulk_write_exception.expect("Expected a duplicate key error.");
+    assert_eq!(1, exception.write_errors.len());
+    assert_eq!(45, exception.write_errors[0].index);
+
+    let ids = result.inserted_ids.expect("Expected inserted ids.");
+    assert_eq!(59, ids.len());
+    assert!(!ids.contains_key(&45));
+    assert_eq!(Some(&Bson::I32(59)), ids.get(&59));
+    assert_eq!(59, coll.count(None, None).expect("Failed to execute count."));
+}
+
+#[test]
+fn partitioned_scan() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("partitioned_scan");
+    coll.drop().expect("Failed to drop collection");
+
+    let mut docs: Vec<_> = (0..200).map(|i| doc! { "_id": i, "even": i % 2 == 0 }).collect();
+    docs.push(doc! { "_id": "text", "even": true });
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let partitions = coll.partitioned_scan(Some(doc! { "even": true }), 4, None)
+        .expect("Failed to split the scan.");
+    assert!(partitions.len() > 1 && partitions.len() <= 4);
+    assert_eq!(None, partitions[0].min);
+    assert_eq!(None, partitions[partitions.len() - 1].max);
+
+    let counts: Vec<usize> = std::thread::scope(|scope| {
+        let threads: Vec<_> = partitions
+            .into_iter()
+            .map(|partition| scope.spawn(move || partition.cursor.map(Result::unwrap).count()))
+            .collect();
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    });
+    assert_eq!(101, counts.iter().sum::<usize>());
+
+    let split = coll.partitioned_scan_at(None, vec![Bson::I32(50), Bson::I32(150)], None)
+        .expect("Failed to split the scan.");
+    let counts: Vec<usize> = split.into_iter().map(|partition| partition.cursor.count()).collect();
+    assert_eq!(vec![51, 100, 50], counts);
+
+    match coll.partitioned_scan(None, 0, None) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an argument error, got {:?}", other.map(|p| p.len())),
+    }
+}
+
+#[test]
+fn insert_coalescer() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("insert_coalescer");
+    coll.drop().expect("Failed to drop collection");
+
+    let coalescer = coll.insert_coalescer(None).expect("Failed to start the coalescer.");
+    for i in 0..500 {
+        coalescer.insert(doc! { "_id": i, "value": i * 2 }).expect("Failed to queue document.");
+    }
+    coalescer.flush().expect("Failed to flush the queue.");
+    assert_eq!(0, coalescer.queued());
+
+    coalescer.insert(doc! { "_id": 500 }).expect("Failed to queue document.");
+    coalescer.close().expect("Failed to close the coalescer.");
+
+    // The inserts are unacknowledged, so the server may still be applying the last ones.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while coll.count(None, None).unwrap() < 501 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(501, coll.count(None, None).unwrap());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn auto_encryption() {
+    use mongodb::encryption::{Algorithm, AutoEncryptionOptions, ClientEncryption, KmsProviders};
+    use std::collections::HashMap;
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.db("test-client-coll").collection("auto_encryption_keys").drop().unwrap();
+    let coll = client.db("test-client-coll").collection("auto_encryption");
+    coll.drop().expect("Failed to drop collection");
+
+    let kms_providers = KmsProviders { local: Some(vec![7; 96]) };
+    let key_vault_namespace = "test-client-coll.auto_encryption_keys";
+    let encryption =
+        ClientEncryption::new(client.clone(), key_vault_namespace, kms_providers.clone()).unwrap();
+    let key_id = encryption
+        .create_data_key("local", vec![String::from("people")])
+        .expect("Failed to create data key.");
+
+    let mut schema_map = HashMap::new();
+    schema_map.insert(String::from("test-client-coll.auto_encryption"), doc! {
+        "bsonType": "object",
+        "encryptMetadata": { "keyId": [key_id] },
+        "properties": {
+            "ssn": { "encrypt": { "algorithm": Algorithm::Deterministic.name() } },
+            "notes": { "encrypt": { "algorithm": Algorithm::Random.name() } },
+        },
+    });
+    let mut auto_encryption = AutoEncryptionOptions::new(key_vault_namespace, kms_providers);
+    auto_encryption.schema_map = schema_map;
+    let options = ClientOptions {
+        auto_encryption: Some(auto_encryption),
+        ..ClientOptions::new()
+    };
+    let encrypting = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let encrypted_coll = encrypting.db("test-client-coll").collection("auto_encryption");
+
+    let doc = doc! { "_id": 1, "name": "Ada", "ssn": "078-05-1120", "notes": "likes tea" };
+    encrypted_coll.insert_one(doc.clone(), None).expect("Failed to insert document.");
+
+    // The server only stores ciphertexts.
+    let stored = coll.find_one(None, None).unwrap().unwrap();
+    match stored.get("ssn") {
+        Some(&Bson::Binary(BinarySubtype::UserDefined(6), _)) => (),
+        other => panic!("Expected an encrypted ssn, got {:?}", other),
+    }
+    assert_eq!(Some(&Bson::String(String::from("Ada"))), stored.get("name"));
+
+    // Deterministically encrypted fields can be queried, and replies are decrypted.
+    let found = encrypted_coll.find_one(Some(doc! { "ssn": "078-05-1120" }), None).unwrap();
+    assert_eq!(Some(doc), found);
+    assert!(encrypted_coll.find_one(Some(doc! { "notes": "likes tea" }), None).is_err());
+
+    let value = encryption.decrypt(stored.get("notes").unwrap().clone()).unwrap();
+    assert_eq!(Bson::String(String::from("likes tea")), value);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn explicit_encryption() {
+    use mongodb::encryption::{Algorithm, ClientEncryption, EncryptOptions, KmsProviders};
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let vault = client.db("test-client-coll").collection("explicit_encryption_keys");
+    vault.drop().expect("Failed to drop collection");
+
+    let kms_providers = KmsProviders { local: Some(vec![9; 96]) };
+    let encryption = ClientEncryption::new(
+        client.clone(),
+        "test-client-coll.explicit_encryption_keys",
+        kms_providers,
+    ).unwrap();
+    let key_id = encryption.create_data_key("local", Vec::new()).unwrap();
+    encryption.add_key_alt_name(&key_id, "orders").unwrap().expect("Expected the key.");
+    assert!(encryption.get_key_by_alt_name("orders").unwrap().is_some());
+
+    let mut options = EncryptOptions::new(Algorithm::Random);
+    options.key_alt_name = Some(String::from("orders"));
+    let encrypted = encryption.encrypt(Bson::I32(42), options).unwrap();
+    assert_eq!(Bson::I32(42), encryption.decrypt(encrypted).unwrap());
+
+    encryption.remove_key_alt_name(&key_id, "orders").unwrap();
+    assert!(encryption.get_key_by_alt_name("orders").unwrap().is_none());
+    assert_eq!(1, encryption.get_keys().unwrap().count());
+
+    assert_eq!(1, encryption.delete_key(&key_id).unwrap().deleted_count);
+    assert!(encryption.get_key(&key_id).unwrap().is_none());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn rewrap_many_data_key() {
+    use mongodb::encryption::{Algorithm, ClientEncryption, EncryptOptions, KmsProviders,
+                              RewrapManyDataKeyOptions};
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let vault = client.db("test-client-coll").collection("rewrap_many_data_key_keys");
+    vault.drop().expect("Failed to drop collection");
+
+    let namespace = "test-client-coll.rewrap_many_data_key_keys";
+    let old = KmsProviders { local: Some(vec![1; 96]) };
+    let encryption = ClientEncryption::new(client.clone(), namespace, old).unwrap();
+    let key_id = encryption.create_data_key("local", Vec::new()).unwrap();
+    encryption.create_data_key("local", Vec::new()).unwrap();
+
+    let mut options = EncryptOptions::new(Algorithm::Deterministic);
+    options.key_id = Some(key_id);
+    let encrypted = encryption.encrypt(Bson::String(String::from("secret")), options).unwrap();
+
+    let rewrap = RewrapManyDataKeyOptions {
+        local_master_key: Some(vec![2; 96]),
+        ..RewrapManyDataKeyOptions::new()
+    };
+    let result = encryption.rewrap_many_data_key(None, Some(rewrap)).unwrap();
+    assert_eq!(2, result.matched_count);
+    assert_eq!(2, result.modified_count);
+
+    // Only the new master key decrypts the data keys now.
+    let new = KmsProviders { local: Some(vec![2; 96]) };
+    let rotated = ClientEncryption::new(client.clone(), namespace, new).unwrap();
+    assert_eq!(Bson::String(String::from("secret")), rotated.decrypt(encrypted.clone()).unwrap());
+
+    let stale = KmsProviders { local: Some(vec![1; 96]) };
+    let stale = ClientEncryption::new(client, namespace, stale).unwrap();
+    assert!(stale.decrypt(encrypted).is_err());
+}
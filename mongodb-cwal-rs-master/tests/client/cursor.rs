@@ -1,12 +1,16 @@
 use bson::{Bson, Document};
 
-use mongodb::{Client, CommandType, ThreadedClient};
+use mongodb::{Client, ClientOptions, CommandType, ThreadedClient};
 use mongodb::common::{ReadMode, ReadPreference};
-use mongodb::coll::options::FindOptions;
+use mongodb::coll::options::{CursorType, FindOptions};
 use mongodb::db::ThreadedDatabase;
+use mongodb::db::options::CreateCollectionOptions;
 use mongodb::cursor::Cursor;
+use mongodb::cursor::extended_json::ExtendedJsonMode;
 use mongodb::wire_protocol::flags::OpQueryFlags;
 
+use std::time::Duration;
+
 #[test]
 fn cursor_features() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -82,3 +86,332 @@ fn cursor_features() {
         };
     }
 }
+
+#[test]
+fn raw_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("raw_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..10).map(|i| doc! { "_id": i, "foo": i as i64 }).collect();
+    coll.insert_many(docs.clone(), None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(3);
+    options.sort = Some(doc! { "_id": 1 });
+
+    let cursor = coll.find(None, Some(options))
+        .expect("Failed to execute find.")
+        .into_raw()
+        .expect("Failed to convert cursor.");
+
+    let raw: Vec<_> = cursor.map(|result| result.expect("Failed to read raw document."))
+        .collect();
+    assert_eq!(10, raw.len());
+
+    for (i, doc) in raw.iter().enumerate() {
+        assert_eq!(Some(Bson::I64(i as i64)), doc.get("foo").unwrap());
+        assert_eq!(docs[i], doc.to_document().unwrap());
+    }
+}
+
+#[test]
+fn cursor_memory_limit() {
+    let mut client_options = ClientOptions::new();
+    client_options.cursor_memory_limit = Some(1);
+    client_options.cursor_memory_wait = Some(Duration::from_millis(10));
+
+    let client = Client::with_uri_and_options("mongodb://localhost:27017", client_options)
+        .unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("cursor_memory_limit");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..10).map(|i| doc! { "_id": i, "foo": i as i64 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(3);
+
+    let mut first = coll.find(None, Some(options.clone())).expect("Failed to execute find.");
+    let mut second = coll.find(None, Some(options)).expect("Failed to execute find.");
+
+    assert!(first.buffered_bytes() > 0);
+    assert_eq!(
+        first.buffered_bytes() + second.buffered_bytes(),
+        client.buffered_cursor_bytes()
+    );
+
+    // Over the limit, getMore requests are delayed but still made.
+    assert_eq!(10, first.by_ref().count());
+    assert_eq!(0, first.buffered_bytes());
+
+    second.next().expect("Failed to read document.").expect("Failed to read document.");
+    drop(second);
+    assert_eq!(0, client.buffered_cursor_bytes());
+}
+
+#[test]
+fn tailable_await_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("tailable_await_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let mut capped = CreateCollectionOptions::new();
+    capped.capped = Some(true);
+    capped.size = Some(4096);
+    db.create_collection("tailable_await_cursor", Some(capped))
+        .expect("Failed to create capped collection.");
+
+    coll.insert_one(doc! { "n": 1 }, None).expect("Failed to insert document.");
+
+    let mut options = FindOptions::new();
+    options.cursor_type = CursorType::TailableAwait;
+    options.max_await_time_ms = Some(100);
+
+    let mut cursor = coll.find(None, Some(options)).expect("Failed to execute find.");
+    assert_eq!(1, cursor.by_ref().count());
+
+    // The cursor outlives its results, and picks up documents inserted afterwards.
+    assert!(cursor.is_alive());
+    coll.insert_one(doc! { "n": 2 }, None).expect("Failed to insert document.");
+
+    let doc = cursor.next().expect("Tailable cursor did not return the new document.");
+    assert_eq!(Some(&Bson::I32(2)), doc.expect("Failed to read document.").get("n"));
+
+    let mut options = FindOptions::new();
+    options.max_await_time_ms = Some(100);
+    assert!(coll.find(None, Some(options)).is_err());
+}
+
+#[test]
+fn cursor_batches() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("cursor_batches");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(3);
+    options.sort = Some(doc! { "_id": 1 });
+
+    let mut cursor = coll.find(None, Some(options)).expect("Failed to execute find.");
+    assert_eq!(3, cursor.buffered_count());
+
+    let sizes: Vec<_> = cursor.batches()
+        .map(|batch| batch.expect("Failed to read batch.").len())
+        .collect();
+    assert_eq!(vec![3, 3, 3, 1], sizes);
+}
+
+#[test]
+fn no_cursor_timeout() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("no_cursor_timeout");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..5).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.no_cursor_timeout = true;
+    options.batch_size = Some(2);
+    assert_eq!(5, coll.find(None, Some(options.clone())).unwrap().count());
+
+    // Through the `find` command as well.
+    options.let_vars = Some(doc! { "min": 0 });
+    let filter = doc! { "$expr": { "$gte": ["$_id", "$$min"] } };
+    assert_eq!(5, coll.find(Some(filter), Some(options)).unwrap().count());
+}
+
+#[test]
+fn kill_cursor_on_drop() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("kill_cursor_on_drop");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..10).map(|i| doc! { "foo": i as i64 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let idle_cursors = || {
+        let result = client.db("admin").command(
+            doc! {
+                "aggregate": 1,
+                "pipeline": [
+                    { "$currentOp": { "idleCursors": true } },
+                    { "$match": {
+                        "type": "idleCursor",
+                        "ns": "test-client-cursor.kill_cursor_on_drop",
+                    } },
+                ],
+                "cursor": {},
+            },
+            CommandType::Aggregate,
+            None,
+        ).unwrap();
+
+        match result.get_document("cursor").unwrap().get("firstBatch") {
+            Some(&Bson::Array(ref batch)) => batch.len(),
+            _ => panic!("Expected a first batch."),
+        }
+    };
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(2);
+
+    let mut cursor = coll.find(None, Some(options)).unwrap();
+    assert!(cursor.next().unwrap().is_ok());
+    assert_eq!(1, idle_cursors());
+
+    drop(cursor);
+    assert_eq!(0, idle_cursors());
+}
+
+#[test]
+fn prefetch_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("prefetch_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..50).map(|i| doc! { "foo": i as i64 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(7);
+    options.sort = Some(doc! { "foo": 1 });
+
+    let cursor = coll.find(None, Some(options)).unwrap().prefetch();
+    let values: Vec<_> = cursor
+        .map(|result| result.unwrap().get_i64("foo").unwrap())
+        .collect();
+
+    assert_eq!((0..50).collect::<Vec<_>>(), values);
+}
+
+#[test]
+fn detach_and_attach_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("detach_and_attach_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..10).map(|i| doc! { "foo": i as i64 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(3);
+    options.sort = Some(doc! { "foo": 1 });
+
+    let mut cursor = coll.find(None, Some(options)).unwrap();
+    assert_eq!(3, cursor.drain_current_batch().unwrap().len());
+
+    let detached = cursor.detach().unwrap();
+    assert_eq!(3, detached.position);
+
+    let saved = bson::to_bson(&detached).unwrap();
+    let other = Client::connect("localhost", 27017).unwrap();
+    let cursor = Cursor::attach(other, bson::from_bson(saved).unwrap());
+
+    let values: Vec<_> = cursor
+        .map(|result| result.unwrap().get_i64("foo").unwrap())
+        .collect();
+    assert_eq!((3..10).collect::<Vec<_>>(), values);
+}
+
+#[test]
+fn raw_cursor_paths() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("raw_cursor_paths");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..5)
+        .map(|i| doc! { "_id": i, "address": { "city": format!("city{}", i) }, "tags": [i, i + 1] })
+        .collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(2);
+    options.sort = Some(doc! { "_id": 1 });
+
+    let cursor = coll.find(None, Some(options))
+        .expect("Failed to execute find.")
+        .into_raw()
+        .expect("Failed to convert cursor.");
+
+    for (i, result) in cursor.enumerate() {
+        let doc = result.expect("Failed to read raw document.");
+        let city = doc.get_path("address.city").unwrap().and_then(|city| city.as_str());
+        assert_eq!(Some(format!("city{}", i)), city.map(String::from));
+        let tag = doc.get_path("tags.1").unwrap().and_then(|tag| tag.as_i32());
+        assert_eq!(Some(i as i32 + 1), tag);
+    }
+}
+
+#[test]
+fn extended_json_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("extended_json_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs: Vec<_> = (0..4).map(|i| doc! { "_id": i, "big": i as i64 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(3);
+    options.sort = Some(doc! { "_id": 1 });
+
+    let json: Vec<_> = coll.find(None, Some(options))
+        .expect("Failed to execute find.")
+        .into_extended_json(ExtendedJsonMode::Canonical)
+        .map(|result| result.expect("Failed to read document.").to_string())
+        .collect();
+
+    assert_eq!(4, json.len());
+    assert_eq!(r#"{"_id":{"$numberInt":"3"},"big":{"$numberLong":"3"}}"#, json[3]);
+}
+
+#[test]
+fn max_decoded_documents() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("max_decoded_documents");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let padding = "x".repeat(512 * 1024);
+    let docs: Vec<_> = (0..20).map(|i| doc! { "_id": i, "padding": padding.clone() }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    let mut options = FindOptions::new();
+    options.batch_size = Some(8);
+    options.sort = Some(doc! { "_id": 1 });
+    options.max_decoded_documents = Some(2);
+
+    let cursor = coll.find(None, Some(options)).expect("Failed to execute find.");
+    assert_eq!(8, cursor.buffered_count());
+
+    let ids: Vec<_> = cursor
+        .map(|doc| doc.expect("Failed to read document.").get_i32("_id").unwrap())
+        .collect();
+    assert_eq!((0..20).collect::<Vec<_>>(), ids);
+}
@@ -1,8 +1,14 @@
 use bson::{self, Bson};
 use mongodb::{Client, ThreadedClient};
+use mongodb::admin::{ProfilingLevel, ServerStatusOptions};
+use mongodb::coll::options::IndexOptions;
+use mongodb::common::{ReadMode, ReadPreference, WriteConcern};
 use mongodb::db::ThreadedDatabase;
-use mongodb::db::options::CreateUserOptions;
-use mongodb::db::roles::{AllDatabaseRole, SingleDatabaseRole, Role};
+use mongodb::db::options::{CreateCollectionOptions, CreateUserOptions, CreateViewOptions,
+                           IndexChange, ListCollectionsOptions, ModifyCollectionOptions,
+                           TimeSeriesGranularity, TimeSeriesOptions, UpdateUserOptions,
+                           ValidationAction, ValidationLevel};
+use mongodb::db::roles::{AllDatabaseRole, AuthenticationRestriction, SingleDatabaseRole, Role};
 
 #[test]
 fn create_collection() {
@@ -106,6 +112,7 @@ fn create_and_get_users() {
     let kevin_options = CreateUserOptions {
         custom_data: None,
         roles: vec![Role::All(AllDatabaseRole::Read)],
+        authentication_restrictions: Vec::new(),
         write_concern: None,
     };
 
@@ -124,6 +131,7 @@ fn create_and_get_users() {
             },
             Role::All(AllDatabaseRole::ReadWrite),
         ],
+        authentication_restrictions: Vec::new(),
         write_concern: None,
     };
 
@@ -193,3 +201,326 @@ fn get_version() {
     let db = client.db("test-client-db-get_version");
     let _ = db.version().unwrap();
 }
+
+#[test]
+fn create_and_drop_with_write_concern() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-create_and_drop_with_write_concern");
+    db.drop_database_with_write_concern(Some(WriteConcern::new())).unwrap();
+
+    let mut write_concern = WriteConcern::new();
+    write_concern.j = true;
+    write_concern.w_timeout = 1000;
+
+    let mut options = CreateCollectionOptions::new();
+    options.write_concern = Some(write_concern);
+    db.create_collection("test", Some(options)).unwrap();
+    assert_eq!(vec![String::from("test")], db.collection_names(None).unwrap());
+
+    db.drop_collection_with_write_concern("test", Some(write_concern)).unwrap();
+    assert!(db.collection_names(None).unwrap().is_empty());
+}
+
+#[test]
+fn update_users_and_get_info() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-update_users_and_get_info");
+    db.drop_database().unwrap();
+    db.drop_all_users(None).unwrap();
+
+    let read = Role::Single {
+        role: SingleDatabaseRole::Read,
+        db: String::from("test-client-db-update_users_and_get_info"),
+    };
+
+    let options = CreateUserOptions {
+        roles: vec![read.clone()],
+        authentication_restrictions: vec![AuthenticationRestriction {
+            client_source: vec![String::from("127.0.0.1")],
+            server_address: Vec::new(),
+        }],
+        ..CreateUserOptions::new()
+    };
+    db.create_user("reporter", "reports!", Some(options)).unwrap();
+
+    let info = db.users_info(vec!["reporter"], None).unwrap();
+    assert_eq!(1, info.len());
+    assert_eq!(vec![read], info[0].roles);
+    let restriction = &info[0].authentication_restrictions[0];
+    assert_eq!(vec![String::from("127.0.0.1")], restriction.client_source);
+
+    let write = Role::Single {
+        role: SingleDatabaseRole::ReadWrite,
+        db: String::from("test-client-db-update_users_and_get_info"),
+    };
+    let update = UpdateUserOptions {
+        roles: Some(vec![write.clone()]),
+        custom_data: Some(doc! { "team": "reports" }),
+        ..UpdateUserOptions::new()
+    };
+    db.update_user("reporter", update).unwrap();
+    assert!(db.update_user("reporter", UpdateUserOptions::new()).is_err());
+
+    let info = db.users_info(Vec::new(), None).unwrap();
+    assert_eq!(1, info.len());
+    assert_eq!(vec![write], info[0].roles);
+    assert_eq!(Some(doc! { "team": "reports" }), info[0].custom_data);
+
+    db.drop_user("reporter", None).unwrap();
+    assert!(db.users_info(Vec::new(), None).unwrap().is_empty());
+}
+
+#[test]
+fn list_collection_infos() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-list_collection_infos");
+    db.drop_database().unwrap();
+
+    let options = CreateCollectionOptions {
+        capped: Some(true),
+        size: Some(100000),
+        ..CreateCollectionOptions::new()
+    };
+    db.create_collection("capped", Some(options)).unwrap();
+    db.create_collection("plain", None).unwrap();
+
+    let infos = db.list_collection_infos(Some(doc! { "name": "capped" }), None).unwrap();
+    assert_eq!(1, infos.len());
+    assert_eq!("collection", infos[0].collection_type);
+    assert_eq!(Some(&Bson::Boolean(true)), infos[0].options.get("capped"));
+    assert!(infos[0].info.as_ref().unwrap().uuid.is_some());
+
+    let options = ListCollectionsOptions {
+        name_only: Some(true),
+        ..ListCollectionsOptions::new()
+    };
+    let mut names: Vec<_> = db.list_collection_infos(None, Some(options))
+        .unwrap()
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    names.sort();
+    assert_eq!(vec![String::from("capped"), String::from("plain")], names);
+}
+
+#[test]
+fn create_validated_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-create_validated_collection");
+    db.drop_database().unwrap();
+
+    let options = CreateCollectionOptions {
+        validator: Some(doc! { "qty": { "$gte": 0 } }),
+        validation_level: Some(ValidationLevel::Strict),
+        validation_action: Some(ValidationAction::Error),
+        ..CreateCollectionOptions::new()
+    };
+    db.create_collection("stock", Some(options)).unwrap();
+
+    let coll = db.collection("stock");
+    coll.insert_one(doc! { "qty": 1 }, None).unwrap();
+    assert!(coll.insert_one(doc! { "qty": -1 }, None).is_err());
+
+    let infos = db.list_collection_infos(Some(doc! { "name": "stock" }), None).unwrap();
+    assert_eq!(Some(&Bson::from("strict")), infos[0].options.get("validationLevel"));
+
+    let expiring = CreateCollectionOptions {
+        expire_after_seconds: Some(60),
+        ..CreateCollectionOptions::new()
+    };
+    assert!(db.create_collection("expiring", Some(expiring)).is_err());
+}
+
+#[test]
+fn create_view() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-create_view");
+    db.drop_database().unwrap();
+
+    let orders = db.collection("orders");
+    orders.insert_one(doc! { "_id": 1, "open": true }, None).unwrap();
+    orders.insert_one(doc! { "_id": 2, "open": false }, None).unwrap();
+
+    let pipeline = vec![doc! { "$match": { "open": true } }];
+    db.create_view("open_orders", "orders", pipeline.clone(), Some(CreateViewOptions::new()))
+        .unwrap();
+
+    let view = db.collection("open_orders");
+    assert_eq!(1, view.count(None, None).unwrap());
+    assert!(view.insert_one(doc! { "_id": 3 }, None).is_err());
+
+    let infos = db.list_collection_infos(Some(doc! { "name": "open_orders" }), None).unwrap();
+    assert!(infos[0].is_view());
+    assert_eq!(Some("orders"), infos[0].view_on());
+    assert_eq!(Some(pipeline), infos[0].pipeline());
+
+    db.drop_collection("open_orders").unwrap();
+    assert!(db.list_collection_infos(None, None).unwrap().iter().all(|info| !info.is_view()));
+}
+
+#[test]
+fn modify_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-modify_collection");
+    db.drop_database().unwrap();
+
+    db.create_collection("events", None).unwrap();
+    let coll = db.collection("events");
+    let mut ttl = IndexOptions::new();
+    ttl.expire_after_seconds = Some(3600);
+    let name = coll.create_index(doc! { "created": 1 }, Some(ttl)).unwrap();
+
+    let mut index = IndexChange::named(&name);
+    index.expire_after_seconds = Some(60);
+    index.hidden = Some(true);
+    let changes = ModifyCollectionOptions {
+        validator: Some(doc! { "created": { "$exists": true } }),
+        index: Some(index),
+        ..ModifyCollectionOptions::new()
+    };
+    db.modify_collection("events", changes).unwrap();
+
+    let infos = db.list_collection_infos(Some(doc! { "name": "events" }), None).unwrap();
+    assert!(infos[0].options.get("validator").is_some());
+    assert!(coll.insert_one(doc! { "x": 1 }, None).is_err());
+
+    let description = coll.list_index_descriptions()
+        .unwrap()
+        .map(|description| description.unwrap())
+        .find(|description| description.name == name)
+        .unwrap();
+    assert_eq!(Some(60), description.options.expire_after_seconds);
+    assert_eq!(Some(true), description.options.hidden);
+
+    assert!(db.modify_collection("events", ModifyCollectionOptions::new()).is_err());
+}
+
+#[test]
+fn server_status() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("admin");
+
+    let status = db.server_status(None).unwrap();
+    assert!(status.connections.unwrap().current > 0);
+    assert!(status.opcounters.unwrap().command > 0);
+    assert!(status.other.get("asserts").is_some());
+
+    let options = ServerStatusOptions {
+        exclude: vec![String::from("asserts")],
+        ..ServerStatusOptions::new()
+    };
+    let status = db.server_status(Some(options)).unwrap();
+    assert!(status.other.get("asserts").is_none());
+}
+
+#[test]
+fn database_stats() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-database_stats");
+    db.drop_database().unwrap();
+    db.collection("test").insert_one(doc! { "x": 1 }, None).unwrap();
+
+    let stats = db.stats(None).unwrap();
+    assert_eq!("test-client-db-database_stats", stats.db);
+    assert_eq!(1, stats.collections);
+    assert_eq!(1, stats.objects);
+    assert_eq!(1, stats.scale_factor);
+
+    assert_eq!(1024, db.stats(Some(1024)).unwrap().scale_factor);
+    assert!(db.stats(Some(0)).is_err());
+}
+
+#[test]
+fn profile_operations() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-profile_operations");
+    db.drop_database().unwrap();
+
+    let coll = db.collection("orders");
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+
+    let previous = db.set_profiling_level(ProfilingLevel::All, Some(150)).unwrap();
+    assert_eq!(ProfilingLevel::Off, previous.level);
+
+    let status = db.get_profiling_level().unwrap();
+    assert_eq!(ProfilingLevel::All, status.level);
+    assert_eq!(150, status.slow_ms);
+
+    coll.find_one(Some(doc! { "x": 1 }), None).unwrap();
+
+    let entry = db.profile_entries(Some(doc! { "op": "query", "ns": coll.namespace.clone() }), None)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(1), entry.returned);
+    assert_eq!(Some(String::from("COLLSCAN")), entry.plan_summary);
+
+    db.set_profiling_level(ProfilingLevel::Off, Some(previous.slow_ms as i32)).unwrap();
+}
+
+#[test]
+fn create_time_series_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-create_time_series_collection");
+    db.drop_database().unwrap();
+
+    let mut timeseries = TimeSeriesOptions::new("ts");
+    timeseries.meta_field = Some(String::from("sensor"));
+    timeseries.granularity = Some(TimeSeriesGranularity::Minutes);
+    let options = CreateCollectionOptions {
+        timeseries: Some(timeseries.clone()),
+        expire_after_seconds: Some(86400),
+        ..CreateCollectionOptions::new()
+    };
+    db.create_collection("metrics", Some(options)).unwrap();
+
+    let infos = db.list_collection_infos(Some(doc! { "name": "metrics" }), None).unwrap();
+    assert!(infos[0].is_time_series());
+    assert_eq!(Some(timeseries), infos[0].time_series());
+    assert_eq!(Some(&Bson::I64(86400)), infos[0].options.get("expireAfterSeconds"));
+}
+
+#[derive(Deserialize, Debug)]
+struct CountReply {
+    n: i32,
+}
+
+#[test]
+fn run_command_as() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-run_command_as");
+    db.drop_database().unwrap();
+
+    let coll = db.collection("items");
+    coll.insert_many(vec![doc! { "_id": 1 }, doc! { "_id": 2 }], None).unwrap();
+
+    let reply: CountReply = db.run_command_as(doc! { "count": "items" }, None).unwrap();
+    assert_eq!(2, reply.n);
+
+    let failure = db.run_command_as::<CountReply>(doc! { "count": 1 }, None);
+    assert!(failure.is_err());
+}
+
+#[test]
+fn sibling_databases() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let mut write_concern = WriteConcern::new();
+    write_concern.w = 1;
+    let secondary = ReadPreference::new(ReadMode::SecondaryPreferred, None);
+    let db = client.db_with_prefs(
+        "test-client-db-sibling_databases",
+        Some(secondary.clone()),
+        Some(write_concern.clone()),
+    );
+
+    let sibling = db.sibling("test-client-db-sibling_databases-other");
+    assert_eq!("test-client-db-sibling_databases-other", sibling.name);
+    assert_eq!(secondary, sibling.read_preference);
+    assert_eq!(write_concern.w, sibling.write_concern.w);
+
+    let primary = db.with_read_preference(ReadPreference::new(ReadMode::Primary, None));
+    assert_eq!(db.name, primary.name);
+    assert_eq!(ReadMode::Primary, primary.read_preference.mode);
+    primary.list_collections(None).unwrap();
+}
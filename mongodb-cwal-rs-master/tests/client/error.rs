@@ -1,6 +1,6 @@
 use mongodb::common::WriteConcern;
-use mongodb::coll::error::{BulkWriteException, WriteConcernError, WriteError};
-use mongodb::Error;
+use mongodb::coll::error::{BulkWriteException, WriteConcernError, WriteError, WriteException};
+use mongodb::{Error, ErrorCode};
 
 #[test]
 fn validate_write_result() {
@@ -90,6 +90,26 @@ fn parse_write_concern_error() {
     }
 }
 
+#[test]
+fn parse_write_concern_timeout() {
+    let doc =
+        doc! {
+            "code": 64,
+            "codeName": "WriteConcernFailed",
+            "errmsg": "waiting for replication timed out",
+            "errInfo": { "wtimeout": true }
+        };
+
+    let err = WriteConcernError::parse(doc, WriteConcern::new()).unwrap();
+    assert_eq!(Some(String::from("WriteConcernFailed")), err.code_name);
+    assert!(err.timed_out());
+
+    let doc = doc! { "code": 100, "errmsg": "Not enough data-bearing nodes." };
+    let err = WriteConcernError::parse(doc, WriteConcern::new()).unwrap();
+    assert_eq!(None, err.err_info);
+    assert!(!err.timed_out());
+}
+
 #[test]
 fn parse_invalid_write_concern_error() {
     let doc = doc! { "code": 1124 };
@@ -129,3 +149,31 @@ fn parse_invalid_write_error() {
     let result = WriteError::parse(doc);
     assert!(result.is_err());
 }
+
+#[test]
+fn parse_structured_write_errors() {
+    let doc = doc! {
+        "ok": 1,
+        "n": 0,
+        "writeErrors": [{
+            "index": 2,
+            "code": 121,
+            "codeName": "DocumentValidationFailure",
+            "errmsg": "Document failed validation",
+            "errInfo": { "failingDocumentId": 7 },
+        }],
+    };
+
+    match WriteException::validate_write_result(doc, WriteConcern::new()) {
+        Err(Error::WriteError(err)) => {
+            let w_err = err.write_error.expect("Expected a write error.");
+            assert_eq!(2, w_err.index);
+            assert_eq!(Some(String::from("DocumentValidationFailure")), w_err.code_name);
+            assert_eq!(Some(doc! { "failingDocumentId": 7 }), w_err.details);
+            assert!(w_err.is_validation_failure());
+            assert!(!w_err.is_duplicate_key());
+            assert_eq!(Some(ErrorCode::DocumentValidationFailure), w_err.error_code());
+        }
+        other => panic!("Expected a WriteError, got {:?}", other),
+    }
+}
@@ -5,6 +5,7 @@ use mongodb::coll::Collection;
 use mongodb::coll::options::{FindOptions, IndexOptions};
 use mongodb::db::ThreadedDatabase;
 use mongodb::gridfs::{Store, ThreadedStore};
+use mongodb::gridfs::bucket::{GridFsBucketOptions, GridFsUploadOptions};
 use mongodb::gridfs::file::DEFAULT_CHUNK_SIZE;
 
 use rand::{thread_rng, Rng};
@@ -209,3 +210,139 @@ fn find() {
     assert_eq!(id, results[0].id);
     assert_eq!(id2, results[1].id);
 }
+
+#[test]
+fn bucket_upload_download() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-gridfs-bucket_upload_download");
+    db.drop_database().unwrap();
+
+    let mut options = GridFsBucketOptions::new();
+    options.bucket_name = Some(String::from("reports"));
+    options.chunk_size_bytes = Some(1024);
+    let bucket = db.gridfs_bucket(Some(options));
+
+    let src = gen_rand_file(2500);
+    let id = bucket.upload_from_bytes("bucket_file", &src, None).unwrap();
+    assert_eq!(3, db.collection("reports.chunks").count(None, None).unwrap());
+
+    let mut dest = Vec::new();
+    assert_eq!(2500, bucket.download_to_writer(id.clone(), &mut dest).unwrap());
+    assert_eq!(src, dest);
+
+    let id2 = bucket.upload_from_reader("bucket_file_2", &mut &src[..1000], None).unwrap();
+    let mut stream = bucket.open_download_stream(id2).unwrap();
+    let mut dest = Vec::new();
+    stream.read_to_end(&mut dest).unwrap();
+    stream.close().unwrap();
+    assert_eq!(&src[..1000], &dest[..]);
+
+    let names: Vec<_> = db.collection("reports.files")
+        .list_indexes()
+        .unwrap()
+        .filter_map(|index| index.ok())
+        .filter_map(|index| index.get_str("name").ok().map(String::from))
+        .collect();
+    assert!(names.contains(&String::from("filename_1_uploadDate_1")));
+    assert!(bucket.download_to_writer(bson::oid::ObjectId::new().unwrap(), &mut dest).is_err());
+}
+
+#[test]
+fn bucket_streams() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-gridfs-bucket_streams");
+    db.drop_database().unwrap();
+
+    let mut options = GridFsBucketOptions::new();
+    options.chunk_size_bytes = Some(1000);
+    let bucket = db.gridfs_bucket(Some(options));
+
+    let src = gen_rand_file(4500);
+    let mut upload = bucket.open_upload_stream("streamed", None).unwrap();
+    for part in src.chunks(700) {
+        upload.write_all(part).unwrap();
+        upload.flush().unwrap();
+    }
+    let id = upload.finish().unwrap();
+    assert_eq!(5, db.collection("fs.chunks").count(None, None).unwrap());
+
+    let mut download = bucket.open_download_stream(id).unwrap();
+    assert_eq!(4500, download.len());
+    let mut dest = Vec::new();
+    download.read_to_end(&mut dest).unwrap();
+    download.close().unwrap();
+    assert_eq!(src, dest);
+
+    let mut aborted = bucket.open_upload_stream("aborted", None).unwrap();
+    let aborted_id = aborted.id().clone();
+    aborted.write_all(&src).unwrap();
+    aborted.abort().unwrap();
+    assert!(bucket.open_download_stream(aborted_id).is_err());
+    assert_eq!(5, db.collection("fs.chunks").count(None, None).unwrap());
+}
+
+#[test]
+fn bucket_find_delete_rename() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-gridfs-bucket_find_delete_rename");
+    db.drop_database().unwrap();
+
+    let bucket = db.gridfs_bucket(None);
+    let src = gen_rand_file(3000);
+    let id = bucket.upload_from_bytes("first", &src, None).unwrap();
+    let id2 = bucket.upload_from_bytes("second", &src[..10], None).unwrap();
+
+    bucket.rename(id.clone(), "renamed").unwrap();
+    assert!(bucket.rename(bson::oid::ObjectId::new().unwrap(), "missing").is_err());
+
+    let mut options = FindOptions::new();
+    options.sort = Some(doc! { "filename": 1 });
+    let files: Vec<_> = bucket.find(None, Some(options)).unwrap().map(|f| f.unwrap()).collect();
+    assert_eq!(2, files.len());
+    assert_eq!(Some(String::from("renamed")), files[0].filename);
+    assert_eq!(3000, files[0].length);
+    assert_eq!(id2, files[1].id);
+
+    bucket.delete(id.clone()).unwrap();
+    assert!(bucket.delete(id).is_err());
+    assert_eq!(1, db.collection("fs.files").count(None, None).unwrap());
+    assert_eq!(1, db.collection("fs.chunks").count(None, None).unwrap());
+
+    bucket.drop().unwrap();
+    assert_eq!(0, bucket.find(None, None).unwrap().count());
+}
+
+#[test]
+fn bucket_resume_upload() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-gridfs-bucket_resume_upload");
+    db.drop_database().unwrap();
+
+    let bucket = db.gridfs_bucket(None);
+    let mut options = GridFsUploadOptions::new();
+    options.chunk_size_bytes = Some(1000);
+
+    let src = gen_rand_file(4500);
+    let mut upload = bucket.open_upload_stream("resumed", Some(options.clone())).unwrap();
+    upload.write_all(&src[..2600]).unwrap();
+    let id = upload.suspend().unwrap();
+    assert_eq!(2, db.collection("fs.chunks").count(None, None).unwrap());
+
+    let mut upload = bucket.resume_upload(id.clone(), "resumed", Some(options.clone())).unwrap();
+    assert_eq!(2000, upload.position());
+    upload.write_all(&src[2000..]).unwrap();
+    assert_eq!(id, upload.finish().unwrap());
+
+    let mut dest = Vec::new();
+    bucket.download_to_writer(id.clone(), &mut dest).unwrap();
+    assert_eq!(src, dest);
+    assert!(bucket.resume_upload(id.clone(), "resumed", Some(options.clone())).is_err());
+    assert!(bucket.abort_upload(id).is_err());
+
+    let mut upload = bucket.open_upload_stream("abandoned", Some(options)).unwrap();
+    upload.write_all(&src).unwrap();
+    let abandoned = upload.suspend().unwrap();
+    bucket.abort_upload(abandoned.clone()).unwrap();
+    let chunks = db.collection("fs.chunks");
+    assert_eq!(0, chunks.count(Some(doc! { "files_id": abandoned }), None).unwrap());
+}
@@ -11,9 +11,16 @@ mod handshake;
 mod wire_protocol;
 
 use bson;
-use mongodb::{Client, ThreadedClient};
+use mongodb::admin::{FailPoint, ListDatabasesOptions};
+use mongodb::coll::options::FindOptions;
+use mongodb::{Client, ClientOptions, ClientOptionsDelta, CommandType, Error, ThreadedClient};
+use mongodb::common::{Credential, ReadMode, ReadPreference, ServerApi, ServerApiVersion,
+                      WriteConcern};
 use mongodb::db::ThreadedDatabase;
+use mongodb::pool::FlushMode;
+use mongodb::rate_limit::RateLimit;
 use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn is_master() {
@@ -22,6 +29,121 @@ fn is_master() {
     assert!(res);
 }
 
+#[test]
+fn shared_topology() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let mut options = ClientOptions::new();
+    options.read_preference = Some(ReadPreference::new(ReadMode::SecondaryPreferred, None));
+    let secondary = client.with_shared_topology(Some(options)).unwrap();
+
+    assert_eq!(ReadMode::Primary, client.read_preference.mode);
+    assert_eq!(ReadMode::SecondaryPreferred, secondary.read_preference.mode);
+
+    let res = secondary.is_master().expect("Failed to execute is_master.");
+    assert!(res);
+
+    // The shared connections are authenticated already, so other credentials are refused.
+    let mut options = ClientOptions::new();
+    options.previous_credential = Some(Credential::new("other", "s3cret"));
+    match client.with_shared_topology(Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an argument error, got {:?}", other.map(|_| ())),
+    }
+
+    // So are server APIs other than the one the connections were handshaken with.
+    let mut options = ClientOptions::new();
+    options.server_api = Some(ServerApi::new(ServerApiVersion::V1));
+    match client.with_shared_topology(Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an argument error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn reconfigure() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let mut delta = ClientOptionsDelta::new();
+    delta.pool_size = Some(2);
+    delta.heartbeat_frequency_ms = Some(500);
+    delta.read_preference = Some(ReadPreference::new(ReadMode::SecondaryPreferred, None));
+    client.reconfigure(delta).expect("Failed to reconfigure client.");
+
+    assert_eq!(ReadMode::Primary, client.read_preference.mode);
+    assert_eq!(ReadMode::SecondaryPreferred, client.current_read_preference().mode);
+    assert_eq!(ReadMode::SecondaryPreferred, client.db("test").read_preference.mode);
+    assert!(client.is_master().unwrap());
+
+    let mut delta = ClientOptionsDelta::new();
+    delta.pool_size = Some(0);
+    assert!(client.reconfigure(delta).is_err());
+}
+
+#[test]
+fn credential_rotation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let credential = Credential::new("rotated", "s3cret");
+    assert!(!format!("{:?}", credential).contains("s3cret"));
+
+    client.rotate_credential(credential).expect("Failed to start credential rotation.");
+    assert!(client.is_master().unwrap());
+
+    client.finish_credential_rotation().expect("Failed to finish credential rotation.");
+    assert!(client.is_master().unwrap());
+}
+
+#[test]
+fn drain_server() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    assert!(client.is_master().unwrap());
+
+    // Nothing is checked out, so the pool empties right away.
+    let checked_out = client
+        .drain_server("localhost:27017", Duration::from_millis(500))
+        .expect("Failed to drain server.");
+    assert_eq!(0, checked_out);
+    assert!(client.is_master().is_err());
+
+    client.resume_server("localhost:27017").expect("Failed to resume server.");
+    assert!(client.is_master().unwrap());
+
+    assert!(client.drain_server("localhost:1", Duration::from_millis(0)).is_err());
+}
+
+#[test]
+fn server_info() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    assert!(client.is_master().unwrap());
+
+    // The version is fetched by the monitor after its first successful check.
+    let mut info = client.server_info("localhost:27017").expect("Failed to read server info.");
+    for _ in 0..50 {
+        if info.version.is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+        info = client.server_info("localhost:27017").expect("Failed to read server info.");
+    }
+
+    assert!(info.version.is_some());
+    assert!(info.last_update_time.is_some());
+    assert!(!info.read_only);
+
+    assert!(client.server_info("localhost:1").is_err());
+}
+
+#[test]
+fn config_metadata_of_unsharded_deployment() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    assert!(client.list_shards().expect("Failed to list shards.").is_empty());
+    assert!(client.list_sharded_collections().expect("Failed to list collections.").is_empty());
+    assert!(client.sharded_collection("test.coll").expect("Failed to read metadata.").is_none());
+    assert!(client.list_chunks("test.coll").expect("Failed to list chunks.").is_empty());
+}
+
 #[test]
 fn database_names() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -130,3 +252,231 @@ fn is_sync() {
     assert!(results.contains(&"test-client-mod-is_sync".to_owned()));
     assert!(results.contains(&"test-client-mod-is_sync_2".to_owned()));
 }
+
+#[test]
+fn rate_limits() {
+    let mut options = ClientOptions::new();
+    options.rate_limits.push(RateLimit {
+        namespace: Some(String::from("test-client-mod-rate_limits.throttled")),
+        command_type: Some(CommandType::Find),
+        per_second: 10.0,
+        burst: 1,
+    });
+
+    let client = Client::with_uri_and_options("mongodb://localhost:27017", options).unwrap();
+    let db = client.db("test-client-mod-rate_limits");
+    let throttled = db.collection("throttled");
+    let other = db.collection("other");
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        other.find_one(None, None).expect("Failed to execute find.");
+    }
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        throttled.find_one(None, None).expect("Failed to execute find.");
+    }
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn list_databases() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-mod-list_databases");
+    db.drop_database().unwrap();
+    db.collection("test").insert_one(doc! { "x": 1 }, None).unwrap();
+
+    let filter = doc! { "name": "test-client-mod-list_databases" };
+    let databases = client.list_databases(Some(filter.clone()), None).unwrap();
+    assert_eq!(1, databases.len());
+    assert_eq!("test-client-mod-list_databases", databases[0].name);
+    assert!(databases[0].size_on_disk.is_some());
+
+    let options = ListDatabasesOptions {
+        name_only: Some(true),
+        ..ListDatabasesOptions::new()
+    };
+    let databases = client.list_databases(Some(filter), Some(options)).unwrap();
+    assert_eq!(1, databases.len());
+    assert_eq!(None, databases[0].size_on_disk);
+
+    db.drop_database().unwrap();
+}
+
+#[test]
+fn fsync_lock() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let outer = client.fsync_lock().unwrap();
+    assert!(outer.lock_count() >= 1);
+
+    {
+        let inner = client.fsync_lock().unwrap();
+        assert_eq!(outer.lock_count() + 1, inner.lock_count());
+    }
+
+    assert_eq!(0, outer.unlock().unwrap());
+    assert!(client.fsync_unlock().is_err());
+}
+
+#[test]
+fn declare_server_api() {
+    let mut server_api = ServerApi::new(ServerApiVersion::V1);
+    server_api.strict = Some(true);
+    let options = ClientOptions {
+        server_api: Some(server_api),
+        ..ClientOptions::new()
+    };
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+    let coll = client.db("test-client-mod-declare_server_api").collection("test");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+    assert_eq!(1, coll.count(None, None).unwrap());
+
+    // Not part of version 1, so rejected in strict mode.
+    let cmd = doc! { "getCmdLineOpts": 1 };
+    assert!(client.db("admin").command(cmd, CommandType::GetCmdLineOpts, None).is_err());
+}
+
+#[test]
+fn connection_health() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.is_master().unwrap();
+
+    let health = client.connection_health();
+    assert!(!health.broken);
+    assert!(health.since_last_success.unwrap() <= health.age);
+}
+
+#[test]
+fn ping() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.ping().unwrap();
+    assert!(!client.connection_health().broken);
+}
+
+#[test]
+fn error_context() {
+    let options = ClientOptions {
+        error_context: true,
+        ..ClientOptions::new()
+    };
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+    let coll = client.db("test-client-mod-error_context").collection("test");
+    let err = coll.find(Some(doc! { "$notAnOperator": 1 }), None).unwrap_err();
+
+    let context = err.context().expect("Expected the context of the failed command.");
+    assert_eq!("find", context.command_name);
+    assert!(context.namespace.starts_with("test-client-mod-error_context."));
+    assert_eq!("localhost:27017", context.server);
+    match *err.without_context() {
+        Error::OperationError(_) => (),
+        ref other => panic!("Expected an operation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn read_buffer_size() {
+    let options = ClientOptions {
+        read_buffer_size: Some(0),
+        ..ClientOptions::new()
+    };
+    match Client::connect_with_options("localhost", 27017, options) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an argument error, got {:?}", other.map(|_| ())),
+    }
+
+    // Replies larger than the buffer are read through it in pieces.
+    let options = ClientOptions {
+        read_buffer_size: Some(64),
+        ..ClientOptions::new()
+    };
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = client.db("test-client-mod-read_buffer_size").collection("test");
+    coll.drop().unwrap();
+
+    let docs: Vec<_> = (0..20).map(|i| doc! { "_id": i, "name": "x".repeat(100) }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let cursor = coll.find(None, None).unwrap();
+    assert_eq!(20, cursor.map(|doc| doc.unwrap()).count());
+}
+
+#[test]
+fn coalesced_flushes() {
+    // A single connection, so that held messages ride along with the next command.
+    let options = ClientOptions {
+        pool_size: Some(1),
+        tcp_nodelay: Some(false),
+        flush_mode: FlushMode::Coalesce,
+        ..ClientOptions::new()
+    };
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = client.db("test-client-mod-coalesced_flushes").collection("test");
+    coll.drop().unwrap();
+
+    let docs: Vec<_> = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    // The killCursors of the dropped cursor is held until the next command.
+    {
+        let options = FindOptions { batch_size: Some(2), ..FindOptions::new() };
+        let mut cursor = coll.find(None, Some(options)).unwrap();
+        cursor.next().unwrap().unwrap();
+    }
+
+    let unacknowledged = WriteConcern { w: 0, ..WriteConcern::new() };
+    coll.insert_one(doc! { "_id": 10 }, Some(unacknowledged)).unwrap();
+
+    // Held writes run before the commands sent after them on the connection.
+    assert_eq!(11, coll.count(None, None).unwrap());
+}
+
+#[test]
+fn fail_points() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-mod-fail_points").collection("test");
+    coll.drop().unwrap();
+
+    let fail_point = FailPoint::fail_command(&["insert"]).times(1).error_code(91);
+    client.enable_fail_point(fail_point).unwrap();
+
+    // Only the first insert is failed.
+    assert!(coll.insert_one(doc! { "_id": 1 }, None).is_err());
+    coll.insert_one(doc! { "_id": 2 }, None).unwrap();
+
+    client.enable_fail_point(FailPoint::fail_command(&["count"])).unwrap();
+    assert!(coll.count(None, None).is_err());
+
+    client.disable_fail_point("failCommand").unwrap();
+    assert_eq!(1, coll.count(None, None).unwrap());
+}
+
+#[test]
+fn ping_with_timeout() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let round_trip = client.ping_with_timeout(None, Duration::from_secs(5)).unwrap();
+    assert!(round_trip < Duration::from_secs(5));
+
+    let health = client.health();
+    assert_eq!(1, health.servers.len());
+    assert!(health.servers[0].pool.open > 0);
+
+    // No server matches the tags, so selection gives up after the timeout.
+    let tags = vec![[(String::from("dc"), String::from("nowhere"))].iter().cloned().collect()];
+    let read_preference = ReadPreference::new(ReadMode::Secondary, Some(tags));
+    let started = Instant::now();
+    assert!(client.ping_with_timeout(Some(read_preference), Duration::from_secs(1)).is_err());
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn await_primary() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.await_primary(Duration::from_secs(10)).unwrap();
+    assert!(client.health().is_writable());
+}
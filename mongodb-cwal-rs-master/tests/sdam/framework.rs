@@ -53,6 +53,9 @@ pub fn run_suite(file: &str, description: Option<TopologyDescription>) {
             StreamConnector::default(),
             None,
             None,
+            None,
+            None,
+            None,
         );
         topology_description.servers.insert(host.clone(), server);
     }
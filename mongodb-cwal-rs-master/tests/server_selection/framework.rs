@@ -31,6 +31,9 @@ pub fn run_suite(file: &str) {
             StreamConnector::default(),
             None,
             None,
+            None,
+            None,
+            None,
         );
 
         {